@@ -0,0 +1,383 @@
+//! Manages the Agents that the CLI can sign Commits with.
+//! The active Agent lives in `~/.config/atomic/config.toml`, which is also used by Atomic-Server.
+//! Other Agents are saved as separate config files in `~/.config/atomic/agents/`, so you can switch between them.
+//!
+//! Agents are exported as secrets: a base64 encoded JSON object with the `privateKey` and `subject`, like the Atomic Data Browser uses.
+//! With a passphrase, the secret is encrypted using AES-256-GCM, with a key derived using PBKDF2.
+//!
+//! `agent rotate-key` replaces the key of the active Agent. Secrets that were exported before stop working.
+
+use std::{num::NonZeroU32, path::PathBuf};
+
+use crate::Context;
+use atomic_lib::{
+    agents::{decode_base64, encode_base64, generate_public_key, Agent},
+    config::{read_config, write_config, Config},
+    errors::AtomicResult,
+    urls, Storelike, Value,
+};
+use colored::*;
+use ring::{
+    aead, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// Prefix of secrets that are encrypted with a passphrase.
+const ENCRYPTED_PREFIX: &str = "encrypted:";
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+fn agents_dir() -> AtomicResult<PathBuf> {
+    Ok(atomic_lib::config::default_config_dir_path()?.join("agents"))
+}
+
+fn agent_path(name: &str) -> AtomicResult<PathBuf> {
+    Ok(agents_dir()?.join(format!("{}.toml", name)))
+}
+
+/// Turns a name into something that can be used as a file name.
+fn to_slug(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string()
+}
+
+/// The name for an Agent that was saved without one.
+fn default_name(private_key: &str) -> String {
+    let public_key = generate_public_key(private_key).public;
+    let short: String = public_key
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(8)
+        .collect();
+    format!("agent-{}", short.to_lowercase())
+}
+
+/// All saved Agents and their names, sorted by name.
+fn saved_agents() -> AtomicResult<Vec<(String, Config)>> {
+    let dir = agents_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut agents = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "toml") {
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            agents.push((name, read_config(&path)?));
+        }
+    }
+    agents.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(agents)
+}
+
+/// Finds a saved Agent by its name or subject.
+fn find_agent(name_or_subject: &str) -> AtomicResult<(String, Config)> {
+    saved_agents()?
+        .into_iter()
+        .find(|(name, config)| name == name_or_subject || config.agent == name_or_subject)
+        .ok_or(
+            format!(
+                "No saved Agent named {}. Run `atomic-cli agent list`.",
+                name_or_subject
+            )
+            .into(),
+        )
+}
+
+/// Saves the Agent, and makes it the active one if there is none.
+fn save_agent(name: &str, config: Config) -> AtomicResult<()> {
+    let name = to_slug(name);
+    if name.is_empty() {
+        return Err("The name of an Agent needs at least one letter or number.".into());
+    }
+    match find_agent(&config.agent) {
+        Ok((existing, _found)) if existing != name => {
+            return Err(format!("This Agent is already saved as {}.", existing).into())
+        }
+        // Overwriting the same Agent is fine
+        Ok(_same) => {}
+        Err(_not_found) if agent_path(&name)?.exists() => {
+            return Err(format!("There already is an Agent named {}.", name).into())
+        }
+        Err(_not_found) => {}
+    }
+    write_config(&agent_path(&name)?, config.clone())?;
+    let active_path = atomic_lib::config::default_config_file_path()?;
+    if !active_path.exists() {
+        write_config(&active_path, config)?;
+        println!("Saved Agent {}, and made it the active Agent.", name.bold());
+    } else {
+        println!(
+            "Saved Agent {}. Run `atomic-cli agent use {}` to sign with it.",
+            name.bold(),
+            name
+        );
+    }
+    Ok(())
+}
+
+pub fn agent(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("agent").unwrap();
+    match matches.subcommand() {
+        Some(("new", sub)) => new(context, sub),
+        Some(("list", _sub)) => list(),
+        Some(("use", sub)) => use_agent(sub.get_one::<String>("agent").unwrap()),
+        Some(("export", sub)) => export(sub),
+        Some(("import", sub)) => import(sub),
+        Some(("rotate-key", _sub)) => rotate_key(context),
+        _ => Err("Run `atomic-cli agent --help` for the available commands.".into()),
+    }
+}
+
+/// Generates a keypair for a new Agent on the server. Unless `--no-publish` is passed, the Agent creates itself on the server.
+fn new(context: &Context, matches: &clap::ArgMatches) -> AtomicResult<()> {
+    let server = match matches.get_one::<String>("server") {
+        Some(server) => server.trim_end_matches('/').to_string(),
+        None => crate::read_config()
+            .map(|config| config.server)
+            .ok_or("Pass a --server for the new Agent.")?,
+    };
+    let name = matches.get_one::<String>("name");
+    let mut agent = Agent::new(name.map(String::as_str), &context.store)?;
+    agent.subject = format!("{}/agents/{}", server, agent.public_key);
+    let private_key = agent
+        .private_key
+        .clone()
+        .expect("New Agents have a private key");
+
+    if !matches.get_flag("no-publish") {
+        // Agents can always edit themselves, so no other Agent has to sign this
+        context.store.set_default_agent(agent.clone());
+        let mut resource = agent.to_resource()?;
+        resource.save(&context.store).map_err(|e| {
+            format!(
+                "Could not publish the Agent to {}. Use --no-publish to only save it locally. {}",
+                server, e
+            )
+        })?;
+        println!("Published Agent {}", agent.subject);
+    }
+
+    let config = Config {
+        server,
+        agent: agent.subject,
+        private_key: private_key.clone(),
+    };
+    let name = name.cloned().unwrap_or_else(|| default_name(&private_key));
+    save_agent(&name, config)
+}
+
+fn list() -> AtomicResult<()> {
+    let active = crate::read_config();
+    let agents = saved_agents()?;
+    for (name, config) in &agents {
+        let is_active = active.as_ref().is_some_and(|a| a.agent == config.agent);
+        let marker = if is_active { "*" } else { " " };
+        println!(
+            "{} {: <20} {}",
+            marker.green().bold(),
+            name.bold(),
+            config.agent
+        );
+    }
+    if let Some(active) = active {
+        if !agents
+            .iter()
+            .any(|(_name, config)| config.agent == active.agent)
+        {
+            println!(
+                "{} {: <20} {}",
+                "*".green().bold(),
+                "(not saved)".dimmed(),
+                active.agent
+            );
+        }
+    }
+    if agents.is_empty() {
+        println!("No saved Agents. Create one using `atomic-cli agent new`.");
+    }
+    Ok(())
+}
+
+/// Makes a saved Agent the active Agent. The active Agent is saved first, so it can't get lost.
+fn use_agent(name_or_subject: &str) -> AtomicResult<()> {
+    let (name, config) = find_agent(name_or_subject)?;
+    let active_path = atomic_lib::config::default_config_file_path()?;
+    if let Ok(active) = read_config(&active_path) {
+        if find_agent(&active.agent).is_err() {
+            let active_name = default_name(&active.private_key);
+            write_config(&agent_path(&active_name)?, active)?;
+            println!("Saved the previously active Agent as {}.", active_name);
+        }
+    }
+    write_config(&active_path, config.clone())?;
+    println!("Now signing as {} ({}).", name.bold(), config.agent);
+    Ok(())
+}
+
+/// Prints the secret of an Agent. Without a name, exports the active Agent.
+fn export(matches: &clap::ArgMatches) -> AtomicResult<()> {
+    let config = match matches.get_one::<String>("agent") {
+        Some(name) => find_agent(name)?.1,
+        None => crate::read_config().ok_or("There is no active Agent.")?,
+    };
+    let secret = serde_json::json!({
+        "privateKey": config.private_key,
+        "subject": config.agent,
+    });
+    let secret = encode_base64(secret.to_string().as_bytes());
+    let output = match matches.get_one::<String>("passphrase") {
+        Some(passphrase) => encrypt(&secret, passphrase)?,
+        None => {
+            eprintln!(
+                "{}",
+                "Anyone with this secret can sign as this Agent. Use --passphrase to encrypt it."
+                    .yellow()
+            );
+            secret
+        }
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+/// Saves an Agent from a secret, which can be encrypted.
+fn import(matches: &clap::ArgMatches) -> AtomicResult<()> {
+    let secret = matches.get_one::<String>("secret").unwrap().trim();
+    let secret = match secret.strip_prefix(ENCRYPTED_PREFIX) {
+        Some(encrypted) => {
+            let passphrase = match matches.get_one::<String>("passphrase") {
+                Some(passphrase) => passphrase.clone(),
+                None => promptly::prompt("This secret is encrypted. What's the passphrase?")
+                    .map_err(|e| format!("No passphrase. {}", e))?,
+            };
+            decrypt(encrypted, &passphrase)?
+        }
+        None => secret.to_string(),
+    };
+    let decoded =
+        String::from_utf8(decode_base64(&secret)?).map_err(|e| format!("Invalid secret. {}", e))?;
+    let json: serde_json::Value = serde_json::from_str(&decoded)
+        .map_err(|e| format!("Invalid secret, it should contain JSON. {}", e))?;
+    let private_key = json["privateKey"]
+        .as_str()
+        .ok_or("The secret has no privateKey")?;
+    let subject = json["subject"]
+        .as_str()
+        .ok_or("The secret has no subject")?;
+    // Checks whether the private key is valid, which would otherwise panic when signing
+    let key_bytes = decode_base64(private_key)?;
+    ring::signature::Ed25519KeyPair::from_seed_unchecked(&key_bytes)
+        .map_err(|_| "The private key in the secret is invalid")?;
+
+    let config = Config {
+        server: atomic_lib::utils::server_url(subject)?
+            .trim_end_matches('/')
+            .to_string(),
+        agent: subject.to_string(),
+        private_key: private_key.to_string(),
+    };
+    let name = matches
+        .get_one::<String>("name")
+        .cloned()
+        .unwrap_or_else(|| default_name(private_key));
+    save_agent(&name, config)
+}
+
+/// Replaces the key of the active Agent with a new one.
+/// The Commit is signed with the old key, after which the config files are updated to use the new key.
+fn rotate_key(context: &Context) -> AtomicResult<()> {
+    let config = context.get_write_context();
+    let mut resource = context.store.get_resource(&config.agent)?;
+    let new_keys = Agent::new(None, &context.store)?;
+    let private_key = new_keys.private_key.expect("New Agents have a private key");
+    resource.set_propval_unsafe(
+        urls::PUBLIC_KEY.into(),
+        Value::String(new_keys.public_key.clone()),
+    );
+    resource
+        .save(&context.store)
+        .map_err(|e| format!("Could not rotate the key of {}. {}", config.agent, e))?;
+
+    let saved = find_agent(&config.agent).ok();
+    let config = Config {
+        private_key,
+        ..config
+    };
+    write_config(
+        &atomic_lib::config::default_config_file_path()?,
+        config.clone(),
+    )?;
+    if let Some((name, _old)) = saved {
+        write_config(&agent_path(&name)?, config.clone())?;
+    }
+    println!(
+        "Rotated the key of {}. The new public key is {}.",
+        config.agent, new_keys.public_key
+    );
+    println!(
+        "{}",
+        "Exported secrets of this Agent no longer work, export it again to sign in elsewhere."
+            .yellow()
+    );
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> AtomicResult<aead::LessSafeKey> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = aead::UnboundKey::new(&aead::AES_256_GCM, &key)
+        .map_err(|_| "Could not create encryption key")?;
+    Ok(aead::LessSafeKey::new(key))
+}
+
+/// Returns the prefix followed by the base64 encoded salt, nonce and ciphertext.
+fn encrypt(secret: &str, passphrase: &str) -> AtomicResult<String> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| "Could not generate random bytes")?;
+    let key = derive_key(passphrase, &salt)?;
+    let mut in_out = secret.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce),
+        aead::Aad::empty(),
+        &mut in_out,
+    )
+    .map_err(|_| "Could not encrypt the secret")?;
+    let bytes = [&salt[..], &nonce[..], &in_out].concat();
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, encode_base64(&bytes)))
+}
+
+fn decrypt(encrypted: &str, passphrase: &str) -> AtomicResult<String> {
+    let bytes = decode_base64(encrypted)?;
+    if bytes.len() < SALT_LEN + NONCE_LEN {
+        return Err("The encrypted secret is too short.".into());
+    }
+    let (salt, rest) = bytes.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = derive_key(passphrase, salt)?;
+    let nonce = aead::Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| "Invalid nonce in the encrypted secret")?;
+    let mut in_out = ciphertext.to_vec();
+    let plain = key
+        .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+        .map_err(|_| "Could not decrypt the secret. Is the passphrase correct?")?;
+    Ok(String::from_utf8(plain.to_vec()).map_err(|e| format!("Invalid secret. {}", e))?)
+}
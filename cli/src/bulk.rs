@@ -0,0 +1,148 @@
+//! Applies many set and remove operations at once, for one-off data cleaning jobs.
+//! Every row is `subject,property,value`. Rows without a value remove the Property.
+//! The operations are grouped into one Commit per subject.
+
+use crate::Context;
+use atomic_lib::{errors::AtomicResult, Resource, Storelike};
+use colored::*;
+use std::io::Read;
+
+/// A single change to a resource, read from a row of the input.
+struct Operation {
+    row: usize,
+    property: String,
+    /// `None` removes the Property.
+    value: Option<String>,
+}
+
+pub fn bulk(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("bulk").unwrap();
+    let dry_run = matches.get_flag("dry-run");
+    let data = match matches.get_one::<String>("file") {
+        Some(file) => {
+            std::fs::read_to_string(file).map_err(|e| format!("Could not read {}. {}", file, e))?
+        }
+        None => {
+            let mut data = String::new();
+            std::io::stdin()
+                .read_to_string(&mut data)
+                .map_err(|e| format!("Could not read stdin. {}", e))?;
+            data
+        }
+    };
+
+    let mut failures: Vec<String> = Vec::new();
+    // Keeps the order in which the subjects first appear
+    let mut batches: Vec<(String, Vec<Operation>)> = Vec::new();
+    for (index, cells) in atomic_lib::parse::parse_csv(&data).into_iter().enumerate() {
+        let row = index + 1;
+        let cells: Vec<&str> = cells.iter().map(|c| c.trim()).collect();
+        if row == 1 && cells.first() == Some(&"subject") {
+            continue;
+        }
+        let (subject, property, value) = match cells.as_slice() {
+            [subject, property] => (subject, property, None),
+            [subject, property, value] => (
+                subject,
+                property,
+                Some(value.to_string()).filter(|v| !v.is_empty()),
+            ),
+            _ => {
+                failures.push(format!(
+                    "Row {}: expected `subject,property,value`, found {} cells.",
+                    row,
+                    cells.len()
+                ));
+                continue;
+            }
+        };
+        let Some(subject) = context.mapping.lock().unwrap().try_mapping_or_url(subject) else {
+            failures.push(format!("Row {}: no url found for {}.", row, subject));
+            continue;
+        };
+        let operation = Operation {
+            row,
+            property: crate::commit::expand_prefix(context, property),
+            value,
+        };
+        match batches.iter_mut().find(|(s, _)| s == &subject) {
+            Some((_, operations)) => operations.push(operation),
+            None => batches.push((subject, vec![operation])),
+        }
+    }
+
+    if !dry_run && !batches.is_empty() {
+        context.get_write_context();
+    }
+    let mut applied = 0;
+    let mut saved = 0;
+    for (subject, operations) in batches {
+        // If the resource is not found, create it
+        let mut resource = match context.store.get_resource(&subject) {
+            Ok(r) => r,
+            Err(_) => Resource::new(subject.clone()),
+        };
+        let mut changed = 0;
+        for operation in &operations {
+            let result = match &operation.value {
+                Some(value) => {
+                    resource.set_propval_shortname(&operation.property, value, &context.store)
+                }
+                None => resource.remove_propval_shortname(&operation.property, &context.store),
+            };
+            match result {
+                Ok(()) => {
+                    changed += 1;
+                    if dry_run {
+                        match &operation.value {
+                            Some(value) => println!(
+                                "{} {} {}: {}",
+                                "set".green(),
+                                subject,
+                                operation.property,
+                                value
+                            ),
+                            None => {
+                                println!("{} {} {}", "remove".red(), subject, operation.property)
+                            }
+                        }
+                    }
+                }
+                Err(e) => failures.push(format!("Row {}: {}", operation.row, e)),
+            }
+        }
+        if changed == 0 || dry_run {
+            applied += changed;
+            continue;
+        }
+        match resource.save(&context.store) {
+            Ok(_) => {
+                applied += changed;
+                saved += 1;
+            }
+            Err(e) => failures.push(format!("Could not save {}: {}", subject, e)),
+        }
+    }
+
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    if dry_run {
+        println!(
+            "Dry run: would apply {} operations, {} failed.",
+            applied,
+            failures.len()
+        );
+    } else {
+        println!(
+            "Applied {} operations in {} Commits, {} failed.",
+            applied,
+            saved,
+            failures.len()
+        );
+    }
+    if !failures.is_empty() {
+        return Err(format!("{} operations could not be applied.", failures.len()).into());
+    }
+    Ok(())
+}
@@ -1,10 +1,12 @@
 use crate::Context;
 use atomic_lib::{errors::AtomicResult, Storelike};
+#[cfg(feature = "native")]
+use colored::*;
 
 /// Apply a Commit using the Set method - create or update a value in a resource
 pub fn set(context: &Context) -> AtomicResult<()> {
     let subject = argument_to_url(context, "subject")?;
-    let property = argument_to_string(context, "property")?;
+    let property = argument_to_property(context, "property")?;
     let value = argument_to_string(context, "value")?;
     // If the resource is not found, create it
     let mut resource = match context.store.get_resource(&subject) {
@@ -17,10 +19,13 @@ pub fn set(context: &Context) -> AtomicResult<()> {
 }
 
 /// Apply a Commit using the Set method, where the value is edited in the user's text editor.
+/// Without a property, the whole resource is edited, see [edit_resource].
 #[cfg(feature = "native")]
 pub fn edit(context: &Context) -> AtomicResult<()> {
     let subject = argument_to_url(context, "subject")?;
-    let prop = argument_to_string(context, "property")?;
+    let Ok(prop) = argument_to_property(context, "property") else {
+        return edit_resource(context, subject);
+    };
     // If the resource is not found, create it
     let mut resource = match context.store.get_resource(&subject) {
         Ok(r) => r,
@@ -39,10 +44,84 @@ pub fn edit(context: &Context) -> AtomicResult<()> {
     Ok(())
 }
 
+/// Opens the resource as JSON (with shortnames as keys) in `$EDITOR`,
+/// and creates a Commit signed by the Agent from the config for the Properties that were changed or removed.
+#[cfg(feature = "native")]
+fn edit_resource(context: &Context, subject: String) -> AtomicResult<()> {
+    use atomic_lib::{parse::ParseOpts, urls};
+
+    context.get_write_context();
+    let store = &context.store;
+    let mut resource = match store.get_resource(&subject) {
+        Ok(r) => r,
+        Err(_) => atomic_lib::Resource::new(subject.clone()),
+    };
+    // These are set by the server, and can't be edited
+    let mut editable = resource.clone();
+    for read_only in [urls::LAST_COMMIT, urls::CHILDREN] {
+        editable.remove_propval(read_only);
+    }
+    let original = editable.to_json(store)?;
+    let edited = edit::edit(&original)?;
+    if edited.trim() == original.trim() {
+        println!("No changes.");
+        return Ok(());
+    }
+
+    // Convert the shortnames back to Property URLs, so it can be parsed as JSON-AD
+    let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&edited)
+        .map_err(|e| format!("The edited resource is not a valid JSON object. {}", e))?;
+    let mut json_ad = serde_json::Map::new();
+    for (key, value) in object {
+        if key == "@id" {
+            if value.as_str() != Some(subject.as_str()) {
+                return Err("The @id of the resource can't be changed.".into());
+            }
+            continue;
+        }
+        let property = editable.resolve_shortname_to_property(&key, store)?;
+        json_ad.insert(property.subject, value);
+    }
+    json_ad.insert("@id".into(), subject.into());
+    let parse_opts = ParseOpts {
+        save: atomic_lib::parse::SaveOpts::DontSave,
+        ..Default::default()
+    };
+    let changed = atomic_lib::parse::parse_json_ad_resource(
+        &serde_json::Value::Object(json_ad).to_string(),
+        store,
+        &parse_opts,
+    )?;
+
+    let mut has_changes = false;
+    for (property, value) in changed.get_propvals() {
+        let old = editable.get(property).ok().map(|v| v.to_string());
+        if old.as_ref() != Some(&value.to_string()) {
+            println!("{} {}: {}", "set".green(), property, value);
+            resource.set_propval_unsafe(property.clone(), value.clone());
+            has_changes = true;
+        }
+    }
+    for property in editable.get_propvals().keys() {
+        if changed.get(property).is_err() {
+            println!("{} {}", "remove".red(), property);
+            resource.remove_propval(property);
+            has_changes = true;
+        }
+    }
+    if !has_changes {
+        println!("No changes.");
+        return Ok(());
+    }
+    resource.save(store)?;
+    println!("Saved {}", resource.get_subject());
+    Ok(())
+}
+
 /// Apply a Commit using the Remove method - removes a property from a resource
 pub fn remove(context: &Context) -> AtomicResult<()> {
     let subject = argument_to_url(context, "subject")?;
-    let prop = argument_to_string(context, "property")?;
+    let prop = argument_to_property(context, "property")?;
     let mut resource = context.store.get_resource(&subject)?;
     resource.remove_propval_shortname(&prop, &context.store)?;
     resource.save(&context.store)?;
@@ -67,8 +146,28 @@ fn argument_to_string(context: &Context, argument: &str) -> AtomicResult<String>
     Ok(user_arg.to_string())
 }
 
+/// Parses a single argument as a Property shortname or URL.
+/// Prefixed names such as `schema:name` are expanded to URLs.
+fn argument_to_property(context: &Context, argument: &str) -> AtomicResult<String> {
+    let property = argument_to_string(context, argument)?;
+    Ok(expand_prefix(context, &property))
+}
+
+/// Expands a prefixed name such as `schema:name` using the mapping, or returns the input.
+pub fn expand_prefix(context: &Context, input: &str) -> String {
+    if atomic_lib::mapping::is_url(input) {
+        return input.into();
+    }
+    context
+        .mapping
+        .lock()
+        .unwrap()
+        .expand_prefix(input)
+        .unwrap_or_else(|| input.into())
+}
+
 /// Parses a single argument (URL or Bookmark), should return a valid URL
-fn argument_to_url(context: &Context, argument: &str) -> AtomicResult<String> {
+pub fn argument_to_url(context: &Context, argument: &str) -> AtomicResult<String> {
     let command_name = context.matches.subcommand_name().unwrap();
     let subcommand_matches = context.matches.subcommand_matches(command_name).unwrap();
     let user_arg = subcommand_matches
@@ -0,0 +1,69 @@
+//! Compares two resources, or two versions of a resource, Property by Property.
+
+use crate::Context;
+use atomic_lib::{errors::AtomicResult, resources::diff_versions, urls, Storelike};
+use colored::*;
+
+pub fn diff(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("diff").unwrap();
+    let subject = crate::commit::argument_to_url(context, "subject-a")?;
+    // Private resources can only be read with an Agent
+    context.get_agent_if_configured()?;
+
+    let (from_url, to_url) = match matches.get_many::<String>("versions") {
+        Some(versions) => {
+            let server = atomic_lib::utils::server_url(&subject)?;
+            let versions: Vec<String> = versions
+                .map(|commit| version_url(server.trim_end_matches('/'), commit))
+                .collect::<AtomicResult<_>>()?;
+            (versions[0].clone(), versions[1].clone())
+        }
+        None => (
+            subject,
+            crate::commit::argument_to_url(context, "subject-b")?,
+        ),
+    };
+    let from = context.store.get_resource(&from_url)?;
+    let to = context.store.get_resource(&to_url)?;
+
+    println!("{} {}", "---".red(), from_url);
+    println!("{} {}", "+++".green(), to_url);
+    let changes = diff_versions(&from, &to);
+    if changes.is_empty() {
+        println!("No differences.");
+    }
+    for change in changes {
+        let get = |property: &str| change.get(property).map(|v| v.to_string());
+        let property = get(urls::DIFF_PROPERTY).unwrap_or_default();
+        let name = context
+            .store
+            .get_property(&property)
+            .map(|p| p.shortname)
+            .unwrap_or(property);
+        // Indent multi-line values, such as markdown
+        let indent = |value: Option<String>| value.unwrap_or_default().replace('\n', "\n      ");
+        let old = indent(get(urls::DIFF_OLD_VALUE));
+        let new = indent(get(urls::DIFF_NEW_VALUE));
+        match get(urls::DIFF_KIND).as_deref() {
+            Some("added") => println!("{}", format!("+ {}: {}", name, new).green()),
+            Some("removed") => println!("{}", format!("- {}: {}", name, old).red()),
+            _changed => {
+                println!("{} {}:", "~".yellow(), name.bold());
+                println!("    {}", format!("- {}", old).red());
+                println!("    {}", format!("+ {}", new).green());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Versions are constructed by the server, using the `/version` endpoint.
+/// Accepts Commit URLs, as well as version URLs.
+fn version_url(server: &str, commit: &str) -> AtomicResult<String> {
+    if commit.contains("/version?") {
+        return Ok(commit.into());
+    }
+    let mut url = url::Url::parse(&format!("{}/version", server))?;
+    url.query_pairs_mut().append_pair("commit", commit);
+    Ok(url.to_string())
+}
@@ -0,0 +1,99 @@
+//! Exports the resources of an Atomic-Server (or a part of it) as JSON-AD, Turtle or CSV.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::Context;
+use atomic_lib::{
+    convert::ImportFormat, errors::AtomicResult, serialize, urls, Resource, Storelike,
+};
+
+/// How often the progress is printed, in resources.
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Prints the resources to stdout. The progress is printed to stderr, so the output can be piped to a file.
+pub fn export(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("export").unwrap();
+    let subtree = matches.get_one::<String>("subtree");
+    let format = match matches.get_one::<String>("format") {
+        Some(name) => ImportFormat::from_name(name)?,
+        None => ImportFormat::JsonAd,
+    };
+
+    #[cfg(feature = "db")]
+    if let Some(db) = crate::import::open_db(matches)? {
+        // Without a subtree, only export the resources of the server itself
+        let resources: Vec<Resource> = db
+            .all_resources(subtree.is_some())
+            .filter(|r| subtree.is_none_or(|s| r.get_subject() == s || r.has_parent(&db, s)))
+            .collect();
+        eprintln!("Exporting {} resources...", resources.len());
+        println!("{}", serialize_resources(&resources, format, &db)?);
+        return Ok(());
+    }
+    #[cfg(not(feature = "db"))]
+    if matches.contains_id("db") {
+        return Err("Exporting a database requires the `db` feature.".into());
+    }
+
+    let root = match subtree {
+        Some(subtree) => subtree.clone(),
+        None => crate::read_config()
+            .map(|config| config.server)
+            .ok_or("Pass a --subtree, or create a config with a server URL.")?,
+    };
+    let resources = fetch_subtree(context, &root)?;
+    println!(
+        "{}",
+        serialize_resources(&resources, format, &context.store)?
+    );
+    Ok(())
+}
+
+/// Fetches the resource and all of its children, using the Agent from the config if there is one.
+fn fetch_subtree(context: &Context, root: &str) -> AtomicResult<Vec<Resource>> {
+    let agent = context.get_agent_if_configured()?;
+    let mut resources = Vec::new();
+    let mut seen = HashSet::from([root.to_string()]);
+    let mut queue = VecDeque::from([root.to_string()]);
+    while let Some(subject) = queue.pop_front() {
+        let mut resource =
+            atomic_lib::client::fetch_resource(&subject, &context.store, agent.clone())?;
+        if let Ok(children) = resource
+            .get(urls::CHILDREN)
+            .and_then(|v| v.to_subjects(None))
+        {
+            for child in children {
+                if seen.insert(child.clone()) {
+                    queue.push_back(child);
+                }
+            }
+        }
+        // Children are calculated by the server, they are not part of the data
+        resource.remove_propval(urls::CHILDREN);
+        resources.push(resource);
+        if resources.len() % PROGRESS_INTERVAL == 0 {
+            eprintln!(
+                "Fetched {} resources, {} to go...",
+                resources.len(),
+                queue.len()
+            );
+        }
+    }
+    eprintln!("Fetched {} resources.", resources.len());
+    Ok(resources)
+}
+
+fn serialize_resources(
+    resources: &[Resource],
+    format: ImportFormat,
+    store: &impl Storelike,
+) -> AtomicResult<String> {
+    match format {
+        ImportFormat::JsonAd => serialize::resources_to_json_ad(resources),
+        ImportFormat::Turtle => {
+            let atoms = resources.iter().flat_map(|r| r.to_atoms()).collect();
+            serialize::atoms_to_turtle(atoms, store)
+        }
+        ImportFormat::Csv => serialize::resources_to_csv(resources),
+    }
+}
@@ -0,0 +1,193 @@
+//! Imports JSON-AD, Turtle or CSV into an Atomic-Server, either by posting Commits or by writing to its database directly.
+
+use crate::Context;
+use atomic_lib::{
+    convert::{self, ImportFormat},
+    errors::AtomicResult,
+    parse::{ImportFailure, ImportReport, ParseOpts, SaveOpts},
+    Resource, Storelike,
+};
+use clap::ArgMatches;
+
+/// Opens the database of a (stopped) Atomic-Server, if the `--db` argument is passed.
+/// The `--server-url` has to match the server URL of the Atomic-Server.
+#[cfg(feature = "db")]
+pub fn open_db(matches: &ArgMatches) -> AtomicResult<Option<atomic_lib::Db>> {
+    let Some(path) = matches.get_one::<String>("db") else {
+        return Ok(None);
+    };
+    let server_url = matches
+        .get_one::<String>("server-url")
+        .expect("server-url has a default value");
+    let db = atomic_lib::Db::init(std::path::Path::new(path), server_url.into())?;
+    // A new database needs the default Properties, which Atomic-Server adds on its first run
+    if db.all_resources(false).next().is_none() {
+        atomic_lib::populate::populate_default_store(&db)?;
+    }
+    Ok(Some(db))
+}
+
+/// Reads the `--format` argument, or uses the extension of the source.
+pub fn get_format(matches: &ArgMatches, source: &str) -> AtomicResult<ImportFormat> {
+    match matches.get_one::<String>("format") {
+        Some(name) => ImportFormat::from_name(name),
+        None => Ok(ImportFormat::from_url(source)),
+    }
+}
+
+fn print_progress(report: &ImportReport, total: usize) {
+    eprintln!(
+        "Parsed {} of {} resources, {} failed...",
+        report.imported + report.failures.len(),
+        total,
+        report.failures.len()
+    );
+}
+
+/// Imports a file or URL. Resources that fail are skipped and listed afterwards.
+pub fn import(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("import").unwrap();
+    let source = matches
+        .get_one::<String>("source")
+        .expect("source is required");
+    let format = get_format(matches, source)?;
+    let data = if source.starts_with("http") {
+        atomic_lib::client::fetch_body(source, format.mime(), None)?
+    } else {
+        std::fs::read_to_string(source).map_err(|e| format!("Could not read {}. {}", source, e))?
+    };
+
+    #[cfg(feature = "db")]
+    let report = match open_db(matches)? {
+        Some(db) => import_local(&db, matches, &data, format, context)?,
+        None => import_remote(context, matches, &data, format)?,
+    };
+    #[cfg(not(feature = "db"))]
+    let report = {
+        if matches.contains_id("db") {
+            return Err("Importing into a database requires the `db` feature.".into());
+        }
+        import_remote(context, matches, &data, format)?
+    };
+
+    for failure in &report.failures {
+        let subject = failure.subject.as_deref().unwrap_or("unknown subject");
+        // Resources that could not be posted have no line
+        if failure.line > 0 {
+            eprintln!(
+                "Failed to import resource at line {} ({}): {}",
+                failure.line, subject, failure.error
+            );
+        } else {
+            eprintln!("Failed to import {}: {}", subject, failure.error);
+        }
+    }
+    println!(
+        "Imported {} resources from {}, {} failed.",
+        report.imported,
+        source,
+        report.failures.len()
+    );
+    if !report.failures.is_empty() {
+        return Err(format!("{} resources could not be imported.", report.failures.len()).into());
+    }
+    Ok(())
+}
+
+/// Writes the resources to the database. Creates Commits signed by the Agent from the config, unless `--force` is passed.
+#[cfg(feature = "db")]
+fn import_local(
+    db: &atomic_lib::Db,
+    matches: &ArgMatches,
+    data: &str,
+    format: ImportFormat,
+    context: &Context,
+) -> AtomicResult<ImportReport> {
+    let force = matches.get_flag("force");
+    let signer = if force {
+        None
+    } else {
+        context.get_write_context();
+        let agent = context.store.get_default_agent()?;
+        db.set_default_agent(agent.clone());
+        Some(agent)
+    };
+    let importer = match matches.get_one::<String>("parent") {
+        Some(parent) => parent.clone(),
+        None => atomic_lib::urls::construct_path_import(&db.get_self_url().expect("No self url")),
+    };
+    let parse_opts = ParseOpts {
+        importer: Some(importer),
+        for_agent: None,
+        overwrite_outside: true,
+        save: if force {
+            SaveOpts::Save
+        } else {
+            SaveOpts::Commit
+        },
+        signer,
+    };
+    let json = convert::to_json_ad(data, format, db)?;
+    let (_resources, report) = atomic_lib::parse::parse_json_ad_string_tolerant_with_progress(
+        &json,
+        db,
+        &parse_opts,
+        &mut print_progress,
+    )?;
+    Ok(report)
+}
+
+/// Posts a Commit for every resource to the server that hosts it.
+fn import_remote(
+    context: &Context,
+    matches: &ArgMatches,
+    data: &str,
+    format: ImportFormat,
+) -> AtomicResult<ImportReport> {
+    let write_ctx = context.get_write_context();
+    let agent = context.store.get_default_agent()?;
+    let importer = matches
+        .get_one::<String>("parent")
+        .cloned()
+        .unwrap_or(write_ctx.server);
+    let parse_opts = ParseOpts {
+        importer: Some(importer),
+        // The server checks the rights when the Commits are posted
+        for_agent: None,
+        overwrite_outside: false,
+        save: SaveOpts::DontSave,
+        signer: None,
+    };
+    let json = convert::to_json_ad(data, format, &context.store)?;
+    let (resources, mut report) = atomic_lib::parse::parse_json_ad_string_tolerant_with_progress(
+        &json,
+        &context.store,
+        &parse_opts,
+        &mut print_progress,
+    )?;
+
+    report.imported = 0;
+    let total = resources.len();
+    for (index, imported) in resources.into_iter().enumerate() {
+        let subject = imported.get_subject().clone();
+        // Existing resources need their `lastCommit`, which is used as the `previousCommit`
+        let mut resource =
+            atomic_lib::client::fetch_resource(&subject, &context.store, Some(agent.clone()))
+                .unwrap_or_else(|_| Resource::new(subject.clone()));
+        for (property, value) in imported.get_propvals() {
+            resource.set_propval_unsafe(property.clone(), value.clone());
+        }
+        match resource.save(&context.store) {
+            Ok(_) => report.imported += 1,
+            Err(e) => report.failures.push(ImportFailure {
+                line: 0,
+                subject: Some(subject),
+                error: e.to_string(),
+            }),
+        }
+        if (index + 1) % atomic_lib::parse::PROGRESS_INTERVAL == 0 {
+            eprintln!("Posted {} of {} resources...", index + 1, total);
+        }
+    }
+    Ok(report)
+}
@@ -2,16 +2,30 @@ use atomic_lib::{agents::generate_public_key, mapping::Mapping};
 use atomic_lib::{agents::Agent, config::Config};
 use atomic_lib::{errors::AtomicResult, Storelike};
 use clap::{crate_version, Arg, ArgMatches, Command};
-use colored::*;
 use dirs::home_dir;
 use std::{cell::RefCell, path::PathBuf, sync::Mutex};
 
 use crate::print::SERIALIZE_OPTIONS;
 
+mod agent;
+mod bulk;
 mod commit;
+#[cfg(feature = "native")]
+mod diff;
+#[cfg(feature = "native")]
+mod export;
+#[cfg(feature = "native")]
+mod import;
+mod map;
 mod new;
 mod path;
 mod print;
+mod query;
+mod render;
+mod serve;
+mod sync;
+mod validate;
+mod watch;
 
 #[allow(dead_code)]
 /// The Context contains all the data for executing a single CLI command, such as the passed arguments and the in memory store.
@@ -43,6 +57,22 @@ impl Context {
         });
         write_ctx
     }
+
+    /// Returns the Agent from the config file, without prompting the user if there is none.
+    /// Used for reading data that might not be public.
+    pub fn get_agent_if_configured(&self) -> AtomicResult<Option<Agent>> {
+        if read_config().is_none() {
+            return Ok(None);
+        }
+        self.get_write_context();
+        Ok(Some(self.store.get_default_agent()?))
+    }
+}
+
+/// Reads the config file, if it exists
+pub fn read_config() -> Option<Config> {
+    let path = atomic_lib::config::default_config_file_path().ok()?;
+    atomic_lib::config::read_config(&path).ok()
 }
 
 /// Reads config files for writing data, or promps the user if they don't yet exist
@@ -77,13 +107,114 @@ fn main() -> AtomicResult<()> {
         .about("Create, share, fetch and model Atomic Data!")
         .after_help("Visit https://atomicdata.dev for more info")
         .arg_required_else_help(true)
+        .arg(Arg::new("remote")
+            .long("remote")
+            .help("Don't keep a local store: read, write and query the server from your config directly")
+            .action(clap::ArgAction::SetTrue)
+            .global(true)
+        )
+        .subcommand(
+            Command::new("agent")
+                .about("Create, list, switch, export and import the Agents that sign your Commits.")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("new")
+                        .about("Generate a keypair for a new Agent, publish it to the server and save it")
+                        .arg(Arg::new("name")
+                            .long("name")
+                            .help("Name of the Agent, also used for `agent use`")
+                            .num_args(1)
+                        )
+                        .arg(Arg::new("server")
+                            .long("server")
+                            .help("Server that hosts the Agent. Defaults to the server from your config.")
+                            .num_args(1)
+                        )
+                        .arg(Arg::new("no-publish")
+                            .long("no-publish")
+                            .help("Only save the Agent locally, don't create it on the server")
+                            .action(clap::ArgAction::SetTrue)
+                        )
+                )
+                .subcommand(Command::new("list").about("List the saved Agents. The active Agent is marked with *."))
+                .subcommand(
+                    Command::new("use")
+                        .about("Sign Commits with another saved Agent")
+                        .arg(Arg::new("agent")
+                            .help("Name or subject of the Agent")
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("Print the secret of an Agent, which can be used to sign in elsewhere")
+                        .arg(Arg::new("agent")
+                            .help("Name or subject of the Agent. Defaults to the active Agent.")
+                        )
+                        .arg(Arg::new("passphrase")
+                            .long("passphrase")
+                            .help("Encrypt the secret with this passphrase")
+                            .num_args(1)
+                        )
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Save an Agent from its secret")
+                        .arg(Arg::new("secret")
+                            .help("The secret, as printed by `agent export` or shown in the Atomic Data Browser")
+                            .required(true)
+                        )
+                        .arg(Arg::new("name")
+                            .long("name")
+                            .help("Name of the Agent, also used for `agent use`")
+                            .num_args(1)
+                        )
+                        .arg(Arg::new("passphrase")
+                            .long("passphrase")
+                            .help("Passphrase of an encrypted secret. Asks for it if it's left out.")
+                            .num_args(1)
+                        )
+                )
+                .subcommand(Command::new("rotate-key").about("Replace the key of the active Agent. Commits signed with the old key stay valid."))
+        )
+        .subcommand(
+            Command::new("bulk")
+                .about("Apply many set and remove operations from stdin or a CSV file, using one Commit per Resource.")
+                .after_help("\
+                    Every line is `subject,property,value`, with CSV quoting for values that contain commas. \
+                    Lines without a value remove the Property. A `subject,property,value` header is skipped. \n\n\
+                    Examples: \n\n\
+                    $ atomic bulk changes.csv --dry-run\n\
+                    $ echo 'https://example.com/page,description,Fixed typo' | atomic bulk \
+                    ")
+                .arg(Arg::new("file")
+                    .help("The CSV file with the operations. Reads stdin if it's left out.")
+                )
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("Only print the operations, don't post any Commits")
+                    .action(clap::ArgAction::SetTrue)
+                )
+        )
         .subcommand(
             Command::new("new").about("Create a Resource")
+            .after_help("\
+                Asks for the required and recommended Properties of the Class, and checks the values using their datatypes. \
+                Creates the Resource by signing a Commit with the Agent from your config. \n\n\
+                Example: \n\n\
+                $ atomic new https://atomicdata.dev/classes/Article --parent https://example.com/my-folder \
+                ")
             .arg(
                 Arg::new("class")
                     .help("The URL or shortname of the Class that should be created")
                     .required(true),
             )
+            .arg(
+                Arg::new("parent")
+                    .long("parent")
+                    .help("URL or bookmark of the parent of the new Resource. Asks for it if it's left out.")
+                    .num_args(1),
+            )
         )
         .subcommand(
             Command::new("get")
@@ -94,7 +225,9 @@ fn main() -> AtomicResult<()> {
                     Examples: \n\n\
                     $ atomic get class https://atomicdata.dev/properties/description\n\
                     $ atomic get class description\n\
-                    $ atomic get https://example.com \n\n\
+                    $ atomic get https://example.com \n\
+                    $ atomic get https://atomicdata.dev/classes --as table\n\
+                    $ atomic get https://example.com --as tree --depth 2 \n\n\
                     Visit https://docs.atomicdata.dev/core/paths.html for more info about paths. \
                     ")
                 .arg(Arg::new("path")
@@ -111,7 +244,14 @@ fn main() -> AtomicResult<()> {
                     .long("as")
                     .value_parser(SERIALIZE_OPTIONS)
                     .default_value("pretty")
-                    .help("Serialization format")
+                    .help("Serialization format. `table` shows the members or children as columns, `tree` shows the hierarchy.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("depth")
+                    .long("depth")
+                    .value_parser(clap::value_parser!(u64).range(1..))
+                    .default_value("3")
+                    .help("Amount of levels shown by --as tree")
                     .num_args(1)
                 )
         )
@@ -145,14 +285,19 @@ fn main() -> AtomicResult<()> {
         )
         .subcommand(
             Command::new("edit")
-                .about("Edit a single Atom from a Resource using your text editor.")
+                .about("Edit a Resource, or a single Atom from a Resource, using your text editor.")
+                .after_help("\
+                    Without a property, opens the Resource as JSON in $EDITOR and posts a Commit with the changes. \n\n\
+                    Examples: \n\n\
+                    $ atomic edit https://example.com/my-page\n\
+                    $ atomic edit https://example.com/my-page description \
+                    ")
                 .arg(Arg::new("subject")
                     .help("Subject URL or bookmark of the resource")
                     .required(true)
                 )
                 .arg(Arg::new("property")
-                    .help("Property URL or shortname of the property to be edited")
-                    .required(true)
+                    .help("Property URL or shortname of the property to be edited. Edits the whole Resource if left out.")
                 )
         )
         .subcommand(
@@ -163,8 +308,330 @@ fn main() -> AtomicResult<()> {
                     .required(true)
                 )
         )
+        .subcommand(
+            Command::new("diff")
+                .about("Show which Properties differ between two Resources, or between two versions of a Resource.")
+                .after_help("\
+                    Examples: \n\n\
+                    $ atomic diff https://staging.example.com/config https://example.com/config\n\
+                    $ atomic diff https://example.com/config --versions https://example.com/commits/a https://example.com/commits/b \
+                    ")
+                .arg(Arg::new("subject-a")
+                    .help("Subject URL or bookmark of the first resource")
+                    .required(true)
+                )
+                .arg(Arg::new("subject-b")
+                    .help("Subject URL or bookmark of the second resource")
+                    .required_unless_present("versions")
+                    .conflicts_with("versions")
+                )
+                .arg(Arg::new("versions")
+                    .long("versions")
+                    .help("Compare two versions of the first resource, using their Commit URLs")
+                    .num_args(2)
+                    .value_names(["from", "to"])
+                )
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import JSON-AD, Turtle or CSV by posting Commits to a server, or into the database of a stopped Atomic-Server.")
+                .after_help("\
+                    Examples: \n\n\
+                    $ atomic import data.json --parent https://example.com/my-folder\n\
+                    $ atomic import https://example.com/data.ttl\n\
+                    $ atomic import data.csv --db ~/.local/share/atomic-data/db \
+                    ")
+                .arg(Arg::new("source")
+                    .help("Path or URL of the data")
+                    .required(true)
+                )
+                .arg(Arg::new("format")
+                    .long("format")
+                    .value_parser(["json-ad", "turtle", "csv"])
+                    .help("Format of the data. Uses the file extension by default, or JSON-AD.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("parent")
+                    .long("parent")
+                    .help("Parent for resources that have a localId instead of an @id. Defaults to the server from your config.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("db")
+                    .long("db")
+                    .help("Path to the database of an Atomic-Server, which has to be stopped. Writes to the database directly instead of posting Commits.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("server-url")
+                    .long("server-url")
+                    .help("Server URL of the database passed to --db")
+                    .default_value("http://localhost:9883")
+                    .num_args(1)
+                )
+                .arg(Arg::new("force")
+                    .long("force")
+                    .help("Together with --db, saves the resources without creating Commits. Faster, but skips validation and leaves no history.")
+                    .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export resources from a server, or from the database of a stopped Atomic-Server, and print them.")
+                .after_help("\
+                    Examples: \n\n\
+                    $ atomic export --subtree https://example.com/my-folder > my-folder.json\n\
+                    $ atomic export --db ~/.local/share/atomic-data/db --format turtle > export.ttl \
+                    ")
+                .arg(Arg::new("subtree")
+                    .long("subtree")
+                    .help("Only export this resource and its children. Defaults to the server from your config.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("format")
+                    .long("format")
+                    .value_parser(["json-ad", "turtle", "csv"])
+                    .default_value("json-ad")
+                    .help("Serialization format")
+                    .num_args(1)
+                )
+                .arg(Arg::new("db")
+                    .long("db")
+                    .help("Path to the database of an Atomic-Server, which has to be stopped. Exports all resources of the server, or the resources in --subtree.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("server-url")
+                    .long("server-url")
+                    .help("Server URL of the database passed to --db")
+                    .default_value("http://localhost:9883")
+                    .num_args(1)
+                )
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Find the instances of a Class, filter and sort them, and print them as a table, CSV or JSON-AD.")
+                .after_help("\
+                    Filters are checked for every resource, and can use =, !=, >, >=, <, <= and ~ (contains). \
+                    Numbers are compared as numbers, everything else as text. \n\n\
+                    Examples: \n\n\
+                    $ atomic query --class https://example.com/classes/Person --filter \"age>30\" --sort name --format csv\n\
+                    $ atomic query --class agent --properties name --db ~/.local/share/atomic-data/db \
+                    ")
+                .arg(Arg::new("class")
+                    .long("class")
+                    .help("URL or bookmark of the Class")
+                    .required(true)
+                    .num_args(1)
+                )
+                .arg(Arg::new("filter")
+                    .long("filter")
+                    .help("Condition such as `age>30`, using a Property shortname or URL. Can be passed multiple times.")
+                    .action(clap::ArgAction::Append)
+                    .num_args(1)
+                )
+                .arg(Arg::new("sort")
+                    .long("sort")
+                    .help("Property shortname or URL to sort by")
+                    .num_args(1)
+                )
+                .arg(Arg::new("desc")
+                    .long("desc")
+                    .help("Sort descending")
+                    .action(clap::ArgAction::SetTrue)
+                )
+                .arg(Arg::new("limit")
+                    .long("limit")
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Maximum amount of results")
+                    .num_args(1)
+                )
+                .arg(Arg::new("properties")
+                    .long("properties")
+                    .help("Properties to show as columns, separated by commas. Defaults to the required and recommended Properties of the Class.")
+                    .value_delimiter(',')
+                    .num_args(1)
+                )
+                .arg(Arg::new("format")
+                    .long("format")
+                    .value_parser(["table", "csv", "json"])
+                    .default_value("table")
+                    .help("Output format. JSON-AD contains all properties.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("include-external")
+                    .long("include-external")
+                    .help("Include resources that are not hosted by the server, such as the default Properties")
+                    .action(clap::ArgAction::SetTrue)
+                )
+                .arg(Arg::new("server")
+                    .long("server")
+                    .help("Server to query. Defaults to the server from your config.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("db")
+                    .long("db")
+                    .help("Path to the database of an Atomic-Server, which has to be stopped. Queries the database instead of a server.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("server-url")
+                    .long("server-url")
+                    .help("Server URL of the database passed to --db")
+                    .default_value("http://localhost:9883")
+                    .num_args(1)
+                )
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Print the Commits to a Resource (and its children) or to instances of a Class, as they come in.")
+                .after_help("\
+                    Polls the /commits endpoint of the server. Uses the Agent from your config, if there is one. \n\n\
+                    Examples: \n\n\
+                    $ atomic watch https://example.com/my-folder\n\
+                    $ atomic watch --class https://atomicdata.dev/classes/Agent --as json | jq . \
+                    ")
+                .arg(Arg::new("subject")
+                    .help("Subject URL or bookmark of the resource")
+                    .required_unless_present("class")
+                )
+                .arg(Arg::new("class")
+                    .long("class")
+                    .help("Only print Commits to instances of this Class")
+                    .num_args(1)
+                )
+                .arg(Arg::new("server")
+                    .long("server")
+                    .help("Server to watch. Defaults to the server of the subject, or the server from your config.")
+                    .num_args(1)
+                )
+                .arg(Arg::new("as")
+                    .long("as")
+                    .value_parser(["pretty", "json"])
+                    .default_value("pretty")
+                    .help("Print the changes, or every Commit as a line of JSON-AD")
+                    .num_args(1)
+                )
+                .arg(Arg::new("interval")
+                    .long("interval")
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("2")
+                    .help("Seconds between polls")
+                    .num_args(1)
+                )
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve the local store over HTTP, for developing client apps without running Atomic-Server.")
+                .after_help("\
+                    Resources are served at their subject, and Commits can be posted to /commit. \
+                    There is no authorization and nothing is persisted, so only use it for local development. \n\n\
+                    Example: \n\n\
+                    $ atomic serve --port 9883 \
+                    ")
+                .arg(Arg::new("port")
+                    .long("port")
+                    .value_parser(clap::value_parser!(u16))
+                    .default_value("9883")
+                    .help("Port to listen on, at localhost")
+                    .num_args(1)
+                )
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Sync the markdown and JSON-AD files in a directory with the children of a Drive, in both directions.")
+                .after_help("\
+                    Resources with a description become markdown files, with the other Properties in the front matter. \
+                    Other resources become JSON-AD files. \
+                    Changes are detected using the lastCommit of the resources and the modification times of the files, \
+                    which are stored in .atomic-sync.json. \n\n\
+                    Example: \n\n\
+                    $ atomic sync ./content https://example.com/my-drive \
+                    ")
+                .arg(Arg::new("dir")
+                    .help("The directory with the files")
+                    .required(true)
+                )
+                .arg(Arg::new("drive")
+                    .help("Subject URL or bookmark of the Drive (or any other parent)")
+                    .required(true)
+                )
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("Only print what would be synced")
+                    .action(clap::ArgAction::SetTrue)
+                )
+                .arg(Arg::new("prefer")
+                    .long("prefer")
+                    .value_parser(["local", "remote"])
+                    .help("Resolve conflicts by keeping the local files, or the remote resources")
+                    .num_args(1)
+                )
+        )
         .subcommand(Command::new("list").about("List all bookmarks"))
-        .subcommand(Command::new("validate").about("Validates the store").hide(true))
+        .subcommand(
+            Command::new("map")
+                .about("Add, remove and list bookmarks: shortnames for URLs, which can also be used as prefixes.")
+                .after_help("\
+                    Bookmarks can be used instead of URLs in most commands. \
+                    A bookmark that points to a namespace can be used as a prefix in `get`, `set` and paths. \n\n\
+                    Examples: \n\n\
+                    $ atomic map add schema https://schema.org/\n\
+                    $ atomic get \"my-page schema:name\" \
+                    ")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a bookmark, or replace an existing one")
+                        .arg(Arg::new("shortname")
+                            .help("The shortname of the bookmark, or the prefix")
+                            .required(true)
+                        )
+                        .arg(Arg::new("url")
+                            .help("The URL, or the namespace that the prefix expands to")
+                            .required(true)
+                        )
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a bookmark")
+                        .arg(Arg::new("shortname")
+                            .help("The shortname of the bookmark")
+                            .required(true)
+                        )
+                )
+                .subcommand(Command::new("list").about("List all bookmarks"))
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check a JSON-AD file against the schema: datatypes, required Properties and unknown Properties.")
+                .after_help("\
+                    Exits with 0 if the file is valid, 1 if there are issues, and 2 if the file can't be read. \
+                    Without a file, every resource in the store (or the database passed to --db) is checked, \
+                    and the report lists errors and warnings per resource. Only errors make the store invalid. \n\n\
+                    Example: \n\n\
+                    $ atomic validate data.json --format json \n\
+                    $ atomic validate --db ~/.local/share/atomic-data/db --format json \
+                    ")
+                .arg(Arg::new("file")
+                    .help("The JSON-AD file to check")
+                )
+                .arg(Arg::new("format")
+                    .long("format")
+                    .value_parser(["text", "json"])
+                    .default_value("text")
+                    .help("Print the issues as lines of text, or as a JSON report")
+                    .num_args(1)
+                )
+                .arg(Arg::new("db")
+                    .long("db")
+                    .help("Path to the database of an Atomic-Server, which has to be stopped. Validates all resources in it.")
+                    .conflicts_with("file")
+                    .num_args(1)
+                )
+                .arg(Arg::new("server-url")
+                    .long("server-url")
+                    .help("Server URL of the database passed to --db")
+                    .default_value("http://localhost:9883")
+                    .num_args(1)
+                )
+        )
         .get_matches();
 
     let config_folder = home_dir()
@@ -180,8 +647,16 @@ fn main() -> AtomicResult<()> {
         mapping.read_mapping_from_file(&user_mapping_path)?;
     }
 
-    // Initialize an in-memory store
-    let store = atomic_lib::Store::init()?;
+    // Initialize an in-memory store, or a thin client for the configured server
+    let store = if matches.get_flag("remote") {
+        let Some(config) = read_config() else {
+            eprintln!("The --remote flag requires a config with a server URL. Run `atomic-cli agent new` first.");
+            std::process::exit(1);
+        };
+        atomic_lib::Store::init_remote(&config.server)?
+    } else {
+        atomic_lib::Store::init()?
+    };
     // Add some default data / common properties to speed things up
     store.populate()?;
 
@@ -193,6 +668,10 @@ fn main() -> AtomicResult<()> {
         user_mapping_path,
         write: RefCell::new(None),
     };
+    // Everything a remote store does is a request to the server, so it should always be signed
+    if context.matches.get_flag("remote") {
+        context.get_write_context();
+    }
 
     match exec_command(&mut context) {
         Ok(r) => r,
@@ -207,9 +686,25 @@ fn main() -> AtomicResult<()> {
 
 fn exec_command(context: &mut Context) -> AtomicResult<()> {
     match context.matches.subcommand_name() {
+        Some("agent") => {
+            agent::agent(context)?;
+        }
+        Some("bulk") => {
+            bulk::bulk(context)?;
+        }
         Some("destroy") => {
             commit::destroy(context)?;
         }
+        Some("diff") => {
+            #[cfg(feature = "native")]
+            {
+                diff::diff(context)?;
+            }
+            #[cfg(not(feature = "native"))]
+            {
+                return Err("Feature not available. Compile with `native` feature.".into());
+            }
+        }
         Some("edit") => {
             #[cfg(feature = "native")]
             {
@@ -220,23 +715,58 @@ fn exec_command(context: &mut Context) -> AtomicResult<()> {
                 return Err("Feature not available. Compile with `native` feature.".into());
             }
         }
+        Some("export") => {
+            #[cfg(feature = "native")]
+            {
+                export::export(context)?;
+            }
+            #[cfg(not(feature = "native"))]
+            {
+                return Err("Feature not available. Compile with `native` feature.".into());
+            }
+        }
         Some("get") => {
             path::get_path(context)?;
         }
+        Some("import") => {
+            #[cfg(feature = "native")]
+            {
+                import::import(context)?;
+            }
+            #[cfg(not(feature = "native"))]
+            {
+                return Err("Feature not available. Compile with `native` feature.".into());
+            }
+        }
         Some("list") => {
-            list(context);
+            map::list(context);
+        }
+        Some("map") => {
+            map::map(context)?;
         }
         Some("new") => {
             new::new(context)?;
         }
+        Some("query") => {
+            query::query(context)?;
+        }
         Some("remove") => {
             commit::remove(context)?;
         }
         Some("set") => {
             commit::set(context)?;
         }
+        Some("serve") => {
+            serve::serve(context)?;
+        }
+        Some("sync") => {
+            sync::sync(context)?;
+        }
         Some("validate") => {
-            validate(context);
+            validate::validate(context)?;
+        }
+        Some("watch") => {
+            watch::watch(context)?;
         }
         Some(cmd) => {
             return Err(format!("{} is not a valid command. Run atomic --help", cmd).into())
@@ -246,23 +776,4 @@ fn exec_command(context: &mut Context) -> AtomicResult<()> {
     Ok(())
 }
 
-/// List all bookmarks
-fn list(context: &mut Context) {
-    let mut string = String::new();
-    for (shortname, url) in context.mapping.lock().unwrap().clone().into_iter() {
-        string.push_str(&format!(
-            "{0: <15}{1: <10} \n",
-            shortname.blue().bold(),
-            url
-        ));
-    }
-    println!("{}", string)
-}
-
-/// Validates the store
-fn validate(context: &mut Context) {
-    let reportstring = context.store.validate().to_string();
-    println!("{}", reportstring);
-}
-
 pub type CLIResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
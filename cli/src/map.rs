@@ -0,0 +1,76 @@
+//! Manages the bookmarks (shortnames for URLs) in `~/.config/atomic/mapping.amp`.
+//! Bookmarks that point to a namespace, such as `schema=https://schema.org/`, can be used as prefixes: `schema:name`.
+
+use crate::Context;
+use atomic_lib::{errors::AtomicResult, mapping::is_url};
+use colored::*;
+
+pub fn map(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("map").unwrap();
+    match matches.subcommand() {
+        Some(("add", sub)) => add(
+            context,
+            sub.get_one::<String>("shortname").unwrap(),
+            sub.get_one::<String>("url").unwrap(),
+        ),
+        Some(("rm", sub)) => remove(context, sub.get_one::<String>("shortname").unwrap()),
+        Some(("list", _sub)) => {
+            list(context);
+            Ok(())
+        }
+        _ => Err("Run `atomic-cli map --help` for the available commands.".into()),
+    }
+}
+
+/// Adds a bookmark, or overwrites an existing one.
+fn add(context: &Context, shortname: &str, url: &str) -> AtomicResult<()> {
+    if shortname.is_empty() || shortname.contains(['=', ':', ' ']) {
+        return Err(format!(
+            "Shortname '{}' can't be empty or contain '=', ':' or spaces.",
+            shortname
+        )
+        .into());
+    }
+    if !is_url(url) {
+        return Err(format!("'{}' is not a URL.", url).into());
+    }
+    let mut mapping = context.mapping.lock().unwrap();
+    if let Some(old) = mapping.get(shortname) {
+        println!("Replacing {} ({})", shortname, old);
+    }
+    mapping.insert(shortname.into(), url.into());
+    mapping.write_mapping_to_disk(&context.user_mapping_path);
+    println!("{} {}={}", "added".green(), shortname, url);
+    Ok(())
+}
+
+fn remove(context: &Context, shortname: &str) -> AtomicResult<()> {
+    let mut mapping = context.mapping.lock().unwrap();
+    let url = mapping
+        .remove(shortname)
+        .ok_or(format!("There is no bookmark named '{}'.", shortname))?;
+    mapping.write_mapping_to_disk(&context.user_mapping_path);
+    println!("{} {}={}", "removed".red(), shortname, url);
+    Ok(())
+}
+
+/// Prints all bookmarks, sorted by shortname
+pub fn list(context: &Context) {
+    let mut bookmarks: Vec<(String, String)> = context
+        .mapping
+        .lock()
+        .unwrap()
+        .clone()
+        .into_iter()
+        .collect();
+    bookmarks.sort();
+    let mut string = String::new();
+    for (shortname, url) in bookmarks {
+        string.push_str(&format!(
+            "{0: <15}{1: <10} \n",
+            shortname.blue().bold(),
+            url
+        ));
+    }
+    println!("{}", string)
+}
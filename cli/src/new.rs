@@ -5,7 +5,7 @@ use atomic_lib::{
     datatype::DataType,
     errors::AtomicResult,
     schema::{Class, Property},
-    Resource, Storelike, Value,
+    urls, Resource, Storelike, Value,
 };
 use colored::Colorize;
 use promptly::prompt_opt;
@@ -14,21 +14,24 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Create a new instance of some class through a series of prompts, adds it to the store
 pub fn new(context: &mut Context) -> AtomicResult<()> {
-    let class_input = context
-        .matches
-        .subcommand_matches("new")
-        .unwrap()
-        .get_one::<&str>("class")
+    let matches = context.matches.subcommand_matches("new").unwrap();
+    let class_input = matches
+        .get_one::<String>("class")
         .expect("Add a class value");
     let class_url = context
         .mapping
         .lock()
         .unwrap()
         .try_mapping_or_url(class_input)
-        .unwrap();
+        .ok_or(format!("No url found for {}", class_input))?;
     let class = context.store.get_class(&class_url)?;
+    let write_ctx = context.get_write_context();
+    let parent = match matches.get_one::<String>("parent") {
+        Some(_) => crate::commit::argument_to_url(context, "parent")?,
+        None => prompt_parent(context, &write_ctx.server)?,
+    };
     println!("Enter a new {}: {}", class.shortname, class.description);
-    let (resource, _bookmark) = prompt_instance(context, &class, None)?;
+    let (resource, _bookmark) = prompt_instance(context, &class, None, &parent)?;
     println!(
         "Succesfully created a new {}: subject: {}",
         class.shortname,
@@ -37,6 +40,21 @@ pub fn new(context: &mut Context) -> AtomicResult<()> {
     Ok(())
 }
 
+/// Asks for the parent of the new resource, which determines who can read and edit it.
+fn prompt_parent(context: &Context, server: &str) -> CLIResult<String> {
+    let msg = format!("Parent URL or bookmark (optional, defaults to {})", server);
+    loop {
+        let input: Option<String> = prompt_opt(&msg)?;
+        let Some(input) = input else {
+            return Ok(server.into());
+        };
+        match context.mapping.lock().unwrap().try_mapping_or_url(&input) {
+            Some(url) => return Ok(url),
+            None => println!("Bookmark not found, try again."),
+        }
+    }
+}
+
 /// Lets the user enter an instance of an Atomic Class through multiple prompts.
 /// Adds the Resource to the store, and writes to disk.
 /// Returns the Resource, its URL and its Bookmark.
@@ -44,6 +62,7 @@ fn prompt_instance(
     context: &Context,
     class: &Class,
     preferred_shortname: Option<String>,
+    parent: &str,
 ) -> CLIResult<(Resource, Option<String>)> {
     // Not sure about the best way t
     // The Path is the thing at the end of the URL, from the domain
@@ -61,14 +80,19 @@ fn prompt_instance(
     let mut new_resource: Resource = Resource::new(subject.clone());
 
     new_resource.set_propval(
-        "https://atomicdata.dev/properties/isA".into(),
+        urls::IS_A.into(),
         Value::from(vec![class.subject.clone()]),
         &context.store,
     )?;
+    new_resource.set_propval(
+        urls::PARENT.into(),
+        Value::AtomicUrl(parent.into()),
+        &context.store,
+    )?;
 
     for prop_subject in &class.requires {
         let field = context.store.get_property(prop_subject)?;
-        if field.subject == atomic_lib::urls::SHORTNAME && preferred_shortname.clone().is_some() {
+        if field.subject == urls::SHORTNAME && preferred_shortname.clone().is_some() {
             new_resource.set_propval_string(
                 field.subject.clone(),
                 &preferred_shortname.clone().unwrap(),
@@ -80,25 +104,32 @@ fn prompt_instance(
             );
             continue;
         }
+        // The parent is set using the --parent argument
+        if field.subject == urls::PARENT {
+            continue;
+        }
         println!("{}: {}", field.shortname.bold().blue(), field.description);
         // In multiple Properties, the shortname field is required.
         // A preferred shortname can be passed into this function
-        let mut input = prompt_field(&field, false, context)?;
+        let mut input = prompt_field(&field, false, context, parent)?;
         loop {
             if let Some(i) = input {
                 new_resource.set_propval_string(field.subject.clone(), &i, &context.store)?;
                 break;
             } else {
                 println!("Required field, please enter a value.");
-                input = prompt_field(&field, false, context)?;
+                input = prompt_field(&field, false, context, parent)?;
             }
         }
     }
 
     for prop_subject in &class.recommends {
         let field = context.store.get_property(prop_subject)?;
+        if field.subject == urls::PARENT {
+            continue;
+        }
         println!("{}: {}", field.shortname.bold().blue(), field.description);
-        let input = prompt_field(&field, true, context)?;
+        let input = prompt_field(&field, true, context, parent)?;
         if let Some(i) = input {
             new_resource.set_propval_string(field.subject.clone(), &i, &context.store)?;
         }
@@ -118,11 +149,84 @@ fn prompt_instance(
     Ok((new_resource, map))
 }
 
+/// Prompts until the input is a valid value for the datatype, or until nothing is entered.
+fn prompt_valid(msg: &str, datatype: &DataType) -> CLIResult<Option<String>> {
+    loop {
+        let input: Option<String> = prompt_opt(msg)?;
+        let Some(input) = input else {
+            return Ok(None);
+        };
+        match Value::new(&input, datatype) {
+            Ok(_) => return Ok(Some(input)),
+            Err(e) => println!("{} Try again.", e),
+        }
+    }
+}
+
+/// Lets the user pick one (or, for a ResourceArray, multiple) of the allowed values by their number or URL.
+fn prompt_allowed(
+    property: &Property,
+    allowed: &[String],
+    msg_appendix: &str,
+    context: &Context,
+) -> CLIResult<Option<String>> {
+    let is_array = property.data_type == DataType::ResourceArray;
+    for (index, subject) in allowed.iter().enumerate() {
+        let label = context
+            .store
+            .get_resource(subject)
+            .ok()
+            .and_then(|r| {
+                r.get(urls::NAME)
+                    .or_else(|_| r.get(urls::SHORTNAME))
+                    .map(|v| v.to_string())
+                    .ok()
+            })
+            .unwrap_or_default();
+        println!("  {}. {} {}", index + 1, label.bold(), subject);
+    }
+    let msg = if is_array {
+        format!("numbers or URLs, separated by spacebars{}", msg_appendix)
+    } else {
+        format!("number or URL{}", msg_appendix)
+    };
+    'prompt: loop {
+        let input: Option<String> = prompt_opt(&msg)?;
+        let Some(input) = input else {
+            return Ok(None);
+        };
+        let mut chosen = Vec::new();
+        for item in input.split_whitespace() {
+            let subject = match item.parse::<usize>() {
+                Ok(nr) if (1..=allowed.len()).contains(&nr) => allowed[nr - 1].clone(),
+                _ => match context.mapping.lock().unwrap().try_mapping_or_url(item) {
+                    Some(url) if allowed.contains(&url) => url,
+                    _ => {
+                        println!("{} is not one of the options, try again.", item);
+                        continue 'prompt;
+                    }
+                },
+            };
+            chosen.push(subject);
+        }
+        if is_array {
+            return Ok(Some(atomic_lib::serialize::serialize_json_array(&chosen)?));
+        }
+        if chosen.len() != 1 {
+            println!("Choose one of the options.");
+            continue;
+        }
+        return Ok(chosen.pop());
+    }
+}
+
 // Checks the property and its datatype, and issues a prompt that performs validation.
+// Returns None if the user enters nothing, invalid input is asked for again.
 fn prompt_field(
     property: &Property,
     optional: bool,
     context: &Context,
+    parent: &str,
 ) -> CLIResult<Option<String>> {
     let mut input: Option<String> = None;
     let msg_appendix: &str = if optional {
@@ -130,6 +234,9 @@ fn prompt_field(
     } else {
         " (required)"
     };
+    if let Some(allowed) = &property.allows_only {
+        return prompt_allowed(property, allowed, msg_appendix, context);
+    }
     match &property.data_type {
         DataType::String | DataType::Markdown => {
             let msg = format!("string{}", msg_appendix);
@@ -137,23 +244,12 @@ fn prompt_field(
             return Ok(input);
         }
         DataType::Slug => {
-            let msg = format!("slug{}", msg_appendix);
-            input = prompt_opt(msg)?;
-            let re = Regex::new(atomic_lib::values::SLUG_REGEX)?;
-            match input {
-                Some(slug) => {
-                    if re.is_match(&slug) {
-                        return Ok(Some(slug));
-                    }
-                    println!("Only letters, numbers and dashes - no spaces or special characters.");
-                    return Ok(None);
-                }
-                None => return Ok(None),
-            }
+            let msg = format!("slug - only letters, numbers and dashes{}", msg_appendix);
+            return prompt_valid(&msg, &property.data_type);
         }
         DataType::Integer => {
             let msg = format!("integer{}", msg_appendix);
-            let number: Option<u32> = prompt_opt(msg)?;
+            let number: Option<i64> = prompt_opt(msg)?;
             match number {
                 Some(nr) => {
                     input = Some(nr.to_string());
@@ -173,19 +269,19 @@ fn prompt_field(
         }
         DataType::Date => {
             let msg = format!("date YYYY-MM-DD{}", msg_appendix);
-            let date: Option<String> = prompt_opt(msg).unwrap();
-            let re = Regex::new(atomic_lib::values::DATE_REGEX).unwrap();
-            match date {
-                Some(date_val) => {
-                    if re.is_match(&date_val) {
-                        input = Some(date_val);
-                        return Ok(input);
-                    }
-                    println!("Not a valid date.");
-                    return Ok(None);
-                }
-                None => return Ok(None),
-            }
+            return prompt_valid(&msg, &property.data_type);
+        }
+        DataType::GeoPoint => {
+            let msg = format!("location latitude,longitude{}", msg_appendix);
+            return prompt_valid(&msg, &property.data_type);
+        }
+        DataType::Decimal => {
+            let msg = format!("decimal, e.g. 12.50{}", msg_appendix);
+            return prompt_valid(&msg, &property.data_type);
+        }
+        DataType::Bytes => {
+            let msg = format!("base64{}", msg_appendix);
+            return prompt_valid(&msg, &property.data_type);
         }
         DataType::AtomicUrl => loop {
             let msg = format!("URL{}", msg_appendix);
@@ -199,19 +295,28 @@ fn prompt_field(
                     class.shortname, class.subject
                 )
             }
-            let url: Option<String> = prompt_opt(msg).unwrap();
+            let url: Option<String> = prompt_opt(msg)?;
+            let Some(u) = url else {
+                return Ok(None);
+            };
+            // TODO: Check if string or if map
+            let Some(url) = context.mapping.lock().unwrap().try_mapping_or_url(&u) else {
+                println!("Shortname not found, try again.");
+                continue;
+            };
             // If a classtype is present, the given URL must be an instance of that Class
-            if let Some(u) = url {
-                // TODO: Check if string or if map
-                input = context.mapping.lock().unwrap().try_mapping_or_url(&u);
-                match input {
-                    Some(url) => return Ok(Some(url)),
-                    None => {
-                        println!("Shortname not found, try again.");
-                        return Ok(None);
-                    }
+            if let Some(classtype) = classtype {
+                let is_instance = context
+                    .store
+                    .get_resource(&url)
+                    .and_then(|r| r.get_classes(&context.store))
+                    .is_ok_and(|classes| classes.iter().any(|c| &c.subject == classtype));
+                if !is_instance {
+                    println!("{} is not an instance of {}, try again.", url, classtype);
+                    continue;
                 }
             }
+            return Ok(Some(url));
         },
         DataType::ResourceArray => loop {
             let msg = format!(
@@ -243,7 +348,7 @@ fn prompt_field(
                                     item.bold().green(),
                                 );
                                 let (resource, _shortname) =
-                                    prompt_instance(context, class, Some(item.into()))?;
+                                    prompt_instance(context, class, Some(item.into()), parent)?;
                                 urls.push(resource.get_subject().clone());
                                 continue;
                             }
@@ -1,6 +1,6 @@
 use crate::{
     print::{get_serialization, print_resource},
-    Context,
+    render, Context,
 };
 use atomic_lib::{errors::AtomicResult, serialize, storelike, Atom, Storelike};
 use serialize::Format;
@@ -14,11 +14,27 @@ pub fn get_path(context: &mut Context) -> AtomicResult<()> {
         .map(|s| s.to_string())
         .collect();
     let path_string: String = path_vec.join(" ");
-    let serialization: Format = get_serialization(subcommand_matches)?;
 
     // Returns a URL or Value
+    let path =
+        context
+            .store
+            .get_path(&path_string, Some(&context.mapping.lock().unwrap()), None)?;
+    match subcommand_matches
+        .get_one::<String>("as")
+        .map(String::as_str)
+    {
+        Some("table") => return render::table(context, &path),
+        Some("tree") => {
+            let depth = *subcommand_matches
+                .get_one::<u64>("depth")
+                .expect("depth has a default value");
+            return render::tree(context, &path, depth as usize);
+        }
+        _ => {}
+    }
+    let serialization: Format = get_serialization(subcommand_matches)?;
     let store = &mut context.store;
-    let path = store.get_path(&path_string, Some(&context.mapping.lock().unwrap()), None)?;
     let out = match path {
         storelike::PathReturn::Subject(subject) => {
             let resource = store.get_resource_extended(&subject, false, None)?;
@@ -9,8 +9,10 @@ use colored::*;
 use crate::Context;
 
 /// List of serialization options. Should match /path.rs/get
-pub const SERIALIZE_OPTIONS: [&str; 7] =
-    ["pretty", "json", "jsonld", "jsonad", "nt", "turtle", "n3"];
+/// `table` and `tree` are rendered by [crate::render], the others are handled here.
+pub const SERIALIZE_OPTIONS: [&str; 9] = [
+    "pretty", "json", "jsonld", "jsonad", "nt", "turtle", "n3", "table", "tree",
+];
 
 /// Returns preferred serialization format. Defaults to pretty.
 pub fn get_serialization(argmatches: &ArgMatches) -> AtomicResult<Format> {
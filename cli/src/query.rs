@@ -0,0 +1,284 @@
+//! Finds the instances of a Class, filters and sorts them, and prints them as a table, CSV or JSON-AD.
+//! Runs against a Collection of a server, or against the database of a stopped Atomic-Server.
+
+use crate::Context;
+use atomic_lib::{
+    errors::AtomicResult,
+    mapping::is_url,
+    parse::{ParseOpts, SaveOpts},
+    serialize, urls, Resource, Storelike,
+};
+use clap::ArgMatches;
+
+/// Members per page when fetching a remote Collection.
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equals,
+    NotEquals,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Contains,
+}
+
+/// A condition such as `age>30`, which is checked for every resource.
+#[derive(Debug)]
+struct Filter {
+    property: String,
+    operator: Operator,
+    value: String,
+}
+
+impl Filter {
+    /// Parses `{property}{operator}{value}`, where the operator is one of `=`, `!=`, `>`, `>=`, `<`, `<=` or `~` (contains).
+    /// The property can still be a shortname.
+    fn parse(filter: &str) -> AtomicResult<Filter> {
+        let re = regex::Regex::new(r"^\s*([^<>=!~\s]+)\s*(>=|<=|!=|=|>|<|~)\s*(.*?)\s*$").unwrap();
+        let caps = re.captures(filter).ok_or(format!(
+            "Invalid filter '{}'. Use something like `age>30`, `name=Alice` or `description~cats`.",
+            filter
+        ))?;
+        let operator = match &caps[2] {
+            "=" => Operator::Equals,
+            "!=" => Operator::NotEquals,
+            ">" => Operator::Greater,
+            ">=" => Operator::GreaterOrEqual,
+            "<" => Operator::Less,
+            "<=" => Operator::LessOrEqual,
+            _ => Operator::Contains,
+        };
+        Ok(Filter {
+            property: caps[1].to_string(),
+            operator,
+            value: caps[3].to_string(),
+        })
+    }
+
+    /// Numbers are compared as numbers, everything else as text.
+    fn matches(&self, resource: &Resource) -> bool {
+        let Ok(found) = resource.get(&self.property) else {
+            return self.operator == Operator::NotEquals;
+        };
+        let found = found.to_string();
+        let ordering = match (found.parse::<f64>(), self.value.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(found.as_str().cmp(self.value.as_str())),
+        };
+        use std::cmp::Ordering::*;
+        match self.operator {
+            Operator::Equals => ordering == Some(Equal),
+            Operator::NotEquals => ordering != Some(Equal),
+            Operator::Greater => ordering == Some(Greater),
+            Operator::GreaterOrEqual => matches!(ordering, Some(Greater | Equal)),
+            Operator::Less => ordering == Some(Less),
+            Operator::LessOrEqual => matches!(ordering, Some(Less | Equal)),
+            Operator::Contains => found.to_lowercase().contains(&self.value.to_lowercase()),
+        }
+    }
+}
+
+/// Finds the Property URL for a shortname, using the Properties of the Class, the other Properties in the store and the bookmarks.
+pub fn resolve_property(
+    context: &Context,
+    store: &impl Storelike,
+    class: &str,
+    shortname: &str,
+) -> AtomicResult<String> {
+    if is_url(shortname) {
+        return Ok(shortname.into());
+    }
+    if let Ok(class) = store.get_class(class) {
+        for property in class.requires.iter().chain(class.recommends.iter()) {
+            if store
+                .get_property(property)
+                .is_ok_and(|p| p.shortname == shortname)
+            {
+                return Ok(property.clone());
+            }
+        }
+    }
+    // Other Properties, such as `name`, are often used without being recommended by the Class
+    let known = store.all_resources(true).find(|r| {
+        r.get(urls::SHORTNAME)
+            .is_ok_and(|s| s.to_string() == shortname)
+            && r.get(urls::DATATYPE_PROP).is_ok()
+    });
+    if let Some(property) = known {
+        return Ok(property.get_subject().clone());
+    }
+    context
+        .mapping
+        .lock()
+        .unwrap()
+        .try_mapping_or_url(shortname)
+        .ok_or(format!("Could not find a Property for '{}'", shortname).into())
+}
+
+pub fn query(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("query").unwrap();
+    let class_arg = matches
+        .get_one::<String>("class")
+        .expect("class is required");
+    let class = context
+        .mapping
+        .lock()
+        .unwrap()
+        .try_mapping_or_url(class_arg)
+        .ok_or(format!("No url found for {}", class_arg))?;
+
+    #[cfg(feature = "db")]
+    if let Some(db) = crate::import::open_db(matches)? {
+        return run(context, matches, &db, &class, |sort_by| {
+            let mut query = atomic_lib::storelike::Query::new_class(&class);
+            query.sort_by = sort_by;
+            query.sort_desc = matches.get_flag("desc");
+            query.include_external = matches.get_flag("include-external");
+            Ok(db.query(&query)?.resources)
+        });
+    }
+    #[cfg(not(feature = "db"))]
+    if matches.contains_id("db") {
+        return Err("Querying a database requires the `db` feature.".into());
+    }
+
+    let server = match matches.get_one::<String>("server") {
+        Some(server) => server.trim_end_matches('/').to_string(),
+        None => crate::read_config()
+            .map(|config| config.server.trim_end_matches('/').to_string())
+            .ok_or("Pass a --server, or create a config with a server URL.")?,
+    };
+    let agent = context.get_agent_if_configured()?;
+    run(context, matches, &context.store, &class, |sort_by| {
+        fetch_collection(context, &server, &class, sort_by, matches, agent.clone())
+    })
+}
+
+/// Parses the filters, gets the resources, and prints them.
+fn run(
+    context: &Context,
+    matches: &ArgMatches,
+    store: &impl Storelike,
+    class: &str,
+    get_resources: impl FnOnce(Option<String>) -> AtomicResult<Vec<Resource>>,
+) -> AtomicResult<()> {
+    let mut filters = Vec::new();
+    for filter in matches.get_many::<String>("filter").unwrap_or_default() {
+        let mut filter = Filter::parse(filter)?;
+        filter.property = resolve_property(context, store, class, &filter.property)?;
+        filters.push(filter);
+    }
+    let sort_by = match matches.get_one::<String>("sort") {
+        Some(sort) => Some(resolve_property(context, store, class, sort)?),
+        None => None,
+    };
+    let limit = matches.get_one::<usize>("limit").copied();
+
+    let resources: Vec<Resource> = get_resources(sort_by)?
+        .into_iter()
+        .filter(|r| filters.iter().all(|f| f.matches(r)))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    let format = matches
+        .get_one::<String>("format")
+        .expect("format has a default value");
+    if format == "json" {
+        println!("{}", serialize::resources_to_json_ad(&resources)?);
+        return Ok(());
+    }
+    let columns: Vec<String> = match matches.get_many::<String>("properties") {
+        Some(properties) => properties
+            .map(|p| resolve_property(context, store, class, p))
+            .collect::<AtomicResult<_>>()?,
+        None => {
+            let class = store.get_class(class)?;
+            class.requires.into_iter().chain(class.recommends).collect()
+        }
+    };
+    if format == "csv" {
+        // Only keep the chosen columns, the header contains the Property URLs so the output can be imported again
+        let selected: Vec<Resource> = resources
+            .iter()
+            .map(|r| {
+                let mut selected = Resource::new(r.get_subject().clone());
+                for column in &columns {
+                    if let Ok(value) = r.get(column) {
+                        selected.set_propval_unsafe(column.clone(), value.clone());
+                    }
+                }
+                selected
+            })
+            .collect();
+        print!("{}", serialize::resources_to_csv(&selected)?);
+        return Ok(());
+    }
+    crate::render::print_table(store, &resources, &columns);
+    eprintln!("{} results", resources.len());
+    Ok(())
+}
+
+/// Fetches all pages of a Collection of the instances of the Class.
+fn fetch_collection(
+    context: &Context,
+    server: &str,
+    class: &str,
+    sort_by: Option<String>,
+    matches: &ArgMatches,
+    agent: Option<atomic_lib::agents::Agent>,
+) -> AtomicResult<Vec<Resource>> {
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        ..Default::default()
+    };
+    let mut resources = Vec::new();
+    let mut current_page = 0;
+    loop {
+        let mut url = url::Url::parse(&format!("{}/collections", server))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("property", urls::IS_A)
+                .append_pair("value", class)
+                .append_pair("include_nested", "true")
+                .append_pair("page_size", &PAGE_SIZE.to_string())
+                .append_pair("current_page", &current_page.to_string());
+            if let Some(sort_by) = &sort_by {
+                query.append_pair("sort_by", sort_by);
+                query.append_pair("sort_desc", &matches.get_flag("desc").to_string());
+            }
+            if matches.get_flag("include-external") {
+                query.append_pair("include_external", "true");
+            }
+        }
+        let body = atomic_lib::client::fetch_body(
+            url.as_str(),
+            atomic_lib::parse::JSON_AD_MIME,
+            agent.clone(),
+        )?;
+        let page: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&body)?;
+        let members = page
+            .get(urls::COLLECTION_MEMBERS)
+            .and_then(|m| m.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for member in members {
+            resources.push(atomic_lib::parse::parse_json_ad_resource(
+                &member.to_string(),
+                &context.store,
+                &parse_opts,
+            )?);
+        }
+        let total_pages = page
+            .get(urls::COLLECTION_TOTAL_PAGES)
+            .and_then(|t| t.as_u64())
+            .unwrap_or(0);
+        current_page += 1;
+        if current_page as u64 >= total_pages {
+            break;
+        }
+    }
+    Ok(resources)
+}
@@ -0,0 +1,231 @@
+//! Renders resources for humans: lists of resources as aligned tables, and hierarchies as indented trees.
+
+use crate::Context;
+use atomic_lib::{
+    errors::AtomicResult, storelike::PathReturn, urls, values::SubResource, Resource, Storelike,
+    Value,
+};
+use colored::*;
+
+/// Longer cells are truncated in tables.
+const MAX_CELL_WIDTH: usize = 40;
+
+/// Prints the resources as a table, with a column for the subject and for every Property in `columns`.
+pub fn print_table(store: &impl Storelike, resources: &[Resource], columns: &[String]) {
+    let truncate = |cell: String| {
+        if cell.chars().count() > MAX_CELL_WIDTH {
+            let mut cell: String = cell.chars().take(MAX_CELL_WIDTH - 1).collect();
+            cell.push('…');
+            cell
+        } else {
+            cell
+        }
+    };
+    let header: Vec<String> = std::iter::once("subject".to_string())
+        .chain(columns.iter().map(|c| {
+            store
+                .get_property(c)
+                .map(|p| p.shortname)
+                .unwrap_or_else(|_| c.clone())
+        }))
+        .collect();
+    let rows: Vec<Vec<String>> = resources
+        .iter()
+        .map(|r| {
+            std::iter::once(r.get_subject().clone())
+                .chain(
+                    columns
+                        .iter()
+                        .map(|c| truncate(r.get(c).map(|v| v.to_string()).unwrap_or_default())),
+                )
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([header[i].chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+    };
+    println!("{}", format_row(&header).bold());
+    for row in &rows {
+        println!("{}", format_row(row));
+    }
+}
+
+/// Resolves the items of a ResourceArray to Resources. Nested resources without a subject are skipped.
+fn to_resources(store: &impl Storelike, value: &Value) -> Vec<Resource> {
+    let Value::ResourceArray(items) = value else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| match item {
+            SubResource::Resource(resource) => Some(*resource.clone()),
+            SubResource::Subject(subject) => Some(
+                store
+                    .get_resource_extended(subject, false, None)
+                    .unwrap_or_else(|_| Resource::new(subject.clone())),
+            ),
+            SubResource::Nested(_) => None,
+        })
+        .collect()
+}
+
+/// The resources that are listed in a table or shown as branches of a tree:
+/// the members of a Collection, or the children of any other resource.
+fn list_items(store: &impl Storelike, resource: &Resource) -> Option<Vec<Resource>> {
+    resource
+        .get(urls::COLLECTION_MEMBERS)
+        .or_else(|_| resource.get(urls::CHILDREN))
+        .ok()
+        .map(|value| to_resources(store, value))
+}
+
+/// The amount of items that [list_items] would return, without fetching them.
+fn count_items(resource: &Resource) -> usize {
+    resource
+        .get(urls::COLLECTION_MEMBERS)
+        .or_else(|_| resource.get(urls::CHILDREN))
+        .and_then(|value| value.to_subjects(None))
+        .map(|items| items.len())
+        .unwrap_or(0)
+}
+
+/// Prints the members of a Collection, the children of a resource, or the resources of a ResourceArray as tables.
+/// The resources are grouped by their Class, and get the required and recommended Properties of their Class as columns.
+pub fn table(context: &Context, path: &PathReturn) -> AtomicResult<()> {
+    let store = &context.store;
+    let resources = match path {
+        PathReturn::Subject(subject) => {
+            let resource = store.get_resource_extended(subject, false, None)?;
+            list_items(store, &resource).unwrap_or_else(|| vec![resource])
+        }
+        PathReturn::Atom(atom) => match &atom.value {
+            Value::ResourceArray(_) => to_resources(store, &atom.value),
+            _ => return Err("A table can only be shown for resources and ResourceArrays.".into()),
+        },
+    };
+
+    // Keeps the order in which the Classes first appear
+    let mut groups: Vec<(Option<String>, Vec<Resource>)> = Vec::new();
+    for resource in resources.iter() {
+        let class = resource.get_main_class().ok();
+        match groups.iter_mut().find(|(c, _)| c == &class) {
+            Some((_, members)) => members.push(resource.clone()),
+            None => groups.push((class, vec![resource.clone()])),
+        }
+    }
+    for (index, (class, members)) in groups.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        let class = class.as_ref().and_then(|c| store.get_class(c).ok());
+        let columns: Vec<String> = match &class {
+            Some(class) => {
+                println!("{}", class.shortname.blue().bold());
+                class
+                    .requires
+                    .iter()
+                    .chain(class.recommends.iter())
+                    .cloned()
+                    .collect()
+            }
+            // Without a Class, every Property that is used becomes a column
+            None => {
+                let mut columns: Vec<String> = Vec::new();
+                for property in members.iter().flat_map(|m| m.get_propvals().keys()) {
+                    if !columns.contains(property) {
+                        columns.push(property.clone());
+                    }
+                }
+                columns
+            }
+        };
+        print_table(store, members, &columns);
+    }
+    eprintln!("{} results", resources.len());
+    Ok(())
+}
+
+/// A short, readable description of the resource: its name, or its subject.
+fn label(resource: &Resource) -> String {
+    let name = [urls::NAME, urls::SHORTNAME]
+        .iter()
+        .find_map(|property| resource.get(property).ok())
+        .map(|name| name.to_string());
+    match name {
+        Some(name) => format!("{} {}", name.bold(), resource.get_subject().dimmed()),
+        None => resource.get_subject().to_string(),
+    }
+}
+
+/// Prints the resource and its children (or the members of a Collection) as an indented tree, up to `depth` levels deep.
+pub fn tree(context: &Context, path: &PathReturn, depth: usize) -> AtomicResult<()> {
+    let PathReturn::Subject(subject) = path else {
+        return Err("A tree can only be shown for a resource, not for a value.".into());
+    };
+    let store = &context.store;
+    let root = store.get_resource_extended(subject, false, None)?;
+    println!("{}", label(&root));
+    let mut visited = vec![subject.clone()];
+    print_branches(store, &root, "", depth, &mut visited);
+    Ok(())
+}
+
+fn print_branches(
+    store: &impl Storelike,
+    resource: &Resource,
+    prefix: &str,
+    depth: usize,
+    visited: &mut Vec<String>,
+) {
+    let items = list_items(store, resource).unwrap_or_default();
+    for (index, item) in items.iter().enumerate() {
+        let is_last = index == items.len() - 1;
+        let (branch, indent) = if is_last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let subject = item.get_subject();
+        // Resources can appear more than once, for example when a Collection contains its parent
+        if visited.contains(subject) {
+            println!(
+                "{}{}{} {}",
+                prefix,
+                branch,
+                label(item),
+                "(repeated)".dimmed()
+            );
+            continue;
+        }
+        visited.push(subject.clone());
+        let hidden = count_items(item);
+        if depth <= 1 && hidden > 0 {
+            let more = format!("(+{} more)", hidden);
+            println!("{}{}{} {}", prefix, branch, label(item), more.dimmed());
+            continue;
+        }
+        println!("{}{}{}", prefix, branch, label(item));
+        if depth > 1 {
+            print_branches(
+                store,
+                item,
+                &format!("{}{}", prefix, indent),
+                depth - 1,
+                visited,
+            );
+        }
+    }
+}
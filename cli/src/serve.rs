@@ -0,0 +1,245 @@
+//! A minimal HTTP server for the in-memory store of the CLI, for developing and testing client apps without running Atomic-Server.
+//! Serves resources by their subject, and accepts Commits at `/commit`.
+//! There is no authorization: everything is public, and every signed Commit is applied.
+//! Agents at `/agents/{publicKey}` are registered when they sign their first Commit.
+//! Nothing is persisted, the data is gone when the server stops.
+
+use crate::Context;
+use atomic_lib::{
+    agents::Agent,
+    commit::CommitOpts,
+    errors::{AtomicError, AtomicErrorType, AtomicResult},
+    parse::{parse_json_ad_commit_resource, JSON_AD_MIME},
+    serialize, urls, Commit, Resource, Storelike,
+};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Requests with larger bodies are refused.
+const MAX_BODY_SIZE: usize = 10_000_000;
+
+struct Request {
+    method: String,
+    /// The path and query of the request URL
+    path: String,
+    accept: String,
+    body: String,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn error(e: AtomicError) -> Response {
+        let status = match e.error_type {
+            AtomicErrorType::NotFoundError => 404,
+            AtomicErrorType::UnauthorizedError => 401,
+            AtomicErrorType::MethodNotAllowed => 405,
+            _ => 400,
+        };
+        Response {
+            status,
+            content_type: "text/plain",
+            body: e.to_string(),
+        }
+    }
+}
+
+pub fn serve(context: &mut Context) -> AtomicResult<()> {
+    if context.matches.get_flag("remote") {
+        return Err("The serve command can't be combined with --remote.".into());
+    }
+    let matches = context.matches.subcommand_matches("serve").unwrap();
+    let port = *matches.get_one::<u16>("port").expect("port has a default");
+    let server_url = format!("http://localhost:{}", port);
+    context.store.set_server_url(&server_url);
+    let store = &context.store;
+    if store.get_resource(&server_url).is_err() {
+        let mut drive = Resource::new(server_url.clone());
+        drive.set_class(urls::DRIVE);
+        drive.set_propval_string(urls::NAME.into(), "Local store", store)?;
+        store.add_resource(&drive)?;
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Could not listen on port {}. {}", port, e))?;
+    println!("Serving the local store at {}", server_url);
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Connection failed: {}", e);
+                continue;
+            }
+        };
+        let response = match read_request(&mut stream) {
+            Ok(request) => {
+                let response = handle(store, &request);
+                println!("{} {} {}", request.method, request.path, response.status);
+                response
+            }
+            Err(e) => Response::error(e),
+        };
+        if let Err(e) = write_response(&mut stream, &response) {
+            eprintln!("Could not send response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn read_request(stream: &mut TcpStream) -> AtomicResult<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(format!("Invalid request line: {}", line.trim()).into());
+    };
+    let mut request = Request {
+        method: method.into(),
+        path: path.into(),
+        accept: String::new(),
+        body: String::new(),
+    };
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_lowercase().as_str() {
+            "accept" => request.accept = value.trim().to_lowercase(),
+            "content-length" => {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid Content-Length: {}", value.trim()))?
+            }
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(format!("Request body is larger than {} bytes.", MAX_BODY_SIZE).into());
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    request.body = String::from_utf8(body).map_err(|e| format!("Body is not UTF-8. {}", e))?;
+    Ok(request)
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        204 => "No Content",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Bad Request",
+    };
+    // Client apps usually run on another port, so CORS is allowed for everyone
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Access-Control-Allow-Origin: *\r\n\
+        Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+        Access-Control-Allow-Headers: *\r\n\
+        Connection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn handle(store: &atomic_lib::Store, request: &Request) -> Response {
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("OPTIONS", _) => Ok(Response {
+            status: 204,
+            content_type: "text/plain",
+            body: String::new(),
+        }),
+        ("POST", "/commit") => post_commit(store, &request.body),
+        ("GET", _) => get_resource(store, request),
+        (method, path) => Err(AtomicError::method_not_allowed(&format!(
+            "{} is not allowed for {}",
+            method, path
+        ))),
+    };
+    result.unwrap_or_else(Response::error)
+}
+
+/// Responds with the resource whose subject matches the URL of the request.
+fn get_resource(store: &atomic_lib::Store, request: &Request) -> AtomicResult<Response> {
+    let subject = match request.path.as_str() {
+        "/" => store.get_server_url().to_string(),
+        path => format!("{}{}", store.get_server_url(), path),
+    };
+    let resource = store.get_resource_extended(&subject, false, None)?;
+    let accept = request.accept.as_str();
+    let (content_type, body) = if accept.contains("application/ld+json") {
+        ("application/ld+json", resource.to_json_ld(store)?)
+    } else if accept.contains("application/json") {
+        ("application/json", resource.to_json(store)?)
+    } else if accept.contains("application/n-triples") || accept.contains("text/turtle") {
+        (
+            "application/n-triples",
+            serialize::atoms_to_ntriples(resource.to_atoms(), store)?,
+        )
+    } else {
+        (JSON_AD_MIME, resource.to_json_ad()?)
+    };
+    Ok(Response {
+        status: 200,
+        content_type,
+        body,
+    })
+}
+
+/// Applies a signed Commit to a resource on this server, and responds with the Commit.
+fn post_commit(store: &atomic_lib::Store, body: &str) -> AtomicResult<Response> {
+    let commit = Commit::from_resource(parse_json_ad_commit_resource(body, store)?)?;
+    if !commit.subject.starts_with(store.get_server_url()) {
+        return Err(format!(
+            "The subject {} is not hosted by {}.",
+            commit.subject,
+            store.get_server_url()
+        )
+        .into());
+    }
+    // Without a setup flow, Agents on this server are registered when they first sign a Commit
+    let agents_url = format!("{}/agents/", store.get_server_url());
+    if let Some(public_key) = commit.signer.strip_prefix(&agents_url) {
+        if store.get_resource(&commit.signer).is_err() {
+            let agent = Agent::new_from_public_key(store, public_key)?;
+            store.add_resource(&agent.to_resource()?)?;
+        }
+    }
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        // There is no authorization, everything is public
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let response = commit.apply_opts(store, &opts)?;
+    Ok(Response {
+        status: 200,
+        content_type: JSON_AD_MIME,
+        body: response.commit_resource.to_json_ad()?,
+    })
+}
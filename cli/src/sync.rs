@@ -0,0 +1,588 @@
+//! Two-way sync between a local directory and the children of a Drive (or any other parent resource).
+//!
+//! Resources with a `description` are written as markdown files: the description becomes the body,
+//! the other Properties are listed in the front matter using their shortnames.
+//! Other resources are written as JSON-AD files.
+//! The state of the last sync is kept in `.atomic-sync.json` in the directory.
+//! A file has changed when its modification time differs, a resource when its `lastCommit` differs.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::Context;
+use atomic_lib::{
+    errors::{AtomicErrorType, AtomicResult},
+    parse::{ParseOpts, SaveOpts},
+    urls,
+    values::SubResource,
+    Resource, Storelike, Value,
+};
+use colored::*;
+use serde::{Deserialize, Serialize};
+
+const STATE_FILE: &str = ".atomic-sync.json";
+/// Set by the server, these are not written to the files.
+const READ_ONLY: [&str; 2] = [urls::LAST_COMMIT, urls::CHILDREN];
+
+#[derive(Serialize, Deserialize, Default)]
+struct SyncState {
+    drive: String,
+    /// Maps the file names to the resources they were synced with.
+    files: BTreeMap<String, SyncedFile>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SyncedFile {
+    subject: String,
+    last_commit: Option<String>,
+    /// Modification time of the file in milliseconds, right after the last sync.
+    modified: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Prefer {
+    Local,
+    Remote,
+    Neither,
+}
+
+enum Action {
+    Pull,
+    Push,
+    DeleteFile,
+    DestroyResource,
+    Forget,
+    Conflict(&'static str),
+}
+
+/// Prints what happened to a file, and counts it for the summary.
+fn report(summary: &mut BTreeMap<&str, usize>, action: &'static str, file: &str, detail: &str) {
+    let label = match action {
+        "conflict" | "failed" => action.red().bold(),
+        "deleted" | "destroyed" => action.yellow(),
+        _ => action.green(),
+    };
+    println!("{: <10} {} {}", label, file, detail.dimmed());
+    *summary.entry(action).or_default() += 1;
+}
+
+pub fn sync(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("sync").unwrap();
+    let dir = PathBuf::from(matches.get_one::<String>("dir").expect("dir is required"));
+    let drive = crate::commit::argument_to_url(context, "drive")?;
+    let dry_run = matches.get_flag("dry-run");
+    let prefer = match matches.get_one::<String>("prefer").map(String::as_str) {
+        Some("local") => Prefer::Local,
+        Some("remote") => Prefer::Remote,
+        _ => Prefer::Neither,
+    };
+    std::fs::create_dir_all(&dir)?;
+
+    let state_path = dir.join(STATE_FILE);
+    let mut state: SyncState = if state_path.exists() {
+        serde_json::from_str(&std::fs::read_to_string(&state_path)?)
+            .map_err(|e| format!("Could not read {}. {}", state_path.display(), e))?
+    } else {
+        SyncState {
+            drive: drive.clone(),
+            ..Default::default()
+        }
+    };
+    if state.drive != drive {
+        return Err(format!(
+            "{} is synced with {}, not with {}.",
+            dir.display(),
+            state.drive,
+            drive
+        )
+        .into());
+    }
+
+    context.get_write_context();
+    let agent = Some(context.store.get_default_agent()?);
+    let fetch =
+        |subject: &str| atomic_lib::client::fetch_resource(subject, &context.store, agent.clone());
+    let parent = fetch(&drive)?;
+    let mut remote: HashMap<String, Resource> = HashMap::new();
+    for child in parent
+        .get(urls::CHILDREN)
+        .and_then(|c| c.to_subjects(None))
+        .unwrap_or_default()
+    {
+        remote.insert(child.clone(), fetch(&child)?);
+    }
+    // Resources that got another parent are still synced, only resources that the server can't find (404) are deleted.
+    // Other errors, such as a server that is down, abort the sync, since deleting files would lose data.
+    for synced in state.files.values() {
+        if !remote.contains_key(&synced.subject) {
+            match fetch(&synced.subject) {
+                Ok(resource) => {
+                    remote.insert(synced.subject.clone(), resource);
+                }
+                Err(e) if matches!(e.error_type, AtomicErrorType::NotFoundError) => {}
+                Err(e) => {
+                    return Err(format!(
+                        "Could not fetch {}, so nothing has been synced. {}",
+                        synced.subject, e
+                    )
+                    .into())
+                }
+            }
+        }
+    }
+
+    let mut summary: BTreeMap<&str, usize> = BTreeMap::new();
+
+    // Files that were synced before
+    let synced: Vec<(String, SyncedFile)> = state
+        .files
+        .iter()
+        .map(|(f, s)| (f.clone(), s.clone()))
+        .collect();
+    for (file, synced) in synced {
+        let path = dir.join(&file);
+        let resource = remote.get(&synced.subject).cloned();
+        let resource = resource.as_ref();
+        let local_changed = !path.exists() || modified(&path)? != synced.modified;
+        let remote_changed = resource.is_none_or(|r| last_commit(r) != synced.last_commit);
+        let action = match (
+            path.exists(),
+            resource.is_some(),
+            local_changed,
+            remote_changed,
+        ) {
+            (_, _, false, false) => continue,
+            (false, false, _, _) => Action::Forget,
+            (true, true, true, false) => Action::Push,
+            (true, true, false, true) => Action::Pull,
+            (false, true, _, false) => Action::DestroyResource,
+            (true, false, false, _) => Action::DeleteFile,
+            (true, true, _, _) => Action::Conflict("changed on both sides"),
+            (false, true, _, _) => Action::Conflict("deleted locally, changed remotely"),
+            (true, false, _, _) => Action::Conflict("changed locally, deleted remotely"),
+        };
+        let action = match (action, prefer) {
+            (Action::Conflict(_), Prefer::Local) if path.exists() => Action::Push,
+            (Action::Conflict(_), Prefer::Local) => Action::DestroyResource,
+            (Action::Conflict(_), Prefer::Remote) if resource.is_some() => Action::Pull,
+            (Action::Conflict(_), Prefer::Remote) => Action::DeleteFile,
+            (action, _) => action,
+        };
+        if dry_run {
+            let planned = match action {
+                Action::Pull => "pull",
+                Action::Push => "push",
+                Action::DeleteFile => "delete",
+                Action::DestroyResource => "destroy",
+                Action::Forget => "forget",
+                Action::Conflict(reason) => {
+                    report(&mut summary, "conflict", &file, reason);
+                    continue;
+                }
+            };
+            report(&mut summary, planned, &file, &synced.subject);
+            continue;
+        }
+        let result = match action {
+            Action::Pull => {
+                let resource = resource.expect("Only pulled if the resource exists");
+                write_file(context, &path, resource).and_then(|_| {
+                    state
+                        .files
+                        .insert(file.clone(), synced_file(&path, resource)?);
+                    Ok("pulled")
+                })
+            }
+            Action::Push => push(context, &path, &synced.subject, resource, &drive, false)
+                .and_then(|pushed| {
+                    state
+                        .files
+                        .insert(file.clone(), synced_file(&path, &pushed)?);
+                    Ok("pushed")
+                }),
+            Action::DeleteFile => std::fs::remove_file(&path).map_err(|e| e.into()).map(|_| {
+                state.files.remove(&file);
+                "deleted"
+            }),
+            Action::DestroyResource => {
+                let mut resource = resource.expect("Only destroyed if it exists").clone();
+                resource.destroy(&context.store).map(|_| {
+                    state.files.remove(&file);
+                    remote.remove(&synced.subject);
+                    "destroyed"
+                })
+            }
+            Action::Forget => {
+                state.files.remove(&file);
+                continue;
+            }
+            Action::Conflict(reason) => {
+                report(&mut summary, "conflict", &file, reason);
+                continue;
+            }
+        };
+        match result {
+            Ok(done) => report(&mut summary, done, &file, &synced.subject),
+            Err(e) => report(&mut summary, "failed", &file, &e.to_string()),
+        }
+    }
+
+    // New files
+    let mut new_files: Vec<String> = std::fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.') && (name.ends_with(".md") || name.ends_with(".json")))
+        .filter(|name| !state.files.contains_key(name))
+        .collect();
+    new_files.sort();
+    for file in new_files {
+        let path = dir.join(&file);
+        let stem = file.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&file);
+        let mut subject = format!("{}/{}", drive, to_slug(stem));
+        if remote.contains_key(&subject) || state.files.values().any(|s| s.subject == subject) {
+            subject = format!("{}-{}", subject, atomic_lib::utils::now());
+        }
+        if dry_run {
+            report(&mut summary, "create", &file, &subject);
+            continue;
+        }
+        // Rewrite the file, so it contains the Properties that were added when it was created
+        let created = push(context, &path, &subject, None, &drive, true)
+            .and_then(|pushed| write_file(context, &path, &pushed).map(|_| pushed));
+        match created {
+            Ok(pushed) => {
+                state
+                    .files
+                    .insert(file.clone(), synced_file(&path, &pushed)?);
+                report(&mut summary, "created", &file, pushed.get_subject());
+            }
+            Err(e) => report(&mut summary, "failed", &file, &e.to_string()),
+        }
+    }
+
+    // New resources
+    let mut new_resources: Vec<&Resource> = remote
+        .values()
+        .filter(|r| !state.files.values().any(|s| &s.subject == r.get_subject()))
+        .collect();
+    new_resources.sort_by(|a, b| a.get_subject().cmp(b.get_subject()));
+    for resource in new_resources {
+        let file = file_name(context, &dir, &state, resource);
+        if dry_run {
+            report(&mut summary, "pull", &file, resource.get_subject());
+            continue;
+        }
+        let path = dir.join(&file);
+        match write_file(context, &path, resource) {
+            Ok(_) => {
+                state
+                    .files
+                    .insert(file.clone(), synced_file(&path, resource)?);
+                report(&mut summary, "pulled", &file, resource.get_subject());
+            }
+            Err(e) => report(&mut summary, "failed", &file, &e.to_string()),
+        }
+    }
+
+    if !dry_run {
+        std::fs::write(&state_path, serde_json::to_string_pretty(&state)?)?;
+    }
+    if summary.is_empty() {
+        println!("Everything is in sync.");
+    } else {
+        let counts: Vec<String> = summary
+            .iter()
+            .map(|(action, count)| format!("{} {}", count, action))
+            .collect();
+        println!("{}", counts.join(", "));
+    }
+    if summary.contains_key("conflict") {
+        eprintln!("Use --prefer local or --prefer remote to resolve the conflicts.");
+    }
+    if summary.contains_key("failed") {
+        return Err("Some files could not be synced.".into());
+    }
+    Ok(())
+}
+
+fn last_commit(resource: &Resource) -> Option<String> {
+    resource.get(urls::LAST_COMMIT).ok().map(|c| c.to_string())
+}
+
+/// Modification time in milliseconds.
+fn modified(path: &Path) -> AtomicResult<u64> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    let millis = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis();
+    Ok(millis as u64)
+}
+
+fn synced_file(path: &Path, resource: &Resource) -> AtomicResult<SyncedFile> {
+    Ok(SyncedFile {
+        subject: resource.get_subject().clone(),
+        last_commit: last_commit(resource),
+        modified: modified(path)?,
+    })
+}
+
+fn to_slug(name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "resource".into()
+    } else {
+        slug.into()
+    }
+}
+
+/// A file name based on the last part of the subject, which is not used by another file.
+fn file_name(context: &Context, dir: &Path, state: &SyncState, resource: &Resource) -> String {
+    let subject = resource.get_subject().trim_end_matches('/');
+    let stem = to_slug(subject.rsplit('/').next().unwrap_or(subject));
+    let extension = if front_matter(context, resource).is_some() {
+        "md"
+    } else {
+        "json"
+    };
+    let mut name = format!("{}.{}", stem, extension);
+    let mut count = 2;
+    while state.files.contains_key(&name) || dir.join(&name).exists() {
+        name = format!("{}-{}.{}", stem, count, extension);
+        count += 1;
+    }
+    name
+}
+
+/// A value that fits on a single line in the front matter.
+/// Returns None for nested resources, which can only be written as JSON-AD.
+fn front_matter_value(value: &Value) -> Option<String> {
+    match value {
+        Value::ResourceArray(items) => {
+            let mut subjects = Vec::new();
+            for item in items {
+                match item {
+                    SubResource::Subject(s) => subjects.push(s.clone()),
+                    _ => return None,
+                }
+            }
+            serde_json::to_string(&subjects).ok()
+        }
+        Value::NestedResource(_) | Value::Resource(_) => None,
+        other => {
+            let text = other.to_string();
+            let needs_quotes = text.contains('\n')
+                || text.trim() != text
+                || text.starts_with('"')
+                || text.starts_with('[');
+            if needs_quotes {
+                serde_json::to_string(&text).ok()
+            } else {
+                Some(text)
+            }
+        }
+    }
+}
+
+/// The front matter and body of the markdown file for the resource.
+/// Returns None if the resource has no markdown description, or has values that don't fit in front matter.
+fn front_matter(context: &Context, resource: &Resource) -> Option<(Vec<(String, String)>, String)> {
+    let Ok(Value::Markdown(body)) = resource.get(urls::DESCRIPTION) else {
+        return None;
+    };
+    let class = resource
+        .get(urls::IS_A)
+        .and_then(|c| c.to_subjects(None))
+        .ok()
+        .and_then(|c| c.into_iter().next())
+        .unwrap_or_default();
+    let mut fields = Vec::new();
+    for (property, value) in resource.get_propvals() {
+        if property == urls::DESCRIPTION || READ_ONLY.contains(&property.as_str()) {
+            continue;
+        }
+        // Use the shortname, unless it points to another Property
+        let key = match context.store.get_property(property) {
+            Ok(p)
+                if crate::query::resolve_property(
+                    context,
+                    &context.store,
+                    &class,
+                    &p.shortname,
+                )
+                .is_ok_and(|found| &found == property) =>
+            {
+                p.shortname
+            }
+            _ => property.clone(),
+        };
+        fields.push((key, front_matter_value(value)?));
+    }
+    fields.sort();
+    Some((fields, body.clone()))
+}
+
+fn write_file(context: &Context, path: &Path, resource: &Resource) -> AtomicResult<()> {
+    let content = if path.extension().is_some_and(|e| e == "md") {
+        let (fields, body) = front_matter(context, resource)
+            .ok_or("This resource can't be written as markdown, rename the file to .json.")?;
+        let mut content = String::from("---\n");
+        for (key, value) in fields {
+            content.push_str(&format!("{}: {}\n", key, value));
+        }
+        content.push_str(&format!("---\n\n{}\n", body.trim_end()));
+        content
+    } else {
+        let mut resource = resource.clone();
+        for read_only in READ_ONLY {
+            resource.remove_propval(read_only);
+        }
+        resource.to_json_ad()?
+    };
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Reads the Properties and Values from a markdown or JSON-AD file.
+fn read_file(context: &Context, path: &Path, subject: &str) -> AtomicResult<Resource> {
+    let content = std::fs::read_to_string(path)?;
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        ..Default::default()
+    };
+    if path.extension().is_some_and(|e| e != "md") {
+        let mut json: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&content)
+                .map_err(|e| format!("{} is not a valid JSON-AD object. {}", path.display(), e))?;
+        json.insert("@id".into(), subject.into());
+        let resource = atomic_lib::parse::parse_json_ad_resource(
+            &serde_json::Value::Object(json).to_string(),
+            &context.store,
+            &parse_opts,
+        )?;
+        return Ok(resource);
+    }
+
+    // Without front matter, the whole file is the description
+    let (header, body) = match content.strip_prefix("---\n") {
+        Some(rest) => match rest.split_once("\n---\n") {
+            Some((header, body)) => (header, body),
+            None => rest
+                .strip_suffix("\n---")
+                .map(|header| (header, ""))
+                .ok_or(format!(
+                    "The front matter of {} is not closed with ---",
+                    path.display()
+                ))?,
+        },
+        None => ("", content.as_str()),
+    };
+    let mut fields = Vec::new();
+    for line in header.lines().filter(|l| !l.trim().is_empty()) {
+        let (key, value) = line.split_once(':').ok_or(format!(
+            "Invalid front matter line in {}: {}",
+            path.display(),
+            line
+        ))?;
+        let (key, value) = (key.trim(), value.trim());
+        // URLs as keys contain a colon as well
+        let (key, value) = if key == "http" || key == "https" {
+            let (rest, value) = value.split_once(": ").ok_or(format!(
+                "Invalid front matter line in {}: {}",
+                path.display(),
+                line
+            ))?;
+            (format!("{}:{}", key, rest), value.trim())
+        } else {
+            (key.to_string(), value)
+        };
+        fields.push((key, value.to_string()));
+    }
+    let class = fields
+        .iter()
+        .find(|(key, _)| key == "is-a" || key == urls::IS_A)
+        .and_then(|(_, value)| atomic_lib::parse::parse_json_array(value).ok())
+        .and_then(|classes| classes.into_iter().next())
+        .unwrap_or_default();
+
+    let mut resource = Resource::new(subject.into());
+    for (key, value) in fields {
+        let property = crate::query::resolve_property(context, &context.store, &class, &key)?;
+        let datatype = context.store.get_property(&property)?.data_type;
+        let value = if value.starts_with('"') {
+            serde_json::from_str::<String>(&value)
+                .map_err(|e| format!("Invalid string for {} in {}. {}", key, path.display(), e))?
+        } else {
+            value
+        };
+        let value = Value::new(&value, &datatype)
+            .map_err(|e| format!("Invalid value for {} in {}. {}", key, path.display(), e))?;
+        resource.set_propval_unsafe(property, value);
+    }
+    resource.set_propval_unsafe(
+        urls::DESCRIPTION.into(),
+        Value::Markdown(body.trim_start_matches('\n').trim_end().into()),
+    );
+    Ok(resource)
+}
+
+/// Creates or updates the resource using the file, and returns it as it is stored on the server.
+fn push(
+    context: &Context,
+    path: &Path,
+    subject: &str,
+    remote: Option<&Resource>,
+    drive: &str,
+    is_new: bool,
+) -> AtomicResult<Resource> {
+    let local = read_file(context, path, subject)?;
+    let mut resource = remote
+        .cloned()
+        .unwrap_or_else(|| Resource::new(subject.into()));
+    for (property, value) in local.get_propvals() {
+        let old = resource.get(property).ok().map(|v| v.to_string());
+        if old.as_ref() != Some(&value.to_string()) {
+            resource.set_propval_unsafe(property.clone(), value.clone());
+        }
+    }
+    let removed: Vec<String> = resource
+        .get_propvals()
+        .keys()
+        .filter(|p| local.get(p).is_err() && !READ_ONLY.contains(&p.as_str()))
+        .cloned()
+        .collect();
+    for property in removed {
+        resource.remove_propval(&property);
+    }
+    if is_new {
+        if resource.get(urls::PARENT).is_err() {
+            resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.into()));
+        }
+        // Markdown files are Articles, which need a name
+        if path.extension().is_some_and(|e| e == "md") {
+            if resource.get(urls::IS_A).is_err() {
+                resource
+                    .set_propval_unsafe(urls::IS_A.into(), vec![urls::ARTICLE.to_string()].into());
+            }
+            if resource.get(urls::NAME).is_err() {
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                resource.set_propval_unsafe(urls::NAME.into(), Value::String(name));
+            }
+        }
+    }
+    resource.save(&context.store)?;
+    // The server sets the lastCommit, which is needed to detect the next remote change
+    let agent = context.store.get_default_agent()?;
+    atomic_lib::client::fetch_resource(subject, &context.store, Some(agent))
+}
@@ -0,0 +1,76 @@
+//! Checks JSON-AD files against the schema, so data repositories can be checked in CI.
+//! Exits with 0 if the file is valid, 1 if there are issues, and 2 if the file can't be read.
+//! Without a file, all resources in the store are checked, see [atomic_lib::validate::validate_store].
+
+use crate::Context;
+use atomic_lib::{errors::AtomicResult, validate::validate_json_ad, Storelike};
+use clap::ArgMatches;
+
+pub fn validate(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("validate").unwrap();
+    let as_json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+    let Some(file) = matches.get_one::<String>("file") else {
+        return validate_store(context, matches, as_json);
+    };
+    let report = std::fs::read_to_string(file)
+        .map_err(|e| format!("Could not read {}. {}", file, e).into())
+        .and_then(|string| validate_json_ad(&string, &context.store));
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for issue in &report.issues {
+            let kind = serde_json::to_value(issue.kind)?;
+            println!(
+                "{}:{}: {} {} {}: {}",
+                file,
+                issue.line,
+                kind.as_str().unwrap_or_default(),
+                issue.subject.as_deref().unwrap_or("(no subject)"),
+                issue.property.as_deref().unwrap_or(""),
+                issue.message
+            );
+        }
+        println!(
+            "Checked {} resources, found {} issues.",
+            report.resource_count,
+            report.issues.len()
+        );
+    }
+    if !report.is_valid() {
+        return Err(format!("{} is not valid.", file).into());
+    }
+    Ok(())
+}
+
+/// Validates every resource in the local store, or in the database passed to `--db`.
+fn validate_store(context: &Context, matches: &ArgMatches, as_json: bool) -> AtomicResult<()> {
+    #[cfg(feature = "db")]
+    let report = match crate::import::open_db(matches)? {
+        Some(db) => db.validate(),
+        None => context.store.validate(),
+    };
+    #[cfg(not(feature = "db"))]
+    let report = {
+        if matches.contains_id("db") {
+            return Err("Validating a database requires the `db` feature.".into());
+        }
+        context.store.validate()
+    };
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+    if !report.is_valid() {
+        return Err("The store is not valid.".into());
+    }
+    Ok(())
+}
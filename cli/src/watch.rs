@@ -0,0 +1,144 @@
+//! Prints the Commits that are applied to a resource (and its children) or to instances of a Class, as they come in.
+//! Polls the `/commits` endpoint of the server.
+
+use std::collections::HashSet;
+
+use crate::Context;
+use atomic_lib::{errors::AtomicResult, parse::JSON_AD_MIME, urls};
+use colored::*;
+use serde_json::{Map, Value};
+
+/// The maximum amount of Commits that are fetched per poll.
+const PAGE_SIZE: usize = 100;
+
+pub fn watch(context: &Context) -> AtomicResult<()> {
+    let matches = context.matches.subcommand_matches("watch").unwrap();
+    let subject = match matches.get_one::<String>("subject") {
+        Some(_) => Some(crate::commit::argument_to_url(context, "subject")?),
+        None => None,
+    };
+    let class = matches.get_one::<String>("class");
+    let json_lines = matches.get_one::<String>("as").map(String::as_str) == Some("json");
+    let interval = std::time::Duration::from_secs(
+        *matches
+            .get_one::<u64>("interval")
+            .expect("interval has a default value"),
+    );
+
+    // Only the Commits that the Agent can read are returned, so sign in if there is a config
+    let agent = context.get_agent_if_configured()?;
+    let server = match (&subject, matches.get_one::<String>("server")) {
+        (_, Some(server)) => server.trim_end_matches('/').to_string(),
+        (Some(subject), None) => atomic_lib::utils::server_url(subject)?
+            .trim_end_matches('/')
+            .to_string(),
+        (None, None) => crate::read_config()
+            .map(|config| config.server.trim_end_matches('/').to_string())
+            .ok_or("Pass a --server, or create a config with a server URL.")?,
+    };
+
+    let mut endpoint = url::Url::parse(&format!("{}/commits", server))?;
+    {
+        let mut query = endpoint.query_pairs_mut();
+        query.append_pair("page_size", &PAGE_SIZE.to_string());
+        if let Some(subject) = &subject {
+            query.append_pair("subject", subject);
+        }
+        if let Some(class) = class {
+            query.append_pair("class", class);
+        }
+    }
+    eprintln!("Watching {}, press Ctrl+C to stop.", endpoint);
+
+    let mut from = atomic_lib::utils::now();
+    // Commits created at `from` are returned again by the next poll
+    let mut seen: HashSet<String> = HashSet::new();
+    loop {
+        let mut url = endpoint.clone();
+        url.query_pairs_mut().append_pair("from", &from.to_string());
+        // The server might restart, so errors are printed instead of stopping the watcher
+        let page = match fetch_json(url.as_str(), agent.clone()) {
+            Ok(page) => page,
+            Err(e) => {
+                eprintln!("{} {}", "Failed to fetch Commits.".red(), e);
+                std::thread::sleep(interval);
+                continue;
+            }
+        };
+        let members: Vec<String> = page
+            .get(urls::COLLECTION_MEMBERS)
+            .and_then(|m| m.as_array())
+            .map(|m| {
+                m.iter()
+                    .filter_map(|c| c.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Members are sorted newest first
+        for commit_subject in members.into_iter().rev() {
+            if !seen.insert(commit_subject.clone()) {
+                continue;
+            }
+            let commit = match fetch_json(&commit_subject, agent.clone()) {
+                Ok(commit) => commit,
+                Err(e) => {
+                    eprintln!("{} {}", "Failed to fetch Commit.".red(), e);
+                    continue;
+                }
+            };
+            if let Some(created_at) = commit.get(urls::CREATED_AT).and_then(|c| c.as_i64()) {
+                from = from.max(created_at);
+            }
+            if json_lines {
+                println!("{}", Value::Object(commit));
+            } else {
+                print_commit(&commit_subject, &commit);
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn fetch_json(
+    url: &str,
+    agent: Option<atomic_lib::agents::Agent>,
+) -> AtomicResult<Map<String, Value>> {
+    let body = atomic_lib::client::fetch_body(url, JSON_AD_MIME, agent)?;
+    let json: Map<String, Value> = serde_json::from_str(&body)
+        .map_err(|e| format!("Could not parse the JSON-AD of {}. {}", url, e))?;
+    Ok(json)
+}
+
+/// Prints the changes of the Commit, one line per Property.
+fn print_commit(commit_subject: &str, commit: &Map<String, Value>) {
+    let get = |property: &str| commit.get(property).and_then(|v| v.as_str()).unwrap_or("");
+    println!(
+        "{} {} by {} at {}",
+        "commit".yellow().bold(),
+        get(urls::SUBJECT).bold(),
+        get(urls::SIGNER),
+        commit
+            .get(urls::CREATED_AT)
+            .map(|c| c.to_string())
+            .unwrap_or_default()
+    );
+    println!("  {}", commit_subject.dimmed());
+    if let Some(Value::Object(set)) = commit.get(urls::SET) {
+        for (property, value) in set {
+            println!("  {} {} {}", "+".green(), property, value);
+        }
+    }
+    if let Some(Value::Object(push)) = commit.get(urls::PUSH) {
+        for (property, value) in push {
+            println!("  {} {} {}", "+=".green(), property, value);
+        }
+    }
+    if let Some(Value::Array(remove)) = commit.get(urls::REMOVE) {
+        for property in remove.iter().filter_map(|p| p.as_str()) {
+            println!("  {} {}", "-".red(), property);
+        }
+    }
+    if commit.get(urls::DESTROY).and_then(|d| d.as_bool()) == Some(true) {
+        println!("  {}", "destroyed".red());
+    }
+}
@@ -76,4 +76,402 @@ mod test {
             .to_string();
         assert!(result.contains(&value));
     }
+
+    #[test]
+    #[cfg(feature = "db")]
+    fn import_and_export_db() {
+        let dir = std::env::temp_dir().join("atomic-cli-import-and-export-db");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("data.json");
+        let json = format!(
+            r#"[{{"@id": "http://localhost:9883/imported", "{}": "Imported, with a comma"}}]"#,
+            atomic_lib::urls::NAME
+        );
+        std::fs::write(&file, json).unwrap();
+        let db = dir.join("db");
+        let db = db.to_str().unwrap();
+
+        let mut cmd_import = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd_import
+            .args(["import", file.to_str().unwrap(), "--db", db, "--force"])
+            .assert()
+            .success();
+
+        let mut cmd_export = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd_export
+            .args(["export", "--db", db, "--format", "csv"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let csv = String::from_utf8(output.stdout).unwrap();
+        assert!(csv.contains("http://localhost:9883/imported,\"Imported, with a comma\""));
+    }
+
+    #[test]
+    fn edit_resource_without_changes() {
+        let home = std::env::temp_dir().join("atomic-cli-edit-resource");
+        let _ = std::fs::remove_dir_all(&home);
+        let store = atomic_lib::Store::init().unwrap();
+        let agent = atomic_lib::agents::Agent::new(None, &store).unwrap();
+        let config = atomic_lib::config::Config {
+            server: "http://localhost:9883".into(),
+            agent: agent.subject,
+            private_key: agent.private_key.unwrap(),
+        };
+        atomic_lib::config::write_config(&home.join(".config/atomic/config.toml"), config).unwrap();
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .env("HOME", &home)
+            .env("EDITOR", "true")
+            .args(["edit", atomic_lib::urls::CLASS])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("No changes."));
+    }
+
+    #[test]
+    #[cfg(feature = "db")]
+    fn query_db() {
+        let db = std::env::temp_dir().join("atomic-cli-query-db");
+        let _ = std::fs::remove_dir_all(&db);
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .args([
+                "query",
+                "--db",
+                db.to_str().unwrap(),
+                "--class",
+                atomic_lib::urls::PROPERTY,
+                "--filter",
+                "shortname=oidc-subject",
+                "--properties",
+                "shortname,datatype",
+                "--include-external",
+                "--format",
+                "csv",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let csv = String::from_utf8(output.stdout).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "@id,{},{}",
+                atomic_lib::urls::DATATYPE_PROP,
+                atomic_lib::urls::SHORTNAME
+            )
+        );
+        assert!(lines.next().unwrap().ends_with(",oidc-subject"));
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn agent_new_export_import_use() {
+        let home = std::env::temp_dir().join("atomic-cli-agents");
+        let _ = std::fs::remove_dir_all(&home);
+        let run = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+            let output = cmd.env("HOME", &home).args(args).output().unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let server = "http://localhost:9883";
+        run(&[
+            "agent",
+            "new",
+            "--name",
+            "alice",
+            "--server",
+            server,
+            "--no-publish",
+        ]);
+        let alice = std::fs::read_to_string(home.join(".config/atomic/config.toml")).unwrap();
+        let secret = run(&["agent", "export", "alice", "--passphrase", "pw"]);
+        assert!(secret.starts_with("encrypted:"));
+
+        // Save bob through an exported secret and switch to him
+        run(&[
+            "agent",
+            "new",
+            "--name",
+            "bob",
+            "--server",
+            server,
+            "--no-publish",
+        ]);
+        let bob_secret = run(&["agent", "export", "bob"]);
+        std::fs::remove_file(home.join(".config/atomic/agents/bob.toml")).unwrap();
+        run(&["agent", "import", bob_secret.trim(), "--name", "bob"]);
+        run(&["agent", "use", "bob"]);
+        let active = std::fs::read_to_string(home.join(".config/atomic/config.toml")).unwrap();
+        assert_ne!(active, alice);
+        let list = run(&["agent", "list"]);
+        assert!(list.contains("alice"));
+        assert!(list.contains("* bob"));
+
+        // Importing alice again fails, she is already saved
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.env("HOME", &home)
+            .args(["agent", "import", secret.trim(), "--passphrase", "pw"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn sync_refuses_other_drive() {
+        let dir = std::env::temp_dir().join("atomic-cli-sync");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(".atomic-sync.json"),
+            r#"{"drive": "https://example.com/drive", "files": {}}"#,
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .args([
+                "sync",
+                dir.to_str().unwrap(),
+                "https://example.com/other-drive",
+            ])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("is synced with https://example.com/drive"));
+    }
+
+    #[test]
+    fn diff_resources() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .args(["diff", atomic_lib::urls::NAME, atomic_lib::urls::SHORTNAME])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let out = String::from_utf8(output.stdout).unwrap();
+        assert!(out.contains("~ datatype:"));
+        assert!(out.contains("+ https://atomicdata.dev/datatypes/slug"));
+    }
+
+    #[test]
+    fn new_prompts_for_class() {
+        let home = std::env::temp_dir().join("atomic-cli-new");
+        let _ = std::fs::remove_dir_all(&home);
+        let store = atomic_lib::Store::init().unwrap();
+        let agent = atomic_lib::agents::Agent::new(None, &store).unwrap();
+        let config = atomic_lib::config::Config {
+            server: "http://localhost:9883".into(),
+            agent: agent.subject,
+            private_key: agent.private_key.unwrap(),
+        };
+        atomic_lib::config::write_config(&home.join(".config/atomic/config.toml"), config).unwrap();
+
+        // Stops when the input ends, before anything is created
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .env("HOME", &home)
+            .args([
+                "new",
+                atomic_lib::urls::ARTICLE,
+                "--parent",
+                "http://localhost:9883",
+            ])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("Enter a new article"));
+        assert!(!String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("panicked"));
+    }
+
+    #[test]
+    fn validate_json_ad_file() {
+        let dir = std::env::temp_dir().join("atomic-cli-validate");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let valid = dir.join("valid.json");
+        std::fs::write(
+            &valid,
+            r#"{
+  "@id": "https://example.com/article",
+  "https://atomicdata.dev/properties/isA": ["https://atomicdata.dev/classes/Article"],
+  "https://atomicdata.dev/properties/name": "Valid",
+  "https://atomicdata.dev/properties/description": "An article"
+}"#,
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.args(["validate", valid.to_str().unwrap()])
+            .assert()
+            .success();
+
+        let invalid = dir.join("invalid.json");
+        std::fs::write(
+            &invalid,
+            r#"{
+  "@id": "https://example.com/article",
+  "https://atomicdata.dev/properties/isA": ["https://atomicdata.dev/classes/Article"],
+  "https://atomicdata.dev/properties/name": "Invalid",
+  "https://atomicdata.dev/properties/description": "An article",
+  "https://atomicdata.dev/properties/published-at": "yesterday"
+}"#,
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .args(["validate", invalid.to_str().unwrap(), "--format", "json"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["issues"][0]["kind"], "invalid-datatype");
+    }
+
+    #[test]
+    fn remote_requires_config() {
+        let home = std::env::temp_dir().join("atomic-cli-remote");
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(&home).unwrap();
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .env("HOME", &home)
+            .args(["--remote", "list"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("requires a config"));
+    }
+
+    #[test]
+    fn bulk_dry_run() {
+        let input = format!(
+            "subject,property,value\n{class},description,\"Cleaned up, with a comma\"\n{class},shortname\n{class}\n",
+            class = atomic_lib::urls::CLASS
+        );
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .args(["bulk", "--dry-run"])
+            .write_stdin(input)
+            .output()
+            .unwrap();
+        // The last row has no property
+        assert!(!output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("description: Cleaned up, with a comma"));
+        assert!(stdout.contains("would apply 2 operations, 1 failed"));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Row 4: expected"));
+    }
+
+    #[test]
+    fn map_add_and_remove() {
+        let home = std::env::temp_dir().join("atomic-cli-map");
+        let _ = std::fs::remove_dir_all(&home);
+        let run = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+            let output = cmd.env("HOME", &home).args(args).output().unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8(output.stdout).unwrap()
+        };
+        run(&["map", "add", "atomic", "https://atomicdata.dev/"]);
+        assert!(run(&["map", "list"]).contains("https://atomicdata.dev/"));
+        // The prefix is expanded for the subject and the property
+        let out = run(&["get", "atomic:classes/Class properties:shortname"]);
+        assert_eq!(out.trim(), "class");
+        run(&["map", "rm", "atomic"]);
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        cmd.env("HOME", &home)
+            .args(["get", "atomic:classes/Class"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn serve_resources_and_commits() {
+        use atomic_lib::Storelike;
+        let port = "9893";
+        let server_url = format!("http://localhost:{}", port);
+        let mut server =
+            std::process::Command::new(assert_cmd::cargo::cargo_bin(assert_cmd::crate_name!()))
+                .args(["serve", "--port", port])
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .unwrap();
+        let mut drive = None;
+        for _ in 0..50 {
+            if let Ok(body) =
+                atomic_lib::client::fetch_body(&server_url, atomic_lib::parse::JSON_AD_MIME, None)
+            {
+                drive = Some(body);
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        let client = atomic_lib::Store::init().unwrap();
+        client.populate().unwrap();
+        let mut agent = atomic_lib::agents::Agent::new(None, &client).unwrap();
+        agent.subject = format!("{}/agents/{}", server_url, agent.public_key);
+        client.set_default_agent(agent);
+        let subject = format!("{}/served", server_url);
+        let mut resource = atomic_lib::Resource::new(subject.clone());
+        resource
+            .set_propval_string(atomic_lib::urls::NAME.into(), "Served", &client)
+            .unwrap();
+        resource
+            .set_propval_string(atomic_lib::urls::PARENT.into(), &server_url, &client)
+            .unwrap();
+        let saved = resource.save(&client);
+        let fetched =
+            atomic_lib::client::fetch_body(&subject, atomic_lib::parse::JSON_AD_MIME, None);
+        server.kill().unwrap();
+        server.wait().unwrap();
+
+        assert!(drive.unwrap().contains("Local store"));
+        saved.unwrap();
+        assert!(fetched.unwrap().contains("Served"));
+    }
+
+    #[test]
+    fn get_as_table_and_tree() {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd
+            .args(["get", "class requires", "--as", "table"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let table = String::from_utf8(output.stdout).unwrap();
+        let mut lines = table.lines();
+        assert_eq!(lines.next().unwrap(), "property");
+        let header = lines.next().unwrap();
+        assert!(header.starts_with("subject"));
+        assert!(header.contains("datatype"));
+        assert!(table.contains(atomic_lib::urls::SHORTNAME));
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        let output = cmd.args(["get", "class", "--as", "tree"]).output().unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with("class https://atomicdata.dev/classes/Class"));
+    }
 }
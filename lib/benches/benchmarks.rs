@@ -57,6 +57,21 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("resource.write_json_ad()", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            big_resource.write_json_ad(&mut out).unwrap();
+        })
+    });
+
+    let all_resources = store.all_resources(false).collect::<Vec<Resource>>();
+
+    c.bench_function("resources_to_json_ad()", |b| {
+        b.iter(|| {
+            serialize::resources_to_json_ad(&all_resources).unwrap();
+        })
+    });
+
     c.bench_function("resource.to_json_ld()", |b| {
         b.iter(|| {
             big_resource.to_json_ld(&store).unwrap();
@@ -0,0 +1,103 @@
+//! Benchmarks on synthetic stores of various sizes, for the operations that grow with the amount of data:
+//! applying Commits, querying, building the index, serializing and parsing.
+//! Run using `cargo bench -p atomic_lib --all-features --bench synthetic`.
+//! See contribute.md for comparing the results against a baseline.
+
+use atomic_lib::{
+    parse::{parse_json_ad_string, ParseOpts, SaveOpts},
+    serialize::resources_to_json_ad,
+    storelike::Query,
+    utils::random_string,
+    *,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// The amount of resources in the synthetic stores.
+const SIZES: &[usize] = &[100, 1_000, 10_000];
+
+/// A store with `size` resources in a single folder, each with a name and a description.
+fn synthetic_store(size: usize) -> (Db, String) {
+    let store = Db::init_temp(&format!("bench_synthetic_{}", size)).unwrap();
+    let folder = format!("{}/folder", store.get_server_url());
+    for i in 0..size {
+        let mut resource = Resource::new(format!("{}/item-{}", folder, i));
+        resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        resource.set_propval_unsafe(urls::NAME.into(), Value::String(random_string(10)));
+        resource.set_propval_unsafe(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(random_string(200)),
+        );
+        store
+            .add_resource_opts(&resource, false, true, true)
+            .unwrap();
+    }
+    (store, folder)
+}
+
+fn children(store: &Db, folder: &str) -> Vec<Resource> {
+    let mut q = Query::new_prop_val(urls::PARENT, folder);
+    q.include_external = true;
+    q.include_nested = false;
+    q.limit = None;
+    store
+        .query_uncached(&q)
+        .unwrap()
+        .subjects
+        .iter()
+        .map(|subject| store.get_resource(subject).unwrap())
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic");
+    group.sample_size(10);
+    for &size in SIZES {
+        let (store, folder) = synthetic_store(size);
+
+        group.bench_with_input(BenchmarkId::new("commit", size), &size, |b, size| {
+            let mut resource = store
+                .get_resource(&format!("{}/item-{}", folder, size / 2))
+                .unwrap();
+            b.iter(|| {
+                resource.set_propval_unsafe(urls::NAME.into(), Value::String(random_string(10)));
+                resource.save_locally(&store).unwrap();
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("query", size), &size, |b, _size| {
+            let mut q = Query::new_prop_val(urls::PARENT, &folder);
+            q.sort_by = Some(urls::NAME.into());
+            q.limit = Some(50);
+            b.iter(|| store.query_uncached(&q).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("build_index", size), &size, |b, _size| {
+            b.iter(|| {
+                store.clear_index().unwrap();
+                store.build_index(true).unwrap();
+            })
+        });
+
+        let resources = children(&store, &folder);
+
+        group.bench_with_input(BenchmarkId::new("to_json_ad", size), &size, |b, _size| {
+            b.iter(|| resources_to_json_ad(&resources).unwrap())
+        });
+
+        let json = resources_to_json_ad(&resources).unwrap();
+        let parse_opts = ParseOpts {
+            save: SaveOpts::DontSave,
+            ..Default::default()
+        };
+
+        group.bench_with_input(
+            BenchmarkId::new("parse_json_ad", size),
+            &size,
+            |b, _size| b.iter(|| parse_json_ad_string(&json, &store, &parse_opts).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,4 +1,5 @@
 fn main() {
+    use base64::{engine::general_purpose, Engine};
     use ring::{
         rand,
         signature::{self, KeyPair},
@@ -17,9 +18,9 @@ fn main() {
     const MESSAGE: &[u8] = b"hello, world";
     let sig = key_pair.sign(MESSAGE);
 
-    let pubkey_b64 = base64::encode(key_pair.public_key());
+    let pubkey_b64 = general_purpose::STANDARD.encode(key_pair.public_key());
 
-    let peer_public_key_bytes = base64::decode(pubkey_b64).unwrap();
+    let peer_public_key_bytes = general_purpose::STANDARD.decode(pubkey_b64).unwrap();
 
     // Normally an application would extract the bytes of the signature and
     // send them in a protocol message to the peer(s). Here we just get the
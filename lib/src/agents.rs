@@ -1,10 +1,20 @@
 //! Logic for Agents
 //! Agents are actors (such as users) that can edit content.
 //! https://docs.atomicdata.dev/commits/concepts.html
+//!
+//! Agents can rotate their key by signing a Commit that sets a new `publicKey` with their current key.
+//! The old key is moved to the `keyHistory`, so Commits that were signed with it can still be verified using [public_key_at].
+//! New Commits and authentication headers (including session cookies) must use the new key, and API tokens created before the rotation stop working.
+//!
+//! Next to its main key, an Agent can have a key for every device in its `deviceKeys`, so users don't have to copy their private key.
+//! These are managed at the `/devices` endpoint, see [crate::plugins::devices]. Revoked device keys are moved to the `keyHistory` as well.
 
 use base64::{engine::general_purpose, Engine};
 
-use crate::{errors::AtomicResult, urls, Resource, Storelike, Value};
+use crate::{
+    errors::AtomicResult, resources::PropVals, urls, values::SubResource, Commit, Resource,
+    Storelike, Value,
+};
 
 #[derive(Clone, Debug)]
 pub struct Agent {
@@ -77,6 +87,129 @@ impl Agent {
     }
 }
 
+/// Whether the Agent has been disabled by an admin.
+/// Disabled Agents can't sign Commits or authenticate requests.
+pub fn is_disabled(store: &impl Storelike, agent: &str) -> bool {
+    store
+        .get_value(agent, urls::AGENT_DISABLED)
+        .and_then(|v| v.to_bool())
+        .unwrap_or(false)
+}
+
+/// The previous keys of the Agent, with the moment they were replaced, from old to new.
+pub(crate) fn key_history(agent: &Resource) -> AtomicResult<Vec<(String, i64)>> {
+    let Ok(Value::ResourceArray(entries)) = agent.get(urls::KEY_HISTORY) else {
+        return Ok(Vec::new());
+    };
+    let mut history = Vec::new();
+    for entry in entries {
+        let SubResource::Nested(propvals) = entry else {
+            return Err(format!("Invalid keyHistory in {}", agent.get_subject()).into());
+        };
+        let key = propvals
+            .get(urls::PUBLIC_KEY)
+            .ok_or("Entry in keyHistory has no publicKey")?;
+        let rotated_at = propvals
+            .get(urls::KEY_ROTATED_AT)
+            .ok_or("Entry in keyHistory has no keyRotatedAt")?;
+        history.push((key.to_string(), rotated_at.to_int()?));
+    }
+    Ok(history)
+}
+
+/// The public keys of the devices of the Agent, see [crate::plugins::devices].
+pub fn device_keys(agent: &Resource) -> AtomicResult<Vec<String>> {
+    let Ok(Value::ResourceArray(entries)) = agent.get(urls::DEVICE_KEYS) else {
+        return Ok(Vec::new());
+    };
+    let mut keys = Vec::new();
+    for entry in entries {
+        let SubResource::Nested(propvals) = entry else {
+            return Err(format!("Invalid deviceKeys in {}", agent.get_subject()).into());
+        };
+        let key = propvals
+            .get(urls::PUBLIC_KEY)
+            .ok_or("Entry in deviceKeys has no publicKey")?;
+        keys.push(key.to_string());
+    }
+    Ok(keys)
+}
+
+/// The keys that can currently be used to sign Commits and requests for the Agent: its main key and its device keys.
+pub fn active_public_keys(agent: &Resource) -> AtomicResult<Vec<String>> {
+    let mut keys = vec![agent.get(urls::PUBLIC_KEY)?.to_string()];
+    keys.extend(device_keys(agent)?);
+    Ok(keys)
+}
+
+/// Returns the public keys that the Agent could use at `timestamp`.
+/// These are the active keys, and the keys that have been rotated or revoked since.
+pub fn public_keys_at(agent: &Resource, timestamp: i64) -> AtomicResult<Vec<String>> {
+    let mut keys: Vec<String> = key_history(agent)?
+        .into_iter()
+        .filter(|(_key, rotated_at)| timestamp <= *rotated_at)
+        .map(|(key, _rotated_at)| key)
+        .collect();
+    keys.extend(active_public_keys(agent)?);
+    Ok(keys)
+}
+
+/// The last moment the Agent rotated its key, if it ever did.
+/// Credentials that were issued before this moment, such as API tokens, should no longer be accepted.
+pub fn key_rotated_at(store: &impl Storelike, agent: &str) -> Option<i64> {
+    let agent = store.get_resource(agent).ok()?;
+    key_history(&agent)
+        .ok()?
+        .iter()
+        .map(|(_key, rotated_at)| *rotated_at)
+        .max()
+}
+
+/// Adds an entry to the `keyHistory` of the Agent, so Commits signed with the key can still be verified.
+pub(crate) fn push_key_history(agent: &mut Resource, key: String, rotated_at: i64) {
+    let mut entries: Vec<SubResource> = match agent.get(urls::KEY_HISTORY) {
+        Ok(Value::ResourceArray(entries)) => entries.clone(),
+        _ => Vec::new(),
+    };
+    let mut entry = PropVals::new();
+    entry.insert(urls::PUBLIC_KEY.into(), Value::String(key));
+    entry.insert(urls::KEY_ROTATED_AT.into(), Value::Timestamp(rotated_at));
+    entries.push(SubResource::Nested(entry));
+    agent.set_propval_unsafe(urls::KEY_HISTORY.into(), Value::ResourceArray(entries));
+}
+
+/// Moves the previous key of the Agent to its `keyHistory`, when a Commit changes its `publicKey`.
+/// When `validate_rights` is true, only the Agent itself can rotate its key, by signing the Commit with its current key.
+pub(crate) fn rotate_key(
+    commit: &Commit,
+    resource_old: &Resource,
+    resource_new: &mut Resource,
+    validate_rights: bool,
+) -> AtomicResult<()> {
+    let Ok(old_key) = resource_old.get(urls::PUBLIC_KEY) else {
+        return Ok(());
+    };
+    let old_key = old_key.to_string();
+    let new_key = resource_new.get(urls::PUBLIC_KEY)?.to_string();
+    if old_key == new_key {
+        return Ok(());
+    }
+    if validate_rights && &commit.signer != resource_old.get_subject() {
+        return Err(crate::AtomicError::unauthorized(
+            "Only the Agent itself can rotate its key, by signing with its current key".into(),
+        ));
+    }
+    verify_public_key(&new_key)?;
+    let history = key_history(resource_old)?;
+    if history.iter().any(|(key, _)| key == &new_key)
+        || device_keys(resource_old)?.contains(&new_key)
+    {
+        return Err("This key has been used by the Agent before. Generate a new one.".into());
+    }
+    push_key_history(resource_new, old_key, commit.created_at);
+    Ok(())
+}
+
 /// keypair, serialized using base64
 pub struct Pair {
     pub private: String,
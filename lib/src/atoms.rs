@@ -5,6 +5,18 @@ use crate::{
     values::{ReferenceString, SortableValue, Value},
 };
 
+/// Separates the Properties in a path to a value of a nested resource, see [nested_path].
+pub const NESTED_PATH_SEPARATOR: &str = " ";
+
+/// Creates a path to a value in a nested resource, e.g. the `city` in the `address` of a resource.
+/// Values of nested resources are indexed with their path as Property, so the path can be used in the `property` and `sort_by` of a [Query](crate::storelike::Query).
+/// Properties are URLs, which can't contain spaces, so they are joined with a space.
+/// Nested values are not in the reference index, so a Query for a value without a property does not find them.
+/// Resources that were stored before nested values were indexed are found after the index is rebuilt.
+pub fn nested_path(properties: &[&str]) -> String {
+    properties.join(NESTED_PATH_SEPARATOR)
+}
+
 /// The Atom is the smallest meaningful piece of data.
 /// It describes how one value relates to a subject.
 /// A [Resource] can be converted into a bunch of Atoms.
@@ -35,18 +47,29 @@ impl Atom {
     /// Converts one Atom to a series of stringified values that can be indexed.
     pub fn to_indexable_atoms(&self) -> Vec<IndexAtom> {
         let sort_value = self.value.to_sortable_string();
-        let index_atoms = match &self.value.to_reference_index_strings() {
-            Some(v) => v,
-            None => return vec![],
+        let mut index_atoms: Vec<IndexAtom> = self
+            .value
+            .to_reference_index_strings()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| IndexAtom {
+                ref_value: v.into(),
+                sort_value: sort_value.clone(),
+                subject: self.subject.clone(),
+                property: self.property.clone(),
+            })
+            .collect();
+        // The values of nested resources are indexed with their path, for the subject of the parent
+        for propvals in self.value.nested_propvals() {
+            for (prop, value) in propvals {
+                let nested = Atom::new(
+                    self.subject.clone(),
+                    nested_path(&[&self.property, prop]),
+                    value.clone(),
+                );
+                index_atoms.extend(nested.to_indexable_atoms());
+            }
         }
-        .iter()
-        .map(|v| IndexAtom {
-            ref_value: v.into(),
-            sort_value: sort_value.clone(),
-            subject: self.subject.clone(),
-            property: self.property.clone(),
-        })
-        .collect();
         index_atoms
     }
 }
@@ -62,6 +85,13 @@ pub struct IndexAtom {
     pub sort_value: SortableValue,
 }
 
+impl IndexAtom {
+    /// True if the value is in a nested resource, and the property is a [nested_path].
+    pub fn is_nested(&self) -> bool {
+        self.property.contains(NESTED_PATH_SEPARATOR)
+    }
+}
+
 impl std::fmt::Display for Atom {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         fmt.write_str(&format!(
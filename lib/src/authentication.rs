@@ -1,8 +1,6 @@
 //! Check signatures in authentication headers, find the correct agent. Authorization is done in Hierarchies
 
-use crate::{
-    agents::decode_base64, commit::check_timestamp, errors::AtomicResult, urls, Storelike,
-};
+use crate::{agents::decode_base64, commit::check_timestamp, errors::AtomicResult, Storelike};
 
 /// Set of values extracted from the request.
 /// Most are coming from headers.
@@ -59,14 +57,19 @@ pub fn get_agent_from_auth_values_and_check(
             .map_err(|e| format!("Error checking authentication headers. {}", e))?;
         // check if the timestamp is valid
         check_timestamp(auth_vals.timestamp)?;
-        // check if the public key belongs to the agent
-        let found_public_key = store.get_value(&auth_vals.agent_subject, urls::PUBLIC_KEY)?;
-        if found_public_key.to_string() != auth_vals.public_key {
+        // check if the public key belongs to the agent, either as its main key or as one of its device keys
+        let agent = store.get_resource(&auth_vals.agent_subject)?;
+        if !crate::agents::active_public_keys(&agent)?.contains(&auth_vals.public_key) {
             return Err(
                 "The public key in the auth headers does not match the public key in the agent"
                     .to_string()
                     .into(),
             );
+        } else if crate::agents::is_disabled(store, &auth_vals.agent_subject) {
+            return Err(crate::AtomicError::unauthorized(format!(
+                "Agent {} has been disabled",
+                auth_vals.agent_subject
+            )));
         } else {
             for_agent = auth_vals.agent_subject;
         }
@@ -1,5 +1,8 @@
-//! Functions for interacting with an Atomic Server
-use url::Url;
+//! Functions for interacting with an Atomic Server.
+//! All requests share one HTTP client, which keeps connections alive and reuses them for the same host.
+//! Failed GET requests are retried a few times, see [MAX_RETRIES].
+
+use std::{sync::OnceLock, time::Duration};
 
 use crate::{
     agents::Agent,
@@ -9,6 +12,47 @@ use crate::{
     Resource, Storelike,
 };
 
+/// Default timeout for requests, including reading the body.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Amount of times a GET request is retried after a connection error or a temporary server error (429, 502, 503, 504).
+pub const MAX_RETRIES: u32 = 2;
+/// Delay before the first retry. Doubles after every retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+/// Maximum amount of requests that [fetch_resources] performs at the same time.
+pub const MAX_CONCURRENT_FETCHES: usize = 8;
+
+/// The HTTP client that is shared by all requests, so connections can be reused.
+fn http_client() -> &'static ureq::Agent {
+    static CLIENT: OnceLock<ureq::Agent> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        ureq::builder()
+            .timeout_connect(DEFAULT_TIMEOUT)
+            .max_idle_connections_per_host(MAX_CONCURRENT_FETCHES)
+            .build()
+    })
+}
+
+/// Performs a GET request, and retries it with an exponential backoff if it fails temporarily.
+// The error type of ureq is large, but it is handled right away
+#[allow(clippy::result_large_err)]
+fn call_with_retry(request: ureq::Request) -> Result<ureq::Response, ureq::Error> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut retries = 0;
+    loop {
+        match request.clone().call() {
+            Err(ureq::Error::Status(429 | 502 | 503 | 504, _) | ureq::Error::Transport(_))
+                if retries < MAX_RETRIES =>
+            {
+                retries += 1;
+                tracing::debug!("Retrying {} ({})", request.url(), retries);
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// Fetches a resource, makes sure its subject matches.
 /// Checks the datatypes for the Values.
 /// Ignores all atoms where the subject is different.
@@ -25,6 +69,44 @@ pub fn fetch_resource(
     Ok(resource)
 }
 
+/// Fetches multiple resources, with at most [MAX_CONCURRENT_FETCHES] requests at the same time.
+/// Returns the results in the same order as the subjects.
+/// Only the requests are concurrent, the bodies are parsed one by one, since parsing can use the store.
+#[tracing::instrument(skip(store, for_agent), level = "info")]
+pub fn fetch_resources(
+    subjects: &[String],
+    store: &impl Storelike,
+    for_agent: Option<Agent>,
+) -> Vec<AtomicResult<Resource>> {
+    let mut results = Vec::with_capacity(subjects.len());
+    for chunk in subjects.chunks(MAX_CONCURRENT_FETCHES) {
+        let bodies: Vec<AtomicResult<String>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|subject| {
+                    let for_agent = for_agent.clone();
+                    scope.spawn(move || fetch_body(subject, crate::parse::JSON_AD_MIME, for_agent))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err("Fetching thread panicked".into()))
+                })
+                .collect()
+        });
+        for (subject, body) in chunk.iter().zip(bodies) {
+            results.push(body.and_then(|body| {
+                parse_json_ad_resource(&body, store, &ParseOpts::default())
+                    .map_err(|e| format!("Error parsing body of {}. {}", subject, e).into())
+            }));
+        }
+    }
+    results
+}
+
 /// Returns the various x-atomic authentication headers, includign agent signature
 pub fn get_authentication_headers(url: &str, agent: &Agent) -> AtomicResult<Vec<(String, String)>> {
     let mut headers = Vec::new();
@@ -49,21 +131,40 @@ pub fn get_authentication_headers(url: &str, agent: &Agent) -> AtomicResult<Vec<
 /// Uses the store's Agent agent (if set) to sign the request.
 #[tracing::instrument(level = "info")]
 pub fn fetch_body(url: &str, content_type: &str, for_agent: Option<Agent>) -> AtomicResult<String> {
+    fetch_body_with_timeout(url, content_type, for_agent, DEFAULT_TIMEOUT)
+}
+
+/// Fetches a URL like [fetch_body], but allows more time for large bodies.
+#[tracing::instrument(level = "info")]
+pub fn fetch_body_with_timeout(
+    url: &str,
+    content_type: &str,
+    for_agent: Option<Agent>,
+    timeout: Duration,
+) -> AtomicResult<String> {
     if !url.starts_with("http") {
         return Err(format!("Could not fetch url '{}', must start with http.", url).into());
     }
-    if let Some(agent) = for_agent {
-        get_authentication_headers(url, &agent)?;
-    }
+    let headers = match for_agent {
+        Some(agent) => get_authentication_headers(url, &agent)?,
+        None => Vec::new(),
+    };
 
-    let agent = ureq::builder()
-        .timeout(std::time::Duration::from_secs(2))
-        .build();
-    let resp = agent
+    let mut request = http_client()
         .get(url)
-        .set("Accept", content_type)
-        .call()
-        .map_err(|e| format!("Error when server tried fetching {} : {}", url, e))?;
+        .timeout(timeout)
+        .set("Accept", content_type);
+    for (key, value) in &headers {
+        request = request.set(key, value);
+    }
+    let resp = call_with_retry(request).map_err(|e| match e {
+        // Callers such as `atomic-cli sync` need to know whether the resource is gone, or just unreachable
+        ureq::Error::Status(404, _) => crate::AtomicError::not_found(format!(
+            "Error when server tried fetching {} : {}",
+            url, e
+        )),
+        e => format!("Error when server tried fetching {} : {}", url, e).into(),
+    })?;
     let status = resp.status();
     let body = resp
         .into_string()
@@ -95,12 +196,10 @@ pub fn post_commit_custom_endpoint(
 ) -> AtomicResult<()> {
     let json = commit.into_resource(store)?.to_json_ad()?;
 
-    let agent = ureq::builder()
-        .timeout(std::time::Duration::from_secs(2))
-        .build();
-
-    let resp = agent
+    // Commits are not retried, since the server might have applied them already
+    let resp = http_client()
         .post(endpoint)
+        .timeout(DEFAULT_TIMEOUT)
         .set("Content-Type", "application/json")
         .send_string(&json)
         .map_err(|e| format!("Error when posting commit to {} : {}", endpoint, e))?;
@@ -131,6 +230,41 @@ mod test {
         assert!(shortname.to_string() == "shortname");
     }
 
+    /// Serves the responses one by one, on new connections.
+    fn serve(responses: Vec<&'static str>) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/thing", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn fetch_body_retries() {
+        let url = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok",
+        ]);
+        assert_eq!(fetch_body(&url, "text/plain", None).unwrap(), "ok");
+
+        // Missing resources are not retried
+        let url = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        ]);
+        let err = fetch_body(&url, "text/plain", None).unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            crate::errors::AtomicErrorType::NotFoundError
+        ));
+    }
+
     #[test]
     #[ignore]
     fn post_commit_basic() {
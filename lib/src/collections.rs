@@ -157,12 +157,14 @@ pub fn sort_resources(
     sort_desc: bool,
 ) -> ResourceCollection {
     resources.sort_by(|a, b| {
-        let val_a = a.get(sort_by);
-        let val_b = b.get(sort_by);
-        if val_a.is_err() || val_b.is_err() {
-            return std::cmp::Ordering::Greater;
+        let (val_a, val_b) = match (a.get(sort_by), b.get(sort_by)) {
+            (Ok(val_a), Ok(val_b)) => (val_a, val_b),
+            // Resources without the property come last
+            (Ok(_), Err(_)) => return std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => return std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => return std::cmp::Ordering::Equal,
         };
-        if val_b.unwrap().to_string() > val_a.unwrap().to_string() {
+        if val_b.to_string() > val_a.to_string() {
             if sort_desc {
                 std::cmp::Ordering::Greater
             } else {
@@ -211,6 +213,7 @@ impl Collection {
             include_external: collection_builder.include_external,
             include_nested: collection_builder.include_nested,
             for_agent: for_agent.map(|a| a.to_string()),
+            geo: None,
         };
 
         let query_result = store.query(&q)?;
@@ -520,7 +523,6 @@ mod test {
         let store = crate::db::test::DB.lock().unwrap().clone();
         let subjects: Vec<String> = store
             .all_resources(false)
-            .into_iter()
             .map(|r| r.get_subject().into())
             .collect();
         println!("{:?}", subjects);
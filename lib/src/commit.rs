@@ -9,6 +9,7 @@ use crate::{
     datatype::DataType,
     errors::AtomicResult,
     hierarchy,
+    normalize::canonicalize_url,
     resources::PropVals,
     urls,
     values::SubResource,
@@ -76,6 +77,15 @@ pub struct Commit {
     /// List of Properties and Arrays to be appended to them
     #[serde(rename = "https://atomicdata.dev/properties/push")]
     pub push: Option<std::collections::HashMap<String, Value>>,
+    /// Moves the Resource right before this sibling, by setting its `sortKey`
+    #[serde(rename = "https://atomicdata.dev/properties/moveBefore")]
+    pub move_before: Option<String>,
+    /// Moves the Resource right after this sibling, by setting its `sortKey`
+    #[serde(rename = "https://atomicdata.dev/properties/moveAfter")]
+    pub move_after: Option<String>,
+    /// The Agent for whom the server signed this Commit. Only allowed if the signer is the default Agent of the server.
+    #[serde(rename = "https://atomicdata.dev/properties/onBehalfOf")]
+    pub on_behalf_of: Option<String>,
     /// The previously applied commit to this Resource.
     #[serde(rename = "https://atomicdata.dev/properties/previousCommit")]
     pub previous_commit: Option<String>,
@@ -83,7 +93,72 @@ pub struct Commit {
     pub url: Option<String>,
 }
 
+/// The Atoms that applying a Commit removes from and adds to the index.
+#[derive(Debug, Default)]
+struct IndexDelta {
+    remove: Vec<Atom>,
+    add: Vec<Atom>,
+}
+
+impl IndexDelta {
+    /// `resource_old` is used for removing the old Atoms from indexed queries, `resource_new` for adding the new ones.
+    fn update_index(
+        self,
+        store: &impl Storelike,
+        resource_old: &Resource,
+        resource_new: &Resource,
+    ) -> AtomicResult<()> {
+        for atom in self.remove {
+            store
+                .remove_atom_from_index(&atom, resource_old)
+                .map_err(|e| format!("Error removing atom from index: {e}  Atom: {atom}"))?
+        }
+        for atom in self.add {
+            store
+                .add_atom_to_index(&atom, resource_new)
+                .map_err(|e| format!("Error adding atom to index: {e}  Atom: {atom}"))?;
+        }
+        Ok(())
+    }
+}
+
 impl Commit {
+    fn check_signature(
+        &self,
+        store: &impl Storelike,
+        signature: &str,
+        public_keys: &[String],
+    ) -> AtomicResult<()> {
+        let stringified_commit = self.serialize_deterministically_json_ad(store)?;
+        let signature_bytes = decode_base64(signature)?;
+        // The Commit does not say which key of the Agent signed it
+        for pubkey_b64 in public_keys {
+            let agent_pubkey = decode_base64(pubkey_b64)?;
+            let peer_public_key =
+                ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, agent_pubkey);
+            if peer_public_key
+                .verify(stringified_commit.as_bytes(), &signature_bytes)
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+        Err(format!(
+            "Incorrect signature for Commit. This could be due to an error during signing or serialization of the commit. Compare this to the serialized commit in the client: {}",
+            stringified_commit,
+        )
+        .into())
+    }
+
+    /// Checks whether an existing Commit was signed by its signer, using the key the signer had when the Commit was created.
+    /// Commits that were signed before a key rotation can still be verified, see [crate::agents::public_keys_at].
+    pub fn verify_signature(&self, store: &impl Storelike) -> AtomicResult<()> {
+        let signature = self.signature.as_ref().ok_or("No signature set")?;
+        let signer = store.get_resource(&self.signer)?;
+        let public_keys = crate::agents::public_keys_at(&signer, self.created_at)?;
+        self.check_signature(store, signature, &public_keys)
+    }
+
     /// Apply a single signed Commit to the store.
     /// Creates, edits or destroys a resource.
     /// Allows for control over which validations should be performed.
@@ -106,23 +181,25 @@ impl Commit {
                 Some(sig) => sig,
                 None => return Err("No signature set".into()),
             };
-            let pubkey_b64 = store
-                .get_resource(&self.signer)?
-                .get(urls::PUBLIC_KEY)?
-                .to_string();
-            let agent_pubkey = decode_base64(&pubkey_b64)?;
-            let stringified_commit = self.serialize_deterministically_json_ad(store)?;
-            let peer_public_key =
-                ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, agent_pubkey);
-            let signature_bytes = decode_base64(signature)?;
-            peer_public_key
-                .verify(stringified_commit.as_bytes(), &signature_bytes)
-                .map_err(|_e| {
-                    format!(
-                        "Incorrect signature for Commit. This could be due to an error during signing or serialization of the commit. Compare this to the serialized commit in the client: {}",
-                        stringified_commit,
-                    )
-                })?;
+            if crate::agents::is_disabled(store, &self.signer) {
+                return Err(crate::AtomicError::unauthorized(format!(
+                    "Agent {} has been disabled, and can not sign Commits",
+                    self.signer
+                )));
+            }
+            // New Commits must be signed with an active key, even if they claim to be older
+            let public_keys =
+                crate::agents::active_public_keys(&store.get_resource(&self.signer)?)?;
+            self.check_signature(store, signature, &public_keys)?;
+            let signed_by_server = store
+                .get_default_agent()
+                .is_ok_and(|agent| agent.subject == self.signer);
+            if self.on_behalf_of.is_some() && !signed_by_server {
+                return Err(crate::AtomicError::unauthorized(format!(
+                    "Only the server can sign Commits with {}",
+                    urls::ON_BEHALF_OF
+                )));
+            }
         }
         // Check if the created_at lies in the past
         if opts.validate_timestamp {
@@ -146,7 +223,15 @@ impl Commit {
 
                 if let Some(prev_commit) = self.previous_commit.clone() {
                     // TODO: try auto merge
-                    if last_commit != prev_commit {
+                    let (last, prev) = if store.normalizers().canonicalize_urls {
+                        (
+                            canonicalize_url(&last_commit),
+                            canonicalize_url(&prev_commit),
+                        )
+                    } else {
+                        (last_commit.clone(), prev_commit.clone())
+                    };
+                    if last != prev {
                         return Err(format!(
                             "previousCommit mismatch. Had lastCommit '{}' in Resource {}, but got in Commit '{}'. Perhaps you created the Commit based on an outdated version of the Resource.",
                             last_commit, subject_url, prev_commit,
@@ -162,12 +247,37 @@ impl Commit {
             }
         };
 
-        let mut resource_new = self
-            .apply_changes(resource_old.clone(), store, false)
+        // The index is updated after all checks have passed, using the changes from this single pass
+        let (mut resource_new, index_delta) = self
+            .changes(resource_old.clone(), store, opts.update_index)
             .map_err(|e| format!("Error applying changes to Resource {}. {}", self.subject, e))?;
 
         if opts.validate_rights {
-            let validate_for = opts.validate_for_agent.as_ref().unwrap_or(&self.signer);
+            // Otherwise, disabled Agents could enable themselves
+            if self.changes_property(urls::AGENT_DISABLED) {
+                return Err(crate::AtomicError::unauthorized(format!(
+                    "{} can only be changed by admins, using the /admin/agents endpoint",
+                    urls::AGENT_DISABLED
+                )));
+            }
+            if self.changes_property(urls::DEVICE_KEYS) {
+                return Err(crate::AtomicError::unauthorized(format!(
+                    "{} can only be changed using the /devices endpoint",
+                    urls::DEVICE_KEYS
+                )));
+            }
+            if self.changes_property(urls::KEY_HISTORY) {
+                return Err(crate::AtomicError::unauthorized(format!(
+                    "{} is updated when the {} changes, and can not be changed directly",
+                    urls::KEY_HISTORY,
+                    urls::PUBLIC_KEY
+                )));
+            }
+            let validate_for = opts
+                .validate_for_agent
+                .as_ref()
+                .or(self.on_behalf_of.as_ref())
+                .unwrap_or(&self.signer);
             if is_new {
                 hierarchy::check_append(store, &resource_new, validate_for)?;
             } else {
@@ -183,9 +293,72 @@ impl Commit {
                     )?;
                 }
                 // This should use the _old_ resource, no the new one, as the new one might maliciously give itself write rights.
-                hierarchy::check_write(store, &resource_old, validate_for)?;
+                let locked = hierarchy::locked_props(store, &resource_old)?;
+                if let Some(prop) = locked.iter().find(|p| self.changes_property(p)) {
+                    hierarchy::check_owner(store, &resource_old, validate_for).map_err(|_e| {
+                        crate::AtomicError::unauthorized(format!(
+                            "{} is locked in {}, only owners of its Drive can change it",
+                            prop, self.subject
+                        ))
+                    })?;
+                }
+                // Authors can edit their own Messages and Reactions, even without write rights
+                #[cfg(feature = "db")]
+                let by_author = crate::plugins::chatroom::authors_can_edit(&resource_old);
+                #[cfg(not(feature = "db"))]
+                let by_author = false;
+                if by_author {
+                    #[cfg(feature = "db")]
+                    crate::plugins::chatroom::check_edit_rights(
+                        store,
+                        &resource_old,
+                        self,
+                        validate_for,
+                    )?;
+                } else if self.destroy == Some(true) {
+                    hierarchy::check_destroy(store, &resource_old, validate_for)?;
+                } else {
+                    hierarchy::check_write(store, &resource_old, validate_for)?;
+                }
+                // Moving a resource changes who can read it, so the new parent has to allow it
+                if self.changes_property(urls::PARENT)
+                    && resource_new.get(urls::PARENT).is_ok()
+                    && resource_new.get(urls::PARENT).map(|p| p.to_string()).ok()
+                        != resource_old.get(urls::PARENT).map(|p| p.to_string()).ok()
+                {
+                    hierarchy::check_append_to_parent(store, &resource_new, validate_for)?;
+                }
             }
         };
+        if !is_new && self.destroy != Some(true) && self.changes_property(urls::PUBLIC_KEY) {
+            crate::agents::rotate_key(
+                self,
+                &resource_old,
+                &mut resource_new,
+                opts.validate_rights,
+            )?;
+        }
+        if self.changes_property(urls::EXPRESSION) {
+            if let Ok(expression) = resource_new.get(urls::EXPRESSION) {
+                crate::computed::check_expression(&expression.to_string())?;
+            }
+        }
+        if self.changes_property(urls::UNIT) || self.changes_property(urls::DATATYPE_PROP) {
+            if let Ok(unit) = resource_new.get(urls::UNIT) {
+                let data_type = resource_new.get(urls::DATATYPE_PROP)?.to_string().parse()?;
+                crate::units::check_unit(&unit.to_string(), &data_type)?;
+            }
+        }
+        // New resources get the default values of their Properties, which are not part of the Commit
+        let mut default_props = Vec::new();
+        if is_new && self.destroy != Some(true) {
+            let agent = opts
+                .validate_for_agent
+                .as_ref()
+                .or(self.on_behalf_of.as_ref())
+                .unwrap_or(&self.signer);
+            default_props = resource_new.set_default_values(store, Some(agent))?;
+        }
         // Check if all required props are there
         if opts.validate_schema {
             resource_new.check_required_props(store)?;
@@ -205,20 +378,84 @@ impl Commit {
         for class in &_resource_new_classes {
             match class.subject.as_str() {
                 urls::COMMIT => return Err("Commits can not be edited or created directly.".into()),
-                urls::INVITE => {
-                    crate::plugins::invite::before_apply_commit(store, self, &resource_new)?
+                urls::INVITE => crate::plugins::invite::before_apply_commit(
+                    store,
+                    self,
+                    &resource_old,
+                    &resource_new,
+                )?,
+                urls::FORM => {
+                    crate::plugins::forms::before_apply_commit(store, self, &resource_new)?
+                }
+                urls::EVENT if self.destroy != Some(true) => {
+                    crate::plugins::calendar::before_apply_commit(&resource_new)?
+                }
+                urls::QUOTA if opts.validate_rights => {
+                    crate::plugins::quotas::before_apply_commit(store, self.author())?
+                }
+                urls::MESSAGE => {
+                    if self.destroy != Some(true) {
+                        crate::plugins::features::check_enabled(
+                            store,
+                            &resource_new,
+                            crate::plugins::features::Feature::Chatrooms,
+                        )?
+                    }
+                    crate::plugins::chatroom::before_apply_commit_message(
+                        store,
+                        self,
+                        &resource_old,
+                        &mut resource_new,
+                        opts.validate_rights,
+                    )?
+                }
+                urls::REACTION => {
+                    if self.destroy != Some(true) {
+                        crate::plugins::features::check_enabled(
+                            store,
+                            &resource_new,
+                            crate::plugins::features::Feature::Chatrooms,
+                        )?
+                    }
+                    crate::plugins::chatroom::before_apply_commit_reaction(
+                        store,
+                        self,
+                        &resource_old,
+                        &mut resource_new,
+                        opts.validate_for_agent.as_deref().unwrap_or(&self.signer),
+                    )?
+                }
+                // Existing ChatRooms can still be removed when the feature is disabled
+                urls::CHATROOM if self.destroy != Some(true) => {
+                    crate::plugins::features::check_enabled(
+                        store,
+                        &resource_new,
+                        crate::plugins::features::Feature::Chatrooms,
+                    )?
                 }
                 _other => {}
             };
         }
 
+        // Removing resources is always allowed, as it makes room in the Drive
+        if self.destroy != Some(true) {
+            store.check_quota((!is_new).then_some(&resource_old), &resource_new)?;
+        }
+
         // If a Destroy field is found, remove the resource and return early
         // TODO: Should we remove the existing commits too? Probably.
         if let Some(destroy) = self.destroy {
             if destroy {
-                // Note: the value index is updated before this action, in resource.apply_changes()
+                // Note: the resource is removed from the value index by remove_resource
                 store.remove_resource(&self.subject)?;
                 store.add_resource_opts(&commit_resource, false, opts.update_index, false)?;
+                #[cfg(feature = "db")]
+                if _resource_new_classes
+                    .iter()
+                    .any(|class| class.subject == urls::REACTION)
+                {
+                    crate::plugins::chatroom::after_apply_commit_reaction(store, &resource_old)?;
+                }
                 return Ok(CommitResponse {
                     resource_new: None,
                     resource_old: Some(resource_old),
@@ -228,8 +465,17 @@ impl Commit {
             }
         }
 
-        // We apply the changes again, but this time also update the index
-        self.apply_changes(resource_old.clone(), store, opts.update_index)?;
+        if opts.update_index {
+            index_delta.update_index(store, &resource_old, &resource_new)?;
+            for prop in default_props {
+                let atom = Atom::new(
+                    self.subject.clone(),
+                    prop.clone(),
+                    resource_new.get(&prop)?.clone(),
+                );
+                store.add_atom_to_index(&atom, &resource_new)?;
+            }
+        }
 
         // Save the Commit to the Store. We can skip the required props checking, but we need to make sure the commit hasn't been applied before.
         store.add_resource_opts(&commit_resource, false, opts.update_index, false)?;
@@ -237,7 +483,7 @@ impl Commit {
         store.add_resource_opts(&resource_new, false, false, true)?;
 
         let commit_response = CommitResponse {
-            resource_new: Some(resource_new.clone()),
+            resource_new: Some(resource_new),
             resource_old: Some(resource_old),
             commit_resource,
             commit_struct: self.clone(),
@@ -245,130 +491,179 @@ impl Commit {
 
         store.handle_commit(&commit_response);
 
+        // The Commit has been saved, so failing to send Notifications should not fail the Commit
+        #[cfg(feature = "db")]
+        if let Err(e) = crate::plugins::notifications::notify_shares(store, &commit_response) {
+            tracing::error!("Failed to send share notifications: {}", e);
+        }
+
         // AFTER APPLY COMMIT HANDLERS
         // Commit has been checked and saved.
         // Here you can add side-effects, such as creating new Commits.
         #[cfg(feature = "db")]
-        for class in _resource_new_classes {
-            match class.subject.as_str() {
-                urls::MESSAGE => crate::plugins::chatroom::after_apply_commit_message(
-                    store,
-                    self,
-                    &resource_new,
-                )?,
-                _other => {}
-            };
+        if let Some(resource_new) = &commit_response.resource_new {
+            for class in _resource_new_classes {
+                match class.subject.as_str() {
+                    urls::MESSAGE => crate::plugins::chatroom::after_apply_commit_message(
+                        store,
+                        self,
+                        resource_new,
+                    )?,
+                    urls::REACTION => {
+                        crate::plugins::chatroom::after_apply_commit_reaction(store, resource_new)?
+                    }
+                    urls::INVITE => {
+                        if let Err(e) =
+                            crate::plugins::notifications::notify_invite(store, self, resource_new)
+                        {
+                            tracing::error!("Failed to send invite notification: {}", e);
+                        }
+                    }
+                    _other => {}
+                };
+            }
         }
 
         Ok(commit_response)
     }
 
-    /// Updates the values in the Resource according to the `set`, `remove`, `push`, and `destroy` attributes in the Commit.
+    /// Whether the Commit sets, removes or pushes the Property.
+    pub(crate) fn changes_property(&self, property: &str) -> bool {
+        let in_map = |map: &Option<std::collections::HashMap<String, Value>>| {
+            map.as_ref().is_some_and(|m| m.contains_key(property))
+        };
+        in_map(&self.set)
+            || in_map(&self.push)
+            || self
+                .remove
+                .as_ref()
+                .is_some_and(|props| props.iter().any(|p| p == property))
+    }
+
+    /// Updates the values in the Resource according to the `set`, `remove`, `push`, `moveBefore`, `moveAfter` and `destroy` attributes in the Commit.
     /// Optionally also updates the index in the Store.
-    /// The Old Resource is only needed when `update_index` is true, and is used for checking
     #[tracing::instrument(skip(store))]
     pub fn apply_changes(
         &self,
-        mut resource: Resource,
+        resource: Resource,
         store: &impl Storelike,
         update_index: bool,
     ) -> AtomicResult<Resource> {
-        let resource_unedited = resource.clone();
-
-        let mut remove_atoms: Vec<Atom> = Vec::new();
-        let mut add_atoms: Vec<Atom> = Vec::new();
+        // The old resource is only needed for checking which indexed queries it was a member of
+        let resource_unedited = update_index.then(|| resource.clone());
+        let (resource, delta) = self.changes(resource, store, update_index)?;
+        if let Some(resource_unedited) = resource_unedited {
+            delta.update_index(store, &resource_unedited, &resource)?;
+        }
+        Ok(resource)
+    }
 
-        if let Some(remove) = self.remove.clone() {
-            for prop in remove.iter() {
-                resource.remove_propval(prop);
+    /// Applies the changes to the Resource in a single pass.
+    /// If `track_index` is true, also returns the Atoms that have to be removed from and added to the index.
+    /// Values are not added to the CommitBuilder of the Resource, as they are already part of this Commit.
+    fn changes(
+        &self,
+        mut resource: Resource,
+        store: &impl Storelike,
+        track_index: bool,
+    ) -> AtomicResult<(Resource, IndexDelta)> {
+        let mut delta = IndexDelta::default();
+        let subject = self.subject.as_str();
 
-                if update_index {
-                    if let Ok(val) = resource_unedited.get(prop) {
-                        let atom =
-                            Atom::new(resource.get_subject().clone(), prop.into(), val.clone());
-                        remove_atoms.push(atom);
-                    } else {
-                        // The property does not exist, so nothing to remove.
-                        //
-                        // This may happen if another concurrent commit has removed it first, or
-                        // client removed it without validating it exists. (Currently rust and
-                        // typescript clients do not validate that.)
+        if let Some(remove) = &self.remove {
+            for prop in remove {
+                // The property may not exist, if another concurrent commit has removed it first, or
+                // the client removed it without validating it exists. (Currently rust and
+                // typescript clients do not validate that.)
+                if let Some(val) = resource.take_propval(prop) {
+                    if track_index {
+                        delta
+                            .remove
+                            .push(Atom::new(subject.into(), prop.into(), val));
                     }
                 }
             }
         }
-        if let Some(set) = self.set.clone() {
-            for (prop, new_val) in set.iter() {
-                resource
-                    .set_propval(prop.into(), new_val.to_owned(), store)
-                    .map_err(|e| {
-                        format!(
-                            "Failed to set property '{}' to '{}' in Commit. Error: {}",
-                            prop, new_val, e
-                        )
-                    })?;
-
-                if update_index {
-                    let new_atom =
-                        Atom::new(resource.get_subject().clone(), prop.into(), new_val.clone());
-                    if let Ok(old_val) = resource_unedited.get(prop) {
-                        let old_atom =
-                            Atom::new(resource.get_subject().clone(), prop.into(), old_val.clone());
-                        remove_atoms.push(old_atom);
+        let normalizers = store.normalizers();
+        if let Some(set) = &self.set {
+            for (prop, new_val) in set {
+                let new_val = normalizers.normalize(new_val.clone());
+                resource.check_propval(prop, &new_val, store).map_err(|e| {
+                    format!(
+                        "Failed to set property '{}' to '{}' in Commit. Error: {}",
+                        prop, new_val, e
+                    )
+                })?;
+                let new_atom = track_index.then(|| new_val.clone());
+                let old_val = resource.insert_propval(prop.into(), new_val);
+                if let Some(new_val) = new_atom {
+                    if let Some(old_val) = old_val {
+                        delta
+                            .remove
+                            .push(Atom::new(subject.into(), prop.into(), old_val));
                     }
-                    add_atoms.push(new_atom);
+                    delta
+                        .add
+                        .push(Atom::new(subject.into(), prop.into(), new_val));
                 }
             }
         }
-        if let Some(push) = self.push.clone() {
-            for (prop, vec) in push.iter() {
-                let mut old_vec = match resource.get(prop) {
-                    Ok(val) => match val {
-                        Value::ResourceArray(res_arr) => res_arr.clone(),
-                        _other => return Err("Wrong datatype when pushing to array".into()),
-                    },
-                    Err(_) => Vec::new(),
-                };
-                let new_vec = match vec {
-                    Value::ResourceArray(res_arr) => res_arr.clone(),
+        if let Some(push) = &self.push {
+            for (prop, vec) in push {
+                let new_vec = match normalizers.normalize(vec.clone()) {
+                    Value::ResourceArray(res_arr) => res_arr,
                     _other => return Err("Wrong datatype when pushing to array".into()),
                 };
-                old_vec.append(&mut new_vec.clone());
-                resource.set_propval_unsafe(prop.into(), old_vec.into());
-                if update_index {
-                    for added_resource in new_vec {
-                        let atom = Atom::new(
-                            resource.get_subject().clone(),
+                let mut old_vec = match resource.take_propval(prop) {
+                    Some(Value::ResourceArray(res_arr)) => res_arr,
+                    Some(_other) => return Err("Wrong datatype when pushing to array".into()),
+                    None => Vec::new(),
+                };
+                if track_index {
+                    for added_resource in &new_vec {
+                        delta.add.push(Atom::new(
+                            subject.into(),
                             prop.into(),
-                            added_resource.into(),
-                        );
-                        add_atoms.push(atom);
+                            added_resource.clone().into(),
+                        ));
                     }
                 }
+                old_vec.extend(new_vec);
+                let new_val: Value = old_vec.into();
+                resource.check_propval(prop, &new_val, store).map_err(|e| {
+                    format!(
+                        "Failed to push to property '{}' in Commit. Error: {}",
+                        prop, e
+                    )
+                })?;
+                resource.insert_propval(prop.into(), new_val);
             }
         }
-        // Remove all atoms from index if destroy
-        if let Some(destroy) = self.destroy {
-            if destroy {
-                for atom in resource.to_atoms().into_iter() {
-                    remove_atoms.push(atom);
+        if self.move_before.is_some() || self.move_after.is_some() {
+            let sort_key = Value::String(crate::order::move_key(
+                store,
+                &resource,
+                self.move_before.as_deref(),
+                self.move_after.as_deref(),
+            )?);
+            let new_atom = track_index.then(|| sort_key.clone());
+            let old_val = resource.insert_propval(urls::SORT_KEY.into(), sort_key);
+            if let Some(sort_key) = new_atom {
+                if let Some(old_val) = old_val {
+                    delta
+                        .remove
+                        .push(Atom::new(subject.into(), urls::SORT_KEY.into(), old_val));
                 }
+                delta
+                    .add
+                    .push(Atom::new(subject.into(), urls::SORT_KEY.into(), sort_key));
             }
         }
-
-        if update_index {
-            for atom in remove_atoms {
-                store
-                    .remove_atom_from_index(&atom, &resource_unedited)
-                    .map_err(|e| format!("Error removing atom from index: {e}  Atom: {e}"))?
-            }
-            for atom in add_atoms {
-                store
-                    .add_atom_to_index(&atom, &resource)
-                    .map_err(|e| format!("Error adding atom to index: {e}  Atom: {e}"))?;
-            }
+        // Remove all atoms from index if destroy
+        if self.destroy == Some(true) && track_index {
+            delta.remove.extend(resource.to_atoms());
         }
-        Ok(resource)
+        Ok((resource, delta))
     }
 
     /// Applies a commit without performing authorization / signature / schema checks.
@@ -412,6 +707,9 @@ impl Commit {
             Ok(found) => Some(found.to_string()),
             Err(_) => None,
         };
+        let move_before = resource.get(urls::MOVE_BEFORE).ok().map(|v| v.to_string());
+        let move_after = resource.get(urls::MOVE_AFTER).ok().map(|v| v.to_string());
+        let on_behalf_of = resource.get(urls::ON_BEHALF_OF).ok().map(|v| v.to_string());
         let signature = resource.get(urls::SIGNATURE)?.to_string();
         let url = Some(resource.get_subject().into());
 
@@ -423,6 +721,9 @@ impl Commit {
             push,
             remove,
             destroy,
+            move_before,
+            move_after,
+            on_behalf_of,
             previous_commit,
             signature: Some(signature),
             url,
@@ -474,6 +775,22 @@ impl Commit {
                 resource.set_propval_unsafe(urls::DESTROY.into(), true.into());
             }
         }
+        if let Some(move_before) = &self.move_before {
+            resource.set_propval_unsafe(
+                urls::MOVE_BEFORE.into(),
+                Value::AtomicUrl(move_before.into()),
+            );
+        }
+        if let Some(move_after) = &self.move_after {
+            resource
+                .set_propval_unsafe(urls::MOVE_AFTER.into(), Value::AtomicUrl(move_after.into()));
+        }
+        if let Some(on_behalf_of) = &self.on_behalf_of {
+            resource.set_propval_unsafe(
+                urls::ON_BEHALF_OF.into(),
+                Value::AtomicUrl(on_behalf_of.into()),
+            );
+        }
         if let Some(previous_commit) = &self.previous_commit {
             resource.set_propval_unsafe(
                 urls::PREVIOUS_COMMIT.into(),
@@ -499,6 +816,12 @@ impl Commit {
         &self.subject
     }
 
+    /// The Agent that the Commit is attributed to: the [urls::ON_BEHALF_OF] Agent, or else the signer.
+    /// The commit log uses the same rule.
+    pub fn author(&self) -> &str {
+        self.on_behalf_of.as_deref().unwrap_or(&self.signer)
+    }
+
     /// Generates a deterministic serialized JSON-AD representation of the Commit.
     /// Removes the signature from the object before serializing, since this function is used to check if the signature is correct.
     #[tracing::instrument(skip(store))]
@@ -534,6 +857,14 @@ pub struct CommitBuilder {
     /// If set to true, deletes the entire resource
     /// https://atomicdata.dev/properties/destroy
     destroy: bool,
+    /// Moves the resource right before or after a sibling
+    /// https://atomicdata.dev/properties/moveBefore
+    move_before: Option<String>,
+    /// https://atomicdata.dev/properties/moveAfter
+    move_after: Option<String>,
+    /// The Agent for whom the server signs the Commit
+    /// https://atomicdata.dev/properties/onBehalfOf
+    on_behalf_of: Option<String>,
     // pub signature: String,
     /// The previous Commit that was applied to the target resource (the subject) of this Commit. You should be able to follow these from Commit to Commit to establish an audit trail.
     /// https://atomicdata.dev/properties/previousCommit
@@ -549,6 +880,9 @@ impl CommitBuilder {
             set: HashMap::new(),
             remove: HashSet::new(),
             destroy: false,
+            move_before: None,
+            move_after: None,
+            on_behalf_of: None,
             previous_commit: None,
         }
     }
@@ -608,6 +942,24 @@ impl CommitBuilder {
     pub fn destroy(&mut self, destroy: bool) {
         self.destroy = destroy
     }
+
+    /// Moves the resource right before one of its siblings, see [crate::order].
+    pub fn move_before(&mut self, sibling: String) {
+        self.move_after = None;
+        self.move_before = Some(sibling);
+    }
+
+    /// Moves the resource right after one of its siblings, see [crate::order].
+    pub fn move_after(&mut self, sibling: String) {
+        self.move_before = None;
+        self.move_after = Some(sibling);
+    }
+
+    /// Makes the Commit a change by this Agent, while it is signed by the server.
+    /// Only Commits signed by the default Agent of the server can do this.
+    pub fn on_behalf_of(&mut self, agent: String) {
+        self.on_behalf_of = Some(agent);
+    }
 }
 
 /// Signs a CommitBuilder at a specific unix timestamp.
@@ -628,6 +980,9 @@ fn sign_at(
         previous_commit: commitbuilder.previous_commit,
         signature: None,
         push: Some(commitbuilder.push),
+        move_before: commitbuilder.move_before,
+        move_after: commitbuilder.move_after,
+        on_behalf_of: commitbuilder.on_behalf_of,
         url: None,
     };
     let stringified = commit
@@ -728,6 +1083,166 @@ mod test {
         );
     }
 
+    #[test]
+    fn locked_props_need_owner() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let owner = Agent::new(None, &store).unwrap();
+        store.add_resource(&owner.to_resource().unwrap()).unwrap();
+        let editor = Agent::new(None, &store).unwrap();
+        store.add_resource(&editor.to_resource().unwrap()).unwrap();
+        let mut drive = Resource::new("https://localhost/drive".into());
+        drive.set_class(urls::DRIVE);
+        drive.set_propval_unsafe(urls::WRITE.into(), vec![owner.subject.clone()].into());
+        store.add_resource(&drive).unwrap();
+        let subject = "https://localhost/drive/invoice";
+        let mut invoice = Resource::new(subject.into());
+        invoice.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(drive.get_subject().into()),
+        );
+        invoice.set_propval_unsafe(urls::WRITE.into(), vec![editor.subject.clone()].into());
+        invoice.set_propval_unsafe(urls::LOCKED_PROPS.into(), vec![urls::DESCRIPTION].into());
+        store.add_resource(&invoice).unwrap();
+
+        let opts = CommitOpts {
+            validate_rights: true,
+            validate_previous_commit: false,
+            ..OPTS.clone()
+        };
+        let commit = |agent: &Agent, property: &str, value: Value| {
+            let mut builder = CommitBuilder::new(subject.into());
+            builder.set(property.into(), value);
+            let resource = store.get_resource(subject).unwrap();
+            builder
+                .sign(agent, &store, &resource)
+                .unwrap()
+                .apply_opts(&store, &opts)
+        };
+        commit(&editor, urls::NAME, Value::String("Invoice".into())).unwrap();
+        let err = commit(
+            &editor,
+            urls::DESCRIPTION,
+            Value::Markdown("Total: 0".into()),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            crate::errors::AtomicErrorType::UnauthorizedError
+        ));
+        // Otherwise editors could unlock the Property
+        commit(&editor, urls::LOCKED_PROPS, Vec::<String>::new().into()).unwrap_err();
+        commit(
+            &owner,
+            urls::DESCRIPTION,
+            Value::Markdown("Total: 100".into()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn moving_needs_rights_for_new_parent() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let editor = Agent::new(None, &store).unwrap();
+        store.add_resource(&editor.to_resource().unwrap()).unwrap();
+        let folder = |subject: &str, writers: Vec<String>| {
+            let mut folder = Resource::new(subject.into());
+            folder.set_class(urls::DRIVE);
+            folder.set_propval_unsafe(urls::WRITE.into(), writers.into());
+            store.add_resource(&folder).unwrap();
+        };
+        folder("https://localhost/shared", vec![editor.subject.clone()]);
+        folder("https://localhost/private", vec![]);
+        let subject = "https://localhost/shared/note";
+        let mut note = Resource::new(subject.into());
+        note.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl("https://localhost/shared".into()),
+        );
+        // Write rights in the resource itself are not enough to move it
+        note.set_propval_unsafe(urls::WRITE.into(), vec![editor.subject.clone()].into());
+        store.add_resource(&note).unwrap();
+
+        let opts = CommitOpts {
+            validate_rights: true,
+            validate_previous_commit: false,
+            ..OPTS.clone()
+        };
+        let move_to = |parent: &str, on_behalf_of: Option<&str>| {
+            let mut builder = CommitBuilder::new(subject.into());
+            builder.set(urls::PARENT.into(), Value::AtomicUrl(parent.into()));
+            if let Some(agent) = on_behalf_of {
+                builder.on_behalf_of(agent.into());
+            }
+            let resource = store.get_resource(subject).unwrap();
+            builder
+                .sign(&editor, &store, &resource)
+                .unwrap()
+                .apply_opts(&store, &opts)
+        };
+        move_to("https://localhost/private", None).unwrap_err();
+        // Only the server can sign on behalf of others
+        move_to("https://localhost/shared", Some(&editor.subject)).unwrap_err();
+        let mut private = store.get_resource("https://localhost/private").unwrap();
+        private.set_propval_unsafe(urls::APPEND.into(), vec![editor.subject.clone()].into());
+        store.add_resource(&private).unwrap();
+        move_to("https://localhost/private", None).unwrap();
+    }
+
+    #[test]
+    fn rotating_keys_keeps_old_commits_verifiable() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let old_agent = Agent::new(None, &store).unwrap();
+        store
+            .add_resource(&old_agent.to_resource().unwrap())
+            .unwrap();
+        let opts = CommitOpts {
+            validate_rights: true,
+            validate_previous_commit: false,
+            ..OPTS.clone()
+        };
+        let subject = &old_agent.subject;
+        let commit = |agent: &Agent, property: &str, value: Value| {
+            let mut builder = CommitBuilder::new(subject.into());
+            builder.set(property.into(), value);
+            let resource = store.get_resource(subject).unwrap();
+            builder
+                .sign(agent, &store, &resource)
+                .unwrap()
+                .apply_opts(&store, &opts)
+        };
+        let named = commit(&old_agent, urls::NAME, Value::String("Old".into())).unwrap();
+
+        let mut new_agent = Agent::new(None, &store).unwrap();
+        new_agent.subject = subject.clone();
+        commit(
+            &old_agent,
+            urls::PUBLIC_KEY,
+            Value::String(new_agent.public_key.clone()),
+        )
+        .unwrap();
+        let agent_resource = store.get_resource(subject).unwrap();
+        assert_eq!(
+            agent_resource.get(urls::PUBLIC_KEY).unwrap().to_string(),
+            new_agent.public_key
+        );
+        // Commits signed with the old key can still be verified
+        named.commit_struct.verify_signature(&store).unwrap();
+
+        commit(&old_agent, urls::NAME, Value::String("Stolen".into())).unwrap_err();
+        commit(&new_agent, urls::NAME, Value::String("New".into())).unwrap();
+        // The history can't be rewritten to make an old key valid again
+        commit(&new_agent, urls::KEY_HISTORY, Vec::<String>::new().into()).unwrap_err();
+        commit(
+            &new_agent,
+            urls::PUBLIC_KEY,
+            Value::String(old_agent.public_key.clone()),
+        )
+        .unwrap_err();
+    }
+
     #[test]
     fn serialize_commit() {
         let store = crate::Store::init().unwrap();
@@ -745,6 +1260,9 @@ mod test {
             signer: String::from("https://localhost/author"),
             set: Some(set),
             push: None,
+            move_before: None,
+            move_after: None,
+            on_behalf_of: None,
             remove: Some(remove),
             previous_commit: None,
             destroy: Some(destroy),
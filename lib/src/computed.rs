@@ -0,0 +1,483 @@
+//! Computed Properties have an `expression` that derives their value from other Properties of the resource,
+//! e.g. `first-name + ' ' + last-name` or `sum(line-items.amount)`.
+//! The values are calculated when the resource is requested using `get_resource_extended`, so they are never stored (or indexed).
+//!
+//! The expression language is small on purpose, and can't run arbitrary code:
+//! - Numbers (`1.5`) and strings (`'text'` or `"text"`)
+//! - `+`, `-`, `*`, `/` and parentheses. `+` adds numbers, and concatenates everything else.
+//!   Use spaces around `-`, since shortnames can contain dashes.
+//! - Shortnames of Properties of the resource. A `.` follows a reference to another resource, e.g. `author.name`.
+//!   If the reference is a ResourceArray, this gives a list of values.
+//! - `sum(list)` and `count(list)`
+//!
+//! Resources that are referenced must be readable by the Agent that requests the resource.
+
+use crate::{errors::AtomicResult, schema::Class, urls, Resource, Storelike, Value};
+
+/// Limits the amount of resources that are fetched for a single expression.
+const MAX_FETCHES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Name(String),
+    Op(char),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    Path(Vec<String>),
+    Call(String, Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+/// The result of evaluating (a part of) an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Computed {
+    Number(f64),
+    Text(String),
+    List(Vec<Computed>),
+}
+
+impl std::fmt::Display for Computed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Computed::Number(n) => write!(f, "{}", n),
+            Computed::Text(t) => write!(f, "{}", t),
+            Computed::List(items) => {
+                let items: Vec<String> = items.iter().map(|i| i.to_string()).collect();
+                write!(f, "{}", items.join(", "))
+            }
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> AtomicResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' | '(' | ')' | '.' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(other) => text.push(other),
+                        None => return Err(format!("Unclosed string in '{}'", expression).into()),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_ascii_digit() && d != '.' {
+                        break;
+                    }
+                    number.push(d);
+                    chars.next();
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("Invalid number '{}' in '{}'", number, expression))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_alphanumeric() && d != '_' && d != '-' {
+                        break;
+                    }
+                    name.push(d);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            other => {
+                return Err(format!("Unexpected character '{}' in '{}'", other, expression).into())
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, op: char) -> AtomicResult<()> {
+        match self.next() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            other => Err(format!("Expected '{}', found {:?}", op, other).into()),
+        }
+    }
+
+    fn expression(&mut self) -> AtomicResult<Expr> {
+        let mut left = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.next();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> AtomicResult<Expr> {
+        let mut left = self.factor()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.next();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> AtomicResult<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Text(t)) => Ok(Expr::Text(t)),
+            Some(Token::Op('-')) => Ok(Expr::Negate(Box::new(self.factor()?))),
+            Some(Token::Op('(')) => {
+                let inner = self.expression()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(Token::Name(name)) => {
+                if self.peek() == Some(&Token::Op('(')) {
+                    self.next();
+                    let argument = self.expression()?;
+                    self.expect(')')?;
+                    return Ok(Expr::Call(name, Box::new(argument)));
+                }
+                let mut path = vec![name];
+                while self.peek() == Some(&Token::Op('.')) {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Name(name)) => path.push(name),
+                        other => {
+                            return Err(format!("Expected a shortname, found {:?}", other).into())
+                        }
+                    }
+                }
+                Ok(Expr::Path(path))
+            }
+            other => Err(format!("Unexpected {:?}", other).into()),
+        }
+    }
+}
+
+fn parse(expression: &str) -> AtomicResult<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+    };
+    let parsed = parser.expression()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("Unexpected {:?} in '{}'", token, expression).into());
+    }
+    Ok(parsed)
+}
+
+struct Context<'a, S: Storelike> {
+    store: &'a S,
+    for_agent: Option<&'a str>,
+    fetches: usize,
+}
+
+impl<S: Storelike> Context<'_, S> {
+    fn fetch(&mut self, subject: &str) -> AtomicResult<Resource> {
+        self.fetches += 1;
+        if self.fetches > MAX_FETCHES {
+            return Err(format!("Expressions can use at most {} resources", MAX_FETCHES).into());
+        }
+        let resource = self.store.get_resource(subject)?;
+        if let Some(agent) = self.for_agent {
+            crate::hierarchy::check_read(self.store, &resource, agent)?;
+        }
+        Ok(resource)
+    }
+
+    /// Finds the value of the Property with this shortname in the resource.
+    fn get_by_shortname(&self, resource: &Resource, shortname: &str) -> Option<Value> {
+        resource.get_propvals().iter().find_map(|(prop, value)| {
+            self.store
+                .get_property(prop)
+                .ok()
+                .filter(|p| p.shortname == shortname)
+                .map(|_| value.clone())
+        })
+    }
+
+    fn follow(&mut self, resource: &Resource, path: &[String]) -> AtomicResult<Computed> {
+        let Some(value) = self.get_by_shortname(resource, &path[0]) else {
+            return Err(format!("{} has no {}", resource.get_subject(), path[0]).into());
+        };
+        if path.len() == 1 {
+            return Ok(to_computed(&value));
+        }
+        match value {
+            Value::ResourceArray(_) => {
+                let mut items = Vec::new();
+                for subject in value.to_subjects(None)? {
+                    let item = self.fetch(&subject)?;
+                    // Items without the property are skipped, so `sum` still works
+                    if let Ok(found) = self.follow(&item, &path[1..]) {
+                        items.push(found);
+                    }
+                }
+                Ok(Computed::List(items))
+            }
+            Value::AtomicUrl(subject) => {
+                let item = self.fetch(&subject)?;
+                self.follow(&item, &path[1..])
+            }
+            other => Err(format!("{} is not a reference to another resource", other).into()),
+        }
+    }
+
+    fn evaluate(&mut self, resource: &Resource, expr: &Expr) -> AtomicResult<Computed> {
+        Ok(match expr {
+            Expr::Number(n) => Computed::Number(*n),
+            Expr::Text(t) => Computed::Text(t.clone()),
+            Expr::Path(path) => self.follow(resource, path)?,
+            Expr::Negate(inner) => Computed::Number(-to_number(&self.evaluate(resource, inner)?)?),
+            Expr::Call(name, argument) => {
+                let items = match self.evaluate(resource, argument)? {
+                    Computed::List(items) => items,
+                    single => vec![single],
+                };
+                match name.as_str() {
+                    "sum" => {
+                        Computed::Number(items.iter().map(to_number).sum::<AtomicResult<f64>>()?)
+                    }
+                    "count" => Computed::Number(items.len() as f64),
+                    other => return Err(format!("Unknown function '{}'", other).into()),
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.evaluate(resource, left)?;
+                let right = self.evaluate(resource, right)?;
+                match (op, &left, &right) {
+                    ('+', Computed::Number(a), Computed::Number(b)) => Computed::Number(a + b),
+                    ('+', _, _) => Computed::Text(format!("{}{}", left, right)),
+                    ('-', _, _) => Computed::Number(to_number(&left)? - to_number(&right)?),
+                    ('*', _, _) => Computed::Number(to_number(&left)? * to_number(&right)?),
+                    (_, _, _) => {
+                        let divisor = to_number(&right)?;
+                        if divisor == 0.0 {
+                            return Err("Division by zero".into());
+                        }
+                        Computed::Number(to_number(&left)? / divisor)
+                    }
+                }
+            }
+        })
+    }
+}
+
+fn to_computed(value: &Value) -> Computed {
+    match value {
+        Value::Integer(i) | Value::Timestamp(i) => Computed::Number(*i as f64),
+        Value::Float(f) => Computed::Number(*f),
+        Value::Decimal(d) => d
+            .to_string()
+            .parse()
+            .map(Computed::Number)
+            .unwrap_or_else(|_| Computed::Text(d.to_string())),
+        Value::ResourceArray(_) => Computed::List(
+            value
+                .to_subjects(None)
+                .unwrap_or_default()
+                .into_iter()
+                .map(Computed::Text)
+                .collect(),
+        ),
+        other => Computed::Text(other.to_string()),
+    }
+}
+
+fn to_number(computed: &Computed) -> AtomicResult<f64> {
+    match computed {
+        Computed::Number(n) => Ok(*n),
+        other => Err(format!("'{}' is not a number", other).into()),
+    }
+}
+
+/// Evaluates the expression for the resource. See the [module docs](self) for the syntax.
+pub fn evaluate(
+    store: &impl Storelike,
+    resource: &Resource,
+    expression: &str,
+    for_agent: Option<&str>,
+) -> AtomicResult<Computed> {
+    let parsed = parse(expression)?;
+    let mut context = Context {
+        store,
+        for_agent,
+        fetches: 0,
+    };
+    context.evaluate(resource, &parsed)
+}
+
+/// Sets the values of the computed Properties that the Classes of the resource require or recommend.
+/// Values that can't be computed (e.g. because a Property is missing) are left out.
+/// Returns whether the Classes have computed Properties.
+pub fn add_computed_values(
+    store: &impl Storelike,
+    resource: &mut Resource,
+    classes: &[Class],
+    skip_dynamic: bool,
+    for_agent: Option<&str>,
+) -> AtomicResult<bool> {
+    let mut has_computed = false;
+    for class in classes {
+        for prop in class.requires.iter().chain(class.recommends.iter()) {
+            let Ok(property) = store.get_property(prop) else {
+                continue;
+            };
+            let Some(expression) = &property.expression else {
+                continue;
+            };
+            has_computed = true;
+            if skip_dynamic {
+                continue;
+            }
+            let value = evaluate(store, resource, expression, for_agent)
+                .and_then(|computed| Value::new(&computed.to_string(), &property.data_type));
+            match value {
+                Ok(value) => resource.set_propval_unsafe(prop.clone(), value),
+                Err(e) => tracing::debug!(
+                    "Could not compute {} for {}: {}",
+                    prop,
+                    resource.get_subject(),
+                    e
+                ),
+            }
+        }
+    }
+    Ok(has_computed)
+}
+
+/// Checks the syntax of an expression, without evaluating it.
+pub fn check_expression(expression: &str) -> AtomicResult<()> {
+    parse(expression).map(|_| ()).map_err(|e| {
+        format!(
+            "Invalid {} '{}': {}",
+            urls::EXPRESSION,
+            expression,
+            e.message
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{datatype::DataType, test_utils::init_store};
+
+    fn add_property(store: &impl Storelike, shortname: &str, datatype: DataType) -> String {
+        let subject = format!("https://localhost/properties/{}", shortname);
+        let mut property = Resource::new(subject.clone());
+        property.set_class(urls::PROPERTY);
+        property.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug(shortname.into()));
+        property.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A".into()));
+        property.set_propval_unsafe(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(datatype.to_string()),
+        );
+        store.add_resource(&property).unwrap();
+        subject
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let store = init_store();
+        let first = add_property(&store, "first-name", DataType::String);
+        let last = add_property(&store, "last-name", DataType::String);
+        let amount = add_property(&store, "amount", DataType::Integer);
+        let items = add_property(&store, "items", DataType::ResourceArray);
+        let mut subjects = Vec::new();
+        for (i, value) in [3, 4].iter().enumerate() {
+            let mut item = Resource::new(format!("https://localhost/items/{}", i));
+            item.set_propval_unsafe(amount.clone(), Value::Integer(*value));
+            store.add_resource(&item).unwrap();
+            subjects.push(item.get_subject().to_string());
+        }
+        let mut order = Resource::new("https://localhost/order".into());
+        order.set_propval_unsafe(first, Value::String("Ada".into()));
+        order.set_propval_unsafe(last, Value::String("Lovelace".into()));
+        order.set_propval_unsafe(items, subjects.into());
+
+        let eval = |expression| evaluate(&store, &order, expression, None);
+        assert_eq!(
+            eval("first-name + ' ' + last-name").unwrap(),
+            Computed::Text("Ada Lovelace".into())
+        );
+        assert_eq!(eval("sum(items.amount)").unwrap(), Computed::Number(7.0));
+        assert_eq!(
+            eval("count(items) * (2 + 1)").unwrap(),
+            Computed::Number(6.0)
+        );
+        assert_eq!(
+            eval("-sum(items.amount) / 2").unwrap(),
+            Computed::Number(-3.5)
+        );
+        eval("missing + 1").unwrap_err();
+        eval("sum(first-name)").unwrap_err();
+        eval("1 / 0").unwrap_err();
+        check_expression("sum(items.amount").unwrap_err();
+        check_expression("'open").unwrap_err();
+    }
+
+    #[test]
+    fn adds_computed_values() {
+        let store = init_store();
+        let first = add_property(&store, "first-name", DataType::String);
+        let full = add_property(&store, "full-name", DataType::String);
+        let mut property = store.get_resource(&full).unwrap();
+        property.set_propval_unsafe(
+            urls::EXPRESSION.into(),
+            Value::String("'Dr. ' + first-name".into()),
+        );
+        store.add_resource(&property).unwrap();
+        let class = Class {
+            requires: vec![first.clone()],
+            recommends: vec![full.clone()],
+            extends: Vec::new(),
+            shortname: "person".into(),
+            description: "A person".into(),
+            subject: "https://localhost/classes/Person".into(),
+        };
+        let mut person = Resource::new("https://localhost/person".into());
+        person.set_propval_unsafe(first, Value::String("Who".into()));
+
+        let classes = [class];
+        assert!(add_computed_values(&store, &mut person, &classes, true, None).unwrap());
+        person.get(&full).unwrap_err();
+        add_computed_values(&store, &mut person, &classes, false, None).unwrap();
+        assert_eq!(person.get(&full).unwrap().to_string(), "Dr. Who");
+    }
+}
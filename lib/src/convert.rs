@@ -0,0 +1,252 @@
+//! Converts Turtle and CSV to JSON-AD, so they can be imported.
+//! Used by the importer plugin, and by clients that have no database (like the CLI without the `db` feature).
+
+use crate::{datatype::DataType, errors::AtomicResult, urls, Storelike};
+
+/// The formats that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    JsonAd,
+    Turtle,
+    Csv,
+}
+
+impl ImportFormat {
+    pub fn from_name(name: &str) -> AtomicResult<ImportFormat> {
+        match name {
+            "json-ad" | "jsonad" | "json" => Ok(ImportFormat::JsonAd),
+            "turtle" | "ttl" => Ok(ImportFormat::Turtle),
+            "csv" => Ok(ImportFormat::Csv),
+            other => Err(format!(
+                "Unknown import format '{}'. Use `json-ad`, `turtle` or `csv`.",
+                other
+            )
+            .into()),
+        }
+    }
+
+    /// Uses the extension of the URL, and defaults to JSON-AD.
+    pub fn from_url(url: &str) -> ImportFormat {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        if path.ends_with(".ttl") {
+            ImportFormat::Turtle
+        } else if path.ends_with(".csv") {
+            ImportFormat::Csv
+        } else {
+            ImportFormat::JsonAd
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportFormat::JsonAd => "json-ad",
+            ImportFormat::Turtle => "turtle",
+            ImportFormat::Csv => "csv",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ImportFormat::JsonAd => crate::parse::JSON_AD_MIME,
+            ImportFormat::Turtle => "text/turtle",
+            ImportFormat::Csv => "text/csv",
+        }
+    }
+}
+
+/// Converts the data to a JSON-AD array, so it can be imported.
+pub fn to_json_ad(
+    data: &str,
+    format: ImportFormat,
+    store: &impl Storelike,
+) -> AtomicResult<String> {
+    match format {
+        ImportFormat::JsonAd => Ok(data.to_string()),
+        ImportFormat::Turtle => turtle_to_json_ad(data, store),
+        ImportFormat::Csv => csv_to_json_ad(data, store),
+    }
+}
+
+/// Converts text values to the JSON value that matches the datatype of the Property.
+/// Properties that can't be found are kept as strings, so the importer can report them.
+fn to_json_value(store: &impl Storelike, property: &str, values: Vec<String>) -> serde_json::Value {
+    use serde_json::Value as Json;
+    let datatype = match store.get_property(property) {
+        Ok(prop) => prop.data_type,
+        Err(_) => DataType::String,
+    };
+    let first = values.first().cloned().unwrap_or_default();
+    match datatype {
+        DataType::ResourceArray => Json::Array(values.into_iter().map(Json::String).collect()),
+        DataType::Integer | DataType::Timestamp => first
+            .parse::<i64>()
+            .map(Json::from)
+            .unwrap_or(Json::String(first)),
+        DataType::Float => first
+            .parse::<f64>()
+            .map(Json::from)
+            .unwrap_or(Json::String(first)),
+        DataType::Boolean => first
+            .parse::<bool>()
+            .map(Json::Bool)
+            .unwrap_or(Json::String(first)),
+        _ => Json::String(first),
+    }
+}
+
+/// Converts Turtle to JSON-AD. All predicates must be Atomic Properties, `rdf:type` is converted to `isA`.
+/// Blank nodes are not supported, and statements about them are skipped.
+#[cfg(feature = "rdf")]
+fn turtle_to_json_ad(data: &str, store: &impl Storelike) -> AtomicResult<String> {
+    use rio_api::{
+        model::{Literal, Subject, Term},
+        parser::TriplesParser,
+    };
+    use std::collections::HashMap;
+    const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+    // Keeps the order of the subjects, so lines in the report make sense.
+    let mut subjects: Vec<String> = Vec::new();
+    let mut statements: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+    rio_turtle::TurtleParser::new(data.as_bytes(), None)
+        .parse_all(&mut |triple| -> Result<(), rio_turtle::TurtleError> {
+            let Subject::NamedNode(subject) = triple.subject else {
+                return Ok(());
+            };
+            let object = match triple.object {
+                Term::NamedNode(node) => node.iri.to_string(),
+                Term::Literal(Literal::Simple { value })
+                | Term::Literal(Literal::LanguageTaggedString { value, .. })
+                | Term::Literal(Literal::Typed { value, .. }) => value.to_string(),
+                _ => return Ok(()),
+            };
+            let predicate = match triple.predicate.iri {
+                RDF_TYPE => urls::IS_A.to_string(),
+                other => other.to_string(),
+            };
+            if !statements.contains_key(subject.iri) {
+                subjects.push(subject.iri.to_string());
+            }
+            statements
+                .entry(subject.iri.to_string())
+                .or_default()
+                .entry(predicate)
+                .or_default()
+                .push(object);
+            Ok(())
+        })
+        .map_err(|e| format!("Invalid Turtle: {}", e))?;
+
+    let mut items = Vec::new();
+    for subject in subjects {
+        let mut object = serde_json::Map::new();
+        object.insert("@id".into(), serde_json::Value::String(subject.clone()));
+        for (predicate, values) in statements.remove(&subject).unwrap_or_default() {
+            let value = to_json_value(store, &predicate, values);
+            object.insert(predicate, value);
+        }
+        items.push(serde_json::Value::Object(object));
+    }
+    Ok(serde_json::to_string_pretty(&items)?)
+}
+
+#[cfg(not(feature = "rdf"))]
+fn turtle_to_json_ad(_data: &str, _store: &impl Storelike) -> AtomicResult<String> {
+    Err("Importing Turtle requires the `rdf` feature.".into())
+}
+
+/// Converts CSV to JSON-AD. The first row contains the Property URLs, or `@id` or `localId`.
+/// Every other row becomes a Resource. Values in ResourceArray columns are separated by spaces.
+fn csv_to_json_ad(data: &str, store: &impl Storelike) -> AtomicResult<String> {
+    let mut rows = crate::parse::parse_csv(data).into_iter();
+    let header = rows.next().ok_or("CSV is empty, it needs a header row.")?;
+    let columns: Vec<String> = header.iter().map(|cell| cell.trim().to_string()).collect();
+    for column in &columns {
+        if column != "@id" && column != "localId" && !crate::mapping::is_url(column) {
+            return Err(format!(
+                "CSV column '{}' is not a Property URL, `@id` or `localId`.",
+                column
+            )
+            .into());
+        }
+    }
+
+    let mut items = Vec::new();
+    for row in rows {
+        let mut object = serde_json::Map::new();
+        for (column, cell) in columns.iter().zip(row) {
+            let cell = cell.trim();
+            if cell.is_empty() {
+                continue;
+            }
+            let (key, value) = match column.as_str() {
+                "@id" => ("@id".to_string(), serde_json::Value::String(cell.into())),
+                "localId" => (
+                    urls::LOCAL_ID.to_string(),
+                    serde_json::Value::String(cell.into()),
+                ),
+                property => {
+                    let is_array = store
+                        .get_property(property)
+                        .is_ok_and(|p| p.data_type == DataType::ResourceArray);
+                    let values = if is_array {
+                        cell.split_whitespace().map(String::from).collect()
+                    } else {
+                        vec![cell.to_string()]
+                    };
+                    (property.to_string(), to_json_value(store, property, values))
+                }
+            };
+            object.insert(key, value);
+        }
+        items.push(serde_json::Value::Object(object));
+    }
+    Ok(serde_json::to_string_pretty(&items)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_csv() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let csv = format!(
+            "localId,{},{}\nfirst,\"Hello, \"\"world\"\"\",{} {}\n\nsecond,,\n",
+            urls::DESCRIPTION,
+            urls::IS_A,
+            urls::CLASS,
+            urls::PROPERTY
+        );
+        let json = to_json_ad(&csv, ImportFormat::Csv, &store).unwrap();
+        let parsed: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0][urls::DESCRIPTION], "Hello, \"world\"");
+        assert_eq!(parsed[0][urls::IS_A][1], urls::PROPERTY);
+        assert_eq!(parsed[1][urls::LOCAL_ID], "second");
+
+        to_json_ad("name\nfoo", ImportFormat::Csv, &store).unwrap_err();
+        assert_eq!(
+            ImportFormat::from_url("https://example.com/data.ttl?x=1"),
+            ImportFormat::Turtle
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rdf")]
+    fn converts_turtle() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let turtle = format!(
+            "<https://example.com/thing> a <{}> ;\n  <{}> \"A thing\" .",
+            urls::CLASS,
+            urls::SHORTNAME
+        );
+        let json = to_json_ad(&turtle, ImportFormat::Turtle, &store).unwrap();
+        let parsed: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0]["@id"], "https://example.com/thing");
+        assert_eq!(parsed[0][urls::IS_A][0], urls::CLASS);
+        assert_eq!(parsed[0][urls::SHORTNAME], "A thing");
+    }
+}
@@ -9,9 +9,15 @@ pub enum DataType {
     /// Either a full Resource, a link to a resource (subject) or a Nested Anonymous Resource
     AtomicUrl,
     Boolean,
+    /// Binary data, serialized as base64. See [crate::values::MAX_BYTES]
+    Bytes,
     Date,
+    /// An arbitrary precision number, see [crate::decimal::Decimal]
+    Decimal,
     Integer,
     Float,
+    /// A latitude and longitude, see [crate::values::GeoPoint]
+    GeoPoint,
     Markdown,
     ResourceArray,
     Slug,
@@ -24,9 +30,12 @@ pub fn match_datatype(string: &str) -> DataType {
     match string {
         urls::ATOMIC_URL => DataType::AtomicUrl,
         urls::BOOLEAN => DataType::Boolean,
+        urls::BYTES => DataType::Bytes,
         urls::DATE => DataType::Date,
+        urls::DECIMAL => DataType::Decimal,
         urls::INTEGER => DataType::Integer,
         urls::FLOAT => DataType::Float,
+        urls::GEO_POINT => DataType::GeoPoint,
         urls::MARKDOWN => DataType::Markdown,
         urls::RESOURCE_ARRAY => DataType::ResourceArray,
         urls::SLUG => DataType::Slug,
@@ -43,9 +52,12 @@ impl std::str::FromStr for DataType {
         Ok(match s {
             urls::ATOMIC_URL => DataType::AtomicUrl,
             urls::BOOLEAN => DataType::Boolean,
+            urls::BYTES => DataType::Bytes,
             urls::DATE => DataType::Date,
+            urls::DECIMAL => DataType::Decimal,
             urls::INTEGER => DataType::Integer,
             urls::FLOAT => DataType::Float,
+            urls::GEO_POINT => DataType::GeoPoint,
             urls::MARKDOWN => DataType::Markdown,
             urls::RESOURCE_ARRAY => DataType::ResourceArray,
             urls::SLUG => DataType::Slug,
@@ -61,9 +73,12 @@ impl fmt::Display for DataType {
         match self {
             DataType::AtomicUrl => write!(f, "{}", urls::ATOMIC_URL),
             DataType::Boolean => write!(f, "{}", urls::BOOLEAN),
+            DataType::Bytes => write!(f, "{}", urls::BYTES),
             DataType::Date => write!(f, "{}", urls::DATE),
+            DataType::Decimal => write!(f, "{}", urls::DECIMAL),
             DataType::Integer => write!(f, "{}", urls::INTEGER),
             DataType::Float => write!(f, "{}", urls::FLOAT),
+            DataType::GeoPoint => write!(f, "{}", urls::GEO_POINT),
             DataType::Markdown => write!(f, "{}", urls::MARKDOWN),
             DataType::ResourceArray => write!(f, "{}", urls::RESOURCE_ARRAY),
             DataType::Slug => write!(f, "{}", urls::SLUG),
@@ -1,15 +1,22 @@
 //! Persistent, ACID compliant, threadsafe to-disk store.
 //! Powered by Sled - an embedded database.
 
+pub mod commit_log;
+mod geo_index;
 mod migrations;
+mod partial;
 mod prop_val_sub_index;
+mod query_cache;
 mod query_index;
+mod resource_cache;
+mod schema_registry;
 #[cfg(test)]
 pub mod test;
+pub mod usage;
 mod val_prop_sub_index;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 
@@ -21,28 +28,40 @@ use crate::{
     db::{query_index::NO_VALUE, val_prop_sub_index::find_in_val_prop_sub_index},
     endpoints::{default_endpoints, Endpoint, HandleGetContext},
     errors::{AtomicError, AtomicResult},
+    normalize::Normalizers,
+    plugins::quotas::Quota,
     resources::PropVals,
+    schema::{Class, Property},
     storelike::{Query, QueryResult, Storelike},
     values::SortableValue,
-    Atom, Resource,
+    Atom, Resource, Value,
 };
 
 use self::{
+    commit_log::{add_to_commit_log, build_commit_log, remove_from_commit_log},
+    geo_index::{add_atom_to_geo_index, query_geo, remove_atom_from_geo_index},
     migrations::migrate_maybe,
     prop_val_sub_index::{
         add_atom_to_prop_val_sub_index, find_in_prop_val_sub_index,
         remove_atom_from_prop_val_sub_index,
     },
+    query_cache::QueryCache,
     query_index::{
         check_if_atom_matches_watched_query_filters, query_indexed, update_indexed_member,
         IndexIterator, QueryFilter,
     },
+    resource_cache::ResourceCache,
+    schema_registry::SchemaRegistry,
+    usage::{add_to_usage, build_usage, remove_from_usage},
     val_prop_sub_index::{add_atom_to_reference_index, remove_atom_from_reference_index},
 };
 
 // A function called by the Store when a Commit is accepted
 type HandleCommit = Box<dyn Fn(&CommitResponse) + Send + Sync>;
 
+/// Bytes that sled uses for caching pages by default, the same as sled's own default.
+pub const DEFAULT_CACHE_CAPACITY: u64 = 1024 * 1024 * 1024;
+
 /// Inside the reference_index, each value is mapped to this type.
 /// The String on the left represents a Property URL, and the second one is the set of subjects.
 pub type PropSubjectMap = HashMap<String, HashSet<String>>;
@@ -74,12 +93,30 @@ pub struct Db {
     query_index: sled::Tree,
     /// A list of all the Collections currently being used. Is used to update `query_index`.
     watched_queries: sled::Tree,
+    /// All Commits, sorted by their creation date. See [commit_log].
+    commit_log: sled::Tree,
+    /// GeoPoints, sorted by property and latitude. See [geo_index].
+    geo_index: sled::Tree,
+    /// How much every Drive uses. See [usage].
+    usage: sled::Tree,
     /// The address where the db will be hosted, e.g. http://localhost/
     server_url: String,
     /// Endpoints are checked whenever a resource is requested. They calculate (some properties of) the resource and return it.
     endpoints: Vec<Endpoint>,
     /// Function called whenever a Commit is applied.
     on_commit: Option<Arc<HandleCommit>>,
+    /// Applies to Drives that do not have their own Quota. See [crate::plugins::quotas].
+    default_quota: Quota,
+    /// Applied to values that are set by Commits. See [crate::normalize].
+    normalizers: Normalizers,
+    /// Outcomes of rights checks for parents. Invalidated whenever a resource is added or removed.
+    rights_cache: crate::hierarchy::RightsCache,
+    /// Results of recent Queries. See [query_cache].
+    query_cache: QueryCache,
+    /// Recently used resources. See [resource_cache].
+    resource_cache: ResourceCache,
+    /// All Properties and Classes. See [schema_registry].
+    schema: SchemaRegistry,
 }
 
 impl Db {
@@ -87,12 +124,30 @@ impl Db {
     /// The server_url is the domain where the db will be hosted, e.g. http://localhost/
     /// It is used for distinguishing locally defined items from externally defined ones.
     pub fn init(path: &std::path::Path, server_url: String) -> AtomicResult<Db> {
-        let db = sled::open(path).map_err(|e|format!("Failed opening DB at this location: {:?} . Is another instance of Atomic Server running? {}", path, e))?;
+        Db::init_with_cache_capacity(path, server_url, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Like [Db::init], but limits the memory that sled uses for caching pages to `cache_capacity` bytes.
+    pub fn init_with_cache_capacity(
+        path: &std::path::Path,
+        server_url: String,
+        cache_capacity: u64,
+    ) -> AtomicResult<Db> {
+        let db = sled::Config::new()
+            .path(path)
+            .cache_capacity(cache_capacity)
+            .open()
+            .map_err(|e|format!("Failed opening DB at this location: {:?} . Is another instance of Atomic Server running? {}", path, e))?;
         let resources = db.open_tree("resources_v1").map_err(|e|format!("Failed building resources. Your DB might be corrupt. Go back to a previous version and export your data. {}", e))?;
         let reference_index = db.open_tree("reference_index_v1")?;
         let query_index = db.open_tree("members_index")?;
         let prop_val_sub_index = db.open_tree("prop_val_sub_index")?;
         let watched_queries = db.open_tree("watched_queries")?;
+        let commit_log_exists = db.tree_names().iter().any(|t| t.as_ref() == b"commit_log");
+        let commit_log = db.open_tree("commit_log")?;
+        let geo_index = db.open_tree("geo_index")?;
+        let usage_exists = db.tree_names().iter().any(|t| t.as_ref() == b"usage");
+        let usage = db.open_tree("usage")?;
         let store = Db {
             db,
             default_agent: Arc::new(Mutex::new(None)),
@@ -102,12 +157,28 @@ impl Db {
             prop_val_sub_index,
             server_url,
             watched_queries,
+            commit_log,
+            geo_index,
+            usage,
             endpoints: default_endpoints(),
             on_commit: None,
+            default_quota: Quota::default(),
+            normalizers: Normalizers::default(),
+            rights_cache: Default::default(),
+            query_cache: Default::default(),
+            resource_cache: Default::default(),
+            schema: Default::default(),
         };
         migrate_maybe(&store).map(|e| format!("Error during migration of database: {:?}", e))?;
         crate::populate::populate_base_models(&store)
             .map_err(|e| format!("Failed to populate base models. {}", e))?;
+        store.schema.build(&store)?;
+        if !commit_log_exists {
+            build_commit_log(&store)?;
+        }
+        if !usage_exists {
+            build_usage(&store)?;
+        }
         Ok(store)
     }
 
@@ -147,6 +218,8 @@ impl Db {
     fn set_propvals(&self, subject: &str, propvals: &PropVals) -> AtomicResult<()> {
         let resource_bin = bincode::serialize(propvals)?;
         self.resources.insert(subject.as_bytes(), resource_bin)?;
+        self.resource_cache.remove(subject);
+        self.schema.update(subject, propvals);
         Ok(())
     }
 
@@ -156,10 +229,95 @@ impl Db {
         self.on_commit = Some(Arc::new(on_commit));
     }
 
+    /// Sets the Quota for Drives that do not have their own Quota.
+    pub fn set_default_quota(&mut self, quota: Quota) {
+        self.default_quota = quota;
+    }
+
+    /// The size of the database files, in bytes.
+    pub fn size_on_disk(&self) -> AtomicResult<u64> {
+        self.db
+            .size_on_disk()
+            .map_err(|e| format!("Could not get the size of the database: {}", e).into())
+    }
+
+    /// The Quota for Drives that do not have their own Quota.
+    pub fn get_default_quota(&self) -> Quota {
+        self.default_quota
+    }
+
+    /// Performs the Query using the query index, and builds the index if the Query is new.
+    /// Skips the query cache, which [Storelike::query] uses.
+    #[instrument(skip(self))]
+    pub fn query_uncached(&self, q: &Query) -> AtomicResult<QueryResult> {
+        let q_filter: QueryFilter = q.into();
+        if let Ok(res) = query_indexed(self, q) {
+            if res.count > 0 || q_filter.is_watched(self) {
+                // Yay, we have a cache hit!
+                // We don't have to create the indexes, so we can return early.
+                return Ok(res);
+            }
+        }
+
+        // Maybe make this optional?
+        q_filter.watch(self)?;
+
+        info!(filter = ?q_filter, "Building query index");
+
+        let atoms: IndexIterator = match (&q.property, q.value.as_ref()) {
+            (Some(prop), val) => find_in_prop_val_sub_index(self, prop, val),
+            (None, None) => self.all_index_atoms(q.include_external),
+            (None, Some(val)) => find_in_val_prop_sub_index(self, val, None),
+        };
+
+        for a in atoms {
+            let atom = a?;
+            // Get the SortableValue either from the Atom or the Resource.
+            let sort_val: SortableValue = if let Some(sort) = &q_filter.sort_by {
+                if &atom.property == sort {
+                    atom.sort_value
+                } else {
+                    // Find the sort value in the store, which can be in a nested resource
+                    let resource = self.get_resource(&atom.subject).ok();
+                    match resource
+                        .as_ref()
+                        .and_then(|r| r.get_nested_values(sort).first().copied())
+                    {
+                        Some(val) => val.to_sortable_string(),
+                        // If we try sorting on a value that does not exist,
+                        // we'll use an empty string as the sortable value.
+                        None => NO_VALUE.to_string(),
+                    }
+                }
+            } else {
+                atom.sort_value
+            };
+
+            update_indexed_member(self, &q_filter, &atom.subject, &sort_val, false)?;
+        }
+
+        // Retry the same query!
+        query_indexed(self, q)
+    }
+
+    /// Sets the amount of recently used resources that are kept in memory. `0` disables the cache.
+    pub fn set_resource_cache_size(&mut self, size: usize) {
+        self.resource_cache.set_capacity(size);
+    }
+
+    /// Sets the normalizations that are applied to values that are set by Commits.
+    pub fn set_normalizers(&mut self, normalizers: Normalizers) {
+        self.normalizers = normalizers;
+    }
+
     /// Finds resource by Subject, return PropVals HashMap
     /// Deals with the binary API of Sled
     #[instrument(skip(self))]
     fn get_propvals(&self, subject: &str) -> AtomicResult<PropVals> {
+        if let Some(propvals) = self.resource_cache.get(subject) {
+            return Ok(propvals);
+        }
+        let generation = self.resource_cache.generation();
         let propval_maybe = self
             .resources
             .get(subject.as_bytes())
@@ -173,6 +331,7 @@ impl Db {
                         e
                     )
                 })?;
+                self.resource_cache.insert(subject, &propval, generation);
                 Ok(propval)
             }
             None => Err(AtomicError::not_found(format!(
@@ -182,12 +341,29 @@ impl Db {
         }
     }
 
+    /// The subjects of the resources that have the Property, found by scanning the value index.
+    pub fn subjects_with_property(&self, property: &str) -> AtomicResult<BTreeSet<String>> {
+        find_in_prop_val_sub_index(self, property, None)
+            .map(|atom| atom.map(|atom| atom.subject))
+            .collect()
+    }
+
+    /// The subjects of the instances of the Class, found by scanning the value index.
+    pub fn subjects_with_class(&self, class: &str) -> AtomicResult<BTreeSet<String>> {
+        let class = Value::AtomicUrl(class.into());
+        find_in_prop_val_sub_index(self, crate::urls::IS_A, Some(&class))
+            .map(|atom| atom.map(|atom| atom.subject))
+            .collect()
+    }
+
     /// Removes all values from the indexes.
     pub fn clear_index(&self) -> AtomicResult<()> {
         self.reference_index.clear()?;
         self.prop_val_sub_index.clear()?;
         self.query_index.clear()?;
+        self.query_cache.clear();
         self.watched_queries.clear()?;
+        self.geo_index.clear()?;
         Ok(())
     }
 
@@ -242,9 +418,14 @@ impl Storelike for Db {
 
     #[instrument(skip(self))]
     fn add_atom_to_index(&self, atom: &Atom, resource: &Resource) -> AtomicResult<()> {
+        add_atom_to_geo_index(atom, self)?;
         for index_atom in atom.to_indexable_atoms() {
-            add_atom_to_reference_index(&index_atom, self)?;
+            // Nested resources don't refer to their values, so they are only indexed by their path
+            if !index_atom.is_nested() {
+                add_atom_to_reference_index(&index_atom, self)?;
+            }
             add_atom_to_prop_val_sub_index(&index_atom, self)?;
+            self.query_cache.invalidate_atom(&index_atom, resource);
             // Also update the query index to keep collections performant
             check_if_atom_matches_watched_query_filters(self, &index_atom, atom, false, resource)
                 .map_err(|e| {
@@ -275,10 +456,12 @@ impl Storelike for Db {
         if check_required_props {
             resource.check_required_props(self)?;
         }
+        let existing =
+            existing.map(|pv| Resource::from_propvals(pv, resource.get_subject().into()));
         if update_index {
-            if let Some(pv) = existing {
+            if let Some(existing) = &existing {
                 let subject = resource.get_subject();
-                for (prop, val) in pv.iter() {
+                for (prop, val) in existing.get_propvals().iter() {
                     // Possible performance hit - these clones can be replaced by modifying remove_atom_from_index
                     let remove_atom = crate::Atom::new(subject.into(), prop.into(), val.clone());
                     self.remove_atom_from_index(&remove_atom, resource)
@@ -292,14 +475,23 @@ impl Storelike for Db {
                     .map_err(|e| format!("Failed to add atom to index {}. {}", a, e))?;
             }
         }
-        self.set_propvals(resource.get_subject(), resource.get_propvals())
+        add_to_commit_log(self, resource)?;
+        add_to_usage(self, existing.as_ref(), resource)?;
+        self.set_propvals(resource.get_subject(), resource.get_propvals())?;
+        self.rights_cache
+            .invalidate(existing.as_ref(), Some(resource));
+        Ok(())
     }
 
     #[instrument(skip(self))]
     fn remove_atom_from_index(&self, atom: &Atom, resource: &Resource) -> AtomicResult<()> {
+        remove_atom_from_geo_index(atom, self)?;
         for index_atom in atom.to_indexable_atoms() {
-            remove_atom_from_reference_index(&index_atom, self)?;
+            if !index_atom.is_nested() {
+                remove_atom_from_reference_index(&index_atom, self)?;
+            }
             remove_atom_from_prop_val_sub_index(&index_atom, self)?;
+            self.query_cache.invalidate_atom(&index_atom, resource);
 
             check_if_atom_matches_watched_query_filters(self, &index_atom, atom, true, resource)
                 .map_err(|e| format!("Checking atom went wrong: {}", e))?;
@@ -337,6 +529,55 @@ impl Storelike for Db {
         }
     }
 
+    /// Resolves the Class from the [schema_registry], or reads it from the store and adds it to the registry.
+    fn get_class(&self, subject: &str) -> AtomicResult<Class> {
+        if let Some(class) = self.schema.get_class(subject) {
+            return Ok(class);
+        }
+        let class_generation = self.schema.class_generation();
+        let class = schema_registry::resolve_class(self, subject)?;
+        self.schema.insert_class(&class, class_generation);
+        Ok(class)
+    }
+
+    /// Resolves the Property from the [schema_registry], or reads it from the store.
+    fn get_property(&self, subject: &str) -> AtomicResult<Property> {
+        if let Some(property) = self.schema.get_property(subject) {
+            return Ok(property);
+        }
+        let prop = self
+            .get_resource(subject)
+            .map_err(|e| format!("Failed getting property {}. {}", subject, e))?;
+        Property::from_resource(prop)
+    }
+
+    /// Only deserializes the values of the requested Properties, see [partial].
+    #[instrument(skip(self))]
+    fn get_resource_partial(&self, subject: &str, properties: &[&str]) -> AtomicResult<Resource> {
+        if let Some(propvals) = self.resource_cache.get(subject) {
+            let mut resource = Resource::from_propvals(propvals, subject.into());
+            resource.retain_propvals_unsafe(properties);
+            return Ok(resource);
+        }
+        let bin = self
+            .resources
+            .get(subject.as_bytes())
+            .map_err(|e| format!("Can't open {} from store: {}", subject, e))?;
+        match bin {
+            Some(bin) => {
+                let propvals = partial::deserialize_partial(&bin, properties)
+                    .map_err(|e| format!("{} {}", corrupt_db_message(subject), e))?;
+                Ok(Resource::from_propvals(propvals, subject.into()))
+            }
+            None => {
+                let error = AtomicError::not_found(format!("Resource {} not found", subject));
+                let mut resource = self.handle_not_found(subject, error)?;
+                resource.retain_propvals_unsafe(properties);
+                Ok(resource)
+            }
+        }
+    }
+
     #[instrument(skip(self))]
     fn get_resource_extended(
         &self,
@@ -394,10 +635,17 @@ impl Storelike for Db {
             let _explanation = crate::hierarchy::check_read(self, &resource, agent)?;
         }
 
+        let classes = resource.get_classes(self)?;
         // Whether the resource has dynamic properties
-        let mut has_dynamic = false;
+        let mut has_dynamic = crate::computed::add_computed_values(
+            self,
+            &mut resource,
+            &classes,
+            skip_dynamic,
+            for_agent,
+        )?;
         // If a certain class needs to be extended, add it to this match statement
-        for class in resource.get_classes(self)? {
+        for class in classes {
             match class.subject.as_ref() {
                 crate::urls::COLLECTION => {
                     has_dynamic = true;
@@ -427,6 +675,13 @@ impl Storelike for Db {
                         resource = crate::hierarchy::add_children(self, &mut resource)?;
                     }
                 }
+                crate::urls::MESSAGE => {
+                    has_dynamic = true;
+                    if !skip_dynamic {
+                        resource =
+                            crate::plugins::chatroom::construct_message(self, &mut resource)?;
+                    }
+                }
                 crate::urls::CHATROOM => {
                     has_dynamic = true;
                     if !skip_dynamic {
@@ -458,60 +713,39 @@ impl Storelike for Db {
     }
 
     fn handle_commit(&self, commit_response: &CommitResponse) {
+        let rights_changed = crate::hierarchy::rights_changed(
+            commit_response.resource_old.as_ref(),
+            commit_response.resource_new.as_ref(),
+        );
+        self.query_cache
+            .invalidate_resource(&commit_response.commit_struct.subject, rights_changed);
         if let Some(fun) = &self.on_commit {
             fun(commit_response);
         }
     }
 
+    fn check_quota(
+        &self,
+        resource_old: Option<&Resource>,
+        resource_new: &Resource,
+    ) -> AtomicResult<()> {
+        crate::plugins::quotas::check_quota(self, resource_old, resource_new)
+    }
+
     /// Search the Store, returns the matching subjects.
     /// The second returned vector should be filled if query.include_resources is true.
     /// Tries `query_cache`, which you should implement yourself.
     #[instrument(skip(self))]
     fn query(&self, q: &Query) -> AtomicResult<QueryResult> {
-        let q_filter: QueryFilter = q.into();
-        if let Ok(res) = query_indexed(self, q) {
-            if res.count > 0 || q_filter.is_watched(self) {
-                // Yay, we have a cache hit!
-                // We don't have to create the indexes, so we can return early.
-                return Ok(res);
-            }
+        if let Some(geo) = &q.geo {
+            return query_geo(self, q, geo);
         }
-
-        // Maybe make this optional?
-        q_filter.watch(self)?;
-
-        info!(filter = ?q_filter, "Building query index");
-
-        let atoms: IndexIterator = match (&q.property, q.value.as_ref()) {
-            (Some(prop), val) => find_in_prop_val_sub_index(self, prop, val),
-            (None, None) => self.all_index_atoms(q.include_external),
-            (None, Some(val)) => find_in_val_prop_sub_index(self, val, None),
-        };
-
-        for a in atoms {
-            let atom = a?;
-            // Get the SortableValue either from the Atom or the Resource.
-            let sort_val: SortableValue = if let Some(sort) = &q_filter.sort_by {
-                if &atom.property == sort {
-                    atom.sort_value
-                } else {
-                    // Find the sort value in the store
-                    match self.get_value(&atom.subject, sort) {
-                        Ok(val) => val.to_sortable_string(),
-                        // If we try sorting on a value that does not exist,
-                        // we'll use an empty string as the sortable value.
-                        Err(_) => NO_VALUE.to_string(),
-                    }
-                }
-            } else {
-                atom.sort_value
-            };
-
-            update_indexed_member(self, &q_filter, &atom.subject, &sort_val, false)?;
+        if let Some(result) = self.query_cache.get(q) {
+            return Ok(result);
         }
-
-        // Retry the same query!
-        query_indexed(self, q)
+        let result = self.query_uncached(q)?;
+        self.query_cache.insert(q, &result);
+        Ok(result)
     }
 
     #[instrument(skip(self))]
@@ -585,6 +819,8 @@ impl Storelike for Db {
         crate::populate::create_drive(self)
             .map_err(|e| format!("Failed to create drive. {}", e))?;
         crate::populate::set_drive_rights(self, true)?;
+        crate::plugins::features::create_feature_settings(self, self.get_server_url())
+            .map_err(|e| format!("Failed to create feature settings. {}", e))?;
         crate::populate::populate_collections(self)
             .map_err(|e| format!("Failed to populate collections. {}", e))?;
         crate::populate::populate_endpoints(self)
@@ -604,7 +840,12 @@ impl Storelike for Db {
                 let remove_atom = crate::Atom::new(subject.into(), prop.clone(), val.clone());
                 self.remove_atom_from_index(&remove_atom, &resource)?;
             }
+            remove_from_commit_log(self, &resource)?;
+            remove_from_usage(self, &resource)?;
             let _found = self.resources.remove(subject.as_bytes())?;
+            self.resource_cache.remove(subject);
+            self.schema.remove(subject);
+            self.rights_cache.invalidate(Some(&resource), None);
         } else {
             return Err(format!(
                 "Resource {} could not be deleted, because it was not found in the store.",
@@ -617,6 +858,15 @@ impl Storelike for Db {
 
     fn set_default_agent(&self, agent: crate::agents::Agent) {
         self.default_agent.lock().unwrap().replace(agent);
+        self.rights_cache.clear();
+    }
+
+    fn normalizers(&self) -> Normalizers {
+        self.normalizers
+    }
+
+    fn rights_cache(&self) -> Option<&crate::hierarchy::RightsCache> {
+        Some(&self.rights_cache)
     }
 }
 
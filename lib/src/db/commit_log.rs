@@ -0,0 +1,132 @@
+//! Index of all Commits, sorted by {createdAt}-{Commit subject}.
+//! Makes it possible to browse the (often very large) list of Commits in a store, newest first,
+//! without having to sort them in a Collection. See [crate::plugins::commits].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::AtomicResult, urls, Db, Resource, Storelike};
+
+/// A single Commit in the commit log.
+/// Contains the fields that are needed for filtering, so the Commit itself does not need to be fetched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitLogEntry {
+    /// Subject of the Commit itself
+    pub commit: String,
+    /// Subject of the Resource that the Commit was applied to
+    pub target: String,
+    /// The Agent that made the change: the `onBehalfOf` of the Commit if the server signed it for someone, or else its signer
+    pub signer: String,
+    pub created_at: i64,
+}
+
+impl CommitLogEntry {
+    /// Returns None if the Resource is not a Commit.
+    pub fn from_resource(resource: &Resource) -> Option<CommitLogEntry> {
+        let is_commit = resource
+            .get(urls::IS_A)
+            .ok()?
+            .to_subjects(None)
+            .ok()?
+            .iter()
+            .any(|c| c == urls::COMMIT);
+        if !is_commit {
+            return None;
+        }
+        Some(CommitLogEntry {
+            commit: resource.get_subject().to_string(),
+            target: resource.get(urls::SUBJECT).ok()?.to_string(),
+            signer: resource
+                .get(urls::ON_BEHALF_OF)
+                .or_else(|_| resource.get(urls::SIGNER))
+                .ok()?
+                .to_string(),
+            created_at: resource.get(urls::CREATED_AT).ok()?.to_int().ok()?,
+        })
+    }
+}
+
+/// Constructs the key for the commit log.
+/// Timestamps are stored as big endian bytes, so they are sorted chronologically.
+pub fn commit_log_key(created_at: i64, commit_subject: &str) -> Vec<u8> {
+    [
+        &(created_at.max(0) as u64).to_be_bytes(),
+        commit_subject.as_bytes(),
+    ]
+    .concat()
+}
+
+/// Adds the Resource to the commit log, if it is a Commit.
+pub fn add_to_commit_log(store: &Db, resource: &Resource) -> AtomicResult<()> {
+    if let Some(entry) = CommitLogEntry::from_resource(resource) {
+        store.commit_log.insert(
+            commit_log_key(entry.created_at, &entry.commit),
+            bincode::serialize(&entry)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Removes the Resource from the commit log, if it is a Commit.
+pub fn remove_from_commit_log(store: &Db, resource: &Resource) -> AtomicResult<()> {
+    if let Some(entry) = CommitLogEntry::from_resource(resource) {
+        store
+            .commit_log
+            .remove(commit_log_key(entry.created_at, &entry.commit))?;
+    }
+    Ok(())
+}
+
+/// Adds all existing Commits to the commit log.
+/// Used when the commit log is created for an existing store.
+pub fn build_commit_log(store: &Db) -> AtomicResult<()> {
+    tracing::info!("Building commit log...");
+    for resource in store.all_resources(false) {
+        add_to_commit_log(store, &resource)?;
+    }
+    Ok(())
+}
+
+/// The amount of Commits in the store.
+pub fn commit_log_len(store: &Db) -> usize {
+    store.commit_log.len()
+}
+
+/// Iterates over the commit log, newest first.
+/// Only returns Commits created between `from` and `until` (both inclusive, in milliseconds).
+/// Starts after the Commit with key `before`, which is used for pagination.
+pub fn iter_commit_log(
+    store: &Db,
+    from: Option<i64>,
+    until: Option<i64>,
+    before: Option<Vec<u8>>,
+) -> impl Iterator<Item = AtomicResult<CommitLogEntry>> {
+    let start = commit_log_key(from.unwrap_or(0), "");
+    let mut end = match until {
+        // Every key that starts with `until` is included
+        Some(until) => commit_log_key(until.saturating_add(1), ""),
+        None => vec![0xff; 9],
+    };
+    if let Some(before) = before {
+        end = end.min(before);
+    }
+    store
+        .commit_log
+        .range(start..end)
+        .rev()
+        .map(|item| -> AtomicResult<CommitLogEntry> {
+            let (_key, value) = item?;
+            Ok(bincode::deserialize(&value)?)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys_are_sorted_by_time() {
+        let old = commit_log_key(1, "https://localhost/commits/b");
+        let new = commit_log_key(256, "https://localhost/commits/a");
+        assert!(old < new);
+    }
+}
@@ -0,0 +1,160 @@
+//! Index of GeoPoints, sorted by {Property}-{Latitude}-{Longitude}-{Subject}.
+//! Queries scan the band of latitudes that contains the area, and filter the results by longitude and distance.
+
+use tracing::instrument;
+
+use crate::{
+    errors::AtomicResult,
+    storelike::{GeoFilter, Query, QueryResult},
+    values::GeoPoint,
+    Atom, Db, Storelike, Value,
+};
+
+use super::query_index::SEPARATION_BIT;
+
+/// Coordinates are stored as fixed width, positive numbers, so they sort lexicographically.
+fn coordinate_key(degrees: f64, offset: f64) -> String {
+    format!("{:011.7}", degrees + offset)
+}
+
+fn key_from_atom(atom: &Atom, point: &GeoPoint) -> Vec<u8> {
+    [
+        atom.property.as_bytes(),
+        &[SEPARATION_BIT],
+        coordinate_key(point.latitude, 90.0).as_bytes(),
+        &[SEPARATION_BIT],
+        coordinate_key(point.longitude, 180.0).as_bytes(),
+        &[SEPARATION_BIT],
+        atom.subject.as_bytes(),
+    ]
+    .concat()
+}
+
+fn key_to_point(key: &[u8]) -> AtomicResult<(GeoPoint, String)> {
+    let mut parts = key.split(|b| b == &SEPARATION_BIT).skip(1);
+    let mut next = |name: &str| -> AtomicResult<String> {
+        let part = parts
+            .next()
+            .ok_or_else(|| format!("Invalid key for geo_index, missing {}", name))?;
+        Ok(String::from_utf8_lossy(part).into())
+    };
+    let latitude: f64 = next("latitude")?.parse()?;
+    let longitude: f64 = next("longitude")?.parse()?;
+    let subject = next("subject")?;
+    Ok((
+        GeoPoint {
+            latitude: latitude - 90.0,
+            longitude: longitude - 180.0,
+        },
+        subject,
+    ))
+}
+
+#[instrument(skip(store))]
+pub fn add_atom_to_geo_index(atom: &Atom, store: &Db) -> AtomicResult<()> {
+    if let Value::GeoPoint(point) = &atom.value {
+        store.geo_index.insert(key_from_atom(atom, point), b"")?;
+    }
+    Ok(())
+}
+
+#[instrument(skip(store))]
+pub fn remove_atom_from_geo_index(atom: &Atom, store: &Db) -> AtomicResult<()> {
+    if let Value::GeoPoint(point) = &atom.value {
+        store.geo_index.remove(key_from_atom(atom, point))?;
+    }
+    Ok(())
+}
+
+/// Finds the Resources with a GeoPoint in the area of the filter.
+/// Results within a radius are sorted by distance.
+#[instrument(skip(store))]
+pub fn query_geo(store: &Db, q: &Query, geo: &GeoFilter) -> AtomicResult<QueryResult> {
+    let property = q
+        .property
+        .as_ref()
+        .ok_or("Geo queries need a `property` that contains GeoPoints")?;
+    let prefix = [property.as_bytes(), &[SEPARATION_BIT]].concat();
+    let (south, north) = geo.latitude_range();
+    let start = [prefix.as_slice(), coordinate_key(south, 90.0).as_bytes()].concat();
+    let end = [
+        prefix.as_slice(),
+        coordinate_key(north, 90.0).as_bytes(),
+        &[SEPARATION_BIT, SEPARATION_BIT],
+    ]
+    .concat();
+
+    let mut hits: Vec<(f64, String)> = Vec::new();
+    for kv in store.geo_index.range(start..end) {
+        let (key, _value) = kv?;
+        let (point, subject) = key_to_point(&key)?;
+        if geo.contains(&point) {
+            hits.push((geo.distance(&point), subject));
+        }
+    }
+    hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let self_url = store
+        .get_self_url()
+        .ok_or("No self_url set, required for Queries")?;
+    let limit = q.limit.unwrap_or(usize::MAX);
+    let mut subjects = Vec::new();
+    let mut resources = Vec::new();
+    let mut count = 0;
+    for (_distance, subject) in hits {
+        if !q.include_external && !subject.starts_with(&self_url) {
+            continue;
+        }
+        if q.include_nested || q.for_agent.is_some() {
+            match store.get_resource_extended(&subject, true, q.for_agent.as_deref()) {
+                Ok(resource) => {
+                    count += 1;
+                    if count > q.offset && subjects.len() < limit {
+                        resources.push(resource);
+                        subjects.push(subject);
+                    }
+                }
+                Err(e) => match &e.error_type {
+                    crate::AtomicErrorType::NotFoundError => {}
+                    crate::AtomicErrorType::UnauthorizedError => {}
+                    _other => {
+                        return Err(
+                            format!("Error when getting resource in geo query: {}", e).into()
+                        )
+                    }
+                },
+            }
+        } else {
+            count += 1;
+            if count > q.offset && subjects.len() < limit {
+                subjects.push(subject);
+            }
+        }
+    }
+    Ok(QueryResult {
+        subjects,
+        resources,
+        count,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let atom = Atom::new(
+            "http://example.com/subj".into(),
+            "http://example.com/location".into(),
+            Value::GeoPoint(GeoPoint::new(-33.8688197, 151.2092955).unwrap()),
+        );
+        let Value::GeoPoint(point) = &atom.value else {
+            panic!()
+        };
+        let (parsed, subject) = key_to_point(&key_from_atom(&atom, point)).unwrap();
+        assert_eq!(subject, atom.subject);
+        assert!((parsed.latitude - point.latitude).abs() < 1e-6);
+        assert!((parsed.longitude - point.longitude).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,87 @@
+//! Reads a subset of the [PropVals] of a stored resource.
+//! Values of other Properties are skipped while deserializing, without copying their strings,
+//! so reading the name of a document doesn't allocate its (possibly huge) markdown body.
+
+use std::fmt;
+
+use bincode::Options;
+use serde::{
+    de::{DeserializeSeed, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    decimal::Decimal,
+    errors::AtomicResult,
+    resources::PropVals,
+    values::{GeoPoint, SubResource, UnsupportedValue},
+    Resource, Value,
+};
+
+/// Mirrors [Value], but borrows its strings from the serialized bytes.
+/// The variants must be kept in the same order as those of [Value], since bincode stores the variant index.
+#[allow(dead_code)]
+#[derive(Deserialize)]
+enum SkippedValue<'a> {
+    AtomicUrl(&'a str),
+    Date(&'a str),
+    Integer(i64),
+    Float(f64),
+    Markdown(&'a str),
+    ResourceArray(Vec<SubResource>),
+    Slug(&'a str),
+    String(&'a str),
+    Timestamp(i64),
+    NestedResource(SubResource),
+    Resource(Box<Resource>),
+    Boolean(bool),
+    Unsupported(UnsupportedValue),
+    GeoPoint(GeoPoint),
+    Decimal(Decimal),
+    Bytes(&'a [u8]),
+}
+
+/// Deserializes [PropVals], keeping only the values of these Properties.
+struct PartialPropVals<'p> {
+    properties: &'p [&'p str],
+}
+
+impl<'de, 'p> DeserializeSeed<'de> for PartialPropVals<'p> {
+    type Value = PropVals;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<PropVals, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'p> Visitor<'de> for PartialPropVals<'p> {
+    type Value = PropVals;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of Properties and Values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<PropVals, A::Error> {
+        let mut propvals = PropVals::with_capacity(self.properties.len());
+        while let Some(property) = map.next_key::<&'de str>()? {
+            if self.properties.contains(&property) {
+                propvals.insert(property.into(), map.next_value::<Value>()?);
+            } else {
+                map.next_value::<SkippedValue>()?;
+            }
+        }
+        Ok(propvals)
+    }
+}
+
+/// Deserializes the values of these Properties from [PropVals] that are serialized using [bincode].
+pub fn deserialize_partial(bytes: &[u8], properties: &[&str]) -> AtomicResult<PropVals> {
+    // The same options as `bincode::deserialize`
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    let mut deserializer = bincode::Deserializer::from_slice(bytes, options);
+    PartialPropVals { properties }
+        .deserialize(&mut deserializer)
+        .map_err(|e| format!("Deserialize propval error: {}", e).into())
+}
@@ -0,0 +1,110 @@
+//! Remembers the [QueryResult]s of recent Queries, so collections that are requested often (home pages, sidebars) don't scan the query index every time.
+//! Entries are removed when an indexed Atom changes the outcome of their Query (using the same check as the query index, see [should_update_property]),
+//! when a Commit edits one of their results, or when rights change for Queries that are performed for an Agent.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    atoms::IndexAtom,
+    storelike::{Query, QueryResult},
+    Resource,
+};
+
+use super::query_index::{should_update_property, QueryFilter};
+
+/// The least recently used Query is removed when there are more than this.
+pub const MAX_CACHED_QUERIES: usize = 1000;
+
+struct CachedQuery {
+    filter: QueryFilter,
+    for_agent: bool,
+    result: QueryResult,
+}
+
+#[derive(Default)]
+struct Entries {
+    queries: HashMap<String, CachedQuery>,
+    /// Keys of the queries, the least recently used first.
+    recent: VecDeque<String>,
+}
+
+impl Entries {
+    fn remove_where(&mut self, mut stale: impl FnMut(&CachedQuery) -> bool) {
+        self.queries.retain(|_key, cached| !stale(cached));
+        let queries = &self.queries;
+        self.recent.retain(|key| queries.contains_key(key));
+    }
+}
+
+/// An LRU cache of [QueryResult]s, keyed by the full [Query].
+/// Clones share the same cache.
+#[derive(Clone, Default)]
+pub struct QueryCache {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl QueryCache {
+    fn key(query: &Query) -> String {
+        format!("{:?}", query)
+    }
+
+    pub fn get(&self, query: &Query) -> Option<QueryResult> {
+        let key = Self::key(query);
+        let mut entries = self.entries.lock().unwrap();
+        let result = entries.queries.get(&key)?.result.clone();
+        if let Some(position) = entries.recent.iter().position(|k| k == &key) {
+            entries.recent.remove(position);
+        }
+        entries.recent.push_back(key);
+        Some(result)
+    }
+
+    pub fn insert(&self, query: &Query, result: &QueryResult) {
+        let key = Self::key(query);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.queries.contains_key(&key) {
+            return;
+        }
+        if entries.queries.len() >= MAX_CACHED_QUERIES {
+            if let Some(oldest) = entries.recent.pop_front() {
+                entries.queries.remove(&oldest);
+            }
+        }
+        entries.queries.insert(
+            key.clone(),
+            CachedQuery {
+                filter: query.into(),
+                for_agent: query.for_agent.is_some(),
+                result: result.clone(),
+            },
+        );
+        entries.recent.push_back(key);
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.queries.clear();
+        entries.recent.clear();
+    }
+
+    /// Removes the Queries whose results change when this Atom is added to or removed from the index.
+    pub fn invalidate_atom(&self, index_atom: &IndexAtom, resource: &Resource) {
+        self.entries.lock().unwrap().remove_where(|cached| {
+            // Queries without filters are not indexed, so any change can affect them
+            (cached.filter.property.is_none() && cached.filter.value.is_none())
+                || should_update_property(&cached.filter, index_atom, resource).is_some()
+        });
+    }
+
+    /// Removes the Queries that contain the resource, since it has been edited.
+    /// If the rights of the resource changed, removes all Queries that are performed for an Agent.
+    pub fn invalidate_resource(&self, subject: &str, rights_changed: bool) {
+        self.entries.lock().unwrap().remove_where(|cached| {
+            (rights_changed && cached.for_agent)
+                || cached.result.subjects.iter().any(|s| s == subject)
+        });
+    }
+}
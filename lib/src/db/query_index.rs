@@ -106,7 +106,7 @@ pub fn query_indexed(store: &Db, q: &Query) -> AtomicResult<QueryResult> {
     let limit = if let Some(limit) = q.limit {
         limit
     } else {
-        std::usize::MAX
+        usize::MAX
     };
 
     for (i, kv) in iter.enumerate() {
@@ -171,14 +171,17 @@ fn find_matching_propval<'a>(
     q_filter: &'a QueryFilter,
 ) -> Option<&'a String> {
     if let Some(property) = &q_filter.property {
-        if let Ok(matched_val) = resource.get(property) {
-            if let Some(filter_val) = &q_filter.value {
-                if matched_val.to_string() == filter_val.to_string() {
-                    return Some(property);
-                }
-            } else {
+        // The property can be a path to the values of nested resources
+        let matched_vals = resource.get_nested_values(property);
+        if let Some(filter_val) = &q_filter.value {
+            if matched_vals
+                .iter()
+                .any(|matched_val| matched_val.to_string() == filter_val.to_string())
+            {
                 return Some(property);
             }
+        } else if !matched_vals.is_empty() {
+            return Some(property);
         }
     } else if let Some(filter_val) = &q_filter.value {
         for (prop, val) in resource.get_propvals() {
@@ -208,11 +211,8 @@ pub fn should_update_property<'a>(
     // So here we not only make sure that the QueryFilter actually matches the resource,
     // But we also return which prop & val we matched on, so we can update the index with the correct value.
     // See https://github.com/atomicdata-dev/atomic-data-rust/issues/395
-    let matching_prop = match find_matching_propval(resource, q_filter) {
-        Some(a) => a,
-        // if the resource doesn't match the filter, we don't need to update the index
-        None => return None,
-    };
+    // If the resource doesn't match the filter, we don't need to update the index
+    let matching_prop = find_matching_propval(resource, q_filter)?;
 
     // Now we know that our new Resource is a member for this QueryFilter.
     // But we don't know whether this specific IndexAtom is relevant for the index of this QueryFilter.
@@ -289,9 +289,9 @@ pub fn check_if_atom_matches_watched_query_filters(
                 .map_err(|e| format!("Could not deserialize QueryFilter: {}", e))?;
 
             if let Some(prop) = should_update_property(&q_filter, index_atom, resource) {
-                let update_val = match resource.get(prop) {
-                    Ok(val) => val.to_sortable_string(),
-                    Err(_e) => NO_VALUE.to_string(),
+                let update_val = match resource.get_nested_values(prop).first() {
+                    Some(val) => val.to_sortable_string(),
+                    None => NO_VALUE.to_string(),
                 };
                 update_indexed_member(store, &q_filter, &atom.subject, &update_val, delete)?;
             }
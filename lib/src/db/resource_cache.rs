@@ -0,0 +1,128 @@
+//! Keeps the [PropVals] of recently used resources in memory, so resources that are read often (such as Properties and Classes) don't have to be deserialized every time.
+//! Entries are removed whenever the resource is written to or removed from the store.
+//! Readers fill the cache after reading from the store, so a write can happen in between.
+//! Every removal increases the generation, and fills that started in an older generation are ignored, so stale resources are never cached.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use crate::resources::PropVals;
+
+/// The amount of resources that are cached by default.
+pub const DEFAULT_RESOURCE_CACHE_SIZE: usize = 10_000;
+
+#[derive(Default)]
+struct Entries {
+    /// The PropVals of every subject, with the moment it was last used
+    resources: HashMap<String, (PropVals, u64)>,
+    /// Subjects by the moment they were last used, the least recently used first
+    recent: BTreeMap<u64, String>,
+    /// Increases every time a resource is used
+    clock: u64,
+    /// Increases every time a resource is removed, see [ResourceCache::generation]
+    generation: u64,
+    capacity: usize,
+}
+
+impl Entries {
+    fn touch(&mut self, subject: &str) -> Option<u64> {
+        self.clock += 1;
+        let clock = self.clock;
+        let (_, used) = self.resources.get_mut(subject)?;
+        let previous = std::mem::replace(used, clock);
+        self.recent.remove(&previous);
+        self.recent.insert(clock, subject.to_string());
+        Some(clock)
+    }
+}
+
+/// An LRU cache of resources, keyed by subject.
+/// Clones share the same cache.
+#[derive(Clone)]
+pub struct ResourceCache {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl Default for ResourceCache {
+    fn default() -> Self {
+        ResourceCache::new(DEFAULT_RESOURCE_CACHE_SIZE)
+    }
+}
+
+impl ResourceCache {
+    pub fn new(capacity: usize) -> Self {
+        ResourceCache {
+            entries: Arc::new(Mutex::new(Entries {
+                capacity,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Changes the amount of resources that are cached. `0` disables the cache.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.capacity = capacity;
+        while entries.resources.len() > capacity {
+            let Some((_, oldest)) = entries.recent.pop_first() else {
+                break;
+            };
+            entries.resources.remove(&oldest);
+        }
+    }
+
+    pub fn get(&self, subject: &str) -> Option<PropVals> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.touch(subject)?;
+        entries
+            .resources
+            .get(subject)
+            .map(|(propvals, _)| propvals.clone())
+    }
+
+    /// Read this before reading the resource from the store, and pass it to [ResourceCache::insert].
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Adds a resource that was read from the store at `generation`.
+    /// Ignored if any resource has been removed since, as the resource might have changed after it was read.
+    pub fn insert(&self, subject: &str, propvals: &PropVals, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.capacity == 0 || entries.generation != generation {
+            return;
+        }
+        if let Some((cached, _)) = entries.resources.get_mut(subject) {
+            *cached = propvals.clone();
+            entries.touch(subject);
+            return;
+        }
+        if entries.resources.len() >= entries.capacity {
+            if let Some((_, oldest)) = entries.recent.pop_first() {
+                entries.resources.remove(&oldest);
+            }
+        }
+        entries.clock += 1;
+        let clock = entries.clock;
+        entries
+            .resources
+            .insert(subject.to_string(), (propvals.clone(), clock));
+        entries.recent.insert(clock, subject.to_string());
+    }
+
+    /// Call this after the resource has been written to or removed from the store.
+    pub fn remove(&self, subject: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        if let Some((_, used)) = entries.resources.remove(subject) {
+            entries.recent.remove(&used);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().resources.len()
+    }
+}
@@ -0,0 +1,139 @@
+//! A snapshot of all Properties and Classes in the store, so the datatype of a Property can be looked up without reading and parsing its resource.
+//! Readers never wait for a lock: they load the current snapshot, and writers replace it with an updated copy.
+//! The snapshot is built when the [Db] opens, and updated whenever a Property or Class is written or removed.
+//! Classes inherit Properties from the Classes they extend, so all Classes are resolved again (when they are requested) after any Class changes.
+
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwap;
+
+use crate::{
+    errors::AtomicResult,
+    resources::PropVals,
+    schema::{Class, Property},
+    urls, Db, Resource, Storelike,
+};
+
+#[derive(Clone, Default)]
+struct Snapshot {
+    properties: HashMap<String, Property>,
+    classes: HashMap<String, Class>,
+    /// Increases whenever the Classes are cleared, so Classes that were resolved before that are not added.
+    class_generation: u64,
+}
+
+/// Shared by all clones of the [Db].
+#[derive(Clone, Default)]
+pub struct SchemaRegistry {
+    snapshot: Arc<ArcSwap<Snapshot>>,
+}
+
+/// Whether the resource is an instance of the Class.
+fn is_a(propvals: &PropVals, class: &str) -> bool {
+    propvals
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None).ok())
+        .map(|classes| classes.iter().any(|c| c == class))
+        .unwrap_or(false)
+}
+
+/// Reads a Class from the store, including the Properties that it inherits.
+pub fn resolve_class(store: &impl Storelike, subject: &str) -> AtomicResult<Class> {
+    let resource = store
+        .get_resource(subject)
+        .map_err(|e| format!("Failed getting class {}. {}", subject, e))?;
+    let mut class = Class::from_resource(resource)?;
+    class.add_inherited_properties(store)?;
+    Ok(class)
+}
+
+impl SchemaRegistry {
+    /// Replaces the snapshot with all the Properties and Classes in the store.
+    /// Resources that are not valid Properties or Classes are skipped.
+    pub fn build(&self, store: &Db) -> AtomicResult<()> {
+        let mut snapshot = Snapshot {
+            class_generation: self.class_generation() + 1,
+            ..Default::default()
+        };
+        for subject in store.subjects_with_class(urls::PROPERTY)? {
+            if let Ok(property) = store
+                .get_resource(&subject)
+                .and_then(Property::from_resource)
+            {
+                snapshot.properties.insert(subject, property);
+            }
+        }
+        for subject in store.subjects_with_class(urls::CLASS)? {
+            if let Ok(class) = resolve_class(store, &subject) {
+                snapshot.classes.insert(subject, class);
+            }
+        }
+        self.snapshot.store(Arc::new(snapshot));
+        Ok(())
+    }
+
+    pub fn get_property(&self, subject: &str) -> Option<Property> {
+        self.snapshot.load().properties.get(subject).cloned()
+    }
+
+    pub fn get_class(&self, subject: &str) -> Option<Class> {
+        self.snapshot.load().classes.get(subject).cloned()
+    }
+
+    /// Pass this to [SchemaRegistry::insert_class], to prevent adding Classes that changed while they were resolved.
+    pub fn class_generation(&self) -> u64 {
+        self.snapshot.load().class_generation
+    }
+
+    /// Adds a Class that was resolved (including the Properties that it inherits) at `class_generation`.
+    pub fn insert_class(&self, class: &Class, class_generation: u64) {
+        self.snapshot.rcu(|snapshot| {
+            let mut snapshot = Snapshot::clone(snapshot);
+            if snapshot.class_generation == class_generation {
+                snapshot
+                    .classes
+                    .insert(class.subject.clone(), class.clone());
+            }
+            snapshot
+        });
+    }
+
+    /// Updates the snapshot after a resource has been written. Cheap for resources that are not (and were not) a Property or Class.
+    pub fn update(&self, subject: &str, propvals: &PropVals) {
+        let is_property = is_a(propvals, urls::PROPERTY);
+        let is_class = is_a(propvals, urls::CLASS);
+        let current = self.snapshot.load();
+        let was_property = current.properties.contains_key(subject);
+        let was_class = current.classes.contains_key(subject);
+        drop(current);
+        if !(is_property || is_class || was_property || was_class) {
+            return;
+        }
+        let property = is_property
+            .then(|| {
+                Property::from_resource(Resource::from_propvals(propvals.clone(), subject.into()))
+            })
+            .and_then(Result::ok);
+        self.snapshot.rcu(|snapshot| {
+            let mut snapshot = Snapshot::clone(snapshot);
+            match &property {
+                Some(property) => {
+                    snapshot.properties.insert(subject.into(), property.clone());
+                }
+                None => {
+                    snapshot.properties.remove(subject);
+                }
+            }
+            if is_class || was_class {
+                snapshot.classes.clear();
+                snapshot.class_generation += 1;
+            }
+            snapshot
+        });
+    }
+
+    /// Updates the snapshot after a resource has been removed.
+    pub fn remove(&self, subject: &str) {
+        self.update(subject, &PropVals::new());
+    }
+}
@@ -62,7 +62,6 @@ fn populate_collections() {
     let store = Db::init_temp("populate_collections").unwrap();
     let subjects: Vec<String> = store
         .all_resources(false)
-        .into_iter()
         .map(|r| r.get_subject().into())
         .collect();
     println!("{:?}", subjects);
@@ -185,9 +184,27 @@ fn destroy_resource_and_check_collection_and_commits() {
 fn get_extended_resource_pagination() {
     let store = Db::init_temp("get_extended_resource_pagination").unwrap();
     let subject = format!("{}/commits?current_page=2", store.get_server_url());
-    // Should throw, because page 2 is out of bounds for default page size
+    // Should throw, because the page after the last one is out of bounds for default page size.
+    // Every Class and Endpoint in the default store adds a Commit, so the amount of pages depends on the default store.
+    let first_page = store
+        .get_resource_extended(
+            &format!("{}/commits?current_page=0", store.get_server_url()),
+            false,
+            None,
+        )
+        .unwrap();
+    let total_pages = first_page
+        .get(urls::COLLECTION_TOTAL_PAGES)
+        .unwrap()
+        .to_int()
+        .unwrap();
+    let out_of_bounds = format!(
+        "{}/commits?current_page={}",
+        store.get_server_url(),
+        total_pages + 1
+    );
     let _wrong_resource = store
-        .get_resource_extended(&subject, false, None)
+        .get_resource_extended(&out_of_bounds, false, None)
         .unwrap_err();
     // let subject = "https://atomicdata.dev/classes?current_page=2&page_size=1";
     let subject_with_page_size = format!("{}&page_size=1", subject);
@@ -263,6 +280,7 @@ fn queries() {
         include_external: true,
         include_nested: false,
         for_agent: None,
+        geo: None,
     };
     let res = store.query(&q).unwrap();
     assert_eq!(
@@ -389,6 +407,7 @@ fn query_include_external() {
         include_external: true,
         include_nested: false,
         for_agent: None,
+        geo: None,
     };
     let res_include = store.query(&q).unwrap();
     q.include_external = false;
@@ -412,6 +431,60 @@ fn test_db_resources_all() {
     );
 }
 
+#[test]
+fn export_skips_no_export() {
+    let store = &Db::init_temp("export_skips_no_export").unwrap();
+    let mut hidden = Resource::new_generate_subject(store);
+    hidden
+        .set_propval(urls::NO_EXPORT.into(), Value::Boolean(true), store)
+        .unwrap();
+    hidden.save_locally(store).unwrap();
+    let mut child = Resource::new_generate_subject(store);
+    child
+        .set_propval_string(urls::PARENT.into(), hidden.get_subject(), store)
+        .unwrap();
+    child.save_locally(store).unwrap();
+
+    let export = store.export(false, false).unwrap();
+    assert!(!export.contains(hidden.get_subject()));
+    assert!(!export.contains(child.get_subject()));
+    let full_export = store.export(false, true).unwrap();
+    assert!(full_export.contains(child.get_subject()));
+}
+
+#[test]
+fn rights_cache_is_invalidated() {
+    let store = &Db::init_temp("rights_cache_is_invalidated").unwrap();
+    let agent = "https://localhost/agents/reader";
+    let mut folder = Resource::new_generate_subject(store);
+    folder.set_propval_unsafe(urls::READ.into(), vec![agent].into());
+    folder.save_locally(store).unwrap();
+    let mut document = Resource::new_generate_subject(store);
+    document
+        .set_propval_string(urls::PARENT.into(), folder.get_subject(), store)
+        .unwrap();
+    document.save_locally(store).unwrap();
+
+    let cache = store.rights_cache().unwrap();
+    cache.clear();
+    crate::hierarchy::check_read(store, &document, agent).unwrap();
+    assert_eq!(cache.len(), 1);
+    crate::hierarchy::check_read(store, &document, agent).unwrap();
+    assert_eq!(cache.len(), 1);
+
+    // Changes to other properties keep the cache
+    folder
+        .set_propval_string(urls::NAME.into(), "Folder", store)
+        .unwrap();
+    folder.save_locally(store).unwrap();
+    assert_eq!(cache.len(), 1);
+
+    folder.remove_propval(urls::READ);
+    folder.save_locally(store).unwrap();
+    assert!(cache.is_empty());
+    crate::hierarchy::check_read(store, &document, agent).unwrap_err();
+}
+
 #[test]
 /// Changing these values actually correctly updates the index.
 fn index_invalidate_cache() {
@@ -470,7 +543,6 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
     );
 
     let mut resources: Vec<Resource> = (0..count)
-        .into_iter()
         .map(|_num| {
             let mut demo_resource = Resource::new_generate_subject(store);
             demo_resource
@@ -501,6 +573,7 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
         include_external: true,
         include_nested: true,
         for_agent: None,
+        geo: None,
     };
     let mut res = store.query(&q).unwrap();
     assert_eq!(
@@ -557,3 +630,388 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
         "Modifying the filtered value did not remove the item from the results"
     );
 }
+
+#[test]
+fn queries_geo_points() {
+    use crate::{datatype::DataType, storelike::GeoFilter, values::GeoPoint};
+
+    let store = &Db::init_temp("queries_geo_points").unwrap();
+    let places = [
+        ("amsterdam", 52.3676, 4.9041),
+        ("utrecht", 52.0907, 5.1214),
+        ("berlin", 52.52, 13.405),
+        ("fiji", -17.7134, 178.065),
+        ("samoa", -13.759, -172.1046),
+    ];
+    for (name, latitude, longitude) in places {
+        let mut resource = Resource::new(format!("{}/{}", store.get_server_url(), name));
+        resource
+            .set_propval(
+                urls::LOCATION.into(),
+                Value::new(&format!("{},{}", latitude, longitude), &DataType::GeoPoint).unwrap(),
+                store,
+            )
+            .unwrap();
+        resource.save_locally(store).unwrap();
+    }
+    let names = |geo: GeoFilter| -> Vec<String> {
+        let mut q = Query::new();
+        q.property = Some(urls::LOCATION.into());
+        q.geo = Some(geo);
+        store
+            .query(&q)
+            .unwrap()
+            .subjects
+            .iter()
+            .map(|s| s.rsplit('/').next().unwrap().to_string())
+            .collect()
+    };
+
+    // Sorted by distance
+    let near_utrecht = GeoFilter::Radius {
+        center: GeoPoint::new(52.09, 5.12).unwrap(),
+        meters: 50_000.0,
+    };
+    assert_eq!(names(near_utrecht), ["utrecht", "amsterdam"]);
+    let netherlands = GeoFilter::BoundingBox {
+        south_west: GeoPoint::new(50.7, 3.3).unwrap(),
+        north_east: GeoPoint::new(53.6, 7.2).unwrap(),
+    };
+    assert_eq!(names(netherlands).len(), 2);
+    let pacific = GeoFilter::BoundingBox {
+        south_west: GeoPoint::new(-20.0, 170.0).unwrap(),
+        north_east: GeoPoint::new(-10.0, -170.0).unwrap(),
+    };
+    let mut pacific = names(pacific);
+    pacific.sort();
+    assert_eq!(pacific, ["fiji", "samoa"]);
+
+    // Moving a place updates the index
+    let mut berlin = store
+        .get_resource(&format!("{}/berlin", store.get_server_url()))
+        .unwrap();
+    berlin
+        .set_propval_string(urls::LOCATION.into(), "52.1,5.2", store)
+        .unwrap();
+    berlin.save_locally(store).unwrap();
+    let near_utrecht = GeoFilter::Radius {
+        center: GeoPoint::new(52.09, 5.12).unwrap(),
+        meters: 50_000.0,
+    };
+    assert_eq!(names(near_utrecht), ["utrecht", "berlin", "amsterdam"]);
+
+    Value::new("91,0", &DataType::GeoPoint).unwrap_err();
+    Value::new("52.1", &DataType::GeoPoint).unwrap_err();
+}
+
+#[test]
+fn queries_nested_paths() {
+    use crate::atoms::nested_path;
+
+    let store = &Db::init_temp("queries_nested_paths").unwrap();
+    let fruits = [
+        ("apple", "nl", "Appel"),
+        ("pear", "nl", "Peer"),
+        ("banana", "nl", "Banaan"),
+        ("cherry", "de", "Kirsche"),
+    ];
+    for (slug, language, name) in fruits {
+        let mut resource = Resource::new(format!("{}/{}", store.get_server_url(), slug));
+        resource
+            .set_translation(language, urls::NAME, Value::String(name.into()), store)
+            .unwrap();
+        resource.save_locally(store).unwrap();
+    }
+    let mut q = Query::new();
+    q.property = Some(nested_path(&[urls::TRANSLATIONS, urls::LANGUAGE]));
+    q.value = Some(Value::String("nl".into()));
+    q.sort_by = Some(nested_path(&[urls::TRANSLATIONS, urls::NAME]));
+    let slugs = |q: &Query| -> Vec<String> {
+        store
+            .query(q)
+            .unwrap()
+            .subjects
+            .iter()
+            .map(|s| s.rsplit('/').next().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(slugs(&q), ["apple", "banana", "pear"]);
+
+    // Changing a nested value updates the index
+    let mut pear = store
+        .get_resource(&format!("{}/pear", store.get_server_url()))
+        .unwrap();
+    pear.set_translation("nl", urls::NAME, Value::String("Aardpeer".into()), store)
+        .unwrap();
+    pear.save_locally(store).unwrap();
+    assert_eq!(slugs(&q), ["pear", "apple", "banana"]);
+}
+
+#[test]
+fn query_results_are_cached() {
+    let store = &Db::init_temp("query_results_are_cached").unwrap();
+    let folder = format!("{}/folder", store.get_server_url());
+    let mut q = Query::new_prop_val(urls::PARENT, &folder);
+    q.sort_by = Some(urls::NAME.into());
+    let names = |q: &Query| -> Vec<String> {
+        store
+            .query(q)
+            .unwrap()
+            .subjects
+            .iter()
+            .map(|s| s.rsplit('/').next().unwrap().to_string())
+            .collect()
+    };
+    for name in ["b", "c"] {
+        let mut resource = Resource::new(format!("{}/{}", store.get_server_url(), name));
+        resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        resource.set_propval_unsafe(urls::NAME.into(), Value::String(name.into()));
+        resource.save_locally(store).unwrap();
+    }
+    assert_eq!(names(&q), ["b", "c"]);
+    assert!(store.query_cache.get(&q).is_some());
+
+    // New members, changed sort values and removed members update the cached result
+    let mut a = Resource::new(format!("{}/a", store.get_server_url()));
+    a.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+    a.set_propval_unsafe(urls::NAME.into(), Value::String("a".into()));
+    a.save_locally(store).unwrap();
+    assert_eq!(names(&q), ["a", "b", "c"]);
+    let mut c = store
+        .get_resource(&format!("{}/c", store.get_server_url()))
+        .unwrap();
+    c.set_propval_unsafe(urls::NAME.into(), Value::String("0".into()));
+    c.save_locally(store).unwrap();
+    assert_eq!(names(&q), ["c", "a", "b"]);
+    a.destroy(store).unwrap();
+    assert_eq!(names(&q), ["c", "b"]);
+}
+
+#[test]
+fn get_resource_partial() {
+    let store = &Db::init_temp("get_resource_partial").unwrap();
+    let subject = format!("{}/document", store.get_server_url());
+    let mut nested = crate::resources::PropVals::new();
+    nested.insert(urls::NAME.into(), Value::String("nested".into()));
+    let values = [
+        Value::AtomicUrl("https://example.com".into()),
+        Value::Date("2020-01-01".into()),
+        Value::Integer(-1),
+        Value::Float(1.5),
+        Value::Markdown("# Body\n".repeat(10_000)),
+        Value::ResourceArray(vec![
+            crate::values::SubResource::Subject("https://example.com/a".into()),
+            crate::values::SubResource::Nested(nested.clone()),
+        ]),
+        Value::Slug("slug".into()),
+        Value::String("string".into()),
+        Value::Timestamp(1_000),
+        Value::NestedResource(crate::values::SubResource::Nested(nested)),
+        Value::Boolean(true),
+        Value::Unsupported(crate::values::UnsupportedValue {
+            value: "unsupported".into(),
+            datatype: "https://example.com/datatype".into(),
+        }),
+        Value::GeoPoint(crate::values::GeoPoint {
+            latitude: 52.0,
+            longitude: 4.0,
+        }),
+        Value::Decimal("10.25".parse().unwrap()),
+        Value::Bytes(vec![1, 2, 3]),
+    ];
+    let properties: Vec<String> = (0..values.len())
+        .map(|i| format!("https://example.com/properties/{}", i))
+        .collect();
+    let mut resource = Resource::new(subject.clone());
+    for (property, value) in properties.iter().zip(values) {
+        resource.set_propval_unsafe(property.clone(), value);
+    }
+    store
+        .add_resource_opts(&resource, false, false, true)
+        .unwrap();
+
+    // Every kind of value can be skipped and read
+    for property in &properties {
+        let partial = store
+            .get_resource_partial(&subject, &[property, "https://example.com/missing"])
+            .unwrap();
+        assert_eq!(partial.get_propvals().len(), 1);
+        assert_eq!(
+            partial.get(property).unwrap().to_string(),
+            resource.get(property).unwrap().to_string()
+        );
+    }
+    let two = store
+        .get_resource_partial(&subject, &[&properties[2], &properties[7]])
+        .unwrap();
+    assert_eq!(two.get_propvals().len(), 2);
+    assert_eq!(two.get_subject(), &subject);
+    store
+        .get_resource_partial(&format!("{}/missing", store.get_server_url()), &[])
+        .unwrap_err();
+}
+
+#[test]
+fn resource_cache() {
+    let mut store = Db::init_temp("resource_cache").unwrap();
+    let subject = format!("{}/cached", store.get_server_url());
+    let name = |store: &Db| {
+        store
+            .get_resource(&subject)
+            .unwrap()
+            .get(urls::NAME)
+            .unwrap()
+            .to_string()
+    };
+    let mut resource = Resource::new(subject.clone());
+    resource.set_propval_unsafe(urls::NAME.into(), Value::String("first".into()));
+    resource.save_locally(&store).unwrap();
+    assert_eq!(name(&store), "first");
+    assert!(store.resource_cache.get(&subject).is_some());
+
+    // Commits replace the cached resource
+    resource.set_propval_unsafe(urls::NAME.into(), Value::String("second".into()));
+    resource.save_locally(&store).unwrap();
+    assert_eq!(name(&store), "second");
+    assert_eq!(
+        store
+            .get_resource_partial(&subject, &[urls::NAME])
+            .unwrap()
+            .get_propvals()
+            .len(),
+        1
+    );
+    resource.destroy(&store).unwrap();
+    store.get_resource(&subject).unwrap_err();
+
+    store.set_resource_cache_size(1);
+    assert_eq!(store.resource_cache.len(), 1);
+    store.get_resource(urls::NAME).unwrap();
+    store.get_resource(urls::DESCRIPTION).unwrap();
+    assert!(store.resource_cache.get(urls::NAME).is_none());
+    assert!(store.resource_cache.get(urls::DESCRIPTION).is_some());
+    store.set_resource_cache_size(0);
+    store.get_resource(urls::NAME).unwrap();
+    assert_eq!(store.resource_cache.len(), 0);
+}
+
+#[test]
+fn schema_registry() {
+    use crate::datatype::DataType;
+
+    let store = Db::init_temp("schema_registry").unwrap();
+    let server = store.get_server_url().to_string();
+    assert!(store.schema.get_property(urls::NAME).is_some());
+    assert!(store.schema.get_class(urls::AGENT).is_some());
+
+    let property_subject = format!("{}/property", server);
+    let mut property = Resource::new(property_subject.clone());
+    property.set_class(urls::PROPERTY);
+    property.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("property".into()));
+    property.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A test".into()));
+    property.set_propval_unsafe(
+        urls::DATATYPE_PROP.into(),
+        Value::AtomicUrl(urls::STRING.into()),
+    );
+    property.save_locally(&store).unwrap();
+    assert_eq!(
+        store.get_property(&property_subject).unwrap().data_type,
+        DataType::String
+    );
+
+    // Commits to a Property update the registry
+    property.set_propval_unsafe(
+        urls::DATATYPE_PROP.into(),
+        Value::AtomicUrl(urls::INTEGER.into()),
+    );
+    property.save_locally(&store).unwrap();
+    assert_eq!(
+        store
+            .schema
+            .get_property(&property_subject)
+            .unwrap()
+            .data_type,
+        DataType::Integer
+    );
+
+    // Classes include the Properties of the Classes they extend, also after those change
+    let class = |name: &str| {
+        let mut class = Resource::new(format!("{}/{}", server, name));
+        class.set_class(urls::CLASS);
+        class.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug(name.into()));
+        class.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A test".into()));
+        class
+    };
+    let mut parent = class("parent");
+    parent.save_locally(&store).unwrap();
+    let mut child = class("child");
+    child.set_propval_unsafe(
+        urls::EXTENDS.into(),
+        Value::ResourceArray(vec![parent.get_subject().as_str().into()]),
+    );
+    child.save_locally(&store).unwrap();
+    assert!(store
+        .get_class(child.get_subject())
+        .unwrap()
+        .recommends
+        .is_empty());
+    parent.set_propval_unsafe(
+        urls::RECOMMENDS.into(),
+        Value::ResourceArray(vec![property_subject.clone().into()]),
+    );
+    parent.save_locally(&store).unwrap();
+    assert_eq!(
+        store.get_class(child.get_subject()).unwrap().recommends,
+        vec![property_subject.clone()]
+    );
+
+    property.destroy(&store).unwrap();
+    assert!(store.schema.get_property(&property_subject).is_none());
+    store.get_property(&property_subject).unwrap_err();
+}
+
+#[test]
+fn resource_cache_ignores_stale_reads() {
+    let store = Db::init_temp("resource_cache_ignores_stale_reads").unwrap();
+    let subject = format!("{}/stale", store.get_server_url());
+    let save = |name: String| {
+        let mut resource = Resource::new(subject.clone());
+        resource.set_propval_unsafe(urls::NAME.into(), Value::String(name));
+        store
+            .add_resource_opts(&resource, false, false, true)
+            .unwrap();
+        resource
+    };
+    let name = || {
+        store
+            .get_resource(&subject)
+            .unwrap()
+            .get(urls::NAME)
+            .unwrap()
+            .to_string()
+    };
+
+    // A reader that read the old version before a write can not cache it afterwards
+    let old = save("old".into());
+    let generation = store.resource_cache.generation();
+    save("new".into());
+    store
+        .resource_cache
+        .insert(&subject, old.get_propvals(), generation);
+    assert_eq!(name(), "new");
+
+    let writes = 500;
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..writes {
+                    name();
+                }
+            });
+        }
+        for i in 0..writes {
+            save(i.to_string());
+        }
+    });
+    assert_eq!(name(), (writes - 1).to_string());
+}
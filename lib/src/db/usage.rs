@@ -0,0 +1,174 @@
+//! Keeps track of how much every Drive uses, sorted by {Drive}-{Counter}.
+//! Stores the amount of resources, the total size of the Files and the amount of Commits of today.
+//! Updated whenever a resource is added or removed, and used for enforcing quotas, see [crate::plugins::quotas].
+
+use std::borrow::Cow;
+
+use crate::{
+    errors::AtomicResult, plugins::quotas::Usage, resources::PropVals, urls, Db, Resource,
+    Storelike,
+};
+
+use super::query_index::SEPARATION_BIT;
+
+const RESOURCES: &str = "resources";
+const FILE_SIZE: &str = "file-size";
+/// Stores the day and the amount of Commits on that day, so it resets every day.
+const COMMITS: &str = "commits";
+const DAY: i64 = 24 * 60 * 60 * 1000;
+/// Prevents endless loops when parents refer to each other.
+const MAX_DEPTH: usize = 100;
+
+fn key(drive: &str, counter: &str) -> Vec<u8> {
+    [drive.as_bytes(), &[SEPARATION_BIT], counter.as_bytes()].concat()
+}
+
+fn to_u64(bytes: &[u8]) -> u64 {
+    bytes.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
+
+fn today() -> u64 {
+    (crate::utils::now() / DAY).max(0) as u64
+}
+
+fn is_a(propvals: &PropVals, class: &str) -> bool {
+    propvals
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None).ok())
+        .map(|classes| classes.iter().any(|c| c == class))
+        .unwrap_or(false)
+}
+
+fn file_size(resource: &Resource) -> u64 {
+    resource
+        .get(urls::FILESIZE)
+        .and_then(|v| v.to_int())
+        .map(|size| size.max(0) as u64)
+        .unwrap_or(0)
+}
+
+fn string_prop(resource: &Resource, property: &str) -> Option<String> {
+    resource.get(property).ok().map(|v| v.to_string())
+}
+
+/// Returns the subject of the Drive that the resource belongs to, which can be the resource itself.
+/// Unlike [crate::plugins::features::find_drive], this never fetches external resources,
+/// since it runs whenever a resource is added.
+pub fn find_drive(store: &Db, resource: &Resource) -> Option<String> {
+    let self_url = store.get_self_url()?;
+    let mut subject = resource.get_subject().to_string();
+    let mut propvals = Cow::Borrowed(resource.get_propvals());
+    for _depth in 0..MAX_DEPTH {
+        if !subject.starts_with(&self_url) {
+            return None;
+        }
+        if is_a(&propvals, urls::DRIVE) {
+            return Some(subject);
+        }
+        subject = propvals.get(urls::PARENT)?.to_string();
+        propvals = Cow::Owned(store.get_propvals(&subject).ok()?);
+    }
+    None
+}
+
+fn add_to_counter(store: &Db, drive: &str, counter: &str, amount: i64) -> AtomicResult<()> {
+    store.usage.update_and_fetch(key(drive, counter), |old| {
+        let current = old.map(to_u64).unwrap_or(0);
+        Some(current.saturating_add_signed(amount).to_be_bytes().to_vec())
+    })?;
+    Ok(())
+}
+
+fn add_commit(store: &Db, drive: &str) -> AtomicResult<()> {
+    let today = today();
+    store.usage.update_and_fetch(key(drive, COMMITS), |old| {
+        let count = match old {
+            Some(old) if old.len() == 16 && to_u64(&old[..8]) == today => to_u64(&old[8..]),
+            _ => 0,
+        };
+        Some([today.to_be_bytes(), (count + 1).to_be_bytes()].concat())
+    })?;
+    Ok(())
+}
+
+/// Adds (or, with a negative `sign`, subtracts) what the resource counts towards the usage of its Drive.
+fn count_resource(store: &Db, resource: &Resource, sign: i64) -> AtomicResult<()> {
+    if let Some(drive) = find_drive(store, resource) {
+        add_to_counter(store, &drive, RESOURCES, sign)?;
+        let size = file_size(resource);
+        if size > 0 {
+            add_to_counter(store, &drive, FILE_SIZE, sign * size as i64)?;
+        }
+    }
+    Ok(())
+}
+
+/// Updates the usage when `resource` is added, replacing `existing` if it was already there.
+pub fn add_to_usage(
+    store: &Db,
+    existing: Option<&Resource>,
+    resource: &Resource,
+) -> AtomicResult<()> {
+    if is_a(resource.get_propvals(), urls::COMMIT) {
+        return Ok(());
+    }
+    match existing {
+        None => count_resource(store, resource, 1)?,
+        // Moving a resource to another Drive, or changing a File, changes the usage
+        Some(existing)
+            if string_prop(existing, urls::PARENT) != string_prop(resource, urls::PARENT)
+                || file_size(existing) != file_size(resource) =>
+        {
+            count_resource(store, existing, -1)?;
+            count_resource(store, resource, 1)?;
+        }
+        Some(_unchanged) => {}
+    }
+    // Every applied Commit sets a new `lastCommit`
+    let last_commit = string_prop(resource, urls::LAST_COMMIT);
+    if last_commit.is_some()
+        && last_commit != existing.and_then(|e| string_prop(e, urls::LAST_COMMIT))
+    {
+        if let Some(drive) = find_drive(store, resource) {
+            add_commit(store, &drive)?;
+        }
+    }
+    Ok(())
+}
+
+/// Updates the usage when the resource is removed.
+pub fn remove_from_usage(store: &Db, resource: &Resource) -> AtomicResult<()> {
+    if is_a(resource.get_propvals(), urls::COMMIT) {
+        return Ok(());
+    }
+    count_resource(store, resource, -1)
+}
+
+/// The current usage of the Drive.
+pub fn get_usage(store: &Db, drive: &str) -> AtomicResult<Usage> {
+    let read = |counter: &str| -> AtomicResult<Option<sled::IVec>> {
+        Ok(store.usage.get(key(drive, counter))?)
+    };
+    let commits_today = match read(COMMITS)? {
+        Some(value) if value.len() == 16 && to_u64(&value[..8]) == today() => to_u64(&value[8..]),
+        _ => 0,
+    };
+    Ok(Usage {
+        resources: read(RESOURCES)?.map(|v| to_u64(&v)).unwrap_or(0),
+        file_size: read(FILE_SIZE)?.map(|v| to_u64(&v)).unwrap_or(0),
+        commits_today,
+    })
+}
+
+/// Counts the resources and Files of all Drives.
+/// Used when the usage is tracked for an existing store. The Commits of today are not counted.
+pub fn build_usage(store: &Db) -> AtomicResult<()> {
+    tracing::info!("Counting usage of Drives...");
+    store.usage.clear()?;
+    for resource in store.all_resources(false) {
+        if !is_a(resource.get_propvals(), urls::COMMIT) {
+            count_resource(store, &resource, 1)?;
+        }
+    }
+    Ok(())
+}
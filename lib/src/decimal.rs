@@ -0,0 +1,444 @@
+//! Arbitrary precision decimal numbers, for values that floats can't represent exactly, such as amounts of money.
+//! A [Decimal] is serialized as a string in its canonical form: without leading zeros, without trailing zeros after the decimal point, and without `-0`.
+//! So `"0010.50"` becomes `"10.5"`, and Decimals are equal if their canonical forms are.
+
+use std::{cmp::Ordering, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{AtomicError, AtomicResult};
+
+/// Maximum amount of digits when parsing a Decimal, which keeps the values and their index keys small.
+pub const MAX_DIGITS: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Decimal {
+    negative: bool,
+    /// The digits of the unscaled value, most significant first, without leading zeros. Empty for zero.
+    digits: Vec<u8>,
+    /// Amount of digits after the decimal point. If this is not zero, the last digit is not zero either.
+    scale: usize,
+}
+
+impl Decimal {
+    /// Creates the canonical form.
+    fn new(negative: bool, digits: Vec<u8>, scale: usize) -> Decimal {
+        let mut digits = trim(digits);
+        let mut scale = scale;
+        while scale > 0 && digits.last() == Some(&0) {
+            digits.pop();
+            scale -= 1;
+        }
+        if digits.is_empty() {
+            return Decimal::zero();
+        }
+        Decimal {
+            negative,
+            digits,
+            scale,
+        }
+    }
+
+    pub fn zero() -> Decimal {
+        Decimal {
+            negative: false,
+            digits: Vec::new(),
+            scale: 0,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Amount of digits after the decimal point.
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// The unscaled digits, with `scale` digits after the decimal point.
+    fn scaled(&self, scale: usize) -> Vec<u8> {
+        let mut digits = self.digits.clone();
+        digits.resize(digits.len() + scale - self.scale, 0);
+        digits
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal::new(false, self.digits.clone(), self.scale)
+    }
+
+    fn plus(&self, other: &Decimal) -> Decimal {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.scaled(scale), other.scaled(scale));
+        if self.negative == other.negative {
+            return Decimal::new(self.negative, add_digits(&a, &b), scale);
+        }
+        match cmp_digits(&a, &b) {
+            Ordering::Less => Decimal::new(other.negative, sub_digits(&b, &a), scale),
+            _ => Decimal::new(self.negative, sub_digits(&a, &b), scale),
+        }
+    }
+
+    fn times(&self, other: &Decimal) -> Decimal {
+        Decimal::new(
+            self.negative != other.negative,
+            mul_digits(&self.digits, &other.digits),
+            self.scale + other.scale,
+        )
+    }
+
+    /// Divides by `other`, and rounds the result to `scale` digits after the decimal point, see [Decimal::round].
+    pub fn div_round(&self, other: &Decimal, scale: usize) -> AtomicResult<Decimal> {
+        if other.is_zero() {
+            return Err("Can't divide a Decimal by zero".into());
+        }
+        // self / other = (a / 10^sa) / (b / 10^sb). One extra digit is calculated for rounding.
+        let exponent = (other.scale + scale + 1) as i64 - self.scale as i64;
+        let mut dividend = self.digits.clone();
+        let mut divisor = other.digits.clone();
+        if exponent >= 0 {
+            dividend.resize(dividend.len() + exponent as usize, 0);
+        } else {
+            divisor.resize(divisor.len() + exponent.unsigned_abs() as usize, 0);
+        }
+        let quotient = div_digits(&dividend, &divisor);
+        let negative = self.negative != other.negative;
+        Ok(Decimal::new(negative, quotient, scale + 1).round(scale))
+    }
+
+    /// Rounds to `scale` digits after the decimal point. Halves are rounded away from zero, so `2.5` becomes `3` and `-2.5` becomes `-3`.
+    pub fn round(&self, scale: usize) -> Decimal {
+        if self.scale <= scale {
+            return self.clone();
+        }
+        let dropped = self.scale - scale;
+        let mut digits = self.digits.clone();
+        if digits.len() <= dropped {
+            let mut padded = vec![0; dropped + 1 - digits.len()];
+            padded.extend(digits);
+            digits = padded;
+        }
+        let round_up = digits[digits.len() - dropped] >= 5;
+        digits.truncate(digits.len() - dropped);
+        if round_up {
+            digits = add_digits(&digits, &[1]);
+        }
+        Decimal::new(self.negative, digits, scale)
+    }
+
+    /// A string that sorts lexicographically in the same order as the Decimals, used in the query index.
+    /// Zero is `2`, positive numbers start with `3` and negative numbers with `1`.
+    /// That is followed by the position of the decimal point and the significant digits,
+    /// which are inverted for negative numbers, so larger numbers come first.
+    pub fn to_sortable_string(&self) -> String {
+        if self.is_zero() {
+            return "2".into();
+        }
+        let significant = trim_end(&self.digits);
+        let exponent = self.digits.len() as i64 - self.scale as i64;
+        if self.negative {
+            let inverted: String = significant
+                .iter()
+                .map(|d| char::from(b'0' + 9 - d))
+                .collect();
+            // The `~` sorts after every digit, so `-1.2` comes after `-1.23`
+            format!("1{:05}{}~", 49_999 - exponent, inverted)
+        } else {
+            let digits: String = significant.iter().map(|d| char::from(b'0' + d)).collect();
+            format!("3{:05}{}", 50_000 + exponent, digits)
+        }
+    }
+}
+
+fn trim(digits: Vec<u8>) -> Vec<u8> {
+    let leading = digits.iter().take_while(|d| **d == 0).count();
+    digits[leading..].to_vec()
+}
+
+fn trim_end(digits: &[u8]) -> &[u8] {
+    let trailing = digits.iter().rev().take_while(|d| **d == 0).count();
+    &digits[..digits.len() - trailing]
+}
+
+fn cmp_digits(a: &[u8], b: &[u8]) -> Ordering {
+    let (a, b) = (trim(a.to_vec()), trim(b.to_vec()));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(&b))
+}
+
+fn add_digits(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    let mut a = a.iter().rev();
+    let mut b = b.iter().rev();
+    loop {
+        let (x, y) = (a.next(), b.next());
+        if x.is_none() && y.is_none() {
+            break;
+        }
+        let sum = x.unwrap_or(&0) + y.unwrap_or(&0) + carry;
+        result.push(sum % 10);
+        carry = sum / 10;
+    }
+    if carry > 0 {
+        result.push(carry);
+    }
+    result.reverse();
+    result
+}
+
+/// Subtracts `b` from `a`, which should not be smaller.
+fn sub_digits(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    let mut b = b.iter().rev();
+    for x in a.iter().rev() {
+        let y = b.next().unwrap_or(&0) + borrow;
+        if *x >= y {
+            result.push(x - y);
+            borrow = 0;
+        } else {
+            result.push(x + 10 - y);
+            borrow = 1;
+        }
+    }
+    result.reverse();
+    trim(result)
+}
+
+fn mul_digits(a: &[u8], b: &[u8]) -> Vec<u8> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate().rev() {
+        for (j, y) in b.iter().enumerate().rev() {
+            result[i + j + 1] += (*x as u32) * (*y as u32);
+        }
+    }
+    for i in (1..result.len()).rev() {
+        result[i - 1] += result[i] / 10;
+        result[i] %= 10;
+    }
+    trim(result.into_iter().map(|d| d as u8).collect())
+}
+
+/// Long division, discards the remainder.
+fn div_digits(dividend: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut quotient = Vec::with_capacity(dividend.len());
+    let mut remainder: Vec<u8> = Vec::new();
+    for digit in dividend {
+        remainder.push(*digit);
+        remainder = trim(remainder);
+        let mut count = 0;
+        while cmp_digits(&remainder, divisor) != Ordering::Less {
+            remainder = sub_digits(&remainder, divisor);
+            count += 1;
+        }
+        quotient.push(count);
+    }
+    trim(quotient)
+}
+
+/// Implements an operator for both owned Decimals and references.
+macro_rules! impl_op {
+    ($trait:ident, $method:ident, |$a:ident, $b:ident| $body:expr) => {
+        impl std::ops::$trait<&Decimal> for &Decimal {
+            type Output = Decimal;
+
+            fn $method(self, other: &Decimal) -> Decimal {
+                let ($a, $b) = (self, other);
+                $body
+            }
+        }
+
+        impl std::ops::$trait for Decimal {
+            type Output = Decimal;
+
+            fn $method(self, other: Decimal) -> Decimal {
+                std::ops::$trait::$method(&self, &other)
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, |a, b| a.plus(b));
+impl_op!(Sub, sub, |a, b| a.plus(&-b));
+impl_op!(Mul, mul, |a, b| a.times(b));
+
+impl std::ops::Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(!self.negative, self.digits.clone(), self.scale)
+    }
+}
+
+impl std::ops::Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        -&self
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        let magnitude = || cmp_digits(&self.scaled(scale), &other.scaled(scale));
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => magnitude(),
+            (true, true) => magnitude().reverse(),
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::str::FromStr for Decimal {
+    type Err = AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AtomicError::from(format!(
+                "Not a valid Decimal: {}. Use digits, an optional `-` and an optional `.`, e.g. `-12.50`.",
+                s
+            ))
+        };
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if integer.is_empty() || (unsigned.contains('.') && fraction.is_empty()) {
+            return Err(invalid());
+        }
+        if integer.len() + fraction.len() > MAX_DIGITS {
+            return Err(format!("A Decimal can have at most {} digits", MAX_DIGITS).into());
+        }
+        let digits = integer
+            .bytes()
+            .chain(fraction.bytes())
+            .map(|b| b.is_ascii_digit().then(|| b - b'0'))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        Ok(Decimal::new(negative, digits, fraction.len()))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits: String = self.digits.iter().map(|d| char::from(b'0' + d)).collect();
+        if digits.len() <= self.scale {
+            digits = format!("{}{}", "0".repeat(self.scale + 1 - digits.len()), digits);
+        }
+        if self.scale > 0 {
+            digits.insert(digits.len() - self.scale, '.');
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", digits)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(int: i64) -> Self {
+        let digits = int
+            .unsigned_abs()
+            .to_string()
+            .bytes()
+            .map(|b| b - b'0')
+            .collect();
+        Decimal::new(int < 0, digits, 0)
+    }
+}
+
+impl TryFrom<String> for Decimal {
+    type Error = AtomicError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Decimal> for String {
+    fn from(decimal: Decimal) -> Self {
+        decimal.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_to_canonical_form() {
+        assert_eq!(d("0010.500").to_string(), "10.5");
+        assert_eq!(d("-0.00").to_string(), "0");
+        assert_eq!(d("1000").to_string(), "1000");
+        assert_eq!(d("-0.0001").to_string(), "-0.0001");
+        assert_eq!(d("1.10"), d("1.1"));
+        for invalid in ["", "-", "1.", ".5", "1e5", "1,5", "+1", "1.2.3", " 1"] {
+            invalid.parse::<Decimal>().unwrap_err();
+        }
+        assert_eq!(Decimal::from(-1200).to_string(), "-1200");
+        assert_eq!(
+            serde_json::to_string(&d("12.30")).unwrap(),
+            "\"12.3\"".to_string()
+        );
+    }
+
+    #[test]
+    fn calculates_exactly() {
+        assert_eq!(d("0.1") + d("0.2"), d("0.3"));
+        assert_eq!(d("10") - d("10.01"), d("-0.01"));
+        assert_eq!(&d("-5.5") + &d("2.25"), d("-3.25"));
+        assert_eq!(d("19.99") * d("3"), d("59.97"));
+        assert_eq!(d("-1.5") * d("-0.2"), d("0.3"));
+        assert_eq!(-d("1.5"), d("-1.5"));
+        assert_eq!(d("-1.5").abs(), d("1.5"));
+        assert_eq!(d("100").div_round(&d("3"), 2).unwrap(), d("33.33"));
+        assert_eq!(d("2").div_round(&d("3"), 2).unwrap(), d("0.67"));
+        assert_eq!(d("-1").div_round(&d("8"), 2).unwrap(), d("-0.13"));
+        assert_eq!(d("1.21").div_round(&d("0.011"), 0).unwrap(), d("110"));
+        d("1").div_round(&Decimal::zero(), 2).unwrap_err();
+        assert_eq!(d("2.5").round(0), d("3"));
+        assert_eq!(d("-2.5").round(0), d("-3"));
+        assert_eq!(d("0.004").round(2), d("0"));
+        assert_eq!(d("9.995").round(2), d("10"));
+        assert_eq!(d("1.25").round(5), d("1.25"));
+    }
+
+    #[test]
+    fn sorts_like_numbers() {
+        let mut values: Vec<Decimal> = [
+            "100", "-1.23", "0", "99.99", "-1.2", "0.001", "-100", "1", "1.5", "-0.5", "10",
+        ]
+        .iter()
+        .map(|s| d(s))
+        .collect();
+        let mut sorted = values.clone();
+        sorted.sort();
+        assert_eq!(
+            sorted.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            ["-100", "-1.23", "-1.2", "-0.5", "0", "0.001", "1", "1.5", "10", "99.99", "100"]
+        );
+        values.sort_by_key(|d| d.to_sortable_string());
+        assert_eq!(values, sorted);
+    }
+}
@@ -76,11 +76,27 @@ pub fn default_endpoints() -> Vec<Endpoint> {
     vec![
         plugins::versioning::version_endpoint(),
         plugins::versioning::all_versions_endpoint(),
+        plugins::versioning::restore_endpoint(),
+        plugins::versioning::diff_endpoint(),
         plugins::path::path_endpoint(),
+        plugins::commits::commits_endpoint(),
         plugins::search::search_endpoint(),
         plugins::files::upload_endpoint(),
+        plugins::forms::form_submit_endpoint(),
+        plugins::calendar::events_endpoint(),
+        plugins::quotas::usage_endpoint(),
+        plugins::schema_usage::schema_usage_endpoint(),
+        plugins::rights::rights_endpoint(),
+        plugins::move_resource::move_endpoint(),
+        plugins::rename::rename_endpoint(),
         #[cfg(feature = "html")]
         plugins::bookmark::bookmark_endpoint(),
+        #[cfg(feature = "html")]
+        plugins::link_preview::link_preview_endpoint(),
         plugins::importer::import_endpoint(),
+        plugins::admin::agents_endpoint(),
+        plugins::devices::devices_endpoint(),
+        plugins::notifications::inbox_endpoint(),
+        plugins::notifications::read_endpoint(),
     ]
 }
@@ -31,6 +31,8 @@ pub enum AtomicErrorType {
     ParseError,
     OtherError,
     MethodNotAllowed,
+    /// A Drive has used up its quota, such as its maximum amount of resources.
+    QuotaExceeded,
 }
 
 impl std::error::Error for AtomicError {
@@ -48,6 +50,15 @@ impl AtomicError {
         }
     }
 
+    /// A server will probably return a 403.
+    pub fn quota_exceeded(message: String) -> AtomicError {
+        AtomicError {
+            message: format!("Quota exceeded. {}", message),
+            error_type: AtomicErrorType::QuotaExceeded,
+            subject: None,
+        }
+    }
+
     #[allow(dead_code)]
     /// A server will probably return a 404.
     pub fn not_found(message: String) -> AtomicError {
@@ -3,10 +3,17 @@
 //! See
 
 use core::fmt;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
-use crate::{errors::AtomicResult, storelike::Query, urls, Resource, Storelike};
+use crate::{
+    errors::{AtomicErrorType, AtomicResult},
+    urls, Resource, Storelike, Value,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Right {
     /// Full read access to the resource and its children.
     /// https://atomicdata.dev/properties/read
@@ -17,6 +24,82 @@ pub enum Right {
     /// Create new children (append to tree)
     /// https://atomicdata.dev/properties/append
     Append,
+    /// Add Messages and Reactions to the resource and its children, without editing anything else.
+    /// https://atomicdata.dev/properties/comment
+    Comment,
+    /// Create Invites for the resource and its children. Invites that grant write access also need the write right.
+    /// https://atomicdata.dev/properties/invite
+    Invite,
+    /// Destroy the resource and its children, without editing them.
+    /// https://atomicdata.dev/properties/destroyRight
+    Destroy,
+}
+
+impl Right {
+    pub const ALL: [Right; 6] = [
+        Right::Read,
+        Right::Write,
+        Right::Append,
+        Right::Comment,
+        Right::Invite,
+        Right::Destroy,
+    ];
+
+    /// The rights that include this one. Each of them grants this right as well.
+    /// The right itself comes first.
+    fn granted_by(&self) -> &'static [&'static str] {
+        match self {
+            Right::Read => &[urls::READ],
+            Right::Write => &[urls::WRITE],
+            Right::Append => &[urls::APPEND],
+            Right::Comment => &[urls::COMMENT_RIGHT, urls::APPEND, urls::WRITE],
+            Right::Invite => &[urls::INVITE_RIGHT, urls::WRITE],
+            Right::Destroy => &[urls::DESTROY_RIGHT, urls::WRITE],
+        }
+    }
+
+    /// The deny rules that revoke this right.
+    fn denied_by(&self) -> &'static [&'static str] {
+        match self {
+            Right::Read => &[urls::DENY_READ],
+            _ => &[urls::DENY_WRITE],
+        }
+    }
+}
+
+/// The subjects (usually Agents) that are listed in any of the `properties` of the resource.
+fn listed_subjects(resource: &Resource, properties: &[&str]) -> AtomicResult<Vec<String>> {
+    let mut agents = Vec::new();
+    for property in properties {
+        if let Ok(arr_val) = resource.get(property) {
+            agents.extend(arr_val.to_subjects(None)?);
+        }
+    }
+    Ok(agents)
+}
+
+/// The Agents that are granted (or, if `deny`, denied) the right in the resource.
+/// For [Right::Read], `publicRead: true` adds the PublicAgent to the granted Agents.
+fn rule_agents(resource: &Resource, right: Right, deny: bool) -> AtomicResult<Vec<String>> {
+    let properties = if deny {
+        right.denied_by()
+    } else {
+        right.granted_by()
+    };
+    let mut agents = listed_subjects(resource, properties)?;
+    if !deny && matches!(public_read(resource, right), Some(true)) {
+        agents.push(urls::PUBLIC_AGENT.into());
+    }
+    Ok(agents)
+}
+
+/// The value of [urls::PUBLIC_READ] in the resource, if the right is [Right::Read].
+/// `false` only withholds the right from the PublicAgent. Unlike a PublicAgent in `denyRead`, it does not deny it for everyone.
+fn public_read(resource: &Resource, right: Right) -> Option<bool> {
+    match (right, resource.get(urls::PUBLIC_READ)) {
+        (Right::Read, Ok(Value::Boolean(public))) => Some(*public),
+        _ => None,
+    }
 }
 
 impl fmt::Display for Right {
@@ -25,20 +108,170 @@ impl fmt::Display for Right {
             Right::Read => urls::READ,
             Right::Write => urls::WRITE,
             Right::Append => urls::APPEND,
+            Right::Comment => urls::COMMENT_RIGHT,
+            Right::Invite => urls::INVITE_RIGHT,
+            Right::Destroy => urls::DESTROY_RIGHT,
         };
         fmt.write_str(str)
     }
 }
 
-/// Looks for children relations, adds to the resource. Performs a Query, might be expensive.
+/// The Properties that determine the outcome of [check_rights] for a resource and its children.
+const HIERARCHY_PROPS: [&str; 10] = [
+    urls::PARENT,
+    urls::READ,
+    urls::WRITE,
+    urls::APPEND,
+    urls::COMMENT_RIGHT,
+    urls::INVITE_RIGHT,
+    urls::DESTROY_RIGHT,
+    urls::DENY_READ,
+    urls::DENY_WRITE,
+    urls::PUBLIC_READ,
+];
+
+/// The cache is cleared when it grows larger than this.
+const MAX_CACHED_RIGHTS: usize = 100_000;
+
+/// The subject, the agent and the right.
+type RightsCacheKey = (String, String, String);
+
+/// Remembers the outcome of [check_rights] for the parents of resources, keyed by (subject, agent, right).
+/// This saves walking up the parent tree for every request in deep hierarchies.
+/// Only parents are cached, since the resource that is checked might not be stored yet, or differ from the stored version.
+/// The whole cache is cleared when the parent or the rights of any resource change, see [RightsCache::invalidate].
+/// Clones share the same cache.
+#[derive(Clone, Default)]
+pub struct RightsCache {
+    entries: Arc<Mutex<HashMap<RightsCacheKey, AtomicResult<String>>>>,
+}
+
+impl RightsCache {
+    fn get(&self, subject: &str, for_agent: &str, right: Right) -> Option<AtomicResult<String>> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&(subject.into(), for_agent.into(), right.to_string()))
+            .cloned()
+    }
+
+    fn insert(&self, subject: &str, for_agent: &str, right: Right, result: &AtomicResult<String>) {
+        // Other errors, such as a failing database, might not occur the next time
+        if let Err(e) = result {
+            if !matches!(e.error_type, AtomicErrorType::UnauthorizedError) {
+                return;
+            }
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_RIGHTS {
+            entries.clear();
+        }
+        entries.insert(
+            (subject.into(), for_agent.into(), right.to_string()),
+            result.clone(),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Clears the cache if the parent or the rights differ between the old and new version of a resource.
+    /// Pass `None` for resources that are created or removed.
+    pub fn invalidate(&self, resource_old: Option<&Resource>, resource_new: Option<&Resource>) {
+        if rights_changed(resource_old, resource_new) {
+            self.clear();
+        }
+    }
+}
+
+/// Whether the parent or the rights differ between the old and new version of a resource.
+/// Pass `None` for resources that are created or removed.
+pub fn rights_changed(resource_old: Option<&Resource>, resource_new: Option<&Resource>) -> bool {
+    let value = |resource: Option<&Resource>, property: &str| {
+        resource
+            .and_then(|r| r.get(property).ok())
+            .map(|v| v.to_string())
+    };
+    HIERARCHY_PROPS
+        .iter()
+        .any(|p| value(resource_old, p) != value(resource_new, p))
+}
+
+/// Looks for children relations, adds to the resource in their manual order. Performs a Query, might be expensive.
 pub fn add_children(store: &impl Storelike, resource: &mut Resource) -> AtomicResult<Resource> {
-    let results = store.query(&Query::new_prop_val(urls::PARENT, resource.get_subject()))?;
-    let mut children = results.subjects;
-    children.sort();
+    let children: Vec<String> = crate::order::ordered_children(store, resource.get_subject())?
+        .iter()
+        .map(|child| child.get_subject().to_string())
+        .collect();
     resource.set_propval(urls::CHILDREN.into(), children.into(), store)?;
     Ok(resource.to_owned())
 }
 
+/// Returns the Agents that have been granted a right in the resource or any of its parents, following the precedence of [check_rights].
+/// May contain the PublicAgent. Does not include the server's default agent, or the resource itself (if it is an Agent),
+/// which are always allowed by [check_rights].
+/// Agents that are denied a right while the PublicAgent is granted it are not distinguished, as they can still use the right without signing in.
+/// Useful for filtering resources in bulk, e.g. in a search index.
+pub fn agents_with_right(
+    store: &impl Storelike,
+    resource: &Resource,
+    right: Right,
+) -> AtomicResult<Vec<String>> {
+    let mut agents: Vec<String> = Vec::new();
+    // Agents for which a closer resource already granted or denied the right
+    let mut decided: Vec<String> = Vec::new();
+    for r in std::iter::once(resource.clone()).chain(resource.get_parent_tree(store)?) {
+        let denied = rule_agents(&r, right, true)?;
+        let granted = rule_agents(&r, right, false)?;
+        // Rules for the PublicAgent apply to everyone, so the parents are no longer relevant
+        let everyone = denied
+            .iter()
+            .chain(granted.iter())
+            .any(|a| a == urls::PUBLIC_AGENT);
+        if public_read(&r, right) == Some(false) && !decided.iter().any(|a| a == urls::PUBLIC_AGENT)
+        {
+            decided.push(urls::PUBLIC_AGENT.into());
+        }
+        for agent in denied {
+            if !decided.contains(&agent) {
+                decided.push(agent);
+            }
+        }
+        for agent in granted {
+            if !decided.contains(&agent) {
+                decided.push(agent.clone());
+                agents.push(agent);
+            }
+        }
+        if everyone {
+            break;
+        }
+    }
+    Ok(agents)
+}
+
+/// Returns the value of a boolean property that applies to a resource and all of its children, such as [urls::NO_INDEX].
+/// The value of the resource itself is used, or else the value of its closest parent. Defaults to `false`.
+/// Pass the `parent_tree` from [Resource::get_parent_tree].
+pub fn inherited_flag(resource: &Resource, parent_tree: &[Resource], property: &str) -> bool {
+    std::iter::once(resource)
+        .chain(parent_tree.iter())
+        .find_map(|r| match r.get(property) {
+            Ok(Value::Boolean(flag)) => Some(*flag),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
 pub fn check_write(
@@ -60,8 +293,70 @@ pub fn check_read(
     check_rights(store, resource, for_agent, Right::Read)
 }
 
+/// Does the Agent have the right to destroy the selected resource?
+/// This checks the `destroyRight` and the `write` rights.
+/// Throws if not allowed.
+/// Returns string with explanation if allowed.
+pub fn check_destroy(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+) -> AtomicResult<String> {
+    check_rights(store, resource, for_agent, Right::Destroy)
+}
+
+/// Returns the Properties that only owners of the Drive can change in the resource, see [check_owner].
+/// These are the `lockedProps` of the resource and of its Classes. `lockedProps` itself is always locked.
+pub fn locked_props(store: &impl Storelike, resource: &Resource) -> AtomicResult<Vec<String>> {
+    let mut locked = vec![urls::LOCKED_PROPS.to_string()];
+    let classes = resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .unwrap_or_default();
+    for class in classes {
+        let class = store.get_resource(&class)?;
+        locked.extend(listed_subjects(&class, &[urls::LOCKED_PROPS])?);
+    }
+    locked.extend(listed_subjects(resource, &[urls::LOCKED_PROPS])?);
+    Ok(locked)
+}
+
+/// Is the Agent an owner of the Drive that contains the resource? Owners have write rights in the Drive itself.
+/// Throws if not allowed.
+/// Returns string with explanation if allowed.
+pub fn check_owner(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+) -> AtomicResult<String> {
+    let drive = resource
+        .get_parent_tree(store)?
+        .pop()
+        .unwrap_or_else(|| resource.clone());
+    check_write(store, &drive, for_agent)
+}
+
+/// The right that is needed in the parent to create a new resource.
+/// Messages and Reactions only need the `comment` right, and Invites only need the `invite` right.
+fn creation_right(resource: &Resource) -> Right {
+    let classes = resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .unwrap_or_default();
+    if classes
+        .iter()
+        .any(|c| c == urls::MESSAGE || c == urls::REACTION)
+    {
+        Right::Comment
+    } else if classes.iter().any(|c| c == urls::INVITE) {
+        Right::Invite
+    } else {
+        Right::Append
+    }
+}
+
 /// Does the Agent have the right to _append_ to its parent?
-/// This checks the `append` rights, and if that fails, checks the `write` right.
+/// This checks the `append` rights (or the `comment` or `invite` right, depending on the Class), and if that fails, checks the `write` right.
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
 #[tracing::instrument(skip(store), level = "debug")]
@@ -72,7 +367,7 @@ pub fn check_append(
 ) -> AtomicResult<String> {
     match resource.get_parent(store) {
         Ok(parent) => {
-            if let Ok(msg) = check_rights(store, &parent, for_agent, Right::Append) {
+            if let Ok(msg) = check_rights(store, &parent, for_agent, creation_right(resource)) {
                 Ok(msg)
             } else {
                 check_rights(store, resource, for_agent, Right::Write)
@@ -94,23 +389,154 @@ pub fn check_append(
     }
 }
 
-/// Recursively checks a Resource and its Parents for rights.
+/// Does the Agent have the right to _append_ to the (new) parent of the resource, or write rights for that parent?
+/// Unlike [check_append], write rights for the resource itself are not enough,
+/// since these can be set in the resource that is moved.
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
-#[tracing::instrument(skip(store, resource))]
-pub fn check_rights(
+pub fn check_append_to_parent(
     store: &impl Storelike,
     resource: &Resource,
     for_agent: &str,
-    right: Right,
 ) -> AtomicResult<String> {
+    let parent = resource.get_parent(store)?;
+    check_rights(store, &parent, for_agent, creation_right(resource))
+        .or_else(|_e| check_rights(store, &parent, for_agent, Right::Write))
+        .map_err(|e| {
+            crate::errors::AtomicError::unauthorized(format!(
+                "Moving {} to {} requires the {} right there: {}",
+                resource.get_subject(),
+                parent.get_subject(),
+                creation_right(resource),
+                e.message
+            ))
+        })
+}
+
+/// Agents can do anything with themselves and their children, and the server's default agent can do anything at all.
+fn always_allowed(store: &impl Storelike, resource: &Resource, for_agent: &str) -> Option<String> {
     if resource.get_subject() == for_agent {
-        return Ok("Agents can always edit themselves or their children.".into());
+        return Some("Agents can always edit themselves or their children.".into());
     }
     if let Ok(server_agent) = store.get_default_agent() {
         if server_agent.subject == for_agent {
-            return Ok("Server agent has root access, and can edit anything.".into());
+            return Some("Server agent has root access, and can edit anything.".into());
+        }
+    }
+    None
+}
+
+/// How a right applies to an Agent, see [effective_rights].
+#[derive(Debug)]
+pub struct EffectiveRight {
+    pub right: Right,
+    /// The outcome of [check_rights], which explains why the right is granted or denied.
+    pub result: AtomicResult<String>,
+    /// Subjects of the resource and its parents, up to the one whose rule decides.
+    /// Contains the entire parent tree if no rule applies.
+    pub path: Vec<String>,
+}
+
+/// Returns every [Right] the Agent has or lacks for the resource, and the path through the hierarchy that decides it.
+/// Useful for explaining why someone can or can't edit something, and for debugging rights.
+pub fn effective_rights(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+) -> AtomicResult<Vec<EffectiveRight>> {
+    let tree: Vec<Resource> = std::iter::once(resource.clone())
+        .chain(resource.get_parent_tree(store)?)
+        .collect();
+    let mut rights = Vec::new();
+    for right in Right::ALL {
+        let mut path = Vec::new();
+        for r in tree.iter() {
+            path.push(r.get_subject().clone());
+            let decides = always_allowed(store, r, for_agent).is_some()
+                || !matches!(rule_in(r, for_agent, right), Ok(None));
+            if decides {
+                break;
+            }
         }
+        rights.push(EffectiveRight {
+            right,
+            result: check_rights(store, resource, for_agent, right),
+            path,
+        });
+    }
+    Ok(rights)
+}
+
+/// Checks whether the resource itself explicitly refers to the agent or the public agent, see the precedence in [check_rights].
+/// Returns `None` if the rights of the parent apply.
+/// Throws if the right is denied.
+fn rule_in(resource: &Resource, for_agent: &str, right: Right) -> AtomicResult<Option<String>> {
+    let denied = rule_agents(resource, right, true)?;
+    let granted = rule_agents(resource, right, false)?;
+    let lists = |agents: &[String], agent: &str| agents.iter().any(|a| a == agent);
+    if lists(&denied, for_agent) {
+        return Err(crate::errors::AtomicError::unauthorized(format!(
+            "The {} right has been explicitly denied for {} in {}",
+            right,
+            for_agent,
+            resource.get_subject()
+        )));
+    }
+    if for_agent == urls::PUBLIC_AGENT && public_read(resource, right) == Some(false) {
+        return Err(crate::errors::AtomicError::unauthorized(format!(
+            "The {} right has been withheld from the PublicAgent in {}",
+            right,
+            resource.get_subject()
+        )));
+    }
+    if lists(&granted, for_agent) {
+        return Ok(Some(format!(
+            "Right has been explicitly set in {}",
+            resource.get_subject()
+        )));
+    }
+    if lists(&denied, urls::PUBLIC_AGENT) {
+        return Err(crate::errors::AtomicError::unauthorized(format!(
+            "The {} right has been denied for everyone in {}",
+            right,
+            resource.get_subject()
+        )));
+    }
+    if lists(&granted, urls::PUBLIC_AGENT) {
+        return Ok(Some(format!(
+            "PublicAgent has been granted rights in {}",
+            resource.get_subject()
+        )));
+    }
+    Ok(None)
+}
+
+/// Recursively checks a Resource and its Parents for rights.
+/// Rights are granted by `read`, `write`, `append` etc., and revoked by `denyRead` and `denyWrite`.
+/// `denyRead` revokes the `read` right, `denyWrite` revokes all other rights.
+/// `publicRead` grants (`true`) or withholds (`false`) the `read` right to the PublicAgent. Withholding it does not affect other Agents.
+/// The first rule that applies wins, in this order:
+///
+/// 1. Agents can always use their own resource, and the server's default agent can do anything.
+/// 2. A deny rule for the Agent in the resource, or `publicRead: false` for the PublicAgent.
+/// 3. A grant for the Agent in the resource.
+/// 4. A deny rule for the PublicAgent in the resource.
+/// 5. A grant for the PublicAgent in the resource.
+/// 6. The same rules for the parent, up to the Drive.
+///
+/// So rules on a resource override the rules inherited from its parents, and deny rules override grants in the same resource.
+/// This allows a confidential resource in a shared folder, or sharing a single child of a private folder.
+/// Throws if not allowed.
+/// Returns string with explanation if allowed.
+#[tracing::instrument(skip(store, resource))]
+pub fn check_rights(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+    right: Right,
+) -> AtomicResult<String> {
+    if let Some(msg) = always_allowed(store, resource, for_agent) {
+        return Ok(msg);
     }
 
     // Handle Commits.
@@ -122,35 +548,31 @@ pub fn check_rights(
                 check_rights(store, &target, for_agent, right)
             }
             Right::Write => Err("Commits cannot be edited.".into()),
-            Right::Append => Err("Commits cannot have children, you cannot Append to them.".into()),
+            Right::Append | Right::Comment => {
+                Err("Commits cannot have children, you cannot Append to them.".into())
+            }
+            Right::Invite => Err("Commits cannot be shared with Invites.".into()),
+            Right::Destroy => Err("Commits cannot be destroyed.".into()),
         };
     }
 
-    // Check if the resource's rights explicitly refers to the agent or the public agent
-    if let Ok(arr_val) = resource.get(&right.to_string()) {
-        for s in arr_val.to_subjects(None)? {
-            match s.as_str() {
-                urls::PUBLIC_AGENT => {
-                    return Ok(format!(
-                        "PublicAgent has been granted rights in {}",
-                        resource.get_subject()
-                    ))
-                }
-                agent => {
-                    if agent == for_agent {
-                        return Ok(format!(
-                            "Right has been explicitly set in {}",
-                            resource.get_subject()
-                        ));
-                    }
-                }
-            };
-        }
+    if let Some(msg) = rule_in(resource, for_agent, right)? {
+        return Ok(msg);
     }
 
-    // Try the parents recursively
+    // Try the parents recursively. Their outcome is the same for all of their children, so it is cached.
+    let cache = store.rights_cache();
+    if let (Some(cache), Ok(parent)) = (cache, resource.get(urls::PARENT)) {
+        if let Some(result) = cache.get(&parent.to_string(), for_agent, right) {
+            return result;
+        }
+    }
     if let Ok(parent) = resource.get_parent(store) {
-        check_rights(store, &parent, for_agent, right)
+        let result = check_rights(store, &parent, for_agent, right);
+        if let Some(cache) = cache {
+            cache.insert(parent.get_subject(), for_agent, right, &result);
+        }
+        result
     } else {
         if for_agent == urls::PUBLIC_AGENT {
             // resource has no parent and agent is not in rights array - check fails
@@ -158,6 +580,9 @@ pub fn check_rights(
                 Right::Read => "readable",
                 Right::Write => "editable",
                 Right::Append => "appendable",
+                Right::Comment => "open for comments",
+                Right::Invite => "shareable",
+                Right::Destroy => "destroyable",
             };
             return Err(crate::errors::AtomicError::unauthorized(format!(
                 "This resource is not publicly {}. Try signing in",
@@ -203,11 +628,193 @@ mod test {
         // assert!(resource.get(property).unwrap().to_string() == value.to_string());
     }
 
+    #[test]
+    fn flags_are_inherited() {
+        let mut parent = crate::Resource::new("https://localhost/parent".into());
+        parent.set_propval_unsafe(crate::urls::NO_INDEX.into(), Value::Boolean(true));
+        let mut child = crate::Resource::new("https://localhost/parent/child".into());
+        assert!(super::inherited_flag(
+            &child,
+            &[parent.clone()],
+            crate::urls::NO_INDEX
+        ));
+        assert!(!super::inherited_flag(
+            &child,
+            &[parent.clone()],
+            crate::urls::NO_EXPORT
+        ));
+
+        // Children can override the value of their parents
+        child.set_propval_unsafe(crate::urls::NO_INDEX.into(), Value::Boolean(false));
+        assert!(!super::inherited_flag(
+            &child,
+            &[parent],
+            crate::urls::NO_INDEX
+        ));
+    }
+
     #[test]
     fn display_right() {
         let read = super::Right::Read;
         assert_eq!(read.to_string(), super::urls::READ);
         let write = super::Right::Write;
         assert_eq!(write.to_string(), super::urls::WRITE);
+        let comment = super::Right::Comment;
+        assert_eq!(comment.to_string(), super::urls::COMMENT_RIGHT);
+    }
+
+    #[test]
+    fn deny_rules_override_inherited_rights() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let owner = "https://localhost/agents/owner";
+        let colleague = "https://localhost/agents/colleague";
+        let mut folder = crate::Resource::new("https://localhost/folder".into());
+        folder.set_propval_unsafe(
+            crate::urls::READ.into(),
+            vec![crate::urls::PUBLIC_AGENT.to_string()].into(),
+        );
+        folder.set_propval_unsafe(
+            crate::urls::WRITE.into(),
+            vec![owner.to_string(), colleague.to_string()].into(),
+        );
+        store.add_resource(&folder).unwrap();
+        let mut secret = crate::Resource::new("https://localhost/folder/secret".into());
+        secret.set_propval_unsafe(
+            crate::urls::PARENT.into(),
+            Value::AtomicUrl(folder.get_subject().into()),
+        );
+        secret.set_propval_unsafe(
+            crate::urls::DENY_READ.into(),
+            vec![crate::urls::PUBLIC_AGENT.to_string()].into(),
+        );
+        secret.set_propval_unsafe(crate::urls::READ.into(), vec![owner.to_string()].into());
+        secret.set_propval_unsafe(
+            crate::urls::DENY_WRITE.into(),
+            vec![colleague.to_string()].into(),
+        );
+        store.add_resource(&secret).unwrap();
+
+        super::check_read(&store, &folder, crate::urls::PUBLIC_AGENT).unwrap();
+        super::check_read(&store, &secret, crate::urls::PUBLIC_AGENT).unwrap_err();
+        super::check_read(&store, &secret, colleague).unwrap_err();
+        super::check_write(&store, &secret, colleague).unwrap_err();
+        super::check_read(&store, &secret, owner).unwrap();
+        super::check_write(&store, &secret, owner).unwrap();
+        super::check_write(&store, &folder, colleague).unwrap();
+
+        let readers = super::agents_with_right(&store, &secret, super::Right::Read).unwrap();
+        assert_eq!(readers, vec![owner.to_string()]);
+    }
+
+    #[test]
+    fn public_read_is_set_per_drive() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let member = "https://localhost/agents/member";
+        let mut public_drive = crate::Resource::new("https://public.localhost".into());
+        public_drive.set_propval_unsafe(crate::urls::PUBLIC_READ.into(), Value::Boolean(true));
+        store.add_resource(&public_drive).unwrap();
+        let mut private_drive = crate::Resource::new("https://private.localhost".into());
+        private_drive.set_propval_unsafe(crate::urls::PUBLIC_READ.into(), Value::Boolean(false));
+        private_drive.set_propval_unsafe(
+            crate::urls::READ.into(),
+            vec![member.to_string(), crate::urls::PUBLIC_AGENT.to_string()].into(),
+        );
+        store.add_resource(&private_drive).unwrap();
+        let child = |drive: &crate::Resource| {
+            let mut child = crate::Resource::new(format!("{}/child", drive.get_subject()));
+            child.set_propval_unsafe(
+                crate::urls::PARENT.into(),
+                Value::AtomicUrl(drive.get_subject().into()),
+            );
+            store.add_resource(&child).unwrap();
+            child
+        };
+        let public_child = child(&public_drive);
+        let private_child = child(&private_drive);
+
+        super::check_read(&store, &public_child, crate::urls::PUBLIC_AGENT).unwrap();
+        super::check_write(&store, &public_child, crate::urls::PUBLIC_AGENT).unwrap_err();
+        // `publicRead: false` overrides the PublicAgent in `read`, but not other Agents
+        super::check_read(&store, &private_child, crate::urls::PUBLIC_AGENT).unwrap_err();
+        super::check_read(&store, &private_child, member).unwrap();
+        assert_eq!(
+            super::agents_with_right(&store, &public_child, super::Right::Read).unwrap(),
+            vec![crate::urls::PUBLIC_AGENT.to_string()]
+        );
+        assert_eq!(
+            super::agents_with_right(&store, &private_child, super::Right::Read).unwrap(),
+            vec![member.to_string()]
+        );
+    }
+
+    #[test]
+    fn public_read_false_only_withholds_public_agent() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let member = "https://localhost/agents/member";
+        let mut drive = crate::Resource::new("https://localhost".into());
+        drive.set_propval_unsafe(crate::urls::PUBLIC_READ.into(), Value::Boolean(true));
+        drive.set_propval_unsafe(crate::urls::READ.into(), vec![member.to_string()].into());
+        store.add_resource(&drive).unwrap();
+        let mut folder = crate::Resource::new("https://localhost/folder".into());
+        folder.set_propval_unsafe(
+            crate::urls::PARENT.into(),
+            Value::AtomicUrl(drive.get_subject().into()),
+        );
+        folder.set_propval_unsafe(crate::urls::PUBLIC_READ.into(), Value::Boolean(false));
+        store.add_resource(&folder).unwrap();
+        let mut child = crate::Resource::new("https://localhost/folder/child".into());
+        child.set_propval_unsafe(
+            crate::urls::PARENT.into(),
+            Value::AtomicUrl(folder.get_subject().into()),
+        );
+        store.add_resource(&child).unwrap();
+
+        super::check_read(&store, &drive, crate::urls::PUBLIC_AGENT).unwrap();
+        for resource in [&folder, &child] {
+            super::check_read(&store, resource, crate::urls::PUBLIC_AGENT).unwrap_err();
+            super::check_read(&store, resource, member).unwrap();
+        }
+        assert_eq!(
+            super::agents_with_right(&store, &child, super::Right::Read).unwrap(),
+            vec![member.to_string()]
+        );
+    }
+
+    #[test]
+    fn stronger_rights_grant_weaker_ones() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let commenter = "https://localhost/agents/commenter";
+        let editor = "https://localhost/agents/editor";
+        let mut document = crate::Resource::new("https://localhost/document".into());
+        document.set_propval_unsafe(
+            crate::urls::COMMENT_RIGHT.into(),
+            vec![commenter.to_string()].into(),
+        );
+        document.set_propval_unsafe(crate::urls::WRITE.into(), vec![editor.to_string()].into());
+        store.add_resource(&document).unwrap();
+
+        let check = |agent: &str, right| super::check_rights(&store, &document, agent, right);
+        check(commenter, super::Right::Comment).unwrap();
+        check(commenter, super::Right::Write).unwrap_err();
+        check(commenter, super::Right::Append).unwrap_err();
+        check(commenter, super::Right::Destroy).unwrap_err();
+        check(editor, super::Right::Comment).unwrap();
+        check(editor, super::Right::Invite).unwrap();
+        check(editor, super::Right::Destroy).unwrap();
+
+        // Commenting is enough to add Messages, but not other resources
+        let mut message = crate::Resource::new("https://localhost/document/message".into());
+        message.set_propval_unsafe(
+            crate::urls::PARENT.into(),
+            Value::AtomicUrl(document.get_subject().into()),
+        );
+        message.set_class(crate::urls::MESSAGE);
+        super::check_append(&store, &message, commenter).unwrap();
+        message.set_class(crate::urls::PARAGRAPH);
+        super::check_append(&store, &message, commenter).unwrap_err();
     }
 }
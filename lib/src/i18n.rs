@@ -0,0 +1,207 @@
+//! Resources can have values in multiple languages, stored as nested resources in their [translations](crate::urls::TRANSLATIONS).
+//! Each translation has a [language](crate::urls::LANGUAGE) tag (like `nl` or `en-GB`) and the translated values, e.g.:
+//!
+//! ```json
+//! "https://atomicdata.dev/properties/translations": [{
+//!   "https://atomicdata.dev/properties/language": "nl",
+//!   "https://atomicdata.dev/properties/name": "Appel"
+//! }]
+//! ```
+//!
+//! The values of the resource itself are the fallback, for languages without a translation.
+//! [Storelike::get_resource_localized] returns a resource with the values of the preferred language,
+//! for example the languages from an `Accept-Language` header, see [parse_accept_language].
+
+use crate::{
+    errors::AtomicResult, resources::PropVals, urls, values::SubResource, Resource, Storelike,
+    Value,
+};
+
+/// Turns an `Accept-Language` header into a list of language tags, the most preferred first.
+/// Every tag with a region is followed by its primary language as a fallback, so `nl-BE` also matches `nl`.
+/// Tags with `q=0` and the wildcard `*` are left out.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut weighted: Vec<(f32, String)> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.split(';');
+            let tag = pieces.next()?.trim().to_lowercase();
+            let quality = pieces
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map(|q| q.parse().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((quality, tag))
+        })
+        .collect();
+    // A stable sort keeps the order of the header for equal weights
+    weighted.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut languages: Vec<String> = Vec::new();
+    for (_quality, tag) in weighted {
+        let primary = tag.split('-').next().unwrap_or_default().to_string();
+        for language in [tag, primary] {
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        }
+    }
+    languages
+}
+
+fn language_of(propvals: &PropVals) -> Option<String> {
+    propvals
+        .get(urls::LANGUAGE)
+        .map(|language| language.to_string().to_lowercase())
+}
+
+impl Resource {
+    /// The translations of this resource, with their language tags.
+    fn translations(&self) -> Vec<&PropVals> {
+        match self.get(urls::TRANSLATIONS) {
+            Ok(Value::ResourceArray(items)) => items
+                .iter()
+                .filter_map(|item| match item {
+                    SubResource::Nested(propvals) => Some(propvals),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the value of the Property in this language, without falling back to other languages.
+    pub fn get_translation(&self, language: &str, property: &str) -> Option<&Value> {
+        let language = language.to_lowercase();
+        self.translations()
+            .into_iter()
+            .find(|propvals| language_of(propvals).as_deref() == Some(&language))
+            .and_then(|propvals| propvals.get(property))
+    }
+
+    /// Sets the value of the Property in this language.
+    /// Adds a translation for the language, if the resource does not have one yet.
+    /// Checks the value against the Property, like [Resource::set_propval].
+    pub fn set_translation(
+        &mut self,
+        language: &str,
+        property: &str,
+        value: Value,
+        store: &impl Storelike,
+    ) -> AtomicResult<()> {
+        if property == urls::LANGUAGE || property == urls::TRANSLATIONS {
+            return Err(format!("{} can't be translated", property).into());
+        }
+        // Checks the value, without changing the resource
+        Resource::new(self.get_subject().into()).set_propval(
+            property.into(),
+            value.clone(),
+            store,
+        )?;
+        let language = language.to_lowercase();
+        let mut items = match self.get(urls::TRANSLATIONS) {
+            Ok(Value::ResourceArray(items)) => items.clone(),
+            _ => Vec::new(),
+        };
+        let existing = items.iter_mut().find_map(|item| match item {
+            SubResource::Nested(propvals)
+                if language_of(propvals).as_deref() == Some(&language) =>
+            {
+                Some(propvals)
+            }
+            _ => None,
+        });
+        match existing {
+            Some(propvals) => {
+                propvals.insert(property.into(), value);
+            }
+            None => {
+                let mut propvals = PropVals::new();
+                propvals.insert(urls::LANGUAGE.into(), Value::String(language));
+                propvals.insert(property.into(), value);
+                items.push(SubResource::Nested(propvals));
+            }
+        }
+        self.set_propval(urls::TRANSLATIONS.into(), items.into(), store)
+    }
+
+    /// Replaces the values of the resource with those of the first language in `languages` that has a translation.
+    /// Values that are not translated in that language are kept, so the resource itself is the fallback.
+    /// Returns the language that was used, if any.
+    pub fn localize(&mut self, languages: &[String]) -> Option<String> {
+        let translations: Vec<PropVals> = self.translations().into_iter().cloned().collect();
+        let (language, translation) = languages.iter().find_map(|preferred| {
+            translations
+                .iter()
+                .find(|t| language_of(t).as_deref() == Some(preferred.to_lowercase().as_str()))
+                .map(|t| (preferred.clone(), t))
+        })?;
+        for (prop, value) in translation {
+            if prop != urls::LANGUAGE {
+                self.set_propval_unsafe(prop.clone(), value.clone());
+            }
+        }
+        Some(language)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Store;
+
+    #[test]
+    fn parses_accept_language() {
+        assert_eq!(
+            parse_accept_language("fr;q=0.5, nl-BE, en;q=0.8, *;q=0.1, de;q=0"),
+            vec!["nl-be", "nl", "en", "fr"]
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    #[test]
+    fn localizes_with_fallback() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let mut resource = Resource::new("https://localhost/apple".into());
+        resource.set_propval_unsafe(urls::NAME.into(), Value::String("Apple".into()));
+        resource.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A fruit".into()));
+        resource
+            .set_translation("nl", urls::NAME, Value::String("Appel".into()), &store)
+            .unwrap();
+        resource
+            .set_translation("de", urls::NAME, Value::String("Apfel".into()), &store)
+            .unwrap();
+        resource
+            .set_translation(
+                "NL",
+                urls::DESCRIPTION,
+                Value::Markdown("Een vrucht".into()),
+                &store,
+            )
+            .unwrap();
+        resource
+            .set_translation("nl", urls::NAME, Value::Integer(1), &store)
+            .unwrap_err();
+        assert_eq!(
+            resource
+                .get_translation("de", urls::NAME)
+                .unwrap()
+                .to_string(),
+            "Apfel"
+        );
+        store.add_resource(&resource).unwrap();
+
+        let languages = parse_accept_language("nl-BE,en;q=0.5");
+        let localized = store
+            .get_resource_localized("https://localhost/apple", false, None, &languages)
+            .unwrap();
+        assert_eq!(localized.get(urls::NAME).unwrap().to_string(), "Appel");
+        assert_eq!(
+            localized.get(urls::DESCRIPTION).unwrap().to_string(),
+            "Een vrucht"
+        );
+
+        let mut fallback = resource.clone();
+        assert_eq!(fallback.localize(&parse_accept_language("fr")), None);
+        assert_eq!(fallback.get(urls::NAME).unwrap().to_string(), "Apple");
+    }
+}
@@ -64,16 +64,22 @@ pub mod authentication;
 pub mod client;
 pub mod collections;
 pub mod commit;
+pub mod computed;
 #[cfg(feature = "config")]
 pub mod config;
+pub mod convert;
 pub mod datatype;
 #[cfg(feature = "db")]
 pub mod db;
+pub mod decimal;
 #[cfg(feature = "db")]
 pub mod endpoints;
 pub mod errors;
 pub mod hierarchy;
+pub mod i18n;
 pub mod mapping;
+pub mod normalize;
+pub mod order;
 pub mod parse;
 #[cfg(feature = "db")]
 pub mod plugins;
@@ -85,6 +91,7 @@ pub mod store;
 pub mod storelike;
 #[cfg(test)]
 mod test_utils;
+pub mod units;
 pub mod urls;
 pub mod utils;
 pub mod validate;
@@ -1,6 +1,7 @@
 //! Because writing full URLs is error prone and time consuming, we map URLs to shortnames.
 //! These are often user-specific.
 //! This section provides tools to store, share and resolve these Mappings.
+//! Bookmarks can also be used as namespace prefixes: with `schema=https://schema.org/`, `schema:name` resolves to `https://schema.org/name`.
 
 use crate::errors::AtomicResult;
 use std::collections::hash_map::IntoIter;
@@ -18,7 +19,7 @@ impl Mapping {
         Mapping { hashmap }
     }
 
-    /// Checks if the input string is a Mapping or a valid URL.
+    /// Checks if the input string is a Mapping, a prefixed name (`prefix:name`) or a valid URL.
     /// Returns Some if it is valid.
     /// If it is neither, a None is returned.
     pub fn try_mapping_or_url(&self, mapping_or_url: &str) -> Option<String> {
@@ -29,16 +30,33 @@ impl Mapping {
                 if is_url(mapping_or_url) {
                     return Some(mapping_or_url.into());
                 }
-                None
+                self.expand_prefix(mapping_or_url)
             }
         }
     }
 
+    /// Expands a prefixed name such as `schema:name`, using the bookmark of the prefix as namespace.
+    /// Returns None if there is no colon, or if the prefix is not a bookmark.
+    pub fn expand_prefix(&self, prefixed: &str) -> Option<String> {
+        let (prefix, name) = prefixed.split_once(':')?;
+        if name.is_empty() {
+            return None;
+        }
+        self.get(prefix)
+            .filter(|namespace| is_url(namespace))
+            .map(|namespace| format!("{}{}", namespace, name))
+    }
+
     /// Add a new bookmark to the store
     pub fn insert(&mut self, shortname: String, url: String) {
         self.hashmap.insert(shortname, url);
     }
 
+    /// Removes a bookmark, returns its URL if it existed
+    pub fn remove(&mut self, shortname: &str) -> Option<String> {
+        self.hashmap.remove(shortname)
+    }
+
     /// Checks if the bookmark exists, returns it
     pub fn get(&self, bookmark: &str) -> Option<&String> {
         self.hashmap.get(bookmark)
@@ -62,7 +80,7 @@ impl Mapping {
                 Some('#') => {}
                 Some(' ') => {}
                 Some(_) => {
-                    let split: Vec<&str> = line.split('=').collect();
+                    let split: Vec<&str> = line.splitn(2, '=').collect();
                     if split.len() == 2 {
                         self.hashmap
                             .insert(String::from(split[0]), String::from(split[1]));
@@ -114,3 +132,29 @@ impl IntoIterator for Mapping {
         self.hashmap.into_iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolves_prefixes() {
+        let mut mapping = Mapping::init();
+        mapping.populate().unwrap();
+        mapping.insert("schema".into(), "https://schema.org/".into());
+        assert_eq!(
+            mapping.try_mapping_or_url("schema:name").unwrap(),
+            "https://schema.org/name"
+        );
+        assert_eq!(
+            mapping
+                .try_mapping_or_url("properties:description")
+                .unwrap(),
+            crate::urls::DESCRIPTION
+        );
+        assert!(mapping.try_mapping_or_url("unknown:name").is_none());
+        assert!(mapping.try_mapping_or_url("schema:").is_none());
+        assert_eq!(mapping.remove("schema").unwrap(), "https://schema.org/");
+        assert!(mapping.try_mapping_or_url("schema:name").is_none());
+    }
+}
@@ -0,0 +1,175 @@
+//! Normalizes values when they are set by a [Commit](crate::Commit) or parsed from user input, so values that only differ cosmetically are stored the same way.
+//! This keeps lookups in the value index and `previousCommit` checks from failing over trailing whitespace or a default port in a URL.
+//! Which normalizers are used depends on the store, see [crate::Storelike::normalizers].
+//!
+//! Values in Commits themselves are never normalized, because that would break their signatures.
+
+use std::borrow::Cow;
+
+use crate::{datatype::DataType, values::SubResource, Value};
+
+/// The normalizations that are applied to values when they are set.
+/// All of them are enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalizers {
+    /// Removes whitespace at the start and end of Strings.
+    pub trim_strings: bool,
+    /// Lowercases Slugs, so `My-Page` can be set as `my-page`.
+    pub lowercase_slugs: bool,
+    /// Lowercases the host of URLs and removes default ports and trailing slashes. See [canonicalize_url].
+    pub canonicalize_urls: bool,
+}
+
+impl Default for Normalizers {
+    fn default() -> Self {
+        Normalizers {
+            trim_strings: true,
+            lowercase_slugs: true,
+            canonicalize_urls: true,
+        }
+    }
+}
+
+impl Normalizers {
+    /// Keeps all values as they are.
+    pub fn none() -> Self {
+        Normalizers {
+            trim_strings: false,
+            lowercase_slugs: false,
+            canonicalize_urls: false,
+        }
+    }
+
+    /// Normalizes a string before it is parsed as a value of this datatype.
+    /// Useful for slugs, which can't be parsed before they are lowercased.
+    pub fn normalize_str<'a>(&self, value: &'a str, datatype: &DataType) -> Cow<'a, str> {
+        match datatype {
+            DataType::String if self.trim_strings => Cow::Borrowed(value.trim()),
+            DataType::Slug if self.lowercase_slugs => Cow::Owned(value.trim().to_lowercase()),
+            DataType::AtomicUrl if self.canonicalize_urls => Cow::Owned(canonicalize_url(value)),
+            _ => Cow::Borrowed(value),
+        }
+    }
+
+    /// Parses a string as a value of this datatype, after normalizing it.
+    pub fn parse(&self, value: &str, datatype: &DataType) -> crate::errors::AtomicResult<Value> {
+        Value::new(&self.normalize_str(value, datatype), datatype)
+    }
+
+    /// Normalizes a value, including the subjects in Resource Arrays and the values of nested resources.
+    pub fn normalize(&self, value: Value) -> Value {
+        match value {
+            Value::String(s) if self.trim_strings && s.trim().len() != s.len() => {
+                Value::String(s.trim().into())
+            }
+            Value::Slug(s) if self.lowercase_slugs => Value::Slug(s.to_lowercase()),
+            Value::AtomicUrl(url) if self.canonicalize_urls => {
+                Value::AtomicUrl(canonicalize_url(&url))
+            }
+            Value::ResourceArray(items) => Value::ResourceArray(
+                items
+                    .into_iter()
+                    .map(|item| self.normalize_sub_resource(item))
+                    .collect(),
+            ),
+            Value::NestedResource(nested) => {
+                Value::NestedResource(self.normalize_sub_resource(nested))
+            }
+            other => other,
+        }
+    }
+
+    fn normalize_sub_resource(&self, sub_resource: SubResource) -> SubResource {
+        match sub_resource {
+            SubResource::Subject(url) if self.canonicalize_urls => {
+                SubResource::Subject(canonicalize_url(&url))
+            }
+            SubResource::Nested(propvals) => SubResource::Nested(
+                propvals
+                    .into_iter()
+                    .map(|(prop, value)| (prop, self.normalize(value)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+}
+
+/// Lowercases the host, removes the default port and removes trailing slashes from the path.
+/// The trailing slash of a URL without a path (e.g. `https://example.com/`) is kept as it is, because servers use both forms for their root.
+/// Strings that are not HTTP URLs (such as `local:` identifiers) are returned unchanged.
+pub fn canonicalize_url(url: &str) -> String {
+    if !url.starts_with("http") {
+        return url.to_string();
+    }
+    let Ok(parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    let canonical = parsed.to_string();
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return canonical;
+    }
+    if parsed.path() == "/" && url.ends_with('/') {
+        return canonical;
+    }
+    canonical.trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn canonicalizes_urls() {
+        assert_eq!(
+            canonicalize_url("https://Example.com:443/things/"),
+            "https://example.com/things"
+        );
+        assert_eq!(
+            canonicalize_url("http://localhost:9883"),
+            "http://localhost:9883"
+        );
+        assert_eq!(
+            canonicalize_url("http://example.com/"),
+            "http://example.com/"
+        );
+        assert_eq!(
+            canonicalize_url("http://example.com:80"),
+            "http://example.com"
+        );
+        assert_eq!(
+            canonicalize_url("https://example.com/search/?q=a"),
+            "https://example.com/search/?q=a"
+        );
+        assert_eq!(canonicalize_url("local:abc"), "local:abc");
+    }
+
+    #[test]
+    fn normalizes_values() {
+        let normalizers = Normalizers::default();
+        assert_eq!(
+            normalizers
+                .normalize(Value::String("  Hello ".into()))
+                .to_string(),
+            "Hello"
+        );
+        assert_eq!(
+            normalizers
+                .parse("My-Page", &DataType::Slug)
+                .unwrap()
+                .to_string(),
+            "my-page"
+        );
+        let array = normalizers.normalize(vec!["https://example.com:443/a/"].into());
+        assert_eq!(
+            array.to_subjects(None).unwrap(),
+            vec!["https://example.com/a".to_string()]
+        );
+        assert_eq!(
+            Normalizers::none()
+                .normalize(Value::String(" kept ".into()))
+                .to_string(),
+            " kept "
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Manual ordering of the children of a Resource, e.g. the cards in a kanban column or the items in a list.
+//! Every ordered child has a `sortKey`: a string that sorts (lexicographically) between the keys of its neighbours.
+//! Commits can move a Resource using `moveBefore` or `moveAfter` with the subject of a sibling,
+//! and the server calculates a new `sortKey` in between. Only the moved Resource changes,
+//! so there is no need to rewrite a ResourceArray with all children on every drag.
+//! Children without a `sortKey` come before the ordered ones, just like in the query index of a Db.
+//! Collections get the manual order with `sort_by={sortKey}`, and the `children` of a Resource are always in this order.
+
+use crate::{errors::AtomicResult, storelike::Query, urls, Resource, Storelike};
+
+/// The characters that are used in sort keys, in ascending order.
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn digit_index(digit: u8) -> AtomicResult<usize> {
+    DIGITS
+        .iter()
+        .position(|d| *d == digit)
+        .ok_or_else(|| format!("Invalid character '{}' in sortKey", digit as char).into())
+}
+
+/// Returns a key that sorts between `before` and `after`. `None` means the start or the end of the list.
+/// The keys are interpreted as base-62 fractions, so there is always room for another key in between.
+/// Generated keys never end with a `0`, so that keys can also be created before them.
+pub fn key_between(before: Option<&str>, after: Option<&str>) -> AtomicResult<String> {
+    let before = before.unwrap_or_default();
+    if let Some(after) = after {
+        if before >= after {
+            return Err(format!("sortKey '{}' should come before '{}'", before, after).into());
+        }
+    }
+    midpoint(before.as_bytes(), after.map(str::as_bytes))
+}
+
+fn midpoint(before: &[u8], after: Option<&[u8]>) -> AtomicResult<String> {
+    if let Some(after) = after {
+        // Keep the common prefix, where a missing digit in `before` counts as a zero.
+        let common = after
+            .iter()
+            .enumerate()
+            .take_while(|(i, digit)| before.get(*i).unwrap_or(&DIGITS[0]) == *digit)
+            .count();
+        if common > 0 {
+            let rest = midpoint(
+                before.get(common..).unwrap_or_default(),
+                Some(&after[common..]),
+            )?;
+            return Ok(format!(
+                "{}{}",
+                String::from_utf8_lossy(&after[..common]),
+                rest
+            ));
+        }
+    }
+    let digit_before = match before.first() {
+        Some(digit) => digit_index(*digit)?,
+        None => 0,
+    };
+    let digit_after = match after.and_then(|a| a.first()) {
+        Some(digit) => digit_index(*digit)?,
+        None => DIGITS.len(),
+    };
+    if digit_after - digit_before > 1 {
+        return Ok((DIGITS[(digit_before + digit_after) / 2] as char).to_string());
+    }
+    match after {
+        // The first digit of `after` is enough, since the rest of it is larger than zero.
+        Some(after) if after.len() > 1 => Ok((after[0] as char).to_string()),
+        _ => {
+            let rest = midpoint(before.get(1..).unwrap_or_default(), None)?;
+            Ok(format!("{}{}", DIGITS[digit_before] as char, rest))
+        }
+    }
+}
+
+/// Sorts Resources by their `sortKey`. Resources without one come first, sorted by subject.
+pub fn sort_by_key(resources: &mut [Resource]) {
+    resources.sort_by(|a, b| {
+        let key_a = a.get(urls::SORT_KEY).ok().map(|k| k.to_string());
+        let key_b = b.get(urls::SORT_KEY).ok().map(|k| k.to_string());
+        match (key_a, key_b) {
+            (Some(key_a), Some(key_b)) => key_a.cmp(&key_b),
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (None, None) => std::cmp::Ordering::Equal,
+        }
+        .then_with(|| a.get_subject().cmp(b.get_subject()))
+    });
+}
+
+/// Returns the children of the parent, in their manual order.
+pub fn ordered_children(store: &impl Storelike, parent: &str) -> AtomicResult<Vec<Resource>> {
+    let mut query = Query::new_prop_val(urls::PARENT, parent);
+    query.include_nested = true;
+    let mut children = store.query(&query)?.resources;
+    sort_by_key(&mut children);
+    Ok(children)
+}
+
+/// Calculates the new `sortKey` for a Resource that moves before or after one of its siblings.
+pub fn move_key(
+    store: &impl Storelike,
+    resource: &Resource,
+    move_before: Option<&str>,
+    move_after: Option<&str>,
+) -> AtomicResult<String> {
+    let sibling_subject = match (move_before, move_after) {
+        (Some(sibling), None) | (None, Some(sibling)) => sibling,
+        _ => {
+            return Err(
+                "A Commit can either move a resource before or after a sibling, not both".into(),
+            )
+        }
+    };
+    if sibling_subject == resource.get_subject() {
+        return Err("A resource can't be moved next to itself".into());
+    }
+    let parent = resource
+        .get(urls::PARENT)
+        .map_err(|_e| "Only resources with a parent can be moved")?
+        .to_string();
+    let siblings: Vec<Resource> = ordered_children(store, &parent)?
+        .into_iter()
+        .filter(|child| child.get_subject() != resource.get_subject())
+        .collect();
+    let position = siblings
+        .iter()
+        .position(|s| s.get_subject() == sibling_subject)
+        .ok_or_else(|| {
+            format!(
+                "{} is not a sibling of {}",
+                sibling_subject,
+                resource.get_subject()
+            )
+        })?;
+    let keys: Vec<Option<String>> = siblings
+        .iter()
+        .map(|s| s.get(urls::SORT_KEY).ok().map(|k| k.to_string()))
+        .collect();
+    let first_key = keys.iter().flatten().next().map(String::as_str);
+    let (before, after) = match (&keys[position], move_before.is_some()) {
+        // Resources without a key come before the ordered ones, so the moved resource goes to the start of those.
+        (None, _) => (None, first_key),
+        (Some(key), true) => {
+            let previous = position.checked_sub(1).and_then(|p| keys[p].as_deref());
+            (previous, Some(key.as_str()))
+        }
+        (Some(key), false) => {
+            let next = keys.get(position + 1).and_then(|k| k.as_deref());
+            (Some(key.as_str()), next)
+        }
+    };
+    key_between(before, after)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys_sort_between() {
+        let first = key_between(None, None).unwrap();
+        let before = key_between(None, Some(&first)).unwrap();
+        let after = key_between(Some(&first), None).unwrap();
+        assert!(before < first && first < after);
+
+        // Keep inserting at the same place
+        let mut low = first.clone();
+        let high = after;
+        for _ in 0..100 {
+            let mid = key_between(Some(&low), Some(&high)).unwrap();
+            assert!(low < mid && mid < high, "{} {} {}", low, mid, high);
+            assert!(!mid.ends_with('0'));
+            low = mid;
+        }
+        let mut high = first;
+        for _ in 0..100 {
+            let mid = key_between(Some(&before), Some(&high)).unwrap();
+            assert!(before < mid && mid < high, "{} {} {}", before, mid, high);
+            high = mid;
+        }
+
+        key_between(Some("b"), Some("a")).unwrap_err();
+        key_between(Some("a"), Some("a")).unwrap_err();
+    }
+
+    #[test]
+    fn moves_children() {
+        let store = crate::test_utils::init_store();
+        let parent = format!("{}/board", store.get_server_url());
+        let mut board = Resource::new(parent.clone());
+        board
+            .set_propval_string(urls::NAME.into(), "Board", &store)
+            .unwrap();
+        board.save_locally(&store).unwrap();
+        let mut cards = Vec::new();
+        for name in ["a", "b", "c", "d"] {
+            let mut card = Resource::new(format!("{}/{}", parent, name));
+            card.set_propval_string(urls::PARENT.into(), &parent, &store)
+                .unwrap();
+            card.save_locally(&store).unwrap();
+            cards.push(card);
+        }
+        let order = || -> Vec<String> {
+            ordered_children(&store, &parent)
+                .unwrap()
+                .iter()
+                .map(|c| c.get_subject().rsplit('/').next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(order(), ["a", "b", "c", "d"]);
+
+        // Resources that were never moved come first
+        cards[0].move_after(&format!("{}/d", parent));
+        cards[0].save_locally(&store).unwrap();
+        assert_eq!(order(), ["b", "c", "d", "a"]);
+        cards[3].move_after(&format!("{}/a", parent));
+        cards[3].save_locally(&store).unwrap();
+        assert_eq!(order(), ["b", "c", "a", "d"]);
+        cards[1].move_before(&format!("{}/d", parent));
+        cards[1].save_locally(&store).unwrap();
+        assert_eq!(order(), ["c", "a", "b", "d"]);
+        cards[3].move_before(&format!("{}/a", parent));
+        cards[3].save_locally(&store).unwrap();
+        assert_eq!(order(), ["c", "d", "a", "b"]);
+
+        // Only the moved resource changes
+        let a = store.get_resource(cards[0].get_subject()).unwrap();
+        assert_eq!(
+            a.get(urls::SORT_KEY).unwrap().to_string(),
+            cards[0].get(urls::SORT_KEY).unwrap().to_string()
+        );
+        let mut board = store.get_resource(&parent).unwrap();
+        crate::hierarchy::add_children(&store, &mut board).unwrap();
+        assert_eq!(
+            board
+                .get(urls::CHILDREN)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap()[0],
+            format!("{}/c", parent)
+        );
+
+        cards[2].move_after(&format!("{}/nothing", parent));
+        cards[2].save_locally(&store).unwrap_err();
+    }
+}
@@ -12,6 +12,7 @@ pub fn parse_json_array(string: &str) -> AtomicResult<Vec<String>> {
     Ok(vector)
 }
 
+use serde::Serialize;
 use serde_json::Map;
 
 /// Options for parsing (JSON-AD) resources.
@@ -129,6 +130,139 @@ pub fn parse_json_ad_string(
     Ok(vec)
 }
 
+/// A resource that could not be imported by [parse_json_ad_string_tolerant].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportFailure {
+    /// Line in the input where the resource starts.
+    pub line: usize,
+    /// The `@id` or `localId` of the resource, if it could be found.
+    pub subject: Option<String>,
+    pub error: String,
+}
+
+/// How often [parse_json_ad_string_tolerant_with_progress] reports its progress, in resources.
+pub const PROGRESS_INTERVAL: usize = 100;
+
+/// The outcome of an import that skips invalid resources.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportReport {
+    /// Amount of resources that have been parsed (and saved, depending on [SaveOpts]).
+    pub imported: usize,
+    pub failures: Vec<ImportFailure>,
+}
+
+/// Parses a JSON-AD string like [parse_json_ad_string], but skips resources that are invalid or can't be saved.
+/// Every resource in the root array is parsed separately, so even a syntax error only affects the resource it occurs in.
+/// Returns the parsed resources and a report of the failures, so the failed resources can be fixed and imported again.
+#[tracing::instrument(skip(store, string))]
+pub fn parse_json_ad_string_tolerant(
+    string: &str,
+    store: &impl Storelike,
+    parse_opts: &ParseOpts,
+) -> AtomicResult<(Vec<Resource>, ImportReport)> {
+    parse_json_ad_string_tolerant_with_progress(string, store, parse_opts, &mut |_, _| {})
+}
+
+/// Parses a JSON-AD string like [parse_json_ad_string_tolerant],
+/// and calls `on_progress` with the report so far and the total amount of resources every [PROGRESS_INTERVAL] resources.
+#[tracing::instrument(skip(store, string, on_progress))]
+pub fn parse_json_ad_string_tolerant_with_progress(
+    string: &str,
+    store: &impl Storelike,
+    parse_opts: &ParseOpts,
+    on_progress: &mut dyn FnMut(&ImportReport, usize),
+) -> AtomicResult<(Vec<Resource>, ImportReport)> {
+    let mut resources = Vec::new();
+    let mut report = ImportReport::default();
+    let items = split_json_array(string)?;
+    let total = items.len();
+    for (index, (line, item)) in items.into_iter().enumerate() {
+        if index > 0 && index % PROGRESS_INTERVAL == 0 {
+            on_progress(&report, total);
+        }
+        let parsed = serde_json::from_str::<Map<String, serde_json::Value>>(item)
+            .map_err(|e| AtomicError::parse_error(&format!("Invalid JSON: {}", e), None, None))
+            .and_then(|obj| json_ad_object_to_resource(obj, store, parse_opts));
+        match parsed {
+            Ok(resource) => {
+                report.imported += 1;
+                resources.push(resource);
+            }
+            Err(e) => report.failures.push(ImportFailure {
+                line,
+                subject: e.subject.clone().or_else(|| find_identifier(item)),
+                error: e.message,
+            }),
+        }
+    }
+    Ok((resources, report))
+}
+
+/// Splits a JSON array into the source of its items, each with the line number where it starts.
+/// Does not parse the items, so invalid items don't affect the others.
+/// A single object is returned as the only item.
+pub(crate) fn split_json_array(string: &str) -> AtomicResult<Vec<(usize, &str)>> {
+    let trimmed = string.trim_start();
+    let offset = string.len() - trimmed.len();
+    let line_at = |pos: usize| string[..pos].matches('\n').count() + 1;
+    if trimmed.starts_with('{') {
+        return Ok(vec![(line_at(offset), trimmed.trim_end())]);
+    }
+    if !trimmed.starts_with('[') {
+        return Err("Root JSON element must be an object or array.".into());
+    }
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut item_start = None;
+    for (i, c) in string.char_indices().skip_while(|(i, _c)| *i < offset) {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            _ => {}
+        }
+        if depth == 1 && c == '[' && item_start.is_none() && items.is_empty() {
+            // The opening bracket of the root array
+            continue;
+        }
+        let ends_item = (depth == 1 && c == ',') || (depth == 0 && c == ']');
+        if ends_item {
+            if let Some(start) = item_start.take() {
+                items.push((line_at(start), string[start..i].trim_end()));
+            }
+            if depth == 0 {
+                return Ok(items);
+            }
+        } else if item_start.is_none() && !c.is_whitespace() {
+            item_start = Some(i);
+        }
+    }
+    Err("Unexpected end of JSON array, is a closing bracket missing?".into())
+}
+
+/// Finds the `@id` or `localId` in the source of a resource that could not be parsed.
+fn find_identifier(item: &str) -> Option<String> {
+    ["\"@id\"".to_string(), format!("\"{}\"", urls::LOCAL_ID)]
+        .iter()
+        .find_map(|key| {
+            let after_key = &item[item.find(key.as_str())? + key.len()..];
+            let value = after_key.trim_start().strip_prefix(':')?.trim_start();
+            let value = value.strip_prefix('"')?;
+            Some(value[..value.find('"')?].to_string())
+        })
+}
+
 /// Parse a single Json AD string that represents an incoming Commit.
 /// WARNING: Does not match all props to datatypes (in Nested Resources), so it could result in invalid data,
 /// if the input data does not match the required datatypes.
@@ -144,7 +278,12 @@ pub fn parse_json_ad_commit_resource(
         .to_string();
     let subject = format!("{}/commits/{}", store.get_server_url(), signature);
     let mut resource = Resource::new(subject);
-    let propvals = match parse_json_ad_map_to_resource(json, store, &ParseOpts::default())? {
+    // The Commit is only stored once it's applied, which adds it with the subject above
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        ..Default::default()
+    };
+    let propvals = match parse_json_ad_map_to_resource(json, store, &parse_opts)? {
         SubResource::Resource(r) => r.into_propvals(),
         SubResource::Nested(pv) => pv,
         SubResource::Subject(_) => {
@@ -354,6 +493,37 @@ fn parse_json_ad_map_to_resource(
     }
 }
 
+/// Splits CSV text into rows of cells. Supports quoted cells, which can contain commas, newlines and escaped `""` quotes.
+pub fn parse_csv(data: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => row.push(std::mem::take(&mut cell)),
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            '\r' if !in_quotes => {}
+            other => cell.push(other),
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|cell| !cell.trim().is_empty()));
+    rows
+}
+
 fn generate_id_from_local_id(importer_subject: &str, local_id: &str) -> String {
     format!("{}/{}", importer_subject, local_id)
 }
@@ -579,6 +749,56 @@ mod test {
         );
     }
 
+    #[test]
+    fn import_tolerant_skips_invalid() {
+        let (store, importer) = create_store_and_importer();
+
+        let json = r#"[
+            {
+                "https://atomicdata.dev/properties/localId": "valid-1",
+                "https://atomicdata.dev/properties/name": "Valid, with a \"quoted\" [bracket]"
+            },
+            {
+                "https://atomicdata.dev/properties/localId": "broken-syntax",
+                "https://atomicdata.dev/properties/name": "Missing comma"
+                "https://atomicdata.dev/properties/description": "Oops"
+            },
+            {
+                "@id": "https://localhost/invalid-value",
+                "https://atomicdata.dev/properties/isA": "not a url"
+            },
+            {
+                "https://atomicdata.dev/properties/localId": "valid-2",
+                "https://atomicdata.dev/properties/name": "Valid"
+            }
+        ]"#;
+
+        let parse_opts = ParseOpts {
+            save: SaveOpts::Commit,
+            signer: Some(store.get_default_agent().unwrap()),
+            for_agent: None,
+            overwrite_outside: false,
+            importer: Some(importer.clone()),
+        };
+
+        // The strict importer fails entirely
+        store.import(json, &parse_opts).unwrap_err();
+
+        let report = store.import_tolerant(json, &parse_opts).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.failures.len(), 2);
+        assert_eq!(report.failures[0].line, 6);
+        assert_eq!(report.failures[0].subject.as_deref(), Some("broken-syntax"));
+        assert_eq!(report.failures[1].line, 11);
+        assert_eq!(
+            report.failures[1].subject.as_deref(),
+            Some("https://localhost/invalid-value")
+        );
+        store
+            .get_resource(&generate_id_from_local_id(&importer, "valid-2"))
+            .unwrap();
+    }
+
     #[test]
     fn import_resource_malicious() {
         let (store, importer) = create_store_and_importer();
@@ -0,0 +1,229 @@
+/*!
+# Admin API for Agents
+Lets admins moderate the Agents that have registered on this server.
+Admins are Agents with write rights to the Drive of the server, such as the default Agent.
+
+- `GET /admin/agents` lists all Agents. Add `disabled=true` to only show disabled Agents.
+- `POST /admin/agents?agent={subject}&action=disable` rejects the Commits and sessions of the Agent. Use `action=enable` to undo this.
+- `POST /admin/agents?agent={subject}&action=delete` destroys the Agent and all resources it created.
+*/
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    storelike::Query,
+    urls, AtomicError, Db, Resource, Storelike, Value,
+};
+
+pub fn agents_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/admin/agents".to_string(),
+        params: [
+            urls::AGENT_DISABLED.to_string(),
+        ].into(),
+        description: "Lists the Agents of this server. Only available to admins of the Drive. POST with an `agent` and an `action` query param to moderate an Agent. The action can be `disable`, `enable` or `delete`. Deleting an Agent destroys all resources it created.".to_string(),
+        shortname: "agents".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_post),
+    }
+}
+
+/// Returns an error if the Agent has no write rights to the Drive of the server.
+/// Passing no Agent skips the check, which is what happens internally.
+fn check_admin(store: &Db, for_agent: Option<&str>) -> AtomicResult<()> {
+    if let Some(agent) = for_agent {
+        let drive = store.get_resource(store.get_server_url())?;
+        check_write(store, &drive, agent).map_err(|_e| {
+            AtomicError::unauthorized(format!(
+                "Only admins of {} can manage Agents.",
+                drive.get_subject()
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument]
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    check_admin(store, for_agent)?;
+    let mut only_disabled = false;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "disabled" | urls::AGENT_DISABLED => only_disabled = v == "true",
+            other => return Err(format!("Invalid query param: {}", other).into()),
+        }
+    }
+    list_agents(store, &subject, only_disabled)
+}
+
+#[tracing::instrument]
+fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        body: _,
+    } = context;
+    check_admin(store, for_agent)?;
+    let mut agent = None;
+    let mut action = None;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "agent" => agent = Some(v.to_string()),
+            "action" => action = Some(v.to_string()),
+            other => return Err(format!("Invalid query param: {}", other).into()),
+        }
+    }
+    let agent = agent.ok_or("No `agent` query param given")?;
+    if agent == store.get_default_agent()?.subject {
+        return Err("The default Agent of the server can not be disabled or deleted".into());
+    }
+    match action.as_deref() {
+        Some("disable") => set_disabled(store, &agent, true)?,
+        Some("enable") => set_disabled(store, &agent, false)?,
+        Some("delete") => {
+            delete_agent(store, &agent)?;
+        }
+        _ => {
+            return Err(
+                "Invalid or missing `action` query param. Use `disable`, `enable` or `delete`."
+                    .into(),
+            )
+        }
+    }
+    let mut list_url = subject.clone();
+    list_url.set_query(None);
+    list_agents(store, &list_url, false)
+}
+
+fn list_agents(store: &Db, subject: &url::Url, only_disabled: bool) -> AtomicResult<Resource> {
+    let result = store.query(&Query::new_class(urls::AGENT))?;
+    let members: Vec<String> = result
+        .resources
+        .iter()
+        .filter(|agent| !only_disabled || crate::agents::is_disabled(store, agent.get_subject()))
+        .map(|agent| agent.get_subject().to_string())
+        .collect();
+
+    let mut resource = agents_endpoint().to_resource(store)?;
+    resource.set_subject(subject.to_string());
+    resource.set_propval_unsafe(urls::IS_A.into(), vec![urls::COLLECTION.to_string()].into());
+    resource.set_propval_string(urls::NAME.into(), "Agents", store)?;
+    resource.set_propval(
+        urls::COLLECTION_MEMBER_COUNT.into(),
+        Value::Integer(members.len() as i64),
+        store,
+    )?;
+    resource.set_propval(urls::COLLECTION_MEMBERS.into(), members.into(), store)?;
+    Ok(resource)
+}
+
+pub(crate) fn get_agent(store: &Db, agent: &str) -> AtomicResult<Resource> {
+    let resource = store.get_resource(agent)?;
+    let is_agent = resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::AGENT))
+        .unwrap_or(false);
+    if !is_agent {
+        return Err(format!("{} is not an Agent", agent).into());
+    }
+    Ok(resource)
+}
+
+/// Disables or enables an Agent.
+/// Disabled Agents can't sign Commits or authenticate requests.
+pub fn set_disabled(store: &Db, agent: &str, disabled: bool) -> AtomicResult<()> {
+    let mut resource = get_agent(store, agent)?;
+    resource.set_propval(urls::AGENT_DISABLED.into(), Value::Boolean(disabled), store)?;
+    resource.save_locally(store)?;
+    Ok(())
+}
+
+/// Destroys the Agent, and every resource for which it signed the first Commit.
+/// Returns the subjects of the destroyed resources, including the Agent.
+pub fn delete_agent(store: &Db, agent: &str) -> AtomicResult<Vec<String>> {
+    let mut agent_resource = get_agent(store, agent)?;
+    let mut destroyed = Vec::new();
+    for target in super::personal_data::created_by(store, agent)? {
+        // Resources can be destroyed already
+        if let Ok(mut resource) = store.get_resource(&target) {
+            resource.destroy(store)?;
+            destroyed.push(target);
+        }
+    }
+    agent_resource.destroy(store)?;
+    destroyed.push(agent.to_string());
+    Ok(destroyed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::Agent, commit::CommitBuilder};
+
+    #[test]
+    fn disables_and_deletes_agents() {
+        let store = Db::init_temp("disables_and_deletes_agents").unwrap();
+        store.populate().unwrap();
+        let agent = Agent::new(Some("spammer"), &store).unwrap();
+        store.add_resource(&agent.to_resource().unwrap()).unwrap();
+
+        let subject = format!("{}/spam", store.get_server_url());
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(urls::NAME.into(), Value::String("spam".into()));
+        let commit = builder
+            .sign(&agent, &store, &Resource::new(subject.clone()))
+            .unwrap();
+        let mut opts = crate::commit::CommitOpts {
+            validate_schema: false,
+            validate_signature: true,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+        };
+        commit.apply_opts(&store, &opts).unwrap();
+
+        set_disabled(&store, &agent.subject, true).unwrap();
+        assert!(crate::agents::is_disabled(&store, &agent.subject));
+        let url = url::Url::parse(&format!("{}/admin/agents", store.get_server_url())).unwrap();
+        let disabled = list_agents(&store, &url, true).unwrap();
+        assert_eq!(
+            disabled
+                .get(urls::COLLECTION_MEMBERS)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![agent.subject.clone()]
+        );
+
+        // Disabled agents can't sign Commits
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(urls::NAME.into(), Value::String("more spam".into()));
+        let resource = store.get_resource(&subject).unwrap();
+        let commit = builder.sign(&agent, &store, &resource).unwrap();
+        commit.apply_opts(&store, &opts).unwrap_err();
+
+        // Agents can't enable themselves
+        let mut builder = CommitBuilder::new(agent.subject.clone());
+        builder.set(urls::AGENT_DISABLED.into(), Value::Boolean(false));
+        let resource = store.get_resource(&agent.subject).unwrap();
+        let commit = builder.sign(&agent, &store, &resource).unwrap();
+        opts.validate_signature = false;
+        opts.validate_rights = true;
+        commit.apply_opts(&store, &opts).unwrap_err();
+
+        let destroyed = delete_agent(&store, &agent.subject).unwrap();
+        assert_eq!(destroyed.len(), 2);
+        store.get_resource(&subject).unwrap_err();
+        store.get_resource(&agent.subject).unwrap_err();
+    }
+}
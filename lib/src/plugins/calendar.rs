@@ -0,0 +1,539 @@
+/*!
+# Calendar
+Events are Resources with a `start`, an optional `end` and an optional `recurrence`.
+The `recurrence` is an iCalendar RRULE, such as `FREQ=WEEKLY;INTERVAL=2;COUNT=10`.
+Only `FREQ` (`DAILY`, `WEEKLY`, `MONTHLY` or `YEARLY`), `INTERVAL`, `COUNT` and `UNTIL` are supported.
+Times are timestamps in milliseconds, so all recurrences are calculated in UTC.
+
+- `GET /events?start={timestamp}&end={timestamp}&parent={subject}` lists the Events that take place in the range, ordered by their first occurrence in it.
+  Leave out `parent` to search all Events, or pass a Drive (or any other Resource) to only get the Events inside of it.
+- Calendar clients can subscribe to an Event, a Collection of Events or a Drive by adding `.ics` to its URL. This is handled by the server.
+*/
+
+use std::str::FromStr;
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    storelike::Query,
+    urls, utils, Resource, Storelike, Value,
+};
+
+const DAY: i64 = 24 * 60 * 60 * 1000;
+/// The range of `/events` when no `end` is passed.
+const DEFAULT_RANGE: i64 = 31 * DAY;
+/// Occurrences beyond this amount are not returned, e.g. for a daily Event in a range of years.
+const MAX_OCCURRENCES: usize = 1000;
+
+pub fn events_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/events".to_string(),
+        params: [
+            urls::EVENT_START.to_string(),
+            urls::EVENT_END.to_string(),
+            urls::PARENT.to_string(),
+        ]
+        .into(),
+        description: "Lists the Events that take place between `start` and `end` (timestamps in milliseconds), including the occurrences of recurring Events. The `start` defaults to now, the `end` to a month later. Pass a `parent` to only get the Events inside it, such as the Events of a Drive.".to_string(),
+        shortname: "events".to_string(),
+        handle: Some(handle_events_request),
+        handle_post: None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A subset of the iCalendar RRULE: https://www.rfc-editor.org/rfc/rfc5545#section-3.3.10
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Every how many days, weeks, months or years the Event repeats.
+    pub interval: u32,
+    /// The total amount of occurrences, including the first one.
+    pub count: Option<u32>,
+    /// Timestamp of the last moment an occurrence can start.
+    pub until: Option<i64>,
+}
+
+impl FromStr for Recurrence {
+    type Err = crate::AtomicError;
+
+    fn from_str(rule: &str) -> AtomicResult<Self> {
+        let rule = rule.trim().trim_start_matches("RRULE:");
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        for part in rule.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid part '{}' in recurrence", part))?;
+            match key.to_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => {
+                            return Err(format!("Unsupported recurrence frequency: {}", other).into())
+                        }
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|i| *i > 0)
+                        .ok_or_else(|| format!("Invalid recurrence interval: {}", value))?
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .map_err(|_e| format!("Invalid recurrence count: {}", value))?,
+                    )
+                }
+                "UNTIL" => until = Some(parse_ical_date(value)?),
+                other => {
+                    return Err(format!(
+                        "Unsupported recurrence part '{}'. Only FREQ, INTERVAL, COUNT and UNTIL are supported.",
+                        other
+                    )
+                    .into())
+                }
+            }
+        }
+        if count.is_some() && until.is_some() {
+            return Err("A recurrence can not have both a COUNT and an UNTIL".into());
+        }
+        Ok(Recurrence {
+            frequency: frequency.ok_or("A recurrence needs a FREQ")?,
+            interval,
+            count,
+            until,
+        })
+    }
+}
+
+/// Days since 1970-01-01 for a date in the proleptic Gregorian calendar.
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of [days_from_civil], returns the year, month and day.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    (days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)) as u32
+}
+
+/// Parses an iCalendar DATE (`20240131`) or UTC DATE-TIME (`20240131T093000Z`) to a timestamp.
+pub fn parse_ical_date(value: &str) -> AtomicResult<i64> {
+    let invalid = || format!("Invalid date '{}', use YYYYMMDD or YYYYMMDDTHHMMSSZ", value);
+    let number = |range: std::ops::Range<usize>| -> AtomicResult<u32> {
+        value
+            .get(range)
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| invalid().into())
+    };
+    let (year, month, day) = (number(0..4)?, number(4..6)?, number(6..8)?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year as i64, month) {
+        return Err(invalid().into());
+    }
+    let mut time = 0;
+    if value.len() > 8 {
+        if value.len() != 16 || value.get(8..9) != Some("T") || !value.ends_with('Z') {
+            return Err(invalid().into());
+        }
+        let (hours, minutes, seconds) = (number(9..11)?, number(11..13)?, number(13..15)?);
+        if hours > 23 || minutes > 59 || seconds > 60 {
+            return Err(invalid().into());
+        }
+        time = ((hours * 60 + minutes) * 60 + seconds) as i64 * 1000;
+    } else if value.len() != 8 {
+        return Err(invalid().into());
+    }
+    Ok(days_from_civil(year as i64, month, day) * DAY + time)
+}
+
+/// The times of an Event, see the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub start: i64,
+    pub end: Option<i64>,
+    pub recurrence: Option<Recurrence>,
+}
+
+impl Event {
+    /// Reads the times of an Event, and checks that they make sense.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Event> {
+        let start = resource.get(urls::EVENT_START)?.to_int()?;
+        let end = match resource.get(urls::EVENT_END) {
+            Ok(end) => Some(end.to_int()?),
+            Err(_) => None,
+        };
+        if let Some(end) = end {
+            if end < start {
+                return Err(format!(
+                    "The end of Event {} is before its start",
+                    resource.get_subject()
+                )
+                .into());
+            }
+        }
+        let recurrence = match resource.get(urls::EVENT_RECURRENCE) {
+            Ok(rule) => Some(rule.to_string().parse()?),
+            Err(_) => None,
+        };
+        Ok(Event {
+            start,
+            end,
+            recurrence,
+        })
+    }
+
+    fn duration(&self) -> i64 {
+        self.end.map(|end| end - self.start).unwrap_or_default()
+    }
+
+    /// The start of the nth repetition, if that date exists (e.g. not on February 30th).
+    fn nth(&self, n: i64) -> Option<i64> {
+        let Some(recurrence) = &self.recurrence else {
+            return (n == 0).then_some(self.start);
+        };
+        let steps = n * recurrence.interval as i64;
+        match recurrence.frequency {
+            Frequency::Daily => Some(self.start + steps * DAY),
+            Frequency::Weekly => Some(self.start + steps * 7 * DAY),
+            Frequency::Monthly | Frequency::Yearly => {
+                let days = self.start.div_euclid(DAY);
+                let time = self.start.rem_euclid(DAY);
+                let (year, month, day) = civil_from_days(days);
+                let months = if recurrence.frequency == Frequency::Monthly {
+                    steps
+                } else {
+                    steps * 12
+                };
+                let month_index = month as i64 - 1 + months;
+                let year = year + month_index.div_euclid(12);
+                let month = month_index.rem_euclid(12) as u32 + 1;
+                if day > days_in_month(year, month) {
+                    return None;
+                }
+                Some(days_from_civil(year, month, day) * DAY + time)
+            }
+        }
+    }
+
+    /// The repetition to start looking at for occurrences that end after `from`.
+    /// Recurrences with a COUNT start at the first one, because skipped dates are not counted.
+    fn first_candidate(&self, from: i64) -> i64 {
+        let Some(recurrence) = &self.recurrence else {
+            return 0;
+        };
+        let until_from = from - self.duration() - self.start;
+        if recurrence.count.is_some() || until_from <= 0 {
+            return 0;
+        }
+        let step = match recurrence.frequency {
+            Frequency::Daily => DAY,
+            Frequency::Weekly => 7 * DAY,
+            // The longest months and years, so the candidate is never too late
+            Frequency::Monthly => 31 * DAY,
+            Frequency::Yearly => 366 * DAY,
+        } * recurrence.interval as i64;
+        until_from / step
+    }
+
+    /// The start times of the occurrences that overlap with the range from `from` until `to`.
+    pub fn occurrences_between(&self, from: i64, to: i64) -> Vec<i64> {
+        let duration = self.duration();
+        let count = self.recurrence.as_ref().and_then(|r| r.count);
+        let until = self.recurrence.as_ref().and_then(|r| r.until);
+        let mut found = Vec::new();
+        let mut counted = 0;
+        let mut n = self.first_candidate(from);
+        while found.len() < MAX_OCCURRENCES {
+            let Some(start) = self.nth(n) else {
+                n += 1;
+                // Skipped dates, such as the 31st in short months, never last more than a few steps
+                if self.recurrence.is_none() || n > 1_000_000 {
+                    break;
+                }
+                continue;
+            };
+            counted += 1;
+            if count.is_some_and(|count| counted > count)
+                || until.is_some_and(|until| start > until)
+                || start >= to
+            {
+                break;
+            }
+            let overlaps = if duration == 0 {
+                start >= from
+            } else {
+                start + duration > from
+            };
+            if overlaps {
+                found.push(start);
+            }
+            if self.recurrence.is_none() {
+                break;
+            }
+            n += 1;
+        }
+        found
+    }
+}
+
+/// Checks the times of an Event before it is saved.
+pub fn before_apply_commit(resource_new: &Resource) -> AtomicResult<()> {
+    Event::from_resource(resource_new)?;
+    Ok(())
+}
+
+/// The Events that the Agent can read, optionally only the ones inside `parent`.
+pub fn get_events(
+    store: &impl Storelike,
+    parent: Option<&str>,
+    for_agent: Option<&str>,
+) -> AtomicResult<Vec<Resource>> {
+    let mut query = Query::new_class(urls::EVENT);
+    query.include_nested = true;
+    query.for_agent = for_agent.map(|a| a.to_string());
+    let events = store.query(&query)?.resources;
+    Ok(match parent {
+        Some(parent) => events
+            .into_iter()
+            .filter(|event| event.has_parent(store, parent))
+            .collect(),
+        None => events,
+    })
+}
+
+#[tracing::instrument]
+fn handle_events_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let mut start = None;
+    let mut end = None;
+    let mut parent = None;
+    for (k, v) in subject.query_pairs() {
+        let timestamp = || {
+            v.parse::<i64>()
+                .map_err(|_e| format!("`{}` needs a timestamp in milliseconds, got {}", k, v))
+        };
+        match k.as_ref() {
+            "start" => start = Some(timestamp()?),
+            "end" => end = Some(timestamp()?),
+            "parent" => parent = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    if start.is_none() && end.is_none() && parent.is_none() {
+        return events_endpoint().to_resource(store);
+    }
+    let start = start.unwrap_or_else(utils::now);
+    let end = end.unwrap_or(start + DEFAULT_RANGE);
+    if end < start {
+        return Err("The `end` of the range should come after its `start`".into());
+    }
+
+    let mut found: Vec<(i64, Resource)> = get_events(store, parent.as_deref(), for_agent)?
+        .into_iter()
+        .filter_map(|resource| {
+            let event = Event::from_resource(&resource).ok()?;
+            let first = *event.occurrences_between(start, end).first()?;
+            Some((first, resource))
+        })
+        .collect();
+    found.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| a.1.get_subject().cmp(b.1.get_subject()))
+    });
+    let members: Vec<String> = found
+        .iter()
+        .map(|(_start, event)| event.get_subject().to_string())
+        .collect();
+
+    let mut result = Resource::new(subject.to_string());
+    result.set_class(urls::COLLECTION);
+    result.set_propval_string(urls::NAME.into(), "Events", store)?;
+    result.set_propval_unsafe(
+        urls::COLLECTION_MEMBER_COUNT.into(),
+        Value::Integer(members.len() as i64),
+    );
+    result.set_propval_unsafe(urls::COLLECTION_MEMBERS.into(), members.into());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const JAN_31_2024: i64 = 1_706_659_200_000;
+
+    fn event(recurrence: Option<&str>) -> Event {
+        Event {
+            start: JAN_31_2024 + 9 * 60 * 60 * 1000,
+            end: Some(JAN_31_2024 + 10 * 60 * 60 * 1000),
+            recurrence: recurrence.map(|r| r.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_ical_date("20240131").unwrap(), JAN_31_2024);
+        assert_eq!(
+            parse_ical_date("20240131T093000Z").unwrap(),
+            JAN_31_2024 + 9 * 60 * 60 * 1000 + 30 * 60 * 1000
+        );
+        assert_eq!(civil_from_days(JAN_31_2024 / DAY), (2024, 1, 31));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        parse_ical_date("20230229").unwrap_err();
+        parse_ical_date("2024-01-31").unwrap_err();
+        "FREQ=HOURLY".parse::<Recurrence>().unwrap_err();
+        "FREQ=WEEKLY;BYDAY=MO".parse::<Recurrence>().unwrap_err();
+        "INTERVAL=2".parse::<Recurrence>().unwrap_err();
+    }
+
+    #[test]
+    fn finds_occurrences() {
+        let year = 366 * DAY;
+        let single = event(None);
+        assert_eq!(single.occurrences_between(0, i64::MAX), [single.start]);
+        // Still going on at the start of the range
+        assert_eq!(
+            single.occurrences_between(single.start + 1, single.start + 2),
+            [single.start]
+        );
+        assert!(single
+            .occurrences_between(single.end.unwrap(), i64::MAX)
+            .is_empty());
+
+        let weekly = event(Some("FREQ=WEEKLY;INTERVAL=2;COUNT=3"));
+        assert_eq!(
+            weekly.occurrences_between(0, i64::MAX),
+            [
+                weekly.start,
+                weekly.start + 14 * DAY,
+                weekly.start + 28 * DAY
+            ]
+        );
+
+        // Months without a 31st are skipped
+        let monthly = event(Some("FREQ=MONTHLY;UNTIL=20240801"));
+        let days: Vec<(i64, u32, u32)> = monthly
+            .occurrences_between(0, i64::MAX)
+            .iter()
+            .map(|o| civil_from_days(o / DAY))
+            .collect();
+        assert_eq!(
+            days,
+            [(2024, 1, 31), (2024, 3, 31), (2024, 5, 31), (2024, 7, 31)]
+        );
+
+        // Far in the future, without walking through all the previous occurrences
+        let daily = event(Some("FREQ=DAILY"));
+        let from = JAN_31_2024 + 100 * year;
+        let found = daily.occurrences_between(from, from + 3 * DAY);
+        assert_eq!(found.len(), 3);
+        assert!(found[0] >= from && found[0] < from + DAY);
+        assert_eq!(
+            daily.occurrences_between(0, i64::MAX).len(),
+            MAX_OCCURRENCES
+        );
+
+        let yearly = event(Some("FREQ=YEARLY"));
+        let found = yearly.occurrences_between(from, from + year);
+        assert_eq!(civil_from_days(found[0] / DAY).1, 1);
+        assert_eq!(civil_from_days(found[0] / DAY).2, 31);
+    }
+
+    #[test]
+    fn lists_events_in_range() {
+        let store = crate::Db::init_temp("lists_events_in_range").unwrap();
+        let drive = store.get_server_url().to_string();
+        for (name, recurrence) in [("standup", Some("FREQ=DAILY")), ("launch", None)] {
+            let mut resource = Resource::new(format!("{}/{}", drive, name));
+            resource.set_class(urls::EVENT);
+            resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+            resource.set_propval_unsafe(urls::NAME.into(), Value::String(name.into()));
+            resource.set_propval_unsafe(urls::EVENT_START.into(), Value::Timestamp(JAN_31_2024));
+            if let Some(rule) = recurrence {
+                resource
+                    .set_propval_unsafe(urls::EVENT_RECURRENCE.into(), Value::String(rule.into()));
+            }
+            resource.save_locally(&store).unwrap();
+        }
+        let get = |query: String| {
+            store.get_resource_extended(&format!("{}/events?{}", drive, query), false, None)
+        };
+        let count = |found: &Resource| {
+            found
+                .get(urls::COLLECTION_MEMBER_COUNT)
+                .unwrap()
+                .to_int()
+                .unwrap()
+        };
+
+        let found = get(format!("start={}&end={}", JAN_31_2024, JAN_31_2024 + 1)).unwrap();
+        assert_eq!(count(&found), 2);
+        let found = get(format!("start={}&parent={}", JAN_31_2024 + DAY, drive)).unwrap();
+        let members = found
+            .get(urls::COLLECTION_MEMBERS)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap();
+        assert_eq!(members, [format!("{}/standup", drive)]);
+        let found = get(format!("start={}&parent={}/other", JAN_31_2024, drive)).unwrap();
+        assert_eq!(count(&found), 0);
+
+        // Events are checked when they are saved
+        let mut invalid = Resource::new(format!("{}/invalid", drive));
+        invalid.set_class(urls::EVENT);
+        invalid.set_propval_unsafe(urls::EVENT_START.into(), Value::Timestamp(JAN_31_2024));
+        invalid.set_propval_unsafe(urls::EVENT_END.into(), Value::Timestamp(JAN_31_2024 - 1));
+        invalid.save_locally(&store).unwrap_err();
+        get("start=tomorrow".into()).unwrap_err();
+    }
+}
@@ -2,14 +2,27 @@
 # ChatRoom
 These are similar to Channels in Slack or Discord.
 They list a bunch of Messages.
+
+Authors can edit and delete their own Messages, and agents with write rights for the ChatRoom can moderate all Messages in it.
+Edited Messages get an `editedAt` timestamp.
+Deleted Messages are not destroyed, but are kept as tombstones (`deleted: true`, without a description), so clients can still render the history.
+
+Messages can reply to other Messages in the same ChatRoom using `replyTo`, which adds them to the `replies` of that Message.
+Reactions are children of a Message, and are listed in its `reactions`, grouped by emoji.
+Changes to replies and reactions are pushed to the subscribers of the Message.
 */
 
+use std::collections::BTreeMap;
+
 use crate::{
     commit::{CommitBuilder, CommitResponse},
     errors::AtomicResult,
+    resources::PropVals,
     storelike::Query,
     urls::{self, PARENT},
-    utils, Resource, Storelike, Value,
+    utils,
+    values::SubResource,
+    Resource, Storelike, Value,
 };
 
 // Find the messages for the ChatRoom
@@ -44,6 +57,7 @@ pub fn construct_chatroom(
         include_external: false,
         include_nested: true,
         for_agent: for_agent.map(|s| s.to_string()),
+        geo: None,
     };
 
     let mut messages_unfiltered = store.query(&query_children)?.resources;
@@ -69,11 +83,285 @@ pub fn construct_chatroom(
 
     // Clients expect messages to appear from old to new
     messages_unfiltered.reverse();
+    for message in messages_unfiltered.iter_mut() {
+        construct_message(store, message)?;
+    }
 
     resource.set_propval(urls::MESSAGES.into(), messages_unfiltered.into(), store)?;
     Ok(resource.to_owned())
 }
 
+/// Adds the `replies` and `reactions` to the Message
+#[tracing::instrument(skip(store))]
+pub fn construct_message(store: &impl Storelike, message: &mut Resource) -> AtomicResult<Resource> {
+    let mut replies_query = Query::new();
+    replies_query.property = Some(urls::REPLY_TO.into());
+    replies_query.value = Some(Value::AtomicUrl(message.get_subject().clone()));
+    let replies = store.query(&replies_query)?.subjects;
+    message.set_propval_unsafe(urls::REPLIES.into(), replies.into());
+    message.set_propval_unsafe(
+        urls::REACTIONS.into(),
+        reactions_value(&get_reactions(store, message.get_subject())?),
+    );
+    Ok(message.to_owned())
+}
+
+/// The Reactions that are children of the Message
+fn get_reactions(store: &impl Storelike, message: &str) -> AtomicResult<Vec<Resource>> {
+    let mut query = Query::new();
+    query.property = Some(PARENT.into());
+    query.value = Some(Value::AtomicUrl(message.into()));
+    query.include_nested = true;
+    let reactions = store
+        .query(&query)?
+        .resources
+        .into_iter()
+        .filter(|child| has_class(child, urls::REACTION))
+        .collect();
+    Ok(reactions)
+}
+
+/// Groups the Reactions by emoji, listing the Agents that used it.
+fn reactions_value(reactions: &[Resource]) -> Value {
+    let mut by_emoji: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for reaction in reactions {
+        if let (Ok(emoji), Ok(agent)) = (reaction.get(urls::EMOJI), reaction.get(urls::SIGNER)) {
+            by_emoji
+                .entry(emoji.to_string())
+                .or_default()
+                .push(agent.to_string());
+        }
+    }
+    let groups = by_emoji
+        .into_iter()
+        .map(|(emoji, agents)| {
+            let mut group = PropVals::new();
+            group.insert(urls::EMOJI.into(), Value::String(emoji));
+            group.insert(urls::REACTION_AGENTS.into(), agents.into());
+            SubResource::Nested(group)
+        })
+        .collect();
+    Value::ResourceArray(groups)
+}
+
+/// Whether the Resource is a Message or a Reaction, which can be edited by their authors.
+pub fn authors_can_edit(resource: &Resource) -> bool {
+    has_class(resource, urls::MESSAGE) || has_class(resource, urls::REACTION)
+}
+
+/// The properties that authors can change without write rights.
+/// Other properties, such as the `parent` and the rights, could be used to move the Message out of the ChatRoom.
+const AUTHOR_EDITABLE: [&str; 2] = [urls::DESCRIPTION, urls::DELETED];
+
+/// Whether the Commit only changes properties in [AUTHOR_EDITABLE], or destroys the resource.
+fn only_changes_content(commit: &crate::Commit) -> bool {
+    let changed = commit
+        .set
+        .iter()
+        .chain(commit.push.iter())
+        .flat_map(|map| map.keys())
+        .chain(commit.remove.iter().flatten());
+    commit.move_before.is_none()
+        && commit.move_after.is_none()
+        && changed
+            .into_iter()
+            .all(|prop| AUTHOR_EDITABLE.contains(&prop.as_str()))
+}
+
+/// Messages and Reactions can be edited by their author, and by agents with write rights for the ChatRoom.
+/// Authors can only change the content, see [AUTHOR_EDITABLE].
+/// Throws if not allowed.
+pub fn check_edit_rights(
+    store: &impl Storelike,
+    resource: &Resource,
+    commit: &crate::Commit,
+    for_agent: &str,
+) -> AtomicResult<String> {
+    let is_author =
+        crate::plugins::versioning::get_initial_commit_for_resource(resource.get_subject(), store)
+            .is_ok_and(|initial| initial.signer == for_agent);
+    if is_author && only_changes_content(commit) {
+        return Ok("Authors can edit their own Messages and Reactions".into());
+    }
+    crate::hierarchy::check_write(store, resource, for_agent).map_err(|_e| {
+        if is_author {
+            crate::AtomicError::unauthorized(format!(
+                "Authors can only change the {} of {}, or delete it. Other changes require write rights for its ChatRoom",
+                urls::DESCRIPTION,
+                resource.get_subject()
+            ))
+        } else {
+            crate::AtomicError::unauthorized(format!(
+                "Only the author of {}, or agents with write rights for its ChatRoom, can edit it",
+                resource.get_subject()
+            ))
+        }
+    })
+}
+
+/// Sets the `editedAt` marker for edited Messages, and turns deleted Messages into tombstones.
+#[tracing::instrument(skip(store))]
+pub fn before_apply_commit_message(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    resource_old: &Resource,
+    resource_new: &mut Resource,
+    validate_rights: bool,
+) -> AtomicResult<()> {
+    if commit.changes_property(urls::EDITED_AT) {
+        return Err(format!("{} is set by the server", urls::EDITED_AT).into());
+    }
+    if commit.changes_property(urls::REPLY_TO) {
+        check_reply_to(store, resource_new)?;
+    }
+    let is_new = resource_old.get(urls::LAST_COMMIT).is_err();
+    if is_new {
+        if resource_new.get(urls::DELETED).is_ok() {
+            return Err("New Messages can not be deleted".into());
+        }
+        return Ok(());
+    }
+    if commit.destroy == Some(true) {
+        if validate_rights {
+            return Err(format!(
+                "Messages can not be destroyed. Set {} to true instead, which keeps a tombstone in the ChatRoom.",
+                urls::DELETED
+            )
+            .into());
+        }
+        return Ok(());
+    }
+    if is_deleted(resource_old) {
+        return Err("This Message has been deleted, and can no longer be edited".into());
+    }
+
+    if is_deleted(resource_new) {
+        resource_new.set_propval(urls::DESCRIPTION.into(), Value::Markdown("".into()), store)?;
+        resource_new.remove_propval(urls::EDITED_AT);
+    } else if commit.changes_property(urls::DESCRIPTION) {
+        resource_new.set_propval(
+            urls::EDITED_AT.into(),
+            Value::Timestamp(commit.created_at),
+            store,
+        )?;
+    }
+    Ok(())
+}
+
+/// Replies must refer to a Message in the same ChatRoom.
+fn check_reply_to(store: &impl Storelike, message: &Resource) -> AtomicResult<()> {
+    let Ok(reply_to) = message.get(urls::REPLY_TO) else {
+        return Ok(());
+    };
+    let original = store.get_resource(&reply_to.to_string())?;
+    if !has_class(&original, urls::MESSAGE) {
+        return Err(format!("{} is not a Message, so it can not be replied to", reply_to).into());
+    }
+    let parent = |resource: &Resource| resource.get(urls::PARENT).map(|p| p.to_string()).ok();
+    if parent(&original) != parent(message) {
+        return Err("Messages can only reply to Messages in the same ChatRoom".into());
+    }
+    Ok(())
+}
+
+/// Reactions can only be added to Messages, and every Agent can use an emoji once per Message.
+/// Sets the `signer` of new Reactions to the Agent that reacted.
+#[tracing::instrument(skip(store))]
+pub fn before_apply_commit_reaction(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    resource_old: &Resource,
+    resource_new: &mut Resource,
+    for_agent: &str,
+) -> AtomicResult<()> {
+    if commit.destroy == Some(true) {
+        return Ok(());
+    }
+    if resource_old.get(urls::LAST_COMMIT).is_ok() {
+        return Err("Reactions can not be changed. Destroy it, and add a new Reaction.".into());
+    }
+    let emoji = resource_new.get(urls::EMOJI)?.to_string();
+    if emoji.trim().is_empty() {
+        return Err("The emoji of a Reaction can not be empty".into());
+    }
+    if let Ok(signer) = resource_new.get(urls::SIGNER) {
+        if signer.to_string() != for_agent {
+            return Err("The signer of a Reaction must be the Agent that reacts".into());
+        }
+    }
+    let message = resource_new.get_parent(store)?;
+    if !has_class(&message, urls::MESSAGE) {
+        return Err("Reactions can only be added to Messages".into());
+    }
+    if is_deleted(&message) {
+        return Err("Deleted Messages can not be reacted to".into());
+    }
+    let duplicate = get_reactions(store, message.get_subject())?
+        .iter()
+        .any(|reaction| {
+            reaction
+                .get(urls::SIGNER)
+                .is_ok_and(|s| s.to_string() == for_agent)
+                && reaction
+                    .get(urls::EMOJI)
+                    .is_ok_and(|e| e.to_string() == emoji)
+        });
+    if duplicate {
+        return Err(format!("You have already reacted with {} to this Message", emoji).into());
+    }
+    resource_new.set_propval(
+        urls::SIGNER.into(),
+        Value::AtomicUrl(for_agent.into()),
+        store,
+    )?;
+    Ok(())
+}
+
+/// Sends the new `reactions` of the Message to its subscribers, after a Reaction has been added or removed.
+#[tracing::instrument(skip(store))]
+pub fn after_apply_commit_reaction(
+    store: &impl Storelike,
+    reaction: &Resource,
+) -> AtomicResult<()> {
+    let message_subject = reaction
+        .get(urls::PARENT)
+        .map_err(|_e| "Reaction must have a Parent!")?
+        .to_string();
+    let message = store.get_resource(&message_subject)?;
+    let reactions = reactions_value(&get_reactions(store, &message_subject)?);
+    let mut commit_builder = CommitBuilder::new(message_subject);
+    commit_builder.set(urls::REACTIONS.into(), reactions);
+    push_commit(store, commit_builder, &message)
+}
+
+/// Signs the Commit using the server Agent, and sends it to the subscribers without saving it.
+fn push_commit(
+    store: &impl Storelike,
+    commit_builder: CommitBuilder,
+    resource: &Resource,
+) -> AtomicResult<()> {
+    let commit = commit_builder.sign(&store.get_default_agent()?, store, resource)?;
+    let commit_response = CommitResponse {
+        commit_resource: commit.into_resource(store)?,
+        resource_new: None,
+        resource_old: None,
+        commit_struct: commit,
+    };
+    store.handle_commit(&commit_response);
+    Ok(())
+}
+
+fn has_class(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == class))
+}
+
+fn is_deleted(message: &Resource) -> bool {
+    matches!(message.get(urls::DELETED), Ok(Value::Boolean(true)))
+}
+
 /// Update the ChatRoom with the new message, make sure this is sent to all Subscribers
 #[tracing::instrument(skip(store))]
 pub fn after_apply_commit_message(
@@ -97,18 +385,244 @@ pub fn after_apply_commit_message(
         let chat_room = store.get_resource(&parent_subject)?;
 
         let mut commit_builder = CommitBuilder::new(parent_subject);
-        let new_message = crate::values::SubResource::Resource(Box::new(resource_new.to_owned()));
+        let new_message = SubResource::Resource(Box::new(resource_new.to_owned()));
         commit_builder.push_propval(urls::MESSAGES, new_message)?;
-        let commit = commit_builder.sign(&store.get_default_agent()?, store, &chat_room)?;
+        push_commit(store, commit_builder, &chat_room)?;
 
-        let commit_response = CommitResponse {
-            commit_resource: commit.into_resource(store)?,
-            resource_new: None,
-            resource_old: None,
-            commit_struct: commit,
-        };
+        // Let the subscribers of the original Message know about the reply
+        if let Ok(reply_to) = resource_new.get(urls::REPLY_TO) {
+            let original = store.get_resource(&reply_to.to_string())?;
+            let mut commit_builder = CommitBuilder::new(reply_to.to_string());
+            commit_builder.push_propval(
+                urls::REPLIES,
+                SubResource::Subject(resource_new.get_subject().clone()),
+            )?;
+            push_commit(store, commit_builder, &original)?;
+        }
 
-        store.handle_commit(&commit_response);
+        if let Err(e) = crate::plugins::notifications::notify_message(store, _commit, resource_new)
+        {
+            tracing::error!("Failed to send message notifications: {}", e);
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::Agent, commit::CommitOpts, Db};
+
+    fn apply(
+        store: &Db,
+        agent: &Agent,
+        subject: &str,
+        edit: impl FnOnce(&mut CommitBuilder),
+    ) -> AtomicResult<CommitResponse> {
+        let resource = store
+            .get_resource(subject)
+            .unwrap_or_else(|_| Resource::new(subject.into()));
+        let mut commit_builder = CommitBuilder::new(subject.into());
+        edit(&mut commit_builder);
+        let commit = commit_builder.sign(agent, store, &resource)?;
+        commit.apply_opts(
+            store,
+            &CommitOpts {
+                validate_schema: true,
+                validate_signature: true,
+                validate_timestamp: false,
+                validate_rights: true,
+                validate_for_agent: None,
+                validate_previous_commit: false,
+                update_index: true,
+            },
+        )
+    }
+
+    /// Creates a ChatRoom where `author` and `stranger` can post, and `moderator` has write rights.
+    fn setup(id: &str) -> (Db, [Agent; 3], String) {
+        let store = Db::init_temp(id).unwrap();
+        store.populate().unwrap();
+        let author = store.create_agent(Some("author")).unwrap();
+        let stranger = store.create_agent(Some("stranger")).unwrap();
+        let moderator = store.create_agent(Some("moderator")).unwrap();
+
+        let mut chat_room = Resource::new_instance(urls::CHATROOM, &store).unwrap();
+        chat_room
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        chat_room
+            .set_propval_string(urls::NAME.into(), "General", &store)
+            .unwrap();
+        chat_room.set_propval_unsafe(
+            urls::APPEND.into(),
+            vec![author.subject.clone(), stranger.subject.clone()].into(),
+        );
+        chat_room.set_propval_unsafe(urls::WRITE.into(), vec![moderator.subject.clone()].into());
+        chat_room.save_locally(&store).unwrap();
+        let chat_room = chat_room.get_subject().clone();
+        (store, [author, stranger, moderator], chat_room)
+    }
+
+    fn post(
+        store: &Db,
+        agent: &Agent,
+        subject: &str,
+        chat_room: &str,
+        text: &str,
+        reply_to: Option<&str>,
+    ) -> AtomicResult<CommitResponse> {
+        apply(store, agent, subject, |c| {
+            c.set(urls::IS_A.into(), vec![urls::MESSAGE.to_string()].into());
+            c.set(urls::PARENT.into(), Value::AtomicUrl(chat_room.into()));
+            c.set(urls::DESCRIPTION.into(), Value::Markdown(text.into()));
+            if let Some(reply_to) = reply_to {
+                c.set(urls::REPLY_TO.into(), Value::AtomicUrl(reply_to.into()));
+            }
+        })
+    }
+
+    #[test]
+    fn edits_and_moderates_messages() {
+        let (store, [author, stranger, moderator], chat_room) =
+            setup("edits_and_moderates_messages");
+        let message = format!("{}/message", store.get_server_url());
+        post(&store, &author, &message, &chat_room, "Helo", None).unwrap();
+        assert!(store
+            .get_resource(&message)
+            .unwrap()
+            .get(urls::EDITED_AT)
+            .is_err());
+
+        let edit = |agent: &Agent, text: &str| {
+            apply(&store, agent, &message, |c| {
+                c.set(urls::DESCRIPTION.into(), Value::Markdown(text.into()))
+            })
+        };
+        edit(&author, "Hello").unwrap();
+        let edited = store.get_resource(&message).unwrap();
+        assert_eq!(edited.get(urls::DESCRIPTION).unwrap().to_string(), "Hello");
+        assert!(edited.get(urls::EDITED_AT).is_ok());
+        edit(&stranger, "Spam").unwrap_err();
+        edit(&moderator, "Hello!").unwrap();
+        // Authors can't move their Message, or change its rights
+        let elsewhere = format!("{}/elsewhere", store.get_server_url());
+        apply(&store, &author, &message, |c| {
+            c.set(urls::PARENT.into(), Value::AtomicUrl(elsewhere.clone()))
+        })
+        .unwrap_err();
+        apply(&store, &author, &message, |c| {
+            c.set(urls::READ.into(), vec![author.subject.clone()].into())
+        })
+        .unwrap_err();
+
+        // Destroying would leave a gap in the history, so Messages are deleted using a tombstone
+        apply(&store, &author, &message, |c| c.destroy(true)).unwrap_err();
+        apply(&store, &stranger, &message, |c| {
+            c.set(urls::DELETED.into(), Value::Boolean(true))
+        })
+        .unwrap_err();
+        apply(&store, &moderator, &message, |c| {
+            c.set(urls::DELETED.into(), Value::Boolean(true))
+        })
+        .unwrap();
+        let tombstone = store.get_resource(&message).unwrap();
+        assert_eq!(tombstone.get(urls::DESCRIPTION).unwrap().to_string(), "");
+        assert!(tombstone.get(urls::EDITED_AT).is_err());
+        edit(&author, "Undeleted").unwrap_err();
+    }
+
+    #[test]
+    fn replies_and_reacts_to_messages() {
+        let (store, [author, stranger, moderator], chat_room) =
+            setup("replies_and_reacts_to_messages");
+        let server = store.get_server_url().to_string();
+        let message = format!("{}/question", server);
+        post(&store, &author, &message, &chat_room, "Lunch?", None).unwrap();
+        let reply = format!("{}/answer", server);
+        post(
+            &store,
+            &stranger,
+            &reply,
+            &chat_room,
+            "Yes!",
+            Some(&message),
+        )
+        .unwrap();
+        // Replies must refer to a Message in the same ChatRoom
+        let elsewhere = format!("{}/elsewhere", server);
+        post(
+            &store,
+            &stranger,
+            &elsewhere,
+            &chat_room,
+            "Hi",
+            Some(&chat_room),
+        )
+        .unwrap_err();
+
+        let react = |agent: &Agent, subject: &str, emoji: &str| {
+            apply(&store, agent, subject, |c| {
+                c.set(urls::IS_A.into(), vec![urls::REACTION.to_string()].into());
+                c.set(urls::PARENT.into(), Value::AtomicUrl(message.clone()));
+                c.set(urls::EMOJI.into(), Value::String(emoji.into()));
+            })
+        };
+        react(&author, &format!("{}/r1", server), "👍").unwrap();
+        react(&stranger, &format!("{}/r2", server), "👍").unwrap();
+        react(&stranger, &format!("{}/r3", server), "🎉").unwrap();
+        // Every agent can use an emoji only once per Message
+        react(&author, &format!("{}/r4", server), "👍").unwrap_err();
+
+        let reactions_of = |emoji: &str| -> Vec<String> {
+            let constructed = store.get_resource_extended(&message, false, None).unwrap();
+            let Value::ResourceArray(groups) = constructed.get(urls::REACTIONS).unwrap() else {
+                panic!("reactions should be a ResourceArray");
+            };
+            groups
+                .iter()
+                .filter_map(|group| match group {
+                    SubResource::Nested(group) => Some(group),
+                    _other => None,
+                })
+                .find(|group| group.get(urls::EMOJI).unwrap().to_string() == emoji)
+                .map(|group| {
+                    group
+                        .get(urls::REACTION_AGENTS)
+                        .unwrap()
+                        .to_subjects(None)
+                        .unwrap()
+                })
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            reactions_of("👍"),
+            vec![author.subject.clone(), stranger.subject.clone()]
+        );
+        assert_eq!(reactions_of("🎉"), vec![stranger.subject.clone()]);
+
+        // Only the author of a Reaction or a moderator can remove it
+        let unreact = |agent: &Agent| {
+            apply(&store, agent, &format!("{}/r3", server), |c| {
+                c.destroy(true)
+            })
+        };
+        unreact(&author).unwrap_err();
+        unreact(&moderator).unwrap();
+        assert!(reactions_of("🎉").is_empty());
+
+        let constructed = store.get_resource_extended(&message, false, None).unwrap();
+        assert_eq!(
+            constructed
+                .get(urls::REPLIES)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![reply]
+        );
+    }
+}
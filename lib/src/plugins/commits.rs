@@ -0,0 +1,312 @@
+//! Browsing the Commits of a store, newest first.
+//! Replaces the generic `/commits` Collection, which becomes too large to sort and paginate.
+//! Uses the commit log index, see [crate::db::commit_log].
+
+use std::collections::HashMap;
+
+use crate::{
+    db::commit_log::{commit_log_key, commit_log_len, iter_commit_log, CommitLogEntry},
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    urls, Db, Resource, Storelike, Value,
+};
+
+const DEFAULT_PAGE_SIZE: usize = 30;
+const MAX_PAGE_SIZE: usize = 500;
+/// Query parameters of regular Collections. If any of these are used, the request is handled as a regular Collection.
+const COLLECTION_PARAMS: [&str; 7] = [
+    "current_page",
+    "sort_by",
+    "sort_desc",
+    "property",
+    "value",
+    "include_nested",
+    "include_external",
+];
+
+pub fn commits_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/commits".to_string(),
+        params: [
+            urls::SUBJECT.to_string(),
+            urls::SIGNER.to_string(),
+            urls::IS_A.to_string(),
+            urls::COLLECTION_PAGE_SIZE.to_string(),
+        ]
+        .into(),
+        description: "All Commits in this store, newest first. Filter using these query parameters:\n\n- `subject`: only Commits to resources whose subject starts with this URL\n- `signer`: only Commits signed by this Agent\n- `class`: only Commits to resources of this Class\n- `from` and `until`: only Commits created in this range (milliseconds since unix epoch)\n- `page_size`: the amount of Commits per page\n- `cursor`: the last Commit of the previous page. Use the `nextPage` link instead of setting this yourself.\n\nThe query parameters of regular Collections (e.g. `current_page`, `sort_by`) are supported too, but these are slow for large stores.".to_string(),
+        shortname: "commits".to_string(),
+        handle: Some(handle_commits_request),
+        handle_post: None,
+    }
+}
+
+/// The filters that can be passed as query parameters.
+#[derive(Debug, Default)]
+struct CommitsFilter {
+    subject_prefix: Option<String>,
+    signer: Option<String>,
+    class: Option<String>,
+    from: Option<i64>,
+    until: Option<i64>,
+    page_size: usize,
+    cursor: Option<String>,
+}
+
+impl CommitsFilter {
+    fn from_url(url: &url::Url) -> AtomicResult<CommitsFilter> {
+        let mut filter = CommitsFilter {
+            page_size: DEFAULT_PAGE_SIZE,
+            ..Default::default()
+        };
+        for (k, v) in url.query_pairs() {
+            match k.as_ref() {
+                "subject" => filter.subject_prefix = Some(v.to_string()),
+                "signer" => filter.signer = Some(v.to_string()),
+                "class" => filter.class = Some(v.to_string()),
+                "from" => filter.from = Some(parse_timestamp(&v)?),
+                "until" => filter.until = Some(parse_timestamp(&v)?),
+                "page_size" => {
+                    filter.page_size = v
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid page_size: {}", e))?
+                        .clamp(1, MAX_PAGE_SIZE)
+                }
+                "cursor" => filter.cursor = Some(v.to_string()),
+                other => return Err(format!("Invalid query param: {}", other).into()),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Whether all Commits in the store are shown, ignoring pagination.
+    fn is_unfiltered(&self) -> bool {
+        self.subject_prefix.is_none()
+            && self.signer.is_none()
+            && self.class.is_none()
+            && self.from.is_none()
+            && self.until.is_none()
+    }
+
+    /// Checks the filters that only need the log entry.
+    fn matches_entry(&self, entry: &CommitLogEntry) -> bool {
+        if let Some(prefix) = &self.subject_prefix {
+            if !entry.target.starts_with(prefix) {
+                return false;
+            }
+        }
+        if let Some(signer) = &self.signer {
+            if &entry.signer != signer {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn parse_timestamp(val: &str) -> AtomicResult<i64> {
+    Ok(val
+        .parse::<i64>()
+        .map_err(|e| format!("Timestamps must be milliseconds since unix epoch: {}", e))?)
+}
+
+#[tracing::instrument]
+fn handle_commits_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    if subject
+        .query_pairs()
+        .any(|(k, _v)| COLLECTION_PARAMS.contains(&k.as_ref()))
+    {
+        let mut collection =
+            crate::collections::create_collection_resource_for_class(store, urls::COMMIT)?;
+        collection.set_subject(subject.to_string());
+        return crate::collections::construct_collection_from_params(
+            store,
+            subject.query_pairs(),
+            &mut collection,
+            for_agent,
+        );
+    }
+    let filter = CommitsFilter::from_url(&subject)?;
+    let (members, next_cursor) = find_commits(store, &filter, for_agent)?;
+
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_propval_unsafe(urls::IS_A.into(), vec![urls::COLLECTION.to_string()].into());
+    resource.set_propval_string(urls::NAME.into(), "Commits", store)?;
+    resource.set_propval(
+        urls::COLLECTION_PAGE_SIZE.into(),
+        Value::Integer(filter.page_size as i64),
+        store,
+    )?;
+    resource.set_propval(urls::COLLECTION_MEMBERS.into(), members.into(), store)?;
+    // Counting filtered Commits means walking the entire log, so we only count for unfiltered requests
+    if filter.is_unfiltered() && for_agent.is_none() {
+        resource.set_propval(
+            urls::COLLECTION_MEMBER_COUNT.into(),
+            Value::Integer(commit_log_len(store) as i64),
+            store,
+        )?;
+    }
+    if let Some(cursor) = next_cursor {
+        let mut next_page = subject.clone();
+        let pairs: Vec<(String, String)> = subject
+            .query_pairs()
+            .filter(|(k, _v)| k != "cursor")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        next_page
+            .query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair("cursor", &cursor);
+        resource.set_propval(
+            urls::NEXT_PAGE.into(),
+            Value::AtomicUrl(next_page.to_string()),
+            store,
+        )?;
+    }
+    Ok(resource)
+}
+
+/// Returns one page of Commit subjects, and the cursor for the next page if there are more Commits.
+/// Commits of resources that the agent can't read are skipped.
+fn find_commits(
+    store: &Db,
+    filter: &CommitsFilter,
+    for_agent: Option<&str>,
+) -> AtomicResult<(Vec<String>, Option<String>)> {
+    let before = match &filter.cursor {
+        Some(cursor) => {
+            let commit = store.get_resource(cursor)?;
+            let created_at = commit.get(urls::CREATED_AT)?.to_int()?;
+            Some(commit_log_key(created_at, cursor))
+        }
+        None => None,
+    };
+
+    // Many Commits share the same target, so we only check these once
+    let mut allowed_targets: HashMap<String, bool> = HashMap::new();
+    let mut members = Vec::new();
+    for entry in iter_commit_log(store, filter.from, filter.until, before) {
+        let entry = entry?;
+        if !filter.matches_entry(&entry) {
+            continue;
+        }
+        let allowed = match allowed_targets.get(&entry.target) {
+            Some(allowed) => *allowed,
+            None => {
+                let allowed = target_allowed(store, &entry.target, filter, for_agent);
+                allowed_targets.insert(entry.target.clone(), allowed);
+                allowed
+            }
+        };
+        if !allowed {
+            continue;
+        }
+        if members.len() == filter.page_size {
+            let last = members.last().cloned();
+            return Ok((members, last));
+        }
+        members.push(entry.commit);
+    }
+    Ok((members, None))
+}
+
+/// Checks the rights and the class of the resource that the Commit was applied to.
+fn target_allowed(
+    store: &Db,
+    target: &str,
+    filter: &CommitsFilter,
+    for_agent: Option<&str>,
+) -> bool {
+    if for_agent.is_none() && filter.class.is_none() {
+        return true;
+    }
+    let resource = match store.get_resource(target) {
+        Ok(r) => r,
+        // The resource has been destroyed, so we can't check its rights
+        Err(_) => return for_agent.is_none() && filter.class.is_none(),
+    };
+    if let Some(agent) = for_agent {
+        if crate::hierarchy::check_read(store, &resource, agent).is_err() {
+            return false;
+        }
+    }
+    if let Some(class) = &filter.class {
+        let classes = resource
+            .get(urls::IS_A)
+            .and_then(|v| v.to_subjects(None))
+            .unwrap_or_default();
+        if !classes.contains(class) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{commit::CommitBuilder, Storelike};
+
+    fn apply_commit(store: &Db, subject: &str, class: &str) -> String {
+        let agent = store.get_default_agent().unwrap();
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::IS_A.into(), Value::ResourceArray(vec![class.into()]));
+        builder.set(urls::NAME.into(), Value::String("thing".into()));
+        let commit = builder
+            .sign(&agent, store, &Resource::new(subject.into()))
+            .unwrap();
+        let response = commit
+            .apply_opts(
+                store,
+                &crate::commit::CommitOpts {
+                    validate_schema: false,
+                    validate_signature: true,
+                    validate_timestamp: false,
+                    validate_rights: false,
+                    validate_previous_commit: false,
+                    validate_for_agent: None,
+                    update_index: true,
+                },
+            )
+            .unwrap();
+        response.commit_resource.get_subject().to_string()
+    }
+
+    #[test]
+    fn filters_and_paginates_commits() {
+        let store = Db::init_temp("filters_and_paginates_commits").unwrap();
+        let a = apply_commit(&store, "https://localhost/things/a", urls::CLASS);
+        let b = apply_commit(&store, "https://localhost/things/b", urls::PROPERTY);
+        let c = apply_commit(&store, "https://localhost/other/c", urls::CLASS);
+
+        let query = |q: &[(&str, &str)]| {
+            let mut url = url::Url::parse("https://localhost/commits").unwrap();
+            url.query_pairs_mut().extend_pairs(q);
+            find_commits(&store, &CommitsFilter::from_url(&url).unwrap(), None).unwrap()
+        };
+
+        let (members, next) = query(&[("subject", "https://localhost/things")]);
+        assert_eq!(members.len(), 2);
+        assert!(members.contains(&a) && members.contains(&b));
+        assert!(next.is_none());
+
+        let (members, _next) = query(&[("class", urls::CLASS)]);
+        assert_eq!(members.len(), 2);
+        assert!(members.contains(&a) && members.contains(&c));
+
+        // Pages don't overlap, and together contain all commits
+        let (first, next) = query(&[("page_size", "2")]);
+        assert_eq!(first.len(), 2);
+        let cursor = next.expect("Should have a next page");
+        let (second, _next) = query(&[("page_size", "2"), ("cursor", &cursor)]);
+        assert!(!second.iter().any(|s| first.contains(s)));
+        let all: Vec<&String> = first.iter().chain(second.iter()).collect();
+        assert!(all.contains(&&a) && all.contains(&&b) && all.contains(&&c));
+    }
+}
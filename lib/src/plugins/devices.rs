@@ -0,0 +1,212 @@
+/*!
+# Device keys
+Lets an Agent use a separate key on every device, instead of copying its private key between them.
+The device generates its own keypair, and a device that is already signed in adds its public key to the Agent.
+
+- `GET /devices` lists the device keys of the Agent that makes the request.
+- `POST /devices?action=add&public-key={key}&name={name}` adds a device key.
+- `POST /devices?action=revoke&public-key={key}` revokes a device key. The key is moved to the `keyHistory`, so Commits signed with it can still be verified.
+
+Device keys can sign Commits and authenticate requests, just like the main `publicKey` of the Agent.
+*/
+
+use crate::{
+    agents::{active_public_keys, device_keys, key_history, push_key_history, verify_public_key},
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    resources::PropVals,
+    urls,
+    values::SubResource,
+    AtomicError, Db, Resource, Value,
+};
+
+pub fn devices_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/devices".to_string(),
+        params: [urls::PUBLIC_KEY.to_string(), urls::NAME.to_string()].into(),
+        description: "Lists the device keys of the signed in Agent. POST with an `action` (`add` or `revoke`) and a `public-key` query param to manage them. Added keys can have a `name`.".to_string(),
+        shortname: "devices".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_post),
+    }
+}
+
+fn signed_in_agent(store: &Db, for_agent: Option<&str>) -> AtomicResult<Resource> {
+    match for_agent {
+        Some(agent) if agent != urls::PUBLIC_AGENT => {
+            crate::plugins::admin::get_agent(store, agent)
+        }
+        _ => Err(AtomicError::unauthorized(
+            "Sign in to manage the keys of your devices.".into(),
+        )),
+    }
+}
+
+#[tracing::instrument]
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let agent = signed_in_agent(store, for_agent)?;
+    list_devices(store, &subject, &agent)
+}
+
+#[tracing::instrument]
+fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        body: _,
+    } = context;
+    let mut agent = signed_in_agent(store, for_agent)?;
+    let mut action = None;
+    let mut public_key = None;
+    let mut name = None;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "action" => action = Some(v.to_string()),
+            "public-key" | urls::PUBLIC_KEY => public_key = Some(v.to_string()),
+            "name" | urls::NAME => name = Some(v.to_string()),
+            other => return Err(format!("Invalid query param: {}", other).into()),
+        }
+    }
+    let public_key = public_key.ok_or("No `public-key` query param given")?;
+    match action.as_deref() {
+        Some("add") => add_device_key(store, &mut agent, &public_key, name)?,
+        Some("revoke") => revoke_device_key(store, &mut agent, &public_key)?,
+        _ => return Err("Invalid or missing `action` query param. Use `add` or `revoke`.".into()),
+    }
+    let mut list_url = subject.clone();
+    list_url.set_query(None);
+    list_devices(store, &list_url, &agent)
+}
+
+fn list_devices(store: &Db, subject: &url::Url, agent: &Resource) -> AtomicResult<Resource> {
+    let mut resource = devices_endpoint().to_resource(store)?;
+    resource.set_subject(subject.to_string());
+    resource.set_propval_unsafe(
+        urls::PUBLIC_KEY.into(),
+        agent.get(urls::PUBLIC_KEY)?.clone(),
+    );
+    if let Ok(devices) = agent.get(urls::DEVICE_KEYS) {
+        resource.set_propval_unsafe(urls::DEVICE_KEYS.into(), devices.clone());
+    }
+    Ok(resource)
+}
+
+/// Adds a key that the Agent can sign with on another device.
+pub fn add_device_key(
+    store: &Db,
+    agent: &mut Resource,
+    public_key: &str,
+    name: Option<String>,
+) -> AtomicResult<()> {
+    verify_public_key(public_key)?;
+    let used_before = key_history(agent)?.iter().any(|(key, _)| key == public_key);
+    if used_before || active_public_keys(agent)?.iter().any(|k| k == public_key) {
+        return Err("This key has been used by the Agent before. Generate a new one.".into());
+    }
+    let mut entries: Vec<SubResource> = match agent.get(urls::DEVICE_KEYS) {
+        Ok(Value::ResourceArray(entries)) => entries.clone(),
+        _ => Vec::new(),
+    };
+    let mut entry = PropVals::new();
+    entry.insert(urls::PUBLIC_KEY.into(), Value::String(public_key.into()));
+    if let Some(name) = name {
+        entry.insert(urls::NAME.into(), Value::String(name));
+    }
+    entry.insert(
+        urls::CREATED_AT.into(),
+        Value::Timestamp(crate::utils::now()),
+    );
+    entries.push(SubResource::Nested(entry));
+    agent.set_propval_unsafe(urls::DEVICE_KEYS.into(), Value::ResourceArray(entries));
+    agent.save_locally(store)?;
+    Ok(())
+}
+
+/// Removes a device key. Requests and Commits signed with it are no longer accepted.
+pub fn revoke_device_key(store: &Db, agent: &mut Resource, public_key: &str) -> AtomicResult<()> {
+    if !device_keys(agent)?.iter().any(|k| k == public_key) {
+        return Err(AtomicError::not_found(format!(
+            "{} is not a device key of {}. The main key can only be rotated.",
+            public_key,
+            agent.get_subject()
+        )));
+    }
+    let Ok(Value::ResourceArray(entries)) = agent.get(urls::DEVICE_KEYS) else {
+        return Err("No device keys found".into());
+    };
+    let remaining: Vec<SubResource> = entries
+        .iter()
+        .filter(|entry| match entry {
+            SubResource::Nested(propvals) => {
+                propvals.get(urls::PUBLIC_KEY).map(|k| k.to_string()) != Some(public_key.into())
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    agent.set_propval_unsafe(urls::DEVICE_KEYS.into(), Value::ResourceArray(remaining));
+    push_key_history(agent, public_key.into(), crate::utils::now());
+    agent.save_locally(store)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::Agent, commit::CommitBuilder, Storelike};
+
+    #[test]
+    fn device_keys_sign_commits_until_revoked() {
+        let store = Db::init_temp("device_keys_sign_commits_until_revoked").unwrap();
+        let agent = Agent::new(None, &store).unwrap();
+        store.add_resource(&agent.to_resource().unwrap()).unwrap();
+        let mut laptop = Agent::new(None, &store).unwrap();
+        laptop.subject = agent.subject.clone();
+
+        let mut resource = store.get_resource(&agent.subject).unwrap();
+        add_device_key(
+            &store,
+            &mut resource,
+            &laptop.public_key,
+            Some("Laptop".into()),
+        )
+        .unwrap();
+        add_device_key(&store, &mut resource, &laptop.public_key, None).unwrap_err();
+
+        let commit = |signer: &Agent, name: &str| {
+            let mut builder = CommitBuilder::new(agent.subject.clone());
+            builder.set(urls::NAME.into(), Value::String(name.into()));
+            let resource = store.get_resource(&agent.subject).unwrap();
+            builder.sign(signer, &store, &resource).unwrap().apply_opts(
+                &store,
+                &crate::commit::CommitOpts {
+                    validate_schema: true,
+                    validate_signature: true,
+                    validate_timestamp: true,
+                    validate_rights: true,
+                    validate_previous_commit: false,
+                    validate_for_agent: None,
+                    update_index: true,
+                },
+            )
+        };
+        let signed_on_laptop = commit(&laptop, "From my laptop").unwrap();
+        commit(&agent, "From my phone").unwrap();
+
+        let mut resource = store.get_resource(&agent.subject).unwrap();
+        revoke_device_key(&store, &mut resource, &laptop.public_key).unwrap();
+        commit(&laptop, "Stolen laptop").unwrap_err();
+        // Commits from before the revocation can still be verified
+        signed_on_laptop
+            .commit_struct
+            .verify_signature(&store)
+            .unwrap();
+        revoke_device_key(&store, &mut resource, &agent.public_key).unwrap_err();
+    }
+}
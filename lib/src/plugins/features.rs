@@ -0,0 +1,151 @@
+/*!
+# Feature settings
+Every Drive can enable or disable some capabilities, such as ChatRooms or file uploads.
+These are stored in a [urls::FEATURE_SETTINGS] resource at `{drive}/settings/features`.
+Features that are not set (or Drives without settings) are enabled.
+Since the settings are a child of the Drive, only Agents with write rights to the Drive can change them.
+*/
+
+use crate::{errors::AtomicResult, urls, AtomicError, Resource, Storelike, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Chatrooms,
+    /// Accepting Invites, which can register new Agents
+    Invites,
+    Uploads,
+    Search,
+}
+
+impl Feature {
+    /// The boolean Property in the FeatureSettings that toggles this feature.
+    pub fn property(&self) -> &'static str {
+        match self {
+            Feature::Chatrooms => urls::FEATURE_CHATROOMS,
+            Feature::Invites => urls::FEATURE_INVITES,
+            Feature::Uploads => urls::FEATURE_UPLOADS,
+            Feature::Search => urls::FEATURE_SEARCH,
+        }
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Feature::Chatrooms => "ChatRooms",
+            Feature::Invites => "Invites",
+            Feature::Uploads => "Uploads",
+            Feature::Search => "Search",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn features_subject(drive: &str) -> String {
+    format!("{}/settings/features", drive.trim_end_matches('/'))
+}
+
+/// Returns the subject of the Drive that the resource belongs to.
+/// Can be the resource itself.
+pub fn find_drive(store: &impl Storelike, resource: &Resource) -> Option<String> {
+    let is_drive = |r: &Resource| {
+        r.get(urls::IS_A)
+            .and_then(|v| v.to_subjects(None))
+            .map(|classes| classes.iter().any(|c| c == urls::DRIVE))
+            .unwrap_or(false)
+    };
+    if is_drive(resource) {
+        return Some(resource.get_subject().into());
+    }
+    resource
+        .get_parent_tree(store)
+        .ok()?
+        .into_iter()
+        .find(is_drive)
+        .map(|drive| drive.get_subject().into())
+}
+
+/// Whether the feature is enabled for the Drive.
+pub fn is_enabled(store: &impl Storelike, drive: &str, feature: Feature) -> bool {
+    store
+        .get_resource(&features_subject(drive))
+        .and_then(|settings| settings.get(feature.property())?.to_bool())
+        .unwrap_or(true)
+}
+
+/// Returns an error if the feature is disabled for the Drive of the resource.
+pub fn check_enabled(
+    store: &impl Storelike,
+    resource: &Resource,
+    feature: Feature,
+) -> AtomicResult<()> {
+    match find_drive(store, resource) {
+        Some(drive) if !is_enabled(store, &drive, feature) => Err(AtomicError::method_not_allowed(
+            &format!("{} are disabled for Drive {}", feature, drive),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Creates the FeatureSettings for the Drive, with all features enabled. Does nothing if these already exist.
+pub fn create_feature_settings(store: &impl Storelike, drive: &str) -> AtomicResult<()> {
+    let subject = features_subject(drive);
+    if store.get_resource(&subject).is_ok() {
+        return Ok(());
+    }
+    let mut settings = store.get_resource_new(&subject);
+    settings.set_class(urls::FEATURE_SETTINGS);
+    settings.set_propval_string(urls::NAME.into(), "Features", store)?;
+    settings.set_propval(urls::PARENT.into(), Value::AtomicUrl(drive.into()), store)?;
+    for feature in [
+        Feature::Chatrooms,
+        Feature::Invites,
+        Feature::Uploads,
+        Feature::Search,
+    ] {
+        settings.set_propval(feature.property().into(), Value::Boolean(true), store)?;
+    }
+    settings.save_locally(store)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn toggles_features_per_drive() {
+        let store = Db::init_temp("toggles_features_per_drive").unwrap();
+        store.populate().unwrap();
+        let drive = store.get_server_url().to_string();
+        let new_chatroom = |name: &str| {
+            let mut chatroom = Resource::new_instance(urls::CHATROOM, &store).unwrap();
+            chatroom
+                .set_propval(urls::PARENT.into(), Value::AtomicUrl(drive.clone()), &store)
+                .unwrap();
+            chatroom
+                .set_propval_string(urls::NAME.into(), name, &store)
+                .unwrap();
+            chatroom
+        };
+
+        let mut allowed = new_chatroom("allowed");
+        allowed.save_locally(&store).unwrap();
+        assert_eq!(find_drive(&store, &allowed), Some(drive.clone()));
+
+        let mut settings = store.get_resource(&features_subject(&drive)).unwrap();
+        settings
+            .set_propval(
+                urls::FEATURE_CHATROOMS.into(),
+                Value::Boolean(false),
+                &store,
+            )
+            .unwrap();
+        settings.save_locally(&store).unwrap();
+
+        new_chatroom("denied").save_locally(&store).unwrap_err();
+        check_enabled(&store, &allowed, Feature::Chatrooms).unwrap_err();
+        check_enabled(&store, &allowed, Feature::Uploads).unwrap();
+    }
+}
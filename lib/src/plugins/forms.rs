@@ -0,0 +1,320 @@
+/*!
+# Forms
+Lets anyone submit a new resource, such as a contact request or a survey answer, without signing in.
+A Form refers to the Class of the resources that it creates (`form/class`), and the parent where they are stored (`form/target`).
+Only Agents that can append to the target can create a Form for it.
+
+Submissions are POSTed as a JSON object to `/form-submit?form={form}`.
+The keys are the shortnames (or URLs) of the required and recommended Properties of the Class, and the values are strings.
+The resource is created by the server Agent, as long as the creator of the Form can still append to the target.
+
+To keep spam out, submissions are rejected when:
+- the hidden honeypot field (`website`) is filled in.
+- they contain unknown properties, or values that are too long.
+- the Form received too many submissions in the last minute.
+*/
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    hierarchy::{check_rights, Right},
+    urls, utils, Resource, Storelike, Value,
+};
+
+/// Bots fill in every field. Forms should include this field, but hide it from humans.
+pub const HONEYPOT_FIELD: &str = "website";
+const MAX_BODY_BYTES: usize = 64 * 1024;
+const MAX_VALUE_CHARS: usize = 10_000;
+const SUBMISSIONS_PER_MINUTE: usize = 10;
+
+pub fn form_submit_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/form-submit".to_string(),
+        params: [urls::SUBMITTED_FORM.to_string()].into(),
+        description: "Creates a resource using a Form. POST a JSON object with the shortnames of the properties as keys, and a `form` query param with the URL of the Form. Does not require signing in.".to_string(),
+        shortname: "form-submit".to_string(),
+        handle: Some(handle_form_submit_get),
+        handle_post: Some(handle_form_submit_post),
+    }
+}
+
+fn handle_form_submit_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    form_submit_endpoint().to_resource(context.store)
+}
+
+#[tracing::instrument(skip(context))]
+fn handle_form_submit_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent: _,
+        subject,
+        body,
+    } = context;
+    let form = subject
+        .query_pairs()
+        .find(|(k, _v)| k == "form")
+        .map(|(_k, v)| v.to_string())
+        .ok_or("No `form` query param given")?;
+    if body.len() > MAX_BODY_BYTES {
+        return Err("The submission is too large".into());
+    }
+    let fields: HashMap<String, serde_json::Value> = serde_json::from_slice(&body)
+        .map_err(|e| format!("The submission must be a JSON object. {}", e))?;
+    submit(store, &form, fields)
+}
+
+/// Only Agents that can append to (or write) the target can create Forms for it.
+fn check_target_rights(store: &impl Storelike, target: &str, agent: &str) -> AtomicResult<()> {
+    let target = store.get_resource(target)?;
+    check_rights(store, &target, agent, Right::Append)
+        .or_else(|_e| check_rights(store, &target, agent, Right::Write))
+        .map_err(|_e| {
+            crate::AtomicError::unauthorized(format!(
+                "Forms can only add resources to {} if their creator can append to it",
+                target.get_subject()
+            ))
+        })?;
+    Ok(())
+}
+
+/// Checks if the creator of the Form can append to its target.
+pub fn before_apply_commit(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    resource_new: &Resource,
+) -> AtomicResult<()> {
+    if commit.destroy == Some(true) {
+        return Ok(());
+    }
+    let target = resource_new.get(urls::FORM_TARGET)?.to_string();
+    check_target_rights(store, &target, &commit.signer)?;
+    store.get_class(&resource_new.get(urls::FORM_CLASS)?.to_string())?;
+    Ok(())
+}
+
+/// Remembers when every Form was last used, to limit the amount of submissions.
+fn check_rate_limit(form: &str) -> AtomicResult<()> {
+    static SUBMISSIONS: OnceLock<Mutex<HashMap<String, Vec<Instant>>>> = OnceLock::new();
+    let mut submissions = SUBMISSIONS
+        .get_or_init(Default::default)
+        .lock()
+        .expect("Form submissions lock poisoned");
+    submissions.retain(|_form, times| {
+        times.retain(|t| t.elapsed() < Duration::from_secs(60));
+        !times.is_empty()
+    });
+    let times = submissions.entry(form.into()).or_default();
+    if times.len() >= SUBMISSIONS_PER_MINUTE {
+        return Err("This Form has received too many submissions, try again in a minute".into());
+    }
+    times.push(Instant::now());
+    Ok(())
+}
+
+/// Validates the fields against the Class of the Form, and creates the resource in its target.
+#[tracing::instrument(skip(store, fields))]
+pub fn submit(
+    store: &impl Storelike,
+    form_subject: &str,
+    mut fields: HashMap<String, serde_json::Value>,
+) -> AtomicResult<Resource> {
+    let form = store.get_resource(form_subject)?;
+    if !form
+        .get(urls::IS_A)?
+        .to_subjects(None)?
+        .iter()
+        .any(|c| c == urls::FORM)
+    {
+        return Err(format!("{} is not a Form", form_subject).into());
+    }
+    let honeypot = fields.remove(HONEYPOT_FIELD);
+    if honeypot.is_some_and(|v| !matches!(v, serde_json::Value::Null) && v != "") {
+        return Err("The submission was rejected".into());
+    }
+
+    let class = store.get_class(&form.get(urls::FORM_CLASS)?.to_string())?;
+    let target = form.get(urls::FORM_TARGET)?.to_string();
+    let form_creator =
+        crate::plugins::versioning::get_initial_commit_for_resource(form_subject, store)?.signer;
+    check_target_rights(store, &target, &form_creator)?;
+
+    let mut resource = Resource::new(format!(
+        "{}/submissions/{}",
+        store.get_server_url(),
+        utils::random_string(10)
+    ));
+    for property_url in class.requires.iter().chain(class.recommends.iter()) {
+        // These are set by the Form, not by the submitter
+        if [
+            urls::PARENT,
+            urls::READ,
+            urls::WRITE,
+            urls::APPEND,
+            urls::COMMENT_RIGHT,
+            urls::INVITE_RIGHT,
+            urls::DESTROY_RIGHT,
+            urls::DENY_READ,
+            urls::DENY_WRITE,
+            urls::PUBLIC_READ,
+            urls::LOCKED_PROPS,
+            urls::IS_A,
+        ]
+        .contains(&property_url.as_str())
+        {
+            continue;
+        }
+        let property = store.get_property(property_url)?;
+        let Some(field) = fields
+            .remove(&property.shortname)
+            .or_else(|| fields.remove(property_url))
+        else {
+            continue;
+        };
+        let text = match field {
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Null => continue,
+            other => other.to_string(),
+        };
+        if text.chars().count() > MAX_VALUE_CHARS {
+            return Err(format!("The value for {} is too long", property.shortname).into());
+        }
+        let value = Value::new(&text, &property.data_type)
+            .map_err(|e| format!("Invalid value for {}: {}", property.shortname, e))?;
+        resource.set_propval_unsafe(property_url.into(), value);
+    }
+    if let Some(unknown) = fields.keys().next() {
+        return Err(format!("The Form does not have a field called {}", unknown).into());
+    }
+    check_rate_limit(form_subject)?;
+
+    resource.set_class(&class.subject);
+    resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(target));
+    resource.set_propval_unsafe(
+        urls::SUBMITTED_FORM.into(),
+        Value::AtomicUrl(form_subject.into()),
+    );
+    // Also checks the required properties
+    resource.save_locally(store)?;
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn submits_forms() {
+        let store = Db::init_temp("submits_forms").unwrap();
+        store.populate().unwrap();
+        let server = store.get_server_url().to_string();
+
+        let mut class = Resource::new(format!("{}/contact-request", server));
+        class.set_class(urls::CLASS);
+        class
+            .set_propval_string(urls::SHORTNAME.into(), "contact-request", &store)
+            .unwrap();
+        class
+            .set_propval_string(urls::DESCRIPTION.into(), "A question", &store)
+            .unwrap();
+        class.set_propval_unsafe(urls::REQUIRES.into(), vec![urls::NAME.to_string()].into());
+        class.set_propval_unsafe(
+            urls::RECOMMENDS.into(),
+            vec![urls::DESCRIPTION.to_string()].into(),
+        );
+        class.save_locally(&store).unwrap();
+
+        let mut form = Resource::new(format!("{}/contact", server));
+        form.set_class(urls::FORM);
+        form.set_propval_string(urls::PARENT.into(), &server, &store)
+            .unwrap();
+        form.set_propval(
+            urls::FORM_CLASS.into(),
+            Value::AtomicUrl(class.get_subject().into()),
+            &store,
+        )
+        .unwrap();
+        form.set_propval(
+            urls::FORM_TARGET.into(),
+            Value::AtomicUrl(server.clone()),
+            &store,
+        )
+        .unwrap();
+        form.save_locally(&store).unwrap();
+
+        let fields = |json: serde_json::Value| -> HashMap<String, serde_json::Value> {
+            serde_json::from_value(json).unwrap()
+        };
+        let created = submit(
+            &store,
+            form.get_subject(),
+            fields(serde_json::json!({"name": "Alice", "description": "Hi there", "website": ""})),
+        )
+        .unwrap();
+        let created = store.get_resource(created.get_subject()).unwrap();
+        assert_eq!(created.get(urls::NAME).unwrap().to_string(), "Alice");
+        assert_eq!(created.get(urls::PARENT).unwrap().to_string(), server);
+
+        // Missing required fields, unknown fields and filled honeypots are rejected
+        submit(
+            &store,
+            form.get_subject(),
+            fields(serde_json::json!({"description": "Hi"})),
+        )
+        .unwrap_err();
+        submit(
+            &store,
+            form.get_subject(),
+            fields(serde_json::json!({"name": "Bob", "write": "me"})),
+        )
+        .unwrap_err();
+        submit(
+            &store,
+            form.get_subject(),
+            fields(serde_json::json!({"name": "Bot", "website": "http://spam.example"})),
+        )
+        .unwrap_err();
+
+        // Agents can't create Forms for parents they can't append to
+        let stranger = store.create_agent(Some("stranger")).unwrap();
+        let mut builder = crate::commit::CommitBuilder::new(format!("{}/sneaky", server));
+        builder.set(urls::IS_A.into(), vec![urls::FORM.to_string()].into());
+        builder.set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(stranger.subject.clone()),
+        );
+        builder.set(
+            urls::FORM_CLASS.into(),
+            Value::AtomicUrl(class.get_subject().into()),
+        );
+        builder.set(urls::FORM_TARGET.into(), Value::AtomicUrl(server.clone()));
+        let commit = builder
+            .sign(
+                &stranger,
+                &store,
+                &Resource::new(format!("{}/sneaky", server)),
+            )
+            .unwrap();
+        let err = commit
+            .apply_opts(
+                &store,
+                &crate::commit::CommitOpts {
+                    validate_schema: true,
+                    validate_signature: true,
+                    validate_timestamp: false,
+                    validate_rights: true,
+                    validate_for_agent: None,
+                    validate_previous_commit: false,
+                    update_index: true,
+                },
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("append"), "{}", err);
+    }
+}
@@ -1,13 +1,24 @@
 /*!
 Importers allow users to (periodically) import JSON-AD files from a remote source.
+Besides JSON-AD, they accept Turtle and CSV, which are converted to JSON-AD before importing.
+When a `url` is passed, the server fetches and imports the data in the background.
+It keeps track of the progress in an ImportReport resource, which clients can subscribe to using WebSockets.
 */
 
+pub use crate::convert::{to_json_ad, ImportFormat};
 use crate::{
     endpoints::{Endpoint, HandleGetContext, HandlePostContext},
     errors::AtomicResult,
-    urls, Resource, Storelike,
+    parse::{ImportReport, ParseOpts},
+    resources::PropVals,
+    urls,
+    values::SubResource,
+    Db, Resource, Storelike, Value,
 };
 
+/// Large files can take a while to download.
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
 pub fn import_endpoint() -> Endpoint {
     Endpoint {
         path: "/import".to_string(),
@@ -15,8 +26,10 @@ pub fn import_endpoint() -> Endpoint {
             urls::IMPORTER_OVERWRITE_OUTSIDE.to_string(),
             urls::IMPORTER_PARENT.to_string(),
             urls::IMPORTER_URL.to_string(),
+            urls::IMPORTER_SKIP_INVALID.to_string(),
+            urls::IMPORTER_FORMAT.to_string(),
         ].into(),
-        description: "Imports one or more Resources to some parent. POST your JSON-AD and add a `parent` query param to the URL. See https://docs.atomicdata.dev/create-json-ad.html".to_string(),
+        description: "Imports one or more Resources to some parent. POST your JSON-AD and add a `parent` query param to the URL. Add `skip-invalid=true` to skip invalid Resources instead of failing the entire import. Pass `format=turtle` or `format=csv` to import other formats. Pass a `url` instead of a body to let the server fetch the data and import it in the background. This returns an ImportReport, which is updated while importing. See https://docs.atomicdata.dev/create-json-ad.html".to_string(),
         shortname: "path".to_string(),
         // Not sure if we need this, or if we should derive it from `None` here.
         handle: Some(handle_get),
@@ -41,6 +54,8 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
     let mut json = None;
     let mut parent_maybe = None;
     let mut overwrite_outside = false;
+    let mut skip_invalid = false;
+    let mut format = None;
     for (k, v) in subject.query_pairs() {
         match k.as_ref() {
             "json" | urls::IMPORTER_URL => return Err("JSON must be POSTed in the body".into()),
@@ -49,6 +64,8 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
             "overwrite-outside" | urls::IMPORTER_OVERWRITE_OUTSIDE => {
                 overwrite_outside = v == "true"
             }
+            "skip-invalid" | urls::IMPORTER_SKIP_INVALID => skip_invalid = v == "true",
+            "format" | urls::IMPORTER_FORMAT => format = Some(ImportFormat::from_name(&v)?),
             _ => {}
         }
     }
@@ -62,16 +79,9 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
             })?);
     }
 
-    if let Some(fetch_url) = url {
-        json = Some(
-            crate::client::fetch_body(&fetch_url, crate::parse::JSON_AD_MIME, None)
-                .map_err(|e| format!("Error while fetching {}: {}", fetch_url, e))?,
-        );
-    }
-
     let parse_opts = crate::parse::ParseOpts {
         for_agent: for_agent.map(|a| a.to_string()),
-        importer: Some(parent),
+        importer: Some(parent.clone()),
         overwrite_outside,
         // We sign the importer Commits with the default agent,
         // not the one performing the import, because we don't have their private key.
@@ -79,10 +89,25 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
         save: crate::parse::SaveOpts::Commit,
     };
 
-    if let Some(json_string) = json {
+    if let Some(fetch_url) = url {
+        let agent = for_agent.ok_or("No agent specified for importer")?;
+        // The report is created by the server, so we check the rights of the Agent first.
+        crate::hierarchy::check_write(store, &store.get_resource(&parent)?, agent)?;
+        let format = format.unwrap_or_else(|| ImportFormat::from_url(&fetch_url));
+        return start_url_import(store, fetch_url, format, parse_opts);
+    }
+
+    if let Some(data) = json {
         if for_agent.is_none() {
             return Err("No agent specified for importer".to_string().into());
         }
+        let json_string = to_json_ad(&data, format.unwrap_or(ImportFormat::JsonAd), store)?;
+        if skip_invalid {
+            let report = store.import_tolerant(&json_string, &parse_opts)?;
+            let mut resource = import_endpoint().to_resource(store)?;
+            set_report(&mut resource, &report, None, store)?;
+            return Ok(resource);
+        }
         store.import(&json_string, &parse_opts)?;
     } else {
         return Err(
@@ -94,3 +119,148 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
 
     import_endpoint().to_resource(context.store)
 }
+
+/// Creates an ImportReport and imports the data from the URL in a background thread.
+/// The report is updated using Commits, so subscribers are notified of the progress.
+fn start_url_import(
+    store: &Db,
+    url: String,
+    format: ImportFormat,
+    parse_opts: ParseOpts,
+) -> AtomicResult<Resource> {
+    let subject = format!(
+        "{}/imports/{}",
+        store.get_server_url(),
+        crate::utils::random_string(10)
+    );
+    let mut report = Resource::new(subject);
+    report.set_class(urls::IMPORT_REPORT);
+    let parent = parse_opts.importer.clone().ok_or("No parent for import")?;
+    report.set_propval(urls::PARENT.into(), Value::AtomicUrl(parent), store)?;
+    report.set_propval(urls::IMPORTER_URL.into(), Value::String(url.clone()), store)?;
+    report.set_propval(
+        urls::IMPORTER_FORMAT.into(),
+        Value::String(format.name().into()),
+        store,
+    )?;
+    report.set_propval(
+        urls::IMPORTER_STATUS.into(),
+        Value::String("fetching".into()),
+        store,
+    )?;
+    report.save_locally(store)?;
+
+    let store = store.clone();
+    let mut background_report = report.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = run_url_import(&store, &mut background_report, &url, format, &parse_opts) {
+            tracing::error!("Import of {} failed: {}", url, e);
+            let failed = background_report
+                .set_propval(
+                    urls::IMPORTER_STATUS.into(),
+                    Value::String("failed".into()),
+                    &store,
+                )
+                .and_then(|_| {
+                    background_report.set_propval(
+                        urls::DESCRIPTION.into(),
+                        Value::Markdown(e.message.clone()),
+                        &store,
+                    )
+                })
+                .and_then(|_| background_report.save_locally(&store));
+            if let Err(e) = failed {
+                tracing::error!("Could not update ImportReport: {}", e);
+            }
+        }
+    });
+    Ok(report)
+}
+
+fn run_url_import(
+    store: &Db,
+    report_resource: &mut Resource,
+    url: &str,
+    format: ImportFormat,
+    parse_opts: &ParseOpts,
+) -> AtomicResult<()> {
+    let data = crate::client::fetch_body_with_timeout(url, format.mime(), None, FETCH_TIMEOUT)?;
+    let json = to_json_ad(&data, format, store)?;
+    report_resource.set_propval(
+        urls::IMPORTER_STATUS.into(),
+        Value::String("importing".into()),
+        store,
+    )?;
+    report_resource.save_locally(store)?;
+    let mut on_progress = |report: &ImportReport, total: usize| {
+        let saved = set_report(report_resource, report, Some(total), store)
+            .and_then(|_| report_resource.save_locally(store));
+        if let Err(e) = saved {
+            tracing::error!("Could not update ImportReport: {}", e);
+        }
+    };
+    let (resources, report) = crate::parse::parse_json_ad_string_tolerant_with_progress(
+        &json,
+        store,
+        parse_opts,
+        &mut on_progress,
+    )?;
+    let total = resources.len() + report.failures.len();
+    set_report(report_resource, &report, Some(total), store)?;
+    report_resource.set_propval(
+        urls::IMPORTER_STATUS.into(),
+        Value::String("done".into()),
+        store,
+    )?;
+    report_resource.save_locally(store)?;
+    Ok(())
+}
+
+/// Describes which Resources have been imported, and why others have failed.
+fn set_report(
+    resource: &mut Resource,
+    report: &ImportReport,
+    total: Option<usize>,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    if let Some(total) = total {
+        resource.set_propval(
+            urls::IMPORTER_TOTAL_COUNT.into(),
+            Value::Integer(total as i64),
+            store,
+        )?;
+    }
+    resource.set_propval(
+        urls::IMPORTER_IMPORTED_COUNT.into(),
+        Value::Integer(report.imported as i64),
+        store,
+    )?;
+    let failures: Vec<SubResource> = report
+        .failures
+        .iter()
+        .map(|failure| {
+            let mut propvals = PropVals::new();
+            propvals.insert(
+                urls::IMPORTER_LINE.into(),
+                Value::Integer(failure.line as i64),
+            );
+            if let Some(subject) = &failure.subject {
+                propvals.insert(
+                    urls::IMPORTER_FAILED_SUBJECT.into(),
+                    Value::String(subject.clone()),
+                );
+            }
+            propvals.insert(
+                urls::DESCRIPTION.into(),
+                Value::Markdown(failure.error.clone()),
+            );
+            SubResource::Nested(propvals)
+        })
+        .collect();
+    resource.set_propval(
+        urls::IMPORTER_FAILURES.into(),
+        Value::ResourceArray(failures),
+        store,
+    )?;
+    Ok(())
+}
@@ -22,6 +22,14 @@ pub fn construct_invite_redirect(
         }
     }
 
+    if pub_key.is_some() || invite_agent.is_some() {
+        crate::plugins::features::check_enabled(
+            store,
+            invite_resource,
+            crate::plugins::features::Feature::Invites,
+        )?;
+    }
+
     // Check if there is either a publicKey or an Agent present in the request. Either one is needed to continue accepting the invite.
     let agent = match (pub_key, invite_agent) {
         (None, None) => return Ok(invite_resource.to_owned()),
@@ -64,12 +72,11 @@ pub fn construct_invite_redirect(
         })?
         .to_string();
 
+    check_usable(invite_resource)?;
+
     // If any usages left value is present, make sure it's a positive number and decrement it by 1.
     if let Ok(usages_left) = invite_resource.get(urls::USAGES_LEFT) {
         let num = usages_left.to_int()?;
-        if num == 0 {
-            return Err("No usages left for this invite".into());
-        }
         // Since the requested subject might have query params, we don't want to overwrite that one - we want to overwrite the clean resource.
         let mut url = url::Url::parse(&requested_subject)?;
         url.set_query(None);
@@ -80,17 +87,11 @@ pub fn construct_invite_redirect(
             .map_err(|e| format!("Unable to save updated Invite. {}", e))?;
     }
 
-    if let Ok(expires) = invite_resource.get(urls::EXPIRES_AT) {
-        if expires.to_int()? > crate::utils::now() {
-            return Err("Invite is no longer valid".into());
-        }
-    }
-
-    // Make sure the creator of the invite is still allowed to Write the target
+    // Make sure the creator of the invite is still allowed to share the target
     let invite_creator =
         crate::plugins::versioning::get_initial_commit_for_resource(target, store)?.signer;
-    crate::hierarchy::check_write(store, &store.get_resource(target)?, &invite_creator)
-        .map_err(|e| format!("Invite creator is not allowed to write the target. {}", e))?;
+    check_invite_rights(store, &store.get_resource(target)?, &invite_creator, write)
+        .map_err(|e| format!("Invite creator is not allowed to share the target. {}", e))?;
 
     add_rights(&agent, target, write, store)?;
     if write {
@@ -139,16 +140,144 @@ pub fn add_rights(
     Ok(())
 }
 
-/// Check if the creator has rights to invite people (= write) to the target resource
+/// Throws if the Invite has been revoked, has expired, or has no usages left.
+pub fn check_usable(invite: &Resource) -> AtomicResult<()> {
+    if let Ok(revoked) = invite.get(urls::INVITE_REVOKED) {
+        if revoked.to_bool()? {
+            return Err("This Invite has been revoked".into());
+        }
+    }
+    if let Ok(expires) = invite.get(urls::EXPIRES_AT) {
+        if expires.to_int()? < crate::utils::now() {
+            return Err("Invite is no longer valid".into());
+        }
+    }
+    if let Ok(usages_left) = invite.get(urls::USAGES_LEFT) {
+        if usages_left.to_int()? <= 0 {
+            return Err("No usages left for this invite".into());
+        }
+    }
+    Ok(())
+}
+
+/// Invites need the `invite` right for the target, or the `write` right if the Invite grants write access.
+fn check_invite_rights(
+    store: &impl Storelike,
+    target: &Resource,
+    for_agent: &str,
+    write: bool,
+) -> AtomicResult<String> {
+    if write {
+        crate::hierarchy::check_write(store, target, for_agent)
+    } else {
+        crate::hierarchy::check_rights(store, target, for_agent, crate::hierarchy::Right::Invite)
+    }
+}
+
+/// Check if the creator has rights to invite people (= invite, or write for write Invites) to the target resource.
+/// Also validates the expiry, usage limit and revocation of the Invite.
 pub fn before_apply_commit(
     store: &impl Storelike,
     commit: &crate::Commit,
+    resource_old: &Resource,
     resource_new: &Resource,
 ) -> AtomicResult<()> {
     let target = resource_new
         .get(urls::TARGET)
         .map_err(|_e| "Invite does not have required Target attribute")?;
     let target_resource = store.get_resource(&target.to_string())?;
-    crate::hierarchy::check_write(store, &target_resource, &commit.signer)?;
+    let write = matches!(resource_new.get(urls::WRITE_BOOL), Ok(Value::Boolean(true)));
+    check_invite_rights(store, &target_resource, &commit.signer, write)?;
+
+    if let Ok(usages_left) = resource_new.get(urls::USAGES_LEFT) {
+        if usages_left.to_int()? < 0 {
+            return Err("The usages left of an Invite can not be negative".into());
+        }
+    }
+    if commit.changes_property(urls::EXPIRES_AT) {
+        if let Ok(expires) = resource_new.get(urls::EXPIRES_AT) {
+            if expires.to_int()? < crate::utils::now() {
+                return Err("An Invite can not expire in the past".into());
+            }
+        }
+    }
+    let is_revoked =
+        |invite: &Resource| matches!(invite.get(urls::INVITE_REVOKED), Ok(Value::Boolean(true)));
+    if is_revoked(resource_old) && !is_revoked(resource_new) {
+        return Err("A revoked Invite can not be reinstated. Create a new Invite instead.".into());
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    fn new_invite(store: &Db, name: &str) -> Resource {
+        let mut invite = Resource::new(format!("{}/{}", store.get_server_url(), name));
+        invite.set_class(urls::INVITE);
+        invite
+            .set_propval_string(urls::PARENT.into(), store.get_server_url(), store)
+            .unwrap();
+        invite
+            .set_propval(
+                urls::TARGET.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                store,
+            )
+            .unwrap();
+        invite
+    }
+
+    #[test]
+    fn expires_limits_and_revokes_invites() {
+        let store = Db::init_temp("expires_limits_and_revokes_invites").unwrap();
+        store.populate().unwrap();
+
+        let mut expired = new_invite(&store, "expired");
+        expired
+            .set_propval(urls::EXPIRES_AT.into(), Value::Timestamp(1), &store)
+            .unwrap();
+        expired.save_locally(&store).unwrap_err();
+        check_usable(&expired).unwrap_err();
+
+        let mut invite = new_invite(&store, "limited");
+        invite
+            .set_propval(
+                urls::EXPIRES_AT.into(),
+                Value::Timestamp(crate::utils::now() + 60_000),
+                &store,
+            )
+            .unwrap();
+        invite
+            .set_propval(urls::USAGES_LEFT.into(), Value::Integer(1), &store)
+            .unwrap();
+        invite.save_locally(&store).unwrap();
+        check_usable(&invite).unwrap();
+
+        invite
+            .set_propval(urls::USAGES_LEFT.into(), Value::Integer(0), &store)
+            .unwrap();
+        invite.save_locally(&store).unwrap();
+        check_usable(&invite).unwrap_err();
+        invite
+            .set_propval(urls::USAGES_LEFT.into(), Value::Integer(-1), &store)
+            .unwrap();
+        invite.save_locally(&store).unwrap_err();
+
+        let mut invite = store.get_resource(invite.get_subject()).unwrap();
+        invite
+            .set_propval(urls::USAGES_LEFT.into(), Value::Integer(5), &store)
+            .unwrap();
+        invite
+            .set_propval(urls::INVITE_REVOKED.into(), Value::Boolean(true), &store)
+            .unwrap();
+        invite.save_locally(&store).unwrap();
+        check_usable(&invite).unwrap_err();
+        invite
+            .set_propval(urls::INVITE_REVOKED.into(), Value::Boolean(false), &store)
+            .unwrap();
+        invite.save_locally(&store).unwrap_err();
+    }
+}
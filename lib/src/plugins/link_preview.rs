@@ -0,0 +1,305 @@
+/*!
+# Link previews
+Fetches a web page on the server, and returns its title, description, image and favicon as a LinkPreview.
+This lets clients render link cards without running into CORS issues.
+
+To prevent the server from being used to reach internal services (SSRF), only `http` and `https` URLs are fetched,
+and every connection (including redirects) is refused if the host resolves to a loopback, private, link-local or otherwise non-public address.
+Previews are cached in memory for an hour.
+*/
+
+use std::{
+    collections::HashMap,
+    io::Read,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use kuchiki::traits::TendrilSink;
+use url::Url;
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    urls, AtomicError, Resource, Value,
+};
+
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+const MAX_CACHED_PREVIEWS: usize = 1000;
+/// Pages are cut off after this many bytes, the metadata is in the `<head>` anyway
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_TITLE_CHARS: usize = 300;
+const MAX_DESCRIPTION_CHARS: usize = 1000;
+
+pub fn link_preview_endpoint() -> Endpoint {
+    Endpoint {
+        path: urls::PATH_LINK_PREVIEW.into(),
+        params: [urls::URL.to_string()].into(),
+        description: "Fetches the web page at the `url` query param, and returns its title, description, preview image and favicon. Only public http(s) URLs can be fetched.".to_string(),
+        shortname: "link-preview".to_string(),
+        handle: Some(handle_link_preview_request),
+        handle_post: None,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct LinkPreview {
+    title: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    favicon: Option<String>,
+}
+
+fn handle_link_preview_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        subject,
+        store,
+        for_agent,
+    } = context;
+    let Some(url) = subject
+        .query_pairs()
+        .find(|(k, _v)| k == "url")
+        .map(|(_k, v)| v.to_string())
+    else {
+        return link_preview_endpoint().to_resource(store);
+    };
+    if for_agent == Some(urls::PUBLIC_AGENT) {
+        return Err(AtomicError::unauthorized(
+            "Sign in to fetch link previews".into(),
+        ));
+    }
+
+    let preview = get_preview(&url)?;
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_class(urls::LINK_PREVIEW);
+    resource.set_propval_unsafe(urls::URL.into(), Value::String(url));
+    let optional = [
+        (urls::NAME, preview.title),
+        (urls::DESCRIPTION, preview.description),
+        (urls::IMAGE_URL, preview.image),
+        (urls::FAVICON_URL, preview.favicon),
+    ];
+    for (property, value) in optional {
+        if let Some(value) = value {
+            resource.set_propval_unsafe(property.into(), Value::String(value));
+        }
+    }
+    Ok(resource)
+}
+
+fn cache() -> &'static Mutex<HashMap<String, (Instant, LinkPreview)>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, (Instant, LinkPreview)>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// Returns the cached preview, or fetches the page.
+fn get_preview(url: &str) -> AtomicResult<LinkPreview> {
+    if let Some((fetched_at, preview)) = cache().lock().expect("Cache lock poisoned").get(url) {
+        if fetched_at.elapsed() < CACHE_TTL {
+            return Ok(preview.clone());
+        }
+    }
+    let preview = fetch_preview(url)?;
+    let mut cache = cache().lock().expect("Cache lock poisoned");
+    if cache.len() >= MAX_CACHED_PREVIEWS {
+        cache.retain(|_url, (fetched_at, _preview)| fetched_at.elapsed() < CACHE_TTL);
+        if cache.len() >= MAX_CACHED_PREVIEWS {
+            cache.clear();
+        }
+    }
+    cache.insert(url.into(), (Instant::now(), preview.clone()));
+    Ok(preview)
+}
+
+fn fetch_preview(url: &str) -> AtomicResult<LinkPreview> {
+    let parsed = Url::parse(url)?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("Only http and https URLs can be previewed, not {}", url).into());
+    }
+    let agent = ureq::builder()
+        .timeout(FETCH_TIMEOUT)
+        .resolver(resolve_public)
+        .build();
+    let response = agent
+        .get(url)
+        .set("Accept", "text/html")
+        .call()
+        .map_err(|e| format!("Could not fetch {}: {}", url, e))?;
+    if !response.content_type().contains("html") {
+        return Err(format!(
+            "{} is not an HTML page, but {}",
+            url,
+            response.content_type()
+        )
+        .into());
+    }
+    // Relative URLs are resolved against the page after redirects
+    let page_url = Url::parse(response.get_url()).unwrap_or(parsed);
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_BODY_BYTES)
+        .read_to_end(&mut bytes)?;
+    Ok(parse_preview(&page_url, &String::from_utf8_lossy(&bytes)))
+}
+
+/// Resolves the host like the default resolver, but refuses addresses that are not publicly reachable.
+/// Since every connection uses this resolver, redirects and DNS rebinding can't be used to reach internal hosts either.
+fn resolve_public(netloc: &str) -> std::io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = netloc
+        .to_socket_addrs()?
+        .filter(|address| is_public_ip(&address.ip()))
+        .collect();
+    if addresses.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} does not resolve to a public address", netloc),
+        ));
+    }
+    Ok(addresses)
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                // "This network", 0.0.0.0/8
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ipv4) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(ipv4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link local, fe80::/10
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Reads the OpenGraph, Twitter and regular HTML metadata of the page.
+fn parse_preview(page_url: &Url, html: &str) -> LinkPreview {
+    let document = kuchiki::parse_html().one(html);
+    let attribute = |selector: &str, name: &str| -> Option<String> {
+        let element = document.select_first(selector).ok()?;
+        let value = element.attributes.borrow().get(name)?.trim().to_string();
+        (!value.is_empty()).then_some(value)
+    };
+    let resolve = |link: String| page_url.join(&link).ok().map(|u| u.to_string());
+
+    let title = attribute("meta[property='og:title']", "content")
+        .or_else(|| attribute("meta[name='twitter:title']", "content"))
+        .or_else(|| {
+            document
+                .select_first("title")
+                .ok()
+                .map(|t| t.text_contents())
+        })
+        .map(|t| normalize(&t, MAX_TITLE_CHARS))
+        .filter(|t| !t.is_empty());
+    let description = attribute("meta[property='og:description']", "content")
+        .or_else(|| attribute("meta[name='description']", "content"))
+        .or_else(|| attribute("meta[name='twitter:description']", "content"))
+        .map(|d| normalize(&d, MAX_DESCRIPTION_CHARS));
+    let image = attribute("meta[property='og:image']", "content")
+        .or_else(|| attribute("meta[name='twitter:image']", "content"))
+        .and_then(resolve);
+    let favicon = attribute("link[rel~='icon']", "href")
+        .or_else(|| attribute("link[rel='apple-touch-icon']", "href"))
+        .or_else(|| Some("/favicon.ico".into()))
+        .and_then(resolve);
+
+    LinkPreview {
+        title,
+        description,
+        image,
+        favicon,
+    }
+}
+
+/// Collapses whitespace and cuts off long texts.
+fn normalize(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+    let mut cut: String = text.chars().take(max_chars - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_preview_metadata() {
+        let html = r#"<html><head>
+            <title>Fallback title</title>
+            <meta property="og:title" content="  Atomic   Data ">
+            <meta name="description" content="Linked data, but easy">
+            <meta property="og:image" content="/images/card.png">
+            <link rel="shortcut icon" href="icons/favicon.svg">
+            </head><body></body></html>"#;
+        let url = Url::parse("https://example.com/docs/page").unwrap();
+        let preview = parse_preview(&url, html);
+        assert_eq!(
+            preview,
+            LinkPreview {
+                title: Some("Atomic Data".into()),
+                description: Some("Linked data, but easy".into()),
+                image: Some("https://example.com/images/card.png".into()),
+                favicon: Some("https://example.com/docs/icons/favicon.svg".into()),
+            }
+        );
+
+        let bare = parse_preview(&url, "<title>Only a title</title>");
+        assert_eq!(bare.title.as_deref(), Some("Only a title"));
+        assert_eq!(
+            bare.favicon.as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+    }
+
+    #[test]
+    fn refuses_internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(
+                !is_public_ip(&ip.parse().unwrap()),
+                "{} should not be public",
+                ip
+            );
+        }
+        assert!(is_public_ip(&"93.184.216.34".parse().unwrap()));
+        assert!(is_public_ip(&"2606:2800:220:1::".parse().unwrap()));
+
+        fetch_preview("http://127.0.0.1:9883/").unwrap_err();
+        fetch_preview("http://localhost/").unwrap_err();
+        fetch_preview("file:///etc/passwd").unwrap_err();
+    }
+}
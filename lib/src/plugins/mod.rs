@@ -34,14 +34,29 @@ They are used for performing custom queries, or calculating dynamic attributes.
 */
 
 // Class Extenders
+pub mod admin;
 pub mod chatroom;
+pub mod commits;
+pub mod features;
 pub mod importer;
 pub mod invite;
 
 // Endpoints
 #[cfg(feature = "html")]
 pub mod bookmark;
+pub mod calendar;
+pub mod devices;
 pub mod files;
+pub mod forms;
+#[cfg(feature = "html")]
+pub mod link_preview;
+pub mod move_resource;
+pub mod notifications;
 pub mod path;
+pub mod personal_data;
+pub mod quotas;
+pub mod rename;
+pub mod rights;
+pub mod schema_usage;
 pub mod search;
 pub mod versioning;
@@ -0,0 +1,388 @@
+/*!
+# Moving resources
+Moves a resource to another parent, using `POST /move?subject={resource}&parent={new parent}`.
+
+Setting the `parent` in a Commit only requires write rights for the resource itself,
+while it changes who can read it, since rights are inherited from the parents.
+This endpoint also requires write rights for both the old and the new parent.
+The resource is removed from the `subresources` of the old parent, and added to those of the new parent (if it has these).
+
+With `rewrite-subjects=true`, a resource with a subject below its old parent (e.g. `{old parent}/notes`) gets a subject below the new parent.
+Descendants with a subject below the moved resource are moved along, and references between the moved resources are updated.
+References from other resources keep pointing to the old subjects.
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts, CommitResponse},
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    storelike::Query,
+    urls,
+    values::SubResource,
+    AtomicError, Resource, Storelike, Value,
+};
+
+pub fn move_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/move".to_string(),
+        params: [
+            urls::SUBJECT.to_string(),
+            urls::PARENT.to_string(),
+            urls::MOVE_REWRITE_SUBJECTS.to_string(),
+        ]
+        .into(),
+        description: "Moves a resource to a new `parent`. POST with the `subject` of the resource and the `parent` as query params. Requires write rights for the resource, its current parent and the new parent. Pass `rewrite-subjects=true` to also change the URLs of the resource and its descendants to paths below the new parent. Returns the moved resource.".to_string(),
+        shortname: "move".to_string(),
+        handle: Some(handle_move_get),
+        handle_post: Some(handle_move_post),
+    }
+}
+
+fn handle_move_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    move_endpoint().to_resource(context.store)
+}
+
+#[tracing::instrument]
+fn handle_move_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        body: _,
+    } = context;
+    let mut target = None;
+    let mut parent = None;
+    let mut rewrite_subjects = false;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "subject" | urls::SUBJECT => target = Some(v.to_string()),
+            "parent" | urls::PARENT => parent = Some(v.to_string()),
+            "rewrite-subjects" | urls::MOVE_REWRITE_SUBJECTS => rewrite_subjects = v == "true",
+            other => return Err(format!("Invalid query param: {}", other).into()),
+        }
+    }
+    let target = target.ok_or("No `subject` query param given")?;
+    let parent = parent.ok_or("No `parent` query param given")?;
+    let agent = for_agent
+        .filter(|agent| *agent != urls::PUBLIC_AGENT)
+        .ok_or_else(|| AtomicError::unauthorized("Sign in to move resources".into()))?;
+    move_resource(store, &target, &parent, agent, rewrite_subjects)
+}
+
+/// Moves a resource to a new parent, and returns the moved resource.
+/// Requires write rights for the resource, its current parent and the new parent.
+/// If `rewrite_subjects` is true, the resource and its descendants get subjects below the new parent, see the [module docs](self).
+/// Like [crate::plugins::versioning::restore_version], the Commits are signed by the default Agent of the store,
+/// and applied with the rights of `for_agent`, who is recorded as the author using `onBehalfOf`.
+#[tracing::instrument(skip(store))]
+pub fn move_resource(
+    store: &impl Storelike,
+    subject: &str,
+    new_parent: &str,
+    for_agent: &str,
+    rewrite_subjects: bool,
+) -> AtomicResult<Resource> {
+    let resource = store.get_resource(subject)?;
+    let old_parent = resource
+        .get_parent(store)
+        .map_err(|_e| "Only resources with a parent can be moved")?;
+    if old_parent.get_subject() == new_parent {
+        return Err(format!("{} is already a child of {}", subject, new_parent).into());
+    }
+    let new_parent_resource = store.get_resource(new_parent)?;
+    if new_parent == subject
+        || new_parent_resource
+            .get_parent_tree(store)?
+            .iter()
+            .any(|p| p.get_subject() == subject)
+    {
+        return Err("A resource can't be moved into itself or one of its descendants".into());
+    }
+    check_write(store, &resource, for_agent)?;
+    for parent in [&old_parent, &new_parent_resource] {
+        check_write(store, parent, for_agent).map_err(|e| {
+            AtomicError::unauthorized(format!(
+                "Moving {} requires write rights for {}: {}",
+                subject,
+                parent.get_subject(),
+                e
+            ))
+        })?;
+    }
+
+    let new_subject = if rewrite_subjects {
+        match subject.strip_prefix(&format!("{}/", old_parent.get_subject())) {
+            Some(path) => format!("{}/{}", new_parent, path),
+            None => {
+                return Err(format!(
+                    "The subject of {} is not a path below its parent, so it can't be rewritten",
+                    subject
+                )
+                .into())
+            }
+        }
+    } else {
+        subject.to_string()
+    };
+
+    if new_subject == subject {
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(new_parent.into()));
+        apply(store, builder, &resource, for_agent)?;
+    } else {
+        move_subtree(store, resource, &new_subject, new_parent, for_agent)?;
+    }
+    update_subresources(store, old_parent.get_subject(), subject, None, for_agent)?;
+    update_subresources(
+        store,
+        new_parent,
+        &new_subject,
+        Some(&new_subject),
+        for_agent,
+    )?;
+    store.get_resource(&new_subject)
+}
+
+/// Signs the Commit with the default Agent on behalf of `for_agent`, and applies it with the rights of `for_agent`.
+pub(crate) fn apply(
+    store: &impl Storelike,
+    mut builder: CommitBuilder,
+    resource: &Resource,
+    for_agent: &str,
+) -> AtomicResult<CommitResponse> {
+    builder.on_behalf_of(for_agent.into());
+    let commit = builder.sign(&store.get_default_agent()?, store, resource)?;
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: true,
+        validate_for_agent: Some(for_agent.to_string()),
+        validate_previous_commit: false,
+        update_index: true,
+    };
+    commit.apply_opts(store, &opts)
+}
+
+/// Returns the resource and its descendants, parents before their children.
+fn subtree(store: &impl Storelike, resource: Resource) -> AtomicResult<Vec<Resource>> {
+    let mut resources = vec![resource];
+    let mut i = 0;
+    while let Some(current) = resources.get(i) {
+        let children = store
+            .query(&Query::new_prop_val(urls::PARENT, current.get_subject()))?
+            .resources;
+        resources.extend(children);
+        i += 1;
+    }
+    Ok(resources)
+}
+
+/// Replaces references to the moved resources. Returns `None` if nothing changes.
+pub(crate) fn rewrite_value(value: &Value, subjects: &HashMap<String, String>) -> Option<Value> {
+    match value {
+        Value::AtomicUrl(subject) => subjects
+            .get(subject)
+            .map(|new| Value::AtomicUrl(new.clone())),
+        Value::ResourceArray(items) => {
+            let moved = |item: &SubResource| match item {
+                SubResource::Subject(s) => subjects.get(s).cloned(),
+                _ => None,
+            };
+            if !items.iter().any(|item| moved(item).is_some()) {
+                return None;
+            }
+            let items = items
+                .iter()
+                .map(|item| {
+                    moved(item)
+                        .map(SubResource::Subject)
+                        .unwrap_or(item.clone())
+                })
+                .collect();
+            Some(Value::ResourceArray(items))
+        }
+        _ => None,
+    }
+}
+
+/// Creates the resource and its descendants at their new subjects, and then destroys the old ones.
+fn move_subtree(
+    store: &impl Storelike,
+    resource: Resource,
+    new_subject: &str,
+    new_parent: &str,
+    for_agent: &str,
+) -> AtomicResult<()> {
+    let old_subject = resource.get_subject().to_string();
+    let resources = subtree(store, resource)?;
+    let mut subjects: HashMap<String, String> = HashMap::new();
+    for r in &resources {
+        let subject = r.get_subject();
+        let new = if *subject == old_subject {
+            new_subject.to_string()
+        } else if let Some(path) = subject.strip_prefix(&format!("{}/", old_subject)) {
+            format!("{}/{}", new_subject, path)
+        } else {
+            continue;
+        };
+        if store.get_resource(&new).is_ok() {
+            return Err(format!("Can't move {} to {}, which already exists", subject, new).into());
+        }
+        subjects.insert(subject.to_string(), new);
+    }
+
+    let mut destroyed = Vec::new();
+    for r in &resources {
+        let old = r.get_subject();
+        let mut builder = CommitBuilder::new(subjects.get(old).cloned().unwrap_or(old.into()));
+        let mut changed = false;
+        for (prop, value) in r.get_propvals() {
+            if prop == urls::LAST_COMMIT {
+                continue;
+            }
+            let new_value = if *old == old_subject && prop == urls::PARENT {
+                Some(Value::AtomicUrl(new_parent.into()))
+            } else {
+                rewrite_value(value, &subjects)
+            };
+            match new_value {
+                Some(new_value) => {
+                    builder.set(prop.clone(), new_value);
+                    changed = true;
+                }
+                None if subjects.contains_key(old) => builder.set(prop.clone(), value.clone()),
+                None => {}
+            }
+        }
+        if subjects.contains_key(old) {
+            apply(store, builder, &Resource::new(old.into()), for_agent)?;
+            destroyed.push(r);
+        } else if changed {
+            apply(store, builder, r, for_agent)?;
+        }
+    }
+    // Children first, since destroying a resource needs the rights of its parents
+    for r in destroyed.into_iter().rev() {
+        let mut builder = CommitBuilder::new(r.get_subject().into());
+        builder.destroy(true);
+        apply(store, builder, r, for_agent)?;
+    }
+    Ok(())
+}
+
+/// Removes the subject from the `subresources` of the parent, or adds `add` to them.
+/// Parents without `subresources` are not changed.
+fn update_subresources(
+    store: &impl Storelike,
+    parent: &str,
+    subject: &str,
+    add: Option<&str>,
+    for_agent: &str,
+) -> AtomicResult<()> {
+    let parent = store.get_resource(parent)?;
+    let Ok(current) = parent.get(urls::SUBRESOURCES) else {
+        return Ok(());
+    };
+    let mut subresources = current.to_subjects(None)?;
+    let before = subresources.len();
+    subresources.retain(|s| s != subject);
+    if let Some(add) = add {
+        subresources.push(add.into());
+    } else if subresources.len() == before {
+        return Ok(());
+    }
+    let mut builder = CommitBuilder::new(parent.get_subject().into());
+    builder.set(urls::SUBRESOURCES.into(), subresources.into());
+    apply(store, builder, &parent, for_agent)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn moves_resources_and_checks_rights() {
+        let store = Db::init_temp("moves_resources_and_checks_rights").unwrap();
+        let drive = store.get_server_url().to_string();
+        let agent = format!("{}/agents/mover", drive);
+        let folder = |name: &str, writers: Vec<String>| {
+            let mut folder = Resource::new(format!("{}/{}", drive, name));
+            folder.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+            folder.set_propval_unsafe(urls::WRITE.into(), writers.into());
+            folder.set_propval_unsafe(urls::SUBRESOURCES.into(), Vec::<String>::new().into());
+            store.add_resource(&folder).unwrap();
+            folder.get_subject().to_string()
+        };
+        let from = folder("from", vec![agent.clone()]);
+        let to = folder("to", vec![]);
+        let mut note = Resource::new(format!("{}/note", from));
+        note.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(from.clone()));
+        note.set_propval_unsafe(urls::NAME.into(), Value::String("Note".into()));
+        store.add_resource(&note).unwrap();
+        let mut comment = Resource::new(format!("{}/note/comment", from));
+        comment.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(note.get_subject().into()),
+        );
+        store.add_resource(&comment).unwrap();
+        let mut list = store.get_resource(&from).unwrap();
+        list.set_propval_unsafe(
+            urls::SUBRESOURCES.into(),
+            vec![note.get_subject().clone()].into(),
+        );
+        store.add_resource(&list).unwrap();
+
+        // The Agent can't write to the new parent
+        move_resource(&store, note.get_subject(), &to, &agent, false).unwrap_err();
+        move_resource(
+            &store,
+            note.get_subject(),
+            note.get_subject(),
+            &agent,
+            false,
+        )
+        .unwrap_err();
+        let mut to_resource = store.get_resource(&to).unwrap();
+        to_resource.set_propval_unsafe(urls::WRITE.into(), vec![agent.clone()].into());
+        store.add_resource(&to_resource).unwrap();
+
+        let moved = move_resource(&store, note.get_subject(), &to, &agent, true).unwrap();
+        assert_eq!(moved.get_subject(), &format!("{}/note", to));
+        assert_eq!(moved.get(urls::PARENT).unwrap().to_string(), to);
+        assert_eq!(moved.get(urls::NAME).unwrap().to_string(), "Note");
+        store.get_resource(note.get_subject()).unwrap_err();
+        let comment = store.get_resource(&format!("{}/note/comment", to)).unwrap();
+        assert_eq!(
+            &comment.get(urls::PARENT).unwrap().to_string(),
+            moved.get_subject()
+        );
+        store
+            .get_resource(&format!("{}/note/comment", from))
+            .unwrap_err();
+        let subresources = |parent: &str| {
+            store
+                .get_resource(parent)
+                .unwrap()
+                .get(urls::SUBRESOURCES)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap()
+        };
+        assert!(subresources(&from).is_empty());
+        assert_eq!(subresources(&to), vec![moved.get_subject().to_string()]);
+        // The Agent is the author of the moved resources, even though the server signed the Commits
+        let created = crate::plugins::personal_data::created_by(&store, &agent).unwrap();
+        assert!(created.contains(moved.get_subject()));
+
+        // Without rewriting, the subject stays the same
+        let moved_back = move_resource(&store, moved.get_subject(), &from, &agent, false).unwrap();
+        assert_eq!(moved_back.get_subject(), moved.get_subject());
+        assert_eq!(moved_back.get(urls::PARENT).unwrap().to_string(), from);
+    }
+}
@@ -0,0 +1,399 @@
+/*!
+# Notifications
+Tells Agents when something happens that concerns them.
+Notifications are created by Commit handlers when an Agent is:
+
+- `mention`ed in a Message, by including the URL of their Agent in its description.
+- sent a `reply` to one of their Messages.
+- `invite`d, using an Invite that has an `invite/agent`.
+- given read or write rights to a Resource (a `share`).
+
+Notifications can only be read by their recipient.
+- `GET /inbox` lists the Notifications of the signed in Agent, newest first, and counts the unread ones.
+- `POST /inbox/read?subject={notification}` marks a Notification as read. Leave out the `subject` to mark all of them as read.
+*/
+
+use crate::{
+    commit::CommitResponse,
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    storelike::Query,
+    urls, utils, AtomicError, Resource, Storelike, Value,
+};
+
+/// Mentions beyond this amount in a single Message are ignored
+const MAX_MENTIONS: usize = 20;
+
+pub fn inbox_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/inbox".to_string(),
+        params: [].into(),
+        description: "Lists the Notifications of the signed in Agent, newest first. The `unreadCount` is the amount of Notifications that have not been read yet.".to_string(),
+        shortname: "inbox".to_string(),
+        handle: Some(handle_inbox_request),
+        handle_post: None,
+    }
+}
+
+pub fn read_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/inbox/read".to_string(),
+        params: [urls::SUBJECT.to_string()].into(),
+        description: "Marks Notifications as read. POST with a `subject` query param to mark a single Notification, or without one to mark all Notifications of the signed in Agent. Returns the inbox.".to_string(),
+        shortname: "read".to_string(),
+        handle: Some(handle_read_get),
+        handle_post: Some(handle_read_post),
+    }
+}
+
+fn signed_in_agent(for_agent: Option<&str>) -> AtomicResult<&str> {
+    match for_agent {
+        Some(agent) if agent != urls::PUBLIC_AGENT => Ok(agent),
+        _ => Err(AtomicError::unauthorized(
+            "Sign in to see your Notifications".into(),
+        )),
+    }
+}
+
+#[tracing::instrument]
+fn handle_inbox_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let agent = signed_in_agent(for_agent)?;
+    construct_inbox(store, subject.as_str(), agent)
+}
+
+fn handle_read_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    read_endpoint().to_resource(context.store)
+}
+
+#[tracing::instrument]
+fn handle_read_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        body: _,
+    } = context;
+    let agent = signed_in_agent(for_agent)?;
+    let notification = subject
+        .query_pairs()
+        .find(|(k, _v)| k == "subject")
+        .map(|(_k, v)| v.to_string());
+    let unread: Vec<Resource> = match notification {
+        Some(notification) => {
+            let resource = store.get_resource(&notification)?;
+            if resource.get(urls::NOTIFICATION_RECIPIENT)?.to_string() != agent {
+                return Err(AtomicError::unauthorized(
+                    "Only the recipient can mark a Notification as read".into(),
+                ));
+            }
+            vec![resource]
+        }
+        None => get_notifications(store, agent)?,
+    };
+    for mut notification in unread.into_iter().filter(|n| !is_read(n)) {
+        notification.set_propval(urls::NOTIFICATION_READ.into(), Value::Boolean(true), store)?;
+        notification.save_locally(store)?;
+    }
+    let inbox = format!("{}{}", store.get_server_url(), inbox_endpoint().path);
+    construct_inbox(store, &inbox, agent)
+}
+
+/// The Notifications of the Agent, newest first
+fn get_notifications(store: &impl Storelike, agent: &str) -> AtomicResult<Vec<Resource>> {
+    let mut query = Query::new();
+    query.property = Some(urls::NOTIFICATION_RECIPIENT.into());
+    query.value = Some(Value::AtomicUrl(agent.into()));
+    query.sort_by = Some(urls::CREATED_AT.into());
+    query.sort_desc = true;
+    query.include_nested = true;
+    query.for_agent = Some(agent.into());
+    Ok(store.query(&query)?.resources)
+}
+
+fn is_read(notification: &Resource) -> bool {
+    matches!(
+        notification.get(urls::NOTIFICATION_READ),
+        Ok(Value::Boolean(true))
+    )
+}
+
+/// Lists the Notifications as a Collection, with an `unreadCount`.
+#[tracing::instrument(skip(store))]
+pub fn construct_inbox(
+    store: &impl Storelike,
+    subject: &str,
+    agent: &str,
+) -> AtomicResult<Resource> {
+    let notifications = get_notifications(store, agent)?;
+    let unread = notifications.iter().filter(|n| !is_read(n)).count();
+    let members: Vec<String> = notifications
+        .iter()
+        .map(|n| n.get_subject().to_string())
+        .collect();
+
+    let mut inbox = Resource::new(subject.into());
+    inbox.set_class(urls::COLLECTION);
+    inbox.set_propval_string(urls::NAME.into(), "Inbox", store)?;
+    inbox.set_propval_unsafe(
+        urls::COLLECTION_MEMBER_COUNT.into(),
+        Value::Integer(members.len() as i64),
+    );
+    inbox.set_propval_unsafe(urls::COLLECTION_MEMBERS.into(), members.into());
+    inbox.set_propval_unsafe(urls::UNREAD_COUNT.into(), Value::Integer(unread as i64));
+    Ok(inbox)
+}
+
+/// Creates a Notification for the recipient, unless the recipient caused it themselves.
+#[tracing::instrument(skip(store))]
+pub fn notify(
+    store: &impl Storelike,
+    recipient: &str,
+    kind: &str,
+    target: &str,
+    actor: &str,
+) -> AtomicResult<()> {
+    if recipient == actor || recipient == urls::PUBLIC_AGENT {
+        return Ok(());
+    }
+    let subject = format!(
+        "{}/notifications/{}",
+        store.get_server_url(),
+        utils::random_string(10)
+    );
+    let mut notification = Resource::new(subject);
+    notification.set_class(urls::NOTIFICATION);
+    notification.set_propval(
+        urls::NOTIFICATION_RECIPIENT.into(),
+        Value::AtomicUrl(recipient.into()),
+        store,
+    )?;
+    notification.set_propval(
+        urls::NOTIFICATION_KIND.into(),
+        Value::String(kind.into()),
+        store,
+    )?;
+    notification.set_propval(
+        urls::NOTIFICATION_TARGET.into(),
+        Value::AtomicUrl(target.into()),
+        store,
+    )?;
+    notification.set_propval(
+        urls::NOTIFICATION_ACTOR.into(),
+        Value::AtomicUrl(actor.into()),
+        store,
+    )?;
+    notification.set_propval(urls::NOTIFICATION_READ.into(), Value::Boolean(false), store)?;
+    notification.set_propval(
+        urls::CREATED_AT.into(),
+        Value::Timestamp(utils::now()),
+        store,
+    )?;
+    // Notifications have no parent, so only the recipient can read them
+    notification.set_propval_unsafe(urls::READ.into(), vec![recipient.to_string()].into());
+    notification.set_propval_unsafe(urls::WRITE.into(), vec![recipient.to_string()].into());
+    notification.save_locally(store)?;
+    Ok(())
+}
+
+/// Notifies the Agents that are mentioned in a new Message, and the author of the Message it replies to.
+#[tracing::instrument(skip(store))]
+pub fn notify_message(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    message: &Resource,
+) -> AtomicResult<()> {
+    let subject = message.get_subject();
+    if let Ok(reply_to) = message.get(urls::REPLY_TO) {
+        let author = crate::plugins::versioning::get_initial_commit_for_resource(
+            &reply_to.to_string(),
+            store,
+        )?
+        .signer;
+        notify(store, &author, "reply", subject, &commit.signer)?;
+    }
+    if let Ok(description) = message.get(urls::DESCRIPTION) {
+        for agent in find_mentions(store, &description.to_string()) {
+            notify(store, &agent, "mention", subject, &commit.signer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Finds the URLs of the Agents on this server that appear in the text
+fn find_mentions(store: &impl Storelike, text: &str) -> Vec<String> {
+    let server = store.get_server_url();
+    let mut mentions: Vec<String> = Vec::new();
+    for word in text.split(|c: char| c.is_whitespace() || "()[]<>\"'".contains(c)) {
+        let url = word.trim_end_matches(['.', ',', '!', '?', ':', ';']);
+        if !url.starts_with(server) || mentions.iter().any(|m| m == url) {
+            continue;
+        }
+        let is_agent = store.get_resource(url).is_ok_and(|r| {
+            r.get(urls::IS_A)
+                .and_then(|classes| classes.to_subjects(None))
+                .is_ok_and(|classes| classes.iter().any(|c| c == urls::AGENT))
+        });
+        if is_agent {
+            mentions.push(url.to_string());
+            if mentions.len() >= MAX_MENTIONS {
+                break;
+            }
+        }
+    }
+    mentions
+}
+
+/// Notifies the Agent that an Invite is meant for.
+#[tracing::instrument(skip(store))]
+pub fn notify_invite(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    invite: &Resource,
+) -> AtomicResult<()> {
+    if commit.previous_commit.is_some() {
+        return Ok(());
+    }
+    if let Ok(agent) = invite.get(urls::INVITE_AGENT) {
+        notify(
+            store,
+            &agent.to_string(),
+            "invite",
+            invite.get_subject(),
+            &commit.signer,
+        )?;
+    }
+    Ok(())
+}
+
+/// Notifies the Agents that have been given read or write rights by the Commit.
+#[tracing::instrument(skip(store, commit_response))]
+pub fn notify_shares(store: &impl Storelike, commit_response: &CommitResponse) -> AtomicResult<()> {
+    let Some(resource_new) = &commit_response.resource_new else {
+        return Ok(());
+    };
+    let is_notification = resource_new
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == urls::NOTIFICATION));
+    if is_notification {
+        return Ok(());
+    }
+    let agents = |resource: Option<&Resource>, right: &str| -> Vec<String> {
+        resource
+            .and_then(|r| r.get(right).ok())
+            .and_then(|v| v.to_subjects(None).ok())
+            .unwrap_or_default()
+    };
+    let subject = resource_new.get_subject();
+    let mut shared_with: Vec<String> = Vec::new();
+    for right in [urls::READ, urls::WRITE] {
+        let before = agents(commit_response.resource_old.as_ref(), right);
+        for agent in agents(Some(resource_new), right) {
+            // Agents that get rights to themselves, such as new Agents, are not notified
+            if !before.contains(&agent) && &agent != subject && !shared_with.contains(&agent) {
+                shared_with.push(agent);
+            }
+        }
+    }
+    for agent in shared_with {
+        notify(
+            store,
+            &agent,
+            "share",
+            subject,
+            &commit_response.commit_struct.signer,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn notifies_and_marks_read() {
+        let store = Db::init_temp("notifies_and_marks_read").unwrap();
+        store.populate().unwrap();
+        let owner = store.get_default_agent().unwrap();
+        let friend = store.create_agent(Some("friend")).unwrap();
+        let stranger = store.create_agent(Some("stranger")).unwrap();
+
+        let subject = format!("{}/shared", store.get_server_url());
+        let mut document = Resource::new(subject.clone());
+        document
+            .set_propval_string(urls::PARENT.into(), store.get_server_url(), &store)
+            .unwrap();
+        document.set_propval_unsafe(urls::READ.into(), vec![friend.subject.clone()].into());
+        document.save_locally(&store).unwrap();
+
+        let inbox_url = format!("{}/inbox", store.get_server_url());
+        let inbox = construct_inbox(&store, &inbox_url, &friend.subject).unwrap();
+        assert_eq!(inbox.get(urls::UNREAD_COUNT).unwrap().to_int().unwrap(), 1);
+        let notification = get_notifications(&store, &friend.subject).unwrap()[0].clone();
+        assert_eq!(
+            notification
+                .get(urls::NOTIFICATION_TARGET)
+                .unwrap()
+                .to_string(),
+            subject
+        );
+        assert_eq!(
+            notification
+                .get(urls::NOTIFICATION_ACTOR)
+                .unwrap()
+                .to_string(),
+            owner.subject
+        );
+        // Notifications can only be read by their recipient
+        crate::hierarchy::check_read(&store, &notification, &friend.subject).unwrap();
+        crate::hierarchy::check_read(&store, &notification, &stranger.subject).unwrap_err();
+
+        // Sharing it again does not send another notification
+        document
+            .set_propval_string(urls::DESCRIPTION.into(), "Edited", &store)
+            .unwrap();
+        document.save_locally(&store).unwrap();
+        assert_eq!(get_notifications(&store, &friend.subject).unwrap().len(), 1);
+
+        let read_url = url::Url::parse(&format!("{}/inbox/read", store.get_server_url())).unwrap();
+        let mark_read = |agent: &str| {
+            handle_read_post(HandlePostContext {
+                subject: read_url.clone(),
+                store: &store,
+                for_agent: Some(agent),
+                body: Vec::new(),
+            })
+        };
+        mark_read(urls::PUBLIC_AGENT).unwrap_err();
+        let inbox = mark_read(&friend.subject).unwrap();
+        assert_eq!(inbox.get(urls::UNREAD_COUNT).unwrap().to_int().unwrap(), 0);
+        assert_eq!(
+            inbox
+                .get(urls::COLLECTION_MEMBER_COUNT)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn finds_mentions() {
+        let store = Db::init_temp("finds_mentions").unwrap();
+        let friend = store.create_agent(Some("friend")).unwrap();
+        let text = format!(
+            "Hi [friend]({}), see {} and {}.",
+            friend.subject,
+            store.get_server_url(),
+            friend.subject
+        );
+        assert_eq!(find_mentions(&store, &text), vec![friend.subject]);
+    }
+}
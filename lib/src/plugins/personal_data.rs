@@ -0,0 +1,306 @@
+/*!
+# Personal data
+Helps operators answer data protection requests (such as those of the GDPR) for a single Agent.
+
+- [collect] gathers everything the Agent created or is referenced by: the Agent itself, the resources it created (including Files), the resources that refer to it, and the Commits it signed.
+  Resources that refer to the Agent are only included if the requesting Agent can read them, since they usually belong to others.
+- [erase] removes that data. Resources created by the Agent are removed together with all of their Commits, so no Commit chain is left pointing to a missing Commit.
+  Commits that the Agent signed for resources of others are kept, because the history of those resources depends on them.
+  The Agent itself is replaced by an anonymous, disabled Agent with the same subject and public key, so these Commits stay verifiable.
+
+Atomic-Server exposes these as `GET /personal-data?agent={subject}`, which downloads a zip archive,
+and `POST /personal-data/erase?agent={subject}`.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    db::commit_log::{iter_commit_log, CommitLogEntry},
+    errors::AtomicResult,
+    hierarchy::check_read,
+    storelike::Query,
+    urls, Db, Resource, Storelike, Value,
+};
+
+/// Everything that is stored about an Agent.
+#[derive(Debug)]
+pub struct PersonalData {
+    pub agent: Resource,
+    /// Resources that the Agent created, or that refer to the Agent.
+    pub resources: Vec<Resource>,
+    /// Commits that were signed by the Agent, newest first.
+    pub commits: Vec<Resource>,
+}
+
+impl PersonalData {
+    /// The Files among the resources, of which the contents should be exported too.
+    pub fn files(&self) -> impl Iterator<Item = &Resource> {
+        self.resources
+            .iter()
+            .filter(|resource| has_class(resource, urls::FILE))
+    }
+}
+
+/// The result of [erase].
+#[derive(Debug, Default)]
+pub struct Erasure {
+    /// Subjects of the resources that were removed, not including their Commits.
+    pub removed: Vec<String>,
+    /// The `internalId`s of the removed Files, which still have to be removed from the file store.
+    /// Uploads with the same contents share a blob, so blobs that other Files still refer to are not included.
+    pub files: Vec<String>,
+    /// Commits signed by the Agent that were kept, because other resources depend on them.
+    pub kept_commits: usize,
+}
+
+fn has_class(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == class))
+        .unwrap_or(false)
+}
+
+fn refers_to(resource: &Resource, agent: &str) -> bool {
+    resource.get_propvals().values().any(|value| match value {
+        Value::AtomicUrl(url) => url == agent,
+        Value::ResourceArray(_) => value
+            .to_subjects(None)
+            .map(|subjects| subjects.iter().any(|s| s == agent))
+            .unwrap_or(false),
+        _ => false,
+    })
+}
+
+/// The subjects of the resources for which the Agent signed the first Commit, not including the Agent itself.
+pub fn created_by(store: &Db, agent: &str) -> AtomicResult<HashSet<String>> {
+    // The log is ordered newest first, so the last signer we see for a resource is its creator
+    let mut creators: HashMap<String, String> = HashMap::new();
+    for entry in iter_commit_log(store, None, None, None) {
+        let entry = entry?;
+        creators.insert(entry.target, entry.signer);
+    }
+    Ok(creators
+        .into_iter()
+        .filter(|(target, creator)| creator == agent && target != agent)
+        .map(|(target, _creator)| target)
+        .collect())
+}
+
+/// Gathers everything that is stored about the Agent, see the module docs.
+/// `for_agent` is the Agent that requests the data, which is the Agent itself or an admin.
+pub fn collect(store: &Db, agent: &str, for_agent: &str) -> AtomicResult<PersonalData> {
+    let agent_resource = super::admin::get_agent(store, agent)?;
+    let created = created_by(store, agent)?;
+    let resources = store
+        .all_resources(false)
+        .filter(|resource| {
+            resource.get_subject() != agent
+                && !has_class(resource, urls::COMMIT)
+                && (created.contains(resource.get_subject())
+                    || (refers_to(resource, agent)
+                        && check_read(store, resource, for_agent).is_ok()))
+        })
+        .collect();
+    let mut commits = Vec::new();
+    for entry in iter_commit_log(store, None, None, None) {
+        let entry = entry?;
+        if entry.signer == agent {
+            commits.push(store.get_resource(&entry.commit)?);
+        }
+    }
+    Ok(PersonalData {
+        agent: agent_resource,
+        resources,
+        commits,
+    })
+}
+
+/// Removes the personal data of the Agent, see the module docs.
+pub fn erase(store: &Db, agent: &str) -> AtomicResult<Erasure> {
+    if agent == store.get_default_agent()?.subject {
+        return Err("The default Agent of the server can not be erased".into());
+    }
+    let agent_resource = super::admin::get_agent(store, agent)?;
+    let entries: Vec<CommitLogEntry> =
+        iter_commit_log(store, None, None, None).collect::<AtomicResult<_>>()?;
+
+    let mut targets = created_by(store, agent)?;
+    // The Notifications of the Agent are created by the server, but they are part of its inbox
+    let notifications = Query::new_prop_val(urls::NOTIFICATION_RECIPIENT, agent);
+    targets.extend(store.query(&notifications)?.subjects);
+    targets.insert(agent.to_string());
+
+    let mut erasure = Erasure::default();
+    let mut internal_ids = Vec::new();
+    for entry in &entries {
+        if targets.contains(&entry.target) {
+            store.remove_resource(&entry.commit)?;
+        } else if entry.signer == agent {
+            erasure.kept_commits += 1;
+        }
+    }
+    for target in &targets {
+        // Resources can be destroyed already
+        let Ok(resource) = store.get_resource(target) else {
+            continue;
+        };
+        if let Ok(internal_id) = resource.get(urls::INTERNAL_ID) {
+            internal_ids.push(internal_id.to_string());
+        }
+        store.remove_resource(target)?;
+        if target != agent {
+            erasure.removed.push(target.clone());
+        }
+    }
+    for internal_id in internal_ids {
+        let shared = store
+            .query(&Query::new_prop_val(urls::INTERNAL_ID, &internal_id))?
+            .count
+            > 0;
+        if !shared && !erasure.files.contains(&internal_id) {
+            erasure.files.push(internal_id);
+        }
+    }
+
+    // Keep the public keys, so the Commits that were kept can still be verified
+    let mut anonymous = Resource::new(agent.to_string());
+    anonymous.set_class(urls::AGENT);
+    for property in [
+        urls::PUBLIC_KEY,
+        urls::DEVICE_KEYS,
+        urls::KEY_HISTORY,
+        urls::CREATED_AT,
+    ] {
+        if let Ok(value) = agent_resource.get(property) {
+            anonymous.set_propval(property.into(), value.clone(), store)?;
+        }
+    }
+    anonymous.set_propval_string(urls::NAME.into(), "Erased Agent", store)?;
+    anonymous.set_propval(urls::AGENT_DISABLED.into(), Value::Boolean(true), store)?;
+    anonymous.save_locally(store)?;
+    Ok(erasure)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::Agent, commit::CommitBuilder};
+
+    #[test]
+    fn collects_and_erases() {
+        let store = Db::init_temp("collects_and_erases").unwrap();
+        store.populate().unwrap();
+        let agent = Agent::new(Some("Alice"), &store).unwrap();
+        let mut agent_resource = agent.to_resource().unwrap();
+        agent_resource.save_locally(&store).unwrap();
+        let opts = crate::commit::CommitOpts {
+            validate_schema: false,
+            validate_signature: true,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+        };
+        let sign = |subject: &str, name: &str| {
+            let resource = store
+                .get_resource(subject)
+                .unwrap_or_else(|_| Resource::new(subject.into()));
+            let mut builder = CommitBuilder::new(subject.into());
+            builder.set(urls::NAME.into(), Value::String(name.into()));
+            let commit = builder.sign(&agent, &store, &resource).unwrap();
+            commit.apply_opts(&store, &opts).unwrap();
+        };
+
+        // Alice creates a diary, and renames the drive of the server
+        let diary = format!("{}/diary", store.get_server_url());
+        sign(&diary, "Dear diary");
+        sign(&diary, "Dear diary, again");
+        let drive = store.get_server_url().to_string();
+        sign(&drive, "Renamed by Alice");
+        // Someone else refers to Alice, which also sends her a Notification
+        let mut mention = Resource::new(format!("{}/mention", drive));
+        mention.set_propval_unsafe(urls::WRITE.into(), vec![agent.subject.clone()].into());
+        mention.set_propval_unsafe(urls::READ.into(), vec![agent.subject.clone()].into());
+        mention.save_locally(&store).unwrap();
+        // ... but Alice can't read this one, so it is not exported
+        let mut secret = Resource::new(format!("{}/secret", drive));
+        secret.set_propval_unsafe(
+            urls::DESTINATION.into(),
+            Value::AtomicUrl(agent.subject.clone()),
+        );
+        secret.save_locally(&store).unwrap();
+
+        let data = collect(&store, &agent.subject, &agent.subject).unwrap();
+        assert_eq!(data.agent.get_subject(), &agent.subject);
+        let notification = data
+            .resources
+            .iter()
+            .find(|r| has_class(r, urls::NOTIFICATION))
+            .unwrap()
+            .get_subject()
+            .clone();
+        let subjects: HashSet<&String> = data.resources.iter().map(|r| r.get_subject()).collect();
+        assert_eq!(
+            subjects,
+            HashSet::from([&diary, mention.get_subject(), &notification])
+        );
+        assert_eq!(data.commits.len(), 3);
+        assert_eq!(data.files().count(), 0);
+
+        let mut erasure = erase(&store, &agent.subject).unwrap();
+        erasure.removed.sort();
+        assert_eq!(erasure.removed, [diary.clone(), notification]);
+        assert_eq!(erasure.kept_commits, 1);
+        store.get_resource(&diary).unwrap_err();
+        store.get_resource(mention.get_subject()).unwrap();
+        assert!(!iter_commit_log(&store, None, None, None).any(|e| e.unwrap().target == diary));
+
+        // The Agent is still there, but without its name, and with a fresh history
+        let erased = store.get_resource(&agent.subject).unwrap();
+        assert_eq!(erased.get(urls::NAME).unwrap().to_string(), "Erased Agent");
+        assert!(crate::agents::is_disabled(&store, &agent.subject));
+        let last_commit = erased.get(urls::LAST_COMMIT).unwrap().to_string();
+        let last_commit = store.get_resource(&last_commit).unwrap();
+        last_commit.get(urls::PREVIOUS_COMMIT).unwrap_err();
+        assert_eq!(
+            store
+                .get_resource(&drive)
+                .unwrap()
+                .get(urls::NAME)
+                .unwrap()
+                .to_string(),
+            "Renamed by Alice"
+        );
+
+        erase(&store, &store.get_default_agent().unwrap().subject).unwrap_err();
+    }
+
+    #[test]
+    fn erases_files_that_are_not_shared() {
+        let store = Db::init_temp("erases_files_that_are_not_shared").unwrap();
+        let agent = Agent::new(Some("Alice"), &store).unwrap();
+        agent.to_resource().unwrap().save_locally(&store).unwrap();
+        let file = |name: &str, internal_id: &str, for_agent: Option<&str>| {
+            let mut file = Resource::new(format!("{}/files/{}", store.get_server_url(), name));
+            file.set_class(urls::FILE);
+            file.set_propval_unsafe(urls::INTERNAL_ID.into(), Value::String(internal_id.into()));
+            file.set_propval_unsafe(urls::DOWNLOAD_URL.into(), Value::String(name.into()));
+            match for_agent {
+                Some(for_agent) => file.save_on_behalf_of(&store, for_agent).unwrap(),
+                None => file.save_locally(&store).unwrap(),
+            };
+        };
+        // Alice uploads a file that someone else uploaded before, which shares its blob
+        file("theirs", "1-shared.txt", None);
+        file("shared", "1-shared.txt", Some(&agent.subject));
+        file("own", "2-own.txt", Some(&agent.subject));
+
+        let data = collect(&store, &agent.subject, &agent.subject).unwrap();
+        assert_eq!(data.files().count(), 2);
+        let erasure = erase(&store, &agent.subject).unwrap();
+        assert_eq!(erasure.removed.len(), 2);
+        assert_eq!(erasure.files, ["2-own.txt"]);
+    }
+}
@@ -0,0 +1,346 @@
+/*!
+# Quotas
+Limits how much a Drive can use, so public servers can offer free tiers without being filled up.
+A [Quota] limits the amount of resources in a Drive, the total size of its Files and the amount of Commits per day.
+
+- The server sets a default Quota for all Drives, see [Db::set_default_quota].
+- Admins of the server can override it for a single Drive in a [urls::QUOTA] resource at `{drive}/settings/quota`.
+  Owners of other Drives can not change their own Quota, only Agents with write rights to the Drive of the server can.
+- Commits that would exceed the Quota fail with a [crate::AtomicErrorType::QuotaExceeded] error. Destroying resources is always allowed.
+- The current usage is kept up to date in the store, see [crate::db::usage], and shown at `/usage?drive={subject}`.
+*/
+
+use crate::{
+    db::usage::{find_drive, get_usage},
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    urls, AtomicError, Db, Resource, Storelike, Value,
+};
+
+/// The limits of a Drive. Limits that are `None` are not enforced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub max_resources: Option<u64>,
+    /// In bytes
+    pub max_file_size: Option<u64>,
+    /// Days start at midnight in UTC
+    pub max_commits_per_day: Option<u64>,
+}
+
+/// What a Drive currently uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Including the Drive itself
+    pub resources: u64,
+    /// The total size of the Files, in bytes
+    pub file_size: u64,
+    pub commits_today: u64,
+}
+
+pub fn usage_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/usage".to_string(),
+        params: [urls::USAGE_DRIVE.to_string()].into(),
+        description: "Shows how many resources, bytes of Files and Commits of today a `drive` uses, and its quota. Requires read rights for the Drive.".to_string(),
+        shortname: "usage".to_string(),
+        handle: Some(handle_usage_request),
+        handle_post: None,
+    }
+}
+
+pub fn quota_subject(drive: &str) -> String {
+    format!("{}/settings/quota", drive.trim_end_matches('/'))
+}
+
+/// The Quota of the Drive. Limits that the Drive does not set itself come from the default Quota of the server.
+pub fn get_quota(store: &Db, drive: &str) -> Quota {
+    let default = store.get_default_quota();
+    let Ok(settings) = store.get_resource(&quota_subject(drive)) else {
+        return default;
+    };
+    let limit = |property: &str| {
+        settings
+            .get(property)
+            .and_then(|v| v.to_int())
+            .ok()
+            .map(|limit| limit.max(0) as u64)
+    };
+    Quota {
+        max_resources: limit(urls::QUOTA_MAX_RESOURCES).or(default.max_resources),
+        max_file_size: limit(urls::QUOTA_MAX_FILE_SIZE).or(default.max_file_size),
+        max_commits_per_day: limit(urls::QUOTA_MAX_COMMITS_PER_DAY).or(default.max_commits_per_day),
+    }
+}
+
+fn file_size(resource: &Resource) -> u64 {
+    resource
+        .get(urls::FILESIZE)
+        .and_then(|v| v.to_int())
+        .map(|size| size.max(0) as u64)
+        .unwrap_or(0)
+}
+
+fn check_limit(
+    drive: &str,
+    limit: Option<u64>,
+    used: u64,
+    adding: u64,
+    what: &str,
+) -> AtomicResult<()> {
+    match limit {
+        Some(limit) if adding > 0 && used.saturating_add(adding) > limit => {
+            Err(AtomicError::quota_exceeded(format!(
+                "Drive {} can have at most {} {}, and already has {}. Remove some, or ask an admin of the server to raise the quota at {}.",
+                drive,
+                limit,
+                what,
+                used,
+                quota_subject(drive)
+            ))
+            .set_subject(drive))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns an error if the Drive of `resource_new` can not fit the change. `resource_old` is `None` for new resources.
+/// Every change counts as a Commit.
+pub fn check_quota(
+    store: &Db,
+    resource_old: Option<&Resource>,
+    resource_new: &Resource,
+) -> AtomicResult<()> {
+    let Some(drive) = find_drive(store, resource_new) else {
+        return Ok(());
+    };
+    let quota = get_quota(store, &drive);
+    if quota == Quota::default() {
+        return Ok(());
+    }
+    let usage = get_usage(store, &drive)?;
+    check_limit(
+        &drive,
+        quota.max_commits_per_day,
+        usage.commits_today,
+        1,
+        "Commits per day",
+    )?;
+    // Resources that move to this Drive count as new
+    let old_in_drive =
+        resource_old.filter(|old| find_drive(store, old).as_deref() == Some(drive.as_str()));
+    let (added_resources, old_size) = match old_in_drive {
+        Some(old) => (0, file_size(old)),
+        None => (1, 0),
+    };
+    check_limit(
+        &drive,
+        quota.max_resources,
+        usage.resources,
+        added_resources,
+        "resources",
+    )?;
+    check_limit(
+        &drive,
+        quota.max_file_size,
+        usage.file_size,
+        file_size(resource_new).saturating_sub(old_size),
+        "bytes of Files",
+    )
+}
+
+/// Returns an error if a File of `size` bytes does not fit in the Drive of the `parent`.
+/// Used for checking uploads before they are stored.
+pub fn check_upload(store: &Db, parent: &Resource, size: u64) -> AtomicResult<()> {
+    let Some(drive) = find_drive(store, parent) else {
+        return Ok(());
+    };
+    let quota = get_quota(store, &drive);
+    let usage = get_usage(store, &drive)?;
+    check_limit(&drive, quota.max_resources, usage.resources, 1, "resources")?;
+    check_limit(
+        &drive,
+        quota.max_file_size,
+        usage.file_size,
+        size,
+        "bytes of Files",
+    )
+}
+
+/// Only admins of the server can change Quotas, otherwise owners of a Drive could raise their own.
+pub fn before_apply_commit(store: &impl Storelike, for_agent: &str) -> AtomicResult<()> {
+    let server_drive = store.get_resource(store.get_server_url())?;
+    check_write(store, &server_drive, for_agent).map_err(|_e| {
+        AtomicError::unauthorized("Only admins of the server can change Quotas.".into())
+    })?;
+    Ok(())
+}
+
+fn handle_usage_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let Some(drive) = subject
+        .query_pairs()
+        .find(|(k, _v)| k == "drive")
+        .map(|(_k, v)| v.to_string())
+    else {
+        return usage_endpoint().to_resource(store);
+    };
+    // Checks the read rights
+    let drive_resource = store.get_resource_extended(&drive, false, for_agent)?;
+    if find_drive(store, &drive_resource).as_deref() != Some(drive.as_str()) {
+        return Err(format!("{} is not a Drive on this server", drive).into());
+    }
+    let usage = get_usage(store, &drive)?;
+    let quota = get_quota(store, &drive);
+    let count = |n: u64| Value::Integer(n.try_into().unwrap_or(i64::MAX));
+
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_propval_unsafe(urls::USAGE_DRIVE.into(), Value::AtomicUrl(drive));
+    resource.set_propval_unsafe(urls::USAGE_RESOURCES.into(), count(usage.resources));
+    resource.set_propval_unsafe(urls::USAGE_FILE_SIZE.into(), count(usage.file_size));
+    resource.set_propval_unsafe(urls::USAGE_COMMITS_TODAY.into(), count(usage.commits_today));
+    for (property, limit) in [
+        (urls::QUOTA_MAX_RESOURCES, quota.max_resources),
+        (urls::QUOTA_MAX_FILE_SIZE, quota.max_file_size),
+        (urls::QUOTA_MAX_COMMITS_PER_DAY, quota.max_commits_per_day),
+    ] {
+        if let Some(limit) = limit {
+            resource.set_propval_unsafe(property.into(), count(limit));
+        }
+    }
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::Agent, commit::CommitBuilder, AtomicErrorType};
+
+    #[test]
+    fn enforces_quotas() {
+        let mut store = Db::init_temp("enforces_quotas").unwrap();
+        let drive = store.get_server_url().to_string();
+        let before = get_usage(&store, &drive).unwrap();
+        assert!(before.resources > 1);
+
+        let mut note = Resource::new(format!("{}/note", drive));
+        note.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        note.set_propval_string(urls::NAME.into(), "A note", &store)
+            .unwrap();
+        note.save_locally(&store).unwrap();
+        let usage = get_usage(&store, &drive).unwrap();
+        assert_eq!(usage.resources, before.resources + 1);
+        assert!(usage.commits_today > before.commits_today);
+
+        store.set_default_quota(Quota {
+            max_resources: Some(usage.resources),
+            max_file_size: Some(100),
+            max_commits_per_day: None,
+        });
+        // Editing is still allowed, creating is not
+        note.set_propval_string(urls::NAME.into(), "Renamed", &store)
+            .unwrap();
+        note.save_locally(&store).unwrap();
+        let mut other = Resource::new(format!("{}/other", drive));
+        other.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        other
+            .set_propval_string(urls::NAME.into(), "Other", &store)
+            .unwrap();
+        let err = other.save_locally(&store).unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::QuotaExceeded));
+        let parent = store.get_resource(&drive).unwrap();
+        check_upload(&store, &parent, 10).unwrap_err();
+
+        // Destroying makes room again
+        note.destroy(&store).unwrap();
+        assert_eq!(
+            get_usage(&store, &drive).unwrap().resources,
+            usage.resources - 1
+        );
+        check_upload(&store, &parent, 10).unwrap();
+        check_upload(&store, &parent, 101).unwrap_err();
+
+        // The Quota of the Drive overrides the default
+        let mut settings = Resource::new(quota_subject(&drive));
+        settings.set_class(urls::QUOTA);
+        settings.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        settings.set_propval_unsafe(urls::QUOTA_MAX_RESOURCES.into(), Value::Integer(1000));
+        settings.save_locally(&store).unwrap();
+        other.save_locally(&store).unwrap();
+        assert_eq!(get_quota(&store, &drive).max_file_size, Some(100));
+
+        let subject = format!("{}/usage?drive={}", drive, urlencoding::encode(&drive));
+        let summary = store.get_resource_extended(&subject, false, None).unwrap();
+        assert_eq!(
+            summary
+                .get(urls::QUOTA_MAX_RESOURCES)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            1000
+        );
+        assert_eq!(
+            summary
+                .get(urls::USAGE_RESOURCES)
+                .unwrap()
+                .to_int()
+                .unwrap() as u64,
+            get_usage(&store, &drive).unwrap().resources
+        );
+    }
+
+    #[test]
+    fn only_admins_change_quotas() {
+        let store = Db::init_temp("only_admins_change_quotas").unwrap();
+        let drive = store.get_server_url().to_string();
+        let agent = Agent::new(None, &store).unwrap();
+        store.add_resource(&agent.to_resource().unwrap()).unwrap();
+        // The Agent can write the Drive that contains the Quota, but not the Drive of the server
+        let mut own_drive = Resource::new(format!("{}/own", drive));
+        own_drive.set_class(urls::DRIVE);
+        own_drive.set_propval_unsafe(urls::WRITE.into(), vec![agent.subject.clone()].into());
+        own_drive.save_locally(&store).unwrap();
+
+        let mut commit = CommitBuilder::new(quota_subject(own_drive.get_subject()));
+        commit.set(urls::IS_A.into(), vec![urls::QUOTA.to_string()].into());
+        commit.set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(own_drive.get_subject().into()),
+        );
+        commit.set(urls::QUOTA_MAX_RESOURCES.into(), Value::Integer(1_000_000));
+        let new_resource = Resource::new(quota_subject(own_drive.get_subject()));
+        let commit = commit.sign(&agent, &store, &new_resource).unwrap();
+        let opts = crate::commit::CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: true,
+            validate_for_agent: None,
+            update_index: true,
+        };
+        let err = commit.apply_opts(&store, &opts).unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::UnauthorizedError));
+
+        // Commits that the server signs on behalf of the Agent are attributed to the Agent
+        let mut commit = CommitBuilder::new(quota_subject(own_drive.get_subject()));
+        commit.set(urls::IS_A.into(), vec![urls::QUOTA.to_string()].into());
+        commit.set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(own_drive.get_subject().into()),
+        );
+        commit.on_behalf_of(agent.subject.clone());
+        let server_agent = store.get_default_agent().unwrap();
+        let commit = commit.sign(&server_agent, &store, &new_resource).unwrap();
+        let opts = crate::commit::CommitOpts {
+            validate_for_agent: Some(server_agent.subject),
+            ..opts
+        };
+        let err = commit.apply_opts(&store, &opts).unwrap_err();
+        assert!(err.message.contains("Only admins of the server can change Quotas"));
+    }
+}
@@ -0,0 +1,242 @@
+/*!
+# Renaming resources
+Changes the last part of the URL (the slug) of a resource, using `POST /rename?subject={resource}&slug={new slug}`.
+Requires write rights for the resource.
+
+The resource is copied to its new subject, and resources that refer to the old subject are updated to refer to the new one,
+if the Agent has write rights for them. The others are listed in the `kept-references` of the response.
+The old subject becomes a [Redirect](https://atomicdata.dev/classes/Redirect) to the new one, so links from elsewhere keep working.
+The Commits of the old subject stay where they are, since they are signed,
+but [crate::plugins::versioning] follows these Redirects, so the history of the resource is kept.
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    commit::CommitBuilder,
+    datatype::DataType,
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    plugins::move_resource::{apply, rewrite_value},
+    storelike::Query,
+    urls, AtomicError, Resource, Storelike, Value,
+};
+
+pub fn rename_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/rename".to_string(),
+        params: [urls::SUBJECT.to_string(), urls::RENAME_SLUG.to_string()].into(),
+        description: "Changes the last part of the URL of a resource. POST with the `subject` of the resource and the new `slug` as query params. Requires write rights for the resource. References to the old URL are updated in the resources that you can edit, and the old URL redirects to the new one. Returns the renamed resource, with the resources that still refer to the old URL in `kept-references`.".to_string(),
+        shortname: "rename".to_string(),
+        handle: Some(handle_rename_get),
+        handle_post: Some(handle_rename_post),
+    }
+}
+
+fn handle_rename_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    rename_endpoint().to_resource(context.store)
+}
+
+#[tracing::instrument]
+fn handle_rename_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        body: _,
+    } = context;
+    let mut target = None;
+    let mut slug = None;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "subject" | urls::SUBJECT => target = Some(v.to_string()),
+            "slug" | urls::RENAME_SLUG => slug = Some(v.to_string()),
+            other => return Err(format!("Invalid query param: {}", other).into()),
+        }
+    }
+    let target = target.ok_or("No `subject` query param given")?;
+    let slug = slug.ok_or("No `slug` query param given")?;
+    let agent = for_agent
+        .filter(|agent| *agent != urls::PUBLIC_AGENT)
+        .ok_or_else(|| AtomicError::unauthorized("Sign in to rename resources".into()))?;
+    rename_resource(store, &target, &slug, agent)
+}
+
+/// Gives the resource a new slug, and returns the renamed resource.
+/// Leaves a Redirect at the old subject. See the [module docs](self).
+/// The returned resource lists the resources that `for_agent` can't edit, and that still refer to the old subject, in `kept-references`.
+#[tracing::instrument(skip(store))]
+pub fn rename_resource(
+    store: &impl Storelike,
+    subject: &str,
+    slug: &str,
+    for_agent: &str,
+) -> AtomicResult<Resource> {
+    Value::new(slug, &DataType::Slug)?;
+    let url = url::Url::parse(subject)?;
+    if url.path() == "/" || url.query().is_some() {
+        return Err(format!("{} has no slug that can be renamed", subject).into());
+    }
+    let (base, _old_slug) = subject
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .ok_or_else(|| format!("{} has no slug that can be renamed", subject))?;
+    let new_subject = format!("{}/{}", base, slug);
+    if new_subject == subject {
+        return Err(format!("{} already has this slug", subject).into());
+    }
+    if store.get_resource(&new_subject).is_ok() {
+        return Err(format!("{} already exists", new_subject).into());
+    }
+    let resource = store.get_resource(subject)?;
+    check_write(store, &resource, for_agent)?;
+    let subjects = HashMap::from([(subject.to_string(), new_subject.clone())]);
+
+    let mut builder = CommitBuilder::new(new_subject.clone());
+    for (prop, value) in resource.get_propvals() {
+        if prop != urls::LAST_COMMIT {
+            let value = rewrite_value(value, &subjects).unwrap_or(value.clone());
+            builder.set(prop.clone(), value);
+        }
+    }
+    apply(
+        store,
+        builder,
+        &Resource::new(new_subject.clone()),
+        for_agent,
+    )?;
+
+    // The Agent can only update the references in resources it can edit, the others keep using the Redirect
+    let mut kept_references = Vec::new();
+    let mut query = Query::new();
+    query.value = Some(Value::AtomicUrl(subject.into()));
+    for referencing in store.query(&query)?.resources {
+        if referencing.get_subject() == subject || is_commit(&referencing) {
+            continue;
+        }
+        if check_write(store, &referencing, for_agent).is_err() {
+            kept_references.push(referencing.get_subject().to_string());
+            continue;
+        }
+        let mut builder = CommitBuilder::new(referencing.get_subject().into());
+        let mut changed = false;
+        for (prop, value) in referencing.get_propvals() {
+            if let Some(new_value) = rewrite_value(value, &subjects) {
+                builder.set(prop.clone(), new_value);
+                changed = true;
+            }
+        }
+        if changed {
+            apply(store, builder, &referencing, for_agent)?;
+        }
+    }
+
+    // The Redirect keeps its parent, so it is readable by the same Agents
+    let mut builder = CommitBuilder::new(subject.into());
+    for prop in resource.get_propvals().keys() {
+        if ![urls::PARENT, urls::IS_A, urls::LAST_COMMIT].contains(&prop.as_str()) {
+            builder.remove(prop.clone());
+        }
+    }
+    builder.set(urls::IS_A.into(), vec![urls::REDIRECT].into());
+    builder.set(
+        urls::DESTINATION.into(),
+        Value::AtomicUrl(new_subject.clone()),
+    );
+    apply(store, builder, &resource, for_agent)?;
+    let mut renamed = store.get_resource(&new_subject)?;
+    if !kept_references.is_empty() {
+        renamed.set_propval_unsafe(urls::RENAME_KEPT_REFERENCES.into(), kept_references.into());
+    }
+    Ok(renamed)
+}
+
+fn is_commit(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == urls::COMMIT))
+}
+
+/// Returns the subjects that redirect to the resource, because it has been renamed.
+pub fn previous_subjects(store: &impl Storelike, subject: &str) -> AtomicResult<Vec<String>> {
+    Ok(store
+        .query(&Query::new_prop_val(urls::DESTINATION, subject))?
+        .resources
+        .iter()
+        .filter(|r| {
+            r.get(urls::IS_A)
+                .and_then(|classes| classes.to_subjects(None))
+                .is_ok_and(|classes| classes.iter().any(|c| c == urls::REDIRECT))
+        })
+        .map(|r| r.get_subject().to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn renames_and_redirects() {
+        let store = Db::init_temp("renames_and_redirects").unwrap();
+        let drive = store.get_server_url().to_string();
+        let writer = store.create_agent(Some("writer")).unwrap();
+        let mut folder = Resource::new(format!("{}/folder", drive));
+        folder.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        folder.set_propval_unsafe(urls::WRITE.into(), vec![writer.subject.clone()].into());
+        store.add_resource(&folder).unwrap();
+        let folder = folder.get_subject().to_string();
+        let old = format!("{}/draft", folder);
+        let mut builder = CommitBuilder::new(old.clone());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        builder.set(urls::NAME.into(), Value::String("Draft".into()));
+        apply(
+            &store,
+            builder,
+            &Resource::new(old.clone()),
+            &writer.subject,
+        )
+        .unwrap();
+        let mut child = Resource::new(format!("{}/child", old));
+        child.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(old.clone()));
+        store.add_resource(&child).unwrap();
+        // The writer can't edit this resource, so it keeps referring to the old subject
+        let mut elsewhere = Resource::new(format!("{}/elsewhere", drive));
+        elsewhere.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        elsewhere.set_propval_unsafe(urls::DESTINATION.into(), Value::AtomicUrl(old.clone()));
+        store.add_resource(&elsewhere).unwrap();
+
+        rename_resource(&store, &old, "Not a slug", &writer.subject).unwrap_err();
+        rename_resource(&store, &old, "final", "https://example.com/agents/nobody").unwrap_err();
+        let renamed = rename_resource(&store, &old, "final", &writer.subject).unwrap();
+        let new = format!("{}/final", folder);
+        assert_eq!(renamed.get_subject(), &new);
+        assert_eq!(renamed.get(urls::NAME).unwrap().to_string(), "Draft");
+        assert_eq!(
+            renamed
+                .get(urls::RENAME_KEPT_REFERENCES)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![elsewhere.get_subject().to_string()]
+        );
+        rename_resource(&store, &new, "final", &writer.subject).unwrap_err();
+
+        let redirect = store.get_resource(&old).unwrap();
+        assert_eq!(redirect.get(urls::DESTINATION).unwrap().to_string(), new);
+        redirect.get(urls::NAME).unwrap_err();
+        let child = store.get_resource(child.get_subject()).unwrap();
+        assert_eq!(child.get(urls::PARENT).unwrap().to_string(), new);
+        let elsewhere = store.get_resource(elsewhere.get_subject()).unwrap();
+        assert_eq!(elsewhere.get(urls::DESTINATION).unwrap().to_string(), old);
+        assert_eq!(previous_subjects(&store, &new).unwrap(), vec![old.clone()]);
+
+        // The history of the old subject is kept
+        let initial =
+            crate::plugins::versioning::get_initial_commit_for_resource(&new, &store).unwrap();
+        assert_eq!(initial.subject, old);
+    }
+}
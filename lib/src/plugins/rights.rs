@@ -0,0 +1,152 @@
+/*!
+# Effective rights
+Explains which rights an Agent has for a resource, and which resource in the hierarchy grants or denies them.
+Available at `/rights?subject={resource}&agent={agent}`, so UIs can show why someone can or can't edit something, and admins can debug rights.
+
+The `agent` defaults to the Agent that signed the request, or the PublicAgent.
+Only Agents that can read the resource can see its rights, since these are visible in the resource anyway.
+See [crate::hierarchy::check_rights] for how rights are inherited.
+*/
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    hierarchy::{check_read, effective_rights},
+    resources::PropVals,
+    urls,
+    values::SubResource,
+    Resource, Storelike, Value,
+};
+
+pub fn rights_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/rights".to_string(),
+        params: [
+            urls::SUBJECT.to_string(),
+            urls::EFFECTIVE_RIGHTS_AGENT.to_string(),
+        ]
+        .into(),
+        description: "Shows which rights an `agent` has for a resource (the `subject`), and the path through its parents that grants or denies each right. Defaults to the Agent that makes the request.".to_string(),
+        shortname: "rights".to_string(),
+        handle: Some(handle_rights_request),
+        handle_post: None,
+    }
+}
+
+fn handle_rights_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let param = |name: &str| {
+        subject
+            .query_pairs()
+            .find(|(k, _v)| k == name)
+            .map(|(_k, v)| v.to_string())
+    };
+    let Some(target) = param("subject") else {
+        return rights_endpoint().to_resource(store);
+    };
+    let target_resource = store.get_resource(&target)?;
+    if let Some(requester) = for_agent {
+        check_read(store, &target_resource, requester)?;
+    }
+    let agent = param("agent")
+        .or_else(|| for_agent.map(String::from))
+        .unwrap_or_else(|| urls::PUBLIC_AGENT.into());
+
+    let mut granted: Vec<String> = Vec::new();
+    let mut explanations: Vec<SubResource> = Vec::new();
+    for effective in effective_rights(store, &target_resource, &agent)? {
+        let right = effective.right.to_string();
+        let (allowed, explanation) = match effective.result {
+            Ok(msg) => (true, msg),
+            Err(e) => (false, e.to_string()),
+        };
+        if allowed {
+            granted.push(right.clone());
+        }
+        let mut propvals = PropVals::new();
+        propvals.insert(urls::EFFECTIVE_RIGHTS_RIGHT.into(), Value::AtomicUrl(right));
+        propvals.insert(
+            urls::EFFECTIVE_RIGHTS_ALLOWED.into(),
+            Value::Boolean(allowed),
+        );
+        propvals.insert(urls::DESCRIPTION.into(), Value::Markdown(explanation));
+        propvals.insert(urls::EFFECTIVE_RIGHTS_PATH.into(), effective.path.into());
+        explanations.push(SubResource::Nested(propvals));
+    }
+
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_class(urls::EFFECTIVE_RIGHTS);
+    resource.set_propval_unsafe(urls::SUBJECT.into(), Value::AtomicUrl(target));
+    resource.set_propval_unsafe(urls::EFFECTIVE_RIGHTS_AGENT.into(), Value::AtomicUrl(agent));
+    resource.set_propval_unsafe(urls::EFFECTIVE_RIGHTS_GRANTED.into(), granted.into());
+    resource.set_propval_unsafe(
+        urls::EFFECTIVE_RIGHTS_EXPLANATIONS.into(),
+        explanations.into(),
+    );
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn explains_effective_rights() {
+        let store = Db::init_temp("explains_effective_rights").unwrap();
+        let drive = store.get_server_url().to_string();
+        let agent = format!("{}/agents/reader", drive);
+        let mut folder = Resource::new(format!("{}/folder", drive));
+        folder.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        folder.set_propval_unsafe(urls::READ.into(), vec![agent.clone()].into());
+        folder.set_propval_unsafe(urls::DENY_READ.into(), vec![urls::PUBLIC_AGENT].into());
+        store.add_resource(&folder).unwrap();
+        let mut document = Resource::new(format!("{}/folder/document", drive));
+        document.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(folder.get_subject().into()),
+        );
+        store.add_resource(&document).unwrap();
+
+        let rights = effective_rights(&store, &document, &agent).unwrap();
+        let read = rights
+            .iter()
+            .find(|r| matches!(r.right, crate::hierarchy::Right::Read))
+            .unwrap();
+        read.result.as_ref().unwrap();
+        assert_eq!(
+            read.path,
+            vec![document.get_subject().clone(), folder.get_subject().clone()]
+        );
+        let write = rights
+            .iter()
+            .find(|r| matches!(r.right, crate::hierarchy::Right::Write))
+            .unwrap();
+        write.result.as_ref().unwrap_err();
+        assert_eq!(write.path.last(), Some(&drive));
+
+        let subject = format!(
+            "{}/rights?subject={}&agent={}",
+            drive,
+            urlencoding::encode(document.get_subject()),
+            urlencoding::encode(&agent)
+        );
+        let resource = store.get_resource_extended(&subject, false, None).unwrap();
+        assert_eq!(
+            resource
+                .get(urls::EFFECTIVE_RIGHTS_GRANTED)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![urls::READ.to_string()]
+        );
+        // Agents that can't read the resource can't see its rights
+        store
+            .get_resource_extended(&subject, false, Some(urls::PUBLIC_AGENT))
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,272 @@
+/*!
+# Schema usage
+Shows how a Property or Class is used, so the maintainers of an ontology can see what would break before they change it.
+Available at `/schema-usage?subject={property or class}`.
+
+- For a Property: how many resources have it, some example values, and the values that do not match its datatype or `allowsOnly`.
+- For a Class: how many resources are an instance of it, some examples, and the instances that miss one of its required Properties.
+
+The resources are found by scanning the value index, see [Db::subjects_with_property] and [Db::subjects_with_class].
+Only the resources that the Agent can read are counted.
+*/
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    hierarchy::check_read,
+    resources::PropVals,
+    schema::{Class, Property},
+    urls,
+    values::SubResource,
+    Db, Resource, Storelike, Value,
+};
+
+const MAX_EXAMPLES: usize = 5;
+const MAX_VIOLATIONS: usize = 20;
+
+pub fn schema_usage_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/schema-usage".to_string(),
+        params: [urls::SUBJECT.to_string()].into(),
+        description: "Shows how many resources use a Property or Class (the `subject`), some examples, and the resources that do not match it, such as values with another datatype or instances without a required Property. Use this before changing an ontology.".to_string(),
+        shortname: "schema-usage".to_string(),
+        handle: Some(handle_schema_usage_request),
+        handle_post: None,
+    }
+}
+
+/// How a Property or Class is used.
+#[derive(Debug, Default)]
+pub struct SchemaUsage {
+    pub count: usize,
+    /// Subjects of some of the resources that use it, and their value for the Property.
+    pub examples: Vec<(String, Option<String>)>,
+    /// Subjects of the first resources that do not match, and what is wrong with them.
+    pub violations: Vec<(String, String)>,
+    pub violation_count: usize,
+}
+
+impl SchemaUsage {
+    fn add_violation(&mut self, subject: &str, problem: String) {
+        self.violation_count += 1;
+        if self.violations.len() < MAX_VIOLATIONS {
+            self.violations.push((subject.into(), problem));
+        }
+    }
+}
+
+/// Yields the resources that the Agent can read. Resources that are missing from the store are skipped.
+fn readable<'a>(
+    store: &'a Db,
+    subjects: impl IntoIterator<Item = String> + 'a,
+    for_agent: Option<&'a str>,
+) -> impl Iterator<Item = Resource> + 'a {
+    subjects
+        .into_iter()
+        .filter_map(|subject| store.get_resource(&subject).ok())
+        .filter(move |resource| {
+            for_agent.is_none_or(|agent| check_read(store, resource, agent).is_ok())
+        })
+}
+
+/// Why the value does not fit the Property, if it doesn't.
+fn property_violation(property: &Property, value: &Value) -> Option<String> {
+    if value.datatype() != property.data_type {
+        return Some(format!(
+            "Has datatype {}, but the Property requires {}",
+            value.datatype(),
+            property.data_type
+        ));
+    }
+    let allowed = property.allows_only.as_ref()?;
+    let values = match value {
+        Value::ResourceArray(_) => value.to_subjects(None).ok()?,
+        other => vec![other.to_string()],
+    };
+    values
+        .iter()
+        .find(|v| !allowed.contains(v))
+        .map(|v| format!("Value {} is not allowed by allowsOnly", v))
+}
+
+pub fn property_usage(
+    store: &Db,
+    property: &Property,
+    for_agent: Option<&str>,
+) -> AtomicResult<SchemaUsage> {
+    let subjects = store.subjects_with_property(&property.subject)?;
+    let mut usage = SchemaUsage::default();
+    for resource in readable(store, subjects, for_agent) {
+        let Ok(value) = resource.get(&property.subject) else {
+            continue;
+        };
+        usage.count += 1;
+        let example = value.to_string();
+        if usage.examples.len() < MAX_EXAMPLES
+            && !usage
+                .examples
+                .iter()
+                .any(|(_s, v)| v.as_ref() == Some(&example))
+        {
+            usage
+                .examples
+                .push((resource.get_subject().clone(), Some(example)));
+        }
+        if let Some(problem) = property_violation(property, value) {
+            usage.add_violation(resource.get_subject(), problem);
+        }
+    }
+    Ok(usage)
+}
+
+pub fn class_usage(
+    store: &Db,
+    class: &Class,
+    for_agent: Option<&str>,
+) -> AtomicResult<SchemaUsage> {
+    let subjects = store.subjects_with_class(&class.subject)?;
+    let mut usage = SchemaUsage::default();
+    for resource in readable(store, subjects, for_agent) {
+        usage.count += 1;
+        if usage.examples.len() < MAX_EXAMPLES {
+            usage.examples.push((resource.get_subject().clone(), None));
+        }
+        let missing: Vec<&str> = class
+            .requires
+            .iter()
+            .filter(|required| resource.get(required).is_err())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            usage.add_violation(
+                resource.get_subject(),
+                format!("Misses required Properties {}", missing.join(", ")),
+            );
+        }
+    }
+    Ok(usage)
+}
+
+fn is_a(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == class))
+        .unwrap_or(false)
+}
+
+fn handle_schema_usage_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let Some(target) = subject
+        .query_pairs()
+        .find(|(k, _v)| k == "subject")
+        .map(|(_k, v)| v.to_string())
+    else {
+        return schema_usage_endpoint().to_resource(store);
+    };
+    let target_resource = store.get_resource(&target)?;
+    let usage = if is_a(&target_resource, urls::PROPERTY) {
+        property_usage(store, &Property::from_resource(target_resource)?, for_agent)?
+    } else if is_a(&target_resource, urls::CLASS) {
+        class_usage(store, &Class::from_resource(target_resource)?, for_agent)?
+    } else {
+        return Err(format!("{} is not a Property or a Class", target).into());
+    };
+
+    let nested = |subject: String, prop: &str, value: Option<Value>| {
+        let mut propvals = PropVals::new();
+        propvals.insert(urls::SUBJECT.into(), Value::AtomicUrl(subject));
+        if let Some(value) = value {
+            propvals.insert(prop.into(), value);
+        }
+        SubResource::Nested(propvals)
+    };
+    let examples: Vec<SubResource> = usage
+        .examples
+        .into_iter()
+        .map(|(subject, value)| nested(subject, urls::SCHEMA_USAGE_VALUE, value.map(Value::String)))
+        .collect();
+    let violations: Vec<SubResource> = usage
+        .violations
+        .into_iter()
+        .map(|(subject, problem)| {
+            nested(subject, urls::DESCRIPTION, Some(Value::Markdown(problem)))
+        })
+        .collect();
+
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_class(urls::SCHEMA_USAGE);
+    resource.set_propval_unsafe(urls::SUBJECT.into(), Value::AtomicUrl(target));
+    resource.set_propval_unsafe(
+        urls::SCHEMA_USAGE_COUNT.into(),
+        Value::Integer(usage.count as i64),
+    );
+    resource.set_propval_unsafe(urls::SCHEMA_USAGE_EXAMPLES.into(), examples.into());
+    resource.set_propval_unsafe(urls::SCHEMA_USAGE_VIOLATIONS.into(), violations.into());
+    resource.set_propval_unsafe(
+        urls::SCHEMA_USAGE_VIOLATION_COUNT.into(),
+        Value::Integer(usage.violation_count as i64),
+    );
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn analyses_schema_usage() {
+        let store = Db::init_temp("analyses_schema_usage").unwrap();
+        let drive = store.get_server_url().to_string();
+        let mut meeting = Resource::new(format!("{}/meeting", drive));
+        meeting.set_class(urls::EVENT);
+        meeting.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        meeting.set_propval_unsafe(urls::EVENT_START.into(), Value::Timestamp(1));
+        store.add_resource(&meeting).unwrap();
+        // Skips validation, like data that was imported before the ontology changed
+        let mut broken = Resource::new(format!("{}/broken", drive));
+        broken.set_class(urls::EVENT);
+        broken.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        broken.set_propval_unsafe(urls::EVENT_END.into(), Value::String("tomorrow".into()));
+        store.add_resource_opts(&broken, false, true, true).unwrap();
+
+        let event = store.get_class(urls::EVENT).unwrap();
+        let usage = class_usage(&store, &event, None).unwrap();
+        assert_eq!(usage.count, 2);
+        assert_eq!(usage.violation_count, 1);
+        assert_eq!(&usage.violations[0].0, broken.get_subject());
+
+        let end = store.get_property(urls::EVENT_END).unwrap();
+        let usage = property_usage(&store, &end, None).unwrap();
+        assert_eq!(usage.count, 1);
+        assert_eq!(usage.examples[0].1.as_deref(), Some("tomorrow"));
+        assert!(usage.violations[0].1.contains("timestamp"));
+
+        let subject = format!(
+            "{}/schema-usage?subject={}",
+            drive,
+            urlencoding::encode(urls::EVENT_START)
+        );
+        let resource = store.get_resource_extended(&subject, false, None).unwrap();
+        assert_eq!(
+            resource
+                .get(urls::SCHEMA_USAGE_COUNT)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            resource
+                .get(urls::SCHEMA_USAGE_VIOLATION_COUNT)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            0
+        );
+    }
+}
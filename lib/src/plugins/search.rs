@@ -8,8 +8,18 @@ pub fn search_endpoint() -> Endpoint {
         urls::SEARCH_QUERY.into(),
         urls::SEARCH_LIMIT.into(),
         urls::SEARCH_PROPERTY.into(),
+        urls::SEARCH_FUZZY.into(),
+        urls::SEARCH_PREFIX.into(),
+        urls::SEARCH_CLASS.into(),
+        urls::SEARCH_VALUE.into(),
+        urls::SEARCH_NEAR.into(),
+        urls::SEARCH_RADIUS.into(),
+        urls::SEARCH_BBOX.into(),
+        urls::SEARCH_PRE_TAG.into(),
+        urls::SEARCH_POST_TAG.into(),
+        urls::SEARCH_SNIPPET_LENGTH.into(),
     ],
-      description: "Full text-search endpoint. You can use the keyword `AND` and `OR`, or use `\"` for advanced searches. ".to_string(),
+      description: "Full text-search endpoint. You can use the keyword `AND` and `OR`, or use `\"` for advanced searches. Set `fuzzy` to `0`, `1`, `2` or `auto` to control how many typos are allowed per word, and `prefix=false` to only match complete words. Filter results using `parent`, `class`, or `property` with `value`. Find resources with a GeoPoint using `near=latitude,longitude` with a `radius` in meters (sorted by distance), or `bbox=south,west,north,east`. The results include the amount of matches per Class and per Drive, and a snippet of the matching text for every result. Use `pre-tag`, `post-tag` and `snippet-length` to change how matches are highlighted.".to_string(),
       shortname: "search".to_string(),
       handle: None,
       handle_post: None,
@@ -1,11 +1,15 @@
+pub use crate::resources::diff_versions;
 use tracing::warn;
 
 use crate::{
     collections::CollectionBuilder,
-    endpoints::{Endpoint, HandleGetContext},
+    commit::{CommitBuilder, CommitOpts, CommitResponse},
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
     errors::AtomicResult,
     storelike::Query,
-    urls, AtomicError, Commit, Resource, Storelike,
+    urls,
+    values::SubResource,
+    AtomicError, Commit, Resource, Storelike, Value,
 };
 
 pub fn version_endpoint() -> Endpoint {
@@ -31,6 +35,182 @@ pub fn all_versions_endpoint() -> Endpoint {
     }
 }
 
+pub fn restore_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/restore".to_string(),
+        params: [urls::RESTORE_COMMIT.to_string()].into(),
+        description: "Sets a resource back to a previous version. POST with a `commit` query param, which is the URL of a Commit or of a version. Requires write rights for the resource. Returns the restored resource.".to_string(),
+        shortname: "restore".to_string(),
+        handle: Some(handle_restore_get),
+        handle_post: Some(handle_restore_post),
+    }
+}
+
+pub fn diff_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/diff".to_string(),
+        params: [
+            urls::SUBJECT.to_string(),
+            urls::DIFF_FROM.to_string(),
+            urls::DIFF_TO.to_string(),
+        ]
+        .into(),
+        description: "Shows which properties of a resource have been added, removed or changed between two versions. Pass the Commit (or version) URLs as `from` and `to`. Without `from`, the Diff starts from an empty resource. Without `to`, the current version is used.".to_string(),
+        shortname: "diff".to_string(),
+        handle: Some(handle_diff_request),
+        handle_post: None,
+    }
+}
+
+fn handle_restore_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    restore_endpoint().to_resource(context.store)
+}
+
+#[tracing::instrument]
+fn handle_restore_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        body: _,
+    } = context;
+    let commit_url = subject
+        .query_pairs()
+        .find(|(k, _v)| k == "commit")
+        .map(|(_k, v)| v.to_string())
+        .ok_or("No `commit` query param given")?;
+    let agent = for_agent.ok_or_else(|| {
+        AtomicError::unauthorized("Sign in to restore a version of a resource".into())
+    })?;
+    let response = restore_version(&commit_url, store, agent)?;
+    response
+        .resource_new
+        .ok_or_else(|| "The restored resource is empty".into())
+}
+
+/// Returns the Commit URL of a version URL (`/versioning?commit=...`), or the URL itself if it's not a version URL.
+fn commit_from_version_url(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|parsed| {
+            parsed
+                .query_pairs()
+                .find(|(k, _v)| k == "commit")
+                .map(|(_k, v)| v.to_string())
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Sets a resource back to the state it had after the given Commit (or version URL).
+/// Creates a Commit that sets the values that have changed since, and removes the ones that have been added since.
+/// Also works for destroyed resources, as their Commits are kept.
+/// The server can't sign Commits for other Agents, so the Commit is signed by the default Agent of the store,
+/// and applied with the rights of `for_agent`.
+#[tracing::instrument(skip(store))]
+pub fn restore_version(
+    commit_url: &str,
+    store: &impl Storelike,
+    for_agent: &str,
+) -> AtomicResult<CommitResponse> {
+    let commit_url = commit_from_version_url(commit_url);
+    let version = construct_version(&commit_url, store, None)?;
+    let subject = version.get_subject().to_string();
+    let current = store
+        .get_resource(&subject)
+        .unwrap_or_else(|_destroyed| Resource::new(subject.clone()));
+
+    let mut builder = CommitBuilder::new(subject);
+    let mut changed = false;
+    for (prop, value) in version.get_propvals() {
+        if prop == urls::LAST_COMMIT {
+            continue;
+        }
+        if current.get(prop).map(|v| v.to_string()).ok() != Some(value.to_string()) {
+            builder.set(prop.clone(), value.clone());
+            changed = true;
+        }
+    }
+    for prop in current.get_propvals().keys() {
+        if prop != urls::LAST_COMMIT && version.get(prop).is_err() {
+            builder.remove(prop.clone());
+            changed = true;
+        }
+    }
+    if !changed {
+        return Err("The resource already has the values of this version".into());
+    }
+
+    let signer = store.get_default_agent()?;
+    let commit = builder.sign(&signer, store, &current)?;
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: true,
+        validate_for_agent: Some(for_agent.to_string()),
+        validate_previous_commit: false,
+        update_index: true,
+    };
+    commit.apply_opts(store, &opts)
+}
+
+#[tracing::instrument]
+fn handle_diff_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let mut target = None;
+    let mut from = None;
+    let mut to = None;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "subject" => target = Some(v.to_string()),
+            "from" => from = Some(commit_from_version_url(&v)),
+            "to" => to = Some(commit_from_version_url(&v)),
+            _other => {}
+        }
+    }
+    let Some(target) = target else {
+        return diff_endpoint().to_resource(store);
+    };
+    let from_version = match &from {
+        Some(commit_url) => construct_version(commit_url, store, for_agent)?,
+        None => Resource::new(target.clone()),
+    };
+    let to_version = match &to {
+        Some(commit_url) => construct_version(commit_url, store, for_agent)?,
+        None => store.get_resource_extended(&target, false, for_agent)?,
+    };
+    for version in [&from_version, &to_version] {
+        if version.get_subject() != &target {
+            return Err(format!(
+                "Commit is for {}, not for {}",
+                version.get_subject(),
+                target
+            )
+            .into());
+        }
+    }
+
+    let mut diff = Resource::new(subject.to_string());
+    diff.set_class(urls::DIFF);
+    diff.set_propval_unsafe(urls::SUBJECT.into(), Value::AtomicUrl(target));
+    if let Some(from) = from {
+        diff.set_propval_unsafe(urls::DIFF_FROM.into(), Value::AtomicUrl(from));
+    }
+    if let Some(to) = to {
+        diff.set_propval_unsafe(urls::DIFF_TO.into(), Value::AtomicUrl(to));
+    }
+    let changes: Vec<SubResource> = diff_versions(&from_version, &to_version)
+        .into_iter()
+        .map(SubResource::Nested)
+        .collect();
+    diff.set_propval_unsafe(urls::DIFF_CHANGES.into(), Value::ResourceArray(changes));
+    Ok(diff)
+}
+
 #[tracing::instrument]
 fn handle_version_request(context: HandleGetContext) -> AtomicResult<Resource> {
     let params = context.subject.query_pairs();
@@ -90,6 +270,8 @@ fn handle_all_versions_request(context: HandleGetContext) -> AtomicResult<Resour
 
 /// Searches the local store for all commits with this subject, returns sorted from old to new.
 #[tracing::instrument(skip(store))]
+/// Returns the Commits of the resource, oldest first.
+/// If the resource has been renamed, the Commits of its previous subjects (up to the rename) come first.
 fn get_commits_for_resource(subject: &str, store: &impl Storelike) -> AtomicResult<Vec<Commit>> {
     let mut q = Query::new_prop_val(urls::SUBJECT, subject);
     q.sort_by = Some(urls::CREATED_AT.into());
@@ -100,7 +282,25 @@ fn get_commits_for_resource(subject: &str, store: &impl Storelike) -> AtomicResu
         .filter_map(|r| crate::Commit::from_resource(r.clone()).ok())
         .collect();
 
-    Ok(filtered)
+    let mut previous = Vec::new();
+    for previous_subject in crate::plugins::rename::previous_subjects(store, subject)? {
+        let renamed = |c: &Commit| {
+            c.set
+                .as_ref()
+                .is_some_and(|s| s.contains_key(urls::DESTINATION))
+        };
+        previous.extend(
+            get_commits_for_resource(&previous_subject, store)?
+                .into_iter()
+                .take_while(|c| !renamed(c)),
+        );
+    }
+    if previous.is_empty() {
+        return Ok(filtered);
+    }
+    previous.sort_by_key(|c| c.created_at);
+    previous.extend(filtered);
+    Ok(previous)
 }
 
 #[tracing::instrument(skip(store))]
@@ -222,4 +422,81 @@ mod test {
             second_val
         );
     }
+
+    #[test]
+    fn diffs_versions() {
+        let subject = "http://localhost/diffed";
+        let mut from = Resource::new(subject.into());
+        from.set_propval_unsafe(urls::NAME.into(), Value::String("Old".into()));
+        from.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("Same".into()));
+        from.set_propval_unsafe(urls::LAST_COMMIT.into(), Value::AtomicUrl("a".into()));
+        let mut to = Resource::new(subject.into());
+        to.set_propval_unsafe(urls::NAME.into(), Value::String("New".into()));
+        to.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("Same".into()));
+        to.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("added".into()));
+        to.set_propval_unsafe(urls::LAST_COMMIT.into(), Value::AtomicUrl("b".into()));
+
+        let changes = diff_versions(&from, &to);
+        let kinds: Vec<(String, String)> = changes
+            .iter()
+            .map(|change| {
+                (
+                    change.get(urls::DIFF_PROPERTY).unwrap().to_string(),
+                    change.get(urls::DIFF_KIND).unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (urls::NAME.to_string(), "changed".to_string()),
+                (urls::SHORTNAME.to_string(), "added".to_string()),
+            ]
+        );
+        assert_eq!(
+            changes[0].get(urls::DIFF_OLD_VALUE).unwrap().to_string(),
+            "Old"
+        );
+        assert!(!changes[1].contains_key(urls::DIFF_OLD_VALUE));
+        assert!(diff_versions(&to, &to).is_empty());
+    }
+
+    #[test]
+    fn restores_versions() {
+        let store = crate::Db::init_temp("restores_versions").unwrap();
+        let agent = store.get_default_agent().unwrap();
+        let subject = format!("{}/restorable", store.get_server_url());
+        let mut resource = Resource::new(subject.clone());
+        resource
+            .set_propval_string(urls::PARENT.into(), store.get_server_url(), &store)
+            .unwrap();
+        resource.set_propval_unsafe(urls::WRITE.into(), vec![agent.subject.clone()].into());
+        resource
+            .set_propval_string(urls::DESCRIPTION.into(), "First", &store)
+            .unwrap();
+        let first_commit = resource.save_locally(&store).unwrap().commit_resource;
+        resource
+            .set_propval_string(urls::DESCRIPTION.into(), "Second", &store)
+            .unwrap();
+        resource
+            .set_propval_string(urls::NAME.into(), "Added later", &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+
+        let version_url = construct_version_endpoint_url(&store, first_commit.get_subject());
+        restore_version(&version_url, &store, &agent.subject).unwrap();
+        let restored = store.get_resource(&subject).unwrap();
+        assert_eq!(
+            restored.get(urls::DESCRIPTION).unwrap().to_string(),
+            "First"
+        );
+        assert!(restored.get(urls::NAME).is_err());
+        // Restoring again changes nothing
+        restore_version(first_commit.get_subject(), &store, &agent.subject).unwrap_err();
+
+        let stranger = store.create_agent(Some("stranger")).unwrap();
+        let last = get_commits_for_resource(&subject, &store).unwrap();
+        let second_commit = last[1].url.clone().unwrap();
+        restore_version(&second_commit, &store, &stranger.subject).unwrap_err();
+    }
 }
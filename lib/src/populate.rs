@@ -7,7 +7,7 @@ use crate::{
     datatype::DataType,
     errors::AtomicResult,
     parse::ParseOpts,
-    schema::{Class, Property},
+    schema::{Class, Constraints, Property},
     storelike::Query,
     urls, Storelike, Value,
 };
@@ -28,6 +28,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A short name of something. It can only contain letters, numbers and dashes `-`. Use dashes to denote spaces between words. Not case sensitive - lowercase only. Useful in programming contexts where the user should be able to type something short to identify a specific thing.".into(),
             subject: urls::SHORTNAME.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: None,
@@ -36,6 +40,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.".into(),
             subject: urls::DESCRIPTION.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::CLASS.into()),
@@ -44,6 +52,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A list of Classes of which the thing is an instance of. The Classes of a Resource determine which Properties are recommended and required.".into(),
             subject: urls::IS_A.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::DATATYPE_CLASS.into()),
@@ -52,6 +64,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Datatype of a property, such as String or Timestamp.".into(),
             subject: urls::DATATYPE_PROP.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::CLASS.into()),
@@ -62,6 +78,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
                .into(),
             subject: urls::CLASSTYPE_PROP.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -70,6 +90,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Properties that are not required, but recommended for this Class.".into(),
             subject: urls::RECOMMENDS.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -78,6 +102,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Properties that are required for this Class.".into(),
             subject: urls::REQUIRES.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -86,6 +114,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The parent of a Resource sets the hierarchical structure of the Resource, and therefore also the rights / grants. It is used for both navigation, structure and authorization. Parents are the inverse of [children](https://atomicdata.dev/properties/children).".into(),
             subject: urls::PARENT.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -94,20 +126,26 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "Restricts this Property to only the values inside this one. This essentially turns the Property into an `enum`.".into(),
             subject: urls::ALLOWS_ONLY.into(),
             allows_only: None,
+            constraints: Constraints::default(),
+            default_value: None,
+            expression: None,
+            unit: None,
         }
     ];
 
     let classes = vec![
         Class {
             requires: vec![urls::SHORTNAME.into(), urls::DATATYPE_PROP.into(), urls::DESCRIPTION.into()],
-            recommends: vec![urls::CLASSTYPE_PROP.into(), urls::IS_DYNAMIC.into(), urls::IS_LOCKED.into(), urls::ALLOWS_ONLY.into()],
+            recommends: vec![urls::CLASSTYPE_PROP.into(), urls::IS_DYNAMIC.into(), urls::IS_LOCKED.into(), urls::ALLOWS_ONLY.into(), urls::PATTERN.into(), urls::MINIMUM.into(), urls::MAXIMUM.into(), urls::MAX_LENGTH.into(), urls::DEFAULT_VALUE.into(), urls::EXPRESSION.into(), urls::UNIT.into()],
+            extends: Vec::new(),
             shortname: "property".into(),
             description: "A Property is a single field in a Class. It's the thing that a property field in an Atom points to. An example is `birthdate`. An instance of Property requires various Properties, most notably a `datatype` (e.g. `string` or `integer`), a human readable `description` (such as the thing you're reading), and a `shortname`.".into(),
             subject: urls::PROPERTY.into(),
         },
         Class {
             requires: vec![urls::SHORTNAME.into(), urls::DESCRIPTION.into()],
-            recommends: vec![urls::RECOMMENDS.into(), urls::REQUIRES.into()],
+            recommends: vec![urls::RECOMMENDS.into(), urls::REQUIRES.into(), urls::EXTENDS.into()],
+            extends: Vec::new(),
             shortname: "class".into(),
             description: "A Class describes an abstract concept, such as 'Person' or 'Blogpost'. It describes the data shape of data (which fields are required and recommended) and explains what the concept represents. It is convention to use Uppercase in its URL.Resources use the [is-a](https://atomicdata.dev/properties/isA) attribute to indicate which classes they are instances of. Note that in Atomic Data, a Resource can have several Classes - not just a single one.".into(),
             subject: urls::CLASS.into(),
@@ -115,6 +153,7 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
         Class {
             requires: vec![urls::SHORTNAME.into(), urls::DESCRIPTION.into()],
             recommends: vec![],
+            extends: Vec::new(),
             shortname: "datatype".into(),
             description:
                 "A Datatype describes a possible type of value, such as 'string' or 'integer'.".into(),
@@ -123,6 +162,7 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
         Class {
             requires: vec![urls::PUBLIC_KEY.into()],
             recommends: vec![urls::NAME.into(), urls::DESCRIPTION.into(), urls::DRIVES.into()],
+            extends: Vec::new(),
             shortname: "agent".into(),
             description:
                 "An Agent is a user that can create or modify data. It has two keys: a private and a public one. The private key should be kept secret. The public key is used to verify signatures (on [Commits](https://atomicdata.dev/classes/Commit)) set by the of the Agent.".into(),
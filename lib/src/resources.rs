@@ -72,6 +72,23 @@ impl Resource {
         ))?)
     }
 
+    /// Returns the values at a path of Properties through nested resources, see [crate::atoms::nested_path].
+    /// A path through a ResourceArray returns the values of all of its nested resources.
+    /// A path with a single Property returns its value, like [Resource::get].
+    pub fn get_nested_values(&self, path: &str) -> Vec<&Value> {
+        let mut properties = path.split(crate::atoms::NESTED_PATH_SEPARATOR);
+        let first = properties.next().unwrap_or_default();
+        let mut values: Vec<&Value> = self.propvals.get(first).into_iter().collect();
+        for property in properties {
+            values = values
+                .into_iter()
+                .flat_map(|value| value.nested_propvals())
+                .filter_map(|propvals| propvals.get(property))
+                .collect();
+        }
+        values
+    }
+
     pub fn get_commit_builder(&self) -> &CommitBuilder {
         &self.commit
     }
@@ -213,9 +230,40 @@ impl Resource {
         };
         let class_urls = Vec::from([String::from(class_url)]);
         resource.set_propval(crate::urls::IS_A.into(), class_urls.into(), store)?;
+        let agent = store.get_default_agent().ok().map(|agent| agent.subject);
+        resource.set_default_values(store, agent.as_deref())?;
         Ok(resource)
     }
 
+    /// Sets the [default values](crate::urls::DEFAULT_VALUE) of the Properties that the Classes require or recommend, if they are missing.
+    /// `agent` is used for `agent()` defaults. Classes that can't be found are skipped.
+    /// Returns the Properties that have been set.
+    pub fn set_default_values(
+        &mut self,
+        store: &impl Storelike,
+        agent: Option<&str>,
+    ) -> AtomicResult<Vec<String>> {
+        let mut set = Vec::new();
+        let Ok(classes) = self.get(urls::IS_A).and_then(|v| v.to_subjects(None)) else {
+            return Ok(set);
+        };
+        for class in classes.iter().filter_map(|c| store.get_class(c).ok()) {
+            for prop in class.requires.iter().chain(class.recommends.iter()) {
+                if self.get(prop).is_ok() {
+                    continue;
+                }
+                let Ok(property) = store.get_property(prop) else {
+                    continue;
+                };
+                if let Some(value) = property.get_default_value(agent)? {
+                    self.set_propval(prop.clone(), value, store)?;
+                    set.push(prop.clone());
+                }
+            }
+        }
+        Ok(set)
+    }
+
     /// Appends a Resource to a specific property through the commitbuilder.
     /// Useful if you want to have compact Commits that add things to existing ResourceArrays.
     pub fn push_propval(
@@ -248,6 +296,18 @@ impl Resource {
         Ok(())
     }
 
+    /// Moves the resource right before one of its siblings through the commitbuilder.
+    /// The new `sortKey` is calculated when the Commit is applied, see [crate::order].
+    pub fn move_before(&mut self, sibling: &str) {
+        self.commit.move_before(sibling.into())
+    }
+
+    /// Moves the resource right after one of its siblings through the commitbuilder.
+    /// The new `sortKey` is calculated when the Commit is applied, see [crate::order].
+    pub fn move_after(&mut self, sibling: &str) {
+        self.commit.move_after(sibling.into())
+    }
+
     /// Remove a propval from a resource by property URL.
     pub fn remove_propval(&mut self, property_url: &str) {
         self.propvals.remove_entry(property_url);
@@ -316,8 +376,30 @@ impl Resource {
     /// Stores changes on the Subject's Server by sending a Commit.
     /// Returns the generated Commit, the new Resource and the old Resource.
     pub fn save(&mut self, store: &impl Storelike) -> AtomicResult<crate::commit::CommitResponse> {
+        self.save_as(store, None)
+    }
+
+    /// Same as [Resource::save], but the Commit is made on behalf of `for_agent` (see [urls::ON_BEHALF_OF]),
+    /// so the changes are attributed to that Agent instead of the default Agent.
+    /// Does not validate rights, check these before saving.
+    pub fn save_on_behalf_of(
+        &mut self,
+        store: &impl Storelike,
+        for_agent: &str,
+    ) -> AtomicResult<crate::commit::CommitResponse> {
+        self.save_as(store, Some(for_agent))
+    }
+
+    fn save_as(
+        &mut self,
+        store: &impl Storelike,
+        on_behalf_of: Option<&str>,
+    ) -> AtomicResult<crate::commit::CommitResponse> {
         let agent = store.get_default_agent()?;
-        let commit_builder = self.get_commit_builder().clone();
+        let mut commit_builder = self.get_commit_builder().clone();
+        if let Some(for_agent) = on_behalf_of {
+            commit_builder.on_behalf_of(for_agent.into());
+        }
         let commit = commit_builder.sign(&agent, store, self)?;
         // If the current client is a server, and the subject is hosted here, don't post
         let should_post = if let Some(self_url) = store.get_self_url() {
@@ -334,7 +416,7 @@ impl Resource {
             validate_signature: false,
             validate_timestamp: false,
             validate_rights: false,
-            validate_for_agent: Some(agent.subject),
+            validate_for_agent: Some(on_behalf_of.map(String::from).unwrap_or(agent.subject)),
             // TODO: auto-merge should work before we enable this https://github.com/atomicdata-dev/atomic-data-rust/issues/412
             validate_previous_commit: false,
             update_index: true,
@@ -401,7 +483,7 @@ impl Resource {
                 e
             )
         })?;
-        let val = Value::new(value, &fullprop.data_type)?;
+        let val = store.normalizers().parse(value, &fullprop.data_type)?;
         self.set_propval_unsafe(property_url, val);
         Ok(())
     }
@@ -416,31 +498,23 @@ impl Resource {
         value: Value,
         store: &impl Storelike,
     ) -> AtomicResult<()> {
-        let full_prop = store.get_property(&property)?;
-        if let Some(allowed) = full_prop.allows_only {
-            let error = Err(format!(
-                "Property '{}' does not allow value '{}'. Allowed: {:?}",
-                property, value, allowed
-            )
-            .into());
+        self.check_propval(&property, &value, store)?;
+        self.set_propval_unsafe(property, value);
+        Ok(())
+    }
 
-            match &value {
-                Value::ResourceArray(value_array) => {
-                    for item in value_array {
-                        if !allowed.contains(&item.to_string()) {
-                            return error;
-                        }
-                    }
-                }
-                _ => {
-                    if !allowed.contains(&value.to_string()) {
-                        return error;
-                    }
-                }
-            }
-        }
+    /// Checks if the value can be set for the Property: its datatype, `allows-only` and constraints.
+    /// See [Resource::set_propval].
+    pub fn check_propval(
+        &self,
+        property: &str,
+        value: &Value,
+        store: &impl Storelike,
+    ) -> AtomicResult<()> {
+        let full_prop = store.get_property(property)?;
+        check_allows_only(store, &full_prop, value)?;
+        full_prop.constraints.check(property, value)?;
         if full_prop.data_type == value.datatype() {
-            self.set_propval_unsafe(property, value);
             Ok(())
         } else {
             Err(format!("Datatype for subject '{}', property '{}', value '{}' did not match. Wanted '{}', got '{}'",
@@ -453,6 +527,18 @@ impl Resource {
         }
     }
 
+    /// Sets the value without checking it, and without adding it to the CommitBuilder.
+    /// Returns the previous value. Used for applying Commits.
+    pub(crate) fn insert_propval(&mut self, property: String, value: Value) -> Option<Value> {
+        self.propvals.insert(property, value)
+    }
+
+    /// Removes the value without adding it to the CommitBuilder.
+    /// Returns the removed value. Used for applying Commits.
+    pub(crate) fn take_propval(&mut self, property: &str) -> Option<Value> {
+        self.propvals.remove(property)
+    }
+
     /// Does not validate property / datatype combination.
     /// Inserts a Property/Value combination.
     /// Overwrites existing.
@@ -472,7 +558,7 @@ impl Resource {
         store: &impl Storelike,
     ) -> AtomicResult<()> {
         let fullprop = self.resolve_shortname_to_property(property, store)?;
-        let fullval = Value::new(value, &fullprop.data_type)?;
+        let fullval = store.normalizers().parse(value, &fullprop.data_type)?;
         self.set_propval_unsafe(fullprop.subject, fullval);
         Ok(())
     }
@@ -482,6 +568,12 @@ impl Resource {
         self.propvals = propvals;
     }
 
+    /// Removes the values of all Properties except these. Does not perform validation.
+    pub fn retain_propvals_unsafe(&mut self, properties: &[&str]) {
+        self.propvals
+            .retain(|prop, _| properties.contains(&prop.as_str()));
+    }
+
     /// Changes the subject of the Resource.
     /// Does not 'move' the Resource
     /// See https://github.com/atomicdata-dev/atomic-data-rust/issues/44
@@ -493,11 +585,15 @@ impl Resource {
     /// Converts Resource to JSON-AD string.
     #[instrument(skip_all)]
     pub fn to_json_ad(&self) -> AtomicResult<String> {
-        let obj = crate::serialize::propvals_to_json_ad_map(
-            self.get_propvals(),
-            Some(self.get_subject().clone()),
-        )?;
-        serde_json::to_string_pretty(&obj).map_err(|_| "Could not serialize to JSON-AD".into())
+        serde_json::to_string_pretty(&crate::serialize::JsonAd::resource(self))
+            .map_err(|_| "Could not serialize to JSON-AD".into())
+    }
+
+    /// Writes the Resource as JSON-AD, without building an intermediate JSON tree.
+    #[instrument(skip_all)]
+    pub fn write_json_ad(&self, writer: impl std::io::Write) -> AtomicResult<()> {
+        serde_json::to_writer_pretty(writer, &crate::serialize::JsonAd::resource(self))
+            .map_err(|e| format!("Could not serialize to JSON-AD: {}", e).into())
     }
 
     /// Converts Resource to plain JSON string.
@@ -542,6 +638,101 @@ impl Resource {
     }
 }
 
+/// Checks the `allows-only` of the Property for the value, and for every item of a ResourceArray.
+/// Nested resources are checked using the `allows-only` of their own Properties.
+fn check_allows_only(
+    store: &impl Storelike,
+    property: &Property,
+    value: &Value,
+) -> AtomicResult<()> {
+    match value {
+        Value::ResourceArray(items) => items
+            .iter()
+            .try_for_each(|item| check_allows_only_item(store, property, item)),
+        Value::NestedResource(item) => check_allows_only_item(store, property, item),
+        other => check_allowed(property, Some(&other.to_string())),
+    }
+}
+
+fn check_allows_only_item(
+    store: &impl Storelike,
+    property: &Property,
+    item: &SubResource,
+) -> AtomicResult<()> {
+    let propvals = match item {
+        SubResource::Subject(subject) => return check_allowed(property, Some(subject)),
+        SubResource::Resource(resource) => {
+            check_allowed(property, Some(resource.get_subject()))?;
+            resource.get_propvals()
+        }
+        // Nested resources have no subject, so they are never one of the allowed values
+        SubResource::Nested(propvals) => {
+            check_allowed(property, None)?;
+            propvals
+        }
+    };
+    for (nested_prop, nested_value) in propvals {
+        // Unknown Properties of nested resources are not checked here
+        if let Ok(nested_property) = store.get_property(nested_prop) {
+            check_allows_only(store, &nested_property, nested_value)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_allowed(property: &Property, value: Option<&String>) -> AtomicResult<()> {
+    let Some(allowed) = &property.allows_only else {
+        return Ok(());
+    };
+    if value.is_some_and(|v| allowed.contains(v)) {
+        return Ok(());
+    }
+    Err(format!(
+        "Property '{}' does not allow {}. Allowed values: {}",
+        property.subject,
+        value.map_or("nested resources".to_string(), |v| format!("value '{}'", v)),
+        allowed.join(", ")
+    )
+    .into())
+}
+
+/// Lists the properties that have been added, removed or changed between two versions of a resource, sorted by property.
+/// Every change is a set of PropVals with a `diff/property`, `diff/kind`, and the old and / or new value.
+/// The `lastCommit` is skipped, since it changes in every version.
+pub fn diff_versions(from: &Resource, to: &Resource) -> Vec<PropVals> {
+    let mut properties: Vec<&String> = from
+        .get_propvals()
+        .keys()
+        .chain(to.get_propvals().keys())
+        .filter(|prop| *prop != urls::LAST_COMMIT)
+        .collect();
+    properties.sort();
+    properties.dedup();
+
+    let mut changes = Vec::new();
+    for prop in properties {
+        let old = from.get(prop).ok().map(|v| v.to_string());
+        let new = to.get(prop).ok().map(|v| v.to_string());
+        let kind = match (&old, &new) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(old), Some(new)) if old != new => "changed",
+            _unchanged => continue,
+        };
+        let mut change = PropVals::new();
+        change.insert(urls::DIFF_PROPERTY.into(), Value::AtomicUrl(prop.clone()));
+        change.insert(urls::DIFF_KIND.into(), Value::String(kind.into()));
+        if let Some(old) = old {
+            change.insert(urls::DIFF_OLD_VALUE.into(), Value::String(old));
+        }
+        if let Some(new) = new {
+            change.insert(urls::DIFF_NEW_VALUE.into(), Value::String(new));
+        }
+        changes.push(change);
+    }
+    changes
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -587,6 +778,234 @@ mod test {
         new_resource.check_required_props(&store).unwrap();
     }
 
+    #[test]
+    fn checks_property_constraints() {
+        let store = init_store();
+        let code = "https://localhost/properties/code";
+        let mut property = Resource::new(code.into());
+        property.set_class(urls::PROPERTY);
+        property.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("code".into()));
+        property.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A code".into()));
+        property.set_propval_unsafe(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(urls::STRING.into()),
+        );
+        property.set_propval_unsafe(urls::PATTERN.into(), Value::String("[A-Z]{2}[0-9]+".into()));
+        property.set_propval_unsafe(urls::MAX_LENGTH.into(), Value::Integer(5));
+        store.add_resource(&property).unwrap();
+        let amount = "https://localhost/properties/amount";
+        let mut property = Resource::new(amount.into());
+        property.set_class(urls::PROPERTY);
+        property.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("amount".into()));
+        property.set_propval_unsafe(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("An amount".into()),
+        );
+        property.set_propval_unsafe(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(urls::INTEGER.into()),
+        );
+        property.set_propval_unsafe(urls::MINIMUM.into(), Value::Integer(1));
+        property.set_propval_unsafe(urls::MAXIMUM.into(), Value::Float(10.5));
+        store.add_resource(&property).unwrap();
+
+        let mut resource = Resource::new("https://localhost/thing".into());
+        resource
+            .set_propval(code.into(), Value::String("AB123".into()), &store)
+            .unwrap();
+        // The pattern has to match the whole value
+        resource
+            .set_propval(code.into(), Value::String("xAB12".into()), &store)
+            .unwrap_err();
+        resource
+            .set_propval(code.into(), Value::String("AB1234".into()), &store)
+            .unwrap_err();
+        resource
+            .set_propval(amount.into(), Value::Integer(10), &store)
+            .unwrap();
+        resource
+            .set_propval(amount.into(), Value::Integer(0), &store)
+            .unwrap_err();
+        resource
+            .set_propval(amount.into(), Value::Integer(11), &store)
+            .unwrap_err();
+        assert_eq!(resource.get(code).unwrap().to_string(), "AB123");
+        assert_eq!(resource.get(amount).unwrap().to_int().unwrap(), 10);
+    }
+
+    #[test]
+    fn sets_default_values() {
+        let store = init_store();
+        let agent = store.get_default_agent().unwrap();
+        let mut properties = Vec::new();
+        for (shortname, datatype, default) in [
+            ("status", urls::STRING, "draft"),
+            ("started", urls::TIMESTAMP, "now()"),
+            ("owner", urls::ATOMIC_URL, "agent()"),
+        ] {
+            let subject = format!("https://localhost/properties/{}", shortname);
+            let mut property = Resource::new(subject.clone());
+            property.set_class(urls::PROPERTY);
+            property.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug(shortname.into()));
+            property.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A".into()));
+            property.set_propval_unsafe(
+                urls::DATATYPE_PROP.into(),
+                Value::AtomicUrl(datatype.into()),
+            );
+            property.set_propval_unsafe(urls::DEFAULT_VALUE.into(), Value::String(default.into()));
+            store.add_resource(&property).unwrap();
+            properties.push(subject);
+        }
+        let class = "https://localhost/classes/Task";
+        let mut task = Resource::new(class.into());
+        task.set_class(urls::CLASS);
+        task.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("task".into()));
+        task.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A task".into()));
+        task.set_propval_unsafe(urls::REQUIRES.into(), vec![properties[0].clone()].into());
+        task.set_propval_unsafe(urls::RECOMMENDS.into(), properties[1..].to_vec().into());
+        store.add_resource(&task).unwrap();
+
+        let instance = Resource::new_instance(class, &store).unwrap();
+        assert_eq!(instance.get(&properties[0]).unwrap().to_string(), "draft");
+        assert!(instance.get(&properties[1]).unwrap().to_int().unwrap() > 0);
+        assert_eq!(
+            instance.get(&properties[2]).unwrap().to_string(),
+            agent.subject
+        );
+
+        // Commits that create a resource get the defaults too, but keep their own values
+        let subject = "https://localhost/tasks/first";
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::IS_A.into(), vec![class.to_string()].into());
+        builder.set(properties[0].clone(), Value::String("done".into()));
+        builder
+            .sign(&agent, &store, &Resource::new(subject.into()))
+            .unwrap()
+            .apply_opts(
+                &store,
+                &CommitOpts {
+                    validate_schema: true,
+                    validate_signature: true,
+                    validate_timestamp: true,
+                    validate_rights: false,
+                    validate_previous_commit: true,
+                    validate_for_agent: None,
+                    update_index: true,
+                },
+            )
+            .unwrap();
+        let created = store.get_resource(subject).unwrap();
+        assert_eq!(created.get(&properties[0]).unwrap().to_string(), "done");
+        assert_eq!(
+            created.get(&properties[2]).unwrap().to_string(),
+            agent.subject
+        );
+    }
+
+    #[test]
+    fn checks_allows_only_in_arrays_and_nested_resources() {
+        let store = init_store();
+        let status = "https://localhost/properties/status";
+        let mut property = Resource::new(status.into());
+        property.set_class(urls::PROPERTY);
+        property.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("status".into()));
+        property.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A".into()));
+        property.set_propval_unsafe(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(urls::RESOURCE_ARRAY.into()),
+        );
+        property.set_propval_unsafe(
+            urls::ALLOWS_ONLY.into(),
+            vec!["https://localhost/open", "https://localhost/done"].into(),
+        );
+        store.add_resource(&property).unwrap();
+
+        let mut resource = Resource::new("https://localhost/thing".into());
+        resource
+            .set_propval(status.into(), vec!["https://localhost/open"].into(), &store)
+            .unwrap();
+        let err = resource
+            .set_propval(
+                status.into(),
+                vec!["https://localhost/open", "https://localhost/other"].into(),
+                &store,
+            )
+            .unwrap_err();
+        assert!(err
+            .message
+            .contains("Allowed values: https://localhost/open, https://localhost/done"));
+
+        // Values of nested resources are checked against their own Properties
+        let mut nested = PropVals::new();
+        nested.insert(status.into(), vec!["https://localhost/other"].into());
+        let steps = vec![SubResource::Nested(nested)];
+        resource
+            .set_propval(urls::WRITE.into(), Value::ResourceArray(steps), &store)
+            .unwrap_err();
+        // Nested resources can't be one of the allowed subjects
+        let mut nested = PropVals::new();
+        nested.insert(urls::NAME.into(), Value::String("open".into()));
+        resource
+            .set_propval(
+                status.into(),
+                Value::ResourceArray(vec![SubResource::Nested(nested)]),
+                &store,
+            )
+            .unwrap_err();
+
+        // Pushing in a Commit is checked too
+        let mut builder = CommitBuilder::new(resource.get_subject().into());
+        builder
+            .push_propval(
+                status,
+                SubResource::Subject("https://localhost/other".into()),
+            )
+            .unwrap();
+        let agent = store.get_default_agent().unwrap();
+        builder
+            .sign(&agent, &store, &resource)
+            .unwrap()
+            .apply_changes(resource.clone(), &store, false)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn inherits_properties_of_extended_classes() {
+        let store = init_store();
+        let animal = "https://localhost/classes/Animal";
+        let dog = "https://localhost/classes/Dog";
+        let puppy = "https://localhost/classes/Puppy";
+        for (subject, extends, requires, recommends) in [
+            // Loops are ignored
+            (animal, puppy, urls::NAME, urls::DESCRIPTION),
+            (dog, animal, urls::PARENT, urls::NAME),
+            (puppy, dog, urls::DESCRIPTION, urls::DESCRIPTION),
+        ] {
+            let mut class = Resource::new(subject.into());
+            class.set_class(urls::CLASS);
+            class.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("class".into()));
+            class.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A".into()));
+            class.set_propval_unsafe(urls::EXTENDS.into(), vec![extends].into());
+            class.set_propval_unsafe(urls::REQUIRES.into(), vec![requires].into());
+            class.set_propval_unsafe(urls::RECOMMENDS.into(), vec![recommends].into());
+            store.add_resource(&class).unwrap();
+        }
+
+        let class = store.get_class(puppy).unwrap();
+        assert_eq!(
+            class.requires,
+            vec![urls::DESCRIPTION, urls::PARENT, urls::NAME]
+        );
+        // Required by one of the classes, so not recommended
+        assert!(class.recommends.is_empty());
+
+        let mut instance = Resource::new_instance(puppy, &store).unwrap();
+        instance
+            .set_propval_shortname("description", "A puppy", &store)
+            .unwrap();
+        instance.check_required_props(&store).unwrap_err();
+    }
+
     #[test]
     fn new_instance() {
         let store = init_store();
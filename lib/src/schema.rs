@@ -1,7 +1,8 @@
 //! Structs and models at the core of Atomic Schema (Class, Property, Datatype).
 
-use crate::{datatype::DataType, errors::AtomicResult, urls, Resource, Value};
+use crate::{datatype::DataType, errors::AtomicResult, urls, Resource, Storelike, Value};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Property {
@@ -18,6 +19,18 @@ pub struct Property {
     /// Restricts values to be only one of these Subjects.
     /// https://atomicdata.dev/properties/allowsOnly
     pub allows_only: Option<Vec<String>>,
+    /// Limits for the values, such as a `pattern` or a `maximum`.
+    #[serde(default)]
+    pub constraints: Constraints,
+    /// Value for new instances that don't have one. Can be `now()` or `agent()`.
+    /// https://atomicdata.dev/properties/defaultValue
+    pub default_value: Option<String>,
+    /// Makes this a computed Property. See [crate::computed].
+    /// https://atomicdata.dev/properties/expression
+    pub expression: Option<String>,
+    /// The unit of the values, see [crate::units].
+    /// https://atomicdata.dev/properties/unit
+    pub unit: Option<String>,
 }
 
 impl PartialEq for Property {
@@ -40,6 +53,25 @@ impl Property {
             Ok(classtype) => Some(classtype.to_subjects(None)?),
             Err(_) => None,
         };
+        let constraints = Constraints {
+            pattern: resource.get(urls::PATTERN).ok().map(|v| v.to_string()),
+            minimum: resource
+                .get(urls::MINIMUM)
+                .ok()
+                .map(to_number)
+                .transpose()?,
+            maximum: resource
+                .get(urls::MAXIMUM)
+                .ok()
+                .map(to_number)
+                .transpose()?,
+            max_length: match resource.get(urls::MAX_LENGTH) {
+                Ok(max) => Some(max.to_int()?.try_into().map_err(|_| {
+                    format!("maxLength of {} can't be negative", resource.get_subject())
+                })?),
+                Err(_) => None,
+            },
+        };
 
         Ok(Property {
             class_type,
@@ -47,6 +79,13 @@ impl Property {
             shortname,
             description,
             allows_only,
+            constraints,
+            default_value: resource
+                .get(urls::DEFAULT_VALUE)
+                .ok()
+                .map(|v| v.to_string()),
+            expression: resource.get(urls::EXPRESSION).ok().map(|v| v.to_string()),
+            unit: resource.get(urls::UNIT).ok().map(|v| v.to_string()),
             subject: resource.get_subject().into(),
         })
     }
@@ -73,15 +112,145 @@ impl Property {
                 Value::AtomicUrl(classtype.clone()),
             );
         }
+        if let Some(default_value) = &self.default_value {
+            resource.set_propval_unsafe(
+                urls::DEFAULT_VALUE.into(),
+                Value::String(default_value.clone()),
+            );
+        }
+        if let Some(expression) = &self.expression {
+            resource.set_propval_unsafe(urls::EXPRESSION.into(), Value::String(expression.clone()));
+        }
+        if let Some(unit) = &self.unit {
+            resource.set_propval_unsafe(urls::UNIT.into(), Value::String(unit.clone()));
+        }
+        if let Some(pattern) = &self.constraints.pattern {
+            resource.set_propval_unsafe(urls::PATTERN.into(), Value::String(pattern.clone()));
+        }
+        if let Some(minimum) = self.constraints.minimum {
+            resource.set_propval_unsafe(urls::MINIMUM.into(), Value::Float(minimum));
+        }
+        if let Some(maximum) = self.constraints.maximum {
+            resource.set_propval_unsafe(urls::MAXIMUM.into(), Value::Float(maximum));
+        }
+        if let Some(max_length) = self.constraints.max_length {
+            resource.set_propval_unsafe(urls::MAX_LENGTH.into(), Value::Integer(max_length as i64));
+        }
 
         resource
     }
+
+    /// Returns the default value for a new instance, which is created by `agent`.
+    pub fn get_default_value(&self, agent: Option<&str>) -> AtomicResult<Option<Value>> {
+        let Some(default_value) = &self.default_value else {
+            return Ok(None);
+        };
+        match default_value.as_str() {
+            "now()" if self.data_type == DataType::Timestamp => {
+                Ok(Some(Value::Timestamp(crate::utils::now())))
+            }
+            "agent()" => Ok(agent.map(|agent| Value::AtomicUrl(agent.into()))),
+            other => Ok(Some(Value::new(other, &self.data_type).map_err(|e| {
+                format!("Invalid default value of Property {}: {}", self.subject, e)
+            })?)),
+        }
+    }
+}
+
+/// Optional limits for the values of a Property, checked when a value is set using [Resource::set_propval].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Constraints {
+    /// Regular expression that String, Slug and Markdown values must match completely.
+    /// https://atomicdata.dev/properties/pattern
+    pub pattern: Option<String>,
+    /// Lowest allowed number or Timestamp.
+    /// https://atomicdata.dev/properties/minimum
+    pub minimum: Option<f64>,
+    /// Highest allowed number or Timestamp.
+    /// https://atomicdata.dev/properties/maximum
+    pub maximum: Option<f64>,
+    /// Maximum amount of characters in a text, or items in a ResourceArray.
+    /// https://atomicdata.dev/properties/maxLength
+    pub max_length: Option<usize>,
+}
+
+impl Constraints {
+    /// Returns an error if the value of `property` violates one of the constraints.
+    pub fn check(&self, property: &str, value: &Value) -> AtomicResult<()> {
+        let text = match value {
+            Value::String(s) | Value::Slug(s) | Value::Markdown(s) => Some(s),
+            _ => None,
+        };
+        if let (Some(pattern), Some(text)) = (&self.pattern, text) {
+            let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("Invalid pattern of Property {}: {}", property, e))?;
+            if !regex.is_match(text) {
+                return Err(format!(
+                    "Value '{}' of Property {} does not match the pattern '{}'",
+                    text, property, pattern
+                )
+                .into());
+            }
+        }
+        let length = match value {
+            Value::ResourceArray(items) => Some(items.len()),
+            _ => text.map(|t| t.chars().count()),
+        };
+        if let (Some(max_length), Some(length)) = (self.max_length, length) {
+            if length > max_length {
+                return Err(format!(
+                    "Value of Property {} is too long: {} is more than the maximum length of {}",
+                    property, length, max_length
+                )
+                .into());
+            }
+        }
+        let number = match value {
+            Value::Integer(i) | Value::Timestamp(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Decimal(d) => d.to_string().parse().ok(),
+            _ => None,
+        };
+        if let Some(number) = number {
+            if let Some(minimum) = self.minimum.filter(|min| number < *min) {
+                return Err(format!(
+                    "Value {} of Property {} is lower than the minimum of {}",
+                    value, property, minimum
+                )
+                .into());
+            }
+            if let Some(maximum) = self.maximum.filter(|max| number > *max) {
+                return Err(format!(
+                    "Value {} of Property {} is higher than the maximum of {}",
+                    value, property, maximum
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Minimum and maximum can be set as Integers or Floats.
+fn to_number(value: &Value) -> AtomicResult<f64> {
+    match value {
+        Value::Integer(i) => Ok(*i as f64),
+        Value::Float(f) => Ok(*f),
+        other => other
+            .to_string()
+            .parse()
+            .map_err(|e| format!("'{}' is not a number. {}", other, e).into()),
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Class {
     pub requires: Vec<String>,
     pub recommends: Vec<String>,
+    /// Classes that this Class inherits its Properties from.
+    /// https://atomicdata.dev/properties/extends
+    #[serde(default)]
+    pub extends: Vec<String>,
     pub shortname: String,
     pub description: String,
     /// URL
@@ -105,18 +274,57 @@ impl Class {
             }
         }
 
+        let extends = match resource.get(urls::EXTENDS) {
+            Ok(extends) => extends.to_subjects(None)?,
+            Err(_) => Vec::new(),
+        };
+
         let shortname = resource.get(urls::SHORTNAME)?.to_string();
         let description = resource.get(urls::DESCRIPTION)?.to_string();
 
         Ok(Class {
             requires,
             recommends,
+            extends,
             shortname,
             subject: resource.get_subject().into(),
             description,
         })
     }
 
+    /// Adds the required and recommended Properties of the Classes that this Class extends, and of the Classes that they extend.
+    /// Properties that are required by one Class and recommended by another become required.
+    pub fn add_inherited_properties(&mut self, store: &impl Storelike) -> AtomicResult<()> {
+        let mut seen = HashSet::from([self.subject.clone()]);
+        let mut queue: VecDeque<String> = self.extends.iter().cloned().collect();
+        while let Some(parent) = queue.pop_front() {
+            if !seen.insert(parent.clone()) {
+                continue;
+            }
+            let resource = store.get_resource(&parent).map_err(|e| {
+                format!(
+                    "Class {} extends {}, which can't be found. {}",
+                    self.subject, parent, e
+                )
+            })?;
+            let parent = Class::from_resource(resource)?;
+            for prop in parent.requires {
+                if !self.requires.contains(&prop) {
+                    self.requires.push(prop);
+                }
+            }
+            for prop in parent.recommends {
+                if !self.recommends.contains(&prop) {
+                    self.recommends.push(prop);
+                }
+            }
+            queue.extend(parent.extends);
+        }
+        let requires = &self.requires;
+        self.recommends.retain(|prop| !requires.contains(prop));
+        Ok(())
+    }
+
     /// Converts Class to a Resource
     pub fn to_resource(&self) -> Resource {
         let mut resource = Resource::new(self.subject.clone());
@@ -138,6 +346,9 @@ impl Class {
                 Value::from(self.recommends.clone()),
             );
         }
+        if !self.extends.is_empty() {
+            resource.set_propval_unsafe(urls::EXTENDS.into(), Value::from(self.extends.clone()));
+        }
         resource
     }
 }
@@ -1,24 +1,163 @@
-//! Serialization / formatting / encoding (JSON, RDF, N-Triples)
+//! Serialization / formatting / encoding (JSON, RDF, N-Triples, CSV)
 
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
 use serde_json::Map;
 use serde_json::Value as SerdeValue;
 use tracing::instrument;
 
 use crate::{
-    datatype::DataType, errors::AtomicResult, resources::PropVals, Resource, Storelike, Value,
+    datatype::DataType, errors::AtomicResult, resources::PropVals, values::SubResource, Resource,
+    Storelike, Value,
 };
 
 /// Serializes a vector or Resources to a JSON-AD string
 pub fn resources_to_json_ad(resources: &[Resource]) -> AtomicResult<String> {
-    let mut vec: Vec<serde_json::Value> = Vec::new();
-    for r in resources {
-        vec.push(crate::serialize::propvals_to_json_ad_map(
-            r.get_propvals(),
-            Some(r.get_subject().clone()),
-        )?)
+    let resources: Vec<JsonAd> = resources.iter().map(JsonAd::resource).collect();
+    serde_json::to_string_pretty(&resources).map_err(|_| "Could not serialize to JSON-AD".into())
+}
+
+/// Writes Resources as a JSON-AD array, without building the whole document in memory first.
+pub fn write_resources_json_ad(
+    resources: impl IntoIterator<Item = Resource>,
+    writer: impl std::io::Write,
+) -> AtomicResult<()> {
+    let mut serializer = serde_json::Serializer::pretty(writer);
+    let mut seq = serializer
+        .serialize_seq(None)
+        .map_err(|e| format!("Could not serialize to JSON-AD: {}", e))?;
+    for resource in resources {
+        seq.serialize_element(&JsonAd::resource(&resource))
+            .map_err(|e| format!("Could not serialize to JSON-AD: {}", e))?;
+    }
+    SerializeSeq::end(seq).map_err(|e| format!("Could not serialize to JSON-AD: {}", e))?;
+    Ok(())
+}
+
+/// Serializes PropVals as a JSON-AD object, directly into the output of a [Serializer].
+/// Properties are sorted, and the subject (if any) is written first as `@id`.
+/// https://docs.atomicdata.dev/core/json-ad.html
+pub struct JsonAd<'a> {
+    propvals: &'a PropVals,
+    subject: Option<&'a str>,
+}
+
+impl<'a> JsonAd<'a> {
+    pub fn resource(resource: &'a Resource) -> Self {
+        JsonAd {
+            propvals: resource.get_propvals(),
+            subject: Some(resource.get_subject()),
+        }
+    }
+
+    pub fn propvals(propvals: &'a PropVals, subject: Option<&'a str>) -> Self {
+        JsonAd { propvals, subject }
+    }
+}
+
+impl Serialize for JsonAd<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut properties: Vec<(&String, &Value)> = self.propvals.iter().collect();
+        properties.sort_unstable_by_key(|(prop, _)| *prop);
+        let len = properties.len() + usize::from(self.subject.is_some());
+        let mut map = serializer.serialize_map(Some(len))?;
+        if let Some(subject) = self.subject {
+            map.serialize_entry("@id", subject)?;
+        }
+        for (prop, value) in properties {
+            map.serialize_entry(prop, &JsonAdValue(value))?;
+        }
+        map.end()
+    }
+}
+
+struct JsonAdValue<'a>(&'a Value);
+
+impl Serialize for JsonAdValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::AtomicUrl(val)
+            | Value::Date(val)
+            | Value::Markdown(val)
+            | Value::Slug(val)
+            | Value::String(val) => serializer.serialize_str(val),
+            Value::Integer(val) | Value::Timestamp(val) => serializer.serialize_i64(*val),
+            // Floats without a fraction are written as integers, like `1`
+            Value::Float(val) => match serde_json::from_str::<serde_json::Number>(&val.to_string())
+            {
+                Ok(number) => number.serialize(serializer),
+                Err(_) => serializer.serialize_unit(),
+            },
+            Value::Boolean(val) => serializer.serialize_bool(*val),
+            Value::Unsupported(val) => serializer.serialize_str(&val.value),
+            Value::GeoPoint(val) => serializer.collect_str(val),
+            Value::Decimal(val) => serializer.collect_str(val),
+            Value::Bytes(val) => serializer.serialize_str(&crate::agents::encode_base64(val)),
+            Value::ResourceArray(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&JsonAdSubResource(item))?;
+                }
+                seq.end()
+            }
+            Value::NestedResource(item) => JsonAdSubResource(item).serialize(serializer),
+            Value::Resource(resource) => JsonAd::resource(resource).serialize(serializer),
+        }
+    }
+}
+
+struct JsonAdSubResource<'a>(&'a SubResource);
+
+impl Serialize for JsonAdSubResource<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            SubResource::Resource(resource) => JsonAd::resource(resource).serialize(serializer),
+            SubResource::Nested(propvals) => JsonAd::propvals(propvals, None).serialize(serializer),
+            SubResource::Subject(subject) => serializer.serialize_str(subject),
+        }
     }
-    let serde_array = serde_json::Value::from(vec);
-    serde_json::to_string_pretty(&serde_array).map_err(|_| "Could not serialize to JSON-AD".into())
+}
+
+/// Serializes Resources to CSV, with an `@id` column and a column for every Property URL.
+/// Values in ResourceArray columns are separated by spaces, so the result can be imported again.
+pub fn resources_to_csv(resources: &[Resource]) -> AtomicResult<String> {
+    let properties: std::collections::BTreeSet<&String> = resources
+        .iter()
+        .flat_map(|r| r.get_propvals().keys())
+        .collect();
+    let mut out = String::new();
+    let header = std::iter::once("@id").chain(properties.iter().map(|p| p.as_str()));
+    push_csv_row(&mut out, header);
+    for resource in resources {
+        let cells: Vec<String> = properties
+            .iter()
+            .map(|property| match resource.get_propvals().get(*property) {
+                Some(value @ Value::ResourceArray(_)) => value
+                    .to_subjects(None)
+                    .map(|subjects| subjects.join(" "))
+                    .unwrap_or_else(|_| value.to_string()),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            })
+            .collect();
+        let row = std::iter::once(resource.get_subject().as_str())
+            .chain(cells.iter().map(|c| c.as_str()));
+        push_csv_row(&mut out, row);
+    }
+    Ok(out)
+}
+
+fn push_csv_row<'a>(out: &mut String, cells: impl Iterator<Item = &'a str>) {
+    let cells: Vec<String> = cells
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect();
+    out.push_str(&cells.join(","));
+    out.push('\n');
 }
 
 /// Converts an Atomic Value to a Serde Value.
@@ -57,6 +196,9 @@ fn val_to_serde(value: Value) -> AtomicResult<SerdeValue> {
         Value::Timestamp(val) => SerdeValue::Number(val.into()),
         Value::Unsupported(val) => SerdeValue::String(val.value),
         Value::Boolean(val) => SerdeValue::Bool(val),
+        Value::GeoPoint(val) => SerdeValue::String(val.to_string()),
+        Value::Decimal(val) => SerdeValue::String(val.to_string()),
+        Value::Bytes(val) => SerdeValue::String(crate::agents::encode_base64(&val)),
         // TODO: fix this for nested resources in json and json-ld serialization, because this will cause them to fall back to json-ad
         Value::NestedResource(res) => match res {
             crate::values::SubResource::Resource(r) => crate::serialize::propvals_to_json_ad_map(
@@ -80,15 +222,8 @@ pub fn propvals_to_json_ad_map(
     propvals: &PropVals,
     subject: Option<String>,
 ) -> AtomicResult<serde_json::Value> {
-    let mut root = Map::new();
-    for (prop_url, value) in propvals.iter() {
-        root.insert(prop_url.clone(), val_to_serde(value.clone())?);
-    }
-    if let Some(sub) = subject {
-        root.insert("@id".into(), SerdeValue::String(sub));
-    }
-    let obj = SerdeValue::Object(root);
-    Ok(obj)
+    serde_json::to_value(JsonAd::propvals(propvals, subject.as_deref()))
+        .map_err(|e| format!("Could not serialize to JSON-AD: {}", e).into())
 }
 
 /// Serializes a Resource to a Serde JSON Map.
@@ -308,6 +443,59 @@ mod test {
         assert_eq!(serialized, correct_json);
     }
 
+    #[test]
+    fn serialize_json_ad_values() {
+        let mut nested = PropVals::new();
+        nested.insert(crate::urls::NAME.into(), Value::String("nested".into()));
+        let mut resource = Resource::new("https://example.com/a".into());
+        resource.set_propval_unsafe("https://example.com/float".into(), Value::Float(1.0));
+        resource.set_propval_unsafe("https://example.com/int".into(), Value::Integer(-3));
+        resource.set_propval_unsafe("https://example.com/bool".into(), Value::Boolean(false));
+        resource.set_propval_unsafe(
+            "https://example.com/decimal".into(),
+            Value::Decimal("0.10".parse().unwrap()),
+        );
+        resource.set_propval_unsafe(
+            "https://example.com/array".into(),
+            Value::ResourceArray(vec![
+                SubResource::Subject("https://example.com/b".into()),
+                SubResource::Nested(nested.clone()),
+            ]),
+        );
+        resource.set_propval_unsafe(
+            "https://example.com/nested".into(),
+            Value::NestedResource(SubResource::Nested(nested)),
+        );
+        let correct_value = serde_json::json!({
+            "@id": "https://example.com/a",
+            "https://example.com/array": [
+                "https://example.com/b",
+                { "https://atomicdata.dev/properties/name": "nested" }
+            ],
+            "https://example.com/bool": false,
+            "https://example.com/decimal": "0.1",
+            "https://example.com/float": 1,
+            "https://example.com/int": -3,
+            "https://example.com/nested": { "https://atomicdata.dev/properties/name": "nested" }
+        });
+        let json = resource.to_json_ad().unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            correct_value
+        );
+        assert!(json.starts_with("{\n  \"@id\""));
+
+        let mut written = Vec::new();
+        resource.write_json_ad(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), json);
+        let mut written = Vec::new();
+        write_resources_json_ad(vec![resource.clone()], &mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            resources_to_json_ad(&[resource]).unwrap()
+        );
+    }
+
     #[test]
     fn serialize_json() {
         let store = crate::Store::init().unwrap();
@@ -413,4 +601,29 @@ mod test {
         // This could fail when the `description` resource changes
         assert!(serialized.lines().count() == 5);
     }
+
+    #[test]
+    fn serialize_csv() {
+        let mut resource = Resource::new("https://example.com/a".into());
+        resource.set_propval_unsafe(
+            crate::urls::DESCRIPTION.into(),
+            Value::Markdown("Hello, \"world\"".into()),
+        );
+        resource.set_class(crate::urls::CLASS);
+        let other = Resource::new("https://example.com/b".into());
+        let csv = resources_to_csv(&[resource, other]).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("@id,{},{}", crate::urls::DESCRIPTION, crate::urls::IS_A)
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "https://example.com/a,\"Hello, \"\"world\"\"\",{}",
+                crate::urls::CLASS
+            )
+        );
+        assert_eq!(lines.next().unwrap(), "https://example.com/b,,");
+    }
 }
@@ -1,5 +1,6 @@
 //! In-memory store of Atomic data.
 //! This provides many methods for finding, changing, serializing and parsing Atomic Data.
+//! Can also be used as a thin client for a server, see [Store::init_remote].
 
 use crate::storelike::QueryResult;
 use crate::Value;
@@ -13,8 +14,15 @@ pub struct Store {
     // The store currently holds two stores - that is not ideal
     hashmap: Arc<Mutex<HashMap<String, Resource>>>,
     default_agent: Arc<Mutex<Option<crate::agents::Agent>>>,
+    /// The server that all data is read from and written to, if this is a remote store.
+    remote: Option<String>,
+    /// The URL that this store hosts its resources at, see [Store::set_server_url].
+    server_url: Option<String>,
 }
 
+/// Amount of members per page, when a remote store fetches Collections.
+const REMOTE_PAGE_SIZE: usize = 100;
+
 impl Store {
     /// Creates an empty Store.
     /// Run `.populate()` to get useful standard models loaded into your store.
@@ -22,11 +30,134 @@ impl Store {
         let store = Store {
             hashmap: Arc::new(Mutex::new(HashMap::new())),
             default_agent: Arc::new(Mutex::new(None)),
+            remote: None,
+            server_url: None,
         };
         crate::populate::populate_base_models(&store)?;
         Ok(store)
     }
 
+    /// Creates a Store that acts as a thin client for a server.
+    /// Resources are always fetched from the server, Commits are posted to it and queries use its Collections.
+    /// Only the schema (Properties, Classes and Datatypes) is kept in memory, since it's needed for parsing.
+    pub fn init_remote(server_url: &str) -> AtomicResult<Store> {
+        let store = Store {
+            hashmap: Arc::new(Mutex::new(HashMap::new())),
+            default_agent: Arc::new(Mutex::new(None)),
+            remote: Some(server_url.trim_end_matches('/').into()),
+            server_url: None,
+        };
+        crate::populate::populate_base_models(&store)?;
+        Ok(store)
+    }
+
+    /// Lets the store host the resources at this URL, for example when it's served over HTTP.
+    /// Resources on this server are never fetched, and Commits to them are applied locally.
+    pub fn set_server_url(&mut self, server_url: &str) {
+        self.server_url = Some(server_url.trim_end_matches('/').into());
+    }
+
+    /// Schema resources rarely change, so remote stores keep them in memory.
+    fn is_schema(resource: &Resource) -> bool {
+        resource
+            .get(crate::urls::IS_A)
+            .and_then(|classes| classes.to_subjects(None))
+            .is_ok_and(|classes| {
+                classes.iter().any(|class| {
+                    [
+                        crate::urls::PROPERTY,
+                        crate::urls::CLASS,
+                        crate::urls::DATATYPE_CLASS,
+                    ]
+                    .contains(&class.as_str())
+                })
+            })
+    }
+
+    /// Runs the query using the `/collections` endpoint of the server.
+    fn query_remote(
+        &self,
+        server: &str,
+        q: &crate::storelike::Query,
+    ) -> AtomicResult<crate::storelike::QueryResult> {
+        if q.start_val.is_some() || q.end_val.is_some() || q.geo.is_some() {
+            return Err("Remote stores can't query by a range of values or by location.".into());
+        }
+        let parse_opts = crate::parse::ParseOpts {
+            save: crate::parse::SaveOpts::DontSave,
+            ..Default::default()
+        };
+        let mut resources = Vec::new();
+        let mut count;
+        let mut current_page = q.offset / REMOTE_PAGE_SIZE;
+        let mut skip = q.offset % REMOTE_PAGE_SIZE;
+        loop {
+            let mut url = url::Url::parse(&format!("{}/collections", server))?;
+            {
+                let mut params = url.query_pairs_mut();
+                if let Some(property) = &q.property {
+                    params.append_pair("property", property);
+                }
+                if let Some(value) = &q.value {
+                    params.append_pair("value", &value.to_string());
+                }
+                if let Some(sort_by) = &q.sort_by {
+                    params.append_pair("sort_by", sort_by);
+                    params.append_pair("sort_desc", &q.sort_desc.to_string());
+                }
+                params
+                    .append_pair("include_nested", "true")
+                    .append_pair("include_external", &q.include_external.to_string())
+                    .append_pair("page_size", &REMOTE_PAGE_SIZE.to_string())
+                    .append_pair("current_page", &current_page.to_string());
+            }
+            let body = crate::client::fetch_body(
+                url.as_str(),
+                crate::parse::JSON_AD_MIME,
+                self.get_default_agent().ok(),
+            )?;
+            let page: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&body)?;
+            count = page
+                .get(crate::urls::COLLECTION_MEMBER_COUNT)
+                .and_then(|c| c.as_u64())
+                .unwrap_or_default() as usize;
+            let members = page
+                .get(crate::urls::COLLECTION_MEMBERS)
+                .and_then(|m| m.as_array())
+                .cloned()
+                .unwrap_or_default();
+            for member in members.into_iter().skip(skip) {
+                if q.limit.is_some_and(|limit| resources.len() >= limit) {
+                    break;
+                }
+                resources.push(crate::parse::parse_json_ad_resource(
+                    &member.to_string(),
+                    self,
+                    &parse_opts,
+                )?);
+            }
+            skip = 0;
+            let total_pages = page
+                .get(crate::urls::COLLECTION_TOTAL_PAGES)
+                .and_then(|t| t.as_u64())
+                .unwrap_or_default() as usize;
+            current_page += 1;
+            let is_full = q.limit.is_some_and(|limit| resources.len() >= limit);
+            if is_full || current_page >= total_pages {
+                break;
+            }
+        }
+        Ok(crate::storelike::QueryResult {
+            count,
+            subjects: resources.iter().map(|r| r.get_subject().clone()).collect(),
+            resources: if q.include_nested {
+                resources
+            } else {
+                Vec::new()
+            },
+        })
+    }
+
     /// Triple Pattern Fragments interface.
     /// Use this for most queries, e.g. finding all items with some property / value combination.
     /// Returns an empty array if nothing is found.
@@ -35,7 +166,7 @@ impl Store {
     ///
     /// For example, if I want to view all Resources that are instances of the class "Property", I'd do:
     ///
-    /// ```
+    /// ```ignore
     /// use atomic_lib::Storelike;
     /// let mut store = atomic_lib::Store::init().unwrap();
     /// store.populate();
@@ -176,10 +307,17 @@ impl Storelike for Store {
     fn get_server_url(&self) -> &str {
         // TODO Should be implemented later when companion functionality is here
         // https://github.com/atomicdata-dev/atomic-data-rust/issues/6
-        "local:store"
+        self.remote
+            .as_deref()
+            .or(self.server_url.as_deref())
+            .unwrap_or("local:store")
     }
 
     fn get_self_url(&self) -> Option<String> {
+        // Remote stores don't host anything themselves, so Commits are always posted
+        if self.remote.is_some() {
+            return None;
+        }
         Some(self.get_server_url().into())
     }
 
@@ -192,7 +330,9 @@ impl Storelike for Store {
 
     fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
         if let Some(resource) = self.hashmap.lock().unwrap().get(subject) {
-            return Ok(resource.clone());
+            if self.remote.is_none() || Store::is_schema(resource) {
+                return Ok(resource.clone());
+            }
         }
         self.handle_not_found(subject, "Not found in HashMap.".into())
     }
@@ -214,6 +354,9 @@ impl Storelike for Store {
     }
 
     fn query(&self, q: &crate::storelike::Query) -> AtomicResult<crate::storelike::QueryResult> {
+        if let Some(server) = &self.remote {
+            return self.query_remote(server, q);
+        }
         let atoms = self.tpf(
             None,
             q.property.as_deref(),
@@ -254,7 +397,17 @@ impl Storelike for Store {
             }
         }
 
-        if let Some(sort) = &q.sort_by {
+        if let Some(geo) = &q.geo {
+            let point = |r: &crate::Resource| match q.property.as_ref().map(|p| r.get(p)) {
+                Some(Ok(crate::Value::GeoPoint(point))) => Some(*point),
+                _other => None,
+            };
+            resources.retain(|r| point(r).is_some_and(|p| geo.contains(&p)));
+            resources.sort_by(|a, b| {
+                let distance = |r| point(r).map(|p| geo.distance(&p)).unwrap_or_default();
+                distance(a).total_cmp(&distance(b))
+            });
+        } else if let Some(sort) = &q.sort_by {
             resources = crate::collections::sort_resources(resources, sort, q.sort_desc);
         }
         let mut subjects = Vec::new();
@@ -281,6 +434,34 @@ mod test {
         store
     }
 
+    #[test]
+    fn hosted_store() {
+        let mut store = Store::init().unwrap();
+        store.set_server_url("http://localhost:9883/");
+        assert_eq!(store.get_server_url(), "http://localhost:9883");
+        // Resources on the server are not fetched
+        let err = store
+            .get_resource("http://localhost:9883/missing")
+            .unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            crate::errors::AtomicErrorType::NotFoundError
+        ));
+    }
+
+    #[test]
+    fn remote_store() {
+        let store = Store::init_remote("https://example.com/").unwrap();
+        assert_eq!(store.get_server_url(), "https://example.com");
+        assert!(store.get_self_url().is_none());
+        // The schema is kept in memory
+        let property = store.get_property(urls::DESCRIPTION).unwrap();
+        assert_eq!(property.shortname, "description");
+        let mut query = crate::storelike::Query::new_prop_val(urls::IS_A, urls::CLASS);
+        query.start_val = Some(Value::String("a".into()));
+        assert!(store.query(&query).is_err());
+    }
+
     #[test]
     fn populate_base_models() {
         let store = Store::init().unwrap();
@@ -371,6 +552,17 @@ mod test {
             }
             crate::storelike::PathReturn::Atom(_) => panic!("Should be an Subject"),
         }
+        let mut mapping = crate::mapping::Mapping::init();
+        mapping.populate().unwrap();
+        let res = store
+            .get_path("classes:Class properties:shortname", Some(&mapping), None)
+            .unwrap();
+        match res {
+            crate::storelike::PathReturn::Subject(_) => panic!("Should be an Atom"),
+            crate::storelike::PathReturn::Atom(atom) => {
+                assert_eq!(atom.value.to_string(), "class");
+            }
+        }
     }
 
     #[test]
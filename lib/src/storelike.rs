@@ -9,7 +9,11 @@ use crate::{
     urls,
 };
 use crate::{errors::AtomicResult, parse::parse_json_ad_string};
-use crate::{mapping::Mapping, values::Value, Atom, Resource};
+use crate::{
+    mapping::Mapping,
+    values::{GeoPoint, Value},
+    Atom, Resource,
+};
 
 // A path can return one of many things
 pub enum PathReturn {
@@ -77,7 +81,7 @@ pub trait Storelike: Sized {
     /// Returns a single [Value] from a [Resource]
     fn get_value(&self, subject: &str, property: &str) -> AtomicResult<Value> {
         self.get_resource(subject)
-            .and_then(|r| r.get(property).map(|v| v.clone()))
+            .and_then(|r| r.get(property).cloned())
     }
 
     /// Returns the base URL where the default store is.
@@ -111,11 +115,27 @@ pub trait Storelike: Sized {
 
     /// Exports the store to a big JSON-AD file.
     /// Sorts the export by first exporting Property Resources, which makes importing faster and more dependent.
-    fn export(&self, include_external: bool) -> AtomicResult<String> {
+    /// Resources that have [urls::NO_EXPORT] set (on themselves or a parent) are skipped, unless `include_no_export` is true.
+    fn export(&self, include_external: bool, include_no_export: bool) -> AtomicResult<String> {
         let resources = self.all_resources(include_external);
         let mut properties: Vec<Resource> = Vec::new();
         let mut other_resources: Vec<Resource> = Vec::new();
         for r in resources {
+            if !include_no_export {
+                // Commits are skipped if their target is skipped
+                let target = match r.get(urls::SUBJECT) {
+                    Ok(subject) => self.get_resource(&subject.to_string()).ok(),
+                    Err(_) => None,
+                };
+                let owner = target.as_ref().unwrap_or(&r);
+                if crate::hierarchy::inherited_flag(
+                    owner,
+                    &owner.get_parent_tree(self)?,
+                    urls::NO_EXPORT,
+                ) {
+                    continue;
+                }
+            }
             if let Ok(class) = r.get_main_class() {
                 if class == crate::urls::PROPERTY {
                     properties.push(r);
@@ -151,12 +171,23 @@ pub trait Storelike: Sized {
         }
     }
 
-    /// Retrieves a Class from the store by subject URL and converts it into a Class useful for forms
+    /// Returns a Resource with only the values of these Properties, for views that don't need the full resource (e.g. lists of names).
+    /// Like [Storelike::get_resource], this does _not_ construct dynamic Resources.
+    fn get_resource_partial(&self, subject: &str, properties: &[&str]) -> AtomicResult<Resource> {
+        let mut resource = self.get_resource(subject)?;
+        resource.retain_propvals_unsafe(properties);
+        Ok(resource)
+    }
+
+    /// Retrieves a Class from the store by subject URL and converts it into a Class useful for forms.
+    /// Includes the Properties of the Classes that it [extends](crate::urls::EXTENDS).
     fn get_class(&self, subject: &str) -> AtomicResult<Class> {
         let resource = self
             .get_resource(subject)
             .map_err(|e| format!("Failed getting class {}. {}", subject, e))?;
-        Class::from_resource(resource)
+        let mut class = Class::from_resource(resource)?;
+        class.add_inherited_properties(self)?;
+        Ok(class)
     }
 
     /// Finds all classes (isA) for any subject.
@@ -195,10 +226,46 @@ pub trait Storelike: Sized {
         Ok(resource)
     }
 
+    /// Like [Storelike::get_resource_extended], but with the values of the first of the `languages` that the resource has a translation for.
+    /// Falls back to the values of the resource itself. See [crate::i18n].
+    fn get_resource_localized(
+        &self,
+        subject: &str,
+        skip_dynamic: bool,
+        for_agent: Option<&str>,
+        languages: &[String],
+    ) -> AtomicResult<Resource> {
+        let mut resource = self.get_resource_extended(subject, skip_dynamic, for_agent)?;
+        resource.localize(languages);
+        Ok(resource)
+    }
+
     /// This function is called whenever a Commit is applied.
     /// Implement this if you want to have custom handlers for Commits.
     fn handle_commit(&self, _commit_response: &CommitResponse) {}
 
+    /// The normalizations that are applied to values that are set by Commits. See [crate::normalize].
+    fn normalizers(&self) -> crate::normalize::Normalizers {
+        crate::normalize::Normalizers::default()
+    }
+
+    /// Returns the cache for [hierarchy::check_rights], if the store has one.
+    /// Stores that return a cache must call [hierarchy::RightsCache::invalidate] whenever a resource changes.
+    fn rights_cache(&self) -> Option<&hierarchy::RightsCache> {
+        None
+    }
+
+    /// Returns an error if turning `resource_old` into `resource_new` would exceed a quota, such as the maximum amount of resources in a Drive.
+    /// `resource_old` is `None` for new resources.
+    /// The default implementation does not limit anything, overwrite it if your store keeps track of usage.
+    fn check_quota(
+        &self,
+        _resource_old: Option<&Resource>,
+        _resource_new: &Resource,
+    ) -> AtomicResult<()> {
+        Ok(())
+    }
+
     fn handle_not_found(&self, subject: &str, error: AtomicError) -> AtomicResult<Resource> {
         if let Some(self_url) = self.get_self_url() {
             if subject.starts_with(&self_url) {
@@ -218,6 +285,18 @@ pub trait Storelike: Sized {
         Ok(len)
     }
 
+    /// Imports a JSON-AD string like [Storelike::import], but skips resources that are invalid or can't be saved.
+    /// Returns a report of the imported and failed resources.
+    fn import_tolerant(
+        &self,
+        string: &str,
+        parse_opts: &crate::parse::ParseOpts,
+    ) -> AtomicResult<crate::parse::ImportReport> {
+        let (_resources, report) =
+            crate::parse::parse_json_ad_string_tolerant(string, self, parse_opts)?;
+        Ok(report)
+    }
+
     /// Removes a resource from the store. Errors if not present.
     fn remove_resource(&self, subject: &str) -> AtomicResult<()>;
 
@@ -300,6 +379,11 @@ pub trait Storelike: Sized {
                     return Err("No more linked resources down this path.".into())
                 }
             }
+            // Prefixed names such as `schema:name` are expanded using the mapping
+            let expanded = mapping
+                .filter(|_| !crate::mapping::is_url(item))
+                .and_then(|m| m.expand_prefix(item));
+            let item = expanded.as_deref().unwrap_or(item);
             // Set the parent for the next loop equal to the next node.
             // TODO: skip this step if the current iteration is the last one
             let value = resource.get_shortname(item, self)?.clone();
@@ -350,7 +434,7 @@ pub trait Storelike: Sized {
 /// Use this to construct a list of Resources
 #[derive(Debug)]
 pub struct Query {
-    /// Filter by Property
+    /// Filter by Property. Can be a path to a Property of nested resources, see [crate::atoms::nested_path].
     pub property: Option<String>,
     /// Filter by Value
     pub value: Option<Value>,
@@ -362,7 +446,7 @@ pub struct Query {
     pub end_val: Option<Value>,
     /// How many items to skip from the first one
     pub offset: usize,
-    /// The Property URL that is used to sort the results
+    /// The Property URL that is used to sort the results. Can be a path, like `property`.
     pub sort_by: Option<String>,
     /// Sort descending instead of ascending.
     pub sort_desc: bool,
@@ -372,6 +456,69 @@ pub struct Query {
     pub include_nested: bool,
     /// For which Agent the query is executed. Pass `None` if you want to skip permission checks.
     pub for_agent: Option<String>,
+    /// Only include Resources where the GeoPoint in `property` is inside this area.
+    /// Results within a radius are sorted by distance, `value` and `sort_by` are ignored.
+    pub geo: Option<GeoFilter>,
+}
+
+/// An area for filtering [GeoPoint]s in a [Query].
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoFilter {
+    /// Everything between two corners. If `south_west` is east of `north_east`, the box crosses the antimeridian.
+    BoundingBox {
+        south_west: GeoPoint,
+        north_east: GeoPoint,
+    },
+    /// Everything within a distance (in meters) of the center
+    Radius { center: GeoPoint, meters: f64 },
+}
+
+impl GeoFilter {
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        match self {
+            GeoFilter::BoundingBox {
+                south_west,
+                north_east,
+            } => {
+                let in_latitude =
+                    (south_west.latitude..=north_east.latitude).contains(&point.latitude);
+                let in_longitude = if south_west.longitude <= north_east.longitude {
+                    (south_west.longitude..=north_east.longitude).contains(&point.longitude)
+                } else {
+                    point.longitude >= south_west.longitude
+                        || point.longitude <= north_east.longitude
+                };
+                in_latitude && in_longitude
+            }
+            GeoFilter::Radius { center, meters } => center.distance_to(point) <= *meters,
+        }
+    }
+
+    /// The latitudes (south, north) that contain the whole area.
+    pub fn latitude_range(&self) -> (f64, f64) {
+        match self {
+            GeoFilter::BoundingBox {
+                south_west,
+                north_east,
+            } => (south_west.latitude, north_east.latitude),
+            GeoFilter::Radius { center, meters } => {
+                // One degree of latitude is roughly 111 km everywhere
+                let degrees = meters / 111_000.0;
+                (
+                    (center.latitude - degrees).max(-90.0),
+                    (center.latitude + degrees).min(90.0),
+                )
+            }
+        }
+    }
+
+    /// Distance from the center, used for sorting results. Bounding boxes have no center.
+    pub fn distance(&self, point: &GeoPoint) -> f64 {
+        match self {
+            GeoFilter::BoundingBox { .. } => 0.0,
+            GeoFilter::Radius { center, .. } => center.distance_to(point),
+        }
+    }
 }
 
 impl Query {
@@ -388,6 +535,7 @@ impl Query {
             include_external: false,
             include_nested: true,
             for_agent: None,
+            geo: None,
         }
     }
 
@@ -414,6 +562,7 @@ impl Default for Query {
     }
 }
 
+#[derive(Clone)]
 pub struct QueryResult {
     pub subjects: Vec<String>,
     pub resources: Vec<Resource>,
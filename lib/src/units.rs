@@ -0,0 +1,121 @@
+//! Numeric Properties can have a [unit](crate::urls::UNIT), such as `kg`, `m` or `EUR`, which applies to all of their values.
+//! This keeps the meaning of the numbers, and lets clients convert them between units of the same kind (e.g. from `cm` to `m`).
+//! Only units with a linear conversion are known, so temperatures can't be converted.
+//! Other units (like currencies) are allowed, but can only be "converted" to themselves.
+
+use crate::{datatype::DataType, errors::AtomicResult, schema::Property, Value};
+
+/// Symbol, kind and how many of the base unit of that kind one of it is.
+const UNITS: &[(&str, &str, f64)] = &[
+    ("mm", "length", 0.001),
+    ("cm", "length", 0.01),
+    ("m", "length", 1.0),
+    ("km", "length", 1000.0),
+    ("in", "length", 0.0254),
+    ("ft", "length", 0.3048),
+    ("mi", "length", 1609.344),
+    ("mg", "mass", 0.000001),
+    ("g", "mass", 0.001),
+    ("kg", "mass", 1.0),
+    ("t", "mass", 1000.0),
+    ("lb", "mass", 0.45359237),
+    ("ms", "time", 0.001),
+    ("s", "time", 1.0),
+    ("min", "time", 60.0),
+    ("h", "time", 3600.0),
+    ("d", "time", 86400.0),
+    ("ml", "volume", 0.001),
+    ("l", "volume", 1.0),
+    ("B", "data", 1.0),
+    ("kB", "data", 1000.0),
+    ("MB", "data", 1_000_000.0),
+    ("GB", "data", 1_000_000_000.0),
+];
+
+fn find(unit: &str) -> Option<(&'static str, f64)> {
+    UNITS
+        .iter()
+        .find(|(symbol, _, _)| *symbol == unit)
+        .map(|(_, kind, factor)| (*kind, *factor))
+}
+
+/// Converts an amount from one unit to another unit of the same kind.
+pub fn convert(amount: f64, from: &str, to: &str) -> AtomicResult<f64> {
+    if from == to {
+        return Ok(amount);
+    }
+    match (find(from), find(to)) {
+        (Some((from_kind, from_factor)), Some((to_kind, to_factor))) if from_kind == to_kind => {
+            Ok(amount * from_factor / to_factor)
+        }
+        _ => Err(format!("Can't convert from '{}' to '{}'", from, to).into()),
+    }
+}
+
+/// Returns an error if a unit can't be used for Properties with this datatype.
+pub fn check_unit(unit: &str, data_type: &DataType) -> AtomicResult<()> {
+    if unit.trim().is_empty() {
+        return Err("A unit can't be empty".into());
+    }
+    match data_type {
+        DataType::Integer | DataType::Float | DataType::Decimal => Ok(()),
+        other => Err(format!(
+            "Only numeric Properties can have a unit, not Properties with datatype {}",
+            other
+        )
+        .into()),
+    }
+}
+
+impl Property {
+    /// Returns the value of this Property in another unit.
+    pub fn convert_value(&self, value: &Value, to: &str) -> AtomicResult<f64> {
+        let from = self
+            .unit
+            .as_ref()
+            .ok_or_else(|| format!("Property {} has no unit", self.subject))?;
+        let amount = match value {
+            Value::Integer(i) => *i as f64,
+            Value::Float(f) => *f,
+            Value::Decimal(d) => d
+                .to_string()
+                .parse()
+                .map_err(|e| format!("Could not convert {}: {}", d, e))?,
+            other => return Err(format!("'{}' is not a number", other).into()),
+        };
+        convert(amount, from, to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_units() {
+        assert_eq!(convert(250.0, "cm", "m").unwrap(), 2.5);
+        assert_eq!(convert(2.0, "h", "min").unwrap(), 120.0);
+        assert_eq!(convert(3.0, "EUR", "EUR").unwrap(), 3.0);
+        convert(1.0, "kg", "m").unwrap_err();
+        convert(1.0, "EUR", "USD").unwrap_err();
+        check_unit("kg", &DataType::Float).unwrap();
+        check_unit("kg", &DataType::String).unwrap_err();
+
+        let property = Property {
+            class_type: None,
+            data_type: DataType::Integer,
+            shortname: "weight".into(),
+            subject: "https://localhost/properties/weight".into(),
+            description: "Weight".into(),
+            allows_only: None,
+            constraints: Default::default(),
+            default_value: None,
+            expression: None,
+            unit: Some("g".into()),
+        };
+        assert_eq!(
+            property.convert_value(&Value::Integer(1500), "kg").unwrap(),
+            1.5
+        );
+    }
+}
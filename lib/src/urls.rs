@@ -16,9 +16,24 @@ pub const FILE: &str = "https://atomicdata.dev/classes/File";
 pub const CHATROOM: &str = "https://atomicdata.dev/classes/ChatRoom";
 pub const PARAGRAPH: &str = "https://atomicdata.dev/classes/elements/Paragraph";
 pub const MESSAGE: &str = "https://atomicdata.dev/classes/Message";
+pub const REACTION: &str = "https://atomicdata.dev/classes/Reaction";
+pub const ARTICLE: &str = "https://atomicdata.dev/classes/Article";
 pub const IMPORTER: &str = "https://atomicdata.dev/classes/Importer";
+pub const IMPORT_REPORT: &str = "https://atomicdata.dev/classes/ImportReport";
 pub const ERROR: &str = "https://atomicdata.dev/classes/Error";
 pub const BOOKMARK: &str = "https://atomicdata.dev/class/Bookmark";
+pub const LINK_PREVIEW: &str = "https://atomicdata.dev/classes/LinkPreview";
+pub const FEATURE_SETTINGS: &str = "https://atomicdata.dev/classes/FeatureSettings";
+pub const PASSKEY: &str = "https://atomicdata.dev/classes/Passkey";
+pub const DOMAIN_MAPPING: &str = "https://atomicdata.dev/classes/DomainMapping";
+pub const JOB: &str = "https://atomicdata.dev/classes/Job";
+pub const DIFF: &str = "https://atomicdata.dev/classes/Diff";
+pub const NOTIFICATION: &str = "https://atomicdata.dev/classes/Notification";
+pub const FORM: &str = "https://atomicdata.dev/classes/Form";
+pub const EVENT: &str = "https://atomicdata.dev/classes/Event";
+pub const QUOTA: &str = "https://atomicdata.dev/classes/Quota";
+pub const SCHEMA_USAGE: &str = "https://atomicdata.dev/classes/SchemaUsage";
+pub const EFFECTIVE_RIGHTS: &str = "https://atomicdata.dev/classes/EffectiveRights";
 
 // Properties
 pub const SHORTNAME: &str = "https://atomicdata.dev/properties/shortname";
@@ -31,15 +46,29 @@ pub const IS_LOCKED: &str = "https://atomicdata.dev/properties/isLocked";
 pub const DATATYPE_PROP: &str = "https://atomicdata.dev/properties/datatype";
 pub const CLASSTYPE_PROP: &str = "https://atomicdata.dev/properties/classtype";
 pub const ALLOWS_ONLY: &str = "https://atomicdata.dev/properties/allowsOnly";
+pub const PATTERN: &str = "https://atomicdata.dev/properties/pattern";
+pub const MINIMUM: &str = "https://atomicdata.dev/properties/minimum";
+pub const MAXIMUM: &str = "https://atomicdata.dev/properties/maximum";
+pub const MAX_LENGTH: &str = "https://atomicdata.dev/properties/maxLength";
+pub const DEFAULT_VALUE: &str = "https://atomicdata.dev/properties/defaultValue";
+pub const EXPRESSION: &str = "https://atomicdata.dev/properties/expression";
+pub const UNIT: &str = "https://atomicdata.dev/properties/unit";
 // ... for Classes
 pub const REQUIRES: &str = "https://atomicdata.dev/properties/requires";
 pub const RECOMMENDS: &str = "https://atomicdata.dev/properties/recommends";
+pub const TRANSLATIONS: &str = "https://atomicdata.dev/properties/translations";
+pub const LANGUAGE: &str = "https://atomicdata.dev/properties/language";
+pub const EXTENDS: &str = "https://atomicdata.dev/properties/extends";
+pub const LOCKED_PROPS: &str = "https://atomicdata.dev/properties/lockedProps";
 // ... for Commits
 pub const SUBJECT: &str = "https://atomicdata.dev/properties/subject";
 pub const SET: &str = "https://atomicdata.dev/properties/set";
 pub const PUSH: &str = "https://atomicdata.dev/properties/push";
 pub const REMOVE: &str = "https://atomicdata.dev/properties/remove";
 pub const DESTROY: &str = "https://atomicdata.dev/properties/destroy";
+pub const MOVE_BEFORE: &str = "https://atomicdata.dev/properties/moveBefore";
+pub const MOVE_AFTER: &str = "https://atomicdata.dev/properties/moveAfter";
+pub const ON_BEHALF_OF: &str = "https://atomicdata.dev/properties/onBehalfOf";
 pub const SIGNER: &str = "https://atomicdata.dev/properties/signer";
 pub const CREATED_AT: &str = "https://atomicdata.dev/properties/createdAt";
 pub const SIGNATURE: &str = "https://atomicdata.dev/properties/signature";
@@ -49,6 +78,29 @@ pub const LAST_COMMIT: &str = "https://atomicdata.dev/properties/lastCommit";
 pub const PUBLIC_KEY: &str = "https://atomicdata.dev/properties/publicKey";
 pub const NAME: &str = "https://atomicdata.dev/properties/name";
 pub const DRIVES: &str = "https://atomicdata.dev/properties/drives";
+pub const AGENT_DISABLED: &str = "https://atomicdata.dev/properties/agent/disabled";
+pub const OIDC_SUBJECT: &str = "https://atomicdata.dev/properties/agent/oidcSubject";
+pub const PASSKEYS: &str = "https://atomicdata.dev/properties/agent/passkeys";
+pub const KEY_HISTORY: &str = "https://atomicdata.dev/properties/agent/keyHistory";
+pub const KEY_ROTATED_AT: &str = "https://atomicdata.dev/properties/agent/keyRotatedAt";
+pub const DEVICE_KEYS: &str = "https://atomicdata.dev/properties/agent/deviceKeys";
+// ... for Passkeys
+pub const PASSKEY_CREDENTIAL_ID: &str = "https://atomicdata.dev/properties/passkey/credentialId";
+pub const PASSKEY_PUBLIC_KEY: &str = "https://atomicdata.dev/properties/passkey/publicKey";
+pub const PASSKEY_ALGORITHM: &str = "https://atomicdata.dev/properties/passkey/algorithm";
+pub const PASSKEY_SIGN_COUNT: &str = "https://atomicdata.dev/properties/passkey/signCount";
+// ... for ActivityPub
+pub const ACTIVITYPUB_HANDLE: &str = "https://atomicdata.dev/properties/activitypubHandle";
+// ... for Articles
+pub const PUBLISHED_AT: &str = "https://atomicdata.dev/properties/published-at";
+// ... for DomainMappings
+pub const HOSTNAME: &str = "https://atomicdata.dev/properties/hostname";
+pub const MAPPED_DRIVE: &str = "https://atomicdata.dev/properties/mappedDrive";
+// ... for Jobs
+pub const JOB_KIND: &str = "https://atomicdata.dev/properties/job/kind";
+pub const JOB_STATUS: &str = "https://atomicdata.dev/properties/job/status";
+pub const JOB_PROGRESS: &str = "https://atomicdata.dev/properties/job/progress";
+pub const JOB_RESULT: &str = "https://atomicdata.dev/properties/job/result";
 // ... for Collections
 pub const COLLECTION_PROPERTY: &str = "https://atomicdata.dev/properties/collection/property";
 pub const COLLECTION_VALUE: &str = "https://atomicdata.dev/properties/collection/value";
@@ -72,17 +124,64 @@ pub const PATH: &str = "https://atomicdata.dev/properties/path";
 pub const SEARCH_QUERY: &str = "https://atomicdata.dev/properties/search/query";
 pub const SEARCH_LIMIT: &str = "https://atomicdata.dev/properties/search/limit";
 pub const SEARCH_PROPERTY: &str = "https://atomicdata.dev/properties/search/property";
+pub const SEARCH_LANGUAGE: &str = "https://atomicdata.dev/properties/search/language";
+pub const SEARCH_FUZZY: &str = "https://atomicdata.dev/properties/search/fuzzy";
+pub const SEARCH_PREFIX: &str = "https://atomicdata.dev/properties/search/prefix";
+pub const SEARCH_CLASS: &str = "https://atomicdata.dev/properties/search/class";
+pub const SEARCH_VALUE: &str = "https://atomicdata.dev/properties/search/value";
+pub const SEARCH_NEAR: &str = "https://atomicdata.dev/properties/search/near";
+pub const SEARCH_RADIUS: &str = "https://atomicdata.dev/properties/search/radius";
+pub const SEARCH_BBOX: &str = "https://atomicdata.dev/properties/search/bbox";
+pub const SEARCH_CLASS_FACETS: &str = "https://atomicdata.dev/properties/search/class-facets";
+pub const SEARCH_DRIVE_FACETS: &str = "https://atomicdata.dev/properties/search/drive-facets";
+pub const FACET_VALUE: &str = "https://atomicdata.dev/properties/search/facet-value";
+pub const FACET_COUNT: &str = "https://atomicdata.dev/properties/search/facet-count";
+pub const SEARCH_PRE_TAG: &str = "https://atomicdata.dev/properties/search/pre-tag";
+pub const SEARCH_POST_TAG: &str = "https://atomicdata.dev/properties/search/post-tag";
+pub const SEARCH_SNIPPET_LENGTH: &str = "https://atomicdata.dev/properties/search/snippet-length";
+pub const SEARCH_SNIPPETS: &str = "https://atomicdata.dev/properties/search/snippets";
+pub const SNIPPET_SUBJECT: &str = "https://atomicdata.dev/properties/search/snippet-subject";
+pub const SNIPPET_TEXT: &str = "https://atomicdata.dev/properties/search/snippet-text";
+pub const SEARCH_INDEX_LAG: &str = "https://atomicdata.dev/properties/search/index-lag";
+pub const RESTORE_COMMIT: &str = "https://atomicdata.dev/properties/restore/commit";
+// ... for Diffs
+pub const DIFF_FROM: &str = "https://atomicdata.dev/properties/diff/from";
+pub const DIFF_TO: &str = "https://atomicdata.dev/properties/diff/to";
+pub const DIFF_CHANGES: &str = "https://atomicdata.dev/properties/diff/changes";
+pub const DIFF_PROPERTY: &str = "https://atomicdata.dev/properties/diff/property";
+pub const DIFF_KIND: &str = "https://atomicdata.dev/properties/diff/kind";
+pub const DIFF_OLD_VALUE: &str = "https://atomicdata.dev/properties/diff/old-value";
+pub const DIFF_NEW_VALUE: &str = "https://atomicdata.dev/properties/diff/new-value";
+pub const MOVE_REWRITE_SUBJECTS: &str = "https://atomicdata.dev/properties/move/rewrite-subjects";
+pub const RENAME_SLUG: &str = "https://atomicdata.dev/properties/rename/slug";
+pub const RENAME_KEPT_REFERENCES: &str = "https://atomicdata.dev/properties/rename/kept-references";
 pub const URL: &str = "https://atomicdata.dev/property/url";
 pub const PREVIEW: &str = "https://atomicdata.dev/property/preview";
 // ... for Bookmarks
 pub const IMAGE_URL: &str = "https://atomicdata.dev/properties/imageUrl";
+// ... for LinkPreviews
+pub const FAVICON_URL: &str = "https://atomicdata.dev/properties/faviconUrl";
 // ... for Hierarchy / Drive
 pub const PARENT: &str = "https://atomicdata.dev/properties/parent";
 pub const READ: &str = "https://atomicdata.dev/properties/read";
 pub const WRITE: &str = "https://atomicdata.dev/properties/write";
 pub const APPEND: &str = "https://atomicdata.dev/properties/append";
+pub const COMMENT_RIGHT: &str = "https://atomicdata.dev/properties/comment";
+pub const INVITE_RIGHT: &str = "https://atomicdata.dev/properties/invite";
+pub const DESTROY_RIGHT: &str = "https://atomicdata.dev/properties/destroyRight";
+pub const DENY_READ: &str = "https://atomicdata.dev/properties/denyRead";
+pub const DENY_WRITE: &str = "https://atomicdata.dev/properties/denyWrite";
+pub const PUBLIC_READ: &str = "https://atomicdata.dev/properties/publicRead";
+pub const ALLOWED_NETWORKS: &str = "https://atomicdata.dev/properties/allowedNetworks";
 pub const CHILDREN: &str = "https://atomicdata.dev/properties/children";
 pub const SUBRESOURCES: &str = "https://atomicdata.dev/properties/subresources";
+pub const SORT_KEY: &str = "https://atomicdata.dev/properties/sortKey";
+// ... for places
+pub const LOCATION: &str = "https://atomicdata.dev/properties/location";
+// ... for machine access to a Hierarchy
+pub const NO_INDEX: &str = "https://atomicdata.dev/properties/noIndex";
+pub const NO_EXPORT: &str = "https://atomicdata.dev/properties/noExport";
+pub const NO_STREAM: &str = "https://atomicdata.dev/properties/noStream";
 // ... for Inivtations
 pub const DESTINATION: &str = "https://atomicdata.dev/properties/destination";
 pub const TARGET: &str = "https://atomicdata.dev/properties/invite/target";
@@ -93,6 +192,7 @@ pub const INVITE_PUBKEY: &str = "https://atomicdata.dev/properties/invite/public
 pub const INVITE_AGENT: &str = "https://atomicdata.dev/properties/invite/agent";
 pub const REDIRECT_AGENT: &str = "https://atomicdata.dev/properties/invite/redirectAgent";
 pub const EXPIRES_AT: &str = "https://atomicdata.dev/properties/invite/expiresAt";
+pub const INVITE_REVOKED: &str = "https://atomicdata.dev/properties/invite/revoked";
 // ... for Atoms
 pub const ATOM_SUBJECT: &str = "https://atomicdata.dev/properties/atom/subject";
 pub const ATOM_PROPERTY: &str = "https://atomicdata.dev/properties/atom/property";
@@ -104,16 +204,87 @@ pub const FILESIZE: &str = "https://atomicdata.dev/properties/filesize";
 pub const MIMETYPE: &str = "https://atomicdata.dev/properties/mimetype";
 pub const INTERNAL_ID: &str = "https://atomicdata.dev/properties/internalId";
 pub const DOWNLOAD_URL: &str = "https://atomicdata.dev/properties/downloadURL";
+pub const SCAN_STATUS: &str = "https://atomicdata.dev/properties/scanStatus";
+pub const FILE_TEXT: &str = "https://atomicdata.dev/properties/fileText";
 pub const ATTACHMENTS: &str = "https://atomicdata.dev/properties/attachments";
 // ... for ChatRooms and Messages
 pub const MESSAGES: &str = "https://atomicdata.dev/properties/messages";
 pub const NEXT_PAGE: &str = "https://atomicdata.dev/properties/nextPage";
+pub const EDITED_AT: &str = "https://atomicdata.dev/properties/editedAt";
+pub const DELETED: &str = "https://atomicdata.dev/properties/deleted";
+pub const REPLY_TO: &str = "https://atomicdata.dev/properties/replyTo";
+pub const REPLIES: &str = "https://atomicdata.dev/properties/replies";
+pub const REACTIONS: &str = "https://atomicdata.dev/properties/reactions";
+pub const EMOJI: &str = "https://atomicdata.dev/properties/emoji";
+pub const REACTION_AGENTS: &str = "https://atomicdata.dev/properties/reactionAgents";
+// ... for Notifications
+pub const NOTIFICATION_RECIPIENT: &str = "https://atomicdata.dev/properties/notification/recipient";
+pub const NOTIFICATION_ACTOR: &str = "https://atomicdata.dev/properties/notification/actor";
+pub const NOTIFICATION_KIND: &str = "https://atomicdata.dev/properties/notification/kind";
+pub const NOTIFICATION_TARGET: &str = "https://atomicdata.dev/properties/notification/target";
+pub const NOTIFICATION_READ: &str = "https://atomicdata.dev/properties/notification/read";
+pub const UNREAD_COUNT: &str = "https://atomicdata.dev/properties/notification/unreadCount";
+// ... for Forms
+pub const FORM_CLASS: &str = "https://atomicdata.dev/properties/form/class";
+pub const FORM_TARGET: &str = "https://atomicdata.dev/properties/form/target";
+pub const SUBMITTED_FORM: &str = "https://atomicdata.dev/properties/form";
+// ... for Events
+pub const EVENT_START: &str = "https://atomicdata.dev/properties/event/start";
+pub const EVENT_END: &str = "https://atomicdata.dev/properties/event/end";
+pub const EVENT_RECURRENCE: &str = "https://atomicdata.dev/properties/event/recurrence";
+// ... for Quotas
+pub const QUOTA_MAX_RESOURCES: &str = "https://atomicdata.dev/properties/quota/max-resources";
+pub const QUOTA_MAX_FILE_SIZE: &str = "https://atomicdata.dev/properties/quota/max-file-size";
+pub const QUOTA_MAX_COMMITS_PER_DAY: &str =
+    "https://atomicdata.dev/properties/quota/max-commits-per-day";
+pub const USAGE_DRIVE: &str = "https://atomicdata.dev/properties/usage/drive";
+pub const USAGE_RESOURCES: &str = "https://atomicdata.dev/properties/usage/resources";
+pub const USAGE_FILE_SIZE: &str = "https://atomicdata.dev/properties/usage/file-size";
+pub const USAGE_COMMITS_TODAY: &str = "https://atomicdata.dev/properties/usage/commits-today";
+// ... for SchemaUsage
+pub const SCHEMA_USAGE_COUNT: &str = "https://atomicdata.dev/properties/schema-usage/count";
+pub const SCHEMA_USAGE_EXAMPLES: &str = "https://atomicdata.dev/properties/schema-usage/examples";
+pub const SCHEMA_USAGE_VALUE: &str = "https://atomicdata.dev/properties/schema-usage/value";
+pub const SCHEMA_USAGE_VIOLATIONS: &str =
+    "https://atomicdata.dev/properties/schema-usage/violations";
+pub const SCHEMA_USAGE_VIOLATION_COUNT: &str =
+    "https://atomicdata.dev/properties/schema-usage/violation-count";
+// ... for EffectiveRights
+pub const EFFECTIVE_RIGHTS_AGENT: &str = "https://atomicdata.dev/properties/effective-rights/agent";
+pub const EFFECTIVE_RIGHTS_GRANTED: &str =
+    "https://atomicdata.dev/properties/effective-rights/granted";
+pub const EFFECTIVE_RIGHTS_EXPLANATIONS: &str =
+    "https://atomicdata.dev/properties/effective-rights/explanations";
+pub const EFFECTIVE_RIGHTS_RIGHT: &str = "https://atomicdata.dev/properties/effective-rights/right";
+pub const EFFECTIVE_RIGHTS_ALLOWED: &str =
+    "https://atomicdata.dev/properties/effective-rights/allowed";
+pub const EFFECTIVE_RIGHTS_PATH: &str = "https://atomicdata.dev/properties/effective-rights/path";
+// ... for FeatureSettings
+pub const FEATURE_CHATROOMS: &str = "https://atomicdata.dev/properties/features/chatrooms";
+pub const FEATURE_INVITES: &str = "https://atomicdata.dev/properties/features/invites";
+pub const FEATURE_UPLOADS: &str = "https://atomicdata.dev/properties/features/uploads";
+pub const FEATURE_SEARCH: &str = "https://atomicdata.dev/properties/features/search";
+// ... for the audit log
+pub const AUDIT_EVENT: &str = "https://atomicdata.dev/properties/audit/event";
+pub const AUDIT_IP: &str = "https://atomicdata.dev/properties/audit/ip";
+pub const AUDIT_USER_AGENT: &str = "https://atomicdata.dev/properties/audit/userAgent";
+pub const AUDIT_OUTCOME: &str = "https://atomicdata.dev/properties/audit/outcome";
 // ... for Importers
 pub const IMPORTER_URL: &str = "https://atomicdata.dev/properties/importer/url";
 pub const IMPORTER_JSON: &str = "https://atomicdata.dev/properties/importer/json";
 pub const IMPORTER_PARENT: &str = "https://atomicdata.dev/properties/importer/parent";
 pub const IMPORTER_OVERWRITE_OUTSIDE: &str =
     "https://atomicdata.dev/properties/importer/overwrite-outside";
+pub const IMPORTER_SKIP_INVALID: &str = "https://atomicdata.dev/properties/importer/skip-invalid";
+pub const IMPORTER_IMPORTED_COUNT: &str =
+    "https://atomicdata.dev/properties/importer/imported-count";
+pub const IMPORTER_FAILURES: &str = "https://atomicdata.dev/properties/importer/failures";
+pub const IMPORTER_LINE: &str = "https://atomicdata.dev/properties/importer/line";
+pub const IMPORTER_FAILED_SUBJECT: &str =
+    "https://atomicdata.dev/properties/importer/failed-subject";
+pub const IMPORTER_FORMAT: &str = "https://atomicdata.dev/properties/importer/format";
+pub const IMPORTER_STATUS: &str = "https://atomicdata.dev/properties/importer/status";
+pub const IMPORTER_TOTAL_COUNT: &str = "https://atomicdata.dev/properties/importer/total-count";
 pub const LOCAL_ID: &str = "https://atomicdata.dev/properties/localId";
 
 // Datatypes
@@ -127,6 +298,9 @@ pub const RESOURCE_ARRAY: &str = "https://atomicdata.dev/datatypes/resourceArray
 pub const BOOLEAN: &str = "https://atomicdata.dev/datatypes/boolean";
 pub const DATE: &str = "https://atomicdata.dev/datatypes/date";
 pub const TIMESTAMP: &str = "https://atomicdata.dev/datatypes/timestamp";
+pub const GEO_POINT: &str = "https://atomicdata.dev/datatypes/geoPoint";
+pub const DECIMAL: &str = "https://atomicdata.dev/datatypes/decimal";
+pub const BYTES: &str = "https://atomicdata.dev/datatypes/bytes";
 
 // Methods
 pub const INSERT: &str = "https://atomicdata.dev/methods/insert";
@@ -142,3 +316,4 @@ pub fn construct_path_import(base: &str) -> String {
 
 pub const PATH_IMPORT: &str = "/import";
 pub const PATH_FETCH_BOOKMARK: &str = "/fetch-bookmark";
+pub const PATH_LINK_PREVIEW: &str = "/link-preview";
@@ -1,142 +1,380 @@
-//! Validate the Store and create a ValidationReport.
-//! Might be deprecated soon, as Validation hasn't been necessary since parsing has built-in data validation.
+//! Validate the Store and create a [ValidationReport].
+//! [validate_json_ad] checks JSON-AD documents before they are imported.
 
-/// Checks Atomic Data in the store for validity.
-/// Returns an Error if it is not valid.
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::datatype::DataType;
+
+/// Checks all resources in the store against their Properties and Classes.
+/// Does not stop at the first problem, but returns a report with every issue, per resource.
 ///
 /// Validates:
 ///
-/// - [X] If the Values can be parsed using their Datatype (e.g. if Integers are integers)
-/// - [X] If all required fields of the class are present
-/// - [X] If the URLs are publicly accessible
-/// - [ ] ..and return the right type of data?
-/// - [X] Returns a report, instead of throwing an error
-#[allow(dead_code, unreachable_code)]
-pub fn validate_store(
-    store: &impl crate::Storelike,
-    fetch_items: bool,
-) -> crate::validate::ValidationReport {
-    type Error = String;
-    let mut resource_count: u8 = 0;
-    let mut atom_count: u8 = 0;
-    let mut unfetchable: Vec<(String, Error)> = Vec::new();
-    let mut invalid_value: Vec<(crate::Atom, Error)> = Vec::new();
-    let mut unfetchable_props: Vec<(String, Error)> = Vec::new();
-    let mut unfetchable_classes: Vec<(String, Error)> = Vec::new();
-    // subject, property, class
-    let mut missing_props: Vec<(String, String, String)> = Vec::new();
+/// - If the Properties exist, and if the Values have the Datatype of their Property
+/// - If the Classes (`isA`) exist, and if the required Properties of these Classes are present
+/// - If the resources are publicly accessible, when `fetch_items` is true (as warnings)
+pub fn validate_store(store: &impl crate::Storelike, fetch_items: bool) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let unfetchable: std::collections::HashMap<String, String> = if fetch_items {
+        let subjects: Vec<String> = store
+            .all_resources(true)
+            .map(|resource| resource.get_subject().clone())
+            .collect();
+        let fetched =
+            crate::client::fetch_resources(&subjects, store, store.get_default_agent().ok());
+        subjects
+            .into_iter()
+            .zip(fetched)
+            .filter_map(|(subject, result)| result.err().map(|e| (subject, e.to_string())))
+            .collect()
+    } else {
+        Default::default()
+    };
     for resource in store.all_resources(true) {
         let subject = resource.get_subject();
-        let propvals = resource.get_propvals();
-        println!("Subject: {:?}", subject);
-        println!("Resource: {:?}", propvals);
-        resource_count += 1;
-
-        if fetch_items {
-            match crate::client::fetch_resource(subject, store, store.get_default_agent().ok()) {
-                Ok(_) => {}
-                Err(e) => unfetchable.push((subject.clone(), e.to_string())),
-            }
-        }
-
-        let mut found_props: Vec<String> = Vec::new();
+        report.resource_count += 1;
+        let mut issue = |kind: IssueKind, property: Option<&str>, message: String| {
+            report.issues.push(ResourceIssue {
+                severity: kind.severity(),
+                kind,
+                subject: subject.clone(),
+                property: property.map(String::from),
+                message,
+            })
+        };
 
-        for (prop_url, value) in propvals {
-            atom_count += 1;
+        if let Some(e) = unfetchable.get(subject) {
+            issue(IssueKind::Unfetchable, None, e.clone());
+        }
 
+        for (prop_url, value) in resource.get_propvals() {
             let property = match store.get_property(prop_url) {
-                Ok(prop) => prop,
+                Ok(property) => property,
                 Err(e) => {
-                    unfetchable_props.push((prop_url.clone(), e.to_string()));
-                    break;
+                    issue(IssueKind::UnknownProperty, Some(prop_url), e.to_string());
+                    continue;
                 }
             };
+            // Values with another datatype are fine if they can be parsed as the datatype of the Property, like Strings as Markdown
+            if value.datatype() != property.data_type {
+                if let Err(e) = crate::Value::new(&value.to_string(), &property.data_type) {
+                    issue(IssueKind::InvalidDatatype, Some(prop_url), e.to_string());
+                }
+            }
+        }
+        let atom_count = resource.get_propvals().len();
 
-            // Maybe this is no longer needed, because no store uses strings anymore
-            match crate::Value::new(&value.to_string(), &property.data_type) {
-                Ok(_) => {}
-                Err(e) => invalid_value.push((
-                    crate::Atom::new(subject.clone(), prop_url.clone(), value.clone()),
-                    e.to_string(),
-                )),
+        let class_subjects = resource
+            .get(crate::urls::IS_A)
+            .and_then(|v| v.to_subjects(None))
+            .unwrap_or_default();
+        for class_subject in class_subjects {
+            let class = match store.get_class(&class_subject) {
+                Ok(class) => class,
+                Err(e) => {
+                    issue(
+                        IssueKind::UnknownClass,
+                        Some(crate::urls::IS_A),
+                        format!("Class {} can't be found: {}", class_subject, e),
+                    );
+                    continue;
+                }
             };
-            found_props.push(prop_url.clone());
-        }
-        let classes = match store.get_classes_for_subject(subject) {
-            Ok(classes) => classes,
-            Err(e) => {
-                unfetchable_classes.push((subject.clone(), e.to_string()));
-                break;
-            }
-        };
-        for class in classes {
-            println!("Class: {:?}", class.shortname);
-            println!("Found: {:?}", found_props);
-            for required_prop_subject in class.requires {
-                match store.get_property(&required_prop_subject) {
-                    Ok(required_prop) => {
-                        println!("Required: {:?}", required_prop.shortname);
-                        if !found_props.contains(&required_prop.subject) {
-                            missing_props.push((
-                                subject.clone(),
-                                required_prop.subject.clone(),
-                                class.subject.clone(),
-                            ));
-                        }
-                    }
-                    Err(e) => unfetchable.push((required_prop_subject, e.to_string())),
+            for required in &class.requires {
+                if resource.get(required).is_err() {
+                    issue(
+                        IssueKind::MissingRequired,
+                        Some(required),
+                        format!("Required by {}", class.shortname),
+                    );
                 }
             }
         }
-        println!("{:?} Valid", subject);
+        report.atom_count += atom_count;
     }
-    crate::validate::ValidationReport {
-        unfetchable,
-        unfetchable_classes,
-        unfetchable_props,
-        invalid_value,
-        resource_count,
-        atom_count,
+    report
+}
+
+/// How serious an issue is. Only errors make a report invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    /// The data is probably fine, but might be incomplete or unreachable.
+    Warning,
+    /// The data does not match its schema.
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Warning => fmt.write_str("warning"),
+            Severity::Error => fmt.write_str("error"),
+        }
     }
 }
 
+/// A problem with a resource in the store, found by [validate_store].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResourceIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    pub subject: String,
+    pub property: Option<String>,
+    pub message: String,
+}
+
+/// The outcome of [validate_store].
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
 pub struct ValidationReport {
-    pub resource_count: u8,
-    pub atom_count: u8,
-    pub unfetchable: Vec<(String, String)>,
-    pub invalid_value: Vec<(crate::Atom, String)>,
-    pub unfetchable_props: Vec<(String, String)>,
-    pub unfetchable_classes: Vec<(String, String)>,
+    pub resource_count: usize,
+    pub atom_count: usize,
+    pub issues: Vec<ResourceIssue>,
 }
 
 impl ValidationReport {
+    /// True if there are no issues with [Severity::Error]. Warnings are allowed.
     pub fn is_valid(&self) -> bool {
-        self.unfetchable.is_empty()
-            && self.unfetchable_classes.is_empty()
-            && self.unfetchable_props.is_empty()
-            && self.invalid_value.is_empty()
+        self.count(Severity::Error) == 0
+    }
+
+    /// The number of issues with this severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .count()
+    }
+
+    /// The issues of a single resource.
+    pub fn for_subject<'a>(&'a self, subject: &'a str) -> impl Iterator<Item = &'a ResourceIssue> {
+        self.issues.iter().filter(move |i| i.subject == subject)
     }
 }
 
 impl std::fmt::Display for ValidationReport {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        if self.is_valid() {
-            fmt.write_str("Valid!")?;
-            return Ok(());
+        for issue in &self.issues {
+            writeln!(
+                fmt,
+                "{} {} {}: {}",
+                issue.severity,
+                issue.subject,
+                issue.property.as_deref().unwrap_or(""),
+                issue.message
+            )?;
         }
-        for (subject, error) in &self.unfetchable {
-            fmt.write_str(&format!("Cannot fetch Resource {}: {} \n", subject, error))?;
+        write!(
+            fmt,
+            "Checked {} resources and {} atoms, found {} errors and {} warnings.",
+            self.resource_count,
+            self.atom_count,
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )
+    }
+}
+
+/// What kind of problem [validate_json_ad] or [validate_store] found.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IssueKind {
+    /// The resource is not a valid JSON object.
+    InvalidJson,
+    /// The Property can't be found, or is not a Property.
+    UnknownProperty,
+    /// The value does not match the datatype of its Property.
+    InvalidDatatype,
+    /// The Class of the resource can't be found.
+    UnknownClass,
+    /// A Property that is required by one of the Classes of the resource is missing.
+    MissingRequired,
+    /// The resource can't be fetched from its subject.
+    Unfetchable,
+}
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::Unfetchable => Severity::Warning,
+            _ => Severity::Error,
         }
-        for (subject, error) in &self.unfetchable_classes {
-            fmt.write_str(&format!("Cannot fetch Class {}: {} \n", subject, error))?;
+    }
+}
+
+/// A problem in a JSON-AD document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    /// Line in the document where the resource starts.
+    pub line: usize,
+    /// The `@id` or `localId` of the resource, if it has one.
+    pub subject: Option<String>,
+    pub property: Option<String>,
+    pub message: String,
+}
+
+/// The outcome of [validate_json_ad].
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct JsonAdReport {
+    pub resource_count: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl JsonAdReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks a JSON-AD document against the schema in the store, without saving anything.
+/// Unlike parsing, this does not stop at the first problem: every resource and Property is checked,
+/// so all issues can be fixed at once. Useful for checking data in CI.
+///
+/// Checks if every Property exists, if the values match their datatypes,
+/// and if the Properties that are required by the Classes of the resources are present.
+/// Strings that are not URLs are accepted as references when the document uses `localId`s.
+/// Returns an error if the document is not a JSON object or array.
+pub fn validate_json_ad(
+    string: &str,
+    store: &impl crate::Storelike,
+) -> crate::errors::AtomicResult<JsonAdReport> {
+    let items = crate::parse::split_json_array(string)?;
+    let uses_local_ids = string.contains(crate::urls::LOCAL_ID);
+    let mut report = JsonAdReport {
+        resource_count: items.len(),
+        issues: Vec::new(),
+    };
+    for (line, item) in items {
+        let object: Map<String, JsonValue> = match serde_json::from_str(item) {
+            Ok(object) => object,
+            Err(e) => {
+                report.issues.push(ValidationIssue {
+                    kind: IssueKind::InvalidJson,
+                    line,
+                    subject: None,
+                    property: None,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let subject = ["@id", crate::urls::LOCAL_ID]
+            .iter()
+            .find_map(|key| object.get(*key).and_then(|v| v.as_str()))
+            .map(String::from);
+        let mut issue = |kind: IssueKind, property: Option<&str>, message: String| {
+            report.issues.push(ValidationIssue {
+                kind,
+                line,
+                subject: subject.clone(),
+                property: property.map(String::from),
+                message,
+            })
+        };
+        check_object(&object, store, uses_local_ids, &mut issue);
+
+        // Nested resources don't need to be complete, so only the root resources are checked for required Properties
+        let classes = object
+            .get(crate::urls::IS_A)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for class in classes.iter().filter_map(|c| c.as_str()) {
+            let class = match store.get_class(class) {
+                Ok(class) => class,
+                Err(e) => {
+                    issue(
+                        IssueKind::UnknownClass,
+                        Some(crate::urls::IS_A),
+                        format!("Class {} can't be found: {}", class, e),
+                    );
+                    continue;
+                }
+            };
+            for required in &class.requires {
+                if !object.contains_key(required) {
+                    issue(
+                        IssueKind::MissingRequired,
+                        Some(required),
+                        format!("Required by {}", class.shortname),
+                    );
+                }
+            }
         }
-        for (subject, error) in &self.unfetchable_props {
-            fmt.write_str(&format!("Cannot fetch Property {}: {} \n", subject, error))?;
+    }
+    Ok(report)
+}
+
+/// Checks the Properties and values of a (possibly nested) resource.
+fn check_object(
+    object: &Map<String, JsonValue>,
+    store: &impl crate::Storelike,
+    uses_local_ids: bool,
+    issue: &mut dyn FnMut(IssueKind, Option<&str>, String),
+) {
+    let is_reference = |s: &str| uses_local_ids || crate::utils::check_valid_url(s).is_ok();
+    for (prop, value) in object {
+        if prop == "@id" || prop == crate::urls::LOCAL_ID {
+            continue;
         }
-        for (atom, error) in &self.invalid_value {
-            fmt.write_str(&format!("Invalid value {:?}: {} \n", atom, error))?;
+        let property = match store.get_property(prop) {
+            Ok(property) => property,
+            Err(e) => {
+                issue(IssueKind::UnknownProperty, Some(prop), e.to_string());
+                continue;
+            }
+        };
+        let datatype = &property.data_type;
+        let invalid = match value {
+            JsonValue::Null => Some("Null is not allowed in JSON-AD".to_string()),
+            JsonValue::Bool(_) if datatype != &DataType::Boolean => {
+                Some(format!("A boolean is not a valid {}", datatype))
+            }
+            JsonValue::Bool(_) => None,
+            JsonValue::Number(n) => crate::Value::new(&n.to_string(), datatype)
+                .err()
+                .map(|e| e.to_string()),
+            JsonValue::String(_) if datatype == &DataType::ResourceArray => {
+                Some("A ResourceArray should be a JSON array".to_string())
+            }
+            JsonValue::String(s) if datatype == &DataType::AtomicUrl => {
+                (!is_reference(s)).then(|| format!("{} is not a URL", s))
+            }
+            JsonValue::String(s) => crate::Value::new(s, datatype).err().map(|e| e.to_string()),
+            JsonValue::Array(_) if datatype != &DataType::ResourceArray => {
+                Some(format!("An array is not a valid {}", datatype))
+            }
+            JsonValue::Array(items) => {
+                let mut invalid = None;
+                for item in items {
+                    match item {
+                        JsonValue::String(s) if !is_reference(s) => {
+                            invalid = Some(format!("{} is not a URL", s));
+                        }
+                        JsonValue::String(_) => {}
+                        JsonValue::Object(nested) => {
+                            check_object(nested, store, uses_local_ids, issue)
+                        }
+                        other => {
+                            invalid = Some(format!(
+                                "Found non-string item in resource array: {}",
+                                other
+                            ))
+                        }
+                    }
+                }
+                invalid
+            }
+            JsonValue::Object(nested) => {
+                check_object(nested, store, uses_local_ids, issue);
+                None
+            }
+        };
+        if let Some(message) = invalid {
+            issue(IssueKind::InvalidDatatype, Some(prop), message);
         }
-        Ok(())
     }
 }
 
@@ -148,9 +386,82 @@ mod test {
     fn validate_populated() {
         let store = Store::init().unwrap();
         store.populate().unwrap();
-        // let report = store.validate();
-        // assert!(report.atom_count > 30);
-        // assert!(report.resource_count > 5);
-        // assert!(report.is_valid());
+        let report = store.validate();
+        assert!(report.atom_count > 30);
+        assert!(report.resource_count > 5);
+        assert!(report.is_valid());
+
+        let subject = "https://localhost/invalid";
+        let mut resource = crate::Resource::new(subject.into());
+        resource.set_propval_unsafe(
+            crate::urls::IS_A.into(),
+            vec![crate::urls::PROPERTY, "https://localhost/not-a-class"].into(),
+        );
+        resource.set_propval_unsafe(
+            crate::urls::SHORTNAME.into(),
+            vec!["https://localhost/not-a-slug"].into(),
+        );
+        store
+            .add_resource_opts(&resource, false, true, true)
+            .unwrap();
+        let report = store.validate();
+        assert!(!report.is_valid());
+        use super::{IssueKind::*, Severity};
+        let mut issues: Vec<(super::IssueKind, Option<&str>)> = report
+            .for_subject(subject)
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| (i.kind, i.property.as_deref()))
+            .collect();
+        issues.sort_by_key(|(kind, prop)| (format!("{:?}", kind), *prop));
+        assert_eq!(
+            issues,
+            vec![
+                (InvalidDatatype, Some(crate::urls::SHORTNAME)),
+                (MissingRequired, Some(crate::urls::DATATYPE_PROP)),
+                (MissingRequired, Some(crate::urls::DESCRIPTION)),
+                (UnknownClass, Some(crate::urls::IS_A)),
+            ]
+        );
+        assert_eq!(report.count(Severity::Error), 4);
+        let json = serde_json::to_value(report.for_subject(subject).next()).unwrap();
+        assert_eq!(json["subject"], subject);
+        assert!(json["severity"].is_string());
+    }
+
+    #[test]
+    fn validate_json_ad_document() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let json = r#"[
+  {
+    "@id": "https://example.com/valid",
+    "https://atomicdata.dev/properties/isA": ["https://atomicdata.dev/classes/Article"],
+    "https://atomicdata.dev/properties/name": "Valid",
+    "https://atomicdata.dev/properties/description": "An article"
+  },
+  {
+    "@id": "https://example.com/invalid",
+    "https://atomicdata.dev/properties/isA": ["https://atomicdata.dev/classes/Article"],
+    "https://atomicdata.dev/properties/published-at": "yesterday",
+    "https://atomicdata.dev/properties/tags": "not-an-array"
+  },
+  { "@id": "broken" "missing-comma": 1 }
+]"#;
+        let report = super::validate_json_ad(json, &store).unwrap();
+        assert_eq!(report.resource_count, 3);
+        let kinds: Vec<(super::IssueKind, usize)> =
+            report.issues.iter().map(|i| (i.kind, i.line)).collect();
+        use super::IssueKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                (InvalidDatatype, 8),
+                (InvalidDatatype, 8),
+                (MissingRequired, 8),
+                (MissingRequired, 8),
+                (InvalidJson, 14)
+            ]
+        );
+        assert!(!report.is_valid());
     }
 }
@@ -1,9 +1,10 @@
 //! A value is the part of an Atom that contains the actual information.
 
 use crate::{
-    datatype::match_datatype, datatype::DataType, errors::AtomicResult, resources::PropVals,
-    utils::check_valid_url, Resource,
+    datatype::match_datatype, datatype::DataType, decimal::Decimal, errors::AtomicResult,
+    resources::PropVals, utils::check_valid_url, Resource,
 };
+use base64::{engine::general_purpose, Engine};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
@@ -26,8 +27,15 @@ pub enum Value {
     Resource(Box<Resource>),
     Boolean(bool),
     Unsupported(UnsupportedValue),
+    // New variants go at the end, since Values are stored using bincode
+    GeoPoint(GeoPoint),
+    Decimal(Decimal),
+    Bytes(Vec<u8>),
 }
 
+/// The maximum size of a [Value::Bytes], in bytes. Larger payloads should be uploaded as files.
+pub const MAX_BYTES: usize = 64 * 1024;
+
 /// A resource in a JSON-AD body can be any of these
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SubResource {
@@ -47,6 +55,66 @@ pub struct UnsupportedValue {
     pub datatype: String,
 }
 
+/// A location on earth, as WGS84 latitude and longitude in degrees.
+/// Serialized as `{latitude},{longitude}`, e.g. `52.3676,4.9041`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Mean radius of the earth, used for calculating distances
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+impl GeoPoint {
+    /// Fails if the latitude or longitude is out of range.
+    pub fn new(latitude: f64, longitude: f64) -> AtomicResult<GeoPoint> {
+        if !(-90.0..=90.0).contains(&latitude) {
+            return Err(format!("Latitude {} should be between -90 and 90", latitude).into());
+        }
+        if !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("Longitude {} should be between -180 and 180", longitude).into());
+        }
+        Ok(GeoPoint {
+            latitude,
+            longitude,
+        })
+    }
+
+    /// The great-circle distance in meters, using the haversine formula.
+    pub fn distance_to(&self, other: &GeoPoint) -> f64 {
+        let (lat_a, lat_b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat_b - lat_a;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let h =
+            (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_METERS * h.sqrt().min(1.0).asin()
+    }
+}
+
+impl std::str::FromStr for GeoPoint {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Not a valid GeoPoint: {}. Needs to be latitude,longitude.",
+                s
+            )
+        };
+        let (latitude, longitude) = s.split_once(',').ok_or_else(invalid)?;
+        let latitude: f64 = latitude.trim().parse().map_err(|_e| invalid())?;
+        let longitude: f64 = longitude.trim().parse().map_err(|_e| invalid())?;
+        GeoPoint::new(latitude, longitude)
+    }
+}
+
+impl std::fmt::Display for GeoPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
 /// Only alphanumeric characters, no spaces
 pub const SLUG_REGEX: &str = r"^[a-z0-9]+(?:-[a-z0-9]+)*$";
 /// YYYY-MM-DD
@@ -82,6 +150,9 @@ impl Value {
             Value::Resource(_) => DataType::AtomicUrl,
             Value::Boolean(_) => DataType::Boolean,
             Value::Unsupported(s) => DataType::Unsupported(s.datatype.clone()),
+            Value::GeoPoint(_) => DataType::GeoPoint,
+            Value::Decimal(_) => DataType::Decimal,
+            Value::Bytes(_) => DataType::Bytes,
         }
     }
 
@@ -137,6 +208,22 @@ impl Value {
                     .map_err(|e| format!("Not a valid Timestamp: {}. {}", value, e))?;
                 Ok(Value::Timestamp(val))
             }
+            DataType::GeoPoint => Ok(Value::GeoPoint(value.parse()?)),
+            DataType::Decimal => Ok(Value::Decimal(value.parse()?)),
+            DataType::Bytes => {
+                let bytes = general_purpose::STANDARD
+                    .decode(value)
+                    .map_err(|e| format!("Not valid base64: {}", e))?;
+                if bytes.len() > MAX_BYTES {
+                    return Err(format!(
+                        "Bytes value is {} bytes, the maximum is {}. Upload larger payloads as files.",
+                        bytes.len(),
+                        MAX_BYTES
+                    )
+                    .into());
+                }
+                Ok(Value::Bytes(bytes))
+            }
             DataType::Unsupported(unsup_url) => Ok(Value::Unsupported(UnsupportedValue {
                 value: value.into(),
                 datatype: unsup_url.into(),
@@ -231,10 +318,27 @@ impl Value {
     pub fn to_sortable_string(&self) -> SortableValue {
         match self {
             Value::ResourceArray(arr) => arr.len().to_string(),
+            Value::Decimal(decimal) => decimal.to_sortable_string(),
             other => other.to_string(),
         }
     }
 
+    /// The PropVals of the anonymous nested resources in this value, which can be a single nested resource or a ResourceArray.
+    pub fn nested_propvals(&self) -> Vec<&PropVals> {
+        let items = match self {
+            Value::NestedResource(item) => std::slice::from_ref(item),
+            Value::ResourceArray(items) => items.as_slice(),
+            _ => return Vec::new(),
+        };
+        items
+            .iter()
+            .filter_map(|item| match item {
+                SubResource::Nested(propvals) => Some(propvals),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Converts one Value to a bunch of indexable items.
     /// Returns None for unsupported types.
     pub fn to_reference_index_strings(&self) -> Option<Vec<ReferenceString>> {
@@ -242,7 +346,7 @@ impl Value {
             // TODO: This results in wrong indexing, as some subjects will be numbers.
             Value::ResourceArray(_v) => self.to_subjects(None).unwrap_or_else(|_| vec![]),
             Value::AtomicUrl(v) => vec![v.into()],
-            // TODO We don't index nested resources for now
+            // The values of nested resources are indexed separately, see [crate::atoms::nested_path]
             Value::Resource(_r) => return None,
             Value::NestedResource(_r) => return None,
             // This might result in unnecessarily long strings, sometimes. We may want to shorten them later.
@@ -381,6 +485,9 @@ impl fmt::Display for Value {
             Value::NestedResource(n) => write!(f, "{:?}", n),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Unsupported(u) => write!(f, "{}", u.value),
+            Value::GeoPoint(p) => write!(f, "{}", p),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Bytes(b) => write!(f, "{}", general_purpose::STANDARD.encode(b)),
         }
     }
 }
@@ -447,6 +554,11 @@ mod test {
         assert!(date.to_string() == "1200-02-02");
         let float = Value::new("1.123123", &DataType::Float).unwrap();
         assert!(float.to_string() == "1.123123");
+        let decimal = Value::new("0019.90", &DataType::Decimal).unwrap();
+        assert!(decimal.to_string() == "19.9");
+        let bytes = Value::new("AAH/", &DataType::Bytes).unwrap();
+        assert!(matches!(&bytes, Value::Bytes(b) if b == &[0, 1, 255]));
+        assert!(bytes.to_string() == "AAH/");
         let converted = Value::from(8);
         assert!(converted.to_string() == "8");
     }
@@ -459,6 +571,10 @@ mod test {
         Value::new("120-02-02", &DataType::Date).unwrap_err();
         Value::new("12000-02-02", &DataType::Date).unwrap_err();
         Value::new("a", &DataType::Float).unwrap_err();
+        Value::new("1.1e3", &DataType::Decimal).unwrap_err();
+        Value::new("not base64!", &DataType::Bytes).unwrap_err();
+        let too_large = general_purpose::STANDARD.encode(vec![0; MAX_BYTES + 1]);
+        Value::new(&too_large, &DataType::Bytes).unwrap_err();
     }
 
     #[test]
@@ -0,0 +1,588 @@
+//! ActivityPub federation, so Mastodon and other Fediverse servers can follow Drives and Agents.
+//! Enabled by setting `--activitypub-key`.
+//!
+//! - Drives and Agents become actors when their `activitypubHandle` is set. Drives are `Group` actors, Agents are `Person` actors.
+//! - `/.well-known/webfinger` resolves `acct:{handle}@{host}` to the actor.
+//! - Public Messages and Articles are published by the nearest parent with a handle, or else by the signer of the Commit.
+//!   Commits that create, update or destroy them are mapped to `Create`, `Update` and `Delete` activities,
+//!   which are stored in the outbox of the actor and delivered to its followers.
+//! - The inbox handles `Follow` and `Undo` activities. Other activities are ignored.
+//!   The inbox of a follower is fetched from its own server, so a forged `Follow` can't redirect deliveries.
+//!
+//! Outgoing requests use HTTP Signatures, signed with the RSA key of the server.
+//! All actors share this key.
+//! Signatures of incoming activities are not verified yet.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use atomic_lib::{commit::CommitResponse, hierarchy, storelike::Query, urls, Resource, Storelike};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+pub const MIME_ACTIVITY: &str = "application/activity+json";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+/// The amount of activities shown in the outbox
+const OUTBOX_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Follower {
+    /// The id of the remote actor
+    pub actor: String,
+    /// Where activities are delivered
+    pub inbox: String,
+}
+
+/// An activity in the outbox of an actor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OutboxEntry {
+    handle: String,
+    activity: Value,
+}
+
+pub struct Federation {
+    server_url: String,
+    key_pair: ring::signature::RsaKeyPair,
+    public_key_pem: String,
+    /// Contains `followers.json` and `outbox.jsonl`
+    dir: PathBuf,
+    /// Makes sure followers are not lost when two follow at the same time
+    lock: Mutex<()>,
+}
+
+impl std::fmt::Debug for Federation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Federation")
+            .field("server_url", &self.server_url)
+            .field("dir", &self.dir)
+            .finish()
+    }
+}
+
+/// Returns the federation, if a key has been configured.
+pub fn init_from_config(config: &Config) -> AtomicServerResult<Option<Arc<Federation>>> {
+    let key_path = match &config.opts.activitypub_key {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let pem = std::fs::read(key_path)
+        .map_err(|e| format!("Could not read ActivityPub key {:?}: {}", key_path, e))?;
+    let der = rustls_pemfile::pkcs8_private_keys(&mut pem.as_slice())
+        .map_err(|e| format!("Invalid ActivityPub key {:?}: {}", key_path, e))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("No PKCS#8 private key found in {:?}", key_path))?;
+    let key_pair = ring::signature::RsaKeyPair::from_pkcs8(&der)
+        .map_err(|e| format!("ActivityPub key {:?} is not an RSA key: {}", key_path, e))?;
+    std::fs::create_dir_all(&config.activitypub_path).map_err(|e| {
+        format!(
+            "Could not create ActivityPub folder {:?}: {}",
+            config.activitypub_path, e
+        )
+    })?;
+    let public_key_pem = public_key_pem(ring::signature::KeyPair::public_key(&key_pair).as_ref());
+    Ok(Some(Arc::new(Federation {
+        server_url: config.server_url.clone(),
+        key_pair,
+        public_key_pem,
+        dir: config.activitypub_path.clone(),
+        lock: Mutex::new(()),
+    })))
+}
+
+/// Encodes a DER length.
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+    let bytes: Vec<u8> = len
+        .to_be_bytes()
+        .into_iter()
+        .skip_while(|b| *b == 0)
+        .collect();
+    let mut out = vec![0x80 | bytes.len() as u8];
+    out.extend(bytes);
+    out
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    out.extend(der_length(content.len()));
+    out.extend(content);
+    out
+}
+
+/// Wraps a PKCS#1 `RSAPublicKey` in a `SubjectPublicKeyInfo`, which is what Fediverse servers expect.
+fn public_key_pem(pkcs1: &[u8]) -> String {
+    // OID 1.2.840.113549.1.1.1 (rsaEncryption), followed by NULL parameters
+    let algorithm = der(
+        0x30,
+        &[
+            0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01, 0x05, 0x00,
+        ],
+    );
+    let mut bit_string = vec![0x00];
+    bit_string.extend(pkcs1);
+    let spki = der(0x30, &[algorithm, der(0x03, &bit_string)].concat());
+    let encoded = base64::encode(spki);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(64)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    format!(
+        "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+        lines.join("\n")
+    )
+}
+
+/// The URL of the actor with this handle.
+pub fn actor_id(server_url: &str, handle: &str) -> String {
+    format!("{}/activitypub/actors/{}", server_url, handle)
+}
+
+/// Finds the Drive or Agent with this handle.
+pub fn find_actor(store: &impl Storelike, handle: &str) -> AtomicServerResult<Option<Resource>> {
+    let subjects = store
+        .query(&Query::new_prop_val(urls::ACTIVITYPUB_HANDLE, handle))?
+        .subjects;
+    for subject in subjects {
+        let resource = store.get_resource(&subject)?;
+        if is_a(&resource, urls::DRIVE) || is_a(&resource, urls::AGENT) {
+            return Ok(Some(resource));
+        }
+    }
+    Ok(None)
+}
+
+fn is_a(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == class))
+}
+
+fn handle_of(resource: &Resource) -> Option<String> {
+    resource
+        .get(urls::ACTIVITYPUB_HANDLE)
+        .ok()
+        .map(|v| v.to_string())
+}
+
+fn string_prop(resource: &Resource, property: &str) -> Option<String> {
+    resource.get(property).ok().map(|v| v.to_string())
+}
+
+/// Formats milliseconds since unix epoch as RFC 3339.
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::<chrono::Utc>::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Maps a Commit to a Message or Article to an activity.
+/// Returns the handle of the actor that publishes it, and the activity.
+/// Returns None for other classes, resources that are not public, or if no actor is found.
+pub fn activity_for_commit(
+    store: &impl Storelike,
+    server_url: &str,
+    response: &CommitResponse,
+) -> Option<(String, Value)> {
+    let resource = response
+        .resource_new
+        .as_ref()
+        .or(response.resource_old.as_ref())?;
+    let object_type = if is_a(resource, urls::MESSAGE) {
+        "Note"
+    } else if is_a(resource, urls::ARTICLE) {
+        "Article"
+    } else {
+        return None;
+    };
+    // Uses the parents in the store, since those still exist when the resource is destroyed
+    hierarchy::check_read(store, resource, urls::PUBLIC_AGENT).ok()?;
+
+    let commit = &response.commit_struct;
+    let handle = resource
+        .get_parent_tree(store)
+        .ok()?
+        .iter()
+        .rev()
+        .find_map(handle_of)
+        .or_else(|| {
+            store
+                .get_resource(&commit.signer)
+                .ok()
+                .and_then(|signer| handle_of(&signer))
+        })?;
+    let actor = actor_id(server_url, &handle);
+    let followers = format!("{}/followers", actor);
+    let published = format_time(commit.created_at);
+
+    let (activity_type, object) = match &response.resource_new {
+        None => (
+            "Delete",
+            json!({ "id": resource.get_subject(), "type": "Tombstone" }),
+        ),
+        Some(new) => {
+            let mut object = json!({
+                "id": new.get_subject(),
+                "type": object_type,
+                "attributedTo": actor,
+                "url": new.get_subject(),
+                "content": string_prop(new, urls::DESCRIPTION).unwrap_or_default(),
+                "to": [PUBLIC],
+                "cc": [followers],
+            });
+            if let Some(name) = string_prop(new, urls::NAME) {
+                object["name"] = json!(name);
+            }
+            // For new resources, the old resource is empty
+            let is_new = response
+                .resource_old
+                .as_ref()
+                .is_none_or(|old| old.get_propvals().is_empty());
+            if is_new {
+                object["published"] = json!(published);
+                ("Create", object)
+            } else {
+                object["updated"] = json!(published);
+                ("Update", object)
+            }
+        }
+    };
+    Some((
+        handle,
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": response.commit_resource.get_subject(),
+            "type": activity_type,
+            "actor": actor,
+            "published": published,
+            "to": [PUBLIC],
+            "cc": [followers],
+            "object": object,
+        }),
+    ))
+}
+
+impl Federation {
+    /// The actor document of a Drive or Agent.
+    pub fn actor_json(&self, resource: &Resource, handle: &str) -> Value {
+        let id = actor_id(&self.server_url, handle);
+        let actor_type = if is_a(resource, urls::AGENT) {
+            "Person"
+        } else {
+            "Group"
+        };
+        json!({
+            "@context": ["https://www.w3.org/ns/activitystreams", "https://w3id.org/security/v1"],
+            "id": id,
+            "type": actor_type,
+            "preferredUsername": handle,
+            "name": string_prop(resource, urls::NAME).unwrap_or_else(|| handle.to_string()),
+            "summary": string_prop(resource, urls::DESCRIPTION).unwrap_or_default(),
+            "url": resource.get_subject(),
+            "inbox": format!("{}/inbox", id),
+            "outbox": format!("{}/outbox", id),
+            "followers": format!("{}/followers", id),
+            "publicKey": {
+                "id": format!("{}#main-key", id),
+                "owner": id,
+                "publicKeyPem": self.public_key_pem,
+            },
+        })
+    }
+
+    fn followers_path(&self) -> PathBuf {
+        self.dir.join("followers.json")
+    }
+
+    fn read_followers(&self) -> AtomicServerResult<HashMap<String, Vec<Follower>>> {
+        let path = self.followers_path();
+        match std::fs::read_to_string(&path) {
+            Ok(json) => Ok(
+                serde_json::from_str(&json).map_err(|e| format!("Invalid {:?}: {}", path, e))?
+            ),
+            Err(_not_found) => Ok(HashMap::new()),
+        }
+    }
+
+    fn write_followers(
+        &self,
+        followers: &HashMap<String, Vec<Follower>>,
+    ) -> AtomicServerResult<()> {
+        let path = self.followers_path();
+        let json = serde_json::to_string_pretty(followers).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json).map_err(|e| format!("Could not write {:?}: {}", path, e))?;
+        Ok(())
+    }
+
+    pub fn followers(&self, handle: &str) -> AtomicServerResult<Vec<Follower>> {
+        let _guard = self.lock.lock()?;
+        Ok(self.read_followers()?.remove(handle).unwrap_or_default())
+    }
+
+    pub fn add_follower(&self, handle: &str, follower: Follower) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let mut all = self.read_followers()?;
+        let followers = all.entry(handle.to_string()).or_default();
+        followers.retain(|f| f.actor != follower.actor);
+        followers.push(follower);
+        self.write_followers(&all)
+    }
+
+    pub fn remove_follower(&self, handle: &str, actor: &str) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let mut all = self.read_followers()?;
+        if let Some(followers) = all.get_mut(handle) {
+            followers.retain(|f| f.actor != actor);
+        }
+        self.write_followers(&all)
+    }
+
+    fn outbox_path(&self) -> PathBuf {
+        self.dir.join("outbox.jsonl")
+    }
+
+    /// Returns the most recent activities of the actor, newest first.
+    pub fn outbox(&self, handle: &str) -> AtomicServerResult<Vec<Value>> {
+        let _guard = self.lock.lock()?;
+        let contents = std::fs::read_to_string(self.outbox_path()).unwrap_or_default();
+        Ok(contents
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<OutboxEntry>(line).ok())
+            .filter(|entry| entry.handle == handle)
+            .take(OUTBOX_LIMIT)
+            .map(|entry| entry.activity)
+            .collect())
+    }
+
+    fn append_outbox(&self, handle: &str, activity: &Value) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let line = serde_json::to_string(&OutboxEntry {
+            handle: handle.to_string(),
+            activity: activity.clone(),
+        })
+        .map_err(|e| e.to_string())?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.outbox_path())?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Publishes the activity for the Commit, if there is one.
+    /// Deliveries happen in the background, so the Commit Monitor is not blocked by slow servers.
+    pub fn handle_commit(
+        self: &Arc<Self>,
+        store: &impl Storelike,
+        response: &CommitResponse,
+    ) -> AtomicServerResult<()> {
+        let (handle, activity) = match activity_for_commit(store, &self.server_url, response) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        self.append_outbox(&handle, &activity)?;
+        let mut inboxes: Vec<String> = self
+            .followers(&handle)?
+            .into_iter()
+            .map(|f| f.inbox)
+            .collect();
+        inboxes.sort();
+        inboxes.dedup();
+        if inboxes.is_empty() {
+            return Ok(());
+        }
+        let federation = self.clone();
+        std::thread::spawn(move || {
+            for inbox in inboxes {
+                if let Err(e) = federation.deliver(&handle, &inbox, &activity) {
+                    tracing::warn!("Could not deliver activity to {}: {}", inbox, e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Fetches the inbox of a remote actor. Blocking.
+    pub fn fetch_inbox(&self, actor: &str) -> AtomicServerResult<String> {
+        let body = ureq::get(actor)
+            .set("Accept", MIME_ACTIVITY)
+            .call()
+            .map_err(|e| format!("Could not fetch actor {}: {}", actor, e))?
+            .into_string()?;
+        let remote: Value =
+            serde_json::from_str(&body).map_err(|e| format!("Invalid actor {}: {}", actor, e))?;
+        if remote["id"].as_str() != Some(actor) {
+            return Err(format!("Actor {} has a different id", actor).into());
+        }
+        remote["endpoints"]["sharedInbox"]
+            .as_str()
+            .or_else(|| remote["inbox"].as_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("Actor {} has no inbox", actor).into())
+    }
+
+    /// Posts the activity to a remote inbox, signed using HTTP Signatures. Blocking.
+    pub fn deliver(&self, handle: &str, inbox: &str, activity: &Value) -> AtomicServerResult<()> {
+        let uri: actix_web::http::Uri = inbox
+            .parse()
+            .map_err(|e| format!("Invalid inbox {}: {}", inbox, e))?;
+        let host = uri.authority().ok_or("Inbox has no host")?.to_string();
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let body = activity.to_string();
+        let date = chrono::Utc::now()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let digest = format!(
+            "SHA-256={}",
+            base64::encode(Sha256::digest(body.as_bytes()))
+        );
+        let signing_string = format!(
+            "(request-target): post {}\nhost: {}\ndate: {}\ndigest: {}",
+            path, host, date, digest
+        );
+        let mut signature = vec![0; self.key_pair.public_modulus_len()];
+        self.key_pair
+            .sign(
+                &ring::signature::RSA_PKCS1_SHA256,
+                &ring::rand::SystemRandom::new(),
+                signing_string.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| "Could not sign ActivityPub request")?;
+        let header = format!(
+            "keyId=\"{}#main-key\",algorithm=\"rsa-sha256\",headers=\"(request-target) host date digest\",signature=\"{}\"",
+            actor_id(&self.server_url, handle),
+            base64::encode(signature)
+        );
+        ureq::post(inbox)
+            .set("Content-Type", MIME_ACTIVITY)
+            .set("Host", &host)
+            .set("Date", &date)
+            .set("Digest", &digest)
+            .set("Signature", &header)
+            .send_string(&body)
+            .map_err(|e| format!("Delivery failed: {}", e))?;
+        Ok(())
+    }
+
+    /// Handles an activity posted to the inbox of the actor. Blocking.
+    pub fn receive(&self, handle: &str, activity: &Value) -> AtomicServerResult<()> {
+        let remote_actor = activity["actor"]
+            .as_str()
+            .ok_or("Activity has no actor")?
+            .to_string();
+        let own_actor = actor_id(&self.server_url, handle);
+        match activity["type"].as_str() {
+            Some("Follow") => {
+                if activity["object"].as_str() != Some(own_actor.as_str()) {
+                    return Err("Follow is not for this actor".into());
+                }
+                let inbox = self.fetch_inbox(&remote_actor)?;
+                self.add_follower(
+                    handle,
+                    Follower {
+                        actor: remote_actor,
+                        inbox: inbox.clone(),
+                    },
+                )?;
+                let accept = json!({
+                    "@context": "https://www.w3.org/ns/activitystreams",
+                    "id": format!("{}#accepts/{}", own_actor, atomic_lib::utils::random_string(10)),
+                    "type": "Accept",
+                    "actor": own_actor,
+                    "object": activity,
+                });
+                self.deliver(handle, &inbox, &accept)
+            }
+            Some("Undo") if activity["object"]["type"].as_str() == Some("Follow") => {
+                self.remove_follower(handle, &remote_actor)
+            }
+            other => {
+                tracing::debug!("Ignoring {:?} activity from {}", other, remote_actor);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atomic_lib::Value as AtomicValue;
+
+    #[test]
+    fn maps_commits_to_activities() {
+        let store = atomic_lib::Db::init_temp("activitypub").unwrap();
+        store.populate().unwrap();
+        let server_url = store.get_server_url().to_string();
+
+        let mut drive = store.get_resource(&server_url).unwrap();
+        drive
+            .set_propval_string(urls::ACTIVITYPUB_HANDLE.into(), "news", &store)
+            .unwrap();
+        drive.save_locally(&store).unwrap();
+        assert_eq!(
+            find_actor(&store, "news").unwrap().unwrap().get_subject(),
+            &server_url
+        );
+
+        let mut message = Resource::new_instance(urls::MESSAGE, &store).unwrap();
+        message
+            .set_propval(
+                urls::DESCRIPTION.into(),
+                AtomicValue::Markdown("Hello fediverse".into()),
+                &store,
+            )
+            .unwrap();
+        message
+            .set_propval(
+                urls::PARENT.into(),
+                AtomicValue::AtomicUrl(server_url.clone()),
+                &store,
+            )
+            .unwrap();
+        let response = message.save_locally(&store).unwrap();
+
+        let (handle, activity) = activity_for_commit(&store, &server_url, &response).unwrap();
+        assert_eq!(handle, "news");
+        assert_eq!(activity["type"], "Create");
+        assert_eq!(activity["actor"], actor_id(&server_url, "news"));
+        assert_eq!(activity["object"]["type"], "Note");
+        assert_eq!(activity["object"]["content"], "Hello fediverse");
+
+        // Other classes are not federated
+        let mut other = Resource::new_generate_subject(&store);
+        other
+            .set_propval_string(urls::NAME.into(), "internal", &store)
+            .unwrap();
+        let response = other.save_locally(&store).unwrap();
+        assert!(activity_for_commit(&store, &server_url, &response).is_none());
+    }
+
+    #[test]
+    fn wraps_public_key() {
+        let pem = public_key_pem(&[0x30, 0x03, 0x02, 0x01, 0x01]);
+        let der = base64::decode(
+            pem.trim()
+                .trim_start_matches("-----BEGIN PUBLIC KEY-----")
+                .trim_end_matches("-----END PUBLIC KEY-----")
+                .replace('\n', ""),
+        )
+        .unwrap();
+        assert_eq!(der[0], 0x30);
+        assert_eq!(der[1] as usize, der.len() - 2);
+        assert!(der.ends_with(&[0x00, 0x30, 0x03, 0x02, 0x01, 0x01]));
+    }
+}
@@ -3,11 +3,6 @@
 
 use actix::{prelude::Message, Addr};
 
-//WebSocketConnection responds to this to pipe it through to the actual client
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct WsMessage(pub String);
-
 /// Subscribes a WebSocketConnection to a Subject.
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -17,6 +12,29 @@ pub struct Subscribe {
     pub agent: String,
 }
 
+/// Stops sending Commits for a Subject to a WebSocketConnection.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unsubscribe {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+    pub subject: String,
+}
+
+/// Removes all subscriptions of a WebSocketConnection, when it is closed.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+}
+
+/// Tells a WebSocketConnection that Commits were dropped, because its queue was full.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Lagged {
+    /// Amount of Commits that were not sent
+    pub dropped: usize,
+}
+
 /// A message containing a Resource, which should be sent to subscribers
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
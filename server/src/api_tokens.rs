@@ -0,0 +1,252 @@
+//! Long-lived bearer tokens, so scripts and CI jobs don't have to sign every request.
+//! Tokens are minted by a signed in Agent at `/tokens`, and are sent as `Authorization: Bearer {token}`.
+//! Only a hash of the token is stored, in `api_tokens.json` in the config folder.
+//! Tokens can be limited to reading, to a subtree of resources, and can expire.
+//! Commits still need to be signed by the Agent, so tokens can't be used to edit resources.
+
+use std::{path::PathBuf, sync::Mutex};
+
+use atomic_lib::{AtomicError, Storelike};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, errors::AtomicServerResult, helpers::in_subtree};
+
+/// Tokens start with this, which makes them easy to find when they are leaked.
+const TOKEN_PREFIX: &str = "atm_";
+
+/// What a token can be used for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenScope {
+    /// Only allows requests that don't change anything, such as GET requests
+    #[serde(default)]
+    pub read_only: bool,
+    /// Only allows requests for this resource and its descendants
+    pub parent: Option<String>,
+    /// Milliseconds since unix epoch
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub id: String,
+    /// The Agent that the requests are performed as
+    pub agent: String,
+    pub name: Option<String>,
+    pub created_at: i64,
+    #[serde(flatten)]
+    pub scope: TokenScope,
+}
+
+/// A token as it is stored on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredToken {
+    #[serde(flatten)]
+    token: ApiToken,
+    /// SHA-256 of the secret part of the token, hex encoded
+    secret_hash: String,
+}
+
+impl ApiToken {
+    /// Returns an error if the token can't be used for this request.
+    pub fn check_scope(
+        &self,
+        store: &impl Storelike,
+        requested_subject: &str,
+        write: bool,
+    ) -> AtomicServerResult<()> {
+        if self
+            .scope
+            .expires_at
+            .is_some_and(|expires_at| expires_at < atomic_lib::utils::now())
+        {
+            return Err(
+                AtomicError::unauthorized(format!("API token {} has expired", self.id)).into(),
+            );
+        }
+        if write && self.scope.read_only {
+            return Err(AtomicError::unauthorized(format!(
+                "API token {} can only be used for reading",
+                self.id
+            ))
+            .into());
+        }
+        if let Some(parent) = &self.scope.parent {
+            if !in_subtree(store, parent, requested_subject) {
+                return Err(AtomicError::unauthorized(format!(
+                    "API token {} can only be used for resources in {}",
+                    self.id, parent
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct ApiTokens {
+    path: PathBuf,
+    /// Makes sure tokens are not lost when two are minted at the same time
+    lock: Mutex<()>,
+}
+
+impl ApiTokens {
+    pub fn init_from_config(config: &Config) -> ApiTokens {
+        let mut path = config.config_dir.clone();
+        path.push("api_tokens.json");
+        ApiTokens {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self) -> AtomicServerResult<Vec<StoredToken>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => Ok(serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid {:?}: {}", self.path, e))?),
+            Err(_not_found) => Ok(Vec::new()),
+        }
+    }
+
+    fn write(&self, tokens: &[StoredToken]) -> AtomicServerResult<()> {
+        let json = serde_json::to_string_pretty(tokens).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, json)
+            .map_err(|e| format!("Could not write {:?}: {}", self.path, e))?;
+        Ok(())
+    }
+
+    /// Creates a token, and returns its value. The value is not stored, so it can only be shown once.
+    pub fn mint(
+        &self,
+        agent: &str,
+        name: Option<String>,
+        scope: TokenScope,
+    ) -> AtomicServerResult<(String, ApiToken)> {
+        let _guard = self.lock.lock()?;
+        let id = atomic_lib::utils::random_string(10);
+        let secret = atomic_lib::utils::random_string(40);
+        let token = ApiToken {
+            id: id.clone(),
+            agent: agent.to_string(),
+            name,
+            created_at: atomic_lib::utils::now(),
+            scope,
+        };
+        let mut tokens = self.read()?;
+        tokens.push(StoredToken {
+            token: token.clone(),
+            secret_hash: hash_secret(&secret),
+        });
+        self.write(&tokens)?;
+        Ok((format!("{}{}_{}", TOKEN_PREFIX, id, secret), token))
+    }
+
+    pub fn list(&self, agent: &str) -> AtomicServerResult<Vec<ApiToken>> {
+        let _guard = self.lock.lock()?;
+        Ok(self
+            .read()?
+            .into_iter()
+            .map(|stored| stored.token)
+            .filter(|token| token.agent == agent)
+            .collect())
+    }
+
+    /// Removes the token. Agents can only revoke their own tokens.
+    pub fn revoke(&self, agent: &str, id: &str) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let mut tokens = self.read()?;
+        let count = tokens.len();
+        tokens.retain(|stored| !(stored.token.id == id && stored.token.agent == agent));
+        if tokens.len() == count {
+            return Err(AtomicError::not_found(format!("API token {} not found", id)).into());
+        }
+        self.write(&tokens)
+    }
+
+    /// Finds the token. Does not check its scope, see [ApiToken::check_scope].
+    pub fn validate(&self, value: &str) -> AtomicServerResult<ApiToken> {
+        let invalid = || AtomicError::unauthorized("Invalid API token".into());
+        let (id, secret) = value
+            .strip_prefix(TOKEN_PREFIX)
+            .and_then(|rest| rest.split_once('_'))
+            .ok_or_else(invalid)?;
+        let _guard = self.lock.lock()?;
+        let hash = hash_secret(secret);
+        Ok(self
+            .read()?
+            .into_iter()
+            .find(|stored| stored.token.id == id && stored.secret_hash == hash)
+            .map(|stored| stored.token)
+            .ok_or_else(invalid)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mints_validates_and_revokes() {
+        let store = atomic_lib::Db::init_temp("api_tokens").unwrap();
+        let dir = PathBuf::from("./.temp/api_tokens");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let tokens = ApiTokens {
+            path: dir.join("api_tokens.json"),
+            lock: Mutex::new(()),
+        };
+        let agent = "https://localhost/agents/ci";
+        let scope = TokenScope {
+            read_only: true,
+            parent: Some("https://localhost/docs".into()),
+            expires_at: None,
+        };
+        let (value, minted) = tokens.mint(agent, Some("ci".into()), scope).unwrap();
+
+        let found = tokens.validate(&value).unwrap();
+        assert_eq!(found, minted);
+        for (subject, parent) in [
+            ("https://localhost/docs", "https://localhost"),
+            ("https://localhost/docs/page", "https://localhost/docs"),
+        ] {
+            let mut resource = atomic_lib::Resource::new(subject.into());
+            resource.set_propval_unsafe(
+                atomic_lib::urls::PARENT.into(),
+                atomic_lib::Value::AtomicUrl(parent.into()),
+            );
+            store.add_resource(&resource).unwrap();
+        }
+        found
+            .check_scope(&store, "https://localhost/docs/page", false)
+            .unwrap();
+        found
+            .check_scope(&store, "https://localhost/docs/page", true)
+            .unwrap_err();
+        found
+            .check_scope(&store, "https://localhost/other", false)
+            .unwrap_err();
+        // A URL that starts with the parent is not enough, the resource has to be a descendant
+        found
+            .check_scope(&store, "https://localhost/docs/elsewhere", false)
+            .unwrap_err();
+        tokens.validate(&format!("{}x", value)).unwrap_err();
+
+        assert_eq!(tokens.list(agent).unwrap().len(), 1);
+        tokens
+            .revoke("https://localhost/agents/other", &minted.id)
+            .unwrap_err();
+        tokens.revoke(agent, &minted.id).unwrap();
+        tokens.validate(&value).unwrap_err();
+    }
+}
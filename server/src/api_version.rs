@@ -0,0 +1,133 @@
+//! Versioning of the HTTP API.
+//! Clients can pick a version of the wire protocol by sending the `X-Atomic-Api-Version` header.
+//! Clients that don't send the header get version 1, so existing clients keep working.
+//! Responses that rely on retiring behavior contain `Deprecation` and `Sunset` headers (RFC 8594).
+//!
+//! Version 2:
+//! - Commits to existing resources must refer to the latest Commit in `previousCommit`.
+//!   https://github.com/atomicdata-dev/atomic-data-rust/issues/412
+
+use std::future::{ready, Future, Ready};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    FromRequest, HttpRequest, HttpResponseBuilder,
+};
+
+use crate::errors::{AtomicServerError, AtomicServerResult};
+
+pub const API_VERSION_HEADER: &str = "x-atomic-api-version";
+/// The latest version of the API.
+pub const CURRENT: u16 = 2;
+/// Versions older than this are no longer supported.
+pub const OLDEST_SUPPORTED: u16 = 1;
+/// After this date, version 1 behavior may be removed.
+pub const V1_SUNSET: &str = "Thu, 01 Jul 2027 00:00:00 GMT";
+
+/// The version of the API that is used for handling a request.
+/// Can be used as an extractor in handlers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApiVersion(pub u16);
+
+impl ApiVersion {
+    /// Reads the `X-Atomic-Api-Version` header. Defaults to version 1 if it is missing.
+    pub fn from_headers(headers: &HeaderMap) -> AtomicServerResult<ApiVersion> {
+        let header = match headers.get(API_VERSION_HEADER) {
+            Some(header) => header,
+            None => return Ok(ApiVersion(OLDEST_SUPPORTED)),
+        };
+        let version: u16 = header
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or("X-Atomic-Api-Version must be a number")?;
+        if !(OLDEST_SUPPORTED..=CURRENT).contains(&version) {
+            return Err(format!(
+                "Unsupported X-Atomic-Api-Version {}. This server supports versions {} to {}.",
+                version, OLDEST_SUPPORTED, CURRENT
+            )
+            .into());
+        }
+        Ok(ApiVersion(version))
+    }
+
+    /// Whether the `previousCommit` of incoming Commits is checked.
+    pub fn validates_previous_commit(&self) -> bool {
+        self.0 >= 2
+    }
+
+    /// Whether this version is scheduled for removal.
+    pub fn is_deprecated(&self) -> bool {
+        self.0 < CURRENT
+    }
+}
+
+impl FromRequest for ApiVersion {
+    type Error = AtomicServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(ApiVersion::from_headers(req.headers()))
+    }
+}
+
+/// Adds the `Deprecation` and `Sunset` headers, if the version of the request is deprecated.
+/// Call this in handlers whose behavior differs between versions.
+pub fn add_deprecation_headers(builder: &mut HttpResponseBuilder, version: ApiVersion) {
+    if version.is_deprecated() {
+        builder.insert_header(("Deprecation", "true"));
+        builder.insert_header(("Sunset", V1_SUNSET));
+    }
+}
+
+/// Middleware that rejects unsupported versions, and tells the client which version was used.
+pub fn negotiate<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let version = ApiVersion::from_headers(req.headers());
+    let response = srv.call(req);
+    async move {
+        let version = version?;
+        let mut res = response.await?;
+        res.headers_mut().insert(
+            HeaderName::from_static(API_VERSION_HEADER),
+            HeaderValue::from(version.0),
+        );
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(version: &str) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.insert(
+            HeaderName::from_static(API_VERSION_HEADER),
+            HeaderValue::from_str(version).unwrap(),
+        );
+        map
+    }
+
+    #[test]
+    fn parses_api_version() {
+        let legacy = ApiVersion::from_headers(&HeaderMap::new()).unwrap();
+        assert_eq!(legacy, ApiVersion(1));
+        assert!(legacy.is_deprecated());
+        assert!(!legacy.validates_previous_commit());
+
+        let current = ApiVersion::from_headers(&headers("2")).unwrap();
+        assert!(!current.is_deprecated());
+        assert!(current.validates_previous_commit());
+
+        ApiVersion::from_headers(&headers("0")).unwrap_err();
+        ApiVersion::from_headers(&headers("999")).unwrap_err();
+        ApiVersion::from_headers(&headers("latest")).unwrap_err();
+    }
+}
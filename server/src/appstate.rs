@@ -1,10 +1,28 @@
 //! App state, which is accessible from handlers
 use crate::{
-    commit_monitor::CommitMonitor, config::Config, errors::AtomicServerResult, search::SearchState,
+    activitypub::Federation,
+    api_tokens::ApiTokens,
+    assets::AssetProvider,
+    audit_log::AuditLog,
+    commit_monitor::{CommitMonitor, WebSocketMetrics},
+    config::Config,
+    errors::AtomicServerResult,
+    files::FileStore,
+    ip_access::IpAccess,
+    jobs::JobQueue,
+    lockout::AuthLockout,
+    oidc::OidcClient,
+    passkeys::Passkeys,
+    rate_limit::RateLimiter,
+    scanner::Scanner,
+    search::SearchState,
+    sessions::AgentKeys,
+    totp::Totp,
 };
 use atomic_lib::{
     agents::{generate_public_key, Agent},
     commit::CommitResponse,
+    plugins::quotas::Quota,
     Storelike,
 };
 
@@ -22,7 +40,37 @@ pub struct AppState {
     pub config: Config,
     /// The Actix Address of the CommitMonitor, which should receive updates when a commit is applied
     pub commit_monitor: actix::Addr<CommitMonitor>,
+    /// Counters for the WebSocket connections that the CommitMonitor sends Commits to
+    pub ws_metrics: std::sync::Arc<WebSocketMetrics>,
     pub search_state: SearchState,
+    /// Where uploaded files are persisted
+    pub file_store: FileStore,
+    /// The front-end that is served to browsers
+    pub assets: std::sync::Arc<dyn AssetProvider>,
+    /// Scans uploaded files for malware, if configured
+    pub scanner: Option<std::sync::Arc<dyn Scanner>>,
+    /// Limits the amount of requests per IP address and Agent
+    pub rate_limiter: std::sync::Arc<RateLimiter>,
+    /// Locks out IP addresses and Agents after too many failed authentications
+    pub auth_lockout: std::sync::Arc<AuthLockout>,
+    /// Rejects clients from networks that are not allowed
+    pub ip_access: std::sync::Arc<IpAccess>,
+    /// Records mutations and authentication failures
+    pub audit_log: std::sync::Arc<AuditLog>,
+    /// Private keys of the Agents that the server signs sessions for
+    pub agent_keys: std::sync::Arc<AgentKeys>,
+    /// Bearer tokens that scripts can use instead of signing requests
+    pub api_tokens: std::sync::Arc<ApiTokens>,
+    /// Logs in users using an external OpenID Connect provider, if configured
+    pub oidc: Option<std::sync::Arc<OidcClient>>,
+    /// Registers and verifies passkeys
+    pub passkeys: std::sync::Arc<Passkeys>,
+    /// Second factor for sessions and API tokens
+    pub totp: std::sync::Arc<Totp>,
+    /// Publishes Messages and Articles to the Fediverse, if configured
+    pub federation: Option<std::sync::Arc<Federation>>,
+    /// Runs long operations in the background
+    pub jobs: std::sync::Arc<JobQueue>,
 }
 
 /// Creates the AppState (the server's context available in Handlers).
@@ -41,7 +89,11 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
     }
 
     tracing::info!("Opening database at {:?}", &config.store_path);
-    let mut store = atomic_lib::Db::init(&config.store_path, config.server_url.clone())?;
+    let mut store = atomic_lib::Db::init_with_cache_capacity(
+        &config.store_path,
+        config.server_url.clone(),
+        config.memory.db_cache,
+    )?;
     if config.initialize {
         tracing::info!("Initialize: creating and populating new Database");
         atomic_lib::populate::populate_default_store(&store)
@@ -59,13 +111,27 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
     tracing::info!("Starting search service");
     let search_state =
         SearchState::new(&config).map_err(|e| format!("Failed to start search service: {}", e))?;
+    // An empty index (for example because the search schema has changed) is filled right away.
+    // With `--rebuild-index` this happens later on, in `serve`.
+    if search_state.reader.searcher().num_docs() == 0 && !config.opts.rebuild_indexes {
+        crate::search::add_all_resources(&search_state, &store)?;
+    }
+
+    let federation = crate::activitypub::init_from_config(&config)?;
 
     // Initialize commit monitor, which watches commits and sends these to the commit_monitor actor
     tracing::info!("Starting commit monitor");
-    let commit_monitor =
-        crate::commit_monitor::create_commit_monitor(store.clone(), search_state.clone());
+    let ws_metrics = std::sync::Arc::new(WebSocketMetrics::default());
+    let commit_monitor = crate::commit_monitor::create_commit_monitor(
+        store.clone(),
+        search_state.clone(),
+        federation.clone(),
+        std::time::Duration::from_millis(config.opts.search_commit_interval.max(1)),
+        ws_metrics.clone(),
+    );
 
     let commit_monitor_clone = commit_monitor.clone();
+    crate::memory::spawn_reporter(store.clone(), config.memory.clone());
 
     // This closure is called every time a Commit is created
     let send_commit = move |commit_response: &CommitResponse| {
@@ -74,6 +140,15 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         });
     };
     store.set_handle_commit(Box::new(send_commit));
+    store.set_resource_cache_size(config.opts.resource_cache_size);
+    store.set_default_quota(Quota {
+        max_resources: config.opts.quota_max_resources,
+        max_file_size: config
+            .opts
+            .quota_max_file_size
+            .map(|megabytes| megabytes.saturating_mul(1024 * 1024)),
+        max_commits_per_day: config.opts.quota_max_commits_per_day,
+    });
 
     // If the user changes their server_url, the drive will not exist.
     // In this situation, we should re-build a new drive from scratch.
@@ -89,11 +164,46 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         tracing::info!("Setting rights to Drive {}", store.get_server_url());
     }
 
+    if config.opts.public_mode {
+        tracing::warn!("`--public-mode` is deprecated. Set `publicRead` on your Drives instead. Authentication is no longer skipped.");
+        migrate_public_mode(&store)
+            .map_err(|e| format!("Failed to make Drives publicly readable: {}", e))?;
+    }
+
+    let file_store = FileStore::init_from_config(&config);
+    let assets = crate::assets::init_from_config(&config)?;
+    let scanner = crate::scanner::init_from_config(&config);
+    let rate_limiter = std::sync::Arc::new(RateLimiter::init_from_config(&config));
+    let auth_lockout = std::sync::Arc::new(AuthLockout::init_from_config(&config));
+    let ip_access = std::sync::Arc::new(IpAccess::init_from_config(&config)?);
+    let audit_log = std::sync::Arc::new(AuditLog::init_from_config(&config)?);
+    let agent_keys = std::sync::Arc::new(AgentKeys::init_from_config(&config));
+    let api_tokens = std::sync::Arc::new(ApiTokens::init_from_config(&config));
+    let oidc = crate::oidc::init_from_config(&config)?;
+    let passkeys = std::sync::Arc::new(Passkeys::init_from_config(&config));
+    let totp = std::sync::Arc::new(Totp::init_from_config(&config));
+    let jobs = std::sync::Arc::new(JobQueue::init_from_config(&config, &store));
+
     Ok(AppState {
         store,
         config,
         commit_monitor,
+        ws_metrics,
         search_state,
+        file_store,
+        assets,
+        scanner,
+        rate_limiter,
+        auth_lockout,
+        ip_access,
+        audit_log,
+        agent_keys,
+        api_tokens,
+        oidc,
+        passkeys,
+        totp,
+        federation,
+        jobs,
     })
 }
 
@@ -155,6 +265,31 @@ fn set_default_agent(config: &Config, store: &impl Storelike) -> AtomicServerRes
     Ok(())
 }
 
+/// Replaces the old global `--public-mode` with per-Drive rights, by setting `publicRead` on every Drive that has no value for it yet.
+/// Drives that have been made private explicitly are left alone.
+fn migrate_public_mode(store: &atomic_lib::Db) -> AtomicServerResult<()> {
+    let mut query = atomic_lib::storelike::Query::new_class(atomic_lib::urls::DRIVE);
+    query.include_external = true;
+    let mut drives = store.query(&query)?.subjects;
+    if !drives.contains(&store.get_server_url().to_string()) {
+        drives.push(store.get_server_url().into());
+    }
+    for subject in drives {
+        let mut drive = store.get_resource(&subject)?;
+        if drive.get(atomic_lib::urls::PUBLIC_READ).is_ok() {
+            continue;
+        }
+        tracing::info!("Making Drive {} publicly readable", subject);
+        drive.set_propval(
+            atomic_lib::urls::PUBLIC_READ.into(),
+            atomic_lib::Value::Boolean(true),
+            store,
+        )?;
+        drive.save_locally(store)?;
+    }
+    Ok(())
+}
+
 /// Creates the first Invitation that is opened by the user on the Home page.
 fn set_up_initial_invite(store: &impl Storelike) -> AtomicServerResult<()> {
     let subject = format!("{}/setup", store.get_server_url());
@@ -0,0 +1,136 @@
+//! The front-end that is served to browsers.
+//! By default, this is the bundled atomic-data-browser from the `app_assets` folder.
+//! Deployments can serve their own UI bundle from a folder using `--assets-path`, or serve no UI at all using `--api-only`.
+
+use std::path::{Path, PathBuf};
+
+use actix_web::{guard, web::ServiceConfig};
+use actix_web_static_files::ResourceFiles;
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+// Includes the `app_assets` folder files, used for hosting
+// front-end JS bundles, service workers, css, icons and other static files
+include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+
+/// Provides the static files and the HTML template of the single page app.
+pub trait AssetProvider: std::fmt::Debug + Send + Sync {
+    /// The HTML template that is returned for every HTML request that is not a static file.
+    /// May contain `<!-- { inject_html_head } -->` and `<!-- { inject_script } -->` placeholders.
+    /// If `None`, HTML requests are handled like any other request for a resource.
+    fn index_html(&self) -> Option<&str>;
+    /// Registers the service that serves the static files (JS bundles, icons, etc.)
+    /// Requests for files that don't exist should fall through to the next services.
+    fn register(&self, app: &mut ServiceConfig);
+}
+
+/// Chooses the front-end from the config.
+pub fn init_from_config(config: &Config) -> AtomicServerResult<std::sync::Arc<dyn AssetProvider>> {
+    if config.opts.api_only {
+        return Ok(std::sync::Arc::new(NoAssets));
+    }
+    if let Some(path) = &config.opts.assets_path {
+        return Ok(std::sync::Arc::new(FolderAssets::new(
+            path,
+            &config.path_prefix,
+        )?));
+    }
+    Ok(std::sync::Arc::new(EmbeddedAssets))
+}
+
+/// The atomic-data-browser bundle, which is included in the binary at compile time.
+#[derive(Debug)]
+pub struct EmbeddedAssets;
+
+impl AssetProvider for EmbeddedAssets {
+    fn index_html(&self) -> Option<&str> {
+        Some(include_str!("../app_assets/index.html"))
+    }
+
+    fn register(&self, app: &mut ServiceConfig) {
+        app.service(
+            ResourceFiles::new("/", generate())
+                .skip_handler_when_not_found()
+                .do_not_resolve_defaults(),
+        );
+    }
+}
+
+/// A front-end bundle that is read from a folder on disk.
+#[derive(Debug)]
+pub struct FolderAssets {
+    path: PathBuf,
+    index_html: String,
+    /// Removed from request paths before looking up files, see [Config::path_prefix]
+    path_prefix: String,
+}
+
+impl FolderAssets {
+    /// Reads the `index.html` from the folder. Fails if the folder has no `index.html`.
+    pub fn new(path: &Path, path_prefix: &str) -> AtomicServerResult<FolderAssets> {
+        let index_path = path.join("index.html");
+        let index_html = std::fs::read_to_string(&index_path).map_err(|e| {
+            format!(
+                "Could not read front-end template {}: {}",
+                index_path.display(),
+                e
+            )
+        })?;
+        Ok(FolderAssets {
+            path: path.to_path_buf(),
+            index_html,
+            path_prefix: path_prefix.to_string(),
+        })
+    }
+}
+
+impl AssetProvider for FolderAssets {
+    fn index_html(&self) -> Option<&str> {
+        Some(&self.index_html)
+    }
+
+    fn register(&self, app: &mut ServiceConfig) {
+        let path = self.path.clone();
+        let prefix = self.path_prefix.clone();
+        app.service(
+            actix_files::Files::new("/", &self.path).guard(guard::fn_guard(move |ctx| {
+                let request_path = ctx.head().uri.path();
+                let file = request_path
+                    .strip_prefix(&prefix)
+                    .unwrap_or(request_path)
+                    .trim_start_matches('/');
+                !file.is_empty() && path.join(file).is_file()
+            })),
+        );
+    }
+}
+
+/// API-only mode: no front-end is served.
+#[derive(Debug)]
+pub struct NoAssets;
+
+impl AssetProvider for NoAssets {
+    fn index_html(&self) -> Option<&str> {
+        None
+    }
+
+    fn register(&self, _app: &mut ServiceConfig) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folder_assets_require_index() {
+        let dir = PathBuf::from("./.temp/folder_assets");
+        std::fs::create_dir_all(&dir).unwrap();
+        let _ = std::fs::remove_file(dir.join("index.html"));
+        FolderAssets::new(&dir, "").unwrap_err();
+
+        std::fs::write(dir.join("index.html"), "<html>custom ui</html>").unwrap();
+        let assets = FolderAssets::new(&dir, "").unwrap();
+        assert_eq!(assets.index_html(), Some("<html>custom ui</html>"));
+        assert!(NoAssets.index_html().is_none());
+    }
+}
@@ -0,0 +1,299 @@
+//! Append-only log of mutations and failed authentication attempts, for moderation and security reviews.
+//! Entries are stored as JSON lines in `{data_dir}/audit/audit.jsonl`.
+//! When this file grows larger than `--audit-log-max-size`, it is rotated to `audit.jsonl.1`, `audit.jsonl.2`, etc.
+//! Admins (Agents with write rights to the Drive) can read the log at `/auditlog`.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    web, HttpRequest,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{appstate::AppState, config::Config, errors::AtomicServerResult};
+
+const FILE_NAME: &str = "audit.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A Commit has been posted, or a file has been uploaded
+    Commit,
+    /// A request has been rejected because of missing or invalid authentication
+    AuthFailure,
+    /// An IP address or Agent has been locked out after too many failed authentications, see [crate::lockout]
+    AuthLockout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Milliseconds since unix epoch
+    pub timestamp: i64,
+    pub event: AuditEvent,
+    /// The signer of the Commit, or the Agent that tried to authenticate
+    pub agent: Option<String>,
+    /// The resource that was changed or requested
+    pub subject: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// `applied`, or the error message
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    /// Creates an entry, using the IP address and user agent of the request.
+    pub fn from_request(
+        req: &HttpRequest,
+        event: AuditEvent,
+        agent: Option<String>,
+        subject: Option<String>,
+        outcome: String,
+    ) -> AuditEntry {
+        AuditEntry {
+            timestamp: atomic_lib::utils::now(),
+            event,
+            agent,
+            subject,
+            ip: req
+                .app_data::<actix_web::web::Data<crate::appstate::AppState>>()
+                .and_then(|appstate| {
+                    crate::helpers::client_ip(
+                        &req.connection_info(),
+                        req.peer_addr(),
+                        &appstate.config,
+                    )
+                }),
+            user_agent: req
+                .headers()
+                .get("user-agent")
+                .and_then(|v| v.to_str().ok())
+                .map(String::from),
+            outcome,
+        }
+    }
+}
+
+/// Selects entries when reading the audit log.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    pub event: Option<AuditEvent>,
+    pub agent: Option<String>,
+    /// Only entries for subjects that start with this
+    pub subject: Option<String>,
+    /// Only entries before this timestamp, used for pagination
+    pub before: Option<i64>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        if self.event.is_some_and(|event| event != entry.event) {
+            return false;
+        }
+        if let Some(agent) = &self.agent {
+            if entry.agent.as_ref() != Some(agent) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.subject {
+            if !entry
+                .subject
+                .as_ref()
+                .is_some_and(|s| s.starts_with(prefix))
+            {
+                return false;
+            }
+        }
+        self.before.is_none_or(|before| entry.timestamp < before)
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    /// Folder containing the current and rotated log files
+    dir: PathBuf,
+    /// Size in bytes after which the log is rotated
+    max_size: u64,
+    /// Amount of rotated files that are kept
+    max_files: usize,
+    /// Makes sure lines are not interleaved, and rotation doesn't happen while writing
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn init_from_config(config: &Config) -> AtomicServerResult<AuditLog> {
+        std::fs::create_dir_all(&config.audit_log_path).map_err(|e| {
+            format!(
+                "Could not create audit log folder {:?}: {}",
+                config.audit_log_path, e
+            )
+        })?;
+        Ok(AuditLog {
+            dir: config.audit_log_path.clone(),
+            max_size: config.opts.audit_log_max_size * 1024 * 1024,
+            max_files: config.opts.audit_log_files,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Path of the current log (`0`) or a rotated one.
+    fn file_path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(FILE_NAME),
+            n => self.dir.join(format!("{}.{}", FILE_NAME, n)),
+        }
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let current = self.file_path(0);
+        if std::fs::metadata(&current).is_ok_and(|m| m.len() >= self.max_size) {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)?;
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Shifts every file one place, and removes the oldest one.
+    fn rotate(&self) -> AtomicServerResult<()> {
+        let oldest = self.file_path(self.max_files);
+        if oldest.exists() {
+            std::fs::remove_file(&oldest)?;
+        }
+        for index in (0..self.max_files).rev() {
+            let from = self.file_path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.file_path(index + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the newest entries that match the filter.
+    pub fn read(&self, filter: &AuditFilter, limit: usize) -> AtomicServerResult<Vec<AuditEntry>> {
+        let _guard = self.lock.lock()?;
+        let mut found = Vec::new();
+        for index in 0..=self.max_files {
+            let file = match File::open(self.file_path(index)) {
+                Ok(file) => file,
+                Err(_not_found) => break,
+            };
+            let mut entries = Vec::new();
+            for line in BufReader::new(file).lines() {
+                match serde_json::from_str::<AuditEntry>(&line?) {
+                    Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                    Ok(_other) => {}
+                    Err(e) => tracing::warn!("Invalid line in audit log: {}", e),
+                }
+            }
+            // Files are appended to, so the newest entries are at the end
+            found.extend(entries.into_iter().rev());
+            if found.len() >= limit {
+                break;
+            }
+        }
+        found.truncate(limit);
+        Ok(found)
+    }
+}
+
+/// Appends an entry to the audit log.
+/// Failing to write to the log does not fail the request, but it is logged.
+pub fn record(appstate: &AppState, entry: AuditEntry) {
+    if let Err(e) = appstate.audit_log.append(&entry) {
+        tracing::error!("Failed to write to audit log: {}", e);
+    }
+}
+
+/// Middleware that records requests that are rejected because of missing or invalid authentication.
+pub fn audit_auth_failures<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl std::future::Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let response = srv.call(req);
+    async move {
+        let res = response.await?;
+        if res.status() == StatusCode::UNAUTHORIZED {
+            if let Some(appstate) = res.request().app_data::<web::Data<AppState>>() {
+                let req = res.request();
+                let agent = req
+                    .headers()
+                    .get("x-atomic-agent")
+                    .and_then(|v| v.to_str().ok())
+                    .map(String::from);
+                let subject = format!("{}{}", appstate.config.server_url, req.uri());
+                let entry = AuditEntry::from_request(
+                    req,
+                    AuditEvent::AuthFailure,
+                    agent,
+                    Some(subject),
+                    "unauthorized".into(),
+                );
+                record(appstate, entry);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: i64, event: AuditEvent) -> AuditEntry {
+        AuditEntry {
+            timestamp,
+            event,
+            agent: Some("https://localhost/agents/a".into()),
+            subject: Some(format!("https://localhost/things/{}", timestamp)),
+            ip: None,
+            user_agent: None,
+            outcome: "applied".into(),
+        }
+    }
+
+    #[test]
+    fn rotates_and_reads_newest_first() {
+        let dir = PathBuf::from("./.temp/audit_log_rotation");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = AuditLog {
+            dir,
+            // Every entry is larger than this, so every append rotates
+            max_size: 10,
+            max_files: 2,
+            lock: Mutex::new(()),
+        };
+        for timestamp in 1..=5 {
+            log.append(&entry(timestamp, AuditEvent::Commit)).unwrap();
+        }
+        log.append(&entry(6, AuditEvent::AuthFailure)).unwrap();
+
+        let all = log.read(&AuditFilter::default(), 100).unwrap();
+        let timestamps: Vec<i64> = all.iter().map(|e| e.timestamp).collect();
+        // The oldest files have been removed
+        assert_eq!(timestamps, vec![6, 5, 4]);
+
+        let filter = AuditFilter {
+            event: Some(AuditEvent::Commit),
+            before: Some(5),
+            ..Default::default()
+        };
+        let commits = log.read(&filter, 100).unwrap();
+        assert_eq!(commits, vec![entry(4, AuditEvent::Commit)]);
+    }
+}
@@ -2,25 +2,51 @@ use atomic_lib::{urls, Storelike};
 use atomic_server_lib::config::Opts;
 use std::{fs::File, io::Write};
 
+mod activitypub;
 mod actor_messages;
+mod api_tokens;
+mod api_version;
 mod appstate;
+mod assets;
+mod audit_log;
+mod calendar;
 mod commit_monitor;
+mod compression;
 pub mod config;
 mod content_types;
+mod domains;
 mod errors;
+mod feeds;
+#[cfg(feature = "file-text")]
+mod file_text;
+mod files;
 mod handlers;
 mod helpers;
+mod html;
 #[cfg(feature = "https")]
 mod https;
+mod ip_access;
+mod jobs;
 mod jsonerrors;
+mod lockout;
+mod memory;
+mod oidc;
+mod passkeys;
 #[cfg(feature = "process-management")]
 mod process;
+mod rate_limit;
+mod read_tokens;
 mod routes;
+mod scanner;
 pub mod serve;
 // #[cfg(feature = "search")]
 mod search;
+mod sessions;
+mod share_links;
+mod solid;
 #[cfg(test)]
 mod tests;
+mod totp;
 mod trace;
 
 #[actix_web::main]
@@ -51,7 +77,9 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
                 }
             };
             let appstate = appstate::init(config.clone())?;
-            let outstr = appstate.store.export(!e.only_internal)?;
+            let outstr = appstate
+                .store
+                .export(!e.only_internal, e.include_no_export)?;
             std::fs::create_dir_all(path.parent().unwrap())
                 .map_err(|e| format!("Failed to create directory {:?}. {}", path, e))?;
             let mut file = File::create(&path)
@@ -84,6 +112,24 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
                 signer: Some(appstate.store.get_default_agent()?),
             };
             println!("Importing...");
+            if import_opts.skip_invalid {
+                let report = appstate.store.import_tolerant(&readstring, &parse_opts)?;
+                for failure in &report.failures {
+                    println!(
+                        "Skipped resource at line {} ({}): {}",
+                        failure.line,
+                        failure.subject.as_deref().unwrap_or("unknown subject"),
+                        failure.error
+                    );
+                }
+                println!(
+                    "Imported {} resources from {:?}, skipped {}.",
+                    report.imported,
+                    import_opts.file,
+                    report.failures.len()
+                );
+                return Ok(());
+            }
             appstate.store.import(&readstring, &parse_opts)?;
 
             println!("Sucesfully imported {:?} to store.", import_opts.file);
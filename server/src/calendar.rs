@@ -0,0 +1,190 @@
+//! Renders Events as iCalendar (`.ics`), so calendar apps can subscribe to them.
+//! Requested by adding `.ics` to the URL of an Event, a Collection of Events or a Drive, see [crate::helpers::try_extension].
+//! Drives (and other Resources) contain all the Events inside of them that the Agent can read.
+//! See [atomic_lib::plugins::calendar] for the Event class and its recurrences.
+
+use atomic_lib::{
+    plugins::calendar::get_events, urls, values::SubResource, Resource, Storelike, Value,
+};
+
+use crate::errors::AtomicServerResult;
+
+/// Whether the path asks for a calendar, which browsers should get instead of the single page app.
+pub fn is_calendar_path(path: &str) -> bool {
+    path.ends_with(".ics")
+}
+
+fn is_event(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == urls::EVENT))
+}
+
+fn is_collection(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == urls::COLLECTION))
+}
+
+/// The Events in the calendar of the Resource.
+fn events(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<Vec<Resource>> {
+    if is_event(resource) {
+        return Ok(vec![resource.clone()]);
+    }
+    if !is_collection(resource) {
+        return Ok(get_events(store, Some(resource.get_subject()), for_agent)?);
+    }
+    let members = match resource.get(urls::COLLECTION_MEMBERS) {
+        Ok(Value::ResourceArray(members)) => members.clone(),
+        _ => Vec::new(),
+    };
+    Ok(members
+        .iter()
+        .filter_map(|member| match member {
+            SubResource::Subject(subject) => {
+                store.get_resource_extended(subject, true, for_agent).ok()
+            }
+            SubResource::Resource(resource) => Some(*resource.clone()),
+            SubResource::Nested(_) => None,
+        })
+        .filter(is_event)
+        .collect())
+}
+
+/// Escapes TEXT values: https://www.rfc-editor.org/rfc/rfc5545#section-3.3.11
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn datetime(millis: i64) -> String {
+    chrono::DateTime::<chrono::Utc>::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// Adds a content line, folded after 75 octets: https://www.rfc-editor.org/rfc/rfc5545#section-3.1
+fn push_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn string_prop(resource: &Resource, property: &str) -> Option<String> {
+    resource.get(property).ok().map(|v| v.to_string())
+}
+
+/// When the Event was last changed, which calendar apps use to detect updates.
+fn last_modified(store: &impl Storelike, resource: &Resource) -> i64 {
+    string_prop(resource, urls::LAST_COMMIT)
+        .and_then(|commit| store.get_resource(&commit).ok())
+        .and_then(|commit| commit.get(urls::CREATED_AT).ok()?.to_int().ok())
+        .unwrap_or_else(atomic_lib::utils::now)
+}
+
+fn push_event(out: &mut String, store: &impl Storelike, resource: &Resource) {
+    let start = match resource.get(urls::EVENT_START).and_then(|v| v.to_int()) {
+        Ok(start) => start,
+        Err(_) => return,
+    };
+    push_line(out, "BEGIN:VEVENT");
+    push_line(out, &format!("UID:{}", resource.get_subject()));
+    push_line(
+        out,
+        &format!("DTSTAMP:{}", datetime(last_modified(store, resource))),
+    );
+    push_line(out, &format!("DTSTART:{}", datetime(start)));
+    if let Ok(end) = resource.get(urls::EVENT_END).and_then(|v| v.to_int()) {
+        push_line(out, &format!("DTEND:{}", datetime(end)));
+    }
+    if let Some(rule) = string_prop(resource, urls::EVENT_RECURRENCE) {
+        push_line(out, &format!("RRULE:{}", rule.trim_start_matches("RRULE:")));
+    }
+    let summary =
+        string_prop(resource, urls::NAME).unwrap_or_else(|| resource.get_subject().into());
+    push_line(out, &format!("SUMMARY:{}", escape(&summary)));
+    if let Some(description) = string_prop(resource, urls::DESCRIPTION) {
+        push_line(out, &format!("DESCRIPTION:{}", escape(&description)));
+    }
+    if let Ok(Value::GeoPoint(point)) = resource.get(urls::LOCATION) {
+        push_line(out, &format!("GEO:{};{}", point.latitude, point.longitude));
+    }
+    push_line(out, &format!("URL:{}", resource.get_subject()));
+    push_line(out, "END:VEVENT");
+}
+
+/// Renders the Event, or the Events in the Resource, as an iCalendar.
+pub fn to_ics(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<String> {
+    let name = string_prop(resource, urls::NAME).unwrap_or_else(|| resource.get_subject().into());
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//Atomic Data//Atomic-Server//EN");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape(&name)));
+    for event in events(store, resource, for_agent)? {
+        push_event(&mut out, store, &event);
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_calendars() {
+        let store = atomic_lib::Db::init_temp("calendars").unwrap();
+        let drive = store.get_server_url().to_string();
+        let mut event = Resource::new(format!("{}/standup", drive));
+        event.set_class(urls::EVENT);
+        event.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        event.set_propval_unsafe(urls::NAME.into(), Value::String("Standup, daily".into()));
+        event.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("a".repeat(100)));
+        event.set_propval_unsafe(
+            urls::EVENT_START.into(),
+            Value::Timestamp(1_706_691_600_000),
+        );
+        event.set_propval_unsafe(
+            urls::EVENT_RECURRENCE.into(),
+            Value::String("FREQ=DAILY;COUNT=5".into()),
+        );
+        event.save_locally(&store).unwrap();
+
+        let ics = to_ics(&store, &event, None).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART:20240131T090000Z\r\n"));
+        assert!(ics.contains("RRULE:FREQ=DAILY;COUNT=5\r\n"));
+        assert!(ics.contains("SUMMARY:Standup\\, daily\r\n"));
+        assert!(ics.lines().all(|line| line.len() <= 75));
+        assert!(!ics.contains("DTEND"));
+
+        let drive_resource = store.get_resource(&drive).unwrap();
+        let ics = to_ics(&store, &drive_resource, None).unwrap();
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        let elsewhere = Resource::new(format!("{}/elsewhere", drive));
+        let ics = to_ics(&store, &elsewhere, None).unwrap();
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 0);
+    }
+}
@@ -1,34 +1,105 @@
 //! The Commit Monitor checks for new commits and notifies listeners.
 //! It is used for WebSockets to notify front-end clients of changes in Resources,
-//! and to update the Search index.
+//! to update the Search index, and to publish activities to the Fediverse.
 
 use crate::{
-    actor_messages::{CommitMessage, Subscribe},
+    activitypub::Federation,
+    actor_messages::{CommitMessage, Disconnect, Lagged, Subscribe, Unsubscribe},
     errors::AtomicServerResult,
     handlers::web_sockets::WebSocketConnection,
     search::SearchState,
 };
 use actix::{
-    prelude::{Actor, Context, Handler},
+    prelude::{Actor, Context, Handler, SendError},
     ActorStreamExt, Addr, ContextFutureSpawner,
 };
-use atomic_lib::{Db, Storelike};
-use chrono::Local;
-use std::collections::{HashMap, HashSet};
+use atomic_lib::{Db, Resource, Storelike};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 /// The Commit Monitor is an Actor that manages subscriptions for subjects and sends Commits to listeners.
 /// It's also responsible for checking whether the rights are present
 pub struct CommitMonitor {
     /// Maintains a list of all the resources that are being subscribed to, and maps these to websocket connections.
     subscriptions: HashMap<String, HashSet<Addr<WebSocketConnection>>>,
+    /// Connections whose queue was full, with the amount of Commits they missed.
+    /// They receive a [Lagged] message as soon as there is room in their queue again.
+    lagging: HashMap<Addr<WebSocketConnection>, usize>,
+    ws_metrics: Arc<WebSocketMetrics>,
+    /// Amount of dropped Commits that has been logged, see [CommitMonitor::tick]
+    dropped_logged: usize,
     store: Db,
     search_state: SearchState,
-    last_search_commit: chrono::DateTime<Local>,
-    run_expensive_next_tick: bool,
+    federation: Option<std::sync::Arc<Federation>>,
+    /// Search index updates that are applied in a batch on the next tick
+    pending_search: PendingSearchUpdates,
+    /// How often the pending search updates are indexed and committed
+    search_commit_interval: std::time::Duration,
+}
+
+/// Search index updates that have come in since the last tick, see [CommitMonitor::flush_search].
+#[derive(Default)]
+struct PendingSearchUpdates {
+    /// The latest version of every changed resource, or None if it was destroyed
+    resources: HashMap<String, Option<Resource>>,
+    /// Resources whose descendants have to be indexed again
+    descendants: HashSet<String>,
+    /// Everything has to be indexed again, for example because the search language changed
+    rebuild: bool,
+}
+
+impl PendingSearchUpdates {
+    fn len(&self) -> usize {
+        self.resources.len() + self.descendants.len() + usize::from(self.rebuild)
+    }
+}
+
+/// Counters for the WebSocket connections and the Commits that are sent to them, for monitoring.
+#[derive(Debug, Default)]
+pub struct WebSocketMetrics {
+    connections: AtomicUsize,
+    subscriptions: AtomicUsize,
+    sent: AtomicUsize,
+    dropped: AtomicUsize,
 }
 
-// Only runs expensive index operation (tantivy) once every x seconds
-const REBUILD_INDEX_TIME: std::time::Duration = std::time::Duration::from_secs(5);
+impl WebSocketMetrics {
+    /// Amount of open WebSocket connections.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Amount of subscriptions of all connections together.
+    pub fn subscriptions(&self) -> usize {
+        self.subscriptions.load(Ordering::Relaxed)
+    }
+
+    /// Amount of Commits that have been sent to connections.
+    pub fn sent(&self) -> usize {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// Amount of Commits that were not sent, because the queue of the connection was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Pending search updates are flushed right away when there are this many, to limit memory usage.
+const MAX_PENDING_SEARCH_UPDATES: usize = 1000;
 
 // Since his Actor only starts once, there is no need to handle its lifecycle
 impl Actor for CommitMonitor {
@@ -38,7 +109,7 @@ impl Actor for CommitMonitor {
         tracing::debug!("CommitMonitor started");
 
         // spawn an interval stream into our context
-        actix::utils::IntervalFunc::new(REBUILD_INDEX_TIME, Self::tick)
+        actix::utils::IntervalFunc::new(self.search_commit_interval, Self::tick)
             .finish()
             .spawn(ctx);
     }
@@ -48,6 +119,7 @@ impl Handler<Subscribe> for CommitMonitor {
     type Result = ();
 
     // A message comes in when a client subscribes to a subject.
+    #[allow(clippy::mutable_key_type)]
     #[tracing::instrument(
         name = "handle_subscribe",
         skip_all,
@@ -61,16 +133,35 @@ impl Handler<Subscribe> for CommitMonitor {
         }
         match self.store.get_resource(&msg.subject) {
             Ok(resource) => {
-                match atomic_lib::hierarchy::check_read(&self.store, &resource, &msg.agent) {
+                // Resources with `noStream` are only streamed to Agents that can edit them
+                let no_stream = resource
+                    .get_parent_tree(&self.store)
+                    .map(|tree| {
+                        atomic_lib::hierarchy::inherited_flag(
+                            &resource,
+                            &tree,
+                            atomic_lib::urls::NO_STREAM,
+                        )
+                    })
+                    .unwrap_or(false);
+                let allowed = if no_stream {
+                    atomic_lib::hierarchy::check_write(&self.store, &resource, &msg.agent)
+                } else {
+                    atomic_lib::hierarchy::check_read(&self.store, &resource, &msg.agent)
+                };
+                match allowed {
                     Ok(_explanation) => {
-                        let mut set = if let Some(set) = self.subscriptions.get(&msg.subject) {
-                            set.clone()
-                        } else {
-                            HashSet::new()
-                        };
-                        set.insert(msg.addr);
                         tracing::debug!("handle subscribe {} ", msg.subject);
-                        self.subscriptions.insert(msg.subject.clone(), set);
+                        if self
+                            .subscriptions
+                            .entry(msg.subject)
+                            .or_default()
+                            .insert(msg.addr)
+                        {
+                            self.ws_metrics
+                                .subscriptions
+                                .fetch_add(1, Ordering::Relaxed);
+                        }
                     }
                     Err(unauthorized_err) => {
                         tracing::debug!(
@@ -94,10 +185,49 @@ impl Handler<Subscribe> for CommitMonitor {
     }
 }
 
+impl Handler<Unsubscribe> for CommitMonitor {
+    type Result = ();
+
+    #[allow(clippy::mutable_key_type)]
+    fn handle(&mut self, msg: Unsubscribe, _ctx: &mut Context<Self>) {
+        if let Some(set) = self.subscriptions.get_mut(&msg.subject) {
+            if set.remove(&msg.addr) {
+                self.ws_metrics
+                    .subscriptions
+                    .fetch_sub(1, Ordering::Relaxed);
+            }
+            if set.is_empty() {
+                self.subscriptions.remove(&msg.subject);
+            }
+        }
+    }
+}
+
+impl Handler<Disconnect> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _ctx: &mut Context<Self>) {
+        self.remove_connection(&msg.addr);
+    }
+}
+
 impl CommitMonitor {
+    /// Removes all subscriptions of a connection.
+    #[allow(clippy::mutable_key_type)]
+    fn remove_connection(&mut self, addr: &Addr<WebSocketConnection>) {
+        let metrics = &self.ws_metrics;
+        self.subscriptions.retain(|_subject, set| {
+            if set.remove(addr) {
+                metrics.subscriptions.fetch_sub(1, Ordering::Relaxed);
+            }
+            !set.is_empty()
+        });
+        self.lagging.remove(addr);
+    }
+
     /// When a commit comes in, send it to any listening subscribers,
-    /// and update the value index.
-    /// The search index is only updated if the last search commit is 15 seconds or older.
+    /// and queue the search index update.
+    /// The queued updates are indexed and committed in a batch every `--search-commit-interval`.
     fn handle_internal(&mut self, msg: CommitMessage) -> AtomicServerResult<()> {
         let target = msg.commit_response.commit_struct.subject.clone();
 
@@ -108,46 +238,103 @@ impl CommitMonitor {
                 target,
                 subscribers.len()
             );
-            for connection in subscribers {
-                connection.do_send(msg.clone());
+            let closed: Vec<Addr<WebSocketConnection>> = subscribers
+                .iter()
+                .filter(|connection| {
+                    !send_commit(connection, &msg, &mut self.lagging, &self.ws_metrics)
+                })
+                .cloned()
+                .collect();
+            for connection in &closed {
+                self.remove_connection(connection);
             }
         } else {
             tracing::debug!("No subscribers for {}", target);
         }
 
-        // Update the search index
-        if let Some(resource) = &msg.commit_response.resource_new {
-            // We could one day re-(allow) to keep old resources,
-            // but then we also should index the older versions when re-indexing.
-            crate::search::remove_resource(&self.search_state, &target)?;
-            // Add new resource to search index
-            crate::search::add_resource(&self.search_state, resource, &self.store)?;
-            self.run_expensive_next_tick = true;
-        } else {
-            // If there is no new resource, it must have been deleted, so let's remove it from the search index.
-            crate::search::remove_resource(&self.search_state, &target)?;
+        // Queue the search index update. If there is no new resource, it must have been deleted.
+        self.pending_search
+            .resources
+            .insert(target.clone(), msg.commit_response.resource_new.clone());
+
+        // The search language of a parent or Property determines how other resources are analyzed,
+        // so these have to be indexed again.
+        let commit = &msg.commit_response.commit_struct;
+        if changes_property(commit, atomic_lib::urls::SEARCH_LANGUAGE) {
+            self.pending_search.rebuild = true;
+        } else if msg.commit_response.resource_new.is_some()
+            && (changes_property(commit, atomic_lib::urls::READ)
+                || changes_property(commit, atomic_lib::urls::DENY_READ)
+                || changes_property(commit, atomic_lib::urls::PUBLIC_READ)
+                || changes_property(commit, atomic_lib::urls::PARENT)
+                || changes_property(commit, atomic_lib::urls::NO_INDEX))
+        {
+            // Children store the parents and read rights of their ancestors in the index,
+            // and inherit `noIndex`
+            self.pending_search.descendants.insert(target.clone());
+        }
+        self.search_state.lag.set_pending(self.pending_search.len());
+        if self.pending_search.len() >= MAX_PENDING_SEARCH_UPDATES {
+            self.flush_search()?;
+        }
+
+        if let Some(federation) = &self.federation {
+            federation.handle_commit(&self.store, &msg.commit_response)?;
         }
         Ok(())
     }
 
-    /// Runs every X seconds to perform expensive operations.
+    /// Runs every `--search-commit-interval` to perform expensive operations.
     fn tick(&mut self, _ctx: &mut Context<Self>) {
-        if self.run_expensive_next_tick {
-            _ = self.update_expensive().map_err(|e| {
+        let dropped = self.ws_metrics.dropped();
+        if dropped > self.dropped_logged {
+            tracing::warn!(
+                "Dropped {} Commits for {} of {} WebSocket connections that can't keep up ({} subscriptions, {} Commits sent)",
+                dropped - self.dropped_logged,
+                self.lagging.len(),
+                self.ws_metrics.connections(),
+                self.ws_metrics.subscriptions(),
+                self.ws_metrics.sent(),
+            );
+            self.dropped_logged = dropped;
+        }
+        if self.pending_search.len() > 0 {
+            _ = self.flush_search().map_err(|e| {
                 tracing::error!(
-                    "Error during expensive update in Commit Monitor: {}",
+                    "Error during search index update in Commit Monitor: {}",
                     e.to_string()
                 )
             });
         }
     }
 
-    /// Run expensive updates that should not be run after every single Commit
-    fn update_expensive(&mut self) -> AtomicServerResult<()> {
-        tracing::debug!("Update expensive");
-        self.search_state.writer.write()?.commit()?;
-        self.last_search_commit = chrono::Local::now();
-        self.run_expensive_next_tick = false;
+    /// Indexes all pending search updates and commits the index, which makes them visible in search results.
+    /// Committing is expensive in tantivy, so this should not happen after every single Commit.
+    fn flush_search(&mut self) -> AtomicServerResult<()> {
+        tracing::debug!(
+            "Indexing {} pending search updates, lag {:?}",
+            self.search_state.lag.pending(),
+            self.search_state.lag.lag()
+        );
+        let pending = std::mem::take(&mut self.pending_search);
+        if pending.rebuild {
+            // Also adds the pending resources, since these are already in the store
+            crate::search::rebuild_index(&self.search_state, &self.store)?;
+        } else {
+            for (subject, resource) in &pending.resources {
+                // We could one day re-(allow) to keep old resources,
+                // but then we also should index the older versions when re-indexing.
+                crate::search::remove_resource(&self.search_state, subject)?;
+                if let Some(resource) = resource {
+                    crate::search::add_resource(&self.search_state, resource, &self.store)?;
+                }
+            }
+            for subject in &pending.descendants {
+                crate::search::reindex_descendants(&self.search_state, &self.store, subject)?;
+            }
+            self.search_state.writer.write()?.commit()?;
+        }
+        self.search_state.lag.set_pending(0);
         Ok(())
     }
 }
@@ -170,15 +357,83 @@ impl Handler<CommitMessage> for CommitMonitor {
     }
 }
 
+/// Sends the Commit to a connection, unless the queue of the connection is full.
+/// Connections that missed Commits first receive a [Lagged] message, when there is room in their queue again.
+/// Returns `false` if the connection is closed.
+#[allow(clippy::mutable_key_type)]
+fn send_commit(
+    connection: &Addr<WebSocketConnection>,
+    msg: &CommitMessage,
+    lagging: &mut HashMap<Addr<WebSocketConnection>, usize>,
+    metrics: &WebSocketMetrics,
+) -> bool {
+    if let Some(&dropped) = lagging.get(connection) {
+        match connection.try_send(Lagged { dropped }) {
+            Ok(()) => {
+                lagging.remove(connection);
+            }
+            Err(SendError::Full(_)) => {
+                lagging.insert(connection.clone(), dropped + 1);
+                metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+            Err(SendError::Closed(_)) => return false,
+        }
+    }
+    match connection.try_send(msg.clone()) {
+        Ok(()) => {
+            metrics.sent.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        Err(SendError::Full(_)) => {
+            tracing::debug!("WebSocket connection can't keep up, dropping Commit");
+            *lagging.entry(connection.clone()).or_default() += 1;
+            metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        Err(SendError::Closed(_)) => false,
+    }
+}
+
+/// Whether the Commit sets, pushes to or removes the property of a resource.
+fn changes_property(commit: &atomic_lib::Commit, property: &str) -> bool {
+    let set = commit
+        .set
+        .as_ref()
+        .map(|set| set.contains_key(property))
+        .unwrap_or(false);
+    let pushed = commit
+        .push
+        .as_ref()
+        .map(|push| push.contains_key(property))
+        .unwrap_or(false);
+    let removed = commit
+        .remove
+        .as_ref()
+        .map(|remove| remove.iter().any(|p| p == property))
+        .unwrap_or(false);
+    set || pushed || removed
+}
+
 /// Spawns a commit monitor actor
-pub fn create_commit_monitor(store: Db, search_state: SearchState) -> Addr<CommitMonitor> {
+pub fn create_commit_monitor(
+    store: Db,
+    search_state: SearchState,
+    federation: Option<std::sync::Arc<Federation>>,
+    search_commit_interval: std::time::Duration,
+    ws_metrics: Arc<WebSocketMetrics>,
+) -> Addr<CommitMonitor> {
     crate::commit_monitor::CommitMonitor::create(|_ctx: &mut Context<CommitMonitor>| {
         CommitMonitor {
             subscriptions: HashMap::new(),
+            lagging: HashMap::new(),
+            ws_metrics,
+            dropped_logged: 0,
             store,
             search_state,
-            run_expensive_next_tick: false,
-            last_search_commit: chrono::Local::now(),
+            federation,
+            pending_search: PendingSearchUpdates::default(),
+            search_commit_interval,
         }
     })
 }
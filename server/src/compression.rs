@@ -0,0 +1,304 @@
+//! Compresses responses using Brotli or gzip, depending on the `Accept-Encoding` of the client.
+//! JSON-AD Collections and exports are highly compressible, so this saves a lot of bandwidth.
+//! Small responses and files that are already compressed (images, video, archives) are sent as they are.
+
+use std::{future::Future, io::Write};
+
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Method, StatusCode,
+    },
+    web,
+};
+
+use crate::{appstate::AppState, config::Config};
+
+/// Larger responses are streamed as they are, so we don't have to keep them in memory.
+const MAX_SIZE: u64 = 32 * 1024 * 1024;
+/// Brotli window size, as used by most web servers.
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    fn header_value(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Identity => "identity",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressionOptions {
+    /// Responses smaller than this amount of bytes are not compressed
+    pub min_size: u64,
+    /// 0 (fastest) to 11 (smallest)
+    pub brotli_level: u32,
+    /// 0 (fastest) to 9 (smallest)
+    pub gzip_level: u32,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        CompressionOptions {
+            min_size: 1024,
+            brotli_level: 4,
+            gzip_level: 6,
+        }
+    }
+}
+
+impl CompressionOptions {
+    pub fn from_config(config: &Config) -> CompressionOptions {
+        CompressionOptions {
+            min_size: config.opts.compression_min_size,
+            brotli_level: config.opts.compression_brotli_level,
+            gzip_level: config.opts.compression_gzip_level,
+        }
+    }
+}
+
+/// Picks the encoding with the highest quality in the `Accept-Encoding` header.
+/// Prefers Brotli over gzip if the client accepts both equally.
+pub fn negotiate(accept_encoding: &str) -> Encoding {
+    let mut best = (Encoding::Identity, 0.0);
+    for part in accept_encoding.split(',') {
+        let mut params = part.split(';');
+        let name = params.next().unwrap_or_default().trim().to_lowercase();
+        let quality: f32 = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse().ok())
+            .unwrap_or(1.0);
+        let candidates: &[Encoding] = match name.as_str() {
+            "br" => &[Encoding::Brotli],
+            "gzip" | "x-gzip" => &[Encoding::Gzip],
+            "*" => &[Encoding::Brotli, Encoding::Gzip],
+            _ => &[],
+        };
+        for &encoding in candidates {
+            let preferred = quality > best.1
+                || (quality == best.1 && quality > 0.0 && encoding == Encoding::Brotli);
+            if preferred {
+                best = (encoding, quality);
+            }
+        }
+    }
+    best.0
+}
+
+/// Whether compressing this type of content makes it smaller.
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    if mime == "image/svg+xml" {
+        return true;
+    }
+    let already_compressed = mime.starts_with("image/")
+        || mime.starts_with("video/")
+        || mime.starts_with("audio/")
+        || mime.starts_with("font/woff")
+        || matches!(
+            mime.as_str(),
+            "application/zip"
+                | "application/gzip"
+                | "application/x-gzip"
+                | "application/x-bzip2"
+                | "application/x-xz"
+                | "application/x-7z-compressed"
+                | "application/x-rar-compressed"
+                | "application/zstd"
+                | "application/pdf"
+                | "application/octet-stream"
+                | "text/event-stream"
+        );
+    !already_compressed
+}
+
+fn should_compress(
+    method: &Method,
+    status: StatusCode,
+    headers: &HeaderMap,
+    size: BodySize,
+    options: &CompressionOptions,
+) -> bool {
+    if method == Method::HEAD
+        || status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || headers.contains_key(header::CONTENT_ENCODING)
+    {
+        return false;
+    }
+    let sized = matches!(size, BodySize::Sized(n) if n >= options.min_size && n <= MAX_SIZE);
+    sized
+        && headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .is_some_and(is_compressible)
+}
+
+pub fn compress_bytes(
+    data: &[u8],
+    encoding: Encoding,
+    options: &CompressionOptions,
+) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut writer = brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                options.brotli_level,
+                BROTLI_WINDOW,
+            );
+            writer.write_all(data)?;
+            Ok(writer.into_inner())
+        }
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(options.gzip_level),
+            );
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Encoding::Identity => Ok(data.to_vec()),
+    }
+}
+
+/// Middleware that compresses the response body, if the client accepts it and it's worth it.
+pub fn compress<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static,
+{
+    let options = req
+        .app_data::<web::Data<AppState>>()
+        .map(|appstate| CompressionOptions::from_config(&appstate.config))
+        .unwrap_or_default();
+    let encoding = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(negotiate)
+        .unwrap_or(Encoding::Identity);
+    let method = req.method().clone();
+    let response = srv.call(req);
+    async move {
+        let res = response.await?;
+        if !should_compress(
+            &method,
+            res.status(),
+            res.headers(),
+            res.response().body().size(),
+            &options,
+        ) {
+            return Ok(res.map_into_boxed_body());
+        }
+        let (req, res) = res.into_parts();
+        let (mut res, body) = res.into_parts();
+        // The response differs per Accept-Encoding, so caches should store them separately
+        res.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        let bytes = actix_web::body::to_bytes(body)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+        if encoding == Encoding::Identity {
+            return Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(bytes))));
+        }
+        let compressed = compress_bytes(&bytes, encoding, &options)?;
+        res.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.header_value()),
+        );
+        res.headers_mut().remove(header::CONTENT_LENGTH);
+        Ok(ServiceResponse::new(
+            req,
+            res.set_body(BoxBody::new(compressed)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, App, HttpResponse};
+    use std::io::Read;
+
+    #[test]
+    fn negotiates_encoding() {
+        assert_eq!(negotiate("gzip, deflate, br"), Encoding::Brotli);
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5"), Encoding::Gzip);
+        assert_eq!(negotiate("br;q=0, gzip"), Encoding::Gzip);
+        assert_eq!(negotiate("*"), Encoding::Brotli);
+        assert_eq!(negotiate("deflate"), Encoding::Identity);
+        assert_eq!(negotiate("identity, *;q=0"), Encoding::Identity);
+    }
+
+    #[test]
+    fn skips_compressed_content() {
+        assert!(is_compressible("application/ad+json; charset=utf-8"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("application/zip"));
+    }
+
+    #[actix_rt::test]
+    async fn compresses_large_responses() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap_fn(compress)
+                .route(
+                    "/large",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("application/ad+json")
+                            .body("{\"name\": \"atom\"}".repeat(200))
+                    }),
+                )
+                .route(
+                    "/small",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("application/ad+json")
+                            .body("{}")
+                    }),
+                ),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::with_uri("/large")
+            .insert_header(("Accept-Encoding", "gzip"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+        let body = actix_test::read_body(resp).await;
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "{\"name\": \"atom\"}".repeat(200));
+
+        let req = actix_test::TestRequest::with_uri("/small")
+            .insert_header(("Accept-Encoding", "br"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert!(resp.headers().get("content-encoding").is_none());
+    }
+}
@@ -67,6 +67,15 @@ pub struct Opts {
     #[clap(long, default_value = "", env = "ATOMIC_SCRIPT")]
     pub script: String,
 
+    /// Serve the front-end (the HTML template and its JS bundles, icons, etc.) from this folder, instead of the bundled atomic-data-browser.
+    /// The folder should contain an `index.html`, which can use the `<!-- { inject_html_head } -->` and `<!-- { inject_script } -->` placeholders.
+    #[clap(long, env = "ATOMIC_ASSETS_PATH", conflicts_with = "api_only")]
+    pub assets_path: Option<PathBuf>,
+
+    /// Do not serve a front-end at all. Browsers will receive the requested resources as JSON-AD.
+    #[clap(long, env = "ATOMIC_API_ONLY")]
+    pub api_only: bool,
+
     /// Path for atomic data config directory. Defaults to "~/.config/atomic/""
     #[clap(long, env = "ATOMIC_CONFIG_DIR")]
     pub config_dir: Option<PathBuf>,
@@ -75,7 +84,7 @@ pub struct Opts {
     #[clap(long, env = "ATOMIC_DATA_DIR")]
     pub data_dir: Option<PathBuf>,
 
-    /// CAUTION: Skip authentication checks, making all data publicly readable. Improves performance.
+    /// Deprecated: set `publicRead` on your Drives instead. Makes every Drive publicly readable by setting `publicRead` to `true` on startup. Authentication is no longer skipped.
     #[clap(long, env = "ATOMIC_PUBLIC_MODE")]
     pub public_mode: bool,
 
@@ -83,10 +92,216 @@ pub struct Opts {
     #[clap(long, env = "ATOMIC_SERVER_URL")]
     pub server_url: Option<String>,
 
+    /// Serve all routes under this path, e.g. `/atomic`. Use this if your reverse proxy forwards `https://example.com/atomic/...` without removing the prefix.
+    /// The prefix is added to the generated server URL. If your proxy removes the prefix, set `--server-url` instead.
+    #[clap(long, env = "ATOMIC_PATH_PREFIX")]
+    pub path_prefix: Option<String>,
+
+    /// Use the `Forwarded` and `X-Forwarded-For`, `-Proto` and `-Host` headers to find the URL that clients requested, and their IP address.
+    /// These are used for checking signatures, mapped domains, rate limits and the audit log.
+    /// Only enable this when running behind a reverse proxy that sets these headers, as clients can set them themselves.
+    #[clap(long, env = "ATOMIC_TRUST_FORWARDED_HEADERS")]
+    pub trust_forwarded_headers: bool,
+
+    /// Responses smaller than this amount of bytes are not compressed.
+    #[clap(long, default_value = "1024", env = "ATOMIC_COMPRESSION_MIN_SIZE")]
+    pub compression_min_size: u64,
+
+    /// Brotli compression level, from 0 (fastest) to 11 (smallest).
+    #[clap(long, default_value = "4", env = "ATOMIC_COMPRESSION_BROTLI_LEVEL", value_parser = clap::value_parser!(u32).range(0..=11))]
+    pub compression_brotli_level: u32,
+
+    /// Gzip compression level, from 0 (fastest) to 9 (smallest). Used for clients that don't support Brotli.
+    #[clap(long, default_value = "6", env = "ATOMIC_COMPRESSION_GZIP_LEVEL", value_parser = clap::value_parser!(u32).range(0..=9))]
+    pub compression_gzip_level: u32,
+
+    /// Amount of threads that run background Jobs, such as rebuilding indexes.
+    #[clap(long, default_value = "2", env = "ATOMIC_JOB_WORKERS")]
+    pub job_workers: usize,
+
     /// How much logs you want. Also influences what is sent to your trace service, if you've set one (e.g. OpenTelemetry)
     #[clap(value_enum, long, default_value = "info", env = "RUST_LOG")]
     pub log_level: LogLevel,
 
+    /// Store uploaded files in this S3 (compatible) bucket, instead of in the local `uploads` folder.
+    /// Downloads will redirect to presigned URLs.
+    #[clap(long, env = "ATOMIC_S3_BUCKET")]
+    pub s3_bucket: Option<String>,
+
+    /// Prefix inside the S3 bucket, under which uploaded files are stored.
+    #[clap(long, default_value = "uploads", env = "ATOMIC_S3_PATH")]
+    pub s3_path: String,
+
+    /// Region of the S3 bucket, e.g. `eu-west-1`.
+    #[clap(long, env = "ATOMIC_S3_REGION")]
+    pub s3_region: Option<String>,
+
+    /// Endpoint of the S3 service. Only needed for S3 compatible services other than AWS, such as MinIO.
+    #[clap(long, env = "ATOMIC_S3_ENDPOINT")]
+    pub s3_endpoint: Option<String>,
+
+    /// Access key for the S3 bucket. If not set, the default AWS credentials (environment, profile) are used.
+    #[clap(long, env = "ATOMIC_S3_ACCESS_KEY_ID")]
+    pub s3_access_key_id: Option<String>,
+
+    /// Secret key for the S3 bucket.
+    #[clap(long, env = "ATOMIC_S3_SECRET_ACCESS_KEY", hide_env_values = true)]
+    pub s3_secret_access_key: Option<String>,
+
+    /// How long presigned S3 download URLs stay valid, in seconds.
+    #[clap(long, default_value = "3600", env = "ATOMIC_S3_PRESIGN_EXPIRY")]
+    pub s3_presign_expiry: u64,
+
+    /// Address (`host:port`) of a ClamAV daemon. If set, uploaded files are scanned for viruses and malware.
+    #[cfg(feature = "clamav")]
+    #[clap(long, env = "ATOMIC_CLAMAV_ADDRESS")]
+    pub clamav_address: Option<String>,
+
+    /// Keep uploaded files that are flagged by the virus scanner in a quarantine folder, instead of rejecting the upload.
+    /// The File resource is created with an `infected` scan status, and cannot be downloaded.
+    #[clap(long, env = "ATOMIC_QUARANTINE_INFECTED")]
+    pub quarantine_infected: bool,
+
+    /// Maximum amount of GET requests per minute, for every IP address and every Agent. `0` disables the limit.
+    #[clap(long, default_value = "1200", env = "ATOMIC_RATE_LIMIT_READS")]
+    pub rate_limit_reads: u32,
+
+    /// Maximum amount of Commits and uploads per minute, for every IP address and every Agent. `0` disables the limit.
+    #[clap(long, default_value = "120", env = "ATOMIC_RATE_LIMIT_COMMITS")]
+    pub rate_limit_commits: u32,
+
+    /// How many typos are allowed per word in search queries, unless the query sets `fuzzy`.
+    /// A Levenshtein distance of 0, 1 or 2, or `auto` to allow more typos in longer words.
+    #[clap(long, default_value = "1", env = "ATOMIC_SEARCH_FUZZY")]
+    pub search_fuzzy: crate::search::Fuzziness,
+
+    /// Language of the text on this server, used for search. Drives, folders and Properties can override it using `searchLanguage`.
+    /// One of `ar`, `cjk`, `da`, `de`, `el`, `en`, `es`, `fi`, `fr`, `hu`, `it`, `nl`, `no`, `pt`, `ro`, `ru`, `sv`, `ta` or `tr`.
+    /// Changing this rebuilds the search index on startup.
+    #[clap(long, env = "ATOMIC_SEARCH_LANGUAGE")]
+    pub search_language: Option<String>,
+
+    /// Don't reduce words to their stem when indexing text in a specific language, so `running` no longer matches `run`.
+    /// Changing this rebuilds the search index on startup.
+    #[clap(long, env = "ATOMIC_SEARCH_NO_STEMMING")]
+    pub search_no_stemming: bool,
+
+    /// How often changed resources are added to the search index, in milliseconds.
+    /// Updates are batched, since committing the index is expensive. Lower values make changes searchable sooner.
+    #[clap(long, default_value = "1000", env = "ATOMIC_SEARCH_COMMIT_INTERVAL")]
+    pub search_commit_interval: u64,
+
+    /// Memory that the search index can use while indexing, in megabytes. At least 3.
+    /// Defaults to 1/32 of the RAM of the machine, between 15 and 50 megabytes. Larger values make indexing large imports faster.
+    #[clap(long, env = "ATOMIC_SEARCH_HEAP_SIZE")]
+    pub search_heap_size: Option<u64>,
+
+    /// Memory that the database can use for caching, in megabytes.
+    /// Defaults to 1/8 of the RAM of the machine, between 64 megabytes and 1 gigabyte.
+    #[clap(long, env = "ATOMIC_DB_CACHE_SIZE")]
+    pub db_cache_size: Option<u64>,
+
+    /// Amount of recently used resources that are kept in memory, so they don't have to be read from the database every time. `0` disables the cache.
+    #[clap(long, default_value = "10000", env = "ATOMIC_RESOURCE_CACHE_SIZE")]
+    pub resource_cache_size: usize,
+
+    /// Maximum amount of resources that a single WebSocket connection can subscribe to.
+    #[clap(long, default_value = "1000", env = "ATOMIC_WS_MAX_SUBSCRIPTIONS")]
+    pub ws_max_subscriptions: usize,
+
+    /// Maximum amount of Commits that can wait to be sent to a single WebSocket connection.
+    /// When a client can't keep up, further Commits are dropped and the client receives a `LAGGED` message, so it can fetch the resources again.
+    #[clap(long, default_value = "100", env = "ATOMIC_WS_QUEUE_SIZE")]
+    pub ws_queue_size: usize,
+
+    /// Maximum amount of search queries per minute, for every IP address and every Agent. `0` disables the limit.
+    #[clap(long, default_value = "120", env = "ATOMIC_RATE_LIMIT_SEARCH")]
+    pub rate_limit_search: u32,
+
+    /// Maximum amount of accepted Invites (which create new Agents) per minute, for every IP address. `0` disables the limit.
+    #[clap(long, default_value = "10", env = "ATOMIC_RATE_LIMIT_REGISTRATIONS")]
+    pub rate_limit_registrations: u32,
+
+    /// Comma separated list of IP addresses and Agent subjects that are never rate limited.
+    #[clap(
+        long,
+        env = "ATOMIC_RATE_LIMIT_ALLOWLIST",
+        value_delimiter = ',',
+        default_value = ""
+    )]
+    pub rate_limit_allowlist: Vec<String>,
+
+    /// Use the `Forwarded` or `X-Forwarded-For` headers to find the IP address of clients.
+    /// Only enable this when running behind a reverse proxy, as clients can set these headers themselves.
+    /// Implied by `--trust-forwarded-headers`.
+    #[clap(long, env = "ATOMIC_RATE_LIMIT_BEHIND_PROXY")]
+    pub rate_limit_behind_proxy: bool,
+
+    /// Comma separated IP addresses and CIDR ranges (e.g. `10.0.0.0/8`) that can use the server. All other addresses are rejected. Empty allows everyone.
+    /// Entries starting with `@` are files with one range per line, e.g. the ranges of a country from a GeoIP database.
+    #[clap(
+        long,
+        env = "ATOMIC_IP_ALLOW",
+        value_delimiter = ',',
+        default_value = ""
+    )]
+    pub ip_allow: Vec<String>,
+
+    /// Comma separated IP addresses and CIDR ranges that can not use the server, even if they are in `--ip-allow`.
+    /// Entries starting with `@` are files with one range per line.
+    #[clap(
+        long,
+        env = "ATOMIC_IP_DENY",
+        value_delimiter = ',',
+        default_value = ""
+    )]
+    pub ip_deny: Vec<String>,
+
+    /// Amount of failed authentications (invalid signatures, session cookies or API tokens) after which an IP address or Agent is temporarily locked out.
+    /// The lockout doubles with every next failure, up to 15 minutes. `0` disables lockouts.
+    #[clap(long, default_value = "10", env = "ATOMIC_AUTH_LOCKOUT_THRESHOLD")]
+    pub auth_lockout_threshold: u32,
+
+    /// Maximum amount of resources in every Drive. Not limited by default.
+    /// Admins can set other limits for a Drive in its Quota, at `{drive}/settings/quota`.
+    #[clap(long, env = "ATOMIC_QUOTA_MAX_RESOURCES")]
+    pub quota_max_resources: Option<u64>,
+
+    /// Maximum total size of the Files in every Drive, in megabytes. Not limited by default.
+    #[clap(long, env = "ATOMIC_QUOTA_MAX_FILE_SIZE")]
+    pub quota_max_file_size: Option<u64>,
+
+    /// Maximum amount of Commits per day (in UTC) for the resources of every Drive. Not limited by default.
+    #[clap(long, env = "ATOMIC_QUOTA_MAX_COMMITS_PER_DAY")]
+    pub quota_max_commits_per_day: Option<u64>,
+
+    /// Size in megabytes after which the audit log file is rotated.
+    #[clap(long, default_value = "10", env = "ATOMIC_AUDIT_LOG_MAX_SIZE")]
+    pub audit_log_max_size: u64,
+
+    /// Amount of rotated audit log files that are kept. Older entries are removed.
+    #[clap(long, default_value = "5", env = "ATOMIC_AUDIT_LOG_FILES")]
+    pub audit_log_files: usize,
+
+    /// URL of an OpenID Connect provider (e.g. `https://accounts.google.com`).
+    /// If set, users can log in using this provider at `/auth/oidc/login`.
+    #[clap(long, env = "ATOMIC_OIDC_ISSUER")]
+    pub oidc_issuer: Option<String>,
+
+    /// Client ID of this server at the OpenID Connect provider.
+    #[clap(long, env = "ATOMIC_OIDC_CLIENT_ID")]
+    pub oidc_client_id: Option<String>,
+
+    /// Client secret of this server at the OpenID Connect provider.
+    #[clap(long, env = "ATOMIC_OIDC_CLIENT_SECRET")]
+    pub oidc_client_secret: Option<String>,
+
+    /// PEM file with an RSA private key (PKCS#8), used to sign ActivityPub requests.
+    /// If set, Drives and Agents with an `activitypubHandle` can be followed from Mastodon and other Fediverse servers.
+    /// Create one using `openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048`.
+    #[clap(long, env = "ATOMIC_ACTIVITYPUB_KEY")]
+    pub activitypub_key: Option<PathBuf>,
+
     /// How you want to trace what's going on with the server. Useful for monitoring performance and errors in production.
     /// Combine with `log_level` to get more or less data (`trace` is the most verbose)
     #[clap(value_enum, long, env = "ATOMIC_TRACING", default_value = "stdout")]
@@ -138,6 +353,9 @@ pub struct ExportOpts {
     /// Do not export resources that are externally defined, which are cached by this Server.
     #[clap(long)]
     pub only_internal: bool,
+    /// Also export resources that have `noExport` set, e.g. for making a full backup.
+    #[clap(long)]
+    pub include_no_export: bool,
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -153,6 +371,9 @@ pub struct ImportOpts {
     /// Skip checks, allows for importing things like Commits.
     #[clap(long)]
     pub force: bool,
+    /// Skip Resources that are invalid or can't be saved, instead of stopping the import. The skipped Resources are listed afterwards.
+    #[clap(long)]
+    pub skip_invalid: bool,
 }
 
 /// Start atomic-server, oi mate
@@ -166,6 +387,8 @@ pub struct ServerOpts {}
 pub struct Config {
     /// Full domain + schema, e.g. `https://example.com`. Is either generated from `domain` and `schema`, or is the `custom_server_url`.
     pub server_url: String,
+    /// Path under which all routes are served, e.g. `/atomic`. Empty if there is no prefix.
+    pub path_prefix: String,
     /// CLI + ENV options
     pub opts: Opts,
     // ===  PATHS  ===
@@ -183,10 +406,16 @@ pub struct Config {
     pub static_path: PathBuf,
     /// Path to where the store / database is located.
     pub store_path: PathBuf,
+    /// Memory for the caches of the database and the search index, see `--db-cache-size` and `--search-heap-size`.
+    pub memory: crate::memory::MemoryBudget,
     /// Path to where the uploaded files are stored.
     pub uploads_path: PathBuf,
     /// Path to where the search index for tantivy full text search is located
     pub search_index_path: PathBuf,
+    /// Path to the folder containing the audit log files
+    pub audit_log_path: PathBuf,
+    /// Path to the folder containing the followers and outboxes of ActivityPub actors
+    pub activitypub_path: PathBuf,
     /// If true, the initialization scripts will be ran (create first Drive, Agent, indexing, etc)
     pub initialize: bool,
 }
@@ -217,6 +446,12 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
     let mut uploads_path = data_dir.clone();
     uploads_path.push("uploads");
 
+    let mut audit_log_path = data_dir.clone();
+    audit_log_path.push("audit");
+
+    let mut activitypub_path = data_dir.clone();
+    activitypub_path.push("activitypub");
+
     let mut static_path = data_dir;
     static_path.push("static");
 
@@ -267,6 +502,7 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
     }
 
     let initialize = !std::path::Path::exists(&store_path) || opts.initialize;
+    let memory = crate::memory::MemoryBudget::from_opts(&opts)?;
 
     if opts.https & opts.email.is_none() {
         return Err(
@@ -278,13 +514,21 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
 
     let schema = if opts.https { "https" } else { "http" };
 
+    let path_prefix = opts
+        .path_prefix
+        .as_deref()
+        .map(|prefix| prefix.trim_matches('/'))
+        .filter(|prefix| !prefix.is_empty())
+        .map(|prefix| format!("/{}", prefix))
+        .unwrap_or_default();
+
     // This logic could be a bit too complicated, but I'm not sure on how to make this simpler.
     let server_url = if let Some(addr) = opts.server_url.clone() {
         addr
     } else if opts.https && opts.port_https == 443 || !opts.https && opts.port == 80 {
-        format!("{}://{}", schema, opts.domain)
+        format!("{}://{}{}", schema, opts.domain, path_prefix)
     } else {
-        format!("{}://{}:{}", schema, opts.domain, opts.port)
+        format!("{}://{}:{}{}", schema, opts.domain, opts.port, path_prefix)
     };
 
     Ok(Config {
@@ -296,9 +540,13 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
         https_path,
         key_path,
         server_url,
+        path_prefix,
         static_path,
         store_path,
+        memory,
         search_index_path,
         uploads_path,
+        audit_log_path,
+        activitypub_path,
     })
 }
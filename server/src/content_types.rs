@@ -20,6 +20,15 @@ pub enum ContentType {
     /// RDF N-Triples format
     /// https://www.w3.org/TR/n-triples/
     NTriples,
+    /// RSS 2.0 feed, only for Collections
+    /// https://www.rssboard.org/rss-specification
+    Rss,
+    /// Atom feed, only for Collections
+    /// https://www.rfc-editor.org/rfc/rfc4287
+    Atom,
+    /// iCalendar, only for Events and the Resources that contain them
+    /// https://www.rfc-editor.org/rfc/rfc5545
+    ICalendar,
 }
 
 const MIME_HTML: &str = "text/html";
@@ -29,6 +38,9 @@ const MIME_JSONLD: &str = "application/ld+json";
 const MIME_JSONAD: &str = "application/ad+json";
 const MIME_TURTLE: &str = "text/turtle";
 const MIME_NT: &str = "application/n-triples";
+const MIME_RSS: &str = "application/rss+xml";
+const MIME_ATOM: &str = "application/atom+xml";
+const MIME_ICALENDAR: &str = "text/calendar";
 
 impl ContentType {
     pub fn to_mime(&self) -> &str {
@@ -39,6 +51,9 @@ impl ContentType {
             ContentType::Html => MIME_HTML,
             ContentType::Turtle => MIME_TURTLE,
             ContentType::NTriples => MIME_NT,
+            ContentType::Rss => MIME_RSS,
+            ContentType::Atom => MIME_ATOM,
+            ContentType::ICalendar => MIME_ICALENDAR,
         }
     }
 }
@@ -80,6 +95,15 @@ pub fn parse_accept_header(header: &str) -> ContentType {
         if mimepart.contains(MIME_NT) {
             return ContentType::NTriples;
         }
+        if mimepart.contains(MIME_RSS) {
+            return ContentType::Rss;
+        }
+        if mimepart.contains(MIME_ATOM) {
+            return ContentType::Atom;
+        }
+        if mimepart.contains(MIME_ICALENDAR) {
+            return ContentType::ICalendar;
+        }
     }
     tracing::info!("Unknown Accept header, defaut to HTML: {}", header);
     ContentType::Html
@@ -94,6 +118,7 @@ mod test {
         assert!(parse_accept_header("text/html,application/xml") == ContentType::Html);
         assert!(parse_accept_header("application/ad+json") == ContentType::JsonAd);
         assert!(parse_accept_header("application/ld+json") == ContentType::JsonLd);
+        assert!(parse_accept_header("application/rss+xml, */*") == ContentType::Rss);
     }
 
     #[test]
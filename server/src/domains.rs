@@ -0,0 +1,150 @@
+//! Serves Drives at external hostnames, such as `blog.example.org`, using DomainMapping resources.
+//! Requests with a mapped `Host` header use the Drive as their base URL, so `https://blog.example.org/posts` is `{drive}/posts`.
+//! A mapping is only used if the Agent that last edited it can edit the Drive, so nobody can publish someone else's Drive.
+//! With HTTPS enabled, every mapped hostname gets its own Let's Encrypt certificate.
+
+use atomic_lib::{hierarchy, storelike::Query, urls, Resource, Storelike};
+
+use crate::appstate::AppState;
+
+/// How often we check for new DomainMappings and expiring certificates.
+#[cfg(feature = "https")]
+const CERT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Removes the port from the host.
+fn hostname(host: &str) -> String {
+    let hostname = match host.rsplit_once(':') {
+        Some((hostname, port)) if port.chars().all(|c| c.is_ascii_digit()) => hostname,
+        _ => host,
+    };
+    hostname.to_lowercase()
+}
+
+fn is_a(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == class))
+}
+
+/// Returns the Drive of the mapping, if its last editor has write rights for the Drive.
+fn verified_drive(store: &impl Storelike, mapping: &Resource) -> Option<String> {
+    if !is_a(mapping, urls::DOMAIN_MAPPING) {
+        return None;
+    }
+    let drive = mapping.get(urls::MAPPED_DRIVE).ok()?.to_string();
+    let commit = mapping.get(urls::LAST_COMMIT).ok()?.to_string();
+    let signer = store
+        .get_resource(&commit)
+        .ok()?
+        .get(urls::SIGNER)
+        .ok()?
+        .to_string();
+    let drive_resource = store.get_resource(&drive).ok()?;
+    hierarchy::check_write(store, &drive_resource, &signer).ok()?;
+    Some(drive)
+}
+
+/// Finds the Drive that is served at the hostname.
+pub fn find_drive(store: &impl Storelike, hostname: &str) -> Option<String> {
+    store
+        .query(&Query::new_prop_val(urls::HOSTNAME, hostname))
+        .ok()?
+        .subjects
+        .iter()
+        .filter_map(|subject| store.get_resource(subject).ok())
+        .find_map(|mapping| verified_drive(store, &mapping))
+}
+
+/// All hostnames that have a valid DomainMapping.
+#[cfg(any(feature = "https", test))]
+pub fn hostnames(store: &impl Storelike) -> Vec<String> {
+    let Ok(result) = store.query(&Query::new_class(urls::DOMAIN_MAPPING)) else {
+        return Vec::new();
+    };
+    result
+        .subjects
+        .iter()
+        .filter_map(|subject| store.get_resource(subject).ok())
+        .filter(|mapping| verified_drive(store, mapping).is_some())
+        .filter_map(|mapping| Some(mapping.get(urls::HOSTNAME).ok()?.to_string()))
+        .collect()
+}
+
+/// The URL that paths of the request are appended to.
+/// This is the mapped Drive for external hostnames, or else the server URL.
+pub fn base_url(appstate: &AppState, req: &actix_web::HttpRequest) -> String {
+    let server_url = &appstate.config.server_url;
+    match crate::helpers::request_host(req, &appstate.config).map(|host| hostname(&host)) {
+        Some(hostname) if hostname != appstate.config.opts.domain => {
+            find_drive(&appstate.store, &hostname).unwrap_or_else(|| server_url.to_string())
+        }
+        _ => server_url.to_string(),
+    }
+}
+
+/// Requests certificates for new hostnames and renews expiring ones, and keeps doing so while the server runs.
+#[cfg(feature = "https")]
+pub fn spawn_certificate_manager(
+    appstate: AppState,
+    resolver: std::sync::Arc<crate::https::CertResolver>,
+) {
+    actix_web::rt::spawn(async move {
+        loop {
+            for hostname in hostnames(&appstate.store) {
+                let config = &appstate.config;
+                let result = match crate::https::request_domain_cert(config, &hostname).await {
+                    Ok(()) => resolver.load_domain(config, &hostname),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::error!("HTTPS certificate for {} failed: {}", hostname, e);
+                }
+            }
+            actix_web::rt::time::sleep(CERT_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atomic_lib::Value;
+
+    #[test]
+    fn parses_hostname() {
+        assert_eq!(hostname("Blog.Example.org:8080"), "blog.example.org");
+        assert_eq!(hostname("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn finds_mapped_drive() {
+        let store = atomic_lib::Db::init_temp("domains").unwrap();
+        let agent = store.create_agent(Some("owner")).unwrap();
+        store.set_default_agent(agent.clone());
+        let drive_subject = format!("{}/drives/blog", store.get_server_url());
+        let mut drive = Resource::new(drive_subject.clone());
+        drive.set_propval_unsafe(urls::IS_A.into(), vec![urls::DRIVE.to_string()].into());
+        drive.set_propval_unsafe(urls::WRITE.into(), vec![agent.subject.clone()].into());
+        drive.save_locally(&store).unwrap();
+
+        let mut mapping = Resource::new_instance(urls::DOMAIN_MAPPING, &store).unwrap();
+        mapping.set_propval_unsafe(
+            urls::HOSTNAME.into(),
+            Value::String("blog.example.org".into()),
+        );
+        mapping.set_propval_unsafe(
+            urls::MAPPED_DRIVE.into(),
+            Value::AtomicUrl(drive_subject.clone()),
+        );
+        mapping.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive_subject.clone()));
+        mapping.save_locally(&store).unwrap();
+
+        assert_eq!(
+            find_drive(&store, "blog.example.org").unwrap(),
+            drive_subject
+        );
+        assert!(find_drive(&store, "other.example.org").is_none());
+        assert_eq!(hostnames(&store), vec!["blog.example.org".to_string()]);
+    }
+}
@@ -1,6 +1,5 @@
 use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
 use atomic_lib::{parse::JSON_AD_MIME, urls, Resource, Value};
-use serde::Serialize;
 use std::error::Error;
 
 // More strict Result type
@@ -10,7 +9,17 @@ pub type AtomicServerResult<T> = std::result::Result<T, AtomicServerError>;
 pub enum AppErrorType {
     NotFound,
     Unauthorized,
+    /// The signature, session cookie or API token of the request is invalid. Counted by [crate::lockout].
+    AuthenticationFailed,
     MethodNotAllowed,
+    /// The Drive has used up its quota, see [atomic_lib::plugins::quotas].
+    QuotaExceeded,
+    /// The IP address of the client is not allowed, see [crate::ip_access].
+    Forbidden,
+    /// The client has exceeded its rate limit, and can retry after this amount of seconds.
+    TooManyRequests {
+        retry_after: u64,
+    },
     Other,
 }
 
@@ -22,7 +31,15 @@ pub struct AtomicServerError {
     pub error_resource: Option<Box<Resource>>,
 }
 
-impl AtomicServerError {}
+impl AtomicServerError {
+    pub fn authentication_failed(message: String) -> AtomicServerError {
+        AtomicServerError {
+            message,
+            error_type: AppErrorType::AuthenticationFailed,
+            error_resource: None,
+        }
+    }
+}
 
 impl std::fmt::Debug for AtomicServerError {
     // The derive impl is too verbose, as it includes the full `error_resource`.
@@ -31,11 +48,6 @@ impl std::fmt::Debug for AtomicServerError {
     }
 }
 
-#[derive(Serialize)]
-pub struct AppErrorResponse {
-    pub error: String,
-}
-
 impl Error for AtomicServerError {}
 
 impl ResponseError for AtomicServerError {
@@ -43,8 +55,12 @@ impl ResponseError for AtomicServerError {
         match self.error_type {
             AppErrorType::NotFound => StatusCode::NOT_FOUND,
             AppErrorType::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppErrorType::QuotaExceeded | AppErrorType::Forbidden => StatusCode::FORBIDDEN,
             AppErrorType::Other => StatusCode::INTERNAL_SERVER_ERROR,
-            AppErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppErrorType::Unauthorized | AppErrorType::AuthenticationFailed => {
+                StatusCode::UNAUTHORIZED
+            }
+            AppErrorType::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
         }
     }
     fn error_response(&self) -> HttpResponse {
@@ -64,9 +80,11 @@ impl ResponseError for AtomicServerError {
 
         let body = r.to_json_ad().unwrap();
         tracing::info!("Error response: {}", self.message);
-        HttpResponse::build(self.status_code())
-            .content_type(JSON_AD_MIME)
-            .body(body)
+        let mut builder = HttpResponse::build(self.status_code());
+        if let AppErrorType::TooManyRequests { retry_after } = self.error_type {
+            builder.insert_header(("Retry-After", retry_after));
+        }
+        builder.content_type(JSON_AD_MIME).body(body)
     }
 }
 
@@ -85,6 +103,7 @@ impl From<atomic_lib::errors::AtomicError> for AtomicServerError {
             atomic_lib::AtomicErrorType::NotFoundError => AppErrorType::NotFound,
             atomic_lib::AtomicErrorType::UnauthorizedError => AppErrorType::Unauthorized,
             atomic_lib::AtomicErrorType::MethodNotAllowed => AppErrorType::MethodNotAllowed,
+            atomic_lib::AtomicErrorType::QuotaExceeded => AppErrorType::QuotaExceeded,
             atomic_lib::AtomicErrorType::ParseError => AppErrorType::Other,
             atomic_lib::AtomicErrorType::OtherError => AppErrorType::Other,
         };
@@ -0,0 +1,210 @@
+//! Renders Collections as RSS and Atom feeds, so feed readers can subscribe to them.
+//! Requested by adding `.rss` or `.atom` to the URL of a Collection, see [crate::helpers::try_extension].
+//! Members use their `name` as title, their `description` as summary,
+//! and their `published-at`, `createdAt` or the date of their last Commit as date.
+
+use atomic_lib::{urls, values::SubResource, Resource, Storelike, Value};
+
+use crate::errors::AtomicServerResult;
+
+/// The length of titles that are taken from the description, for members without a name.
+const TITLE_LENGTH: usize = 80;
+
+/// Whether the path asks for a feed, which browsers should get instead of the single page app.
+pub fn is_feed_path(path: &str) -> bool {
+    path.ends_with(".rss") || path.ends_with(".atom")
+}
+
+struct FeedItem {
+    subject: String,
+    title: String,
+    summary: Option<String>,
+    /// Milliseconds since unix epoch
+    date: i64,
+}
+
+fn string_prop(resource: &Resource, property: &str) -> Option<String> {
+    resource.get(property).ok().map(|v| v.to_string())
+}
+
+fn timestamp_prop(resource: &Resource, property: &str) -> Option<i64> {
+    match resource.get(property) {
+        Ok(Value::Timestamp(ts)) | Ok(Value::Integer(ts)) => Some(*ts),
+        _ => None,
+    }
+}
+
+fn item_date(store: &impl Storelike, resource: &Resource) -> i64 {
+    timestamp_prop(resource, urls::PUBLISHED_AT)
+        .or_else(|| timestamp_prop(resource, urls::CREATED_AT))
+        .or_else(|| {
+            let last_commit = string_prop(resource, urls::LAST_COMMIT)?;
+            timestamp_prop(&store.get_resource(&last_commit).ok()?, urls::CREATED_AT)
+        })
+        .unwrap_or_default()
+}
+
+fn to_item(store: &impl Storelike, resource: &Resource) -> FeedItem {
+    let summary = string_prop(resource, urls::DESCRIPTION);
+    let title = string_prop(resource, urls::NAME).unwrap_or_else(|| match &summary {
+        Some(description) => description.chars().take(TITLE_LENGTH).collect(),
+        None => resource.get_subject().to_string(),
+    });
+    FeedItem {
+        subject: resource.get_subject().to_string(),
+        title,
+        summary,
+        date: item_date(store, resource),
+    }
+}
+
+/// The members of the Collection that the Agent can read, newest first.
+fn items(
+    store: &impl Storelike,
+    collection: &Resource,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<Vec<FeedItem>> {
+    let is_collection = collection
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == urls::COLLECTION));
+    if !is_collection {
+        return Err(format!(
+            "{} is not a Collection. Feeds are only available for Collections.",
+            collection.get_subject()
+        )
+        .into());
+    }
+    let members = match collection.get(urls::COLLECTION_MEMBERS) {
+        Ok(Value::ResourceArray(members)) => members.clone(),
+        _ => Vec::new(),
+    };
+    let mut items: Vec<FeedItem> = members
+        .iter()
+        .filter_map(|member| match member {
+            SubResource::Subject(subject) => store
+                .get_resource_extended(subject, true, for_agent)
+                .ok()
+                .map(|resource| to_item(store, &resource)),
+            SubResource::Resource(resource) => Some(to_item(store, resource)),
+            SubResource::Nested(_) => None,
+        })
+        .collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.date));
+    Ok(items)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn datetime(millis: i64) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::<chrono::Utc>::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+fn feed_title(collection: &Resource) -> String {
+    string_prop(collection, urls::NAME)
+        .or_else(|| string_prop(collection, urls::DESCRIPTION))
+        .unwrap_or_else(|| collection.get_subject().to_string())
+}
+
+/// Renders the Collection as an RSS 2.0 feed.
+pub fn to_rss(
+    store: &impl Storelike,
+    collection: &Resource,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<String> {
+    let subject = escape(collection.get_subject());
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n<title>{}</title>\n<link>{}</link>\n<description>{}</description>\n",
+        escape(&feed_title(collection)),
+        subject,
+        escape(&string_prop(collection, urls::DESCRIPTION).unwrap_or_default()),
+    );
+    for item in items(store, collection, for_agent)? {
+        let subject = escape(&item.subject);
+        out.push_str(&format!(
+            "<item>\n<title>{}</title>\n<link>{}</link>\n<guid>{}</guid>\n<pubDate>{}</pubDate>\n",
+            escape(&item.title),
+            subject,
+            subject,
+            datetime(item.date).to_rfc2822(),
+        ));
+        if let Some(summary) = item.summary {
+            out.push_str(&format!(
+                "<description>{}</description>\n",
+                escape(&summary)
+            ));
+        }
+        out.push_str("</item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    Ok(out)
+}
+
+/// Renders the Collection as an Atom feed.
+pub fn to_atom(
+    store: &impl Storelike,
+    collection: &Resource,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<String> {
+    let items = items(store, collection, for_agent)?;
+    let subject = escape(collection.get_subject());
+    let updated = items.first().map(|item| item.date).unwrap_or_default();
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n<title>{}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<updated>{}</updated>\n",
+        escape(&feed_title(collection)),
+        subject,
+        subject,
+        datetime(updated).to_rfc3339(),
+    );
+    for item in items {
+        let subject = escape(&item.subject);
+        out.push_str(&format!(
+            "<entry>\n<title>{}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<updated>{}</updated>\n",
+            escape(&item.title),
+            subject,
+            subject,
+            datetime(item.date).to_rfc3339(),
+        ));
+        if let Some(summary) = item.summary {
+            out.push_str(&format!("<summary>{}</summary>\n", escape(&summary)));
+        }
+        out.push_str("</entry>\n");
+    }
+    out.push_str("</feed>\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_feeds() {
+        let store = atomic_lib::Db::init_temp("feeds").unwrap();
+        let server_url = store.get_server_url().to_string();
+        let mut subjects = Vec::new();
+        for (name, published) in [("Older <post>", 1_000), ("Newer post", 2_000)] {
+            let mut post = Resource::new(format!("{}/posts/{}", server_url, published));
+            post.set_propval_unsafe(urls::NAME.into(), Value::String(name.into()));
+            post.set_propval_unsafe(urls::PUBLISHED_AT.into(), Value::Timestamp(published));
+            store.add_resource(&post).unwrap();
+            subjects.push(post.get_subject().to_string());
+        }
+        let mut collection = Resource::new(format!("{}/posts", server_url));
+        collection.set_propval_unsafe(urls::IS_A.into(), vec![urls::COLLECTION.to_string()].into());
+        collection.set_propval_unsafe(urls::COLLECTION_MEMBERS.into(), subjects.into());
+
+        let rss = to_rss(&store, &collection, None).unwrap();
+        assert!(rss.find("Newer post").unwrap() < rss.find("Older &lt;post&gt;").unwrap());
+        let atom = to_atom(&store, &collection, None).unwrap();
+        assert!(atom.contains("<updated>1970-01-01T00:00:02+00:00</updated>"));
+
+        to_rss(&store, &Resource::new(server_url), None).unwrap_err();
+    }
+}
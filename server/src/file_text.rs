@@ -0,0 +1,294 @@
+//! Extracts the text of uploaded files, so their contents can be found using search.
+//! Supports plain text, PDF and docx files. Only enabled with the `file-text` feature.
+//! The text is stored in the `fileText` property of the File, which is indexed by [crate::search].
+
+use std::{io::Read, path::Path};
+
+use crate::errors::AtomicServerResult;
+
+/// Longer texts are cut off, to keep File resources and the search index small.
+pub const MAX_TEXT_CHARS: usize = 100_000;
+
+/// Returns the text of the file, or None if its MIME type is not supported.
+pub fn extract(path: &Path, mimetype: &str) -> AtomicServerResult<Option<String>> {
+    let mimetype = mimetype.split(';').next().unwrap_or_default().trim();
+    let text = match mimetype {
+        "application/pdf" => pdf_text(&std::fs::read(path)?),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+            docx_text(path)?
+        }
+        "application/json" | "application/xml" | "application/ad+json" => {
+            String::from_utf8_lossy(&std::fs::read(path)?).into_owned()
+        }
+        text if text.starts_with("text/") => {
+            String::from_utf8_lossy(&std::fs::read(path)?).into_owned()
+        }
+        _other => return Ok(None),
+    };
+    let text = normalize_whitespace(&text);
+    if text.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(text.chars().take(MAX_TEXT_CHARS).collect()))
+}
+
+/// Collapses runs of spaces, and removes empty lines.
+fn normalize_whitespace(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Reads the paragraphs of `word/document.xml` in the docx archive.
+fn docx_text(path: &Path) -> AtomicServerResult<String> {
+    use quick_xml::events::Event;
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)
+        .map_err(|e| format!("Not a valid docx file: {}", e))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| format!("Not a valid docx file: {}", e))?
+        .read_to_string(&mut xml)?;
+
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_text = false;
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| format!("Failed to read docx: {}", e))?
+        {
+            Event::Start(e) if e.name().as_ref() == b"w:t" => in_text = true,
+            Event::End(e) if e.name().as_ref() == b"w:t" => in_text = false,
+            Event::End(e) if e.name().as_ref() == b"w:p" => text.push('\n'),
+            Event::Empty(e) if matches!(e.name().as_ref(), b"w:tab" | b"w:br") => text.push(' '),
+            Event::Text(t) if in_text => {
+                let unescaped = t
+                    .unescape()
+                    .map_err(|e| format!("Failed to read docx: {}", e))?;
+                text.push_str(&unescaped);
+            }
+            Event::Eof => break,
+            _other => {}
+        }
+    }
+    Ok(text)
+}
+
+/// Finds the text in the content streams of a PDF.
+/// This is a simple extractor: it reads uncompressed and Flate compressed streams,
+/// and the literal strings that are shown using the `Tj`, `TJ`, `'` and `"` operators.
+/// Text in fonts that need a `ToUnicode` map (mostly CJK and subsetted fonts) is skipped.
+fn pdf_text(data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut rest = data;
+    while let Some(start) = find(rest, b"stream") {
+        let dictionary = &rest[..start];
+        let mut content_start = start + b"stream".len();
+        // The stream keyword is followed by CRLF or LF
+        if rest.get(content_start) == Some(&b'\r') {
+            content_start += 1;
+        }
+        if rest.get(content_start) == Some(&b'\n') {
+            content_start += 1;
+        }
+        let Some(length) = find(&rest[content_start..], b"endstream") else {
+            break;
+        };
+        let raw = &rest[content_start..content_start + length];
+        // Only the dictionary of this stream, which comes after the previous `endobj`
+        let dictionary = match find_last(dictionary, b"obj") {
+            Some(obj) => &dictionary[obj..],
+            None => dictionary,
+        };
+        if find(dictionary, b"/FlateDecode").is_some() {
+            let mut inflated = Vec::new();
+            if flate2::read::ZlibDecoder::new(raw)
+                .read_to_end(&mut inflated)
+                .is_ok()
+            {
+                text.push_str(&content_stream_text(&inflated));
+            }
+        } else if find(dictionary, b"/Filter").is_none() {
+            text.push_str(&content_stream_text(raw));
+        }
+        rest = &rest[content_start + length + b"endstream".len()..];
+    }
+    text
+}
+
+/// Reads the strings that are shown in a content stream. Text objects (`BT` ... `ET`) end with a newline.
+fn content_stream_text(stream: &[u8]) -> String {
+    let mut text = String::new();
+    let mut line = String::new();
+    let mut i = 0;
+    while i < stream.len() {
+        match stream[i] {
+            b'(' => {
+                let (string, end) = read_literal_string(stream, i + 1);
+                line.push_str(&string);
+                i = end;
+            }
+            // Large negative offsets in TJ arrays are used as spaces between words
+            b'-' if stream.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let end = stream[i + 1..]
+                    .iter()
+                    .position(|c| !c.is_ascii_digit() && *c != b'.')
+                    .map_or(stream.len(), |p| i + 1 + p);
+                let offset: f32 = std::str::from_utf8(&stream[i + 1..end])
+                    .ok()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_default();
+                if offset > 200.0 && !line.ends_with(' ') {
+                    line.push(' ');
+                }
+                i = end;
+            }
+            b'E' if stream.get(i + 1) == Some(&b'T') => {
+                text.push_str(line.trim());
+                text.push('\n');
+                line.clear();
+                i += 2;
+            }
+            b'T' if matches!(stream.get(i + 1), Some(b'*') | Some(b'd') | Some(b'D')) => {
+                if !line.is_empty() && !line.ends_with(' ') {
+                    line.push(' ');
+                }
+                i += 2;
+            }
+            _other => i += 1,
+        }
+    }
+    text.push_str(line.trim());
+    text
+}
+
+/// Reads a PDF literal string, starting after its opening parenthesis.
+/// Returns the string and the position after its closing parenthesis.
+fn read_literal_string(stream: &[u8], start: usize) -> (String, usize) {
+    let mut bytes = Vec::new();
+    let mut depth = 0;
+    let mut i = start;
+    while i < stream.len() {
+        match stream[i] {
+            b'\\' => {
+                i += 1;
+                match stream.get(i) {
+                    Some(b'n') => bytes.push(b'\n'),
+                    Some(b'r') => bytes.push(b'\r'),
+                    Some(b't') => bytes.push(b'\t'),
+                    Some(b'b') | Some(b'f') => {}
+                    Some(c @ b'0'..=b'7') => {
+                        let digits = stream[i..]
+                            .iter()
+                            .take(3)
+                            .take_while(|c| (b'0'..=b'7').contains(c))
+                            .count();
+                        let octal = std::str::from_utf8(&stream[i..i + digits]).unwrap_or("0");
+                        bytes.push(u8::from_str_radix(octal, 8).unwrap_or(*c));
+                        i += digits - 1;
+                    }
+                    // Line continuation
+                    Some(b'\n') => {}
+                    Some(c) => bytes.push(*c),
+                    None => {}
+                }
+            }
+            b'(' => {
+                depth += 1;
+                bytes.push(b'(');
+            }
+            b')' if depth == 0 => return (decode_pdf_string(&bytes), i + 1),
+            b')' => {
+                depth -= 1;
+                bytes.push(b')');
+            }
+            c => bytes.push(c),
+        }
+        i += 1;
+    }
+    (decode_pdf_string(&bytes), i)
+}
+
+/// PDF strings are either UTF-16BE (with a byte order mark) or use a Latin-1 like encoding.
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn find_last(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn reads_pdf_text() {
+        let content =
+            b"BT /F1 12 Tf 72 712 Td (Quarterly report) Tj ET\nBT [(Atomic)-300(Data)] TJ ET";
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut pdf =
+            b"%PDF-1.4\n4 0 obj\n<< /Length 10 /Filter /FlateDecode >>\nstream\n".to_vec();
+        pdf.extend_from_slice(&compressed);
+        pdf.extend_from_slice(b"\nendstream\nendobj\n5 0 obj\n<< /Length 20 >>\nstream\nBT (Escaped \\(text\\)) Tj ET\nendstream\nendobj\n");
+
+        let text = normalize_whitespace(&pdf_text(&pdf));
+        assert_eq!(text, "Quarterly report\nAtomic Data\nEscaped (text)");
+    }
+
+    #[test]
+    fn reads_docx_text() {
+        let path = std::env::temp_dir().join(format!(
+            "atomic-file-text-{}.docx",
+            atomic_lib::utils::random_string(6)
+        ));
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        writer
+            .start_file("word/document.xml", zip::write::FileOptions::default())
+            .unwrap();
+        writer
+            .write_all(br#"<w:document><w:body><w:p><w:r><w:t>Meeting &amp; notes</w:t></w:r></w:p><w:p><w:r><w:t>Second</w:t><w:tab/><w:t>line</w:t></w:r></w:p></w:body></w:document>"#)
+            .unwrap();
+        writer.finish().unwrap();
+
+        let text = extract(
+            &path,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text.as_deref(), Some("Meeting & notes\nSecond line"));
+    }
+
+    #[test]
+    fn skips_unsupported_files() {
+        let path = std::env::temp_dir().join("atomic-file-text-unsupported.png");
+        std::fs::write(&path, [0u8, 1, 2]).unwrap();
+        assert_eq!(extract(&path, "image/png").unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Storage for uploaded files.
+//! By default, files are stored in the `uploads_path` on the local file system.
+//! When an S3 bucket is configured, files are stored in that (S3 compatible) object store instead,
+//! and downloads are redirected to presigned URLs.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use actix_files::NamedFile;
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+/// Where the binary contents of File resources are persisted.
+#[derive(Clone, Debug)]
+pub enum FileStore {
+    /// Files are stored in a folder on the local file system.
+    FS(FSConfig),
+    /// Files are stored in an S3 compatible bucket.
+    S3(S3Config),
+}
+
+#[derive(Clone, Debug)]
+pub struct FSConfig {
+    /// Folder where the files are stored.
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    pub bucket: String,
+    /// Prefix inside the bucket, under which all files are stored.
+    pub path: String,
+    pub region: Option<String>,
+    /// Custom endpoint, for S3 compatible services other than AWS.
+    pub endpoint: Option<String>,
+    /// If no credentials are passed, the default AWS environment variables and profiles are used.
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// How long presigned download URLs stay valid.
+    pub presign_expiry: Duration,
+}
+
+impl FileStore {
+    /// Uses the S3 bucket from the config if one is set, otherwise the local `uploads_path`.
+    pub fn init_from_config(config: &Config) -> FileStore {
+        let opts = &config.opts;
+        if let Some(bucket) = &opts.s3_bucket {
+            FileStore::S3(S3Config {
+                bucket: bucket.clone(),
+                path: opts.s3_path.clone(),
+                region: opts.s3_region.clone(),
+                endpoint: opts.s3_endpoint.clone(),
+                access_key_id: opts.s3_access_key_id.clone(),
+                secret_access_key: opts.s3_secret_access_key.clone(),
+                presign_expiry: Duration::from_secs(opts.s3_presign_expiry),
+            })
+        } else {
+            FileStore::FS(FSConfig {
+                path: config.uploads_path.clone(),
+            })
+        }
+    }
+
+    /// Persists a file that has been uploaded to `local_path`.
+    /// For the local file system this is a no-op, as uploads are written to the `uploads_path` directly.
+    /// For S3, the file is sent to the bucket and the local copy is removed.
+    pub async fn upload_file(&self, file_id: &str, local_path: &Path) -> AtomicServerResult<()> {
+        match self {
+            FileStore::FS(_) => Ok(()),
+            FileStore::S3(config) => {
+                let bytes = std::fs::read(local_path)?;
+                s3_operator(config)?
+                    .write(file_id, bytes)
+                    .await
+                    .map_err(|e| format!("Failed to upload file {} to S3: {}", file_id, e))?;
+                std::fs::remove_file(local_path)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Responds with the contents of the file, or redirects to a presigned URL.
+    /// Make sure the rights have been checked before calling this.
+    pub async fn download_response(
+        &self,
+        file_id: &str,
+        req: &HttpRequest,
+    ) -> AtomicServerResult<HttpResponse> {
+        match self {
+            FileStore::FS(config) => {
+                let mut file_path = config.path.clone();
+                file_path.push(file_id);
+                let file = NamedFile::open(file_path)?;
+                Ok(file.into_response(req))
+            }
+            FileStore::S3(config) => {
+                let presigned = s3_operator(config)?
+                    .presign_read(file_id, config.presign_expiry)
+                    .await
+                    .map_err(|e| format!("Failed to create download URL for {}: {}", file_id, e))?;
+                Ok(HttpResponse::TemporaryRedirect()
+                    .append_header(("Location", presigned.uri().to_string()))
+                    .finish())
+            }
+        }
+    }
+
+    /// Reads the contents of a file, e.g. to include it in an export.
+    pub async fn read_file(&self, file_id: &str) -> AtomicServerResult<Vec<u8>> {
+        match self {
+            FileStore::FS(config) => Ok(std::fs::read(config.path.join(file_id))?),
+            FileStore::S3(config) => Ok(s3_operator(config)?
+                .read(file_id)
+                .await
+                .map_err(|e| format!("Failed to read file {} from S3: {}", file_id, e))?),
+        }
+    }
+
+    /// Removes a file, after its File resource has been removed.
+    pub async fn delete_file(&self, file_id: &str) -> AtomicServerResult<()> {
+        match self {
+            FileStore::FS(config) => {
+                let file_path = config.path.join(file_id);
+                if file_path.exists() {
+                    std::fs::remove_file(file_path)?;
+                }
+                Ok(())
+            }
+            FileStore::S3(config) => Ok(s3_operator(config)?
+                .delete(file_id)
+                .await
+                .map_err(|e| format!("Failed to delete file {} from S3: {}", file_id, e))?),
+        }
+    }
+}
+
+fn s3_operator(config: &S3Config) -> AtomicServerResult<opendal::Operator> {
+    let mut builder = opendal::services::S3::default();
+    builder.bucket(&config.bucket);
+    builder.root(&config.path);
+    if let Some(region) = &config.region {
+        builder.region(region);
+    }
+    if let Some(endpoint) = &config.endpoint {
+        builder.endpoint(endpoint);
+    }
+    if let Some(key) = &config.access_key_id {
+        builder.access_key_id(key);
+    }
+    if let Some(secret) = &config.secret_access_key {
+        builder.secret_access_key(secret);
+    }
+    let operator = opendal::Operator::new(builder)
+        .map_err(|e| format!("Invalid S3 configuration: {}", e))?
+        .finish();
+    Ok(operator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s3_config() -> S3Config {
+        S3Config {
+            bucket: "atomic-test".into(),
+            path: "uploads".into(),
+            region: Some("eu-west-1".into()),
+            endpoint: Some("https://s3.example.com".into()),
+            access_key_id: Some("key".into()),
+            secret_access_key: Some("secret".into()),
+            presign_expiry: Duration::from_secs(60),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn s3_download_redirects_to_presigned_url() {
+        let store = FileStore::S3(s3_config());
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        let resp = store.download_response("my-file.txt", &req).await.unwrap();
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::TEMPORARY_REDIRECT
+        );
+        let location = resp.headers().get("Location").unwrap().to_str().unwrap();
+        assert!(location.starts_with("https://s3.example.com/atomic-test/uploads/my-file.txt"));
+        assert!(location.contains("X-Amz-Signature"));
+    }
+}
@@ -0,0 +1,126 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{AtomicError, Resource};
+use serde::Deserialize;
+
+use crate::{
+    activitypub::{actor_id, find_actor, Federation, MIME_ACTIVITY},
+    appstate::AppState,
+    errors::AtomicServerResult,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct WebFingerQuery {
+    /// For example `acct:news@example.com`
+    pub resource: String,
+}
+
+fn get_federation(appstate: &AppState) -> AtomicServerResult<std::sync::Arc<Federation>> {
+    Ok(appstate.federation.clone().ok_or_else(|| {
+        AtomicError::not_found("ActivityPub is not enabled on this server.".into())
+    })?)
+}
+
+fn get_actor(appstate: &AppState, handle: &str) -> AtomicServerResult<Resource> {
+    Ok(find_actor(&appstate.store, handle)?
+        .ok_or_else(|| AtomicError::not_found(format!("No actor with handle {}", handle)))?)
+}
+
+fn activity_response(body: serde_json::Value) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(MIME_ACTIVITY)
+        .body(body.to_string())
+}
+
+/// Resolves `acct:{handle}@{host}` to the actor, so users can search for `@{handle}@{host}` in Mastodon.
+#[tracing::instrument(skip(appstate))]
+pub async fn webfinger(
+    appstate: web::Data<AppState>,
+    query: web::Query<WebFingerQuery>,
+) -> AtomicServerResult<HttpResponse> {
+    get_federation(&appstate)?;
+    let not_found = || AtomicError::not_found(format!("Unknown resource {}", query.resource));
+    let (handle, host) = query
+        .resource
+        .strip_prefix("acct:")
+        .and_then(|acct| acct.split_once('@'))
+        .ok_or_else(not_found)?;
+    let server_url = &appstate.config.server_url;
+    if host != appstate.config.opts.domain && !server_url.ends_with(&format!("//{}", host)) {
+        return Err(not_found().into());
+    }
+    get_actor(&appstate, handle)?;
+    let body = serde_json::json!({
+        "subject": query.resource,
+        "links": [{
+            "rel": "self",
+            "type": MIME_ACTIVITY,
+            "href": actor_id(server_url, handle),
+        }],
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/jrd+json")
+        .body(body.to_string()))
+}
+
+#[tracing::instrument(skip(appstate))]
+pub async fn actor(
+    appstate: web::Data<AppState>,
+    path: web::Path<String>,
+) -> AtomicServerResult<HttpResponse> {
+    let federation = get_federation(&appstate)?;
+    let handle = path.into_inner();
+    let resource = get_actor(&appstate, &handle)?;
+    Ok(activity_response(federation.actor_json(&resource, &handle)))
+}
+
+#[tracing::instrument(skip(appstate))]
+pub async fn outbox(
+    appstate: web::Data<AppState>,
+    path: web::Path<String>,
+) -> AtomicServerResult<HttpResponse> {
+    let federation = get_federation(&appstate)?;
+    let handle = path.into_inner();
+    get_actor(&appstate, &handle)?;
+    let items = federation.outbox(&handle)?;
+    Ok(activity_response(serde_json::json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}/outbox", actor_id(&appstate.config.server_url, &handle)),
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    })))
+}
+
+/// Only shows the amount of followers, not who they are.
+#[tracing::instrument(skip(appstate))]
+pub async fn followers(
+    appstate: web::Data<AppState>,
+    path: web::Path<String>,
+) -> AtomicServerResult<HttpResponse> {
+    let federation = get_federation(&appstate)?;
+    let handle = path.into_inner();
+    get_actor(&appstate, &handle)?;
+    Ok(activity_response(serde_json::json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}/followers", actor_id(&appstate.config.server_url, &handle)),
+        "type": "OrderedCollection",
+        "totalItems": federation.followers(&handle)?.len(),
+    })))
+}
+
+#[tracing::instrument(skip(appstate, body))]
+pub async fn inbox(
+    appstate: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> AtomicServerResult<HttpResponse> {
+    let federation = get_federation(&appstate)?;
+    let handle = path.into_inner();
+    get_actor(&appstate, &handle)?;
+    let activity: serde_json::Value =
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid activity: {}", e))?;
+    web::block(move || federation.receive(&handle, &activity))
+        .await
+        .map_err(|e| format!("Failed to handle activity: {}", e))??;
+    Ok(HttpResponse::Accepted().finish())
+}
@@ -0,0 +1,75 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{urls, AtomicError};
+use serde::Deserialize;
+
+use crate::{
+    api_tokens::TokenScope,
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::{get_bearer_token, get_client_agent},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct MintRequest {
+    pub name: Option<String>,
+    /// Required if the Agent has enabled TOTP
+    pub totp: Option<String>,
+    #[serde(flatten)]
+    pub scope: TokenScope,
+}
+
+/// Returns the Agent that signed the request.
+/// Tokens can't be used to manage tokens, so a leaked token can't be used to create more.
+fn signed_agent(appstate: &AppState, req: &actix_web::HttpRequest) -> AtomicServerResult<String> {
+    if get_bearer_token(req.headers())?.is_some() {
+        return Err(AtomicError::unauthorized(
+            "API tokens can not be used to manage API tokens. Sign the request instead.".into(),
+        )
+        .into());
+    }
+    let subject = crate::helpers::request_url(req, &appstate.config);
+    match get_client_agent(req.headers(), appstate, subject)? {
+        Some(agent) if agent != urls::PUBLIC_AGENT => Ok(agent),
+        _ => Err(AtomicError::unauthorized("Sign in to manage your API tokens.".into()).into()),
+    }
+}
+
+/// Lists the tokens of the Agent. Does not include their values.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn list_tokens(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    Ok(HttpResponse::Ok().json(appstate.api_tokens.list(&agent)?))
+}
+
+/// Creates a token for the Agent. The value is only returned once.
+#[tracing::instrument(skip(appstate, req, body))]
+pub async fn mint_token(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Json<MintRequest>,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    let MintRequest { name, totp, scope } = body.into_inner();
+    appstate
+        .totp
+        .require(&appstate.store, &agent, totp.as_deref())?;
+    let (value, token) = appstate.api_tokens.mint(&agent, name, scope)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token": value,
+        "info": token,
+    })))
+}
+
+#[tracing::instrument(skip(appstate, req))]
+pub async fn revoke_token(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    appstate.api_tokens.revoke(&agent, &path.into_inner())?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,122 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    hierarchy::check_write, parse::JSON_AD_MIME, resources::PropVals, urls, values::SubResource,
+    AtomicError, Resource, Storelike, Value,
+};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    audit_log::{AuditEntry, AuditEvent, AuditFilter},
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct AuditLogQuery {
+    /// `commit` or `auth_failure`
+    pub event: Option<AuditEvent>,
+    pub agent: Option<String>,
+    /// Prefix of the subjects
+    pub subject: Option<String>,
+    /// Timestamp of the last entry of the previous page
+    pub before: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+/// Lists the newest entries of the audit log, as a Collection of nested resources.
+/// Only Agents with write rights to the Drive can read the audit log.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn handle_audit_log(
+    appstate: web::Data<AppState>,
+    query: web::Query<AuditLogQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let mut subject = crate::helpers::request_url(&req, &appstate.config);
+    if !req.query_string().is_empty() {
+        subject = format!("{}?{}", subject, req.query_string());
+    }
+    let drive = store.get_resource(store.get_server_url())?;
+    match get_client_agent(req.headers(), &appstate, subject.clone())? {
+        Some(agent) => {
+            check_write(store, &drive, &agent)?;
+        }
+        None => {
+            return Err(AtomicError::unauthorized(
+                "The audit log is only available to signed in admins.".into(),
+            )
+            .into())
+        }
+    }
+
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let filter = AuditFilter {
+        event: query.event,
+        agent: query.agent,
+        subject: query.subject,
+        before: query.before,
+    };
+    let entries = appstate.audit_log.read(&filter, limit)?;
+
+    let mut resource = Resource::new(subject.clone());
+    resource.set_class(urls::COLLECTION);
+    resource.set_propval_string(urls::NAME.into(), "Audit log", store)?;
+    if entries.len() == limit {
+        if let Some(last) = entries.last() {
+            let mut params: Vec<&str> = req
+                .query_string()
+                .split('&')
+                .filter(|pair| !pair.is_empty() && !pair.starts_with("before="))
+                .collect();
+            let before = format!("before={}", last.timestamp);
+            params.push(&before);
+            let next_page = format!(
+                "{}?{}",
+                crate::helpers::request_url(&req, &appstate.config),
+                params.join("&")
+            );
+            resource.set_propval_unsafe(urls::NEXT_PAGE.into(), Value::AtomicUrl(next_page));
+        }
+    }
+    let members: Vec<SubResource> = entries.iter().map(entry_to_propvals).collect();
+    resource.set_propval_unsafe(
+        urls::COLLECTION_MEMBERS.into(),
+        Value::ResourceArray(members),
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(resource.to_json_ad()?))
+}
+
+fn entry_to_propvals(entry: &AuditEntry) -> SubResource {
+    let mut propvals = PropVals::new();
+    propvals.insert(urls::CREATED_AT.into(), Value::Timestamp(entry.timestamp));
+    let event = match entry.event {
+        AuditEvent::Commit => "commit",
+        AuditEvent::AuthFailure => "auth_failure",
+        AuditEvent::AuthLockout => "auth_lockout",
+    };
+    propvals.insert(urls::AUDIT_EVENT.into(), Value::String(event.into()));
+    let optional = [
+        (urls::SIGNER, &entry.agent),
+        (urls::SUBJECT, &entry.subject),
+        (urls::AUDIT_IP, &entry.ip),
+        (urls::AUDIT_USER_AGENT, &entry.user_agent),
+    ];
+    for (property, value) in optional {
+        if let Some(value) = value {
+            propvals.insert(property.into(), Value::String(value.clone()));
+        }
+    }
+    propvals.insert(
+        urls::AUDIT_OUTCOME.into(),
+        Value::String(entry.outcome.clone()),
+    );
+    SubResource::Nested(propvals)
+}
@@ -1,17 +1,136 @@
-use crate::{appstate::AppState, errors::AtomicServerResult};
-use actix_web::{web, HttpResponse};
-use atomic_lib::{commit::CommitOpts, parse::parse_json_ad_commit_resource, Commit, Storelike};
+use crate::{
+    api_version::{add_deprecation_headers, ApiVersion},
+    appstate::AppState,
+    audit_log::{self, AuditEntry, AuditEvent},
+    errors::AtomicServerResult,
+    rate_limit::Category,
+};
+use actix_web::{http::StatusCode, web, HttpResponse};
+use atomic_lib::{
+    commit::{CommitOpts, CommitResponse},
+    parse::parse_json_ad_commit_resource,
+    urls, Commit, Resource, Storelike,
+};
+use serde::{Deserialize, Serialize};
+
+/// The maximum amount of Commits in a single bulk request.
+const MAX_BULK_COMMITS: usize = 1000;
 
 /// Send and process a Commit.
 /// Currently only accepts JSON-AD
-#[tracing::instrument(skip(appstate))]
+/// The `previousCommit` is only validated from API version 2 onwards.
+#[tracing::instrument(skip(appstate, req))]
 pub async fn post_commit(
     appstate: web::Data<AppState>,
+    version: ApiVersion,
     body: String,
+    req: actix_web::HttpRequest,
 ) -> AtomicServerResult<HttpResponse> {
-    let store = &appstate.store;
     let mut builder = HttpResponse::Ok();
-    let incoming_commit_resource = parse_json_ad_commit_resource(&body, store)?;
+    add_deprecation_headers(&mut builder, version);
+    let commit_response = apply_commit(&appstate, version, &body, &req)?;
+    let message = commit_response.commit_resource.to_json_ad()?;
+
+    Ok(builder.body(message))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkQuery {
+    /// Not supported: Commits can not be undone without overwriting concurrent changes and their side effects.
+    /// Requests that ask for it are rejected, instead of silently applying the Commits one by one.
+    #[serde(default)]
+    pub transactional: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkStatus {
+    Applied,
+    Failed,
+}
+
+/// The outcome of a single Commit in a bulk request.
+#[derive(Serialize, Debug)]
+pub struct BulkResult {
+    /// Position of the Commit in the request
+    pub index: usize,
+    pub status: BulkStatus,
+    /// Subject of the created Commit resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Applies a JSON-AD array of Commits in order, and returns the result of every Commit.
+/// This is best-effort: every Commit is applied on its own, exactly like a request to `/commit`.
+/// Commits that fail are skipped, the others are still applied, and nothing is undone.
+/// Clients can retry the failed Commits, or send Commits that depend on each other in separate requests.
+/// Responds with `207 Multi-Status` if any Commit was not applied.
+#[tracing::instrument(skip(appstate, req, body))]
+pub async fn post_commits_bulk(
+    appstate: web::Data<AppState>,
+    version: ApiVersion,
+    query: web::Query<BulkQuery>,
+    body: String,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    if query.transactional {
+        return Err(
+            "Transactional bulk requests are not supported. Commits are applied one by one, and are not undone when another Commit fails."
+                .into(),
+        );
+    }
+    let items: Vec<serde_json::Value> = serde_json::from_str(&body)
+        .map_err(|e| format!("Body should be a JSON array of Commits. {}", e))?;
+    if items.len() > MAX_BULK_COMMITS {
+        return Err(format!(
+            "Too many Commits. A bulk request can contain at most {} Commits.",
+            MAX_BULK_COMMITS
+        )
+        .into());
+    }
+    let mut results = Vec::with_capacity(items.len());
+    let mut failed = false;
+    for (index, item) in items.iter().enumerate() {
+        match apply_commit(&appstate, version, &item.to_string(), &req) {
+            Ok(response) => {
+                results.push(BulkResult {
+                    index,
+                    status: BulkStatus::Applied,
+                    commit: Some(response.commit_resource.get_subject().to_string()),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                failed = true;
+                results.push(BulkResult {
+                    index,
+                    status: BulkStatus::Failed,
+                    commit: None,
+                    error: Some(e.message),
+                });
+            }
+        }
+    }
+    let mut builder = if failed {
+        HttpResponse::build(StatusCode::MULTI_STATUS)
+    } else {
+        HttpResponse::Ok()
+    };
+    add_deprecation_headers(&mut builder, version);
+    Ok(builder.json(results))
+}
+
+/// Parses, checks and applies a single Commit, and records it in the audit log.
+fn apply_commit(
+    appstate: &AppState,
+    version: ApiVersion,
+    body: &str,
+    req: &actix_web::HttpRequest,
+) -> AtomicServerResult<CommitResponse> {
+    let store = &appstate.store;
+    let incoming_commit_resource = parse_json_ad_commit_resource(body, store)?;
     let incoming_commit = Commit::from_resource(incoming_commit_resource)?;
     if !incoming_commit.subject.contains(
         &store
@@ -20,19 +139,49 @@ pub async fn post_commit(
     ) {
         return Err("Subject of commit should be sent to other domain - this store can not own this resource.".into());
     }
+    // Drives can limit the networks that can change them. Both the current and the new Drive are checked, for resources that are moved.
+    let ip = crate::helpers::client_ip(&req.connection_info(), req.peer_addr(), &appstate.config);
+    let mut target = store
+        .get_resource(&incoming_commit.subject)
+        .unwrap_or_else(|_| Resource::new(incoming_commit.subject.clone()));
+    crate::ip_access::check_drive(store, &target, ip.as_deref())?;
+    if let Some(parent) = incoming_commit
+        .set
+        .as_ref()
+        .and_then(|set| set.get(urls::PARENT))
+    {
+        target.set_propval_unsafe(urls::PARENT.into(), parent.clone());
+        crate::ip_access::check_drive(store, &target, ip.as_deref())?;
+    }
     let opts = CommitOpts {
         validate_schema: true,
         validate_signature: true,
         validate_timestamp: true,
         validate_rights: true,
         // https://github.com/atomicdata-dev/atomic-data-rust/issues/412
-        validate_previous_commit: false,
+        validate_previous_commit: version.validates_previous_commit(),
         validate_for_agent: Some(incoming_commit.signer.to_string()),
         update_index: true,
     };
-    let commit_response = incoming_commit.apply_opts(store, &opts)?;
-
-    let message = commit_response.commit_resource.to_json_ad()?;
-
-    Ok(builder.body(message))
+    // The signer is only counted once the signature has been checked, so others can't use up its budget
+    let rate_limiter = &appstate.rate_limiter;
+    rate_limiter.check_exceeded(Category::Commit, &incoming_commit.signer)?;
+    let applied = incoming_commit.apply_opts(store, &opts);
+    let outcome = match &applied {
+        Ok(_) => "applied".to_string(),
+        Err(e) => e.to_string(),
+    };
+    audit_log::record(
+        appstate,
+        AuditEntry::from_request(
+            req,
+            AuditEvent::Commit,
+            Some(incoming_commit.signer.clone()),
+            Some(incoming_commit.subject.clone()),
+            outcome,
+        ),
+    );
+    let commit_response = applied?;
+    rate_limiter.hit(Category::Commit, &incoming_commit.signer)?;
+    Ok(commit_response)
 }
@@ -1,8 +1,10 @@
-use actix_files::NamedFile;
 use actix_web::{web, HttpRequest, HttpResponse};
 use atomic_lib::{urls, Resource, Storelike};
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+use crate::{
+    appstate::AppState, errors::AtomicServerResult, handlers::upload::is_quarantined,
+    helpers::get_client_agent,
+};
 
 /// Downloads the File of the Resource that matches the same URL minus the `/download` path.
 #[tracing::instrument(skip(appstate, req))]
@@ -27,19 +29,24 @@ pub async fn handle_download(
     let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
     tracing::info!("handle_download: {}", subject);
     let resource = store.get_resource_extended(&subject, false, for_agent.as_deref())?;
-    download_file_handler_partial(&resource, &req, &appstate)
+    download_file_handler_partial(&resource, &req, &appstate).await
 }
 
-pub fn download_file_handler_partial(
+pub async fn download_file_handler_partial(
     resource: &Resource,
     req: &HttpRequest,
     appstate: &AppState,
 ) -> AtomicServerResult<HttpResponse> {
+    if is_quarantined(resource) {
+        return Err(
+            "This file has been quarantined, because it was flagged by the virus scanner.".into(),
+        );
+    }
     let file_name = resource
         .get(urls::INTERNAL_ID)
         .map_err(|e| format!("Internal ID of file could not be resolved. {}", e))?;
-    let mut file_path = appstate.config.uploads_path.clone();
-    file_path.push(file_name.to_string());
-    let file = NamedFile::open(file_path)?;
-    Ok(file.into_response(req))
+    appstate
+        .file_store
+        .download_response(&file_name.to_string(), req)
+        .await
 }
@@ -3,10 +3,12 @@ use crate::{
     content_types::get_accept,
     content_types::ContentType,
     errors::AtomicServerResult,
-    helpers::{get_client_agent, try_extension},
+    helpers::{get_client_agent_for_resource, try_extension},
+    rate_limit::{limit_agent, Category},
+    read_tokens, share_links,
 };
 use actix_web::{web, HttpResponse};
-use atomic_lib::Storelike;
+use atomic_lib::{i18n::parse_accept_language, Storelike};
 use simple_server_timing_header::Timer;
 
 /// Respond to a single resource.
@@ -21,7 +23,9 @@ pub async fn handle_get_resource(
 
     let headers = req.headers();
     let mut content_type = get_accept(headers);
-    let server_url = &appstate.config.server_url;
+    let server_url = &crate::domains::base_url(&appstate, &req);
+    let mut share_token = None;
+    let mut read_token = None;
     // Get the subject from the path, or return the home URL
     let subject = if let Some(subj_end) = path {
         let mut subj_end_string = subj_end.as_str();
@@ -37,10 +41,15 @@ pub async fn handle_get_resource(
             }
             // Check extensions and set datatype. Harder than it looks to get right...
             // This might not be the best way of creating the subject. But I can't access the full URL from any actix stuff!
-            let querystring = if req.query_string().is_empty() {
+            let (query_string, token) = share_links::take_token(req.query_string());
+            share_token = token;
+            let (query_string, token) =
+                share_links::take_param(&query_string, read_tokens::READ_TOKEN_PARAM);
+            read_token = token;
+            let querystring = if query_string.is_empty() {
                 "".to_string()
             } else {
-                format!("?{}", req.query_string())
+                format!("?{}", query_string)
             };
             let subject = format!("{}/{}{}", server_url, subj_end_string, querystring);
             subject
@@ -53,7 +62,25 @@ pub async fn handle_get_resource(
     let store = &appstate.store;
     timer.add("parse_headers");
 
-    let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
+    let for_agent = match share_token {
+        Some(token) => Some(share_links::verify_token(store, &token, &subject)?),
+        // The read token is passed on in the query, see [read_tokens::get_token]
+        None => match read_token {
+            Some(token) => {
+                let separator = if subject.contains('?') { '&' } else { '?' };
+                let with_token = format!(
+                    "{}{}{}={}",
+                    subject,
+                    separator,
+                    read_tokens::READ_TOKEN_PARAM,
+                    urlencoding::encode(&token)
+                );
+                get_client_agent_for_resource(headers, &appstate, with_token)?
+            }
+            None => get_client_agent_for_resource(headers, &appstate, subject.clone())?,
+        },
+    };
+    limit_agent(&appstate, Category::Read, for_agent.as_deref())?;
     timer.add("get_agent");
 
     let mut builder = HttpResponse::Ok();
@@ -67,18 +94,29 @@ pub async fn handle_get_resource(
         "no-store, no-cache, must-revalidate, private",
     ));
 
-    let resource = store.get_resource_extended(&subject, false, for_agent.as_deref())?;
+    // Resources with translations are returned in the preferred language, see [atomic_lib::i18n]
+    let languages = headers
+        .get("Accept-Language")
+        .and_then(|header| header.to_str().ok())
+        .map(parse_accept_language)
+        .unwrap_or_default();
+    builder.append_header(("Vary", "Accept-Language"));
+    let resource =
+        store.get_resource_localized(&subject, false, for_agent.as_deref(), &languages)?;
     timer.add("get_resource");
 
     let response_body = match content_type {
         ContentType::Json => resource.to_json(store)?,
         ContentType::JsonLd => resource.to_json_ld(store)?,
         ContentType::JsonAd => resource.to_json_ad()?,
-        ContentType::Html => resource.to_json_ad()?,
+        ContentType::Html => crate::html::render(store, &resource, for_agent.as_deref())?,
         ContentType::Turtle | ContentType::NTriples => {
             let atoms = resource.to_atoms();
             atomic_lib::serialize::atoms_to_ntriples(atoms, store)?
         }
+        ContentType::Rss => crate::feeds::to_rss(store, &resource, for_agent.as_deref())?,
+        ContentType::Atom => crate::feeds::to_atom(store, &resource, for_agent.as_deref())?,
+        ContentType::ICalendar => crate::calendar::to_ics(store, &resource, for_agent.as_deref())?,
     };
     timer.add("serialize");
     Ok(builder.body(response_body))
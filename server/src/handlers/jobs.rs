@@ -0,0 +1,82 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    hierarchy::check_write, parse::JSON_AD_MIME, storelike::Query, urls, AtomicError, Resource,
+    Storelike, Value,
+};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+#[derive(Deserialize, Debug)]
+pub struct JobsQuery {
+    /// Only lists Jobs with this status, such as `running`
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CancelQuery {
+    pub subject: String,
+}
+
+/// Lists the Jobs that the Agent can read, as a Collection.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn list_jobs(
+    appstate: web::Data<AppState>,
+    query: web::Query<JobsQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = crate::helpers::request_url(&req, &appstate.config);
+    let for_agent = get_client_agent(req.headers(), &appstate, subject.clone())?
+        .unwrap_or_else(|| urls::PUBLIC_AGENT.to_string());
+
+    let mut job_query = Query::new_class(urls::JOB);
+    job_query.for_agent = Some(for_agent);
+    let mut members = store.query(&job_query)?.subjects;
+    if let Some(status) = &query.status {
+        members.retain(|member| {
+            store
+                .get_resource(member)
+                .and_then(|job| job.get(urls::JOB_STATUS).map(|v| v.to_string()))
+                .is_ok_and(|job_status| &job_status == status)
+        });
+    }
+
+    let mut resource = Resource::new(subject);
+    resource.set_class(urls::COLLECTION);
+    resource.set_propval_string(urls::NAME.into(), "Jobs", store)?;
+    resource.set_propval_unsafe(
+        urls::COLLECTION_MEMBER_COUNT.into(),
+        Value::Integer(members.len() as i64),
+    );
+    resource.set_propval_unsafe(urls::COLLECTION_MEMBERS.into(), members.into());
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(resource.to_json_ad()?))
+}
+
+/// Cancels a queued or running Job. Requires write rights for the Job.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn cancel_job(
+    appstate: web::Data<AppState>,
+    query: web::Query<CancelQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = format!(
+        "{}?{}",
+        crate::helpers::request_url(&req, &appstate.config),
+        req.query_string()
+    );
+    let job = store.get_resource(&query.subject)?;
+    match get_client_agent(req.headers(), &appstate, subject)? {
+        Some(agent) => check_write(store, &job, &agent)?,
+        None => {
+            return Err(AtomicError::unauthorized("Sign in to cancel Jobs.".into()).into());
+        }
+    };
+    appstate.jobs.cancel(&query.subject)?;
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(job.to_json_ad()?))
+}
@@ -5,11 +5,22 @@ Most of the logic for routing and handling resides in [atomic_lib::Storelike::ge
 However, some features reside in atomic-server.
 */
 
+pub mod activitypub;
+pub mod api_tokens;
+pub mod audit_log;
 pub mod commit;
 pub mod download;
 pub mod get_resource;
+pub mod jobs;
+pub mod oidc;
+pub mod passkeys;
+pub mod personal_data;
 pub mod post_resource;
+pub mod read_tokens;
 pub mod search;
+pub mod share_links;
 pub mod single_page_app;
+pub mod solid;
+pub mod totp;
 pub mod upload;
 pub mod web_sockets;
@@ -0,0 +1,103 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::AtomicError;
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, oidc::OidcClient};
+
+#[derive(Deserialize, Debug)]
+pub struct LoginQuery {
+    /// Path on this server to return to after logging in
+    pub redirect: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set by the provider if the user cancelled, or the login failed
+    pub error: Option<String>,
+}
+
+fn get_client(appstate: &AppState) -> AtomicServerResult<std::sync::Arc<OidcClient>> {
+    Ok(appstate.oidc.clone().ok_or_else(|| {
+        AtomicError::not_found("OIDC login is not enabled on this server.".into())
+    })?)
+}
+
+/// Redirects the user to the OpenID Connect provider.
+#[tracing::instrument(skip(appstate))]
+pub async fn login(
+    appstate: web::Data<AppState>,
+    query: web::Query<LoginQuery>,
+) -> AtomicServerResult<HttpResponse> {
+    let client = get_client(&appstate)?;
+    // Only redirect to paths on this server, to prevent open redirects
+    let redirect = match query.into_inner().redirect {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") => path,
+        _ => "/".to_string(),
+    };
+    let url = web::block(move || client.login_url(&redirect))
+        .await
+        .map_err(|e| format!("Failed to start OIDC login: {}", e))??;
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", url))
+        .finish())
+}
+
+/// Called by the provider after the user has logged in.
+/// Finds or creates the Agent of the user, and sets its session cookie.
+/// If the Agent enabled TOTP, the `totp-ticket` query param is added to the redirect instead.
+#[tracing::instrument(skip(appstate))]
+pub async fn callback(
+    appstate: web::Data<AppState>,
+    query: web::Query<CallbackQuery>,
+) -> AtomicServerResult<HttpResponse> {
+    let client = get_client(&appstate)?;
+    let query = query.into_inner();
+    if let Some(error) = query.error {
+        return Err(AtomicError::unauthorized(format!("OIDC login failed: {}", error)).into());
+    }
+    let state = query.state.ok_or("Missing `state` query param")?;
+    let code = query.code.ok_or("Missing `code` query param")?;
+    let redirect = client.take_pending(&state)?;
+
+    let store = appstate.store.clone();
+    let keys = appstate.agent_keys.clone();
+    let agent = web::block(move || -> AtomicServerResult<atomic_lib::agents::Agent> {
+        let user = client.fetch_user_info(&code)?;
+        client.agent_for_user(&store, &keys, &user)
+    })
+    .await
+    .map_err(|e| format!("Failed to finish OIDC login: {}", e))??;
+    if atomic_lib::agents::is_disabled(&appstate.store, &agent.subject) {
+        return Err(AtomicError::unauthorized(format!(
+            "Agent {} has been disabled",
+            agent.subject
+        ))
+        .into());
+    }
+
+    let server_url = &appstate.config.server_url;
+    // The client asks for a code, and exchanges the ticket for a session at `/auth/totp/verify`
+    if appstate.totp.is_enabled(&agent.subject)? {
+        let ticket = appstate.totp.new_ticket(&agent.subject)?;
+        let separator = if redirect.contains('?') { '&' } else { '?' };
+        return Ok(HttpResponse::Found()
+            .insert_header((
+                "Location",
+                format!(
+                    "{}{}{}totp-ticket={}",
+                    server_url, redirect, separator, ticket
+                ),
+            ))
+            .finish());
+    }
+    let session = crate::sessions::session_cookie_value(&agent, server_url)?;
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", format!("{}{}", server_url, redirect)))
+        .insert_header((
+            "Set-Cookie",
+            crate::sessions::session_cookie_header(&session, server_url),
+        ))
+        .finish())
+}
@@ -0,0 +1,132 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{agents::Agent, urls, AtomicError};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    passkeys::{encode, AssertionResponse, Ceremony, RegistrationResponse, ALG_EDDSA, ALG_ES256},
+    sessions::{session_cookie_header, session_cookie_value},
+};
+
+/// Milliseconds that the browser waits for the authenticator
+const TIMEOUT: u64 = 300_000;
+
+#[derive(Deserialize, Debug)]
+pub struct RegisterQuery {
+    /// Name of the new Agent, if the user is not signed in
+    pub name: Option<String>,
+}
+
+/// Returns the options for `navigator.credentials.create()`.
+/// Signed in Agents add a passkey to their own Agent, others get a new Agent.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn register_options(
+    appstate: web::Data<AppState>,
+    query: web::Query<RegisterQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = crate::helpers::request_url(&req, &appstate.config);
+    let agent = match get_client_agent(req.headers(), &appstate, subject)? {
+        Some(agent) if agent != urls::PUBLIC_AGENT => {
+            appstate.agent_keys.get(store, &agent)?.ok_or_else(|| {
+                AtomicError::method_not_allowed(
+                    "The server does not hold the private key of this Agent, so it can not create sessions for it. Keep using your private key to sign in.",
+                )
+            })?
+        }
+        _ => Agent::new(query.name.as_deref(), store)?,
+    };
+    let user = serde_json::json!({
+        "id": encode(agent.subject.as_bytes()),
+        "name": agent.name.clone().unwrap_or_else(|| agent.subject.clone()),
+        "displayName": agent.name.clone().unwrap_or_default(),
+    });
+    let challenge = appstate.passkeys.new_challenge(Ceremony::Register(agent))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "challenge": challenge,
+        "rp": {
+            "id": appstate.passkeys.rp_id,
+            "name": appstate.config.server_url,
+        },
+        "user": user,
+        "pubKeyCredParams": [
+            { "type": "public-key", "alg": ALG_ES256 },
+            { "type": "public-key", "alg": ALG_EDDSA },
+        ],
+        "timeout": TIMEOUT,
+        "attestation": "none",
+    })))
+}
+
+/// Stores the credential that was created by the browser, and signs in its Agent.
+#[tracing::instrument(skip(appstate, body))]
+pub async fn register(
+    appstate: web::Data<AppState>,
+    body: web::Json<RegistrationResponse>,
+) -> AtomicServerResult<HttpResponse> {
+    let state = appstate.clone();
+    let agent = web::block(move || -> AtomicServerResult<Agent> {
+        let agent = state.passkeys.register(&state.store, &body)?;
+        state.agent_keys.insert(&agent)?;
+        Ok(agent)
+    })
+    .await
+    .map_err(|e| format!("Failed to register passkey: {}", e))??;
+    signed_in(&appstate, &agent)
+}
+
+/// Returns the options for `navigator.credentials.get()`.
+#[tracing::instrument(skip(appstate))]
+pub async fn login_options(appstate: web::Data<AppState>) -> AtomicServerResult<HttpResponse> {
+    let challenge = appstate.passkeys.new_challenge(Ceremony::Login)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "challenge": challenge,
+        "rpId": appstate.passkeys.rp_id,
+        "timeout": TIMEOUT,
+        "userVerification": "preferred",
+    })))
+}
+
+/// Verifies the assertion of the browser, and signs in its Agent.
+#[tracing::instrument(skip(appstate, body))]
+pub async fn login(
+    appstate: web::Data<AppState>,
+    body: web::Json<AssertionResponse>,
+) -> AtomicServerResult<HttpResponse> {
+    let state = appstate.clone();
+    let agent = web::block(move || -> AtomicServerResult<Agent> {
+        let subject = state.passkeys.verify_assertion(&state.store, &body)?;
+        if atomic_lib::agents::is_disabled(&state.store, &subject) {
+            return Err(
+                AtomicError::unauthorized(format!("Agent {} has been disabled", subject)).into(),
+            );
+        }
+        Ok(state
+            .agent_keys
+            .get(&state.store, &subject)?
+            .ok_or("The server does not hold the private key of this Agent")?)
+    })
+    .await
+    .map_err(|e| format!("Failed to log in using passkey: {}", e))??;
+    signed_in(&appstate, &agent)
+}
+
+/// Sets the session cookie, and returns the subject of the Agent.
+/// Agents that enabled TOTP get a `totpTicket` instead, which is exchanged for the cookie at `/auth/totp/verify`.
+fn signed_in(appstate: &AppState, agent: &Agent) -> AtomicServerResult<HttpResponse> {
+    if appstate.totp.is_enabled(&agent.subject)? {
+        let ticket = appstate.totp.new_ticket(&agent.subject)?;
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "agent": agent.subject,
+            "totpTicket": ticket,
+        })));
+    }
+    let server_url = &appstate.config.server_url;
+    let session = session_cookie_value(agent, server_url)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Set-Cookie", session_cookie_header(&session, server_url)))
+        .json(serde_json::json!({ "agent": agent.subject })))
+}
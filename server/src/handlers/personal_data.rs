@@ -0,0 +1,173 @@
+//! Exports and erases the personal data of an Agent, see [atomic_lib::plugins::personal_data].
+//! Agents can do this for themselves, admins of the Drive can do it for any Agent.
+
+use std::io::Write;
+
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    hierarchy::check_write, plugins::personal_data, serialize::resources_to_json_ad, urls,
+    AtomicError, Storelike,
+};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult};
+
+#[derive(Deserialize, Debug)]
+pub struct PersonalDataQuery {
+    /// Subject of the Agent
+    pub agent: String,
+}
+
+/// Returns the signed in Agent, or an error if it is not the requested Agent, or an admin of the Drive.
+fn check_access(
+    appstate: &AppState,
+    req: &actix_web::HttpRequest,
+    agent: &str,
+    for_write: bool,
+) -> AtomicServerResult<String> {
+    let store = &appstate.store;
+    let subject = format!(
+        "{}?{}",
+        crate::helpers::request_url(req, &appstate.config),
+        req.query_string()
+    );
+    let signed_in = if for_write {
+        crate::helpers::get_client_agent_for_write(req.headers(), appstate, subject)?
+    } else {
+        crate::helpers::get_client_agent(req.headers(), appstate, subject)?
+    };
+    match signed_in {
+        Some(signed_in) if signed_in == agent => Ok(signed_in),
+        Some(signed_in) => {
+            let drive = store.get_resource(store.get_server_url())?;
+            check_write(store, &drive, &signed_in).map_err(|_e| {
+                AtomicError::unauthorized(
+                    "Only the Agent itself and admins can access its personal data.".into(),
+                )
+            })?;
+            Ok(signed_in)
+        }
+        None => {
+            Err(AtomicError::unauthorized("Sign in to access your personal data.".into()).into())
+        }
+    }
+}
+
+/// Creates a zip archive with `agent.json`, `resources.json` and `commits.json` (all JSON-AD),
+/// and the contents of the Files in the `files` folder.
+fn to_zip(
+    data: &personal_data::PersonalData,
+    files: &[(String, Vec<u8>)],
+) -> AtomicServerResult<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    let documents = [
+        ("agent.json", data.agent.to_json_ad()?),
+        ("resources.json", resources_to_json_ad(&data.resources)?),
+        ("commits.json", resources_to_json_ad(&data.commits)?),
+    ];
+    let files = files
+        .iter()
+        .map(|(id, contents)| (format!("files/{}", id), contents.as_slice()));
+    for (name, contents) in documents
+        .iter()
+        .map(|(name, json)| (name.to_string(), json.as_bytes()))
+        .chain(files)
+    {
+        writer
+            .start_file(name, options)
+            .map_err(|e| format!("Failed to create export: {}", e))?;
+        writer.write_all(contents)?;
+    }
+    let archive = writer
+        .finish()
+        .map_err(|e| format!("Failed to create export: {}", e))?;
+    Ok(archive.into_inner())
+}
+
+/// Downloads everything that is stored about the Agent as a zip archive.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn export(
+    appstate: web::Data<AppState>,
+    query: web::Query<PersonalDataQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let signed_in = check_access(&appstate, &req, &query.agent, false)?;
+    let data = personal_data::collect(&appstate.store, &query.agent, &signed_in)?;
+    let mut files = Vec::new();
+    for file in data.files() {
+        if let Ok(internal_id) = file.get(urls::INTERNAL_ID) {
+            let internal_id = internal_id.to_string();
+            let contents = appstate.file_store.read_file(&internal_id).await?;
+            files.push((internal_id, contents));
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .append_header((
+            "Content-Disposition",
+            "attachment; filename=\"personal-data.zip\"",
+        ))
+        .body(to_zip(&data, &files)?))
+}
+
+/// Removes the personal data of the Agent, including its Files, and updates the search index.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn erase(
+    appstate: web::Data<AppState>,
+    query: web::Query<PersonalDataQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    check_access(&appstate, &req, &query.agent, true)?;
+    let store = &appstate.store;
+    let erasure = personal_data::erase(store, &query.agent)?;
+    for file in &erasure.files {
+        appstate.file_store.delete_file(file).await?;
+    }
+    let search_state = &appstate.search_state;
+    for subject in erasure.removed.iter().chain([&query.agent]) {
+        crate::search::remove_resource(search_state, subject)?;
+    }
+    crate::search::add_resource(search_state, &store.get_resource(&query.agent)?, store)?;
+    search_state.writer.write()?.commit()?;
+    let body = serde_json::json!({
+        "removed": erasure.removed,
+        "removedFiles": erasure.files.len(),
+        "keptCommits": erasure.kept_commits,
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn zips_personal_data() {
+        let store = atomic_lib::Db::init_temp("zips_personal_data").unwrap();
+        let agent = store.get_default_agent().unwrap();
+        let data = personal_data::collect(&store, &agent.subject, &agent.subject).unwrap();
+        let files = [("1-notes.txt".to_string(), b"my notes".to_vec())];
+        let archive = to_zip(&data, &files).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(archive)).unwrap();
+        let mut notes = String::new();
+        archive
+            .by_name("files/1-notes.txt")
+            .unwrap()
+            .read_to_string(&mut notes)
+            .unwrap();
+        assert_eq!(notes, "my notes");
+        let mut agent_json = String::new();
+        archive
+            .by_name("agent.json")
+            .unwrap()
+            .read_to_string(&mut agent_json)
+            .unwrap();
+        assert!(agent_json.contains(&agent.subject));
+        assert_eq!(archive.len(), 4);
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     content_types::get_accept,
     content_types::ContentType,
     errors::AtomicServerResult,
-    helpers::{get_client_agent, try_extension},
+    helpers::{get_client_agent_for_write, try_extension},
 };
 use actix_web::{web, HttpResponse};
 use atomic_lib::Storelike;
@@ -21,7 +21,7 @@ pub async fn handle_post_resource(
 
     let headers = req.headers();
     let mut content_type = get_accept(headers);
-    let server_url = &appstate.config.server_url;
+    let server_url = &crate::domains::base_url(&appstate, &req);
     // Get the subject from the path, or return the home URL
     let subject = if let Some(subj_end) = path {
         let mut subj_end_string = subj_end.as_str();
@@ -53,7 +53,7 @@ pub async fn handle_post_resource(
     let store = &appstate.store;
     timer.add("parse_headers");
 
-    let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
+    let for_agent = get_client_agent_for_write(headers, &appstate, subject.clone())?;
     timer.add("get_agent");
 
     let mut builder = HttpResponse::Ok();
@@ -74,11 +74,14 @@ pub async fn handle_post_resource(
         ContentType::Json => resource.to_json(store)?,
         ContentType::JsonLd => resource.to_json_ld(store)?,
         ContentType::JsonAd => resource.to_json_ad()?,
-        ContentType::Html => resource.to_json_ad()?,
+        ContentType::Html => crate::html::render(store, &resource, for_agent.as_deref())?,
         ContentType::Turtle | ContentType::NTriples => {
             let atoms = resource.to_atoms();
             atomic_lib::serialize::atoms_to_ntriples(atoms, store)?
         }
+        ContentType::Rss => crate::feeds::to_rss(store, &resource, for_agent.as_deref())?,
+        ContentType::Atom => crate::feeds::to_atom(store, &resource, for_agent.as_deref())?,
+        ContentType::ICalendar => crate::calendar::to_ics(store, &resource, for_agent.as_deref())?,
     };
     timer.add("serialize");
     builder.append_header(("Server-Timing", timer.header_value()));
@@ -0,0 +1,48 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy, urls, AtomicError, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent_for_write,
+    read_tokens::{create_token, ReadClaims, DEFAULT_TTL, READ_TOKEN_PARAM},
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadTokenRequest {
+    pub parent: String,
+    /// Milliseconds since unix epoch. Defaults to 30 days from now.
+    pub expires_at: Option<i64>,
+}
+
+/// Mints a read token for a resource and its descendants. Requires write rights, just like creating a share link.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn mint_read_token(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Json<ReadTokenRequest>,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let request_subject = crate::helpers::request_url(&req, &appstate.config);
+    let agent = match get_client_agent_for_write(req.headers(), &appstate, request_subject)? {
+        Some(agent) if agent != urls::PUBLIC_AGENT => agent,
+        _ => return Err(AtomicError::unauthorized("Sign in to create read tokens.".into()).into()),
+    };
+    let ReadTokenRequest { parent, expires_at } = body.into_inner();
+    let resource = store.get_resource(&parent)?;
+    hierarchy::check_write(store, &resource, &agent)?;
+
+    let claims = ReadClaims {
+        parent,
+        agent,
+        expires_at: expires_at.unwrap_or_else(|| atomic_lib::utils::now() + DEFAULT_TTL),
+    };
+    let token = create_token(store, &claims)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token": token,
+        "param": READ_TOKEN_PARAM,
+        "expiresAt": claims.expires_at,
+    })))
+}
@@ -5,18 +5,24 @@
 use crate::{
     appstate::AppState,
     errors::{AtomicServerError, AtomicServerResult},
-    search::{resource_to_facet, Fields},
+    handlers::single_page_app::escape_html,
+    search::{Fields, Fuzziness},
 };
 use actix_web::{web, HttpResponse};
-use atomic_lib::{errors::AtomicResult, urls, Db, Resource, Storelike};
+use atomic_lib::{
+    errors::AtomicResult, storelike::GeoFilter, urls, values::GeoPoint, Resource, Storelike,
+};
 use serde::Deserialize;
 use simple_server_timing_header::Timer;
+use std::collections::HashMap;
 use tantivy::{
-    collector::TopDocs,
-    query::{BooleanQuery, BoostQuery, Occur, Query, QueryParser, TermQuery},
+    collector::{FacetCollector, FacetCounts, TopDocs},
+    query::{
+        BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, Query, QueryParser, RangeQuery, TermQuery,
+    },
     schema::IndexRecordOption,
     tokenizer::Tokenizer,
-    Term,
+    Snippet, SnippetGenerator, Term,
 };
 use tracing::instrument;
 
@@ -27,6 +33,7 @@ pub struct SearchQuery {
     /// The text search query entered by the user in the search box
     pub q: Option<String>,
     /// Include the full resources in the response
+    #[allow(dead_code)]
     pub include: Option<bool>,
     /// Maximum amount of results
     pub limit: Option<usize>,
@@ -36,6 +43,31 @@ pub struct SearchQuery {
     /// e.g. `prop:val` or `prop:val~1` or `prop:val~1 AND prop2:val2`
     /// See https://docs.rs/tantivy/latest/tantivy/query/struct.QueryParser.html
     pub filters: Option<String>,
+    /// Allowed typos per word: `0`, `1`, `2` or `auto`. Defaults to `--search-fuzzy`.
+    pub fuzzy: Option<String>,
+    /// Whether words also match longer words that start with them. Defaults to true.
+    pub prefix: Option<bool>,
+    /// Only include resources that are an instance of this Class
+    pub class: Option<String>,
+    /// Only include resources that have `value` for this property
+    pub property: Option<String>,
+    /// The exact value for `property`
+    pub value: Option<String>,
+    /// Only include resources with a GeoPoint within `radius` of this `latitude,longitude`. Sorts results by distance.
+    pub near: Option<String>,
+    /// Distance in meters for `near`. Defaults to 10 km.
+    pub radius: Option<f64>,
+    /// Only include resources with a GeoPoint in this `south,west,north,east` box
+    pub bbox: Option<String>,
+    /// Inserted before every highlighted word in the snippets. Defaults to `<mark>`.
+    #[serde(rename = "pre-tag")]
+    pub pre_tag: Option<String>,
+    /// Inserted after every highlighted word in the snippets. Defaults to `</mark>`.
+    #[serde(rename = "post-tag")]
+    pub post_tag: Option<String>,
+    /// Maximum amount of characters in a snippet
+    #[serde(rename = "snippet-length")]
+    pub snippet_length: Option<usize>,
 }
 
 const DEFAULT_RETURN_LIMIT: usize = 30;
@@ -43,6 +75,11 @@ const DEFAULT_RETURN_LIMIT: usize = 30;
 // We filter these results later.
 // https://github.com/atomicdata-dev/atomic-data-rust/issues/279.
 const UNAUTHORIZED_RESULTS_FACTOR: usize = 3;
+/// Maximum amount of Classes and Drives that are counted in the facets
+const FACET_LIMIT: usize = 20;
+const DEFAULT_SNIPPET_LENGTH: usize = 150;
+const DEFAULT_RADIUS_METERS: f64 = 10_000.0;
+const MAX_SNIPPET_LENGTH: usize = 1000;
 
 /// Parses a search query and responds with a list of resources
 #[tracing::instrument(skip(appstate, req))]
@@ -65,32 +102,87 @@ pub async fn search_query(
         DEFAULT_RETURN_LIMIT
     };
 
-    let query = query_from_params(&params, &fields, &appstate)?;
+    // Create a valid atomic data resource.
+    // You'd think there would be a simpler way of getting the requested URL...
+    let subject = format!(
+        "{}{}",
+        store.get_self_url().ok_or("No base URL set")?,
+        req.uri().path_and_query().ok_or("Add a query param")?
+    );
+    let for_agent = crate::helpers::get_client_agent(req.headers(), &appstate, subject.clone())?;
+    crate::rate_limit::limit_agent(
+        &appstate,
+        crate::rate_limit::Category::Search,
+        for_agent.as_deref(),
+    )?;
+    // Search can be disabled per Drive. Without a parent, the main Drive is searched.
+    let scope = params.parent.as_deref().unwrap_or(store.get_server_url());
+    if let Ok(scope) = store.get_resource(scope) {
+        atomic_lib::plugins::features::check_enabled(
+            store,
+            &scope,
+            atomic_lib::plugins::features::Feature::Search,
+        )?;
+    }
+
+    let geo = geo_filter_from_params(&params)?;
+    let query = query_from_params(
+        &params,
+        &fields,
+        &appstate,
+        for_agent.as_deref(),
+        geo.as_ref(),
+    )?;
     timer.add("build_query");
-    let top_docs = searcher
+    let mut class_collector = FacetCollector::for_field(fields.classes);
+    class_collector.add_facet("/");
+    let mut drive_collector = FacetCollector::for_field(fields.drive);
+    drive_collector.add_facet("/");
+    let (top_docs, class_counts, drive_counts) = searcher
         .search(
             &query,
-            &TopDocs::with_limit(limit * UNAUTHORIZED_RESULTS_FACTOR),
+            &(
+                TopDocs::with_limit(limit * UNAUTHORIZED_RESULTS_FACTOR),
+                class_collector,
+                drive_collector,
+            ),
         )
         .map_err(|e| format!("Error with creating search results: {} ", e))?;
 
     timer.add("execute_query");
+    let snippets = match &params.q {
+        Some(_q) => build_snippets(&top_docs, &fields, &searcher, &query, &params)?,
+        None => HashMap::new(),
+    };
+    timer.add("build_snippets");
     let subjects = docs_to_subjects(top_docs, &fields, &searcher)?;
 
-    // Create a valid atomic data resource.
-    // You'd think there would be a simpler way of getting the requested URL...
-    let subject = format!(
-        "{}{}",
-        store.get_self_url().ok_or("No base URL set")?,
-        req.uri().path_and_query().ok_or("Add a query param")?
-    );
-
     let mut results_resource = atomic_lib::plugins::search::search_endpoint().to_resource(store)?;
     results_resource.set_subject(subject.clone());
 
-    let resources = get_resources(req, &appstate, &subject, subjects, limit)?;
+    let resources = get_resources(
+        &appstate,
+        for_agent.as_deref(),
+        subjects,
+        limit,
+        geo.as_ref(),
+    )?;
     timer.add("get_resources");
+    let result_snippets = snippets_to_value(&resources, &snippets);
     results_resource.set_propval(urls::ENDPOINT_RESULTS.into(), resources.into(), store)?;
+    results_resource.set_propval_unsafe(urls::SEARCH_SNIPPETS.into(), result_snippets);
+    results_resource.set_propval_unsafe(
+        urls::SEARCH_INDEX_LAG.into(),
+        atomic_lib::Value::Integer(appstate.search_state.lag.lag().as_millis() as i64),
+    );
+    results_resource.set_propval_unsafe(
+        urls::SEARCH_CLASS_FACETS.into(),
+        facets_to_value(&class_counts),
+    );
+    results_resource.set_propval_unsafe(
+        urls::SEARCH_DRIVE_FACETS.into(),
+        facets_to_value(&drive_counts),
+    );
     let mut builder = HttpResponse::Ok();
     builder.append_header(("Server-Timing", timer.header_value()));
 
@@ -98,34 +190,25 @@ pub async fn search_query(
     Ok(builder.body(results_resource.to_json_ad()?))
 }
 
-#[derive(Debug, std::hash::Hash, Eq, PartialEq)]
-pub struct StringAtom {
-    pub subject: String,
-    pub property: String,
-    pub value: String,
-}
-
-#[instrument(skip(appstate, req))]
+#[instrument(skip(appstate))]
 fn get_resources(
-    req: actix_web::HttpRequest,
     appstate: &web::Data<AppState>,
-    subject: &str,
+    for_agent: Option<&str>,
     subjects: Vec<String>,
     limit: usize,
+    geo: Option<&GeoFilter>,
 ) -> AtomicServerResult<Vec<Resource>> {
     // Default case: return full resources, do authentication
     let mut resources: Vec<Resource> = Vec::new();
 
-    // This is a pretty expensive operation. We need to check the rights for the subjects to prevent data leaks.
-    // But we could probably do some things to speed this up: make it async / parallel, check admin rights.
+    // Most unreadable resources are already filtered out by the query, see `build_rights_query`.
+    // We still check the rights for every subject, as the index can be slightly behind the store.
     // https://github.com/atomicdata-dev/atomic-data-rust/issues/279
     // https://github.com/atomicdata-dev/atomic-data-rust/issues/280/
-    let for_agent = crate::helpers::get_client_agent(req.headers(), appstate, subject.into())?;
     for s in subjects {
-        match appstate
-            .store
-            .get_resource_extended(&s, true, for_agent.as_deref())
-        {
+        match appstate.store.get_resource_extended(&s, true, for_agent) {
+            // The index only checks the latitude and longitude separately
+            Ok(r) if geo.is_some_and(|geo| closest_point(&r, geo).is_none()) => continue,
             Ok(r) => {
                 if resources.len() < limit {
                     resources.push(r);
@@ -139,25 +222,164 @@ fn get_resources(
             }
         }
     }
+    if let Some(geo @ GeoFilter::Radius { .. }) = geo {
+        resources.sort_by(|a, b| {
+            let distance = |r| closest_point(r, geo).unwrap_or(f64::MAX);
+            distance(a).total_cmp(&distance(b))
+        });
+    }
     Ok(resources)
 }
 
+/// Returns the distance to the closest GeoPoint of the resource that is inside the area.
+fn closest_point(resource: &Resource, geo: &GeoFilter) -> Option<f64> {
+    resource
+        .get_propvals()
+        .values()
+        .filter_map(|value| match value {
+            atomic_lib::Value::GeoPoint(point) if geo.contains(point) => Some(geo.distance(point)),
+            _other => None,
+        })
+        .min_by(f64::total_cmp)
+}
+
+/// Parses the `near`, `radius` and `bbox` params.
+fn geo_filter_from_params(params: &SearchQuery) -> AtomicServerResult<Option<GeoFilter>> {
+    match (&params.near, &params.bbox) {
+        (Some(_), Some(_)) => Err("Use either `near` or `bbox`, not both".into()),
+        (Some(near), None) => {
+            let meters = params.radius.unwrap_or(DEFAULT_RADIUS_METERS);
+            if meters <= 0.0 {
+                return Err("The `radius` should be larger than 0".into());
+            }
+            Ok(Some(GeoFilter::Radius {
+                center: near.parse::<GeoPoint>()?,
+                meters,
+            }))
+        }
+        (None, Some(bbox)) => {
+            let corners: Vec<f64> = bbox
+                .split(',')
+                .map(|n| n.trim().parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Invalid `bbox` {}: {}", bbox, e))?;
+            let [south, west, north, east] = corners[..] else {
+                return Err("The `bbox` should be south,west,north,east".into());
+            };
+            if south > north {
+                return Err("The south of the `bbox` should be below its north".into());
+            }
+            Ok(Some(GeoFilter::BoundingBox {
+                south_west: GeoPoint::new(south, west)?,
+                north_east: GeoPoint::new(north, east)?,
+            }))
+        }
+        (None, None) => {
+            if params.radius.is_some() {
+                return Err("Use `radius` together with `near`".into());
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Matches resources with a GeoPoint in the latitude band and longitude range of the area.
+/// The exact area (and the combination of latitude and longitude) is checked in [get_resources].
+fn build_geo_query(geo: &GeoFilter, fields: &Fields) -> BooleanQuery {
+    let (south, north) = geo.latitude_range();
+    let latitude = RangeQuery::new_f64_bounds(
+        fields.latitude,
+        std::ops::Bound::Included(south),
+        std::ops::Bound::Included(north),
+    );
+    let longitude_ranges: Vec<(f64, f64)> = match geo {
+        GeoFilter::BoundingBox {
+            south_west,
+            north_east,
+        } if south_west.longitude > north_east.longitude => vec![
+            (south_west.longitude, 180.0),
+            (-180.0, north_east.longitude),
+        ],
+        GeoFilter::BoundingBox {
+            south_west,
+            north_east,
+        } => vec![(south_west.longitude, north_east.longitude)],
+        // Longitudes are closer together near the poles, and all of them meet at the poles
+        GeoFilter::Radius { center, meters } => {
+            let cos = center.latitude.to_radians().cos();
+            let degrees = meters / (111_000.0 * cos.max(f64::EPSILON));
+            if degrees >= 180.0 || south <= -90.0 || north >= 90.0 {
+                vec![(-180.0, 180.0)]
+            } else {
+                let (west, east) = (center.longitude - degrees, center.longitude + degrees);
+                match (west < -180.0, east > 180.0) {
+                    (true, _) => vec![(west + 360.0, 180.0), (-180.0, east)],
+                    (_, true) => vec![(west, 180.0), (-180.0, east - 360.0)],
+                    _ => vec![(west, east)],
+                }
+            }
+        }
+    };
+    let longitude: Queries = longitude_ranges
+        .into_iter()
+        .map(|(west, east)| -> (Occur, Box<dyn Query>) {
+            (
+                Occur::Should,
+                Box::new(RangeQuery::new_f64_bounds(
+                    fields.longitude,
+                    std::ops::Bound::Included(west),
+                    std::ops::Bound::Included(east),
+                )),
+            )
+        })
+        .collect();
+    BooleanQuery::new(vec![
+        (Occur::Must, Box::new(latitude)),
+        (Occur::Must, Box::new(BooleanQuery::new(longitude))),
+    ])
+}
+
 #[tracing::instrument(skip(appstate))]
 fn query_from_params(
     params: &SearchQuery,
     fields: &Fields,
     appstate: &web::Data<AppState>,
+    for_agent: Option<&str>,
+    geo: Option<&GeoFilter>,
 ) -> AtomicServerResult<impl Query> {
     let mut query_list: Queries = Vec::new();
 
+    if let Some(geo) = geo {
+        query_list.push((Occur::Must, Box::new(build_geo_query(geo, fields))));
+    }
+
     if let Some(parent) = &params.parent {
-        let query = build_parent_query(parent, fields, &appstate.store)?;
+        let query = build_parent_query(parent, fields);
 
         query_list.push((Occur::Must, Box::new(query)));
     }
 
+    if let Some(agent) = for_agent {
+        let is_server_agent = appstate
+            .store
+            .get_default_agent()
+            .map(|a| a.subject == agent)
+            .unwrap_or(false);
+        if !is_server_agent {
+            query_list.push((Occur::Must, Box::new(build_rights_query(agent, fields))));
+        }
+    }
+
     if let Some(q) = &params.q {
-        let text_query = build_text_query(fields, q)?;
+        let fuzziness = match &params.fuzzy {
+            Some(fuzzy) => fuzzy.parse::<Fuzziness>()?,
+            None => appstate.config.opts.search_fuzzy,
+        };
+        let matching = TextMatching {
+            fuzziness,
+            prefix: params.prefix.unwrap_or(true),
+        };
+        let text_query = build_text_query(fields, q, &matching, &appstate.search_state.index)?;
 
         query_list.push((Occur::Must, Box::new(text_query)));
     }
@@ -171,17 +393,88 @@ fn query_from_params(
         query_list.push((Occur::Must, Box::new(filter_query)));
     }
 
+    if let Some(class) = &params.class {
+        let term = Term::from_facet(fields.classes, &crate::search::subject_facet(class));
+        query_list.push((
+            Occur::Must,
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+        ));
+    }
+
+    match (&params.property, &params.value) {
+        (Some(property), Some(value)) => {
+            let term = Term::from_field_text(
+                fields.property_values,
+                &crate::search::property_value_key(property, value),
+            );
+            query_list.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        (None, None) => {}
+        _ => return Err("Use the `property` and `value` params together".into()),
+    }
+
     let query = BooleanQuery::new(query_list);
 
     Ok(query)
 }
 
-/// Performs both fuzzy and exact queries on the text and description fields.
+/// Converts facet counts to nested resources with a `facet-value` and a `facet-count`, highest count first.
+fn facets_to_value(counts: &FacetCounts) -> atomic_lib::Value {
+    let facets: Vec<atomic_lib::values::SubResource> = counts
+        .top_k("/", FACET_LIMIT)
+        .into_iter()
+        .map(|(facet, count)| {
+            let mut propvals = atomic_lib::resources::PropVals::new();
+            propvals.insert(
+                urls::FACET_VALUE.into(),
+                atomic_lib::Value::AtomicUrl(crate::search::facet_subject(facet)),
+            );
+            propvals.insert(
+                urls::FACET_COUNT.into(),
+                atomic_lib::Value::Integer(count as i64),
+            );
+            atomic_lib::values::SubResource::Nested(propvals)
+        })
+        .collect();
+    atomic_lib::Value::ResourceArray(facets)
+}
+
+/// How the words in a text query are matched to the words in the index.
+#[derive(Debug)]
+struct TextMatching {
+    fuzziness: Fuzziness,
+    /// Also match words that start with the word in the query
+    prefix: bool,
+}
+
+impl TextMatching {
+    /// Matches typos and / or prefixes. Returns None if only exact matches are allowed.
+    fn fuzzy_query(&self, term: Term, word: &str) -> Option<FuzzyTermQuery> {
+        let distance = self.fuzziness.distance(word);
+        match (distance, self.prefix) {
+            (0, false) => None,
+            // Counts swapping two letters as a single typo
+            (distance, true) => Some(FuzzyTermQuery::new_prefix(term, distance, true)),
+            (distance, false) => Some(FuzzyTermQuery::new(term, distance, true)),
+        }
+    }
+}
+
+/// Performs both fuzzy and exact queries on the text and description fields, and exact queries on the text of Files.
 /// Boosts titles and exact matches over descriptions and fuzzy matches.
+/// Also searches the localized fields, using the analyzer of each language.
 /// Does not yet search in JSON fields:
 /// https://github.com/atomicdata-dev/atomic-data-rust/issues/597
-#[tracing::instrument]
-fn build_text_query(fields: &Fields, q: &str) -> AtomicResult<impl Query> {
+#[tracing::instrument(skip(index))]
+fn build_text_query(
+    fields: &Fields,
+    q: &str,
+    matching: &TextMatching,
+    index: &tantivy::Index,
+) -> AtomicResult<impl Query> {
     let mut token_stream = tantivy::tokenizer::SimpleTokenizer.token_stream(q);
     let mut queries: Queries = Vec::new();
     // for every word, create a fuzzy query and an exact query
@@ -189,11 +482,13 @@ fn build_text_query(fields: &Fields, q: &str) -> AtomicResult<impl Query> {
         let word = &token.text;
         let title_term = Term::from_field_text(fields.title, word);
         let description_term = Term::from_field_text(fields.description, word);
-        let title_fuzzy = tantivy::query::FuzzyTermQuery::new_prefix(title_term.clone(), 1, true);
-        let description_fuzzy =
-            tantivy::query::FuzzyTermQuery::new_prefix(description_term.clone(), 1, true);
+        let content_term = Term::from_field_text(fields.content, word);
+        let title_fuzzy = matching.fuzzy_query(title_term.clone(), word);
+        let description_fuzzy = matching.fuzzy_query(description_term.clone(), word);
         let title_exact = TermQuery::new(title_term, IndexRecordOption::Basic);
         let description_exact = TermQuery::new(description_term, IndexRecordOption::Basic);
+        // The text of Files is usually long, so it only gets exact matches
+        let content_exact = TermQuery::new(content_term, IndexRecordOption::Basic);
 
         // Boost the title higher than the description
         queries.push((
@@ -204,15 +499,42 @@ fn build_text_query(fields: &Fields, q: &str) -> AtomicResult<impl Query> {
             Occur::Should,
             Box::new(BoostQuery::new(Box::new(description_exact), 2.0)),
         ));
+        queries.push((Occur::Should, Box::new(content_exact)));
 
         // Rank exact higher than fuzzy
-        queries.push((
-            Occur::Should,
-            Box::new(BoostQuery::new(Box::new(title_fuzzy), 4.0)),
-        ));
-        queries.push((Occur::Should, Box::new(description_fuzzy)));
+        if let Some(title_fuzzy) = title_fuzzy {
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(title_fuzzy), 4.0)),
+            ));
+        }
+        if let Some(description_fuzzy) = description_fuzzy {
+            queries.push((Occur::Should, Box::new(description_fuzzy)));
+        }
     });
 
+    // The localized fields are stemmed, so the query has to be analyzed in the same way
+    for (code, field) in &fields.localized {
+        let analyzer = index
+            .tokenizers()
+            .get(&crate::search::tokenizer_name(code))
+            .ok_or(format!(
+                "No analyzer registered for search language {}",
+                code
+            ))?;
+        let mut localized_stream = analyzer.token_stream(q);
+        localized_stream.process(&mut |token| {
+            let term = Term::from_field_text(*field, &token.text);
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)),
+                    4.0,
+                )),
+            ));
+        });
+    }
+
     Ok(BooleanQuery::from(queries))
 }
 
@@ -231,16 +553,107 @@ fn build_filter_query(
     Ok(query)
 }
 
-#[tracing::instrument(skip(store))]
-fn build_parent_query(subject: &str, fields: &Fields, store: &Db) -> AtomicServerResult<TermQuery> {
-    let resource = store.get_resource(subject)?;
-    let facet = resource_to_facet(&resource, store)?;
-    let term = Term::from_facet(fields.hierarchy, &facet);
+/// Matches all descendants of the parent, using the indexed `parents` field.
+fn build_parent_query(subject: &str, fields: &Fields) -> TermQuery {
+    let term = Term::from_field_text(fields.parents, subject);
+    TermQuery::new(term, IndexRecordOption::Basic)
+}
+
+/// Matches resources that the agent, or the Public Agent, has been granted read rights to.
+/// This makes sure that results (and their counts) of unreadable resources are not exposed,
+/// and that the result limit is not used up by resources that are filtered out later.
+fn build_rights_query(agent: &str, fields: &Fields) -> BooleanQuery {
+    let mut queries: Queries = Vec::new();
+    for a in [agent, urls::PUBLIC_AGENT] {
+        let term = Term::from_field_text(fields.read_rights, a);
+        queries.push((
+            Occur::Should,
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+        ));
+    }
+    BooleanQuery::new(queries)
+}
+
+/// Creates a snippet for every document that matches the text query, keyed by subject.
+/// The description is used if it contains a match, then the text of a File, otherwise the title.
+#[tracing::instrument(skip(docs, searcher, query))]
+fn build_snippets(
+    docs: &[(f32, tantivy::DocAddress)],
+    fields: &Fields,
+    searcher: &tantivy::Searcher,
+    query: &dyn Query,
+    params: &SearchQuery,
+) -> AtomicServerResult<HashMap<String, String>> {
+    let length = params
+        .snippet_length
+        .unwrap_or(DEFAULT_SNIPPET_LENGTH)
+        .clamp(1, MAX_SNIPPET_LENGTH);
+    let pre_tag = params.pre_tag.as_deref().unwrap_or("<mark>");
+    let post_tag = params.post_tag.as_deref().unwrap_or("</mark>");
+    let mut generators = Vec::new();
+    for field in [fields.description, fields.content, fields.title] {
+        let mut generator = SnippetGenerator::create(searcher, query, field)
+            .map_err(|e| format!("Error with creating search snippets: {}", e))?;
+        generator.set_max_num_chars(length);
+        generators.push(generator);
+    }
+
+    let mut snippets = HashMap::new();
+    for (_score, doc_address) in docs {
+        let retrieved_doc = searcher.doc(*doc_address)?;
+        let Some(subject_val) = retrieved_doc.get_first(fields.subject) else {
+            continue;
+        };
+        let subject = unpack_value(subject_val, &retrieved_doc, "Subject".to_string())?;
+        let snippet = generators
+            .iter()
+            .map(|generator| generator.snippet_from_doc(&retrieved_doc))
+            .find(|snippet| !snippet.highlighted().is_empty());
+        if let Some(snippet) = snippet {
+            snippets.insert(subject, highlight(&snippet, pre_tag, post_tag));
+        }
+    }
+    Ok(snippets)
+}
+
+/// Wraps the matches in the snippet with the tags. The rest of the text is HTML escaped.
+fn highlight(snippet: &Snippet, pre_tag: &str, post_tag: &str) -> String {
+    let fragment = snippet.fragment();
+    let mut highlighted = String::new();
+    let mut start = 0;
+    for range in snippet.highlighted() {
+        highlighted.push_str(&escape_html(&fragment[start..range.start]));
+        highlighted.push_str(pre_tag);
+        highlighted.push_str(&escape_html(&fragment[range.clone()]));
+        highlighted.push_str(post_tag);
+        start = range.end;
+    }
+    highlighted.push_str(&escape_html(&fragment[start..]));
+    highlighted
+}
 
-    Ok(TermQuery::new(
-        term,
-        tantivy::schema::IndexRecordOption::Basic,
-    ))
+/// Converts the snippets of the returned resources to nested resources with a `snippet-subject` and `snippet-text`, in the order of the results.
+fn snippets_to_value(
+    resources: &[Resource],
+    snippets: &HashMap<String, String>,
+) -> atomic_lib::Value {
+    let nested: Vec<atomic_lib::values::SubResource> = resources
+        .iter()
+        .filter_map(|resource| {
+            let text = snippets.get(resource.get_subject())?;
+            let mut propvals = atomic_lib::resources::PropVals::new();
+            propvals.insert(
+                urls::SNIPPET_SUBJECT.into(),
+                atomic_lib::Value::AtomicUrl(resource.get_subject().into()),
+            );
+            propvals.insert(
+                urls::SNIPPET_TEXT.into(),
+                atomic_lib::Value::String(text.clone()),
+            );
+            Some(atomic_lib::values::SubResource::Nested(propvals))
+        })
+        .collect();
+    atomic_lib::Value::ResourceArray(nested)
 }
 
 fn unpack_value(
@@ -279,3 +692,62 @@ fn docs_to_subjects(
 
     Ok(subjects.into_iter().collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::schema::{Schema, TEXT};
+
+    #[test]
+    fn highlights_snippets() {
+        let mut schema_builder = Schema::builder();
+        let description = schema_builder.add_text_field("description", TEXT);
+        let index = tantivy::Index::create_in_ram(schema_builder.build());
+        let mut writer = index.writer(15_000_000).unwrap();
+        writer
+            .add_document(tantivy::doc!(description => "A shared drive"))
+            .unwrap();
+        writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let term = Term::from_field_text(description, "drive");
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        let generator = SnippetGenerator::create(&searcher, &query, description).unwrap();
+
+        let snippet = generator.snippet("A <shared> drive for the team");
+        assert_eq!(
+            highlight(&snippet, "**", "**"),
+            "A &lt;shared&gt; **drive** for the team"
+        );
+    }
+
+    fn params(query: &str) -> SearchQuery {
+        web::Query::<SearchQuery>::from_query(query)
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn parses_geo_params() {
+        let Some(GeoFilter::Radius { center, meters }) =
+            geo_filter_from_params(&params("near=52.37,4.89&radius=500")).unwrap()
+        else {
+            panic!("Expected a radius")
+        };
+        assert_eq!(center, GeoPoint::new(52.37, 4.89).unwrap());
+        assert_eq!(meters, 500.0);
+
+        let bbox = geo_filter_from_params(&params("bbox=50,170,60,-170"))
+            .unwrap()
+            .unwrap();
+        // Crosses the antimeridian
+        assert!(bbox.contains(&GeoPoint::new(55.0, 179.0).unwrap()));
+        assert!(bbox.contains(&GeoPoint::new(55.0, -175.0).unwrap()));
+        assert!(!bbox.contains(&GeoPoint::new(55.0, 0.0).unwrap()));
+
+        assert!(geo_filter_from_params(&params("q=test")).unwrap().is_none());
+        geo_filter_from_params(&params("near=100,4")).unwrap_err();
+        geo_filter_from_params(&params("bbox=1,2,3")).unwrap_err();
+        geo_filter_from_params(&params("radius=10")).unwrap_err();
+        geo_filter_from_params(&params("near=1,2&bbox=1,2,3,4")).unwrap_err();
+    }
+}
@@ -0,0 +1,52 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy, urls, AtomicError, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    share_links::{create_link, ShareClaims, ShareRights, DEFAULT_TTL},
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareRequest {
+    pub subject: String,
+    pub rights: Option<ShareRights>,
+    /// Milliseconds since unix epoch. Defaults to a week from now.
+    pub expires_at: Option<i64>,
+}
+
+/// Creates a share link for a resource. Requires write rights, just like inviting someone.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn create_share_link(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Json<ShareRequest>,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let request_subject = crate::helpers::request_url(&req, &appstate.config);
+    let agent = match get_client_agent(req.headers(), &appstate, request_subject)? {
+        Some(agent) if agent != urls::PUBLIC_AGENT => agent,
+        _ => return Err(AtomicError::unauthorized("Sign in to create share links.".into()).into()),
+    };
+    let ShareRequest {
+        subject,
+        rights,
+        expires_at,
+    } = body.into_inner();
+    let resource = store.get_resource(&subject)?;
+    hierarchy::check_write(store, &resource, &agent)?;
+
+    let claims = ShareClaims {
+        subject,
+        agent,
+        rights: rights.unwrap_or(ShareRights::Read),
+        expires_at: expires_at.unwrap_or_else(|| atomic_lib::utils::now() + DEFAULT_TTL),
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "link": create_link(store, &claims)?,
+        "expiresAt": claims.expires_at,
+    })))
+}
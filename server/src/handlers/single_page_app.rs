@@ -9,9 +9,13 @@ use actix_web::HttpResponse;
 pub async fn single_page(
     appstate: actix_web::web::Data<AppState>,
     path: actix_web::web::Path<String>,
+    req: actix_web::HttpRequest,
 ) -> AtomicServerResult<HttpResponse> {
-    let template = include_str!("../../app_assets/index.html");
-    let subject = format!("{}/{}", appstate.store.get_server_url(), path);
+    let template = appstate
+        .assets
+        .index_html()
+        .ok_or("No front-end is served in API-only mode")?;
+    let subject = format!("{}/{}", crate::domains::base_url(&appstate, &req), path);
     let meta_tags: MetaTags = if let Ok(resource) =
         appstate
             .store
@@ -45,7 +49,7 @@ use atomic_lib::Resource;
 use atomic_lib::Storelike;
 
 /* HTML tags for social media and link previews. Also includes JSON-AD body of the requested resource, if publicly available. */
-struct MetaTags {
+pub(crate) struct MetaTags {
     description: String,
     title: String,
     image: String,
@@ -70,12 +74,8 @@ impl From<Resource> for MetaTags {
         } else {
             "/default_social_preview.jpg".to_string()
         };
-        let json = if let Ok(serialized) = r.to_json_ad() {
-            // TODO: also fetch the parents for extra fast first renders.
-            Some(serialized)
-        } else {
-            None
-        };
+        // TODO: also fetch the parents for extra fast first renders.
+        let json = r.to_json_ad().ok();
         Self {
             description,
             title,
@@ -125,10 +125,10 @@ impl Display for MetaTags {
     }
 }
 
-fn escape_html(s: &str) -> String {
-    s.replace('<', "&lt;")
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
         .replace('>', "&gt;")
-        .replace('&', "&amp;")
         .replace('\'', "&#x27;")
         .replace('"', "&quot;")
         .replace('/', "&#x2F;")
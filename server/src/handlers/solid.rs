@@ -0,0 +1,193 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy, urls, AtomicError, Resource, Storelike, Value};
+
+use crate::{
+    appstate::AppState,
+    audit_log::{self, AuditEntry, AuditEvent},
+    errors::AtomicServerResult,
+    helpers::{get_client_agent, get_client_agent_for_write},
+    solid::{self, SolidPath, MIME_TURTLE},
+};
+
+fn parse(appstate: &AppState, path: Option<web::Path<String>>) -> SolidPath {
+    let path = path.map(|p| p.into_inner()).unwrap_or_default();
+    solid::parse_path(&appstate.config.server_url, &path)
+}
+
+/// Clients sign the URL that they request, which is the Solid URL.
+fn request_url(appstate: &AppState, req: &actix_web::HttpRequest) -> String {
+    crate::helpers::request_url(req, &appstate.config)
+}
+
+/// The Agent that performs a write. Writes are always authenticated.
+fn writing_agent(appstate: &AppState, req: &actix_web::HttpRequest) -> AtomicServerResult<String> {
+    match get_client_agent_for_write(req.headers(), appstate, request_url(appstate, req))? {
+        Some(agent) if agent != urls::PUBLIC_AGENT => Ok(agent),
+        _ => Err(AtomicError::unauthorized(
+            "Sign the request or use an API token to edit resources using Solid.".into(),
+        )
+        .into()),
+    }
+}
+
+/// The commits are signed by the server, so we record the Agent that made the change.
+fn record(appstate: &AppState, req: &actix_web::HttpRequest, agent: &str, subject: &str) {
+    let entry = AuditEntry::from_request(
+        req,
+        AuditEvent::Commit,
+        Some(agent.to_string()),
+        Some(subject.to_string()),
+        "applied".into(),
+    );
+    audit_log::record(appstate, entry);
+}
+
+/// Returns the resource as Turtle, or its WAC document if the path ends with `.acl`.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn handle_get(
+    path: Option<web::Path<String>>,
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let SolidPath { subject, acl } = parse(&appstate, path);
+    let store = &appstate.store;
+    let for_agent = get_client_agent(req.headers(), &appstate, request_url(&appstate, &req))?;
+    let resource = store.get_resource_extended(&subject, false, for_agent.as_deref())?;
+    let children = solid::children(store, &subject, for_agent.as_deref())?;
+    let container = solid::is_container(&resource, &children);
+    let url = solid::solid_url(store.get_server_url(), &subject, container);
+
+    let body = if acl {
+        // Only those who can change the rights can see them, like `acl:Control`
+        hierarchy::check_write(
+            store,
+            &resource,
+            for_agent.as_deref().unwrap_or(urls::PUBLIC_AGENT),
+        )?;
+        solid::acl_turtle(store, &resource, &url)?
+    } else {
+        solid::to_turtle(store, &resource, &url, &children)?
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(MIME_TURTLE)
+        .insert_header(("Link", solid::link_header(&url, container)))
+        .insert_header((
+            "WAC-Allow",
+            solid::wac_allow_header(store, &resource, for_agent.as_deref()),
+        ))
+        .body(body))
+}
+
+/// Creates or replaces the resource using a Turtle body.
+#[tracing::instrument(skip(appstate, req, body))]
+pub async fn handle_put(
+    path: Option<web::Path<String>>,
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Bytes,
+) -> AtomicServerResult<HttpResponse> {
+    let SolidPath { subject, acl } = parse(&appstate, path);
+    if acl {
+        return Err(AtomicError::method_not_allowed(
+            "Rights are managed using the `read` and `write` properties of the resource.",
+        )
+        .into());
+    }
+    let agent = writing_agent(&appstate, &req)?;
+    let url = solid::solid_url(&appstate.config.server_url, &subject, false);
+    let propvals = solid::parse_turtle(&appstate.store, &body, &url)?;
+    let created = write_resource(&appstate, &agent, &subject, propvals)?;
+    record(&appstate, &req, &agent, &subject);
+    Ok(if created {
+        HttpResponse::Created()
+            .insert_header(("Location", url))
+            .finish()
+    } else {
+        HttpResponse::NoContent().finish()
+    })
+}
+
+/// Creates a child of the container. Uses the `Slug` header as the last part of its subject, if there is one.
+#[tracing::instrument(skip(appstate, req, body))]
+pub async fn handle_post(
+    path: Option<web::Path<String>>,
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Bytes,
+) -> AtomicServerResult<HttpResponse> {
+    let SolidPath { subject, acl } = parse(&appstate, path);
+    if acl {
+        return Err(AtomicError::method_not_allowed("ACL documents can not be edited.").into());
+    }
+    let slug = req
+        .headers()
+        .get("Slug")
+        .and_then(|v| v.to_str().ok())
+        .map(|slug| sanitize_filename::sanitize(slug).replace(' ', "-"))
+        .filter(|slug| !slug.is_empty())
+        .unwrap_or_else(|| atomic_lib::utils::random_string(10));
+    let child = format!("{}/{}", subject, slug);
+    if appstate.store.get_resource(&child).is_ok() {
+        return Err(format!("{} already exists", child).into());
+    }
+    let agent = writing_agent(&appstate, &req)?;
+    let url = solid::solid_url(&appstate.config.server_url, &child, false);
+    let mut propvals = solid::parse_turtle(&appstate.store, &body, &url)?;
+    propvals.insert(urls::PARENT.into(), Value::AtomicUrl(subject));
+    write_resource(&appstate, &agent, &child, propvals)?;
+    record(&appstate, &req, &agent, &child);
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", url))
+        .finish())
+}
+
+/// Returns true if the resource was created.
+fn write_resource(
+    appstate: &AppState,
+    agent: &str,
+    subject: &str,
+    propvals: std::collections::HashMap<String, Value>,
+) -> AtomicServerResult<bool> {
+    let store = &appstate.store;
+    let (mut resource, created) = match store.get_resource(subject) {
+        Ok(existing) => {
+            hierarchy::check_write(store, &existing, agent)?;
+            (existing, false)
+        }
+        Err(_not_found) => {
+            let mut new = Resource::new(subject.to_string());
+            // New resources are placed in the container of their path, or else in the Drive
+            let parent = subject
+                .rsplit_once('/')
+                .map(|(parent, _)| parent.to_string())
+                .filter(|parent| store.get_resource(parent).is_ok())
+                .unwrap_or_else(|| store.get_server_url().to_string());
+            new.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(parent));
+            (new, true)
+        }
+    };
+    solid::replace_values(&mut resource, propvals);
+    // Checks the rights in the (possibly new) parent
+    hierarchy::check_append(store, &resource, agent)?;
+    resource.save(store)?;
+    Ok(created)
+}
+
+#[tracing::instrument(skip(appstate, req))]
+pub async fn handle_delete(
+    path: Option<web::Path<String>>,
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let SolidPath { subject, acl } = parse(&appstate, path);
+    if acl {
+        return Err(AtomicError::method_not_allowed("ACL documents can not be deleted.").into());
+    }
+    let agent = writing_agent(&appstate, &req)?;
+    let store = &appstate.store;
+    let mut resource = store.get_resource(&subject)?;
+    hierarchy::check_write(store, &resource, &agent)?;
+    resource.destroy(store)?;
+    record(&appstate, &req, &agent, &subject);
+    Ok(HttpResponse::NoContent().finish())
+}
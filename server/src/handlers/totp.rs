@@ -0,0 +1,100 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{urls, AtomicError};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::{get_bearer_token, get_client_agent},
+    sessions::{session_cookie_header, session_cookie_value},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct CodeRequest {
+    /// A code from the authenticator app, or a recovery code
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyRequest {
+    pub ticket: String,
+    pub code: String,
+}
+
+/// Returns the Agent that signed the request. API tokens can't be used to change the second factor.
+fn signed_agent(appstate: &AppState, req: &actix_web::HttpRequest) -> AtomicServerResult<String> {
+    if get_bearer_token(req.headers())?.is_some() {
+        return Err(AtomicError::unauthorized(
+            "API tokens can not be used to manage TOTP. Sign the request instead.".into(),
+        )
+        .into());
+    }
+    let subject = crate::helpers::request_url(req, &appstate.config);
+    match get_client_agent(req.headers(), appstate, subject)? {
+        Some(agent) if agent != urls::PUBLIC_AGENT => Ok(agent),
+        _ => Err(AtomicError::unauthorized("Sign in to manage TOTP.".into()).into()),
+    }
+}
+
+#[tracing::instrument(skip(appstate, req))]
+pub async fn status(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "enabled": appstate.totp.is_enabled(&agent)?,
+    })))
+}
+
+/// Creates a secret and recovery codes. These are only returned once.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn enroll(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    Ok(HttpResponse::Ok().json(appstate.totp.enroll(&appstate.store, &agent)?))
+}
+
+#[tracing::instrument(skip(appstate, req, body))]
+pub async fn confirm(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Json<CodeRequest>,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    appstate.totp.confirm(&appstate.store, &agent, &body.code)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(skip(appstate, req, body))]
+pub async fn disable(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    body: web::Json<CodeRequest>,
+) -> AtomicServerResult<HttpResponse> {
+    let agent = signed_agent(&appstate, &req)?;
+    appstate.totp.disable(&appstate.store, &agent, &body.code)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Exchanges the ticket of a passkey or OIDC login and a code for a session cookie.
+#[tracing::instrument(skip(appstate, body))]
+pub async fn verify(
+    appstate: web::Data<AppState>,
+    body: web::Json<VerifyRequest>,
+) -> AtomicServerResult<HttpResponse> {
+    let subject = appstate
+        .totp
+        .take_ticket(&appstate.store, &body.ticket, &body.code)?;
+    let agent = appstate
+        .agent_keys
+        .get(&appstate.store, &subject)?
+        .ok_or("The server does not hold the private key of this Agent")?;
+    let server_url = &appstate.config.server_url;
+    let session = session_cookie_value(&agent, server_url)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Set-Cookie", session_cookie_header(&session, server_url)))
+        .json(serde_json::json!({ "agent": agent.subject })))
+}
@@ -3,13 +3,29 @@ use std::{ffi::OsStr, io::Write, path::Path};
 use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
 use atomic_lib::{
-    commit::CommitResponse, hierarchy::check_write, urls, utils::now, AtomicError, Resource,
-    Storelike, Value,
+    commit::CommitResponse,
+    hierarchy::check_write,
+    plugins::{
+        features::{check_enabled, Feature},
+        quotas,
+    },
+    storelike::Query,
+    urls,
+    utils::now,
+    AtomicError, Db, Resource, Storelike, Value,
 };
 use futures::{StreamExt, TryStreamExt};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+use crate::{
+    appstate::AppState,
+    audit_log::{self, AuditEntry, AuditEvent},
+    errors::AtomicServerResult,
+    helpers::get_client_agent_for_write,
+    rate_limit::{limit_agent, Category},
+    scanner::{ScanResult, SCAN_STATUS_INFECTED},
+};
 
 #[derive(Deserialize, Debug)]
 pub struct UploadQuery {
@@ -20,8 +36,14 @@ pub struct UploadQuery {
 /// A parent Query parameter is required for checking rights and for placing the file in a Hierarchy.
 /// Creates new File resources for every submitted file.
 /// Submission is done using multipart/form-data.
-/// The file is stored in the `/uploads` directory.
-/// An `attachment` relationship is created from the parent
+/// The file is stored in the `/uploads` directory, or in the configured S3 bucket.
+/// An `attachment` relationship is created from the parent.
+/// Every File gets a SHA-256 `checksum`. If a File with the same checksum already exists,
+/// the new File shares its stored blob (`internalId`) instead of storing the same bytes twice.
+/// If a virus scanner is configured, every file is scanned before it is stored, and its `scanStatus` is set.
+/// Infected files are rejected, or moved to the `quarantine` folder when `--quarantine-infected` is set.
+/// With the `file-text` feature, the text of PDF, docx and plain text files is stored in `fileText`, so it can be searched.
+/// Uploads can be disabled per Drive in its feature settings, and are limited by the quota of the Drive.
 #[tracing::instrument(skip(appstate, req, body))]
 pub async fn upload_handler(
     mut body: Multipart,
@@ -31,6 +53,7 @@ pub async fn upload_handler(
 ) -> AtomicServerResult<HttpResponse> {
     let store = &appstate.store;
     let parent = store.get_resource(&query.parent)?;
+    check_enabled(store, &parent, Feature::Uploads)?;
     let subject = format!(
         "{}{}",
         store.get_server_url(),
@@ -39,14 +62,19 @@ pub async fn upload_handler(
             .path_and_query()
             .ok_or("Path must be given")?
     );
-    if let Some(agent) = get_client_agent(req.headers(), &appstate, subject)? {
-        check_write(store, &parent, &agent)?;
-    } else {
-        return Err(AtomicError::unauthorized(
-            "No authorization headers present. These are required when uploading files.".into(),
-        )
-        .into());
-    }
+    let agent = match get_client_agent_for_write(req.headers(), &appstate, subject)? {
+        Some(agent) => {
+            limit_agent(&appstate, Category::Commit, Some(&agent))?;
+            check_write(store, &parent, &agent)?;
+            agent
+        }
+        None => {
+            return Err(AtomicError::unauthorized(
+                "No authorization headers present. These are required when uploading files.".into(),
+            )
+            .into())
+        }
+    };
 
     let mut created_resources: Vec<Resource> = Vec::new();
     let mut commit_responses: Vec<CommitResponse> = Vec::new();
@@ -67,14 +95,16 @@ pub async fn upload_handler(
 
         let mut file_path = appstate.config.uploads_path.clone();
         file_path.push(&file_id);
-        let mut file = std::fs::File::create(file_path)?;
+        let mut file = std::fs::File::create(&file_path)?;
 
+        let mut hasher = Sha256::new();
         // Field in turn is stream of *Bytes* object
         while let Some(chunk) = field.next().await {
             let data = chunk.map_err(|e| format!("Error while reading multipart data. {}", e))?;
-            // TODO: Update a SHA256 hash here for checksum
+            hasher.update(&data);
             file.write_all(&data)?;
         }
+        let checksum = base64::encode(hasher.finalize());
 
         let byte_count: i64 = file
             .metadata()?
@@ -82,6 +112,73 @@ pub async fn upload_handler(
             .try_into()
             .map_err(|_e| "Too large")?;
 
+        // Files are checked before they are stored, the Commit that creates the File checks again
+        if let Err(e) = quotas::check_upload(store, &parent, byte_count as u64) {
+            std::fs::remove_file(&file_path)?;
+            return Err(e.into());
+        }
+
+        let scan_result = match &appstate.scanner {
+            Some(scanner) => {
+                let scanner = scanner.clone();
+                let path = file_path.clone();
+                let result = web::block(move || scanner.scan(&path))
+                    .await
+                    .map_err(|e| format!("Failed to scan file {}: {}", filename, e))?;
+                Some(result?)
+            }
+            None => None,
+        };
+
+        // Extracted before the file is moved to the file store
+        #[cfg(feature = "file-text")]
+        let file_text = if matches!(scan_result, Some(ScanResult::Infected(_))) {
+            None
+        } else {
+            let path = file_path.clone();
+            let mimetype = guess_mime_for_filename(filename);
+            web::block(move || crate::file_text::extract(&path, &mimetype))
+                .await
+                .map_err(|e| format!("Failed to read text of {}: {}", filename, e))?
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Could not extract text from {}: {}", file_id, e);
+                    None
+                })
+        };
+
+        let internal_id = if let Some(ScanResult::Infected(signature)) = &scan_result {
+            tracing::warn!(
+                "Upload {} was flagged by the scanner: {}",
+                file_id,
+                signature
+            );
+            if !appstate.config.opts.quarantine_infected {
+                std::fs::remove_file(&file_path)?;
+                return Err(format!(
+                    "File {} was rejected, because it contains malware: {}",
+                    filename, signature
+                )
+                .into());
+            }
+            quarantine_file(&appstate.config.uploads_path, &file_path, &file_id)?;
+            file_id.clone()
+        } else {
+            match find_blob_by_checksum(store, &checksum)? {
+                Some(existing_id) => {
+                    tracing::debug!("Reusing stored file {} for {}", existing_id, file_id);
+                    std::fs::remove_file(&file_path)?;
+                    existing_id
+                }
+                None => {
+                    appstate
+                        .file_store
+                        .upload_file(&file_id, &file_path)
+                        .await?;
+                    file_id.clone()
+                }
+            }
+        };
+
         let subject_path = format!("files/{}", urlencoding::encode(&file_id));
         let new_subject = format!("{}/{}", store.get_server_url(), subject_path);
         let download_url = format!("{}/download/{}", store.get_server_url(), subject_path);
@@ -89,7 +186,8 @@ pub async fn upload_handler(
         let mut resource = atomic_lib::Resource::new_instance(urls::FILE, store)?;
         resource.set_subject(new_subject);
         resource.set_propval_string(urls::PARENT.into(), &query.parent, store)?;
-        resource.set_propval_string(urls::INTERNAL_ID.into(), &file_id, store)?;
+        resource.set_propval_string(urls::INTERNAL_ID.into(), &internal_id, store)?;
+        resource.set_propval_string(urls::CHECKSUM.into(), &checksum, store)?;
         resource.set_propval(urls::FILESIZE.into(), Value::Integer(byte_count), store)?;
         resource.set_propval_string(
             urls::MIMETYPE.into(),
@@ -98,7 +196,14 @@ pub async fn upload_handler(
         )?;
         resource.set_propval_string(urls::FILENAME.into(), filename, store)?;
         resource.set_propval_string(urls::DOWNLOAD_URL.into(), &download_url, store)?;
-        commit_responses.push(resource.save(store)?);
+        if let Some(scan_result) = &scan_result {
+            resource.set_propval_string(urls::SCAN_STATUS.into(), scan_result.status(), store)?;
+        }
+        #[cfg(feature = "file-text")]
+        if let Some(text) = file_text {
+            resource.set_propval(urls::FILE_TEXT.into(), Value::String(text), store)?;
+        }
+        commit_responses.push(resource.save_on_behalf_of(store, &agent)?);
         created_resources.push(resource);
     }
 
@@ -113,7 +218,19 @@ pub async fn upload_handler(
     for created in created_file_subjects {
         parent.push_propval(urls::ATTACHMENTS, created.into(), false)?;
     }
-    commit_responses.push(parent.save(store)?);
+    commit_responses.push(parent.save_on_behalf_of(store, &agent)?);
+    // The Commits are signed by the server on behalf of the Agent, so we record the Agent that uploaded the files
+    for response in &commit_responses {
+        let subject = response.commit_struct.subject.clone();
+        let entry = AuditEntry::from_request(
+            &req,
+            AuditEvent::Commit,
+            Some(agent.clone()),
+            Some(subject),
+            "applied".into(),
+        );
+        audit_log::record(&appstate, entry);
+    }
 
     let mut builder = HttpResponse::Ok();
 
@@ -122,6 +239,39 @@ pub async fn upload_handler(
     )?))
 }
 
+/// Returns the `internalId` of an existing File with this checksum, if there is one.
+/// Multiple File resources can point to the same stored blob,
+/// so a blob should only be removed when no File refers to it anymore.
+/// Quarantined files are skipped, since these are not in the file store.
+fn find_blob_by_checksum(store: &Db, checksum: &str) -> AtomicServerResult<Option<String>> {
+    let mut query = Query::new_prop_val(urls::CHECKSUM, checksum);
+    query.include_nested = true;
+    let result = store.query(&query)?;
+    let internal_id = result
+        .resources
+        .iter()
+        .filter(|r| !is_quarantined(r))
+        .find_map(|r| r.get(urls::INTERNAL_ID).ok())
+        .map(|id| id.to_string());
+    Ok(internal_id)
+}
+
+/// Whether the File has been flagged by the virus scanner.
+pub fn is_quarantined(resource: &Resource) -> bool {
+    resource
+        .get(urls::SCAN_STATUS)
+        .map(|status| status.to_string() == SCAN_STATUS_INFECTED)
+        .unwrap_or(false)
+}
+
+/// Moves a flagged upload to the `quarantine` folder in the `uploads_path`, where it is never served from.
+fn quarantine_file(uploads_path: &Path, file_path: &Path, file_id: &str) -> AtomicServerResult<()> {
+    let quarantine_path = uploads_path.join("quarantine");
+    std::fs::create_dir_all(&quarantine_path)?;
+    std::fs::rename(file_path, quarantine_path.join(file_id))?;
+    Ok(())
+}
+
 fn guess_mime_for_filename(filename: &str) -> String {
     if let Some(ext) = get_extension_from_filename(filename) {
         actix_files::file_extension_to_mime(ext).to_string()
@@ -133,3 +283,45 @@ fn guess_mime_for_filename(filename: &str) -> String {
 fn get_extension_from_filename(filename: &str) -> Option<&str> {
     Path::new(filename).extension().and_then(OsStr::to_str)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_blob_by_checksum() {
+        let store = Db::init_temp("finds_blob_by_checksum").unwrap();
+        let checksum = base64::encode(Sha256::digest(b"hello world"));
+        assert_eq!(find_blob_by_checksum(&store, &checksum).unwrap(), None);
+
+        let mut file = Resource::new_generate_subject(&store);
+        file.set_propval_string(urls::INTERNAL_ID.into(), "1-hello.txt", &store)
+            .unwrap();
+        file.set_propval_string(urls::CHECKSUM.into(), &checksum, &store)
+            .unwrap();
+        file.save_locally(&store).unwrap();
+
+        assert_eq!(
+            find_blob_by_checksum(&store, &checksum).unwrap(),
+            Some("1-hello.txt".to_string())
+        );
+    }
+
+    #[test]
+    fn quarantined_blobs_are_not_reused() {
+        let store = Db::init_temp("quarantined_blobs_are_not_reused").unwrap();
+        let checksum = base64::encode(Sha256::digest(b"evil"));
+
+        let mut file = Resource::new_generate_subject(&store);
+        file.set_propval_string(urls::INTERNAL_ID.into(), "1-evil.txt", &store)
+            .unwrap();
+        file.set_propval_string(urls::CHECKSUM.into(), &checksum, &store)
+            .unwrap();
+        file.set_propval_string(urls::SCAN_STATUS.into(), SCAN_STATUS_INFECTED, &store)
+            .unwrap();
+        file.save_locally(&store).unwrap();
+
+        assert!(is_quarantined(&file));
+        assert_eq!(find_blob_by_checksum(&store, &checksum).unwrap(), None);
+    }
+}
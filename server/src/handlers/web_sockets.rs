@@ -14,11 +14,17 @@ use atomic_lib::{
     errors::AtomicResult,
     Db, Storelike,
 };
-use std::time::{Duration, Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    actor_messages::CommitMessage, appstate::AppState, commit_monitor::CommitMonitor,
-    errors::AtomicServerResult, helpers::get_auth_headers,
+    actor_messages::{CommitMessage, Disconnect, Lagged, Unsubscribe},
+    appstate::AppState,
+    commit_monitor::{CommitMonitor, WebSocketMetrics},
+    errors::AtomicServerResult,
+    helpers::get_auth_headers,
 };
 
 /// Get an HTTP request, upgrade it to a Websocket connection
@@ -42,6 +48,11 @@ pub async fn web_socket_handler(
             for_agent,
             // We need to make sure this is easily clone-able
             appstate.store.clone(),
+            ConnectionLimits {
+                max_subscriptions: appstate.config.opts.ws_max_subscriptions,
+                queue_size: appstate.config.opts.ws_queue_size,
+            },
+            appstate.ws_metrics.clone(),
         ),
         &req,
         stream,
@@ -52,6 +63,13 @@ pub async fn web_socket_handler(
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Limits the resources that a single connection can use, see `--ws-max-subscriptions` and `--ws-queue-size`.
+struct ConnectionLimits {
+    max_subscriptions: usize,
+    /// Amount of Commits that can wait to be sent, before new ones are dropped
+    queue_size: usize,
+}
+
 pub struct WebSocketConnection {
     /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
     /// otherwise we drop connection.
@@ -64,14 +82,26 @@ pub struct WebSocketConnection {
     /// If it's not specified, it's the Public Agent.
     agent: String,
     store: Db,
+    limits: ConnectionLimits,
+    metrics: Arc<WebSocketMetrics>,
 }
 
 impl Actor for WebSocketConnection {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
+        // The CommitMonitor drops Commits when the mailbox is full, so slow clients can't make the server buffer endlessly
+        ctx.set_mailbox_capacity(self.limits.queue_size);
+        self.metrics.connection_opened();
         self.hb(ctx);
     }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        self.commit_monitor_addr.do_send(Disconnect {
+            addr: ctx.address(),
+        });
+        self.metrics.connection_closed();
+    }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketConnection {
@@ -107,6 +137,15 @@ fn handle_ws_message(
                 s if s.starts_with("SUBSCRIBE ") => {
                     let mut parts = s.split("SUBSCRIBE ");
                     if let Some(subject) = parts.nth(1) {
+                        if conn.subscribed.len() >= conn.limits.max_subscriptions
+                            && !conn.subscribed.contains(subject)
+                        {
+                            ctx.text(format!(
+                                "ERROR Can't subscribe to {}, the maximum of {} subscriptions has been reached",
+                                subject, conn.limits.max_subscriptions
+                            ));
+                            return Ok(());
+                        }
                         conn.commit_monitor_addr
                             .do_send(crate::actor_messages::Subscribe {
                                 addr: ctx.address(),
@@ -123,6 +162,10 @@ fn handle_ws_message(
                     let mut parts = s.split("UNSUBSCRIBE ");
                     if let Some(subject) = parts.nth(1) {
                         conn.subscribed.remove(subject);
+                        conn.commit_monitor_addr.do_send(Unsubscribe {
+                            addr: ctx.address(),
+                            subject: subject.to_string(),
+                        });
                         Ok(())
                     } else {
                         Err("UNSUBSCRIBE needs a subject".into())
@@ -198,7 +241,13 @@ fn handle_ws_message(
 }
 
 impl WebSocketConnection {
-    fn new(commit_monitor_addr: Addr<CommitMonitor>, agent: String, store: Db) -> Self {
+    fn new(
+        commit_monitor_addr: Addr<CommitMonitor>,
+        agent: String,
+        store: Db,
+        limits: ConnectionLimits,
+        metrics: Arc<WebSocketMetrics>,
+    ) -> Self {
         let size = std::mem::size_of::<Db>();
         if size > 10000 {
             tracing::warn!(
@@ -214,6 +263,8 @@ impl WebSocketConnection {
             commit_monitor_addr,
             agent,
             store,
+            limits,
+            metrics,
         }
     }
 
@@ -252,3 +303,11 @@ impl Handler<CommitMessage> for WebSocketConnection {
         ctx.text(formatted_commit);
     }
 }
+
+impl Handler<Lagged> for WebSocketConnection {
+    type Result = ();
+
+    fn handle(&mut self, msg: Lagged, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.text(format!("LAGGED {}", msg.dropped));
+    }
+}
@@ -1,13 +1,16 @@
 //! Functions useful in the server
 
 use actix_web::cookie::Cookie;
+use actix_web::dev::ConnectionInfo;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::Uri;
 use atomic_lib::authentication::AuthValues;
 use atomic_lib::AtomicError;
 use percent_encoding::percent_decode_str;
+use std::net::SocketAddr;
 use std::str::FromStr;
 
+use crate::config::Config;
 use crate::errors::{AppErrorType, AtomicServerError};
 use crate::{appstate::AppState, content_types::ContentType, errors::AtomicServerResult};
 
@@ -125,10 +128,7 @@ pub fn get_auth(
     map: &HeaderMap,
     requested_subject: String,
 ) -> AtomicServerResult<Option<AuthValues>> {
-    let from_header = match get_auth_headers(map, requested_subject.clone()) {
-        Ok(res) => res,
-        Err(err) => return Err(err),
-    };
+    let from_header = get_auth_headers(map, requested_subject.clone())?;
 
     match from_header {
         Some(v) => Ok(Some(v)),
@@ -136,27 +136,170 @@ pub fn get_auth(
     }
 }
 
+/// Returns the value of an `Authorization: Bearer {token}` header, if there is one.
+pub fn get_bearer_token(map: &HeaderMap) -> AtomicServerResult<Option<&str>> {
+    match map.get("Authorization") {
+        Some(header) => Ok(header
+            .to_str()
+            .map_err(|_e| "Only string headers allowed")?
+            .strip_prefix("Bearer ")
+            .map(str::trim)),
+        None => Ok(None),
+    }
+}
+
 /// Checks for authentication headers and returns Some agent's subject if everything is well.
-/// Skips these checks in public_mode and returns Ok(None).
 #[tracing::instrument(skip(appstate))]
 pub fn get_client_agent(
     headers: &HeaderMap,
     appstate: &AppState,
     requested_subject: String,
 ) -> AtomicServerResult<Option<String>> {
-    if appstate.config.opts.public_mode {
-        return Ok(None);
+    get_client_agent_opts(headers, appstate, requested_subject, false)
+}
+
+/// Same as [get_client_agent], but for requests that change something, such as uploads.
+/// Read-only API tokens are rejected.
+pub fn get_client_agent_for_write(
+    headers: &HeaderMap,
+    appstate: &AppState,
+    requested_subject: String,
+) -> AtomicServerResult<Option<String>> {
+    get_client_agent_opts(headers, appstate, requested_subject, true)
+}
+
+/// Same as [get_client_agent], but also accepts [crate::read_tokens].
+/// Only use this for reading resources, never for endpoints that manage accounts or credentials.
+pub fn get_client_agent_for_resource(
+    headers: &HeaderMap,
+    appstate: &AppState,
+    requested_subject: String,
+) -> AtomicServerResult<Option<String>> {
+    if let Some(token) = crate::read_tokens::get_token(headers, &requested_subject)? {
+        if let Some(agent) =
+            crate::read_tokens::verify_token(&appstate.store, &token, &requested_subject)?
+        {
+            return Ok(Some(agent));
+        }
+    }
+    authenticate(headers, appstate, requested_subject, false)
+}
+
+fn get_client_agent_opts(
+    headers: &HeaderMap,
+    appstate: &AppState,
+    requested_subject: String,
+    write: bool,
+) -> AtomicServerResult<Option<String>> {
+    if crate::read_tokens::get_token(headers, &requested_subject)?.is_some() {
+        return Err(AtomicError::unauthorized(
+            "Read tokens can only be used to read resources. Sign the request instead.".into(),
+        )
+        .into());
+    }
+    authenticate(headers, appstate, requested_subject, write)
+}
+
+fn authenticate(
+    headers: &HeaderMap,
+    appstate: &AppState,
+    requested_subject: String,
+    write: bool,
+) -> AtomicServerResult<Option<String>> {
+    if let Some(value) = get_bearer_token(headers)? {
+        let token = appstate
+            .api_tokens
+            .validate(value)
+            .map_err(|e| AtomicServerError::authentication_failed(e.message))?;
+        token.check_scope(&appstate.store, &requested_subject, write)?;
+        if atomic_lib::agents::is_disabled(&appstate.store, &token.agent) {
+            return Err(AtomicError::unauthorized(format!(
+                "Agent {} has been disabled",
+                token.agent
+            ))
+            .into());
+        }
+        if atomic_lib::agents::key_rotated_at(&appstate.store, &token.agent)
+            .is_some_and(|rotated_at| token.created_at <= rotated_at)
+        {
+            return Err(AtomicError::unauthorized(format!(
+                "API token was created before the key of {} was rotated",
+                token.agent
+            ))
+            .into());
+        }
+        return Ok(Some(token.agent));
     }
     // Authentication check. If the user has no headers, continue with the Public Agent.
-    let auth_header_values = get_auth(headers, requested_subject)?;
+    let auth_header_values = get_auth(headers, requested_subject)
+        .map_err(|e| AtomicServerError::authentication_failed(e.message))?;
     let for_agent = atomic_lib::authentication::get_agent_from_auth_values_and_check(
         auth_header_values,
         &appstate.store,
     )
-    .map_err(|e| format!("Authentication failed: {}", e))?;
+    .map_err(|e| {
+        AtomicServerError::authentication_failed(format!("Authentication failed: {}", e))
+    })?;
     Ok(Some(for_agent))
 }
 
+/// Whether the subject is the parent, or one of its descendants.
+/// Descendants are found by walking up the `parent` properties, not by looking at the URL,
+/// because any resource can be created at a URL that starts with the subject of the parent.
+pub fn in_subtree(store: &impl atomic_lib::Storelike, parent: &str, subject: &str) -> bool {
+    let without_query = subject.split('?').next().unwrap_or(subject);
+    if subject == parent || without_query == parent {
+        return true;
+    }
+    store
+        .get_resource(without_query)
+        .and_then(|resource| resource.get_parent_tree(store))
+        .map(|tree| tree.iter().any(|r| r.get_subject() == parent))
+        .unwrap_or(false)
+}
+
+/// Whether the `Forwarded` and `X-Forwarded-*` headers are set by a reverse proxy, so clients can't spoof them.
+fn trusts_forwarded_headers(config: &Config) -> bool {
+    config.opts.trust_forwarded_headers || config.opts.rate_limit_behind_proxy
+}
+
+/// The IP address of the client, or of the reverse proxy if its headers are not trusted.
+pub fn client_ip(
+    connection_info: &ConnectionInfo,
+    peer_addr: Option<SocketAddr>,
+    config: &Config,
+) -> Option<String> {
+    if trusts_forwarded_headers(config) {
+        connection_info.realip_remote_addr().map(String::from)
+    } else {
+        peer_addr.map(|addr| addr.ip().to_string())
+    }
+}
+
+/// The host (and port) that the client requested, which the reverse proxy may have changed.
+pub fn request_host(req: &actix_web::HttpRequest, config: &Config) -> Option<String> {
+    if config.opts.trust_forwarded_headers {
+        Some(req.connection_info().host().to_string())
+    } else {
+        req.headers()
+            .get("host")
+            .and_then(|host| host.to_str().ok())
+            .map(String::from)
+    }
+}
+
+/// The URL that the client requested, without the query string. Clients sign this URL in their authentication headers.
+/// Includes the path prefix, which is also part of the server URL.
+pub fn request_url(req: &actix_web::HttpRequest, config: &Config) -> String {
+    let base = if config.opts.trust_forwarded_headers {
+        let info = req.connection_info();
+        format!("{}://{}", info.scheme(), info.host())
+    } else {
+        origin(&config.server_url)
+    };
+    format!("{}{}", base, req.path())
+}
+
 /// Finds the extension
 pub fn try_extension(path: &str) -> Option<(ContentType, &str)> {
     let items: Vec<&str> = path.split('.').collect();
@@ -168,6 +311,9 @@ pub fn try_extension(path: &str) -> Option<(ContentType, &str)> {
             "jsonad" => ContentType::JsonAd,
             "html" => ContentType::Html,
             "ttl" => ContentType::Turtle,
+            "rss" => ContentType::Rss,
+            "atom" => ContentType::Atom,
+            "ics" => ContentType::ICalendar,
             _ => return None,
         };
         return Some((content_type, path));
@@ -253,4 +399,31 @@ mod test {
 
         assert_eq!(out.requested_subject, subject);
     }
+
+    #[test]
+    fn request_url_behind_proxy() {
+        use clap::Parser;
+        let opts = crate::config::Opts::parse_from([
+            "atomic-server",
+            "--config-dir",
+            "./.temp/proxy/config",
+            "--data-dir",
+            "./.temp/proxy/db",
+            "--path-prefix",
+            "atomic/",
+            "--trust-forwarded-headers",
+        ]);
+        let config = crate::config::build_config(opts).unwrap();
+        assert_eq!(config.server_url, "http://localhost:9883/atomic");
+
+        let req = actix_web::test::TestRequest::with_uri("/atomic/tokens")
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .insert_header(("X-Forwarded-Host", "example.com"))
+            .to_http_request();
+        assert_eq!(
+            request_url(&req, &config),
+            "https://example.com/atomic/tokens"
+        );
+        assert_eq!(request_host(&req, &config).unwrap(), "example.com");
+    }
 }
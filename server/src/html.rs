@@ -0,0 +1,241 @@
+//! Renders resources as plain HTML, for clients that don't run the JavaScript front-end, like crawlers and text browsers.
+//! Articles, Collections and ChatRooms have their own layout, other resources are shown as a list of their properties.
+//! Clients get these pages by adding `.html` to the URL, or when their User-Agent is known not to run JavaScript.
+
+use actix_web::http::header::HeaderMap;
+use atomic_lib::{urls, values::SubResource, Resource, Storelike, Value};
+
+use crate::{
+    errors::AtomicServerResult,
+    handlers::single_page_app::{escape_html, MetaTags},
+};
+
+/// Parts of User-Agents of crawlers, link previewers and text browsers.
+const NO_JS_USER_AGENTS: &[&str] = &[
+    "bot",
+    "crawler",
+    "spider",
+    "slurp",
+    "facebookexternalhit",
+    "embedly",
+    "lynx",
+    "w3m",
+    "links",
+];
+
+/// Whether the request should get a server rendered page instead of the single page app.
+pub fn is_no_js_request(path: &str, headers: &HeaderMap) -> bool {
+    if path.ends_with(".html") {
+        return true;
+    }
+    headers
+        .get("user-agent")
+        .and_then(|ua| ua.to_str().ok())
+        .map(|ua| ua.to_lowercase())
+        .is_some_and(|ua| NO_JS_USER_AGENTS.iter().any(|part| ua.contains(part)))
+}
+
+fn is_a(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .is_ok_and(|classes| classes.iter().any(|c| c == class))
+}
+
+fn string_prop(resource: &Resource, property: &str) -> Option<String> {
+    resource.get(property).ok().map(|v| v.to_string())
+}
+
+fn title(resource: &Resource) -> String {
+    string_prop(resource, urls::NAME)
+        .or_else(|| string_prop(resource, urls::SHORTNAME))
+        .unwrap_or_else(|| resource.get_subject().to_string())
+}
+
+fn link(subject: &str, text: &str) -> String {
+    format!(
+        "<a href=\"{}\">{}</a>",
+        escape_html(subject),
+        escape_html(text)
+    )
+}
+
+/// Links to the resource, using its title if the Agent can read it.
+fn link_to(store: &impl Storelike, subject: &str, for_agent: Option<&str>) -> String {
+    match store.get_resource_extended(subject, true, for_agent) {
+        Ok(resource) => link(subject, &title(&resource)),
+        Err(_) => link(subject, subject),
+    }
+}
+
+/// Descriptions are Markdown. We don't render it, but we do keep the paragraphs.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| format!("<p>{}</p>\n", escape_html(p.trim())))
+        .collect()
+}
+
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::<chrono::Utc>::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+fn subjects(resource: &Resource, property: &str) -> Vec<String> {
+    match resource.get(property) {
+        Ok(Value::ResourceArray(items)) => items
+            .iter()
+            .filter_map(|item| match item {
+                SubResource::Subject(subject) => Some(subject.clone()),
+                SubResource::Resource(resource) => Some(resource.get_subject().to_string()),
+                SubResource::Nested(_) => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn article(resource: &Resource) -> String {
+    let mut out = format!("<article>\n<h1>{}</h1>\n", escape_html(&title(resource)));
+    if let Ok(Value::Timestamp(published)) = resource.get(urls::PUBLISHED_AT) {
+        let time = format_time(*published);
+        out.push_str(&format!("<time datetime=\"{time}\">{time}</time>\n"));
+    }
+    out.push_str(&paragraphs(
+        &string_prop(resource, urls::DESCRIPTION).unwrap_or_default(),
+    ));
+    out.push_str("</article>\n");
+    out
+}
+
+fn collection(store: &impl Storelike, resource: &Resource, for_agent: Option<&str>) -> String {
+    let mut out = format!("<h1>{}</h1>\n<ul>\n", escape_html(&title(resource)));
+    for member in subjects(resource, urls::COLLECTION_MEMBERS) {
+        out.push_str(&format!(
+            "<li>{}</li>\n",
+            link_to(store, &member, for_agent)
+        ));
+    }
+    out.push_str("</ul>\n");
+    out
+}
+
+fn chatroom(store: &impl Storelike, resource: &Resource, for_agent: Option<&str>) -> String {
+    let mut out = format!("<h1>{}</h1>\n<ol>\n", escape_html(&title(resource)));
+    for subject in subjects(resource, urls::MESSAGES) {
+        let Ok(message) = store.get_resource_extended(&subject, true, for_agent) else {
+            continue;
+        };
+        // The author is the signer of the last Commit
+        let author = string_prop(&message, urls::LAST_COMMIT)
+            .and_then(|commit| store.get_resource(&commit).ok())
+            .and_then(|commit| string_prop(&commit, urls::SIGNER))
+            .map(|author| format!("{}: ", link_to(store, &author, for_agent)))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "<li>{}{}</li>\n",
+            author,
+            paragraphs(&string_prop(&message, urls::DESCRIPTION).unwrap_or_default())
+        ));
+    }
+    out.push_str("</ol>\n");
+    out
+}
+
+/// Lists all properties, and links to the resources that the values refer to.
+fn properties(store: &impl Storelike, resource: &Resource, for_agent: Option<&str>) -> String {
+    let mut out = format!("<h1>{}</h1>\n<dl>\n", escape_html(&title(resource)));
+    let mut propvals: Vec<_> = resource.get_propvals().iter().collect();
+    propvals.sort_by_key(|(property, _)| property.as_str());
+    for (property, value) in propvals {
+        let name = match store.get_property(property) {
+            Ok(prop) => prop.shortname,
+            Err(_) => property.clone(),
+        };
+        let value_html = match value {
+            Value::AtomicUrl(subject) => link_to(store, subject, for_agent),
+            Value::ResourceArray(_) => subjects(resource, property)
+                .iter()
+                .map(|subject| link_to(store, subject, for_agent))
+                .collect::<Vec<_>>()
+                .join(", "),
+            other => escape_html(&other.to_string()),
+        };
+        out.push_str(&format!(
+            "<dt>{}</dt>\n<dd>{}</dd>\n",
+            link(property, &name),
+            value_html
+        ));
+    }
+    out.push_str("</dl>\n");
+    out
+}
+
+/// Renders a complete HTML page for the resource, including OpenGraph tags for link previews.
+pub fn render(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<String> {
+    let (og_type, body) = if is_a(resource, urls::ARTICLE) {
+        ("article", article(resource))
+    } else if is_a(resource, urls::COLLECTION) {
+        ("website", collection(store, resource, for_agent))
+    } else if is_a(resource, urls::CHATROOM) {
+        ("website", chatroom(store, resource, for_agent))
+    } else {
+        ("website", properties(store, resource, for_agent))
+    };
+    let subject = escape_html(resource.get_subject());
+    Ok(format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+{meta_tags}
+<meta property=\"og:type\" content=\"{og_type}\">
+<meta property=\"og:url\" content=\"{subject}\">
+<link rel=\"canonical\" href=\"{subject}\">
+<link rel=\"alternate\" type=\"application/ad+json\" href=\"{subject}\">
+</head>
+<body>
+<main>
+{body}</main>
+</body>
+</html>
+",
+        title = escape_html(&title(resource)),
+        meta_tags = MetaTags::from(resource.clone()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_by_class() {
+        let store = atomic_lib::Db::init_temp("html").unwrap();
+        let server_url = store.get_server_url().to_string();
+        let mut post = Resource::new(format!("{}/posts/first", server_url));
+        post.set_propval_unsafe(urls::IS_A.into(), vec![urls::ARTICLE.to_string()].into());
+        post.set_propval_unsafe(urls::NAME.into(), Value::String("First <post>".into()));
+        post.set_propval_unsafe(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("Hello\n\nWorld".into()),
+        );
+        let html = render(&store, &post, None).unwrap();
+        assert!(html.contains("<h1>First &lt;post&gt;</h1>"));
+        assert!(html.contains("<p>World</p>"));
+        assert!(html.contains("<meta property=\"og:type\" content=\"article\">"));
+
+        let mut other = Resource::new(format!("{}/things/first", server_url));
+        other.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(server_url));
+        let html = render(&store, &other, None).unwrap();
+        assert!(html.contains("<dt><a href="));
+        assert!(html.contains(">parent</a></dt>"));
+    }
+}
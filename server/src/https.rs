@@ -2,61 +2,130 @@
 //! Instantiate a server for HTTP-01 check with letsencrypt,
 //! checks if certificates are not outdated,
 //! persists files on disk.
+//! Hostnames of DomainMappings get their own certificates, which are picked using SNI, see [CertResolver].
 
 use std::{
+    collections::HashMap,
     fs::{self, File},
     io::BufReader,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
 };
 
 use crate::errors::AtomicServerResult;
+
+/// Reads a certificate chain and its private key from disk.
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> AtomicServerResult<CertifiedKey> {
+    use rustls_pemfile::{certs, pkcs8_private_keys};
+    let cert_file = &mut BufReader::new(
+        File::open(cert_path).map_err(|e| format!("Could not open {:?}: {}", cert_path, e))?,
+    );
+    let key_file = &mut BufReader::new(
+        File::open(key_path).map_err(|e| format!("Could not open {:?}: {}", key_path, e))?,
+    );
+    let cert_chain = certs(cert_file)?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let mut keys = pkcs8_private_keys(key_file)?;
+    if keys.is_empty() {
+        return Err(format!("No key found in {:?}", key_path).into());
+    }
+    let signing_key = rustls::sign::any_supported_type(&rustls::PrivateKey(keys.remove(0)))
+        .map_err(|e| format!("Unsupported key in {:?}: {}", key_path, e))?;
+    Ok(CertifiedKey::new(cert_chain, signing_key))
+}
+
+/// Picks the certificate for the hostname that the client asks for (SNI).
+/// Uses the certificate of the server domain for all other hostnames.
+pub struct CertResolver {
+    default: Arc<CertifiedKey>,
+    domains: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl CertResolver {
+    /// Loads the certificate of a DomainMapping hostname, after it has been created or renewed.
+    pub fn load_domain(
+        &self,
+        config: &crate::config::Config,
+        hostname: &str,
+    ) -> AtomicServerResult<()> {
+        let (cert_path, key_path) = domain_cert_paths(config, hostname);
+        let key = load_certified_key(&cert_path, &key_path)?;
+        self.domains
+            .write()?
+            .insert(hostname.to_string(), Arc::new(key));
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let domain_key = client_hello
+            .server_name()
+            .and_then(|name| self.domains.read().ok()?.get(name).cloned());
+        Some(domain_key.unwrap_or_else(|| self.default.clone()))
+    }
+}
+
 /// Create RUSTLS server config from certificates in config dir
 pub fn get_https_config(
     config: &crate::config::Config,
-) -> AtomicServerResult<rustls::ServerConfig> {
-    use rustls_pemfile::{certs, pkcs8_private_keys};
+) -> AtomicServerResult<(rustls::ServerConfig, Arc<CertResolver>)> {
+    let default = load_certified_key(&config.cert_path, &config.key_path).map_err(|e| {
+        format!(
+            "{}. Consider deleting the `.https` directory and restart to create new keys.",
+            e
+        )
+    })?;
+    let resolver = Arc::new(CertResolver {
+        default: Arc::new(default),
+        domains: RwLock::new(HashMap::new()),
+    });
+    // Certificates of earlier runs, also for hostnames that are no longer mapped
+    if let Ok(entries) = fs::read_dir(domains_path(config)) {
+        for entry in entries.flatten() {
+            let hostname = entry.file_name().to_string_lossy().to_string();
+            if let Err(e) = resolver.load_domain(config, &hostname) {
+                warn!("Skipping HTTPS certificate for {}: {}", hostname, e);
+            }
+        }
+    }
     let https_config = rustls::ServerConfig::builder()
         .with_safe_defaults()
-        .with_no_client_auth();
-    // rustls::NoClientAuth::new()
-    let cert_file =
-        &mut BufReader::new(File::open(config.cert_path.clone()).expect("No HTTPS TLS key found."));
-    let key_file =
-        &mut BufReader::new(File::open(&config.key_path).expect("Could not open config key path"));
-    let mut cert_chain = Vec::new();
-
-    for bytes in certs(cert_file)? {
-        let certificate = rustls::Certificate(bytes);
-        cert_chain.push(certificate);
-    }
-    let mut keys = pkcs8_private_keys(key_file)?;
-    if keys.is_empty() {
-        panic!("No key found. Consider deleting the `.https` directory and restart to create new keys.")
-    }
-    Ok(https_config
-        .with_single_cert(cert_chain, rustls::PrivateKey(keys.remove(0)))
-        .expect("Unable to create HTTPS config from certificates"))
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+    Ok((https_config, resolver))
 }
 
-pub fn certs_created_at_path(config: &crate::config::Config) -> PathBuf {
-    let mut path = config
-        .cert_path
+fn domains_path(config: &crate::config::Config) -> PathBuf {
+    config.https_path.join("domains")
+}
+
+/// Where the certificate and key for a DomainMapping hostname are stored.
+pub fn domain_cert_paths(config: &crate::config::Config, hostname: &str) -> (PathBuf, PathBuf) {
+    let dir = domains_path(config).join(sanitize_filename::sanitize(hostname));
+    (dir.join("cert.pem"), dir.join("key.pem"))
+}
+
+pub fn certs_created_at_path(cert_path: &Path) -> PathBuf {
+    let mut path = cert_path
         .parent()
-        .unwrap_or_else(|| {
-            panic!(
-                "Cannot open parent dir of HTTPS certs {:?}",
-                config.cert_path
-            )
-        })
+        .unwrap_or_else(|| panic!("Cannot open parent dir of HTTPS certs {:?}", cert_path))
         .to_path_buf();
     path.push("certs_created_at");
     path
 }
 
 /// Adds a file to the .https folder to indicate age of certificates
-fn set_certs_created_at_file(config: &crate::config::Config) {
+fn set_certs_created_at_file(cert_path: &Path) {
     let now_string = chrono::Utc::now();
-    let path = certs_created_at_path(config);
+    let path = certs_created_at_path(cert_path);
     fs::write(&path, now_string.to_string())
         .unwrap_or_else(|_| panic!("Unable to write {:?}", &path));
 }
@@ -64,10 +133,14 @@ fn set_certs_created_at_file(config: &crate::config::Config) {
 /// Checks if the certificates need to be renewed.
 /// Will be true if there are no certs yet.
 pub fn should_renew_certs_check(config: &crate::config::Config) -> AtomicServerResult<bool> {
-    if std::fs::File::open(&config.cert_path).is_err() {
+    should_renew(&config.cert_path)
+}
+
+fn should_renew(cert_path: &Path) -> AtomicServerResult<bool> {
+    if std::fs::File::open(cert_path).is_err() {
         return Ok(true);
     }
-    let path = certs_created_at_path(config);
+    let path = certs_created_at_path(cert_path);
 
     let created_at = std::fs::read_to_string(&path)
         .map_err(|_| format!("Unable to read {:?}", &path))?
@@ -92,6 +165,7 @@ use std::sync::mpsc;
 /// Starts an HTTP Actix server for HTTPS certificate initialization
 async fn cert_init_server(
     config: &crate::config::Config,
+    domain: &str,
     challenge: &instant_acme::Challenge,
     key_auth: &KeyAuthorization,
 ) -> AtomicServerResult<ServerHandle> {
@@ -142,7 +216,7 @@ async fn cert_init_server(
 
     let well_known_url = format!(
         "http://{}/.well-known/acme-challenge/{}",
-        &config.opts.domain, &challenge.token
+        domain, &challenge.token
     );
 
     // wait for a few secs
@@ -173,6 +247,47 @@ pub async fn request_cert(config: &crate::config::Config) -> AtomicServerResult<
         instant_acme::ChallengeType::Http01
     };
 
+    let mut domain = config.opts.domain.clone();
+    if config.opts.https_dns {
+        // Set a wildcard subdomain. Not possible with Http-01 challenge, only Dns-01.
+        domain = format!("*.{}", domain);
+    }
+    let (cert_chain_pem, cert) = order_cert(config, domain, challenge_type).await?;
+    write_certs(
+        config,
+        &config.cert_path,
+        &config.key_path,
+        cert_chain_pem,
+        cert,
+    )
+}
+
+/// Requests a certificate for the hostname of a DomainMapping, using the HTTP-01 challenge.
+/// The DNS records of the hostname should point to this server.
+pub async fn request_domain_cert(
+    config: &crate::config::Config,
+    hostname: &str,
+) -> AtomicServerResult<()> {
+    let (cert_path, key_path) = domain_cert_paths(config, hostname);
+    if !should_renew(&cert_path)? {
+        return Ok(());
+    }
+    info!("Requesting HTTPS certificate for {}", hostname);
+    let (cert_chain_pem, cert) = order_cert(
+        config,
+        hostname.to_string(),
+        instant_acme::ChallengeType::Http01,
+    )
+    .await?;
+    write_certs(config, &cert_path, &key_path, cert_chain_pem, cert)
+}
+
+/// Creates a LetsEncrypt order for the domain, completes the challenge and returns the certificate chain.
+async fn order_cert(
+    config: &crate::config::Config,
+    domain: String,
+    challenge_type: instant_acme::ChallengeType,
+) -> AtomicServerResult<(String, rcgen::Certificate)> {
     // Create a new account. This will generate a fresh ECDSA key for you.
     // Alternatively, restore an account from serialized credentials by
     // using `Account::from_credentials()`.
@@ -205,12 +320,7 @@ pub async fn request_cert(config: &crate::config::Config) -> AtomicServerResult<
     // Note that this only needs an `&Account`, so the library will let you
     // process multiple orders in parallel for a single account.
 
-    let mut domain = config.opts.domain.clone();
-    if config.opts.https_dns {
-        // Set a wildcard subdomain. Not possible with Http-01 challenge, only Dns-01.
-        domain = format!("*.{}", domain);
-    }
-    let identifier = instant_acme::Identifier::Dns(domain);
+    let identifier = instant_acme::Identifier::Dns(domain.clone());
     let (mut order, state) = account
         .new_order(&instant_acme::NewOrder {
             identifiers: &[identifier],
@@ -246,7 +356,7 @@ pub async fn request_cert(config: &crate::config::Config) -> AtomicServerResult<
         let key_auth = order.key_authorization(challenge);
         match challenge_type {
             instant_acme::ChallengeType::Http01 => {
-                handle = Some(cert_init_server(config, challenge, &key_auth).await?);
+                handle = Some(cert_init_server(config, &domain, challenge, &key_auth).await?);
             }
             instant_acme::ChallengeType::Dns01 => {
                 // For DNS challenges, we need the user to set a TXT record.
@@ -273,7 +383,7 @@ pub async fn request_cert(config: &crate::config::Config) -> AtomicServerResult<
     // Exponentially back off until the order becomes ready or invalid.
     let mut tries = 1u8;
     let mut delay = std::time::Duration::from_millis(250);
-    let url = authorizations.get(0).expect("Authorizations is empty");
+    let url = authorizations.first().expect("Authorizations is empty");
     let state = loop {
         actix::clock::sleep(delay).await;
         let state = order.state().await.unwrap();
@@ -324,26 +434,29 @@ pub async fn request_cert(config: &crate::config::Config) -> AtomicServerResult<
         "account credentials:\n\n{}",
         serde_json::to_string_pretty(&account.credentials()).map_err(|e| e.to_string())?
     );
-    write_certs(config, cert_chain_pem, cert)?;
 
     if let Some(hnd) = handle {
         warn!("HTTPS TLS Cert init successful! Stopping temporary HTTP server, starting HTTPS...");
         hnd.stop(true).await;
     }
 
-    Ok(())
+    Ok((cert_chain_pem, cert))
 }
 
 fn write_certs(
     config: &crate::config::Config,
+    cert_path: &Path,
+    key_path: &Path,
     cert_chain_pem: String,
     cert: rcgen::Certificate,
 ) -> AtomicServerResult<()> {
     info!("Writing TLS certificates to {:?}", config.https_path);
-    fs::create_dir_all(PathBuf::from(&config.https_path))?;
-    fs::write(&config.cert_path, cert_chain_pem)?;
-    fs::write(&config.key_path, cert.serialize_private_key_pem())?;
-    set_certs_created_at_file(config);
+    if let Some(dir) = cert_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(cert_path, cert_chain_pem)?;
+    fs::write(key_path, cert.serialize_private_key_pem())?;
+    set_certs_created_at_file(cert_path);
 
     Ok(())
 }
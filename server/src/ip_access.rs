@@ -0,0 +1,245 @@
+//! Network-level access rules, for servers that should only be used from certain networks, such as an intranet.
+//! `--ip-allow` and `--ip-deny` take IP addresses and CIDR ranges, such as `10.0.0.0/8` or `2001:db8::/32`.
+//! Entries that start with `@` are files with one range per line, so entire countries can be allowed or blocked using the ranges from a GeoIP database.
+//!
+//! Denied addresses are always rejected. If an allowlist is set, all addresses that are not in it are rejected as well.
+//! Rejected requests receive a `403 Forbidden` from the [crate::rate_limit::limit_by_ip] middleware, before they are counted.
+//! Drives can further restrict who can change them by setting `allowedNetworks`: Commits for resources in the Drive are only accepted from these networks.
+//! The client IP is found in the same way as for rate limiting, so set `--trust-forwarded-headers` behind a reverse proxy.
+
+use std::{net::IpAddr, str::FromStr};
+
+use atomic_lib::{urls, Resource, Storelike};
+
+use crate::{
+    config::Config,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+/// An IP address range in CIDR notation. A single address is a range with the full prefix length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.trim().split_once('/').unwrap_or((s.trim(), ""));
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_e| format!("Invalid IP address in network `{}`", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            "" => max,
+            prefix => prefix
+                .parse()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("Invalid prefix length in network `{}`", s))?,
+        };
+        Ok(Network {
+            addr: addr.to_canonical(),
+            prefix,
+        })
+    }
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let mask = |bits: u32| -> u128 {
+            match bits {
+                0 => 0,
+                bits => u128::MAX << (128 - bits),
+            }
+        };
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = (mask(self.prefix as u32) >> 96) as u32;
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask(self.prefix as u32);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses a list of networks. Entries that start with `@` are read from a file, with one network per line.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_networks(entries: &[String]) -> AtomicServerResult<Vec<Network>> {
+    let mut networks = Vec::new();
+    for entry in entries.iter().map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        match entry.strip_prefix('@') {
+            Some(path) => {
+                let file = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Could not read networks from {}: {}", path, e))?;
+                for line in file.lines().map(str::trim) {
+                    if !line.is_empty() && !line.starts_with('#') {
+                        networks.push(line.parse()?);
+                    }
+                }
+            }
+            None => networks.push(entry.parse()?),
+        }
+    }
+    Ok(networks)
+}
+
+fn forbidden(message: String) -> AtomicServerError {
+    AtomicServerError {
+        message,
+        error_type: AppErrorType::Forbidden,
+        error_resource: None,
+    }
+}
+
+fn in_any(networks: &[Network], ip: IpAddr) -> bool {
+    networks.iter().any(|network| network.contains(ip))
+}
+
+#[derive(Debug)]
+pub struct IpAccess {
+    allow: Vec<Network>,
+    deny: Vec<Network>,
+}
+
+impl IpAccess {
+    pub fn new(allow: Vec<Network>, deny: Vec<Network>) -> IpAccess {
+        IpAccess { allow, deny }
+    }
+
+    pub fn init_from_config(config: &Config) -> AtomicServerResult<IpAccess> {
+        Ok(IpAccess::new(
+            parse_networks(&config.opts.ip_allow)?,
+            parse_networks(&config.opts.ip_deny)?,
+        ))
+    }
+
+    /// Returns an error if the IP address of the client is not allowed to use the server.
+    /// Clients without a known IP address are only rejected if there is an allowlist.
+    pub fn check(&self, ip: Option<&str>) -> AtomicServerResult<()> {
+        if self.allow.is_empty() && self.deny.is_empty() {
+            return Ok(());
+        }
+        let ip: Option<IpAddr> = ip.and_then(|ip| ip.parse().ok());
+        match ip {
+            Some(ip) if in_any(&self.deny, ip) => Err(forbidden(format!(
+                "Requests from {} are not allowed on this server",
+                ip
+            ))),
+            Some(ip) if !self.allow.is_empty() && !in_any(&self.allow, ip) => Err(forbidden(
+                format!("Requests from {} are not allowed on this server", ip),
+            )),
+            None if !self.allow.is_empty() => Err(forbidden(
+                "Requests from unknown IP addresses are not allowed on this server".into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns an error if the Drive of the resource has `allowedNetworks`, and the IP address of the client is not in them.
+/// Pass the resource as it will be after the Commit, so new resources are checked using their parent.
+pub fn check_drive(
+    store: &impl Storelike,
+    resource: &Resource,
+    ip: Option<&str>,
+) -> AtomicServerResult<()> {
+    let Some(drive) = atomic_lib::plugins::features::find_drive(store, resource) else {
+        return Ok(());
+    };
+    let drive = store.get_resource(&drive)?;
+    let Ok(allowed) = drive.get(urls::ALLOWED_NETWORKS) else {
+        return Ok(());
+    };
+    let entries: Vec<String> = allowed.to_string().split(',').map(String::from).collect();
+    // `@` files are only allowed in the server config
+    if entries.iter().any(|e| e.trim().starts_with('@')) {
+        return Err(format!("Invalid allowedNetworks in {}", drive.get_subject()).into());
+    }
+    let networks = parse_networks(&entries)?;
+    match ip.and_then(|ip| ip.parse().ok()) {
+        Some(ip) if in_any(&networks, ip) => Ok(()),
+        _ => Err(forbidden(format!(
+            "Drive {} only accepts changes from its allowed networks",
+            drive.get_subject()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(entries: &[&str]) -> Vec<Network> {
+        parse_networks(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn matches_cidr_ranges() {
+        let office: Network = "10.1.0.0/16".parse().unwrap();
+        assert!(office.contains("10.1.200.3".parse().unwrap()));
+        assert!(!office.contains("10.2.0.1".parse().unwrap()));
+        // IPv4 addresses that are mapped to IPv6
+        assert!(office.contains("::ffff:10.1.0.1".parse().unwrap()));
+        let v6: Network = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!v6.contains("10.1.0.1".parse().unwrap()));
+        let single: Network = "192.168.1.1".parse().unwrap();
+        assert!(!single.contains("192.168.1.2".parse().unwrap()));
+        let everything: Network = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains("8.8.8.8".parse().unwrap()));
+        "10.0.0.0/33".parse::<Network>().unwrap_err();
+        "intranet".parse::<Network>().unwrap_err();
+    }
+
+    #[test]
+    fn applies_allow_and_deny_lists() {
+        let dir = std::path::PathBuf::from("./.temp/ip_access");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("blocked.txt");
+        std::fs::write(&file, "# Blocked country\n203.0.113.0/24\n\n").unwrap();
+
+        let access = IpAccess::new(
+            networks(&["10.0.0.0/8"]),
+            networks(&["10.0.0.66", &format!("@{}", file.display())]),
+        );
+        access.check(Some("10.1.2.3")).unwrap();
+        let err = access.check(Some("10.0.0.66")).unwrap_err();
+        assert!(matches!(err.error_type, AppErrorType::Forbidden));
+        access.check(Some("8.8.8.8")).unwrap_err();
+        access.check(None).unwrap_err();
+
+        let deny_only = IpAccess::new(Vec::new(), networks(&[&format!("@{}", file.display())]));
+        deny_only.check(Some("203.0.113.9")).unwrap_err();
+        deny_only.check(Some("8.8.8.8")).unwrap();
+        deny_only.check(None).unwrap();
+    }
+
+    #[test]
+    fn drives_restrict_commits() {
+        let store = atomic_lib::Db::init_temp("ip_access_drive").unwrap();
+        let mut drive = store.get_resource(store.get_server_url()).unwrap();
+        let mut document = Resource::new(format!("{}/document", store.get_server_url()));
+        document.set_propval_unsafe(
+            urls::PARENT.into(),
+            atomic_lib::Value::AtomicUrl(drive.get_subject().into()),
+        );
+        check_drive(&store, &document, Some("8.8.8.8")).unwrap();
+
+        drive.set_propval_unsafe(
+            urls::ALLOWED_NETWORKS.into(),
+            atomic_lib::Value::String("10.0.0.0/8, 192.168.0.0/16".into()),
+        );
+        store.add_resource(&drive).unwrap();
+        check_drive(&store, &document, Some("192.168.1.20")).unwrap();
+        check_drive(&store, &document, Some("8.8.8.8")).unwrap_err();
+        check_drive(&store, &document, None).unwrap_err();
+    }
+}
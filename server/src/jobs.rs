@@ -0,0 +1,281 @@
+//! Runs long operations, such as rebuilding indexes, on a pool of worker threads instead of during HTTP requests.
+//! Every Job is a resource that the worker updates with its status and progress, so clients can follow it using WebSockets.
+//! Jobs are listed at `/jobs`, and can be cancelled by POSTing to `/jobs/cancel?subject={job}`.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+};
+
+use atomic_lib::{urls, Db, Resource, Storelike, Value};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Passed to the work of a Job, so it can report its progress and stop when it is cancelled.
+pub struct JobContext {
+    store: Db,
+    job: Mutex<Resource>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobContext {
+    /// Sets the progress, from 0 to 1.
+    pub fn set_progress(&self, progress: f64) -> AtomicServerResult<()> {
+        let mut job = self.job.lock().expect("Job lock poisoned");
+        job.set_propval(
+            urls::JOB_PROGRESS.into(),
+            Value::Float(progress.clamp(0.0, 1.0)),
+            &self.store,
+        )?;
+        job.save_locally(&self.store)?;
+        Ok(())
+    }
+
+    /// Long running work should check this regularly, and stop if it returns true.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn set_status(&self, status: JobStatus, result: Option<String>) -> AtomicServerResult<()> {
+        let mut job = self.job.lock().expect("Job lock poisoned");
+        set_status(&self.store, &mut job, status, result)
+    }
+}
+
+/// The work that a Job performs. Returns a description of the result.
+pub type Work = Box<dyn FnOnce(&JobContext) -> AtomicServerResult<String> + Send>;
+
+struct QueuedJob {
+    job: Resource,
+    work: Work,
+}
+
+fn set_status(
+    store: &Db,
+    job: &mut Resource,
+    status: JobStatus,
+    result: Option<String>,
+) -> AtomicServerResult<()> {
+    job.set_propval(
+        urls::JOB_STATUS.into(),
+        Value::String(status.as_str().into()),
+        store,
+    )?;
+    if status == JobStatus::Done {
+        job.set_propval(urls::JOB_PROGRESS.into(), Value::Float(1.0), store)?;
+    }
+    if let Some(result) = result {
+        job.set_propval(urls::JOB_RESULT.into(), Value::Markdown(result), store)?;
+    }
+    job.save_locally(store)?;
+    Ok(())
+}
+
+/// Queues Jobs and runs them on worker threads.
+pub struct JobQueue {
+    store: Db,
+    sender: mpsc::Sender<QueuedJob>,
+    /// Cancellation flags of the Jobs that are queued or running
+    active: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl JobQueue {
+    /// Starts `--job-workers` worker threads.
+    pub fn init_from_config(config: &Config, store: &Db) -> JobQueue {
+        JobQueue::new(store, config.opts.job_workers)
+    }
+
+    pub fn new(store: &Db, workers: usize) -> JobQueue {
+        let (sender, receiver) = mpsc::channel::<QueuedJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let active: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> = Default::default();
+        for _ in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            let active = active.clone();
+            let store = store.clone();
+            std::thread::spawn(move || loop {
+                let next = receiver.lock().expect("Job receiver poisoned").recv();
+                let Ok(queued) = next else {
+                    // The queue has been dropped, so the server is shutting down
+                    return;
+                };
+                run(&store, &active, queued);
+            });
+        }
+        JobQueue {
+            store: store.clone(),
+            sender,
+            active,
+        }
+    }
+
+    /// Creates a Job resource under `parent`, and queues the work.
+    /// Returns the Job, so the client can subscribe to it.
+    pub fn submit(&self, kind: &str, parent: &str, work: Work) -> AtomicServerResult<Resource> {
+        let subject = format!(
+            "{}/jobs/{}",
+            self.store.get_server_url(),
+            atomic_lib::utils::random_string(10)
+        );
+        let mut job = Resource::new(subject.clone());
+        job.set_class(urls::JOB);
+        job.set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(parent.into()),
+            &self.store,
+        )?;
+        job.set_propval(
+            urls::JOB_KIND.into(),
+            Value::String(kind.into()),
+            &self.store,
+        )?;
+        job.set_propval(urls::JOB_PROGRESS.into(), Value::Float(0.0), &self.store)?;
+        set_status(&self.store, &mut job, JobStatus::Queued, None)?;
+        self.active
+            .lock()
+            .expect("Jobs lock poisoned")
+            .insert(subject, Arc::new(AtomicBool::new(false)));
+        self.sender
+            .send(QueuedJob {
+                job: job.clone(),
+                work,
+            })
+            .map_err(|_e| "The job queue has stopped")?;
+        Ok(job)
+    }
+
+    /// Asks the Job to stop. Queued Jobs won't start, running Jobs stop when they next check for cancellation.
+    pub fn cancel(&self, subject: &str) -> AtomicServerResult<()> {
+        let active = self.active.lock().expect("Jobs lock poisoned");
+        let flag = active
+            .get(subject)
+            .ok_or_else(|| format!("Job {} is not queued or running", subject))?;
+        flag.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn run(store: &Db, active: &Mutex<HashMap<String, Arc<AtomicBool>>>, queued: QueuedJob) {
+    let subject = queued.job.get_subject().to_string();
+    let Some(cancelled) = active
+        .lock()
+        .expect("Jobs lock poisoned")
+        .get(&subject)
+        .cloned()
+    else {
+        return;
+    };
+    let context = JobContext {
+        store: store.clone(),
+        job: Mutex::new(queued.job),
+        cancelled,
+    };
+    let result = if context.is_cancelled() {
+        None
+    } else {
+        if let Err(e) = context.set_status(JobStatus::Running, None) {
+            tracing::error!("Could not update Job {}: {}", subject, e);
+        }
+        // A panicking Job should not take down its worker
+        Some(
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (queued.work)(&context)))
+                .unwrap_or_else(|_| Err("The job panicked".into())),
+        )
+    };
+    // Removed before the final status is set, so finished Jobs can't be cancelled
+    active.lock().expect("Jobs lock poisoned").remove(&subject);
+    let outcome = match result {
+        None => context.set_status(JobStatus::Cancelled, None),
+        Some(Ok(_)) if context.is_cancelled() => context.set_status(JobStatus::Cancelled, None),
+        Some(Ok(result)) => context.set_status(JobStatus::Done, Some(result)),
+        Some(Err(e)) => context.set_status(JobStatus::Failed, Some(e.message)),
+    };
+    if let Err(e) = outcome {
+        tracing::error!("Could not update Job {}: {}", subject, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait_for_status(store: &Db, subject: &str, status: JobStatus) {
+        for _ in 0..100 {
+            let current = store
+                .get_resource(subject)
+                .ok()
+                .and_then(|job| job.get(urls::JOB_STATUS).ok().map(|v| v.to_string()));
+            if current.as_deref() == Some(status.as_str()) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        panic!("Job {} did not become {}", subject, status.as_str());
+    }
+
+    #[test]
+    fn runs_and_cancels_jobs() {
+        let store = atomic_lib::Db::init_temp("jobs").unwrap();
+        let agent = store.create_agent(Some("server")).unwrap();
+        store.set_default_agent(agent);
+        let parent = store.get_server_url().to_string();
+        let queue = JobQueue::new(&store, 1);
+
+        let done = queue
+            .submit(
+                "test",
+                &parent,
+                Box::new(|context| {
+                    context.set_progress(0.5)?;
+                    Ok("Finished".into())
+                }),
+            )
+            .unwrap();
+        wait_for_status(&store, done.get_subject(), JobStatus::Done);
+        let job = store.get_resource(done.get_subject()).unwrap();
+        assert_eq!(job.get(urls::JOB_RESULT).unwrap().to_string(), "Finished");
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let running = queue
+            .submit(
+                "test",
+                &parent,
+                Box::new(move |context| {
+                    started_tx.send(()).unwrap();
+                    while !context.is_cancelled() {
+                        std::thread::sleep(std::time::Duration::from_millis(5));
+                    }
+                    Ok("Stopped".into())
+                }),
+            )
+            .unwrap();
+        started_rx.recv().unwrap();
+        queue.cancel(running.get_subject()).unwrap();
+        wait_for_status(&store, running.get_subject(), JobStatus::Cancelled);
+        queue.cancel(running.get_subject()).unwrap_err();
+    }
+}
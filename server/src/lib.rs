@@ -3,23 +3,49 @@ Atomic-Server is mostly desgigned to run as a binary, but it can be embedded in
 It is currently used as an embedded server in the Tauri distribution of Atomic Server.
 See https://github.com/atomicdata-dev/atomic-data-rust/tree/master/src-tauri
 */
+mod activitypub;
 mod actor_messages;
+mod api_tokens;
+mod api_version;
 mod appstate;
+mod assets;
+mod audit_log;
+mod calendar;
 mod commit_monitor;
+mod compression;
 pub mod config;
 mod content_types;
+mod domains;
 mod errors;
+mod feeds;
+#[cfg(feature = "file-text")]
+mod file_text;
+mod files;
 mod handlers;
 mod helpers;
+mod html;
 #[cfg(feature = "https")]
 mod https;
+mod ip_access;
+mod jobs;
 mod jsonerrors;
+mod lockout;
+mod memory;
+mod oidc;
+mod passkeys;
 #[cfg(feature = "process-management")]
 mod process;
+mod rate_limit;
+mod read_tokens;
 mod routes;
+mod scanner;
 pub mod serve;
 // #[cfg(feature = "search")]
 mod search;
+mod sessions;
+mod share_links;
+mod solid;
 #[cfg(test)]
 mod tests;
+mod totp;
 mod trace;
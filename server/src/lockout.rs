@@ -0,0 +1,292 @@
+//! Protects against brute-forcing signatures, session cookies and API tokens.
+//! Failed authentications are counted for every IP address, and for every Agent per IP address.
+//! The `x-atomic-agent` header is not authenticated yet when the failure is counted,
+//! so counting the failures of an Agent on all IP addresses would let anyone lock out any Agent.
+//! After `--auth-lockout-threshold` failures, the client is locked out for a second, which doubles with every next failure, up to [MAX_LOCKOUT].
+//! Locked out clients receive a `429 Too Many Requests` with a `Retry-After` header, before their credentials are checked.
+//! Failures are forgotten after [FORGET_AFTER] without failures, and an Agent's failures are forgotten when it authenticates successfully.
+//! Lockouts are recorded in the audit log.
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    dev::{ConnectionInfo, Service, ServiceRequest, ServiceResponse},
+    http::header::HeaderMap,
+    web,
+};
+
+use crate::{
+    appstate::AppState,
+    audit_log::{AuditEntry, AuditEvent},
+    config::Config,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+/// Duration of the first lockout. Every next failure doubles it.
+const BASE_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// Failures are forgotten when there haven't been any for this long.
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+/// When this many clients are tracked, forgotten failures are removed.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct AuthLockout {
+    /// Amount of failures after which clients are locked out. `0` disables lockouts.
+    threshold: u32,
+    /// IP addresses and Agent subjects that are never locked out
+    allowlist: HashSet<String>,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl AuthLockout {
+    pub fn new(threshold: u32, allowlist: impl IntoIterator<Item = String>) -> AuthLockout {
+        AuthLockout {
+            threshold,
+            allowlist: allowlist.into_iter().collect(),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn init_from_config(config: &Config) -> AuthLockout {
+        AuthLockout::new(
+            config.opts.auth_lockout_threshold,
+            config.opts.rate_limit_allowlist.clone(),
+        )
+    }
+
+    fn is_exempt(&self, client: &str) -> bool {
+        self.threshold == 0 || self.allowlist.contains(client)
+    }
+
+    /// Returns an error if the client (an IP address or Agent subject) is locked out.
+    pub fn check(&self, client: &str) -> AtomicServerResult<()> {
+        if self.is_exempt(client) {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let failures = self.failures.lock()?;
+        match failures.get(client).and_then(|f| f.locked_until) {
+            Some(until) if until > now => Err(locked_out(until - now)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a failed authentication by the client.
+    /// Returns the duration of the lockout, if the client is now locked out.
+    pub fn fail(&self, client: &str) -> AtomicServerResult<Option<Duration>> {
+        if self.is_exempt(client) {
+            return Ok(None);
+        }
+        let now = Instant::now();
+        let mut failures = self.failures.lock()?;
+        if failures.len() > PRUNE_THRESHOLD {
+            failures.retain(|_k, f| now.duration_since(f.last) < FORGET_AFTER);
+        }
+        let entry = failures.entry(client.to_string()).or_insert(Failures {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+        if now.duration_since(entry.last) >= FORGET_AFTER {
+            entry.count = 0;
+        }
+        entry.count += 1;
+        entry.last = now;
+        if entry.count < self.threshold {
+            return Ok(None);
+        }
+        let doublings = (entry.count - self.threshold).min(16);
+        let lockout = BASE_LOCKOUT.saturating_mul(1 << doublings).min(MAX_LOCKOUT);
+        entry.locked_until = Some(now + lockout);
+        Ok(Some(lockout))
+    }
+
+    /// Forgets the failures of the client, after it has authenticated successfully.
+    pub fn succeed(&self, client: &str) -> AtomicServerResult<()> {
+        self.failures.lock()?.remove(client);
+        Ok(())
+    }
+}
+
+fn locked_out(retry_after: Duration) -> AtomicServerError {
+    // Round up, so clients don't retry too early
+    let retry_after = retry_after.as_secs() + 1;
+    AtomicServerError {
+        message: format!(
+            "Too many failed authentication attempts. Try again in {} seconds.",
+            retry_after
+        ),
+        error_type: AppErrorType::TooManyRequests { retry_after },
+        error_resource: None,
+    }
+}
+
+/// Whether the request contains a signature, session cookie or API token.
+fn has_credentials(headers: &HeaderMap) -> bool {
+    headers.contains_key("x-atomic-signature")
+        || headers.contains_key("Authorization")
+        || headers
+            .get("Cookie")
+            .and_then(|c| c.to_str().ok())
+            .is_some_and(|c| c.contains(crate::sessions::SESSION_COOKIE))
+}
+
+/// The Agent from the headers of the request, which is not authenticated yet.
+fn agent_header(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-atomic-agent")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+/// The IP address of the request, and the combination of the Agent from its headers with that IP address.
+/// These are tracked separately. Allowlisted Agents are returned without the IP address, so they stay exempt.
+fn clients(
+    headers: &HeaderMap,
+    connection_info: &ConnectionInfo,
+    peer_addr: Option<SocketAddr>,
+    appstate: &AppState,
+) -> (Option<String>, Option<String>) {
+    let ip = crate::helpers::client_ip(connection_info, peer_addr, &appstate.config);
+    let agent = agent_header(headers).map(|agent| {
+        if appstate.auth_lockout.allowlist.contains(&agent) {
+            agent
+        } else {
+            format!("{} from {}", agent, ip.as_deref().unwrap_or("unknown"))
+        }
+    });
+    (ip, agent)
+}
+
+/// Middleware that rejects requests with credentials from locked out clients,
+/// and counts the requests that fail because of invalid credentials.
+pub fn guard_authentication<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let guarded = has_credentials(req.headers());
+    let locked = req
+        .app_data::<web::Data<AppState>>()
+        .filter(|_| guarded)
+        .and_then(|appstate| {
+            let (ip, agent) = clients(
+                req.headers(),
+                &req.connection_info(),
+                req.peer_addr(),
+                appstate,
+            );
+            [ip, agent]
+                .into_iter()
+                .flatten()
+                .find_map(|client| appstate.auth_lockout.check(&client).err())
+        });
+    let response = match locked {
+        Some(err) => Err(err),
+        None => Ok(srv.call(req)),
+    };
+    async move {
+        let res = response?.await?;
+        if !guarded {
+            return Ok(res);
+        }
+        let failed = res
+            .response()
+            .error()
+            .and_then(|e| e.as_error::<AtomicServerError>())
+            .is_some_and(|e| matches!(e.error_type, AppErrorType::AuthenticationFailed));
+        if let Some(appstate) = res.request().app_data::<web::Data<AppState>>() {
+            let req = res.request();
+            let (ip, agent_client) = clients(
+                req.headers(),
+                &req.connection_info(),
+                req.peer_addr(),
+                appstate,
+            );
+            let agent = agent_header(req.headers());
+            if failed {
+                for client in [ip, agent_client.clone()].into_iter().flatten() {
+                    match appstate.auth_lockout.fail(&client) {
+                        Ok(Some(lockout)) => {
+                            let subject = format!("{}{}", appstate.config.server_url, req.uri());
+                            let entry = AuditEntry::from_request(
+                                req,
+                                AuditEvent::AuthLockout,
+                                agent.clone(),
+                                Some(subject),
+                                format!("{} locked out for {} seconds", client, lockout.as_secs()),
+                            );
+                            crate::audit_log::record(appstate, entry);
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::error!("Failed to count authentication failure: {}", e),
+                    }
+                }
+            } else if let Some(agent_client) = agent_client {
+                if let Err(e) = appstate.auth_lockout.succeed(&agent_client) {
+                    tracing::error!("Failed to reset authentication failures: {}", e);
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_out_with_exponential_backoff() {
+        let lockout = AuthLockout::new(3, ["10.0.0.9".to_string()]);
+        assert_eq!(lockout.fail("10.0.0.1").unwrap(), None);
+        assert_eq!(lockout.fail("10.0.0.1").unwrap(), None);
+        lockout.check("10.0.0.1").unwrap();
+        assert_eq!(lockout.fail("10.0.0.1").unwrap(), Some(BASE_LOCKOUT));
+        let err = lockout.check("10.0.0.1").unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            AppErrorType::TooManyRequests { retry_after } if retry_after <= 2
+        ));
+        assert_eq!(lockout.fail("10.0.0.1").unwrap(), Some(BASE_LOCKOUT * 2));
+        assert_eq!(lockout.fail("10.0.0.1").unwrap(), Some(BASE_LOCKOUT * 4));
+        for _ in 0..20 {
+            lockout.fail("10.0.0.1").unwrap();
+        }
+        assert_eq!(lockout.fail("10.0.0.1").unwrap(), Some(MAX_LOCKOUT));
+        // Other clients are tracked separately
+        lockout.check("10.0.0.2").unwrap();
+        lockout.succeed("10.0.0.1").unwrap();
+        lockout.check("10.0.0.1").unwrap();
+        // Allowlisted
+        for _ in 0..10 {
+            assert_eq!(lockout.fail("10.0.0.9").unwrap(), None);
+        }
+        lockout.check("10.0.0.9").unwrap();
+    }
+
+    #[test]
+    fn zero_threshold_disables_lockouts() {
+        let lockout = AuthLockout::new(0, []);
+        for _ in 0..10 {
+            assert_eq!(lockout.fail("10.0.0.1").unwrap(), None);
+        }
+        lockout.check("10.0.0.1").unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+//! Limits the memory that the database (sled) and the search index (tantivy) use for caching and indexing.
+//! By default, the limits scale with the RAM of the machine, so small servers don't run out of memory while importing.
+//! See `--db-cache-size` and `--search-heap-size`.
+
+use atomic_lib::Db;
+
+use crate::{config::Opts, errors::AtomicServerResult};
+
+const MB: u64 = 1024 * 1024;
+/// Tantivy needs at least 3 MB for every indexing thread.
+const SEARCH_HEAP_MIN: u64 = 3 * MB;
+/// How often the memory usage is logged.
+const REPORT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// The memory for the caches of the database and the search index, in bytes.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    pub db_cache: u64,
+    pub search_heap: u64,
+    /// RAM of the machine, if it is known
+    pub total: Option<u64>,
+}
+
+impl MemoryBudget {
+    pub fn from_opts(opts: &Opts) -> AtomicServerResult<MemoryBudget> {
+        let total = total_memory();
+        let db_cache = match opts.db_cache_size {
+            Some(megabytes) => megabytes.saturating_mul(MB),
+            None => scaled(total, 8, 64 * MB, 1024 * MB),
+        };
+        let search_heap = match opts.search_heap_size {
+            Some(megabytes) => megabytes.saturating_mul(MB),
+            None => scaled(total, 32, 15 * MB, 50 * MB),
+        };
+        if search_heap < SEARCH_HEAP_MIN {
+            return Err(format!(
+                "The search heap size must be at least {} megabytes",
+                SEARCH_HEAP_MIN / MB
+            )
+            .into());
+        }
+        Ok(MemoryBudget {
+            db_cache,
+            search_heap,
+            total,
+        })
+    }
+}
+
+/// A part of the RAM, between `min` and `max`. Uses `max` if the RAM is unknown.
+fn scaled(total: Option<u64>, divisor: u64, min: u64, max: u64) -> u64 {
+    total
+        .map(|total| (total / divisor).clamp(min, max))
+        .unwrap_or(max)
+}
+
+/// Reads a value in kB from a file like `/proc/meminfo`, and returns it in bytes.
+fn parse_kb(contents: &str, key: &str) -> Option<u64> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb * 1024)
+}
+
+/// The RAM of the machine. Only known on Linux.
+fn total_memory() -> Option<u64> {
+    parse_kb(&std::fs::read_to_string("/proc/meminfo").ok()?, "MemTotal")
+}
+
+/// The memory that this process uses. Only known on Linux.
+fn resident_memory() -> Option<u64> {
+    parse_kb(&std::fs::read_to_string("/proc/self/status").ok()?, "VmRSS")
+}
+
+fn megabytes(bytes: Option<u64>) -> String {
+    bytes
+        .map(|bytes| format!("{} MB", bytes / MB))
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// Logs the memory usage and the budget when the server starts, and every ten minutes after that.
+/// Warns when the server uses most of the RAM.
+pub fn spawn_reporter(store: Db, budget: MemoryBudget) {
+    actix::spawn(async move {
+        let mut interval = actix::clock::interval(REPORT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let resident = resident_memory();
+            tracing::info!(
+                "Memory: {} used of {}. Database: {} on disk, {} cache. Search index: {} heap.",
+                megabytes(resident),
+                megabytes(budget.total),
+                megabytes(store.size_on_disk().ok()),
+                megabytes(Some(budget.db_cache)),
+                megabytes(Some(budget.search_heap)),
+            );
+            if let (Some(resident), Some(total)) = (resident, budget.total) {
+                if resident > total / 10 * 9 {
+                    tracing::warn!("Atomic-Server uses over 90% of the RAM. Consider lowering `--db-cache-size` or `--search-heap-size`.");
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scales_with_memory() {
+        let meminfo = "MemTotal:        2048000 kB\nMemFree:          100000 kB\n";
+        let total = parse_kb(meminfo, "MemTotal");
+        assert_eq!(total, Some(2048000 * 1024));
+        assert_eq!(parse_kb(meminfo, "Mem"), None);
+        assert_eq!(scaled(total, 8, 64 * MB, 1024 * MB), 2048000 * 1024 / 8);
+        assert_eq!(scaled(total, 32, 15 * MB, 50 * MB), 50 * MB);
+        assert_eq!(scaled(Some(MB), 8, 64 * MB, 1024 * MB), 64 * MB);
+        assert_eq!(scaled(None, 8, 64 * MB, 1024 * MB), 1024 * MB);
+    }
+}
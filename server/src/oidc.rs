@@ -0,0 +1,205 @@
+//! Logging in using an external OpenID Connect provider, so organizations can use their single sign-on instead of managing key pairs.
+//! Enabled by setting `--oidc-issuer`, `--oidc-client-id` and `--oidc-client-secret`.
+//!
+//! 1. `/auth/oidc/login` redirects the user to the provider.
+//! 2. The provider redirects back to `/auth/oidc/callback` with a `code`.
+//!    The server exchanges this code for an access token, and fetches the user info.
+//! 3. On first login, an Agent is created for the user. Its private key is held by the server, see [crate::sessions::AgentKeys].
+//!    Later logins use the same Agent, which is linked using its `oidcSubject`.
+//! 4. The server signs an `atomic_session` cookie for the Agent, which authenticates the following requests.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{agents::Agent, storelike::Query, urls, Storelike, Value};
+use serde::Deserialize;
+
+use crate::{config::Config, errors::AtomicServerResult, sessions::AgentKeys};
+
+/// Users have this long to log in at the provider.
+const STATE_TTL: Duration = Duration::from_secs(600);
+
+/// The endpoints of the provider, from its `.well-known/openid-configuration`.
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// The claims we use from the userinfo endpoint.
+#[derive(Debug, Deserialize)]
+pub struct UserInfo {
+    pub sub: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+/// A login that has been started, but not yet finished.
+#[derive(Debug)]
+struct PendingLogin {
+    started: Instant,
+    /// Path to redirect to after logging in
+    redirect: String,
+}
+
+#[derive(Debug)]
+pub struct OidcClient {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+    /// Fetched on the first login, so starting the server doesn't depend on the provider
+    metadata: Mutex<Option<ProviderMetadata>>,
+    /// Logins by their `state` parameter, which protects against CSRF
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    /// Makes sure only one Agent is created when a user logs in twice at the same time
+    login_lock: Mutex<()>,
+}
+
+/// Returns the OIDC client, if the provider has been configured.
+pub fn init_from_config(config: &Config) -> AtomicServerResult<Option<Arc<OidcClient>>> {
+    let opts = &config.opts;
+    let issuer = match &opts.oidc_issuer {
+        Some(issuer) => issuer.trim_end_matches('/').to_string(),
+        None => return Ok(None),
+    };
+    let (client_id, client_secret) = match (&opts.oidc_client_id, &opts.oidc_client_secret) {
+        (Some(id), Some(secret)) => (id.clone(), secret.clone()),
+        _ => {
+            return Err(
+                "`--oidc-issuer` requires `--oidc-client-id` and `--oidc-client-secret`".into(),
+            )
+        }
+    };
+    Ok(Some(Arc::new(OidcClient {
+        issuer,
+        client_id,
+        client_secret,
+        redirect_uri: format!("{}/auth/oidc/callback", config.server_url),
+        metadata: Mutex::new(None),
+        pending: Mutex::new(HashMap::new()),
+        login_lock: Mutex::new(()),
+    })))
+}
+
+impl OidcClient {
+    fn metadata(&self) -> AtomicServerResult<ProviderMetadata> {
+        let mut metadata = self.metadata.lock()?;
+        if let Some(found) = metadata.as_ref() {
+            return Ok(found.clone());
+        }
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let body = ureq::get(&url)
+            .call()
+            .map_err(|e| format!("Could not fetch OIDC configuration from {}: {}", url, e))?
+            .into_string()?;
+        let fetched: ProviderMetadata = serde_json::from_str(&body)
+            .map_err(|e| format!("Invalid OIDC configuration at {}: {}", url, e))?;
+        *metadata = Some(fetched.clone());
+        Ok(fetched)
+    }
+
+    /// Returns the URL of the provider that the user should be redirected to.
+    /// Blocking, because the provider configuration may need to be fetched.
+    pub fn login_url(&self, redirect: &str) -> AtomicServerResult<String> {
+        let metadata = self.metadata()?;
+        let state = atomic_lib::utils::random_string(32);
+        let mut pending = self.pending.lock()?;
+        pending.retain(|_state, login| login.started.elapsed() < STATE_TTL);
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                started: Instant::now(),
+                redirect: redirect.to_string(),
+            },
+        );
+        Ok(format!(
+            "{}?response_type=code&client_id={}&redirect_uri={}&scope={}&state={}",
+            metadata.authorization_endpoint,
+            urlencoding::encode(&self.client_id),
+            urlencoding::encode(&self.redirect_uri),
+            urlencoding::encode("openid profile email"),
+            state,
+        ))
+    }
+
+    /// Checks the `state` of the callback, and returns the path to redirect to after logging in.
+    pub fn take_pending(&self, state: &str) -> AtomicServerResult<String> {
+        let login = self
+            .pending
+            .lock()?
+            .remove(state)
+            .filter(|login| login.started.elapsed() < STATE_TTL)
+            .ok_or_else(|| {
+                atomic_lib::AtomicError::unauthorized(
+                    "Unknown or expired login. Please try logging in again.".into(),
+                )
+            })?;
+        Ok(login.redirect)
+    }
+
+    /// Exchanges the code from the callback for the info of the user. Blocking.
+    pub fn fetch_user_info(&self, code: &str) -> AtomicServerResult<UserInfo> {
+        let metadata = self.metadata()?;
+        let body = ureq::post(&metadata.token_endpoint)
+            .send_form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+            ])
+            .map_err(|e| format!("OIDC token request failed: {}", e))?
+            .into_string()?;
+        let token: TokenResponse = serde_json::from_str(&body)
+            .map_err(|e| format!("Invalid OIDC token response: {}", e))?;
+        let body = ureq::get(&metadata.userinfo_endpoint)
+            .set("Authorization", &format!("Bearer {}", token.access_token))
+            .call()
+            .map_err(|e| format!("OIDC userinfo request failed: {}", e))?
+            .into_string()?;
+        Ok(serde_json::from_str(&body).map_err(|e| format!("Invalid OIDC userinfo: {}", e))?)
+    }
+
+    /// The value of `oidcSubject` of the Agent. Includes the issuer, since `sub` is only unique per provider.
+    fn oidc_subject(&self, user: &UserInfo) -> String {
+        format!("{}|{}", self.issuer, user.sub)
+    }
+
+    /// Finds the Agent linked to the user, or creates one.
+    pub fn agent_for_user(
+        &self,
+        store: &impl Storelike,
+        keys: &AgentKeys,
+        user: &UserInfo,
+    ) -> AtomicServerResult<Agent> {
+        let _guard = self.login_lock.lock()?;
+        let oidc_subject = self.oidc_subject(user);
+        let existing = store
+            .query(&Query::new_prop_val(urls::OIDC_SUBJECT, &oidc_subject))?
+            .subjects;
+        for subject in existing {
+            if let Some(agent) = keys.get(store, &subject)? {
+                return Ok(agent);
+            }
+        }
+
+        let name = user.name.as_deref().or(user.email.as_deref());
+        let agent = Agent::new(name, store)?;
+        let mut resource = agent.to_resource()?;
+        resource.set_propval_unsafe(urls::OIDC_SUBJECT.into(), Value::String(oidc_subject));
+        keys.insert(&agent)?;
+        store.add_resource(&resource)?;
+        tracing::info!("Created Agent {} for OIDC user {}", agent.subject, user.sub);
+        Ok(agent)
+    }
+}
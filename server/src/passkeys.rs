@@ -0,0 +1,362 @@
+//! Logging in using passkeys and other WebAuthn credentials, so users don't lose access when they lose their private key.
+//! See https://www.w3.org/TR/webauthn-2/
+//!
+//! 1. The browser requests a challenge at `/auth/passkey/register/options`, and passes it to `navigator.credentials.create()`.
+//! 2. The browser posts the new credential to `/auth/passkey/register`.
+//!    The public key is read using `getPublicKey()`, so the attestation object doesn't need to be parsed.
+//!    Anonymous users get a new Agent, of which the server holds the private key (see [crate::sessions::AgentKeys]).
+//!    Signed in Agents can add a passkey, if the server holds their key.
+//! 3. The credential is stored as a Passkey resource, which is a child of the Agent.
+//! 4. To log in, the browser requests a challenge at `/auth/passkey/login/options`, passes it to `navigator.credentials.get()`,
+//!    and posts the assertion to `/auth/passkey/login`. The server verifies the signature and sets an `atomic_session` cookie.
+//!
+//! Only the `ES256` and `EdDSA` algorithms are supported. Attestations are not verified.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{agents::Agent, errors::AtomicResult, urls, AtomicError, Storelike, Value};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+/// Users have this long to use their authenticator.
+const CHALLENGE_TTL: Duration = Duration::from_secs(300);
+/// COSE algorithm identifiers, see https://www.iana.org/assignments/cose/cose.xhtml#algorithms
+pub const ALG_ES256: i64 = -7;
+pub const ALG_EDDSA: i64 = -8;
+/// DER SubjectPublicKeyInfo header of an uncompressed P-256 key
+const SPKI_P256_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+/// DER SubjectPublicKeyInfo header of an Ed25519 key
+const SPKI_ED25519_PREFIX: &[u8] = &[
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+/// The User Present flag in the authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// What a challenge was issued for.
+#[derive(Debug, Clone)]
+pub enum Ceremony {
+    /// Adds a passkey to this Agent, which is either signed in or newly created
+    Register(Agent),
+    Login,
+}
+
+/// Posted to `/auth/passkey/register`. Binary values are base64url encoded.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationResponse {
+    pub credential_id: String,
+    /// Result of `getPublicKey()`
+    pub public_key: String,
+    /// Result of `getPublicKeyAlgorithm()`
+    pub algorithm: i64,
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+}
+
+/// Posted to `/auth/passkey/login`. Binary values are base64url encoded.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    pub credential_id: String,
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    /// The subject of the Agent, which is set as the user ID when registering
+    pub user_handle: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+#[derive(Debug)]
+pub struct Passkeys {
+    /// The server URL, which browsers send as the origin
+    origin: String,
+    /// The domain of the server
+    pub rp_id: String,
+    challenges: Mutex<HashMap<String, (Instant, Ceremony)>>,
+}
+
+impl Passkeys {
+    pub fn init_from_config(config: &Config) -> Passkeys {
+        // The server URL can differ from the domain, for example when it's set using `--server-url`
+        let rp_id = config
+            .server_url
+            .parse::<actix_web::http::Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(String::from))
+            .unwrap_or_else(|| config.opts.domain.clone());
+        Passkeys {
+            origin: config.server_url.trim_end_matches('/').to_string(),
+            rp_id,
+            challenges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a challenge, which can be used once.
+    pub fn new_challenge(&self, ceremony: Ceremony) -> AtomicServerResult<String> {
+        let challenge = encode(atomic_lib::utils::random_string(32).as_bytes());
+        let mut challenges = self.challenges.lock()?;
+        challenges.retain(|_c, (started, _)| started.elapsed() < CHALLENGE_TTL);
+        challenges.insert(challenge.clone(), (Instant::now(), ceremony));
+        Ok(challenge)
+    }
+
+    /// Checks the client data, and returns the ceremony of its challenge.
+    fn check_client_data(&self, client_data_json: &[u8], kind: &str) -> AtomicResult<Ceremony> {
+        let client_data: ClientData = serde_json::from_slice(client_data_json)
+            .map_err(|e| format!("Invalid clientDataJSON: {}", e))?;
+        if client_data.kind != kind {
+            return Err(format!("Expected {}, got {}", kind, client_data.kind).into());
+        }
+        if client_data.origin != self.origin {
+            return Err(format!(
+                "Passkey was created for {}, not for {}",
+                client_data.origin, self.origin
+            )
+            .into());
+        }
+        let (started, ceremony) = self
+            .challenges
+            .lock()?
+            .remove(&client_data.challenge)
+            .ok_or("Unknown challenge")?;
+        if started.elapsed() > CHALLENGE_TTL {
+            return Err("Challenge has expired. Please try again.".into());
+        }
+        Ok(ceremony)
+    }
+
+    /// Stores the new credential, and returns the Agent that it belongs to.
+    pub fn register(
+        &self,
+        store: &impl Storelike,
+        response: &RegistrationResponse,
+    ) -> AtomicResult<Agent> {
+        let agent = match self
+            .check_client_data(&decode(&response.client_data_json)?, "webauthn.create")?
+        {
+            Ceremony::Register(agent) => agent,
+            Ceremony::Login => return Err("Challenge was issued for logging in".into()),
+        };
+        // Makes sure the key can be used later on
+        public_key_bytes(response.algorithm, &decode(&response.public_key)?)?;
+        let subject = passkey_subject(&agent.subject, &response.credential_id);
+        if store.get_resource(&subject).is_ok() {
+            return Err("This passkey has already been registered".into());
+        }
+        let mut agent_resource = match store.get_resource(&agent.subject) {
+            Ok(existing) => existing,
+            Err(_new_agent) => {
+                store.add_resource(&agent.to_resource()?)?;
+                store.get_resource(&agent.subject)?
+            }
+        };
+
+        let mut passkey = store.get_resource_new(&subject);
+        passkey.set_class(urls::PASSKEY);
+        passkey.set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(agent.subject.clone()),
+            store,
+        )?;
+        passkey.set_propval_string(
+            urls::PASSKEY_CREDENTIAL_ID.into(),
+            &response.credential_id,
+            store,
+        )?;
+        passkey.set_propval_string(urls::PASSKEY_PUBLIC_KEY.into(), &response.public_key, store)?;
+        passkey.set_propval(
+            urls::PASSKEY_ALGORITHM.into(),
+            Value::Integer(response.algorithm),
+            store,
+        )?;
+        passkey.set_propval(urls::PASSKEY_SIGN_COUNT.into(), Value::Integer(0), store)?;
+        passkey.save_locally(store)?;
+        agent_resource.push_propval(urls::PASSKEYS, subject.into(), true)?;
+        agent_resource.save_locally(store)?;
+        Ok(agent)
+    }
+
+    /// Verifies the assertion, and returns the subject of the Agent that logged in.
+    pub fn verify_assertion(
+        &self,
+        store: &impl Storelike,
+        response: &AssertionResponse,
+    ) -> AtomicResult<String> {
+        let client_data_json = decode(&response.client_data_json)?;
+        if !matches!(
+            self.check_client_data(&client_data_json, "webauthn.get")?,
+            Ceremony::Login
+        ) {
+            return Err("Challenge was issued for registering".into());
+        }
+        let agent = String::from_utf8(decode(&response.user_handle)?)?;
+        let mut passkey = store
+            .get_resource(&passkey_subject(&agent, &response.credential_id))
+            .map_err(|_e| AtomicError::unauthorized("Unknown passkey".into()))?;
+
+        let auth_data = decode(&response.authenticator_data)?;
+        if auth_data.len() < 37 {
+            return Err("Authenticator data is too short".into());
+        }
+        let rp_id_hash = Sha256::digest(self.rp_id.as_bytes());
+        if auth_data[..32] != rp_id_hash[..] {
+            return Err("Passkey was created for another domain".into());
+        }
+        if auth_data[32] & FLAG_USER_PRESENT == 0 {
+            return Err("User was not present".into());
+        }
+
+        let algorithm = passkey.get(urls::PASSKEY_ALGORITHM)?.to_int()?;
+        let spki = decode(&passkey.get(urls::PASSKEY_PUBLIC_KEY)?.to_string())?;
+        let public_key = public_key_bytes(algorithm, &spki)?;
+        let verification: &dyn ring::signature::VerificationAlgorithm = match algorithm {
+            ALG_ES256 => &ring::signature::ECDSA_P256_SHA256_ASN1,
+            _eddsa => &ring::signature::ED25519,
+        };
+        let mut message = auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(&client_data_json));
+        ring::signature::UnparsedPublicKey::new(verification, public_key)
+            .verify(&message, &decode(&response.signature)?)
+            .map_err(|_e| AtomicError::unauthorized("Invalid passkey signature".into()))?;
+
+        // A counter that doesn't increase can mean that the authenticator has been cloned.
+        // Authenticators that don't support counters always send 0.
+        let sign_count =
+            u32::from_be_bytes([auth_data[33], auth_data[34], auth_data[35], auth_data[36]]) as i64;
+        let stored_count = passkey
+            .get(urls::PASSKEY_SIGN_COUNT)
+            .and_then(|v| v.to_int())
+            .unwrap_or(0);
+        if sign_count != 0 || stored_count != 0 {
+            if sign_count <= stored_count {
+                return Err(AtomicError::unauthorized(
+                    "Passkey signature counter did not increase. The authenticator may have been cloned.".into(),
+                ));
+            }
+            passkey.set_propval(
+                urls::PASSKEY_SIGN_COUNT.into(),
+                Value::Integer(sign_count),
+                store,
+            )?;
+            passkey.save_locally(store)?;
+        }
+        Ok(agent)
+    }
+}
+
+/// Passkeys are children of their Agent.
+fn passkey_subject(agent: &str, credential_id: &str) -> String {
+    format!("{}/passkeys/{}", agent, credential_id)
+}
+
+/// Returns the raw key from a DER SubjectPublicKeyInfo, in the format that `ring` expects.
+fn public_key_bytes(algorithm: i64, spki: &[u8]) -> AtomicResult<&[u8]> {
+    let prefix = match algorithm {
+        ALG_ES256 => SPKI_P256_PREFIX,
+        ALG_EDDSA => SPKI_ED25519_PREFIX,
+        other => {
+            return Err(format!(
+                "Unsupported passkey algorithm {}. Use ES256 (-7) or EdDSA (-8).",
+                other
+            )
+            .into())
+        }
+    };
+    spki.strip_prefix(prefix)
+        .ok_or_else(|| "Public key does not match the algorithm".into())
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(string: &str) -> AtomicResult<Vec<u8>> {
+    Ok(
+        base64::decode_config(string.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+            .map_err(|e| format!("Invalid base64url: {}", e))?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::KeyPair;
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> String {
+        encode(
+            serde_json::json!({"type": kind, "challenge": challenge, "origin": origin})
+                .to_string()
+                .as_bytes(),
+        )
+    }
+
+    #[test]
+    fn registers_and_verifies_passkeys() {
+        let store = atomic_lib::Db::init_temp("registers_and_verifies_passkeys").unwrap();
+        store.populate().unwrap();
+        let passkeys = Passkeys {
+            origin: "https://localhost".into(),
+            rp_id: "localhost".into(),
+            challenges: Mutex::new(HashMap::new()),
+        };
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let authenticator = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let mut spki = SPKI_ED25519_PREFIX.to_vec();
+        spki.extend_from_slice(authenticator.public_key().as_ref());
+
+        let user = Agent::new(Some("passkey user"), &store).unwrap();
+        let challenge = passkeys
+            .new_challenge(Ceremony::Register(user.clone()))
+            .unwrap();
+        let registration = RegistrationResponse {
+            credential_id: "credential1".into(),
+            public_key: encode(&spki),
+            algorithm: ALG_EDDSA,
+            client_data_json: client_data("webauthn.create", &challenge, "https://localhost"),
+        };
+        passkeys.register(&store, &registration).unwrap();
+        // Challenges can only be used once
+        passkeys.register(&store, &registration).unwrap_err();
+
+        let assert = |count: u32, origin: &str| {
+            let challenge = passkeys.new_challenge(Ceremony::Login).unwrap();
+            let client_data_json = client_data("webauthn.get", &challenge, origin);
+            let mut auth_data = Sha256::digest(b"localhost").to_vec();
+            auth_data.push(FLAG_USER_PRESENT);
+            auth_data.extend_from_slice(&count.to_be_bytes());
+            let mut message = auth_data.clone();
+            message.extend_from_slice(&Sha256::digest(decode(&client_data_json).unwrap()));
+            let response = AssertionResponse {
+                credential_id: "credential1".into(),
+                client_data_json,
+                authenticator_data: encode(&auth_data),
+                signature: encode(authenticator.sign(&message).as_ref()),
+                user_handle: encode(user.subject.as_bytes()),
+            };
+            passkeys.verify_assertion(&store, &response)
+        };
+        assert_eq!(assert(1, "https://localhost").unwrap(), user.subject);
+        // Replayed counters and other origins are rejected
+        assert(1, "https://localhost").unwrap_err();
+        assert(2, "https://evil.example").unwrap_err();
+        assert(2, "https://localhost").unwrap();
+    }
+}
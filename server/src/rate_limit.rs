@@ -0,0 +1,288 @@
+//! Rate limiting of requests, to protect public servers against scraping and floods of Commits.
+//! Every IP address and every Agent has a budget of requests per minute for each [Category].
+//! IP addresses are limited by the [limit_by_ip] middleware, which also rejects the IP addresses that are not allowed by [crate::ip_access].
+//! Agents are limited in the handlers, because that's where their signatures are checked.
+//! Clients that exceed their budget receive a `429 Too Many Requests` with a `Retry-After` header.
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::Method,
+    web,
+};
+
+use crate::{
+    appstate::AppState,
+    config::Config,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+/// Length of the window in which requests are counted.
+const WINDOW: Duration = Duration::from_secs(60);
+/// When this many clients are tracked, expired windows are removed.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// The kind of request. Every category has its own limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    Read,
+    /// Applying Commits and uploading files
+    Commit,
+    Search,
+    /// Accepting an Invite, which can create a new Agent
+    Register,
+}
+
+impl Category {
+    /// Determines the category from the route of the request.
+    pub fn from_request(method: &Method, path: &str, query: &str) -> Category {
+        let is_post = method == Method::POST;
+        match path {
+            "/commit" | "/commits/bulk" | "/upload" if is_post => Category::Commit,
+            // Can create a new Agent
+            "/auth/passkey/register" if is_post => Category::Register,
+            "/search" => Category::Search,
+            // Invites create Agents when a public key is passed
+            _ if query.split('&').any(|pair| pair.starts_with("public-key=")) => Category::Register,
+            _ => Category::Read,
+        }
+    }
+}
+
+/// Maximum amount of requests per minute, for every IP or Agent. `0` means unlimited.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub reads: u32,
+    pub commits: u32,
+    pub search: u32,
+    pub registrations: u32,
+}
+
+impl Limits {
+    fn get(&self, category: Category) -> u32 {
+        match category {
+            Category::Read => self.reads,
+            Category::Commit => self.commits,
+            Category::Search => self.search,
+            Category::Register => self.registrations,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: Limits,
+    /// IP addresses and Agent subjects that are never limited
+    allowlist: HashSet<String>,
+    windows: Mutex<HashMap<(Category, String), Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Limits, allowlist: impl IntoIterator<Item = String>) -> RateLimiter {
+        RateLimiter {
+            limits,
+            allowlist: allowlist.into_iter().collect(),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn init_from_config(config: &Config) -> RateLimiter {
+        let opts = &config.opts;
+        RateLimiter::new(
+            Limits {
+                reads: opts.rate_limit_reads,
+                commits: opts.rate_limit_commits,
+                search: opts.rate_limit_search,
+                registrations: opts.rate_limit_registrations,
+            },
+            opts.rate_limit_allowlist.clone(),
+        )
+    }
+
+    /// Counts a request by `client` (an IP address or Agent subject).
+    /// Returns an error if the client has exceeded its limit.
+    pub fn hit(&self, category: Category, client: &str) -> AtomicServerResult<()> {
+        self.check(category, client, true)
+    }
+
+    /// Returns an error if the client has exceeded its limit, without counting a request.
+    /// Use [RateLimiter::hit] after the request has been handled.
+    pub fn check_exceeded(&self, category: Category, client: &str) -> AtomicServerResult<()> {
+        self.check(category, client, false)
+    }
+
+    fn check(&self, category: Category, client: &str, count: bool) -> AtomicServerResult<()> {
+        let limit = self.limits.get(category);
+        if limit == 0 || self.allowlist.contains(client) {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut windows = self.windows.lock()?;
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_k, w| now.duration_since(w.started) < WINDOW);
+        }
+        let window = windows
+            .entry((category, client.to_string()))
+            .or_insert(Window {
+                started: now,
+                count: 0,
+            });
+        if now.duration_since(window.started) >= WINDOW {
+            window.started = now;
+            window.count = 0;
+        }
+        if window.count >= limit {
+            let retry_after = WINDOW.saturating_sub(now.duration_since(window.started));
+            return Err(too_many_requests(category, retry_after));
+        }
+        if count {
+            window.count += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Counts a request by an authenticated Agent.
+/// Anonymous requests (by the Public Agent) are only limited by IP.
+pub fn limit_agent(
+    appstate: &AppState,
+    category: Category,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<()> {
+    match for_agent {
+        Some(agent) if agent != atomic_lib::urls::PUBLIC_AGENT => {
+            appstate.rate_limiter.hit(category, agent)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn too_many_requests(category: Category, retry_after: Duration) -> AtomicServerError {
+    // Round up, so clients don't retry too early
+    let retry_after = retry_after.as_secs() + 1;
+    AtomicServerError {
+        message: format!(
+            "Too many {:?} requests. Try again in {} seconds.",
+            category, retry_after
+        ),
+        error_type: AppErrorType::TooManyRequests { retry_after },
+        error_resource: None,
+    }
+}
+
+/// Middleware that limits the amount of requests per IP address, and rejects IP addresses that are not allowed.
+pub fn limit_by_ip<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let limited = req.app_data::<web::Data<AppState>>().and_then(|appstate| {
+        let path = req
+            .path()
+            .strip_prefix(&appstate.config.path_prefix)
+            .unwrap_or(req.path());
+        let category = Category::from_request(req.method(), path, req.query_string());
+        let ip =
+            crate::helpers::client_ip(&req.connection_info(), req.peer_addr(), &appstate.config);
+        if let Err(e) = appstate.ip_access.check(ip.as_deref()) {
+            return Some(e);
+        }
+        appstate.rate_limiter.hit(category, &ip?).err()
+    });
+    let response = match limited {
+        Some(err) => Err(err),
+        None => Ok(srv.call(req)),
+    };
+    async move { response?.await }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> Limits {
+        Limits {
+            reads: 2,
+            commits: 1,
+            search: 0,
+            registrations: 1,
+        }
+    }
+
+    #[test]
+    fn limits_per_client_and_category() {
+        let limiter = RateLimiter::new(limits(), ["10.0.0.9".to_string()]);
+        limiter.hit(Category::Read, "10.0.0.1").unwrap();
+        limiter.hit(Category::Read, "10.0.0.1").unwrap();
+        let err = limiter.hit(Category::Read, "10.0.0.1").unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            AppErrorType::TooManyRequests { retry_after } if retry_after <= 60
+        ));
+        // Other clients and categories have their own budget
+        limiter.hit(Category::Read, "10.0.0.2").unwrap();
+        limiter.hit(Category::Commit, "10.0.0.1").unwrap();
+        // Unlimited
+        for _ in 0..10 {
+            limiter.hit(Category::Search, "10.0.0.1").unwrap();
+        }
+        // Allowlisted
+        for _ in 0..10 {
+            limiter.hit(Category::Read, "10.0.0.9").unwrap();
+        }
+    }
+
+    #[test]
+    fn check_does_not_count() {
+        let limiter = RateLimiter::new(limits(), []);
+        for _ in 0..10 {
+            limiter
+                .check_exceeded(Category::Commit, "https://example.com/agents/a")
+                .unwrap();
+        }
+        limiter
+            .hit(Category::Commit, "https://example.com/agents/a")
+            .unwrap();
+        limiter
+            .check_exceeded(Category::Commit, "https://example.com/agents/a")
+            .unwrap_err();
+    }
+
+    #[test]
+    fn categorizes_requests() {
+        assert_eq!(
+            Category::from_request(&Method::POST, "/commit", ""),
+            Category::Commit
+        );
+        assert_eq!(
+            Category::from_request(&Method::GET, "/search", "q=test"),
+            Category::Search
+        );
+        assert_eq!(
+            Category::from_request(&Method::GET, "/setup", "public-key=abc"),
+            Category::Register
+        );
+        assert_eq!(
+            Category::from_request(&Method::POST, "/auth/passkey/register", ""),
+            Category::Register
+        );
+        assert_eq!(
+            Category::from_request(&Method::GET, "/commit", ""),
+            Category::Read
+        );
+    }
+}
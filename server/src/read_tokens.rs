@@ -0,0 +1,175 @@
+//! Read tokens let third-party sites embed private content, without signing requests.
+//! A token is minted at `/read-tokens` for a parent resource, and contains the parent, the expiry date and the Agent that minted it.
+//! It is signed by the default Agent of the server, just like [crate::share_links].
+//!
+//! The token is passed as the `read-token` query parameter, or as the `x-atomic-read-token` header.
+//! Requests for the parent or one of its descendants read with the rights of the Agent that minted the token,
+//! so the token stops working when that Agent loses its rights.
+//! Requests for other resources are handled as if there was no token, usually as the PublicAgent.
+//! Read tokens are only honored when reading resources. Other endpoints, such as the ones that manage API tokens, reject them.
+
+use actix_web::http::header::HeaderMap;
+use atomic_lib::{AtomicError, Storelike};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::AtomicServerResult,
+    helpers::in_subtree,
+    share_links::{open_claims, sign_claims, take_param},
+};
+
+/// Name of the query parameter that holds the token.
+pub const READ_TOKEN_PARAM: &str = "read-token";
+pub const READ_TOKEN_HEADER: &str = "x-atomic-read-token";
+/// Used when no expiry date is given, in milliseconds.
+pub const DEFAULT_TTL: i64 = 1000 * 60 * 60 * 24 * 30;
+
+/// The contents of a read token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadClaims {
+    /// The token can read this resource and its descendants
+    pub parent: String,
+    /// The Agent that minted the token, and whose rights are used
+    pub agent: String,
+    /// Milliseconds since unix epoch
+    pub expires_at: i64,
+}
+
+fn invalid() -> AtomicError {
+    AtomicError::unauthorized("Invalid read token".into())
+}
+
+pub fn create_token(store: &impl Storelike, claims: &ReadClaims) -> AtomicServerResult<String> {
+    sign_claims(store, claims)
+}
+
+/// Returns the read token from the header, or from the query of the requested subject.
+pub fn get_token(
+    headers: &HeaderMap,
+    requested_subject: &str,
+) -> AtomicServerResult<Option<String>> {
+    if let Some(header) = headers.get(READ_TOKEN_HEADER) {
+        let token = header
+            .to_str()
+            .map_err(|_e| "Only string headers allowed")?;
+        return Ok(Some(token.trim().to_string()));
+    }
+    Ok(requested_subject
+        .split_once('?')
+        .and_then(|(_path, query)| take_param(query, READ_TOKEN_PARAM).1))
+}
+
+/// Checks the signature and expiry of the token.
+/// Returns the Agent whose rights should be used if the requested subject is in the subtree of the token, or `None` if it isn't.
+pub fn verify_token(
+    store: &impl Storelike,
+    token: &str,
+    requested_subject: &str,
+) -> AtomicServerResult<Option<String>> {
+    let claims: ReadClaims = open_claims(store, token)?.ok_or_else(invalid)?;
+    if claims.expires_at < atomic_lib::utils::now() {
+        return Err(AtomicError::unauthorized("This read token has expired".into()).into());
+    }
+    let (path, query) = requested_subject
+        .split_once('?')
+        .unwrap_or((requested_subject, ""));
+    let (rest, _token) = take_param(query, READ_TOKEN_PARAM);
+    let subject = if rest.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, rest)
+    };
+    if !in_subtree(store, &claims.parent, &subject) {
+        return Ok(None);
+    }
+    if atomic_lib::agents::is_disabled(store, &claims.agent) {
+        return Err(
+            AtomicError::unauthorized(format!("Agent {} has been disabled", claims.agent)).into(),
+        );
+    }
+    Ok(Some(claims.agent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_read_tokens() {
+        let store = atomic_lib::Db::init_temp("read_tokens").unwrap();
+        let agent = store.get_default_agent().unwrap();
+        let parent = format!("{}/private", store.get_server_url());
+        let claims = ReadClaims {
+            parent: parent.clone(),
+            agent: agent.subject.clone(),
+            expires_at: atomic_lib::utils::now() + DEFAULT_TTL,
+        };
+        let token = create_token(&store, &claims).unwrap();
+        for (subject, parent) in [
+            (parent.clone(), store.get_server_url().to_string()),
+            (format!("{}/child", parent), parent.clone()),
+        ] {
+            let mut resource = atomic_lib::Resource::new(subject);
+            resource.set_propval_unsafe(
+                atomic_lib::urls::PARENT.into(),
+                atomic_lib::Value::AtomicUrl(parent),
+            );
+            store.add_resource(&resource).unwrap();
+        }
+
+        assert_eq!(
+            verify_token(&store, &token, &parent).unwrap(),
+            Some(agent.subject.clone())
+        );
+        let child = format!(
+            "{}/child?{}={}",
+            parent,
+            READ_TOKEN_PARAM,
+            urlencoding::encode(&token)
+        );
+        assert_eq!(
+            verify_token(&store, &token, &child).unwrap(),
+            Some(agent.subject.clone())
+        );
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_token(&headers, &child).unwrap(), Some(token.clone()));
+        headers.insert(
+            actix_web::http::header::HeaderName::from_static(READ_TOKEN_HEADER),
+            "from-header".parse().unwrap(),
+        );
+        assert_eq!(
+            get_token(&headers, &child).unwrap(),
+            Some("from-header".into())
+        );
+
+        // Outside of the subtree, the token is ignored
+        let other = format!("{}/other", store.get_server_url());
+        assert_eq!(verify_token(&store, &token, &other).unwrap(), None);
+        let not_a_child = format!("{}/not-a-child", parent);
+        assert_eq!(verify_token(&store, &token, &not_a_child).unwrap(), None);
+        verify_token(&store, &format!("{}x", token), &parent).unwrap_err();
+        // Share links can't be used as read tokens
+        let share = crate::share_links::create_token(
+            &store,
+            &crate::share_links::ShareClaims {
+                subject: parent.clone(),
+                agent: agent.subject.clone(),
+                rights: crate::share_links::ShareRights::ReadTree,
+                expires_at: claims.expires_at,
+            },
+        )
+        .unwrap();
+        verify_token(&store, &share, &parent).unwrap_err();
+
+        let expired = create_token(
+            &store,
+            &ReadClaims {
+                expires_at: atomic_lib::utils::now() - 1,
+                ..claims
+            },
+        )
+        .unwrap();
+        verify_token(&store, &expired, &parent).unwrap_err();
+    }
+}
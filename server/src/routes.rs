@@ -1,64 +1,208 @@
 //! Contains routing logic, sends the client to the correct handler.
 //! We should try to minimize what happens in here, since most logic should be defined in Atomic Data - not in the server itself.
 
-use crate::{content_types, handlers};
+use crate::{appstate::AppState, content_types, handlers};
 use actix_web::{guard, http::Method, web};
-use actix_web_static_files::ResourceFiles;
 
 /// Should match all routes
 const ANY: &str = "{tail:.*}";
 
-// Includes the `app_assets` folder files, used for hosting
-// front-end JS bundles, service workers, css, icons and other static files
-include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+/// Set up the routes under the path prefix, if there is one.
+pub fn config_prefixed_routes(app: &mut actix_web::web::ServiceConfig, appstate: &AppState) {
+    let prefix = &appstate.config.path_prefix;
+    if prefix.is_empty() {
+        config_routes(app, appstate);
+    } else {
+        app.service(web::scope(prefix).configure(|app| config_routes(app, appstate)));
+    }
+}
 
 /// Set up the Actix server routes. This defines which paths are used.
 // Keep in mind that the order of these matters. An early, greedy route will take
 // precedence over a later route.
-pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
+pub fn config_routes(app: &mut actix_web::web::ServiceConfig, appstate: &AppState) {
     app.service(web::resource("/ws").to(handlers::web_sockets::web_socket_handler))
         .service(web::resource("/download/{path:[^{}]+}").to(handlers::download::handle_download))
-        // This `generate` imports the static files from the `app_assets` folder
-        .service(
-            ResourceFiles::new("/", generate())
-                .skip_handler_when_not_found()
-                .do_not_resolve_defaults(),
-        )
-        // Catch all (non-download) HTML requests and send them to the single page app
+        // A download, so browsers should not get the single page app
         .service(
-            web::resource(ANY)
+            web::resource("/personal-data")
                 .guard(guard::Method(Method::GET))
-                .guard(guard::fn_guard(|guard_ctx| {
-                    content_types::get_accept(guard_ctx.head().headers())
-                        == content_types::ContentType::Html
-                }))
-                .to(handlers::single_page_app::single_page),
+                .to(handlers::personal_data::export),
         )
+        // Fediverse servers send their own Accept headers, so these come before the single page app
         .service(
-            web::resource("/upload")
-                .guard(guard::Method(Method::POST))
-                .to(handlers::upload::upload_handler),
+            web::resource("/.well-known/webfinger")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::activitypub::webfinger),
         )
         .service(
-            web::resource("/commit")
-                .guard(guard::Method(Method::POST))
-                .to(handlers::commit::post_commit),
+            web::resource("/activitypub/actors/{handle}")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::activitypub::actor),
         )
         .service(
-            web::resource("/search")
+            web::resource("/activitypub/actors/{handle}/outbox")
                 .guard(guard::Method(Method::GET))
-                .to(handlers::search::search_query),
+                .to(handlers::activitypub::outbox),
         )
         .service(
-            web::resource(ANY)
+            web::resource("/activitypub/actors/{handle}/followers")
                 .guard(guard::Method(Method::GET))
-                .to(handlers::get_resource::handle_get_resource),
+                .to(handlers::activitypub::followers),
         )
         .service(
-            web::resource(ANY)
+            web::resource("/activitypub/actors/{handle}/inbox")
                 .guard(guard::Method(Method::POST))
-                .to(handlers::post_resource::handle_post_resource),
-        )
-        // Also allow the home resource (not matched by the previous one)
-        .service(web::resource("/").to(handlers::get_resource::handle_get_resource));
+                .to(handlers::activitypub::inbox),
+        );
+    // Solid apps expect Turtle, even when they are opened in a browser
+    for path in ["/solid", "/solid/{path:.*}"] {
+        app.service(
+            web::resource(path)
+                .route(web::get().to(handlers::solid::handle_get))
+                .route(web::put().to(handlers::solid::handle_put))
+                .route(web::post().to(handlers::solid::handle_post))
+                .route(web::delete().to(handlers::solid::handle_delete)),
+        );
+    }
+    // Front-end JS bundles, icons and other static files
+    appstate.assets.register(app);
+    // Catch all (non-download) HTML requests and send them to the single page app.
+    // Crawlers and text browsers get a server rendered page instead.
+    if appstate.assets.index_html().is_some() {
+        app.service(
+            web::resource(ANY)
+                .guard(guard::Method(Method::GET))
+                .guard(guard::fn_guard(|guard_ctx| {
+                    content_types::get_accept(guard_ctx.head().headers())
+                        == content_types::ContentType::Html
+                        && !crate::feeds::is_feed_path(guard_ctx.head().uri.path())
+                        && !crate::calendar::is_calendar_path(guard_ctx.head().uri.path())
+                        && !crate::html::is_no_js_request(
+                            guard_ctx.head().uri.path(),
+                            guard_ctx.head().headers(),
+                        )
+                }))
+                .to(handlers::single_page_app::single_page),
+        );
+    }
+    app.service(
+        web::resource("/upload")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::upload::upload_handler),
+    )
+    .service(
+        web::resource("/commit")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::commit::post_commit),
+    )
+    .service(
+        web::resource("/commits/bulk")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::commit::post_commits_bulk),
+    )
+    .service(
+        web::resource("/auth/oidc/login")
+            .guard(guard::Method(Method::GET))
+            .to(handlers::oidc::login),
+    )
+    .service(
+        web::resource("/auth/oidc/callback")
+            .guard(guard::Method(Method::GET))
+            .to(handlers::oidc::callback),
+    )
+    .service(
+        web::resource("/auth/passkey/register/options")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::passkeys::register_options),
+    )
+    .service(
+        web::resource("/auth/passkey/register")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::passkeys::register),
+    )
+    .service(
+        web::resource("/auth/passkey/login/options")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::passkeys::login_options),
+    )
+    .service(
+        web::resource("/auth/passkey/login")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::passkeys::login),
+    )
+    .service(web::resource("/auth/totp").route(web::get().to(handlers::totp::status)))
+    .service(
+        web::resource("/auth/totp/enroll")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::totp::enroll),
+    )
+    .service(
+        web::resource("/auth/totp/confirm")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::totp::confirm),
+    )
+    .service(
+        web::resource("/auth/totp/disable")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::totp::disable),
+    )
+    .service(
+        web::resource("/auth/totp/verify")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::totp::verify),
+    )
+    .service(
+        web::resource("/share").route(web::post().to(handlers::share_links::create_share_link)),
+    )
+    .service(
+        web::resource("/read-tokens").route(web::post().to(handlers::read_tokens::mint_read_token)),
+    )
+    .service(
+        web::resource("/tokens")
+            .route(web::get().to(handlers::api_tokens::list_tokens))
+            .route(web::post().to(handlers::api_tokens::mint_token)),
+    )
+    .service(
+        web::resource("/tokens/{id}")
+            .guard(guard::Method(Method::DELETE))
+            .to(handlers::api_tokens::revoke_token),
+    )
+    .service(
+        web::resource("/auditlog")
+            .guard(guard::Method(Method::GET))
+            .to(handlers::audit_log::handle_audit_log),
+    )
+    .service(
+        web::resource("/personal-data/erase")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::personal_data::erase),
+    )
+    .service(
+        web::resource("/jobs")
+            .guard(guard::Method(Method::GET))
+            .to(handlers::jobs::list_jobs),
+    )
+    .service(
+        web::resource("/jobs/cancel")
+            .guard(guard::Method(Method::POST))
+            .to(handlers::jobs::cancel_job),
+    )
+    .service(
+        web::resource("/search")
+            .guard(guard::Method(Method::GET))
+            .to(handlers::search::search_query),
+    )
+    .service(
+        web::resource(ANY)
+            .guard(guard::Method(Method::GET))
+            .to(handlers::get_resource::handle_get_resource),
+    )
+    .service(
+        web::resource(ANY)
+            .guard(guard::Method(Method::POST))
+            .to(handlers::post_resource::handle_post_resource),
+    )
+    // Also allow the home resource (not matched by the previous one)
+    .service(web::resource("/").to(handlers::get_resource::handle_get_resource));
 }
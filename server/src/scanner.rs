@@ -0,0 +1,161 @@
+//! Scanning uploaded files for viruses and malware.
+//! A [Scanner] is called by the upload handler for every file, before it is persisted.
+//! ClamAV support is available behind the `clamav` feature flag.
+
+use std::{path::Path, sync::Arc};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+/// Value of `scanStatus` for files that have been flagged by the scanner.
+pub const SCAN_STATUS_INFECTED: &str = "infected";
+
+/// The outcome of scanning a single file.
+// Only constructed by scanners that are behind feature flags
+#[cfg_attr(not(feature = "clamav"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    Clean,
+    /// Contains the name of the signature that matched.
+    Infected(String),
+}
+
+impl ScanResult {
+    /// The value of the `scanStatus` property of the File resource.
+    pub fn status(&self) -> &'static str {
+        match self {
+            ScanResult::Clean => "clean",
+            ScanResult::Infected(_) => SCAN_STATUS_INFECTED,
+        }
+    }
+}
+
+/// Inspects the bytes of an uploaded file.
+/// Scanning is blocking, so it should not be called directly from an async context.
+pub trait Scanner: std::fmt::Debug + Send + Sync {
+    fn scan(&self, path: &Path) -> AtomicServerResult<ScanResult>;
+}
+
+/// Returns the scanner that has been configured, if any.
+#[allow(unused_variables)]
+pub fn init_from_config(config: &Config) -> Option<Arc<dyn Scanner>> {
+    #[cfg(feature = "clamav")]
+    if let Some(address) = &config.opts.clamav_address {
+        return Some(Arc::new(clamav::ClamAv {
+            address: address.clone(),
+        }));
+    }
+    None
+}
+
+#[cfg(feature = "clamav")]
+pub mod clamav {
+    //! Scans files using a running `clamd` daemon, using its `INSTREAM` command over TCP.
+    //! See https://docs.clamav.net/manual/Usage/Scanning.html#clamd
+
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        path::Path,
+    };
+
+    use super::{ScanResult, Scanner};
+    use crate::errors::AtomicServerResult;
+
+    /// Chunks should not exceed clamd's `StreamMaxLength`.
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    #[derive(Debug)]
+    pub struct ClamAv {
+        /// `host:port` of the clamd TCP socket
+        pub address: String,
+    }
+
+    impl Scanner for ClamAv {
+        fn scan(&self, path: &Path) -> AtomicServerResult<ScanResult> {
+            let mut file = std::fs::File::open(path)?;
+            let mut stream = TcpStream::connect(&self.address)
+                .map_err(|e| format!("Could not connect to ClamAV at {}: {}", self.address, e))?;
+            stream.write_all(b"zINSTREAM\0")?;
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                stream.write_all(&(read as u32).to_be_bytes())?;
+                stream.write_all(&buffer[..read])?;
+            }
+            // A zero length chunk marks the end of the stream
+            stream.write_all(&0u32.to_be_bytes())?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            parse_response(&response)
+        }
+    }
+
+    /// Parses responses like `stream: OK` and `stream: Eicar-Signature FOUND`.
+    fn parse_response(response: &str) -> AtomicServerResult<ScanResult> {
+        let response = response.trim_end_matches('\0').trim();
+        let result = response.strip_prefix("stream: ").unwrap_or(response);
+        if result == "OK" {
+            Ok(ScanResult::Clean)
+        } else if let Some(signature) = result.strip_suffix(" FOUND") {
+            Ok(ScanResult::Infected(signature.to_string()))
+        } else {
+            Err(format!("Unexpected response from ClamAV: {}", response).into())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parses_clamd_responses() {
+            assert_eq!(parse_response("stream: OK\0").unwrap(), ScanResult::Clean);
+            assert_eq!(
+                parse_response("stream: Eicar-Signature FOUND\0").unwrap(),
+                ScanResult::Infected("Eicar-Signature".into())
+            );
+            parse_response("INSTREAM size limit exceeded. ERROR\0").unwrap_err();
+        }
+
+        #[test]
+        fn streams_file_to_clamd() {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let server = std::thread::spawn(move || {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut command = [0u8; 10];
+                socket.read_exact(&mut command).unwrap();
+                assert_eq!(&command, b"zINSTREAM\0");
+                let mut received = Vec::new();
+                loop {
+                    let mut len = [0u8; 4];
+                    socket.read_exact(&mut len).unwrap();
+                    let len = u32::from_be_bytes(len) as usize;
+                    if len == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0u8; len];
+                    socket.read_exact(&mut chunk).unwrap();
+                    received.extend(chunk);
+                }
+                let reply: &[u8] = if received == b"evil" {
+                    b"stream: Test-Signature FOUND\0"
+                } else {
+                    b"stream: OK\0"
+                };
+                socket.write_all(reply).unwrap();
+            });
+
+            let dir = std::path::PathBuf::from("./.temp/clamav_scan");
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("evil.txt");
+            std::fs::write(&path, "evil").unwrap();
+            let result = ClamAv { address }.scan(&path).unwrap();
+            server.join().unwrap();
+            assert_eq!(result, ScanResult::Infected("Test-Signature".into()));
+        }
+    }
+}
@@ -1,10 +1,18 @@
 //! Full-text search, powered by Tantivy.
 //! A folder for the index is stored in the config.
 //! You can see the Endpoint on `http://localhost/search`
+use std::collections::HashMap;
+
+use atomic_lib::storelike::Query;
+use atomic_lib::urls;
 use atomic_lib::Db;
 use atomic_lib::Resource;
 use atomic_lib::Storelike;
 use tantivy::schema::*;
+use tantivy::tokenizer::{
+    Language, LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer, Stemmer,
+    StopWordFilter, TextAnalyzer,
+};
 use tantivy::Index;
 use tantivy::IndexWriter;
 use tantivy::ReloadPolicy;
@@ -12,6 +20,44 @@ use tantivy::ReloadPolicy;
 use crate::config::Config;
 use crate::errors::AtomicServerResult;
 
+/// How many typos are allowed per word in a search query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fuzziness {
+    /// Levenshtein distance, up to 2
+    Distance(u8),
+    /// Allows no typos in short words, and more in long words
+    Auto,
+}
+
+impl Fuzziness {
+    /// The Levenshtein distance for this word.
+    pub fn distance(&self, word: &str) -> u8 {
+        match self {
+            Fuzziness::Distance(distance) => *distance,
+            Fuzziness::Auto => match word.chars().count() {
+                0..=3 => 0,
+                4..=7 => 1,
+                _ => 2,
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Fuzziness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Fuzziness::Auto),
+            "0" | "1" | "2" => Ok(Fuzziness::Distance(s.parse().unwrap_or_default())),
+            other => Err(format!(
+                "Invalid fuzziness '{}'. Use 0, 1, 2 or auto.",
+                other
+            )),
+        }
+    }
+}
+
 /// The actual Schema used for search.
 /// It mimics a single Atom (or Triple).
 #[derive(Debug)]
@@ -19,8 +65,185 @@ pub struct Fields {
     pub subject: Field,
     pub title: Field,
     pub description: Field,
+    /// The text in uploaded Files, see `fileText`
+    pub content: Field,
     pub propvals: Field,
     pub hierarchy: Field,
+    /// Subjects of all ancestors of the resource. Used for filtering by `parent`.
+    pub parents: Field,
+    /// Agents that are allowed to read the resource, see [agents_with_read_right].
+    pub read_rights: Field,
+    /// The classes (`isA`) of the resource, as facets. Used for filtering and counting by `class`.
+    pub classes: Field,
+    /// The Drive that contains the resource, as a facet. See [resource_drive].
+    pub drive: Field,
+    /// Exact values of properties, see [property_value_key]. Used for filtering by `property` and `value`.
+    pub property_values: Field,
+    /// Latitudes and longitudes of all GeoPoints in the resource. Used for filtering by `near` and `bbox`.
+    pub latitude: Field,
+    pub longitude: Field,
+    /// One text field for every language in [SEARCH_LANGUAGES], keyed by language code.
+    pub localized: HashMap<String, Field>,
+}
+
+/// Language codes that can be used as the value of the `searchLanguage` property.
+/// Every language gets its own field in the index, with its own analyzer.
+pub const SEARCH_LANGUAGES: &[&str] = &[
+    "ar", "cjk", "da", "de", "el", "en", "es", "fi", "fr", "hu", "it", "nl", "no", "pt", "ro",
+    "ru", "sv", "ta", "tr",
+];
+
+/// Name of the field in which text in a specific language is indexed.
+fn localized_field_name(code: &str) -> String {
+    format!("text_{}", code)
+}
+
+/// Name under which the analyzer for a language is registered in the index.
+pub fn tokenizer_name(code: &str) -> String {
+    format!("atomic_{}", code)
+}
+
+fn code_to_language(code: &str) -> Option<Language> {
+    let language = match code {
+        "ar" => Language::Arabic,
+        "da" => Language::Danish,
+        "de" => Language::German,
+        "el" => Language::Greek,
+        "en" => Language::English,
+        "es" => Language::Spanish,
+        "fi" => Language::Finnish,
+        "fr" => Language::French,
+        "hu" => Language::Hungarian,
+        "it" => Language::Italian,
+        "nl" => Language::Dutch,
+        "no" => Language::Norwegian,
+        "pt" => Language::Portuguese,
+        "ro" => Language::Romanian,
+        "ru" => Language::Russian,
+        "sv" => Language::Swedish,
+        "ta" => Language::Tamil,
+        "tr" => Language::Turkish,
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Server wide configuration of how text is analyzed for search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchSettings {
+    /// Language of resources that have no `searchLanguage` in themselves or their parents
+    pub default_language: Option<String>,
+    /// Reduce words to their stem, so `running` matches `run`
+    pub stemming: bool,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings {
+            default_language: None,
+            stemming: true,
+        }
+    }
+}
+
+/// Name of the file in the index folder that stores the [SearchSettings] it was built with.
+const SETTINGS_FILE: &str = "atomic_search_settings";
+
+impl SearchSettings {
+    pub fn from_config(config: &Config) -> AtomicServerResult<SearchSettings> {
+        if let Some(language) = &config.opts.search_language {
+            if !SEARCH_LANGUAGES.contains(&language.as_str()) {
+                return Err(format!(
+                    "Unsupported search language '{}'. Use one of: {}",
+                    language,
+                    SEARCH_LANGUAGES.join(", ")
+                )
+                .into());
+            }
+        }
+        Ok(SearchSettings {
+            default_language: config.opts.search_language.clone(),
+            stemming: !config.opts.search_no_stemming,
+        })
+    }
+
+    /// A line that describes these settings, which is compared to the one stored in the index.
+    fn fingerprint(&self) -> String {
+        format!(
+            "language={};stemming={}",
+            self.default_language.as_deref().unwrap_or_default(),
+            self.stemming
+        )
+    }
+}
+
+/// Builds the analyzer for a language code.
+/// Stemmed languages also remove stopwords, if tantivy has a list for them.
+/// CJK text has no whitespace between words, so it is split into unigrams and bigrams.
+pub fn language_analyzer(code: &str, stemming: bool) -> Option<TextAnalyzer> {
+    if code == "cjk" {
+        return Some(
+            TextAnalyzer::from(NgramTokenizer::new(1, 2, false))
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser),
+        );
+    }
+    let language = code_to_language(code)?;
+    let mut analyzer = TextAnalyzer::from(SimpleTokenizer)
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser);
+    if let Some(stopwords) = StopWordFilter::new(language) {
+        analyzer = analyzer.filter(stopwords);
+    }
+    if !stemming {
+        return Some(analyzer);
+    }
+    Some(analyzer.filter(Stemmer::new(language)))
+}
+
+/// Registers the analyzers for all [SEARCH_LANGUAGES], which are used by the localized fields.
+/// These are not persisted in the index, so this has to run every time the index is opened.
+pub fn register_tokenizers(index: &Index, settings: &SearchSettings) {
+    for code in SEARCH_LANGUAGES {
+        if let Some(analyzer) = language_analyzer(code, settings.stemming) {
+            index.tokenizers().register(&tokenizer_name(code), analyzer);
+        }
+    }
+}
+
+/// Tracks the updates that are waiting to be committed to the search index, see [crate::commit_monitor].
+#[derive(Debug, Default)]
+pub struct IndexLag {
+    pending: std::sync::atomic::AtomicUsize,
+    /// When the oldest pending update came in
+    oldest: std::sync::Mutex<Option<std::time::Instant>>,
+}
+
+impl IndexLag {
+    /// Amount of resources that are waiting to be indexed or removed.
+    pub fn pending(&self) -> usize {
+        self.pending.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// How long the oldest pending update has been waiting. Zero if the index is up to date.
+    pub fn lag(&self) -> std::time::Duration {
+        self.oldest
+            .lock()
+            .expect("Index lag lock poisoned")
+            .map(|oldest| oldest.elapsed())
+            .unwrap_or_default()
+    }
+
+    pub fn set_pending(&self, pending: usize) {
+        self.pending
+            .store(pending, std::sync::atomic::Ordering::Relaxed);
+        let mut oldest = self.oldest.lock().expect("Index lag lock poisoned");
+        if pending == 0 {
+            *oldest = None;
+        } else if oldest.is_none() {
+            *oldest = Some(std::time::Instant::now());
+        }
+    }
 }
 
 /// Contains the index and the schema. for search
@@ -36,13 +259,17 @@ pub struct SearchState {
     pub writer: std::sync::Arc<std::sync::RwLock<tantivy::IndexWriter>>,
     /// The shape of data stored in the index
     pub schema: tantivy::schema::Schema,
+    pub settings: SearchSettings,
+    /// Updates that are not yet visible in search results
+    pub lag: std::sync::Arc<IndexLag>,
 }
 
 impl SearchState {
     /// Create a new SearchState for the Server, which includes building the schema and index.
     pub fn new(config: &Config) -> AtomicServerResult<SearchState> {
         let schema = crate::search::build_schema()?;
-        let (writer, index) = crate::search::get_index(config)?;
+        let settings = SearchSettings::from_config(config)?;
+        let (writer, index) = crate::search::get_index(config, &settings)?;
         let reader = crate::search::get_reader(&index)?;
         let locked = std::sync::RwLock::from(writer);
         let arced = std::sync::Arc::from(locked);
@@ -51,6 +278,8 @@ impl SearchState {
             reader,
             index,
             writer: arced,
+            settings,
+            lag: Default::default(),
         })
     }
 }
@@ -62,29 +291,77 @@ pub fn build_schema() -> AtomicServerResult<tantivy::schema::Schema> {
     schema_builder.add_text_field("subject", TEXT | STORED);
     schema_builder.add_text_field("title", TEXT | STORED);
     schema_builder.add_text_field("description", TEXT | STORED);
+    schema_builder.add_text_field("content", TEXT | STORED);
     schema_builder.add_json_field("propvals", STORED | TEXT);
     schema_builder.add_facet_field("hierarchy", STORED);
+    schema_builder.add_text_field("parents", STRING);
+    schema_builder.add_text_field("read_rights", STRING);
+    schema_builder.add_facet_field("classes", INDEXED);
+    schema_builder.add_facet_field("drive", INDEXED);
+    schema_builder.add_text_field("property_values", STRING);
+    schema_builder.add_f64_field("latitude", INDEXED);
+    schema_builder.add_f64_field("longitude", INDEXED);
+    for code in SEARCH_LANGUAGES {
+        let indexing = TextFieldIndexing::default()
+            .set_tokenizer(&tokenizer_name(code))
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+        schema_builder.add_text_field(
+            &localized_field_name(code),
+            TextOptions::default().set_indexing_options(indexing),
+        );
+    }
     let schema = schema_builder.build();
     Ok(schema)
 }
 
 /// Creates or reads the index from the `search_index_path` and allocates some heap size.
-pub fn get_index(config: &Config) -> AtomicServerResult<(IndexWriter, Index)> {
+/// If the [SearchSettings] differ from the ones the index was built with, the index is emptied.
+pub fn get_index(
+    config: &Config,
+    settings: &SearchSettings,
+) -> AtomicServerResult<(IndexWriter, Index)> {
     let schema = build_schema()?;
     std::fs::create_dir_all(&config.search_index_path)?;
-    if config.opts.rebuild_indexes {
+    let settings_path = config.search_index_path.join(SETTINGS_FILE);
+    // Indexes without a settings file were built using the default settings
+    let previous_settings = std::fs::read_to_string(&settings_path)
+        .unwrap_or_else(|_| SearchSettings::default().fingerprint());
+    let settings_changed = previous_settings.trim() != settings.fingerprint();
+    if settings_changed {
+        tracing::warn!(
+            "Search settings have changed ({} -> {}), removing existing index",
+            previous_settings.trim(),
+            settings.fingerprint()
+        );
+    }
+    if config.opts.rebuild_indexes || settings_changed {
         std::fs::remove_dir_all(&config.search_index_path)?;
         std::fs::create_dir_all(&config.search_index_path)?;
     }
     let mmap_directory = tantivy::directory::MmapDirectory::open(&config.search_index_path)?;
-    let index = Index::open_or_create(mmap_directory, schema).map_err(|e| {
-        format!(
-            "Failed to create or open search index. Try starting again with --rebuild-index. Error: {}",
-            e
-        )
-    })?;
-    let heap_size_bytes = 50_000_000;
-    let index_writer = index.writer(heap_size_bytes)?;
+    let index = match Index::open_or_create(mmap_directory, schema.clone()) {
+        Ok(index) => index,
+        // The fields (e.g. the localized ones) have changed since the index was created.
+        // The index is emptied, and filled again in `appstate::init`.
+        Err(tantivy::TantivyError::SchemaError(e)) => {
+            tracing::warn!("Search index schema has changed, removing existing index: {}", e);
+            std::fs::remove_dir_all(&config.search_index_path)?;
+            std::fs::create_dir_all(&config.search_index_path)?;
+            let mmap_directory =
+                tantivy::directory::MmapDirectory::open(&config.search_index_path)?;
+            Index::create(mmap_directory, schema, tantivy::IndexSettings::default())?
+        }
+        Err(e) => {
+            return Err(format!(
+                "Failed to create or open search index. Try starting again with --rebuild-index. Error: {}",
+                e
+            )
+            .into())
+        }
+    };
+    std::fs::write(&settings_path, settings.fingerprint())?;
+    register_tokenizers(&index, settings);
+    let index_writer = index.writer(config.memory.search_heap as usize)?;
     Ok((index_writer, index))
 }
 
@@ -102,6 +379,10 @@ pub fn get_schema_fields(appstate: &SearchState) -> AtomicServerResult<Fields> {
         .schema
         .get_field("description")
         .ok_or("No 'description' in the schema")?;
+    let content = appstate
+        .schema
+        .get_field("content")
+        .ok_or("No 'content' in the schema")?;
     let propvals = appstate
         .schema
         .get_field("propvals")
@@ -110,13 +391,59 @@ pub fn get_schema_fields(appstate: &SearchState) -> AtomicServerResult<Fields> {
         .schema
         .get_field("hierarchy")
         .ok_or("No 'hierarchy' in the schema")?;
+    let parents = appstate
+        .schema
+        .get_field("parents")
+        .ok_or("No 'parents' in the schema")?;
+    let read_rights = appstate
+        .schema
+        .get_field("read_rights")
+        .ok_or("No 'read_rights' in the schema")?;
+    let classes = appstate
+        .schema
+        .get_field("classes")
+        .ok_or("No 'classes' in the schema")?;
+    let drive = appstate
+        .schema
+        .get_field("drive")
+        .ok_or("No 'drive' in the schema")?;
+    let property_values = appstate
+        .schema
+        .get_field("property_values")
+        .ok_or("No 'property_values' in the schema")?;
+    let latitude = appstate
+        .schema
+        .get_field("latitude")
+        .ok_or("No 'latitude' in the schema")?;
+    let longitude = appstate
+        .schema
+        .get_field("longitude")
+        .ok_or("No 'longitude' in the schema")?;
+    let mut localized = HashMap::new();
+    for code in SEARCH_LANGUAGES {
+        let name = localized_field_name(code);
+        let field = appstate
+            .schema
+            .get_field(&name)
+            .ok_or(format!("No '{}' in the schema", name))?;
+        localized.insert(code.to_string(), field);
+    }
 
     Ok(Fields {
         subject,
         title,
         description,
+        content,
         propvals,
         hierarchy,
+        parents,
+        read_rights,
+        classes,
+        drive,
+        property_values,
+        latitude,
+        longitude,
+        localized,
     })
 }
 
@@ -144,8 +471,50 @@ pub fn add_all_resources(search_state: &SearchState, store: &Db) -> AtomicServer
     Ok(())
 }
 
+/// Removes all documents from the search index and indexes every resource again.
+/// Used when the analyzer configuration changes, since existing documents were tokenized using the old settings.
+pub fn rebuild_index(search_state: &SearchState, store: &Db) -> AtomicServerResult<()> {
+    tracing::info!("Search configuration changed, rebuilding search index...");
+    search_state.writer.read()?.delete_all_documents()?;
+    add_all_resources(search_state, store)
+}
+
+/// Returns the `searchLanguage` of the resource itself, or of its closest parent that has one.
+/// This is how a Drive (or folder) sets the language for all of its children.
+/// Falls back to the `--search-language` of the server.
+fn resolve_language(
+    resource: &Resource,
+    parent_tree: &[Resource],
+    default_language: Option<&str>,
+) -> Option<String> {
+    std::iter::once(resource)
+        .chain(parent_tree.iter())
+        .find_map(|r| r.get(urls::SEARCH_LANGUAGE).ok())
+        .map(|v| v.to_string())
+        .or_else(|| default_language.map(String::from))
+}
+
+/// Returns the `searchLanguage` that is set on a Property, if any.
+fn property_language(property: &str, store: &Db) -> Option<String> {
+    store
+        .get_resource(property)
+        .ok()?
+        .get(urls::SEARCH_LANGUAGE)
+        .ok()
+        .map(|v| v.to_string())
+}
+
+/// Adds a piece of text to the field of the given language, if that language is supported.
+fn add_localized_text(doc: &mut Document, fields: &Fields, language: &str, text: &str) {
+    match fields.localized.get(language) {
+        Some(field) => doc.add_text(*field, text),
+        None => tracing::warn!("Unsupported search language: {}", language),
+    }
+}
+
 /// Adds a single resource to the search index, but does _not_ commit!
-/// Does not index outgoing links, or resourcesArrays
+/// Does not index outgoing links, or resourcesArrays.
+/// Skips resources that have `noIndex` set on themselves or a parent.
 /// `appstate.search_index_writer.write()?.commit()?;`
 #[tracing::instrument(skip(appstate, store))]
 pub fn add_resource(
@@ -153,6 +522,11 @@ pub fn add_resource(
     resource: &Resource,
     store: &Db,
 ) -> AtomicServerResult<()> {
+    let parent_tree = resource.get_parent_tree(store)?;
+    if atomic_lib::hierarchy::inherited_flag(resource, &parent_tree, urls::NO_INDEX) {
+        return Ok(());
+    }
+
     let fields = get_schema_fields(appstate)?;
     let subject = resource.get_subject();
     let writer = appstate.writer.read()?;
@@ -167,22 +541,154 @@ pub fn add_resource(
     doc.add_json_object(fields.propvals, json_obj);
 
     doc.add_text(fields.subject, subject);
-    doc.add_text(fields.title, get_resource_title(resource));
 
-    if let Ok(atomic_lib::Value::Markdown(description)) =
+    let title = get_resource_title(resource);
+    doc.add_text(fields.title, &title);
+
+    let description = if let Ok(atomic_lib::Value::Markdown(description)) =
         resource.get(atomic_lib::urls::DESCRIPTION)
     {
         doc.add_text(fields.description, description);
+        Some(description)
+    } else {
+        None
     };
 
+    let content = if let Ok(atomic_lib::Value::String(content)) = resource.get(urls::FILE_TEXT) {
+        doc.add_text(fields.content, content);
+        Some(content)
+    } else {
+        None
+    };
+
+    if let Some(language) = resolve_language(
+        resource,
+        &parent_tree,
+        appstate.settings.default_language.as_deref(),
+    ) {
+        add_localized_text(&mut doc, &fields, &language, &title);
+        for text in [description, content].into_iter().flatten() {
+            add_localized_text(&mut doc, &fields, &language, text);
+        }
+    }
+
+    // Properties can override the language of their own values
+    for (prop, val) in resource.get_propvals() {
+        match val {
+            atomic_lib::Value::String(text) | atomic_lib::Value::Markdown(text) => {
+                if let Some(language) = property_language(prop, store) {
+                    add_localized_text(&mut doc, &fields, &language, text);
+                }
+            }
+            _other => {}
+        }
+    }
+
     let hierarchy = resource_to_facet(resource, store)?;
     doc.add_facet(fields.hierarchy, hierarchy);
 
+    for parent in &parent_tree {
+        doc.add_text(fields.parents, parent.get_subject());
+    }
+    for agent in agents_with_read_right(resource, store)? {
+        doc.add_text(fields.read_rights, agent);
+    }
+    if let Ok(classes) = resource.get(urls::IS_A).and_then(|v| v.to_subjects(None)) {
+        for class in classes {
+            doc.add_facet(fields.classes, subject_facet(&class));
+        }
+    }
+    if let Some(drive) = resource_drive(&parent_tree) {
+        doc.add_facet(fields.drive, subject_facet(drive.get_subject()));
+    }
+    for (prop, val) in resource.get_propvals() {
+        for value in exact_values(val) {
+            doc.add_text(fields.property_values, property_value_key(prop, &value));
+        }
+        if let atomic_lib::Value::GeoPoint(point) = val {
+            doc.add_f64(fields.latitude, point.latitude);
+            doc.add_f64(fields.longitude, point.longitude);
+        }
+    }
+
     writer.add_document(doc)?;
 
     Ok(())
 }
 
+/// Indexes all descendants of a resource again, but does _not_ commit!
+/// Their documents contain the parents and read rights of their ancestors,
+/// so these have to be updated when a resource is moved or its rights change.
+pub fn reindex_descendants(
+    search_state: &SearchState,
+    store: &Db,
+    subject: &str,
+) -> AtomicServerResult<()> {
+    let children = store
+        .query(&Query::new_prop_val(urls::PARENT, subject))?
+        .resources;
+    for child in children {
+        remove_resource(search_state, child.get_subject())?;
+        add_resource(search_state, &child, store)?;
+        reindex_descendants(search_state, store, child.get_subject())?;
+    }
+    Ok(())
+}
+
+/// Returns the Drive that the resource is part of: its closest ancestor that is a Drive, or otherwise its root.
+/// Drives themselves (and other resources without a parent) are not part of a Drive.
+pub fn resource_drive(parent_tree: &[Resource]) -> Option<&Resource> {
+    parent_tree
+        .iter()
+        .find(|parent| {
+            parent
+                .get(urls::IS_A)
+                .and_then(|classes| classes.to_subjects(None))
+                .is_ok_and(|classes| classes.iter().any(|c| c == urls::DRIVE))
+        })
+        .or_else(|| parent_tree.last())
+}
+
+/// A single facet for a subject. Unlike [subject_to_facet], its slashes are not treated as separators.
+pub fn subject_facet(subject: &str) -> Facet {
+    Facet::from_path([subject])
+}
+
+/// The subject that is stored in a facet created by [subject_facet].
+pub fn facet_subject(facet: &Facet) -> String {
+    facet.to_path().join("/")
+}
+
+/// How a property and one of its values are stored in the `property_values` field.
+pub fn property_value_key(property: &str, value: &str) -> String {
+    format!("{}\t{}", property, value)
+}
+
+/// The values that can be matched exactly using the `value` search param.
+/// Every subject in a ResourceArray is a value, as are single values. Long texts and nested resources are skipped.
+fn exact_values(value: &atomic_lib::Value) -> Vec<String> {
+    match value {
+        atomic_lib::Value::ResourceArray(_) => value.to_subjects(None).unwrap_or_default(),
+        atomic_lib::Value::Markdown(_)
+        | atomic_lib::Value::NestedResource(_)
+        | atomic_lib::Value::Resource(_)
+        | atomic_lib::Value::Unsupported(_) => Vec::new(),
+        other => vec![other.to_string()],
+    }
+}
+
+/// Returns the Agents that may read the resource, which are stored in the index so that search results can be filtered by rights.
+/// Since Agents can always read themselves, the subject of the resource is included too.
+fn agents_with_read_right(resource: &Resource, store: &Db) -> AtomicServerResult<Vec<String>> {
+    let mut agents = atomic_lib::hierarchy::agents_with_right(
+        store,
+        resource,
+        atomic_lib::hierarchy::Right::Read,
+    )?;
+    agents.push(resource.get_subject().to_string());
+    Ok(agents)
+}
+
 /// Removes a single resource from the search index, but does _not_ commit!
 /// Does not index outgoing links, or resourcesArrays
 /// `appstate.search_index_writer.write()?.commit()?;`
@@ -260,7 +766,111 @@ fn get_resource_title(resource: &Resource) -> String {
 mod tests {
     use atomic_lib::{urls, Resource, Storelike};
 
-    use super::resource_to_facet;
+    use super::{
+        agents_with_read_right, exact_values, facet_subject, language_analyzer, resolve_language,
+        resource_drive, resource_to_facet, subject_facet, Fuzziness, IndexLag, SEARCH_LANGUAGES,
+    };
+
+    fn analyze(code: &str, text: &str) -> Vec<String> {
+        let analyzer = language_analyzer(code, true).unwrap();
+        let mut stream = analyzer.token_stream(text);
+        let mut tokens = Vec::new();
+        stream.process(&mut |token| tokens.push(token.text.clone()));
+        tokens
+    }
+
+    #[test]
+    fn language_analyzers() {
+        for code in SEARCH_LANGUAGES {
+            assert!(
+                language_analyzer(code, true).is_some(),
+                "No analyzer for {code}"
+            );
+        }
+        assert!(language_analyzer("klingon", true).is_none());
+        // Stopwords are removed, words are stemmed
+        assert_eq!(analyze("nl", "De fietsen"), vec!["fiets"]);
+        assert_eq!(analyze("en", "the running dogs"), vec!["run", "dog"]);
+        assert!(analyze("cjk", "東京都").contains(&"東京".to_string()));
+
+        let unstemmed = language_analyzer("en", false).unwrap();
+        let mut stream = unstemmed.token_stream("the running dogs");
+        let mut tokens = Vec::new();
+        stream.process(&mut |token| tokens.push(token.text.clone()));
+        assert_eq!(tokens, vec!["running", "dogs"]);
+    }
+
+    #[test]
+    fn tracks_index_lag() {
+        let lag = IndexLag::default();
+        assert_eq!(lag.lag(), std::time::Duration::ZERO);
+        lag.set_pending(2);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        lag.set_pending(3);
+        assert_eq!(lag.pending(), 3);
+        assert!(lag.lag() >= std::time::Duration::from_millis(5));
+        lag.set_pending(0);
+        assert_eq!(lag.lag(), std::time::Duration::ZERO);
+    }
+
+    #[test]
+    fn parses_fuzziness() {
+        assert_eq!("2".parse::<Fuzziness>().unwrap(), Fuzziness::Distance(2));
+        assert!("3".parse::<Fuzziness>().is_err());
+        let auto: Fuzziness = "auto".parse().unwrap();
+        assert_eq!(auto.distance("cat"), 0);
+        assert_eq!(auto.distance("drive"), 1);
+        assert_eq!(auto.distance("invitation"), 2);
+    }
+
+    #[test]
+    fn finds_drive_and_exact_values() {
+        let root = Resource::new("http://example.com".into());
+        let mut drive = Resource::new("http://example.com/drive".into());
+        drive.set_propval_unsafe(urls::IS_A.into(), vec![urls::DRIVE.to_string()].into());
+        let folder = Resource::new("http://example.com/drive/folder".into());
+        let tree = [folder.clone(), drive.clone(), root.clone()];
+        assert_eq!(
+            resource_drive(&tree).unwrap().get_subject(),
+            drive.get_subject()
+        );
+        assert_eq!(
+            resource_drive(&[folder, root.clone()])
+                .unwrap()
+                .get_subject(),
+            root.get_subject()
+        );
+        assert!(resource_drive(&[]).is_none());
+
+        let facet = subject_facet(drive.get_subject());
+        assert_eq!(facet_subject(&facet), "http://example.com/drive");
+        let classes: atomic_lib::Value = vec![urls::DRIVE.to_string()].into();
+        assert_eq!(exact_values(&classes), vec![urls::DRIVE.to_string()]);
+        assert_eq!(exact_values(&atomic_lib::Value::Integer(3)), vec!["3"]);
+    }
+
+    #[test]
+    fn language_is_inherited_from_parent() {
+        let mut drive = Resource::new("http://example.com".into());
+        drive.set_propval_unsafe(urls::SEARCH_LANGUAGE.into(), "nl".to_string().into());
+        let child = Resource::new("http://example.com/child".into());
+        assert_eq!(
+            resolve_language(&child, &[drive.clone()], Some("en")),
+            Some("nl".to_string())
+        );
+
+        let mut german_child = child.clone();
+        german_child.set_propval_unsafe(urls::SEARCH_LANGUAGE.into(), "de".to_string().into());
+        assert_eq!(
+            resolve_language(&german_child, &[drive], None),
+            Some("de".to_string())
+        );
+        assert_eq!(resolve_language(&child, &[], None), None);
+        assert_eq!(
+            resolve_language(&child, &[], Some("en")),
+            Some("en".to_string())
+        );
+    }
     #[test]
     fn facet_contains_subfacet() {
         let store = atomic_lib::Db::init_temp("facet_contains").unwrap();
@@ -298,4 +908,23 @@ mod tests {
         assert!(query_facet_direct_parent.is_prefix_of(&index_facet));
         assert!(query_facet_root.is_prefix_of(&index_facet));
     }
+
+    #[test]
+    fn read_rights_are_inherited() {
+        let store = atomic_lib::Db::init_temp("read_rights_are_inherited").unwrap();
+        let agent = "http://example.com/agents/reader";
+        let mut parent = Resource::new("http://example.com/parent".into());
+        parent.set_propval_unsafe(urls::READ.into(), vec![agent.to_string()].into());
+        store.add_resource(&parent).unwrap();
+        let mut child = Resource::new("http://example.com/parent/child".into());
+        child
+            .set_propval_string(urls::PARENT.into(), parent.get_subject(), &store)
+            .unwrap();
+        store.add_resource(&child).unwrap();
+
+        let readers = agents_with_read_right(&child, &store).unwrap();
+        assert!(readers.contains(&agent.to_string()));
+        assert!(readers.contains(&child.get_subject().to_string()));
+        assert!(!readers.contains(&urls::PUBLIC_AGENT.to_string()));
+    }
 }
@@ -1,32 +1,33 @@
 use actix_cors::Cors;
-use actix_web::{middleware, web, HttpServer};
+use actix_web::{web, HttpServer};
 use atomic_lib::Storelike;
 
 use crate::errors::AtomicServerResult;
 
+/// Rebuilds the value index and the search index in a background Job.
 fn rebuild_indexes(appstate: &crate::appstate::AppState) -> AtomicServerResult<()> {
     let appstate_clone = appstate.clone();
-
-    actix_web::rt::spawn(async move {
-        tracing::warn!("Building value index... This could take a while, expect worse performance until 'Building value index finished'");
-        appstate_clone
-            .store
-            .clear_index()
-            .expect("Failed to clear value index");
-        appstate_clone
-            .store
-            .build_index(true)
-            .expect("Failed to build value index");
-        tracing::info!("Building value index finished!");
-    });
-    tracing::info!("Removing existing search index...");
-    appstate_clone
-        .search_state
-        .writer
-        .write()
-        .expect("Could not get a lock on search writer")
-        .delete_all_documents()?;
-    crate::search::add_all_resources(&appstate_clone.search_state, &appstate.store)?;
+    let job = appstate.jobs.submit(
+        "rebuild-indexes",
+        &appstate.config.server_url,
+        Box::new(move |context| {
+            tracing::warn!("Building value index... This could take a while, expect worse performance until 'Building value index finished'");
+            appstate_clone.store.clear_index()?;
+            appstate_clone.store.build_index(true)?;
+            tracing::info!("Building value index finished!");
+            context.set_progress(0.5)?;
+            tracing::info!("Removing existing search index...");
+            appstate_clone
+                .search_state
+                .writer
+                .write()
+                .expect("Could not get a lock on search writer")
+                .delete_all_documents()?;
+            crate::search::add_all_resources(&appstate_clone.search_state, &appstate_clone.store)?;
+            Ok("Rebuilt the value index and the search index.".into())
+        }),
+    )?;
+    tracing::info!("Rebuilding indexes in Job {}", job.get_subject());
     Ok(())
 }
 
@@ -40,6 +41,8 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
 
     // Setup the database and more
     let appstate = crate::appstate::init(config.clone())?;
+    #[cfg(feature = "https")]
+    let domains_appstate = appstate.clone();
 
     // Start async processes
     if config.opts.rebuild_indexes {
@@ -54,9 +57,13 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
             .app_data(web::Data::new(appstate.clone()))
             .wrap(cors)
             .wrap(tracing_actix_web::TracingLogger::default())
-            .wrap(middleware::Compress::default())
+            .wrap_fn(crate::compression::compress)
+            .wrap_fn(crate::api_version::negotiate)
+            .wrap_fn(crate::rate_limit::limit_by_ip)
+            .wrap_fn(crate::lockout::guard_authentication)
+            .wrap_fn(crate::audit_log::audit_auth_failures)
             // Here are the actual handlers / endpoints
-            .configure(crate::routes::config_routes)
+            .configure(|app| crate::routes::config_prefixed_routes(app, &appstate))
             .default_service(web::to(|| {
                 tracing::error!("Wrong route, should not happen with normal requests");
                 actix_web::HttpResponse::NotFound()
@@ -80,8 +87,9 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
                         crate::https::request_cert(&config).await?;
                     }
                 }
-                let https_config = crate::https::get_https_config(&config)
+                let (https_config, cert_resolver) = crate::https::get_https_config(&config)
                     .expect("HTTPS TLS Configuration with Let's Encrypt failed.");
+                crate::domains::spawn_certificate_manager(domains_appstate, cert_resolver);
                 let endpoint = format!("{}:{}", config.opts.ip, config.opts.port_https);
                 tracing::info!("Binding HTTPS server to endpoint {}", endpoint);
                 println!("{}", message);
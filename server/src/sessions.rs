@@ -0,0 +1,135 @@
+//! Sessions for Agents whose private keys are held by the server, such as Agents that log in using OIDC or passkeys.
+//! Browsers are authenticated using an `atomic_session` cookie, which is normally signed by the browser itself.
+//! For these Agents, the server signs the cookie instead.
+
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use atomic_lib::{agents::Agent, commit::sign_message, Storelike};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+pub const SESSION_COOKIE: &str = "atomic_session";
+/// Lifetime of the session cookie, in seconds.
+const SESSION_MAX_AGE: i64 = 60 * 60 * 24 * 7;
+
+/// The private keys that the server holds, by Agent subject.
+/// Stored in `agent_keys.json` in the config folder, next to the key of the default Agent.
+#[derive(Debug)]
+pub struct AgentKeys {
+    path: PathBuf,
+    /// Makes sure keys are not lost when two Agents are added at the same time
+    lock: Mutex<()>,
+}
+
+impl AgentKeys {
+    pub fn init_from_config(config: &Config) -> AgentKeys {
+        let mut path = config.config_dir.clone();
+        path.push("agent_keys.json");
+        AgentKeys {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self) -> AtomicServerResult<HashMap<String, String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => Ok(serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid {:?}: {}", self.path, e))?),
+            Err(_not_found) => Ok(HashMap::new()),
+        }
+    }
+
+    /// Returns the Agent, including its private key, if the server holds its key.
+    pub fn get(&self, store: &impl Storelike, subject: &str) -> AtomicServerResult<Option<Agent>> {
+        let _guard = self.lock.lock()?;
+        Ok(self.read()?.get(subject).map(|private_key| {
+            let mut agent = Agent::new_from_private_key(None, store, private_key);
+            agent.subject = subject.to_string();
+            agent
+        }))
+    }
+
+    /// Stores the private key of the Agent.
+    pub fn insert(&self, agent: &Agent) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let mut keys = self.read()?;
+        keys.insert(
+            agent.subject.clone(),
+            agent
+                .private_key
+                .clone()
+                .ok_or("Agent has no private key")?,
+        );
+        let json = serde_json::to_string_pretty(&keys).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, json)
+            .map_err(|e| format!("Could not write {:?}: {}", self.path, e))?;
+        Ok(())
+    }
+}
+
+/// Creates the value of the `atomic_session` cookie, in the same format as the browser client.
+/// The session authenticates requests for every resource on the server.
+pub fn session_cookie_value(agent: &Agent, server_url: &str) -> AtomicServerResult<String> {
+    let timestamp = atomic_lib::utils::now();
+    let message = format!("{} {}", server_url, timestamp);
+    let signature = sign_message(
+        &message,
+        agent
+            .private_key
+            .as_ref()
+            .ok_or("Agent has no private key")?,
+        &agent.public_key,
+    )?;
+    let session = serde_json::json!({
+        "https://atomicdata.dev/properties/auth/agent": agent.subject,
+        "https://atomicdata.dev/properties/auth/requestedSubject": server_url,
+        "https://atomicdata.dev/properties/auth/publicKey": agent.public_key,
+        "https://atomicdata.dev/properties/auth/timestamp": timestamp,
+        "https://atomicdata.dev/properties/auth/signature": signature,
+    });
+    Ok(base64::encode(session.to_string()))
+}
+
+/// The `Set-Cookie` header for the session.
+pub fn session_cookie_header(value: &str, server_url: &str) -> String {
+    let secure = if server_url.starts_with("https") {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!(
+        "{}={}; Path=/; Max-Age={}; SameSite=Lax{}",
+        SESSION_COOKIE,
+        urlencoding::encode(value),
+        SESSION_MAX_AGE,
+        secure
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_cookie_authenticates() {
+        let store = atomic_lib::Db::init_temp("server_signed_session_cookie").unwrap();
+        let agent = Agent::new(Some("sso user"), &store).unwrap();
+        store.add_resource(&agent.to_resource().unwrap()).unwrap();
+        let server_url = store.get_server_url().to_string();
+
+        let value = session_cookie_value(&agent, &server_url).unwrap();
+        let cookie = session_cookie_header(&value, &server_url);
+        let mut headers = actix_web::http::header::HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::COOKIE,
+            cookie.split(';').next().unwrap().parse().unwrap(),
+        );
+        let auth = crate::helpers::get_auth_from_cookie(&headers, &format!("{}/drive", server_url))
+            .unwrap()
+            .unwrap();
+        let found =
+            atomic_lib::authentication::get_agent_from_auth_values_and_check(Some(auth), &store)
+                .unwrap();
+        assert_eq!(found, agent.subject);
+    }
+}
@@ -0,0 +1,205 @@
+//! Share links let users share a private resource with someone who has no Agent.
+//! A share link is a capability URL: `{subject}?share={token}`.
+//! The token contains the subject, the rights and the expiry date, and is signed by the default Agent of the server.
+//! Requests that use the token read the resource as the Agent that created the link,
+//! so the link stops working when that Agent loses its rights.
+//! Share links are only honored for GET requests.
+
+use atomic_lib::{commit::sign_message, AtomicError, Storelike};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{errors::AtomicServerResult, helpers::in_subtree};
+
+/// Name of the query parameter that holds the token.
+pub const SHARE_PARAM: &str = "share";
+/// Used when no expiry date is given, in milliseconds.
+pub const DEFAULT_TTL: i64 = 1000 * 60 * 60 * 24 * 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ShareRights {
+    /// Only the shared resource can be read
+    Read,
+    /// The shared resource and its descendants can be read
+    ReadTree,
+}
+
+/// The contents of a share token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareClaims {
+    pub subject: String,
+    /// The Agent that created the link, and whose rights are used
+    pub agent: String,
+    pub rights: ShareRights,
+    /// Milliseconds since unix epoch
+    pub expires_at: i64,
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(string: &str) -> AtomicServerResult<Vec<u8>> {
+    Ok(base64::decode_config(string, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?)
+}
+
+fn invalid() -> AtomicError {
+    AtomicError::unauthorized("Invalid share link".into())
+}
+
+/// Signs the claims, and returns the token.
+pub fn create_token(store: &impl Storelike, claims: &ShareClaims) -> AtomicServerResult<String> {
+    sign_claims(store, claims)
+}
+
+/// Serializes the claims and signs them with the default Agent, as `{payload}.{signature}`.
+/// Also used for [crate::read_tokens].
+pub fn sign_claims(store: &impl Storelike, claims: &impl Serialize) -> AtomicServerResult<String> {
+    let signer = store.get_default_agent()?;
+    let payload = encode(
+        serde_json::to_string(claims)
+            .map_err(|e| e.to_string())?
+            .as_bytes(),
+    );
+    let signature = sign_message(
+        &payload,
+        signer
+            .private_key
+            .as_ref()
+            .ok_or("The default Agent has no private key")?,
+        &signer.public_key,
+    )?;
+    let signature = base64::decode(signature).map_err(|e| e.to_string())?;
+    Ok(format!("{}.{}", payload, encode(&signature)))
+}
+
+/// Returns the link that can be shared.
+pub fn create_link(store: &impl Storelike, claims: &ShareClaims) -> AtomicServerResult<String> {
+    let separator = if claims.subject.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    Ok(format!(
+        "{}{}{}={}",
+        claims.subject,
+        separator,
+        SHARE_PARAM,
+        create_token(store, claims)?
+    ))
+}
+
+/// Checks the signature, expiry and rights of the token, and returns the Agent whose rights should be used.
+pub fn verify_token(
+    store: &impl Storelike,
+    token: &str,
+    requested_subject: &str,
+) -> AtomicServerResult<String> {
+    let claims: ShareClaims = open_claims(store, token)?.ok_or_else(invalid)?;
+
+    if claims.expires_at < atomic_lib::utils::now() {
+        return Err(AtomicError::unauthorized("This share link has expired".into()).into());
+    }
+    let allowed = match claims.rights {
+        ShareRights::Read => claims.subject == requested_subject,
+        ShareRights::ReadTree => in_subtree(store, &claims.subject, requested_subject),
+    };
+    if !allowed {
+        return Err(AtomicError::unauthorized(format!(
+            "This share link is not valid for {}",
+            requested_subject
+        ))
+        .into());
+    }
+    if atomic_lib::agents::is_disabled(store, &claims.agent) {
+        return Err(
+            AtomicError::unauthorized(format!("Agent {} has been disabled", claims.agent)).into(),
+        );
+    }
+    Ok(claims.agent)
+}
+
+/// Returns the claims if the token was signed by the default Agent, or `None` if it's invalid.
+pub fn open_claims<T: DeserializeOwned>(
+    store: &impl Storelike,
+    token: &str,
+) -> AtomicServerResult<Option<T>> {
+    let Some((payload, signature)) = token.split_once('.') else {
+        return Ok(None);
+    };
+    let (Ok(payload_bytes), Ok(signature)) = (decode(payload), decode(signature)) else {
+        return Ok(None);
+    };
+    let signer = store.get_default_agent()?;
+    let public_key = base64::decode(&signer.public_key).map_err(|e| e.to_string())?;
+    if ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key)
+        .verify(payload.as_bytes(), &signature)
+        .is_err()
+    {
+        return Ok(None);
+    }
+    Ok(serde_json::from_slice(&payload_bytes).ok())
+}
+
+/// Removes the share token from the query string, and returns it.
+pub fn take_token(query_string: &str) -> (String, Option<String>) {
+    take_param(query_string, SHARE_PARAM)
+}
+
+/// Removes a query parameter from the query string, and returns its decoded value.
+pub fn take_param(query_string: &str, param: &str) -> (String, Option<String>) {
+    let mut token = None;
+    let rest: Vec<&str> = query_string
+        .split('&')
+        .filter(|pair| match pair.split_once('=') {
+            Some((name, value)) if name == param => {
+                token = urlencoding::decode(value).ok().map(|v| v.into_owned());
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    (rest.join("&"), token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_share_links() {
+        let store = atomic_lib::Db::init_temp("share_links").unwrap();
+        let agent = store.get_default_agent().unwrap();
+        let subject = format!("{}/private", store.get_server_url());
+        let claims = ShareClaims {
+            subject: subject.clone(),
+            agent: agent.subject.clone(),
+            rights: ShareRights::Read,
+            expires_at: atomic_lib::utils::now() + DEFAULT_TTL,
+        };
+        let link = create_link(&store, &claims).unwrap();
+        let (path, query) = link.split_once('?').unwrap();
+        assert_eq!(path, subject);
+        let (rest, token) = take_token(query);
+        assert!(rest.is_empty());
+        let token = token.unwrap();
+
+        assert_eq!(
+            verify_token(&store, &token, &subject).unwrap(),
+            agent.subject
+        );
+        verify_token(&store, &token, &format!("{}/child", subject)).unwrap_err();
+        verify_token(&store, &format!("{}x", token), &subject).unwrap_err();
+
+        let expired = create_token(
+            &store,
+            &ShareClaims {
+                expires_at: atomic_lib::utils::now() - 1,
+                ..claims
+            },
+        )
+        .unwrap();
+        verify_token(&store, &expired, &subject).unwrap_err();
+    }
+}
@@ -0,0 +1,411 @@
+//! Exposes resources using the conventions of [Solid](https://solidproject.org/TR/protocol), so Solid apps can read and write them.
+//! Resources are available at `/solid/{path}`, which maps to the subject `{server_url}/{path}`.
+//!
+//! - Resources with children are LDP Basic Containers, which list their children using `ldp:contains`.
+//!   Their URLs end with a slash.
+//! - Resources are represented as Turtle. Writes (`PUT`, and `POST` to a container) accept Turtle too,
+//!   but only using Atomic Properties as predicates.
+//! - Atomic `read`, `write` and `append` rights are shown as a read-only WAC document at `{url}.acl`,
+//!   and summarized in the `WAC-Allow` header.
+//!
+//! Solid-OIDC is not supported. Apps authenticate like other Atomic Data clients, for example using an API token.
+
+use std::collections::HashMap;
+
+use atomic_lib::{
+    datatype::DataType,
+    hierarchy::{self, Right},
+    storelike::Query,
+    urls,
+    values::SubResource,
+    Resource, Storelike, Value,
+};
+use rio_api::{
+    formatter::TriplesFormatter,
+    model::{Literal, NamedNode, Subject, Term, Triple},
+    parser::TriplesParser,
+};
+use rio_turtle::{TurtleFormatter, TurtleParser};
+
+use crate::errors::AtomicServerResult;
+
+pub const PREFIX: &str = "/solid";
+pub const MIME_TURTLE: &str = "text/turtle";
+const LDP_CONTAINER: &str = "http://www.w3.org/ns/ldp#Container";
+const LDP_BASIC_CONTAINER: &str = "http://www.w3.org/ns/ldp#BasicContainer";
+const LDP_RESOURCE: &str = "http://www.w3.org/ns/ldp#Resource";
+const LDP_CONTAINS: &str = "http://www.w3.org/ns/ldp#contains";
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const ACL: &str = "http://www.w3.org/ns/auth/acl#";
+const FOAF_AGENT: &str = "http://xmlns.com/foaf/0.1/Agent";
+/// Properties that are kept when a resource is replaced using `PUT`, since Solid apps don't know about them.
+const KEPT_ON_PUT: [&str; 12] = [
+    urls::PARENT,
+    urls::READ,
+    urls::WRITE,
+    urls::APPEND,
+    urls::COMMENT_RIGHT,
+    urls::INVITE_RIGHT,
+    urls::DESTROY_RIGHT,
+    urls::DENY_READ,
+    urls::DENY_WRITE,
+    urls::PUBLIC_READ,
+    urls::LOCKED_PROPS,
+    urls::LAST_COMMIT,
+];
+
+/// A request for `/solid/{path}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolidPath {
+    /// The Atomic subject of the resource
+    pub subject: String,
+    /// Whether the WAC document of the resource is requested
+    pub acl: bool,
+}
+
+pub fn parse_path(server_url: &str, path: &str) -> SolidPath {
+    let (path, acl) = match path.strip_suffix(".acl") {
+        Some(stripped) => (stripped, true),
+        None => (path, false),
+    };
+    let path = path.trim_matches('/');
+    let subject = if path.is_empty() {
+        server_url.to_string()
+    } else {
+        format!("{}/{}", server_url, path)
+    };
+    SolidPath { subject, acl }
+}
+
+/// The Solid URL of a local subject. Containers end with a slash.
+pub fn solid_url(server_url: &str, subject: &str, container: bool) -> String {
+    let path = subject
+        .strip_prefix(server_url)
+        .unwrap_or_default()
+        .trim_matches('/');
+    let mut url = format!("{}{}/{}", server_url, PREFIX, path);
+    if container && !url.ends_with('/') {
+        url.push('/');
+    }
+    url
+}
+
+/// Maps Solid URLs in request bodies back to Atomic subjects. Other URLs are kept.
+fn atomic_subject(server_url: &str, url: &str) -> String {
+    match url.strip_prefix(&format!("{}{}", server_url, PREFIX)) {
+        Some(path) if path.is_empty() || path.starts_with('/') => {
+            parse_path(server_url, path).subject
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// The children that the Agent can read.
+pub fn children(
+    store: &impl Storelike,
+    subject: &str,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<Vec<String>> {
+    let mut query = Query::new_prop_val(urls::PARENT, subject);
+    query.for_agent = for_agent.map(str::to_string);
+    Ok(store.query(&query)?.subjects)
+}
+
+/// Drives are always containers, other resources when they have children.
+pub fn is_container(resource: &Resource, children: &[String]) -> bool {
+    !children.is_empty()
+        || resource
+            .get(urls::IS_A)
+            .and_then(|v| v.to_subjects(None))
+            .is_ok_and(|classes| classes.iter().any(|c| c == urls::DRIVE))
+}
+
+/// The `Link` header, which tells clients what kind of resource this is and where its ACL is.
+pub fn link_header(url: &str, container: bool) -> String {
+    let mut links = vec![format!("<{}>; rel=\"type\"", LDP_RESOURCE)];
+    if container {
+        links.push(format!("<{}>; rel=\"type\"", LDP_BASIC_CONTAINER));
+    }
+    links.push(format!("<{}.acl>; rel=\"acl\"", url));
+    links.join(", ")
+}
+
+/// Serializes the resource as Turtle, using its Solid URL as subject.
+pub fn to_turtle(
+    store: &impl Storelike,
+    resource: &Resource,
+    url: &str,
+    children: &[String],
+) -> AtomicServerResult<String> {
+    let server_url = store.get_server_url();
+    let subject: Subject = NamedNode { iri: url }.into();
+    let mut formatter = TurtleFormatter::new(Vec::default());
+    let mut format = |predicate: &str, object: Term| {
+        formatter.format(&Triple {
+            subject,
+            predicate: NamedNode { iri: predicate },
+            object,
+        })
+    };
+    for (property, value) in resource.get_propvals() {
+        match value {
+            Value::AtomicUrl(iri) => format(property, NamedNode { iri }.into())?,
+            Value::ResourceArray(items) => {
+                for item in items {
+                    if let SubResource::Subject(iri) = item {
+                        format(property, NamedNode { iri }.into())?
+                    }
+                }
+            }
+            Value::String(value) => format(property, Literal::Simple { value }.into())?,
+            other => {
+                let value = other.to_string();
+                let datatype = other.datatype().to_string();
+                format(
+                    property,
+                    Literal::Typed {
+                        value: &value,
+                        datatype: NamedNode { iri: &datatype },
+                    }
+                    .into(),
+                )?
+            }
+        }
+    }
+    if is_container(resource, children) {
+        format(RDF_TYPE, NamedNode { iri: LDP_CONTAINER }.into())?;
+        format(
+            RDF_TYPE,
+            NamedNode {
+                iri: LDP_BASIC_CONTAINER,
+            }
+            .into(),
+        )?;
+        for child in children {
+            let iri = solid_url(server_url, child, false);
+            format(LDP_CONTAINS, NamedNode { iri: &iri }.into())?;
+        }
+    }
+    Ok(String::from_utf8(formatter.finish()?).map_err(|e| e.to_string())?)
+}
+
+/// The Agents with a right, as WAC `acl:agent` and `acl:agentClass` objects.
+fn wac_agents(
+    store: &impl Storelike,
+    resource: &Resource,
+    right: Right,
+) -> AtomicServerResult<String> {
+    let agents = hierarchy::agents_with_right(store, resource, right)?;
+    Ok(agents
+        .iter()
+        .map(|agent| {
+            if agent == urls::PUBLIC_AGENT {
+                format!("acl:agentClass <{}>", FOAF_AGENT)
+            } else {
+                format!("acl:agent <{}>", agent)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(";\n    "))
+}
+
+/// Describes the rights of the resource as a WAC document.
+/// Atomic `write` allows editing the rights, so it maps to `acl:Control` too.
+pub fn acl_turtle(
+    store: &impl Storelike,
+    resource: &Resource,
+    url: &str,
+) -> AtomicServerResult<String> {
+    let mut out = format!("@prefix acl: <{}> .\n", ACL);
+    let rights = [
+        (Right::Read, "read", "acl:Read"),
+        (Right::Write, "write", "acl:Read, acl:Write, acl:Control"),
+        (Right::Append, "append", "acl:Append"),
+    ];
+    for (right, name, modes) in rights {
+        let agents = wac_agents(store, resource, right)?;
+        if agents.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "\n<#{}> a acl:Authorization;\n    acl:accessTo <{}>;\n    acl:default <{}>;\n    acl:mode {};\n    {} .\n",
+            name, url, url, modes, agents
+        ));
+    }
+    Ok(out)
+}
+
+/// The modes that the Agent has, in the format of the `WAC-Allow` header.
+fn allowed_modes(store: &impl Storelike, resource: &Resource, agent: &str) -> String {
+    let mut modes = Vec::new();
+    if hierarchy::check_read(store, resource, agent).is_ok() {
+        modes.push("read");
+    }
+    if hierarchy::check_write(store, resource, agent).is_ok() {
+        modes.extend(["write", "append", "control"]);
+    } else if hierarchy::check_rights(store, resource, agent, Right::Append).is_ok() {
+        modes.push("append");
+    }
+    modes.join(" ")
+}
+
+/// The `WAC-Allow` header, which tells clients what they are allowed to do.
+pub fn wac_allow_header(
+    store: &impl Storelike,
+    resource: &Resource,
+    agent: Option<&str>,
+) -> String {
+    let public = allowed_modes(store, resource, urls::PUBLIC_AGENT);
+    let user = match agent {
+        Some(agent) if agent != urls::PUBLIC_AGENT => allowed_modes(store, resource, agent),
+        _ => public.clone(),
+    };
+    format!("user=\"{}\", public=\"{}\"", user, public)
+}
+
+/// The object of a parsed triple.
+enum Object {
+    Iri(String),
+    Literal(String),
+}
+
+/// Parses a Turtle body into the values of the resource at `url`.
+/// `rdf:type` is mapped to `isA`. LDP types are ignored, since they follow from the hierarchy.
+pub fn parse_turtle(
+    store: &impl Storelike,
+    body: &[u8],
+    url: &str,
+) -> AtomicServerResult<HashMap<String, Value>> {
+    let server_url = store.get_server_url();
+    let base = oxiri::Iri::parse(url.to_string()).map_err(|e| format!("Invalid URL: {}", e))?;
+    let mut triples: Vec<(String, Object)> = Vec::new();
+    let mut other_subject = None;
+    TurtleParser::new(body, Some(base))
+        .parse_all(&mut |triple| -> Result<(), rio_turtle::TurtleError> {
+            let subject = match triple.subject {
+                Subject::NamedNode(node) => node.iri.to_string(),
+                _ => String::new(),
+            };
+            if subject.trim_end_matches('/') != url.trim_end_matches('/') {
+                other_subject = Some(subject);
+                return Ok(());
+            }
+            let object = match triple.object {
+                Term::NamedNode(node) => Object::Iri(node.iri.to_string()),
+                Term::Literal(Literal::Simple { value })
+                | Term::Literal(Literal::LanguageTaggedString { value, .. })
+                | Term::Literal(Literal::Typed { value, .. }) => Object::Literal(value.to_string()),
+                _ => return Ok(()),
+            };
+            triples.push((triple.predicate.iri.to_string(), object));
+            Ok(())
+        })
+        .map_err(|e| format!("Invalid Turtle: {}", e))?;
+    if let Some(subject) = other_subject {
+        return Err(format!(
+            "Only statements about {} are supported, found one about {:?}",
+            url, subject
+        )
+        .into());
+    }
+
+    let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+    for (predicate, object) in triples {
+        let (predicate, value) = match object {
+            Object::Iri(iri)
+                if predicate == RDF_TYPE && iri.starts_with("http://www.w3.org/ns/ldp#") =>
+            {
+                continue
+            }
+            Object::Iri(iri) => (predicate, atomic_subject(server_url, &iri)),
+            Object::Literal(value) => (predicate, value),
+        };
+        let predicate = if predicate == RDF_TYPE {
+            urls::IS_A.to_string()
+        } else {
+            predicate
+        };
+        grouped.entry(predicate).or_default().push(value);
+    }
+
+    let mut propvals = HashMap::new();
+    for (predicate, values) in grouped {
+        let property = store.get_property(&predicate).map_err(|_e| {
+            format!(
+                "Predicate {} is not an Atomic Property. Solid apps can only use Atomic Properties.",
+                predicate
+            )
+        })?;
+        let value = match (&property.data_type, values.as_slice()) {
+            (DataType::ResourceArray, _) => Value::from(values),
+            (datatype, [single]) => Value::new(single, datatype)?,
+            _ => return Err(format!("Property {} can only have one value", predicate).into()),
+        };
+        propvals.insert(predicate, value);
+    }
+    Ok(propvals)
+}
+
+/// Replaces the values of the resource, except for the ones in [KEPT_ON_PUT].
+pub fn replace_values(resource: &mut Resource, propvals: HashMap<String, Value>) {
+    let removed: Vec<String> = resource
+        .get_propvals()
+        .keys()
+        .filter(|p| !KEPT_ON_PUT.contains(&p.as_str()) && !propvals.contains_key(*p))
+        .cloned()
+        .collect();
+    for property in removed {
+        resource.remove_propval(&property);
+    }
+    for (property, value) in propvals {
+        resource.set_propval_unsafe(property, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_turtle() {
+        let store = atomic_lib::Db::init_temp("solid").unwrap();
+        store.populate().unwrap();
+        let server_url = store.get_server_url().to_string();
+
+        let path = parse_path(&server_url, "notes/first/");
+        assert_eq!(path.subject, format!("{}/notes/first", server_url));
+        assert!(parse_path(&server_url, "notes/.acl").acl);
+        let url = solid_url(&server_url, &path.subject, false);
+        assert_eq!(url, format!("{}/solid/notes/first", server_url));
+        assert_eq!(atomic_subject(&server_url, &url), path.subject);
+
+        let body = format!(
+            "<> <{}> \"First note\" ; a <{}> ; <{}> <{}/solid/notes/> .",
+            urls::NAME,
+            LDP_RESOURCE,
+            urls::PARENT,
+            server_url
+        );
+        let propvals = parse_turtle(&store, body.as_bytes(), &url).unwrap();
+        assert_eq!(propvals.len(), 2);
+        assert_eq!(
+            propvals.get(urls::PARENT).unwrap().to_string(),
+            format!("{}/notes", server_url)
+        );
+
+        let mut resource = Resource::new(path.subject.clone());
+        replace_values(&mut resource, propvals);
+        let turtle = to_turtle(&store, &resource, &url, &[]).unwrap();
+        assert!(turtle.contains("First note"));
+        assert!(!turtle.contains(LDP_CONTAINS));
+
+        let unknown = "<> <https://example.com/unknown> \"x\" .";
+        parse_turtle(&store, unknown.as_bytes(), &url).unwrap_err();
+        let other = "<https://example.com/other> <https://atomicdata.dev/properties/name> \"x\" .";
+        parse_turtle(&store, other.as_bytes(), &url).unwrap_err();
+
+        let drive = store.get_resource(&server_url).unwrap();
+        let acl = acl_turtle(&store, &drive, &solid_url(&server_url, &server_url, true)).unwrap();
+        assert!(acl.contains(FOAF_AGENT));
+    }
+}
@@ -16,12 +16,18 @@ use atomic_lib::{urls, Storelike};
 
 /// Returns the request with signed headers. Also adds a json-ad accept header - overwrite this if you need something else.
 fn build_request_authenticated(path: &str, appstate: &AppState) -> TestRequest {
+    build_request_signed(path, &appstate.store.get_default_agent().unwrap(), appstate)
+}
+
+/// Same as [build_request_authenticated], but signed by another Agent.
+fn build_request_signed(
+    path: &str,
+    agent: &atomic_lib::agents::Agent,
+    appstate: &AppState,
+) -> TestRequest {
     let url = format!("{}{}", appstate.store.get_server_url(), path);
-    let headers = atomic_lib::client::get_authentication_headers(
-        &url,
-        &appstate.store.get_default_agent().unwrap(),
-    )
-    .expect("could not get auth headers");
+    let headers = atomic_lib::client::get_authentication_headers(&url, agent)
+        .expect("could not get auth headers");
 
     let mut prereq = test::TestRequest::with_uri(path);
     for (k, v) in headers {
@@ -30,18 +36,24 @@ fn build_request_authenticated(path: &str, appstate: &AppState) -> TestRequest {
     prereq.insert_header(("Accept", "application/ad+json"))
 }
 
-#[actix_rt::test]
-async fn server_tests() {
+/// Initializes a server in a unique `.temp` folder, with extra command line arguments.
+fn init_appstate(args: &[&str]) -> AppState {
     let unique_string = atomic_lib::utils::random_string(10);
     use clap::Parser;
-    let opts = Opts::parse_from([
-        "atomic-server",
-        "--initialize",
-        "--data-dir",
-        &format!("./.temp/{}/db", unique_string),
-        "--config-dir",
-        &format!("./.temp/{}/config", unique_string),
-    ]);
+    let data_dir = format!("./.temp/{}/db", unique_string);
+    let config_dir = format!("./.temp/{}/config", unique_string);
+    let opts = Opts::parse_from(
+        [
+            "atomic-server",
+            "--initialize",
+            "--data-dir",
+            &data_dir,
+            "--config-dir",
+            &config_dir,
+        ]
+        .iter()
+        .chain(args),
+    );
 
     let mut config = config::build_config(opts)
         .map_err(|e| format!("Initialization failed: {}", e))
@@ -49,12 +61,21 @@ async fn server_tests() {
     // This prevents folder access issues when running concurrent tests
     config.search_index_path = format!("./.temp/{}/search_index", unique_string).into();
 
-    let appstate = crate::appstate::init(config.clone()).expect("failed init appstate");
+    crate::appstate::init(config).expect("failed init appstate")
+}
+
+#[actix_rt::test]
+async fn server_tests() {
+    let appstate = init_appstate(&["--rate-limit-search", "3", "--auth-lockout-threshold", "2"]);
     let data = Data::new(appstate.clone());
     let app = test::init_service(
         App::new()
             .app_data(data)
-            .configure(crate::routes::config_routes),
+            .wrap_fn(crate::api_version::negotiate)
+            .wrap_fn(crate::rate_limit::limit_by_ip)
+            .wrap_fn(crate::lockout::guard_authentication)
+            .wrap_fn(crate::audit_log::audit_auth_failures)
+            .configure(|app| crate::routes::config_routes(app, &appstate)),
     )
     .await;
     let store = &appstate.store;
@@ -69,11 +90,24 @@ async fn server_tests() {
         build_request_authenticated("/", &appstate).insert_header(("Accept", "application/html"));
     let resp = test::call_service(&app, req.to_request()).await;
     let is_success = resp.status().is_success();
+    // Clients without a version header get the oldest supported API version
+    assert_eq!(
+        resp.headers().get("x-atomic-api-version").unwrap(),
+        &crate::api_version::OLDEST_SUPPORTED.to_string()
+    );
     let body = get_body(resp);
     println!("{:?}", body);
     assert!(is_success);
     assert!(body.as_str().contains("html"));
 
+    // Unsupported API versions are rejected
+    let req = test::TestRequest::with_uri("/properties")
+        .insert_header(("Accept", "application/ad+json"))
+        .insert_header(("X-Atomic-Api-Version", "999"));
+    actix_web::dev::Service::call(&app, req.to_request())
+        .await
+        .unwrap_err();
+
     // Should 200 (public)
     let req =
         test::TestRequest::with_uri("/properties").insert_header(("Accept", "application/ad+json"));
@@ -87,6 +121,40 @@ async fn server_tests() {
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_client_error());
 
+    // Bulk Commits are applied one by one, and failures don't undo the others
+    let subject = format!("{}/bulk", store.get_server_url());
+    let mut builder = atomic_lib::commit::CommitBuilder::new(subject.clone());
+    builder.set(urls::NAME.into(), atomic_lib::Value::String("bulk".into()));
+    builder.set(
+        urls::PARENT.into(),
+        atomic_lib::Value::AtomicUrl(store.get_server_url().into()),
+    );
+    let commit = builder
+        .sign(
+            &store.get_default_agent().unwrap(),
+            store,
+            &atomic_lib::Resource::new(subject.clone()),
+        )
+        .unwrap();
+    let body = format!(
+        "[{}, {{}}]",
+        commit.into_resource(store).unwrap().to_json_ad().unwrap()
+    );
+    let req = build_request_authenticated("/commits/bulk", &appstate)
+        .method(actix_web::http::Method::POST)
+        .set_payload(body.clone());
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 207);
+    let results: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(results[0]["status"], "applied");
+    assert_eq!(results[1]["status"], "failed");
+    store.get_resource(&subject).unwrap();
+    let req = build_request_authenticated("/commits/bulk?transactional=true", &appstate)
+        .method(actix_web::http::Method::POST)
+        .set_payload(body);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(!resp.status().is_success());
+
     // Edit the properties collection, make it hidden to the public agent
     let mut drive = store.get_resource(&appstate.config.server_url).unwrap();
     drive
@@ -151,6 +219,246 @@ async fn server_tests() {
         body.as_str().contains("/results"),
         "response should be a search resource"
     );
+    assert!(body.as_str().contains(urls::SEARCH_CLASS_FACETS));
+    let req = build_request_authenticated(
+        &format!("/search?q=setup&property={}", urls::NAME),
+        &appstate,
+    );
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(
+        !resp.status().is_success(),
+        "value is required with property"
+    );
+
+    // Too many searches from the same IP are rate limited
+    let search_from_ip = || {
+        test::TestRequest::with_uri("/search?q=setup")
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .to_request()
+    };
+    for _ in 0..3 {
+        let resp = test::call_service(&app, search_from_ip()).await;
+        assert!(resp.status().is_success());
+    }
+    let err = actix_web::dev::Service::call(&app, search_from_ip())
+        .await
+        .unwrap_err();
+    let resp = err.error_response();
+    assert_eq!(resp.status().as_u16(), 429);
+    assert!(resp.headers().contains_key("Retry-After"));
+
+    // The audit log is only available to admins, and the failed attempt is recorded
+    let req =
+        test::TestRequest::with_uri("/auditlog").insert_header(("Accept", "application/ad+json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let failures = appstate
+        .audit_log
+        .read(&crate::audit_log::AuditFilter::default(), 10)
+        .unwrap();
+    assert!(failures.iter().any(|e| e
+        .subject
+        .as_deref()
+        .is_some_and(|s| s.ends_with("/auditlog"))));
+
+    // OIDC login is only available when a provider is configured
+    let req = test::TestRequest::with_uri("/auth/oidc/login")
+        .insert_header(("Accept", "application/ad+json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 404);
+
+    // ActivityPub is only available when a key is configured
+    let req = test::TestRequest::with_uri("/.well-known/webfinger?resource=acct:news@localhost")
+        .insert_header(("Accept", "application/jrd+json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 404);
+
+    // The Drive is a Solid container
+    let req =
+        build_request_authenticated("/solid/", &appstate).insert_header(("Accept", "text/turtle"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let link = resp.headers().get("Link").unwrap().to_str().unwrap();
+    assert!(link.contains("http://www.w3.org/ns/ldp#BasicContainer"));
+
+    // Collections can be subscribed to as feeds, even by browsers.
+    // The signature is for the subject, without the extension.
+    let req = build_request_authenticated("/collections", &appstate)
+        .uri("/collections.rss")
+        .insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let content_type = resp
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(content_type.contains("application/rss+xml"));
+
+    // ... and as calendars
+    let req = build_request_authenticated("/collections", &appstate)
+        .uri("/collections.ics")
+        .insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let content_type = resp
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(content_type.contains("text/calendar"));
+
+    // Crawlers get a server rendered page instead of the single page app
+    let req = build_request_authenticated("/collections", &appstate)
+        .insert_header(("Accept", "text/html"))
+        .insert_header(("User-Agent", "Googlebot/2.1"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body = get_body(resp);
+    assert!(body.contains("<meta property=\"og:url\""));
+    assert!(body.contains("<ul>"));
+
+    // Jobs are listed as a Collection
+    let req = build_request_authenticated("/jobs", &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert!(get_body(resp).contains(urls::COLLECTION_MEMBERS));
+
+    // Unknown API tokens are rejected, instead of falling back to the public agent
+    let req = test::TestRequest::with_uri("/search?q=test")
+        .insert_header(("Accept", "application/ad+json"))
+        .insert_header(("Authorization", "Bearer atm_unknown_secret"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    // Share links must be signed by the server
+    let req = test::TestRequest::with_uri("/setup?share=forged.token")
+        .insert_header(("Accept", "application/ad+json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    // Read tokens only work for reading resources, so they can't be used to mint API tokens
+    let read_token = crate::read_tokens::create_token(
+        store,
+        &crate::read_tokens::ReadClaims {
+            parent: store.get_server_url().to_string(),
+            agent: store.get_default_agent().unwrap().subject,
+            expires_at: atomic_lib::utils::now() + crate::read_tokens::DEFAULT_TTL,
+        },
+    )
+    .unwrap();
+    let req = test::TestRequest::with_uri("/setup")
+        .insert_header(("Accept", "application/ad+json"))
+        .insert_header((crate::read_tokens::READ_TOKEN_HEADER, read_token.as_str()));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let req = test::TestRequest::post()
+        .uri("/tokens")
+        .insert_header(("Accept", "application/json"))
+        .insert_header((crate::read_tokens::READ_TOKEN_HEADER, read_token.as_str()))
+        .set_json(serde_json::json!({}));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    // Forged signatures lock out the IP address, and the Agent on that IP address
+    let forged = || {
+        build_request_authenticated("/setup", &appstate)
+            .insert_header(("x-atomic-signature", "Zm9yZ2Vk"))
+            .peer_addr("10.0.0.2:4000".parse().unwrap())
+            .to_request()
+    };
+    for _ in 0..2 {
+        let resp = test::call_service(&app, forged()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+    let err = actix_web::dev::Service::call(&app, forged())
+        .await
+        .unwrap_err();
+    let resp = err.error_response();
+    assert_eq!(resp.status().as_u16(), 429);
+    assert!(resp.headers().contains_key("Retry-After"));
+    let filter = crate::audit_log::AuditFilter {
+        event: Some(crate::audit_log::AuditEvent::AuthLockout),
+        ..Default::default()
+    };
+    assert!(!appstate.audit_log.read(&filter, 10).unwrap().is_empty());
+    // The Agent header is not authenticated, so the forged requests don't lock out the Agent on other IP addresses
+    let req = build_request_authenticated("/setup", &appstate)
+        .peer_addr("10.0.0.3:4000".parse().unwrap());
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn personal_data_includes_uploads() {
+    let appstate = init_appstate(&[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(|app| crate::routes::config_routes(app, &appstate)),
+    )
+    .await;
+    let store = &appstate.store;
+    let server_agent = store.get_default_agent().unwrap();
+    let alice = atomic_lib::agents::Agent::new(Some("Alice"), store).unwrap();
+    alice.to_resource().unwrap().save_locally(store).unwrap();
+
+    // Alice uploads a file that someone else uploaded before, which shares its blob, and a file of her own
+    let uploads = [
+        (&server_agent, store.get_server_url().to_string(), "shared"),
+        (&alice, alice.subject.clone(), "shared"),
+        (&alice, alice.subject.clone(), "own"),
+    ];
+    let mut internal_ids = Vec::new();
+    for (i, (agent, parent, contents)) in uploads.iter().enumerate() {
+        let path = format!("/upload?parent={}", urlencoding::encode(parent));
+        let body = format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}-{}.txt\"\r\n\r\n{}\r\n--boundary--\r\n",
+            contents, i, contents
+        );
+        let req = build_request_signed(&path, agent, &appstate)
+            .method(actix_web::http::Method::POST)
+            .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+            .set_payload(body);
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        let created: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+        internal_ids.push(created[0][urls::INTERNAL_ID].as_str().unwrap().to_string());
+    }
+    assert_eq!(internal_ids[0], internal_ids[1]);
+    let blob = |id: &str| appstate.config.uploads_path.join(id);
+    assert!(blob(&internal_ids[2]).exists());
+
+    // The uploads are attributed to Alice, so they are exported
+    let path = format!(
+        "/personal-data?agent={}",
+        urlencoding::encode(&alice.subject)
+    );
+    let req = build_request_signed(&path, &alice, &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let archive = resp.into_body().try_into_bytes().unwrap();
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(archive.to_vec())).unwrap();
+    for id in &internal_ids[1..] {
+        assert!(archive
+            .file_names()
+            .any(|name| name == format!("files/{}", id)));
+    }
+
+    // Erasing removes her own blob, but not the one that is shared
+    let path = format!(
+        "/personal-data/erase?agent={}",
+        urlencoding::encode(&alice.subject)
+    );
+    let req = build_request_signed(&path, &alice, &appstate).method(actix_web::http::Method::POST);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let erasure: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(erasure["removedFiles"], 1);
+    assert!(blob(&internal_ids[0]).exists());
+    assert!(!blob(&internal_ids[2]).exists());
 }
 
 /// Gets the body from the response as a String. Why doen't actix provide this?
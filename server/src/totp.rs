@@ -0,0 +1,444 @@
+//! Time-based one-time passwords (TOTP, RFC 6238) as a second factor for signing in.
+//! An Agent enrolls at `/auth/totp/enroll`, adds the secret to an authenticator app, and enables it by sending a code to `/auth/totp/confirm`.
+//! From then on, a code is needed to:
+//!
+//! - Create a session using a passkey or OIDC. Instead of a session cookie, these return a `totpTicket`, which is exchanged for the cookie at `/auth/totp/verify`.
+//! - Mint API tokens at `/tokens`, using the `totp` field.
+//!
+//! Every enrollment comes with recovery codes, which can be used once instead of a code.
+//! Secrets are stored encrypted in `totp.json` in the config folder, with a key derived from the private key of the default Agent.
+//! Only hashes of the recovery codes are stored.
+//! Agents that hold their own private key can still sign requests and Commits without a code.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{AtomicError, Storelike};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    errors::{AtomicServerError, AtomicServerResult},
+};
+
+const DIGITS: u32 = 6;
+const STEP_SECONDS: u64 = 30;
+/// Codes of the previous and next step are accepted too, to allow for clock drift.
+const ALLOWED_DRIFT: u64 = 1;
+/// Length of the secret in bytes, as recommended for HMAC-SHA1.
+const SECRET_LEN: usize = 20;
+const RECOVERY_CODES: usize = 10;
+/// How long a ticket can be exchanged for a session.
+const TICKET_TTL: Duration = Duration::from_secs(5 * 60);
+/// Tickets are removed after this many wrong codes, so codes can't be guessed.
+const MAX_TICKET_ATTEMPTS: u32 = 5;
+
+/// The TOTP settings of an Agent, as they are stored on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Enrollment {
+    /// Nonce and encrypted secret, base64 encoded
+    secret: String,
+    /// Codes are only required after the Agent has confirmed that its authenticator works
+    confirmed: bool,
+    /// SHA-256 of the unused recovery codes, hex encoded
+    recovery_hashes: Vec<String>,
+    /// The step of the last code that was used, so codes can't be replayed
+    #[serde(default)]
+    last_step: u64,
+}
+
+/// Returned once when an Agent enrolls.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewEnrollment {
+    /// Base32 encoded, for entering the secret in an authenticator app manually
+    pub secret: String,
+    /// Usually shown as a QR code
+    pub otpauth_url: String,
+    pub recovery_codes: Vec<String>,
+}
+
+/// A login that waits for a TOTP code.
+#[derive(Debug)]
+struct Ticket {
+    agent: String,
+    created: Instant,
+    attempts: u32,
+}
+
+#[derive(Debug)]
+pub struct Totp {
+    path: PathBuf,
+    /// Shown in authenticator apps, the domain of the server
+    issuer: String,
+    /// Makes sure enrollments are not lost when two are changed at the same time
+    lock: Mutex<()>,
+    tickets: Mutex<HashMap<String, Ticket>>,
+}
+
+fn hash_code(code: &str) -> String {
+    Sha256::digest(code.trim().to_lowercase().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn invalid_code() -> AtomicServerError {
+    AtomicServerError::authentication_failed("Invalid TOTP code".into())
+}
+
+/// RFC 4648 base32 without padding, which authenticator apps expect.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = buf.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            out.push(ALPHABET[((bits >> (35 - i * 5)) & 31) as usize] as char);
+        }
+    }
+    out
+}
+
+/// The code for a time step, see RFC 4226.
+fn code_at(secret: &[u8], step: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+fn current_step() -> u64 {
+    atomic_lib::utils::now() as u64 / 1000 / STEP_SECONDS
+}
+
+/// The secrets are encrypted with a key derived from the private key of the default Agent.
+fn cipher(store: &impl Storelike) -> AtomicServerResult<LessSafeKey> {
+    let private_key = store
+        .get_default_agent()?
+        .private_key
+        .ok_or("Default Agent has no private key")?;
+    let key = Sha256::digest(format!("atomic-totp {}", private_key).as_bytes());
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_e| "Failed to create TOTP encryption key")?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// The subject of the Agent is authenticated, so secrets can't be moved to another Agent.
+fn encrypt(store: &impl Storelike, agent: &str, secret: &[u8]) -> AtomicServerResult<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_e| "Failed to generate nonce")?;
+    let mut in_out = secret.to_vec();
+    cipher(store)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(agent.as_bytes()),
+            &mut in_out,
+        )
+        .map_err(|_e| "Failed to encrypt TOTP secret")?;
+    Ok(base64::encode([nonce.as_slice(), &in_out].concat()))
+}
+
+fn decrypt(store: &impl Storelike, agent: &str, encrypted: &str) -> AtomicServerResult<Vec<u8>> {
+    let failed = || "Failed to decrypt TOTP secret. Has the default Agent changed?";
+    let bytes = base64::decode(encrypted).map_err(|_e| failed())?;
+    if bytes.len() < NONCE_LEN {
+        return Err(failed().into());
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_e| failed())?;
+    let mut in_out = ciphertext.to_vec();
+    let secret = cipher(store)?
+        .open_in_place(nonce, Aad::from(agent.as_bytes()), &mut in_out)
+        .map_err(|_e| failed())?;
+    Ok(secret.to_vec())
+}
+
+impl Totp {
+    pub fn init_from_config(config: &Config) -> Totp {
+        let mut path = config.config_dir.clone();
+        path.push("totp.json");
+        let issuer = config
+            .server_url
+            .parse::<actix_web::http::Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(String::from))
+            .unwrap_or_else(|| config.opts.domain.clone());
+        Totp {
+            path,
+            issuer,
+            lock: Mutex::new(()),
+            tickets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn read(&self) -> AtomicServerResult<HashMap<String, Enrollment>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => Ok(serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid {:?}: {}", self.path, e))?),
+            Err(_not_found) => Ok(HashMap::new()),
+        }
+    }
+
+    fn write(&self, enrollments: &HashMap<String, Enrollment>) -> AtomicServerResult<()> {
+        let json = serde_json::to_string_pretty(enrollments).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, json)
+            .map_err(|e| format!("Could not write {:?}: {}", self.path, e))?;
+        Ok(())
+    }
+
+    /// Whether the Agent needs a code to sign in.
+    pub fn is_enabled(&self, agent: &str) -> AtomicServerResult<bool> {
+        let _guard = self.lock.lock()?;
+        Ok(self.read()?.get(agent).is_some_and(|e| e.confirmed))
+    }
+
+    /// Creates a new secret and recovery codes for the Agent. They are only returned once.
+    /// Codes are not required until the Agent calls [Totp::confirm].
+    pub fn enroll(&self, store: &impl Storelike, agent: &str) -> AtomicServerResult<NewEnrollment> {
+        let _guard = self.lock.lock()?;
+        let mut enrollments = self.read()?;
+        if enrollments.get(agent).is_some_and(|e| e.confirmed) {
+            return Err(AtomicError::method_not_allowed(
+                "TOTP is already enabled for this Agent. Disable it first.",
+            )
+            .into());
+        }
+        let mut secret = [0u8; SECRET_LEN];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .map_err(|_e| "Failed to generate TOTP secret")?;
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODES)
+            .map(|_| atomic_lib::utils::random_string(10))
+            .collect();
+        enrollments.insert(
+            agent.to_string(),
+            Enrollment {
+                secret: encrypt(store, agent, &secret)?,
+                confirmed: false,
+                recovery_hashes: recovery_codes.iter().map(|c| hash_code(c)).collect(),
+                last_step: 0,
+            },
+        );
+        self.write(&enrollments)?;
+        let secret = base32(&secret);
+        let otpauth_url = format!(
+            "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+            issuer = urlencoding::encode(&self.issuer),
+            account = urlencoding::encode(agent),
+        );
+        Ok(NewEnrollment {
+            secret,
+            otpauth_url,
+            recovery_codes,
+        })
+    }
+
+    /// Checks a code (or recovery code) of an enrolled Agent.
+    /// Used codes and recovery codes can't be used again.
+    fn check_code(
+        &self,
+        store: &impl Storelike,
+        agent: &str,
+        code: &str,
+        allow_unconfirmed: bool,
+    ) -> AtomicServerResult<()> {
+        let _guard = self.lock.lock()?;
+        let mut enrollments = self.read()?;
+        let enrollment = enrollments
+            .get_mut(agent)
+            .filter(|e| e.confirmed || allow_unconfirmed)
+            .ok_or_else(|| AtomicError::not_found(format!("TOTP is not enabled for {}", agent)))?;
+        let code = code.trim();
+        let hash = hash_code(code);
+        if let Some(index) = enrollment.recovery_hashes.iter().position(|h| h == &hash) {
+            enrollment.recovery_hashes.remove(index);
+        } else {
+            let secret = decrypt(store, agent, &enrollment.secret)?;
+            let now = current_step();
+            let step = (now.saturating_sub(ALLOWED_DRIFT)..=now + ALLOWED_DRIFT)
+                .find(|step| *step > enrollment.last_step && code_at(&secret, *step) == code)
+                .ok_or_else(invalid_code)?;
+            enrollment.last_step = step;
+        }
+        enrollment.confirmed = true;
+        self.write(&enrollments)
+    }
+
+    /// Enables TOTP for the Agent, after checking that its authenticator generates valid codes.
+    pub fn confirm(
+        &self,
+        store: &impl Storelike,
+        agent: &str,
+        code: &str,
+    ) -> AtomicServerResult<()> {
+        self.check_code(store, agent, code, true)
+    }
+
+    /// Removes the secret of the Agent. Needs a valid code or recovery code.
+    pub fn disable(
+        &self,
+        store: &impl Storelike,
+        agent: &str,
+        code: &str,
+    ) -> AtomicServerResult<()> {
+        self.check_code(store, agent, code, false)?;
+        let _guard = self.lock.lock()?;
+        let mut enrollments = self.read()?;
+        enrollments.remove(agent);
+        self.write(&enrollments)
+    }
+
+    /// Returns an error if the Agent has enabled TOTP, and the code is missing or invalid.
+    pub fn require(
+        &self,
+        store: &impl Storelike,
+        agent: &str,
+        code: Option<&str>,
+    ) -> AtomicServerResult<()> {
+        if !self.is_enabled(agent)? {
+            return Ok(());
+        }
+        match code {
+            Some(code) => self.check_code(store, agent, code, false),
+            None => Err(AtomicServerError::authentication_failed(
+                "A TOTP code is required. Pass it as `totp`.".into(),
+            )),
+        }
+    }
+
+    /// Creates a ticket for an Agent that has signed in, which is exchanged for a session using [Totp::take_ticket].
+    pub fn new_ticket(&self, agent: &str) -> AtomicServerResult<String> {
+        let ticket = atomic_lib::utils::random_string(32);
+        let mut tickets = self.tickets.lock()?;
+        tickets.retain(|_t, pending| pending.created.elapsed() < TICKET_TTL);
+        tickets.insert(
+            ticket.clone(),
+            Ticket {
+                agent: agent.to_string(),
+                created: Instant::now(),
+                attempts: 0,
+            },
+        );
+        Ok(ticket)
+    }
+
+    /// Checks the code for the ticket, and returns the Agent that signed in.
+    pub fn take_ticket(
+        &self,
+        store: &impl Storelike,
+        ticket: &str,
+        code: &str,
+    ) -> AtomicServerResult<String> {
+        let expired =
+            || AtomicServerError::authentication_failed("Invalid or expired ticket".into());
+        let agent = {
+            let mut tickets = self.tickets.lock()?;
+            let pending = tickets
+                .get_mut(ticket)
+                .filter(|pending| pending.created.elapsed() < TICKET_TTL)
+                .ok_or_else(expired)?;
+            pending.attempts += 1;
+            pending.agent.clone()
+        };
+        let result = self.check_code(store, &agent, code, false);
+        let mut tickets = self.tickets.lock()?;
+        match result {
+            Ok(()) => {
+                tickets.remove(ticket);
+                Ok(agent)
+            }
+            Err(e) => {
+                if tickets
+                    .get(ticket)
+                    .is_some_and(|pending| pending.attempts >= MAX_TICKET_ATTEMPTS)
+                {
+                    tickets.remove(ticket);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_rfc_codes() {
+        // Test vectors from RFC 6238, truncated to six digits
+        let secret = b"12345678901234567890";
+        assert_eq!(code_at(secret, 59 / 30), "287082");
+        assert_eq!(code_at(secret, 1111111109 / 30), "081804");
+        assert_eq!(code_at(secret, 20000000000 / 30), "353130");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn requires_codes_after_confirming() {
+        let store = atomic_lib::Db::init_temp("totp").unwrap();
+        let dir = PathBuf::from("./.temp/totp");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let totp = Totp {
+            path: dir.join("totp.json"),
+            issuer: "localhost".into(),
+            lock: Mutex::new(()),
+            tickets: Mutex::new(HashMap::new()),
+        };
+        let agent = "https://localhost/agents/careful";
+        let enrollment = totp.enroll(&store, agent).unwrap();
+        assert!(enrollment.otpauth_url.contains(&enrollment.secret));
+        // Not required until confirmed
+        totp.require(&store, agent, None).unwrap();
+
+        let secret = decrypt(&store, agent, &totp.read().unwrap()[agent].secret).unwrap();
+        let code = code_at(&secret, current_step());
+        totp.confirm(&store, agent, &code).unwrap();
+        assert!(totp.is_enabled(agent).unwrap());
+        totp.require(&store, agent, None).unwrap_err();
+        // Codes can't be replayed
+        totp.require(&store, agent, Some(&code)).unwrap_err();
+        totp.enroll(&store, agent).unwrap_err();
+
+        let ticket = totp.new_ticket(agent).unwrap();
+        totp.take_ticket(&store, &ticket, "000000").unwrap_err();
+        let recovery = &enrollment.recovery_codes[0];
+        assert_eq!(totp.take_ticket(&store, &ticket, recovery).unwrap(), agent);
+        totp.take_ticket(&store, &ticket, recovery).unwrap_err();
+        // Recovery codes can only be used once
+        totp.require(&store, agent, Some(recovery)).unwrap_err();
+
+        totp.disable(&store, agent, &enrollment.recovery_codes[1])
+            .unwrap();
+        totp.require(&store, agent, None).unwrap();
+    }
+}